@@ -3,12 +3,12 @@
 // Network-wide ad blocking via your own hardware.
 //
 // API
-// Build Script For Retrieving VCS Data
+// Build Script For Retrieving VCS And Compiler Data
 //
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use std::process::Command;
+use std::{env, process::Command};
 
 fn main() {
     // Read Git data and expose it to the API at compile time
@@ -33,8 +33,18 @@ fn main() {
         .unwrap_or_default();
     let hash = String::from_utf8(hash_raw).unwrap();
 
+    // Plugin libraries must be built with the same compiler as the API
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let rustc_version_raw = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|output| output.stdout)
+        .unwrap_or_default();
+    let rustc_version = String::from_utf8(rustc_version_raw).unwrap();
+
     // This lets us use the `env!()` macro to read these variables at compile time
     println!("cargo:rustc-env=GIT_TAG={}", tag.trim());
     println!("cargo:rustc-env=GIT_BRANCH={}", branch.trim());
     println!("cargo:rustc-env=GIT_HASH={}", hash.trim());
+    println!("cargo:rustc-env=RUSTC_VERSION={}", rustc_version.trim());
 }
@@ -0,0 +1,336 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Alert Threshold Rules
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    fs,
+    io::{Read, Write}
+};
+
+/// The longest time a metric can be required to stay above its threshold,
+/// in seconds
+const MAX_DURATION: u64 = 24 * 60 * 60;
+
+/// The system metrics which threshold rules can watch
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// The one minute load average
+    Load,
+    /// The CPU temperature, in degrees Celsius
+    Temperature
+}
+
+impl Metric {
+    /// Read the current value of the metric
+    pub fn read(self) -> Result<f64, Error> {
+        let (location, scale) = match self {
+            Metric::Load => ("/proc/loadavg", 1.0),
+            // The temperature is in thousandths of a degree
+            Metric::Temperature => ("/sys/class/thermal/thermal_zone0/temp", 1000.0)
+        };
+
+        let contents =
+            fs::read_to_string(location).context(ErrorKind::FileRead(location.to_owned()))?;
+        let value: f64 = contents
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .parse::<f64>()
+            .context(ErrorKind::FileRead(location.to_owned()))?;
+
+        Ok(value / scale)
+    }
+}
+
+/// A rule which raises an alert when a metric goes above a threshold, such
+/// as "load above 2 for 5 minutes". The alert is only resolved once the
+/// metric drops below `clear_below`, so a metric which hovers around the
+/// threshold does not raise alert after alert.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct ThresholdRule {
+    pub metric: Metric,
+    pub above: f64,
+    /// Defaults to `above`, which means there is no hysteresis
+    #[serde(default)]
+    pub clear_below: Option<f64>,
+    /// How long the metric must stay above the threshold, in seconds
+    #[serde(default)]
+    pub duration: u64
+}
+
+impl ThresholdRule {
+    /// Read the saved rules
+    pub fn read_all(env: &Env) -> Result<Vec<ThresholdRule>, Error> {
+        if !env.file_exists(PiholeFile::AlertRules) {
+            return Ok(Vec::new());
+        }
+
+        let file_location = env.file_location(PiholeFile::AlertRules).to_owned();
+        let mut json = String::new();
+        env.read_file(PiholeFile::AlertRules)?
+            .read_to_string(&mut json)
+            .context(ErrorKind::FileRead(file_location.clone()))?;
+
+        if json.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+    }
+
+    /// Save the rules, replacing the previously saved rules. Each rule is
+    /// validated first, and the error says which rule is invalid and why.
+    pub fn write_all(env: &Env, rules: &[ThresholdRule]) -> Result<(), Error> {
+        for (i, rule) in rules.iter().enumerate() {
+            if let Err(reason) = rule.validate() {
+                return Err(Error::from(ErrorKind::InvalidAlertRule(format!(
+                    "rule {}: {}",
+                    i + 1,
+                    reason
+                ))));
+            }
+        }
+
+        let file_location = env.file_location(PiholeFile::AlertRules).to_owned();
+        let mut file = env.write_file(PiholeFile::AlertRules, false)?;
+
+        serde_json::to_writer(&mut file, rules)
+            .context(ErrorKind::FileWrite(file_location.clone()))?;
+        writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+        Ok(())
+    }
+
+    /// Get the value the metric must drop below to resolve the alert
+    pub fn clear_below(&self) -> f64 {
+        self.clear_below.unwrap_or(self.above)
+    }
+
+    /// Check the rule, and describe the problem if it is invalid
+    fn validate(&self) -> Result<(), String> {
+        if !self.above.is_finite() || !self.clear_below().is_finite() {
+            return Err("the thresholds must be numbers".to_owned());
+        }
+
+        if self.clear_below() > self.above {
+            return Err(format!(
+                "clear_below ({}) must not be more than above ({})",
+                self.clear_below(),
+                self.above
+            ));
+        }
+
+        if self.metric == Metric::Load && self.clear_below() < 0.0 {
+            return Err("the load thresholds must not be negative".to_owned());
+        }
+
+        if self.duration > MAX_DURATION {
+            return Err(format!("duration must be at most {} seconds", MAX_DURATION));
+        }
+
+        Ok(())
+    }
+}
+
+/// A rule which was triggered or resolved by a new value of its metric
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ThresholdEvent {
+    pub metric: Metric,
+    pub value: f64,
+    pub threshold: f64,
+    pub triggered: bool
+}
+
+/// Tracks how long each rule's metric has been above its threshold, and
+/// which rules have been triggered. The state is reset when the rules change.
+#[derive(Default)]
+pub struct ThresholdMonitor {
+    rules: Vec<ThresholdRule>,
+    states: Vec<RuleState>
+}
+
+/// The state of a rule between checks
+#[derive(Default, Clone)]
+struct RuleState {
+    /// When the metric went above the threshold
+    above_since: Option<u64>,
+    triggered: bool
+}
+
+impl ThresholdMonitor {
+    /// Check the rules against the current metric values, read with `read`,
+    /// and get the rules which were triggered or resolved. Rules whose metric
+    /// can not be read are skipped, such as when there is no temperature
+    /// sensor.
+    pub fn check<F: Fn(Metric) -> Result<f64, Error>>(
+        &mut self,
+        rules: &[ThresholdRule],
+        now: u64,
+        read: F
+    ) -> Vec<ThresholdEvent> {
+        if self.rules != rules {
+            self.rules = rules.to_vec();
+            self.states = vec![RuleState::default(); rules.len()];
+        }
+
+        let mut events = Vec::new();
+
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            let value = match read(rule.metric) {
+                Ok(value) => value,
+                Err(_) => continue
+            };
+
+            if let Some(triggered) = state.update(rule, value, now) {
+                events.push(ThresholdEvent {
+                    metric: rule.metric,
+                    value,
+                    threshold: if triggered {
+                        rule.above
+                    } else {
+                        rule.clear_below()
+                    },
+                    triggered
+                });
+            }
+        }
+
+        events
+    }
+}
+
+impl RuleState {
+    /// Update the state with a new value of the metric. Returns `Some(true)`
+    /// if the rule was triggered and `Some(false)` if it was resolved.
+    fn update(&mut self, rule: &ThresholdRule, value: f64, now: u64) -> Option<bool> {
+        if self.triggered {
+            if value < rule.clear_below() {
+                self.triggered = false;
+                self.above_since = None;
+                return Some(false);
+            }
+
+            return None;
+        }
+
+        if value <= rule.above {
+            self.above_since = None;
+            return None;
+        }
+
+        let above_since = *self.above_since.get_or_insert(now);
+
+        if now.saturating_sub(above_since) >= rule.duration {
+            self.triggered = true;
+            return Some(true);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Metric, ThresholdEvent, ThresholdMonitor, ThresholdRule};
+    use crate::util::{Error, ErrorKind};
+
+    /// A rule for the load
+    fn load_rule(above: f64, clear_below: Option<f64>, duration: u64) -> ThresholdRule {
+        ThresholdRule {
+            metric: Metric::Load,
+            above,
+            clear_below,
+            duration
+        }
+    }
+
+    /// Check the rules with the load, and get whether each event was a
+    /// trigger or resolve
+    fn check(
+        monitor: &mut ThresholdMonitor,
+        rule: &ThresholdRule,
+        load: f64,
+        now: u64
+    ) -> Vec<bool> {
+        monitor
+            .check(&[rule.clone()], now, |_| Ok(load))
+            .into_iter()
+            .map(|event| event.triggered)
+            .collect()
+    }
+
+    /// The metric must stay above the threshold for the duration
+    #[test]
+    fn duration() {
+        let rule = load_rule(2.0, None, 300);
+        let mut monitor = ThresholdMonitor::default();
+
+        assert!(check(&mut monitor, &rule, 3.0, 0).is_empty());
+        assert!(check(&mut monitor, &rule, 1.0, 100).is_empty());
+        assert!(check(&mut monitor, &rule, 3.0, 200).is_empty());
+        assert!(check(&mut monitor, &rule, 3.0, 400).is_empty());
+        assert_eq!(check(&mut monitor, &rule, 3.0, 500), vec![true]);
+        assert!(check(&mut monitor, &rule, 3.0, 600).is_empty());
+    }
+
+    /// A triggered rule is only resolved once the metric drops below the
+    /// lower threshold
+    #[test]
+    fn hysteresis() {
+        let rule = load_rule(2.0, Some(1.5), 0);
+        let mut monitor = ThresholdMonitor::default();
+
+        assert_eq!(
+            monitor.check(&[rule.clone()], 0, |_| Ok(2.5)),
+            vec![ThresholdEvent {
+                metric: Metric::Load,
+                value: 2.5,
+                threshold: 2.0,
+                triggered: true
+            }]
+        );
+        assert!(check(&mut monitor, &rule, 1.8, 10).is_empty());
+        assert!(check(&mut monitor, &rule, 2.5, 20).is_empty());
+        assert_eq!(check(&mut monitor, &rule, 1.4, 30), vec![false]);
+        assert_eq!(check(&mut monitor, &rule, 2.5, 40), vec![true]);
+    }
+
+    /// Rules whose metric can not be read are skipped
+    #[test]
+    fn unreadable_metric() {
+        let mut monitor = ThresholdMonitor::default();
+
+        assert!(monitor
+            .check(&[load_rule(2.0, None, 0)], 0, |_| Err(Error::from(
+                ErrorKind::Unknown
+            )))
+            .is_empty());
+    }
+
+    /// Invalid rules are described
+    #[test]
+    fn validation() {
+        assert!(load_rule(2.0, Some(1.5), 300).validate().is_ok());
+        assert_eq!(
+            load_rule(2.0, Some(3.0), 0).validate(),
+            Err("clear_below (3) must not be more than above (2)".to_owned())
+        );
+        assert!(load_rule(-1.0, None, 0).validate().is_err());
+        assert!(load_rule(2.0, None, 100_000).validate().is_err());
+        assert!(load_rule(std::f64::NAN, None, 0).validate().is_err());
+    }
+}
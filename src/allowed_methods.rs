@@ -0,0 +1,221 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// OPTIONS Request Handling
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{Header, Method, Status},
+    Request, Response, Rocket
+};
+use std::sync::RwLock;
+
+/// The order methods are listed in the `Allow` header
+const METHOD_ORDER: [Method; 7] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete,
+    Method::Options
+];
+
+/// Answers OPTIONS requests with the methods allowed on the path, using the
+/// `Allow` header. CORS preflight requests are answered by the CORS fairing,
+/// so this only handles OPTIONS requests which would otherwise not be found.
+///
+/// This fairing must be attached after the routes are mounted, because the
+/// routes are recorded when it is attached.
+///
+/// Trailing slashes and HEAD requests are handled by Rocket. Empty path
+/// segments are ignored when matching routes, and HEAD requests are routed
+/// to the GET route with the body removed from the response. The GET route
+/// still builds the body, so the large replies (the query history and the
+/// client export archive) have HEAD routes which only build the headers.
+#[derive(Default)]
+pub struct AllowedMethods {
+    /// The method and path of each mounted route
+    routes: RwLock<Vec<(Method, String)>>
+}
+
+impl AllowedMethods {
+    /// Get the methods allowed on the path, in the order of `METHOD_ORDER`.
+    /// If there are no routes for the path, the list is empty.
+    fn allowed(&self, path: &str) -> Vec<Method> {
+        let methods: Vec<Method> = self
+            .routes
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(_, route_path)| path_matches(route_path, path))
+            .map(|&(method, _)| method)
+            .collect();
+
+        if methods.is_empty() {
+            return methods;
+        }
+
+        METHOD_ORDER
+            .iter()
+            .cloned()
+            .filter(|method| match method {
+                // HEAD is handled by GET routes
+                Method::Head => methods.contains(&Method::Get) || methods.contains(method),
+                Method::Options => true,
+                _ => methods.contains(method)
+            })
+            .collect()
+    }
+}
+
+impl Fairing for AllowedMethods {
+    fn info(&self) -> Info {
+        Info {
+            name: "Allowed Methods",
+            kind: Kind::Attach | Kind::Response
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        *self.routes.write().unwrap_or_else(|e| e.into_inner()) = rocket
+            .routes()
+            .map(|route| (route.method, route.uri.path().to_owned()))
+            .collect();
+
+        Ok(rocket)
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if request.method() != Method::Options || response.status() != Status::NotFound {
+            return;
+        }
+
+        let allowed = self.allowed(request.uri().path());
+
+        if allowed.is_empty() {
+            return;
+        }
+
+        let allowed: Vec<&str> = allowed.iter().map(|method| method.as_str()).collect();
+
+        response.set_status(Status::NoContent);
+        response.take_body();
+        response.remove_header("Content-Type");
+        response.set_header(Header::new("Allow", allowed.join(", ")));
+    }
+}
+
+/// Check if the route path matches the request path. Empty segments are
+/// ignored, like when Rocket matches routes. A dynamic segment (`<name>`)
+/// matches any segment, and a dynamic path (`<name..>`) matches the rest of
+/// the path.
+fn path_matches(route_path: &str, path: &str) -> bool {
+    let mut route_segments = route_path.split('/').filter(|segment| !segment.is_empty());
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+
+    loop {
+        match (route_segments.next(), segments.next()) {
+            (Some(route_segment), _) if route_segment.ends_with("..>") => return true,
+            (Some(route_segment), Some(segment)) => {
+                if !route_segment.starts_with('<') && route_segment != segment {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            _ => return false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::path_matches;
+    use crate::testing::TestBuilder;
+    use rocket::http::{Method, Status};
+
+    /// Static and dynamic segments are matched
+    #[test]
+    fn matches() {
+        assert!(path_matches(
+            "/admin/api/dns/whitelist",
+            "/admin/api/dns/whitelist"
+        ));
+        assert!(path_matches(
+            "/admin/api/dns/whitelist",
+            "/admin/api/dns/whitelist/"
+        ));
+        assert!(path_matches(
+            "/admin/api/dns/whitelist/<domain>",
+            "/admin/api/dns/whitelist/example.com"
+        ));
+        assert!(path_matches("/admin/<path..>", "/admin/js/app.js"));
+        assert!(!path_matches(
+            "/admin/api/dns/whitelist/<domain>",
+            "/admin/api/dns/whitelist"
+        ));
+        assert!(!path_matches(
+            "/admin/api/dns/whitelist",
+            "/admin/api/dns/blacklist"
+        ));
+    }
+
+    /// OPTIONS requests are answered with the methods of every route on the
+    /// path
+    #[test]
+    fn options() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist")
+            .method(Method::Options)
+            .should_auth(false)
+            .expect_status(Status::NoContent)
+            .expect_header("Allow", "GET, HEAD, POST, OPTIONS")
+            .expect_empty_body()
+            .test();
+    }
+
+    /// OPTIONS requests to unknown paths are still not found
+    #[test]
+    fn options_not_found() {
+        TestBuilder::new()
+            .endpoint("/not_an_endpoint")
+            .method(Method::Options)
+            .should_auth(false)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Trailing slashes are ignored
+    #[test]
+    fn trailing_slash() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist/")
+            .should_auth(false)
+            .expect_json(json!([]))
+            .test();
+    }
+
+    /// HEAD requests are answered by the GET route, without a body
+    #[test]
+    fn head() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist")
+            .method(Method::Head)
+            .should_auth(false)
+            .expect_header("Content-Type", "application/json")
+            .expect_empty_body()
+            .test();
+    }
+}
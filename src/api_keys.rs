@@ -0,0 +1,145 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Named API Keys
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{current_time, random_base64, Error, ErrorKind}
+};
+use failure::ResultExt;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+/// The maximum length of a key name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// What a named API key is allowed to do
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Read anything, such as stats and settings
+    Read,
+    /// Change the DNS lists, groups, and blocking status
+    Lists,
+    /// Change the settings
+    Settings
+}
+
+/// A named API key, for scripts and integrations which should not have full
+/// access. Only a hash of the key is saved.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// When the key was created, as a Unix timestamp
+    pub created: u64,
+    /// The SHA-256 hash of the key, in base64
+    key_hash: String
+}
+
+/// The key information which is shown by the API
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ApiKeyInfo {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created: u64
+}
+
+impl ApiKey {
+    /// Create a new key with the scopes. The key is returned with the record,
+    /// and can not be retrieved later.
+    pub fn generate(name: String, scopes: Vec<Scope>) -> Result<(ApiKey, String), Error> {
+        let valid_name = !name.is_empty()
+            && name.chars().count() <= MAX_NAME_LENGTH
+            && !name.chars().any(char::is_control);
+
+        if !valid_name || scopes.is_empty() {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+
+        let key = random_base64(32)?;
+        let api_key = ApiKey {
+            name,
+            scopes,
+            created: current_time(),
+            key_hash: hash_key(&key)
+        };
+
+        Ok((api_key, key))
+    }
+
+    /// Get the key information without the hash
+    pub fn info(&self) -> ApiKeyInfo {
+        ApiKeyInfo {
+            name: self.name.clone(),
+            scopes: self.scopes.clone(),
+            created: self.created
+        }
+    }
+
+    /// Find the named key which matches the input key
+    pub fn find_by_key(env: &Env, key: &str) -> Result<Option<ApiKey>, Error> {
+        let key_hash = hash_key(key);
+
+        Ok(ApiKey::read_all(env)?
+            .into_iter()
+            .find(|api_key| api_key.key_hash == key_hash))
+    }
+
+    /// Read the keys. If the file does not exist, there are no keys.
+    pub fn read_all(env: &Env) -> Result<Vec<ApiKey>, Error> {
+        if !env.file_exists(PiholeFile::ApiKeys) {
+            return Ok(Vec::new());
+        }
+
+        let file_location = env.file_location(PiholeFile::ApiKeys).to_owned();
+        let mut json = String::new();
+        env.read_file(PiholeFile::ApiKeys)?
+            .read_to_string(&mut json)
+            .context(ErrorKind::FileRead(file_location.clone()))?;
+
+        if json.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+    }
+
+    /// Save the keys, replacing the previously saved keys
+    pub fn write_all(env: &Env, keys: &[ApiKey]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::ApiKeys).to_owned();
+        let mut file = env.write_file(PiholeFile::ApiKeys, false)?;
+
+        serde_json::to_writer(&mut file, keys)
+            .context(ErrorKind::FileWrite(file_location.clone()))?;
+        writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+        Ok(())
+    }
+}
+
+/// Hash the key with SHA-256, encoded in base64. The keys are random, so
+/// they do not need a salt.
+fn hash_key(key: &str) -> String {
+    base64::encode(&Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ApiKey, Scope};
+
+    /// Keys must have a name and at least one scope
+    #[test]
+    fn invalid_key() {
+        assert!(ApiKey::generate("".to_owned(), vec![Scope::Read]).is_err());
+        assert!(ApiKey::generate("script".to_owned(), Vec::new()).is_err());
+    }
+}
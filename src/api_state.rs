@@ -0,0 +1,213 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Persisted API State
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{random_base64, Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::io::{Read, Write};
+
+/// State which the API otherwise only keeps in memory, saved so that it is not
+/// lost when the API restarts
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ApiState {
+    /// When blocking is re-enabled after being disabled for a time, as a Unix
+    /// timestamp
+    #[serde(default)]
+    pub blocking_reenable_at: Option<u64>,
+    /// When a skipped Gravity update is tried again, as a Unix timestamp
+    #[serde(default)]
+    pub gravity_retry_at: Option<u64>,
+    /// The session key saved by older versions. It is only read, so it can be
+    /// moved to its own file.
+    #[serde(default, skip_serializing)]
+    session_key: Option<String>
+}
+
+impl ApiState {
+    /// Read the state. If the file does not exist, nothing has been saved.
+    pub fn read(env: &Env) -> Result<ApiState, Error> {
+        if !env.file_exists(PiholeFile::ApiState) {
+            return Ok(ApiState::default());
+        }
+
+        let file_location = env.file_location(PiholeFile::ApiState).to_owned();
+        let mut json = String::new();
+        env.read_file(PiholeFile::ApiState)?
+            .read_to_string(&mut json)
+            .context(ErrorKind::FileRead(file_location.clone()))?;
+
+        if json.trim().is_empty() {
+            return Ok(ApiState::default());
+        }
+
+        Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+    }
+
+    /// Save the state, replacing the previously saved state
+    pub fn write(&self, env: &Env) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::ApiState).to_owned();
+        let mut file = env.write_file(PiholeFile::ApiState, false)?;
+
+        serde_json::to_writer(&mut file, self)
+            .context(ErrorKind::FileWrite(file_location.clone()))?;
+        writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+        Ok(())
+    }
+
+    /// Read the state, change it, and save it again
+    pub fn update<F: FnOnce(&mut ApiState)>(env: &Env, change: F) -> Result<(), Error> {
+        let mut state = ApiState::read(env)?;
+        change(&mut state);
+        state.write(env)
+    }
+
+    /// Get the key which encrypts the session cookies, generating and saving
+    /// a new key if there is none. Keeping the key means sessions stay valid
+    /// after a restart. Anyone who can read the key can forge a session, so
+    /// it is kept in its own file which only the owner can read. A key saved
+    /// in the state by an older version is moved there.
+    pub fn session_key(env: &Env) -> Result<String, Error> {
+        if env.file_exists(PiholeFile::SessionKey) {
+            let file_location = env.file_location(PiholeFile::SessionKey).to_owned();
+            let mut key = String::new();
+            env.read_file(PiholeFile::SessionKey)?
+                .read_to_string(&mut key)
+                .context(ErrorKind::FileRead(file_location))?;
+
+            if !key.trim().is_empty() {
+                // The file may have been created with looser permissions
+                env.restrict_file(PiholeFile::SessionKey)?;
+                return Ok(key.trim().to_owned());
+            }
+        }
+
+        let mut state = ApiState::read(env)?;
+        let old_key = state.session_key.take();
+        let key = match old_key {
+            Some(ref key) => key.clone(),
+            None => random_base64(32)?
+        };
+
+        let file_location = env.file_location(PiholeFile::SessionKey).to_owned();
+        let mut file = env.write_private_file(PiholeFile::SessionKey)?;
+        writeln!(file, "{}", key).context(ErrorKind::FileWrite(file_location))?;
+
+        // Remove the old key from the state, which others can read
+        if old_key.is_some() {
+            state.write(env)?;
+        }
+
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ApiState;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+    use std::{
+        fs::{self, Permissions},
+        os::unix::fs::PermissionsExt
+    };
+    use tempfile::tempdir;
+
+    /// Nothing is saved if the file is empty
+    #[test]
+    fn read_empty() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new().file(PiholeFile::ApiState, "").build()
+        );
+
+        assert_eq!(ApiState::read(&env).unwrap(), ApiState::default());
+    }
+
+    /// Changes are saved, keeping the rest of the state
+    #[test]
+    fn update() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::ApiState,
+            "{\"gravity_retry_at\":100}\n",
+            "{\"blocking_reenable_at\":200,\"gravity_retry_at\":100}\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        ApiState::update(&env, |state| state.blocking_reenable_at = Some(200)).unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+
+    /// A saved session key is used instead of generating a new one
+    #[test]
+    fn saved_session_key() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SessionKey, "key\n")
+                .build()
+        );
+
+        assert_eq!(ApiState::session_key(&env).unwrap(), "key");
+    }
+
+    /// A session key saved in the state by an older version is moved to the
+    /// session key file
+    #[test]
+    fn move_session_key() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::ApiState,
+                "{\"gravity_retry_at\":100,\"session_key\":\"key\"}",
+                "{\"blocking_reenable_at\":null,\"gravity_retry_at\":100}\n"
+            )
+            .file_expect(PiholeFile::SessionKey, "", "key\n");
+        let test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(ApiState::session_key(&env).unwrap(), "key");
+
+        let mut buffer = String::new();
+        for mut test_file in test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// The session key file can only be read by its owner, even if it was
+    /// created with looser permissions
+    #[test]
+    fn session_key_permissions() {
+        let dir = tempdir().unwrap();
+        let key_path = dir.path().join("api_session_key");
+        let config: Config = toml::from_str(&format!(
+            "[file_locations]\napi_state = \"{}\"\nsession_key = \"{}\"\n",
+            dir.path().join("api_state.json").display(),
+            key_path.display()
+        ))
+        .unwrap();
+        let env = Env::Production(config);
+        let mode = || fs::metadata(&key_path).unwrap().permissions().mode() & 0o777;
+
+        let key = ApiState::session_key(&env).unwrap();
+        assert_eq!(mode(), 0o600);
+
+        fs::set_permissions(&key_path, Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(ApiState::session_key(&env).unwrap(), key);
+        assert_eq!(mode(), 0o600);
+    }
+}
@@ -0,0 +1,309 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Nicknames
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::{network, FtlDatabase},
+    env::{Env, PiholeFile},
+    ftl::{ClientReply, FtlClient, FtlStrings},
+    util::{Error, ErrorKind}
+};
+use diesel::{prelude::*, SqliteConnection};
+use failure::ResultExt;
+use rocket::fairing::{AdHoc, Fairing};
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+    sync::{Arc, RwLock}
+};
+
+/// Friendly names assigned to network devices. Nicknames are saved by MAC
+/// address, and are mapped to the IP addresses of the device using the
+/// network table so that stats can look them up by client IP. Clones share
+/// the nicknames.
+#[derive(Clone, Default)]
+pub struct ClientNicknames {
+    by_ip: Arc<RwLock<HashMap<String, String>>>
+}
+
+impl ClientNicknames {
+    /// Get the nickname of the client with this IP address
+    pub fn get(&self, ip: &str) -> Option<String> {
+        self.by_ip
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(ip)
+            .cloned()
+    }
+
+    /// Replace the client's name with its nickname, if it has one
+    pub fn apply(&self, client: &mut ClientReply) {
+        if let Some(nickname) = self.get(&client.ip) {
+            client.name = nickname;
+        }
+    }
+
+    /// Convert the FTL client into the reply format, using its nickname as the
+    /// name if it has one
+    pub fn client_reply(&self, client: &FtlClient, strings: &FtlStrings) -> ClientReply {
+        let mut reply = client.as_reply(strings);
+        self.apply(&mut reply);
+        reply
+    }
+
+    /// Load the nicknames and map them to the IP addresses of each device
+    pub fn reload(&self, env: &Env, db: &SqliteConnection) -> Result<(), Error> {
+        let nicknames = read_nicknames(env)?;
+        let devices: Vec<(String, String)> = network::table
+            .select((network::ip, network::hwaddr))
+            .load(db)
+            .context(ErrorKind::FtlDatabase)?;
+
+        let by_ip = devices
+            .into_iter()
+            .filter_map(|(ip, hwaddr)| {
+                nicknames
+                    .get(&hwaddr.to_lowercase())
+                    .map(|nickname| (ip, nickname.to_owned()))
+            })
+            .collect();
+
+        *self.by_ip.write().unwrap_or_else(|e| e.into_inner()) = by_ip;
+        Ok(())
+    }
+
+    /// Create a fairing which loads the nicknames when the server is set up.
+    /// It must be attached after the database and the managed nicknames.
+    pub fn fairing() -> impl Fairing {
+        AdHoc::on_attach("Client Nicknames", |rocket| {
+            if let (Some(env), Some(nicknames), Some(db)) = (
+                rocket.state::<Env>(),
+                rocket.state::<ClientNicknames>(),
+                FtlDatabase::get_one(&rocket)
+            ) {
+                if let Err(e) = nicknames.reload(env, &db) {
+                    e.print_stacktrace();
+                }
+            }
+
+            Ok(rocket)
+        })
+    }
+
+    /// Set the nickname of a client IP address, without using the network
+    /// table
+    #[cfg(test)]
+    pub fn insert(&self, ip: &str, nickname: &str) {
+        self.by_ip
+            .write()
+            .unwrap()
+            .insert(ip.to_owned(), nickname.to_owned());
+    }
+}
+
+/// A device's saved nickname, and an optional comment about it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(test, derive(Debug))]
+pub struct ClientAlias {
+    pub mac: String,
+    pub name: String,
+    pub comment: String
+}
+
+/// Read the saved aliases. Each line of the file has a MAC address followed by
+/// a space and the nickname, and optionally a tab and a comment.
+pub fn read_aliases(env: &Env) -> Result<Vec<ClientAlias>, Error> {
+    // If the file does not exist, then there are no nicknames
+    if !env.file_exists(PiholeFile::ClientNicknames) {
+        return Ok(Vec::new());
+    }
+
+    Ok(env
+        .read_file_lines(PiholeFile::ClientNicknames)?
+        .iter()
+        .filter_map(|line| {
+            let mut split = line.splitn(2, ' ');
+
+            match (split.next(), split.next()) {
+                (Some(mac), Some(rest)) => {
+                    let mut rest = rest.splitn(2, '\t');
+                    let name = rest.next().unwrap_or_default();
+
+                    if name.is_empty() {
+                        return None;
+                    }
+
+                    Some(ClientAlias {
+                        mac: mac.to_lowercase(),
+                        name: name.to_owned(),
+                        comment: rest.next().unwrap_or_default().to_owned()
+                    })
+                }
+                _ => None
+            }
+        })
+        .collect())
+}
+
+/// Save the aliases, replacing the saved aliases
+pub fn write_aliases(env: &Env, aliases: &[ClientAlias]) -> Result<(), Error> {
+    let mut aliases = aliases.to_vec();
+
+    // Keep the file in a stable order
+    aliases.sort();
+
+    let file_location = env.file_location(PiholeFile::ClientNicknames).to_owned();
+    let mut writer = BufWriter::new(env.write_file(PiholeFile::ClientNicknames, false)?);
+
+    for alias in aliases {
+        let result = if alias.comment.is_empty() {
+            writeln!(writer, "{} {}", alias.mac, alias.name)
+        } else {
+            writeln!(writer, "{} {}\t{}", alias.mac, alias.name, alias.comment)
+        };
+
+        result.context(ErrorKind::FileWrite(file_location.clone()))?;
+    }
+
+    writer
+        .flush()
+        .context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+/// Read the saved nicknames, as a map of MAC address to nickname
+pub fn read_nicknames(env: &Env) -> Result<HashMap<String, String>, Error> {
+    Ok(read_aliases(env)?
+        .into_iter()
+        .map(|alias| (alias.mac, alias.name))
+        .collect())
+}
+
+/// Save the nickname of a device, keeping its comment. If the nickname is
+/// `None`, the device's alias is removed.
+pub fn write_nickname(env: &Env, mac: &str, nickname: Option<&str>) -> Result<(), Error> {
+    let mac = mac.to_lowercase();
+    let mut aliases = read_aliases(env)?;
+    let comment = aliases
+        .iter()
+        .find(|alias| alias.mac == mac)
+        .map(|alias| alias.comment.clone())
+        .unwrap_or_default();
+
+    aliases.retain(|alias| alias.mac != mac);
+
+    if let Some(nickname) = nickname {
+        aliases.push(ClientAlias {
+            mac,
+            name: nickname.to_owned(),
+            comment
+        });
+    }
+
+    write_aliases(env, &aliases)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_aliases, read_nicknames, write_nickname, ClientAlias, ClientNicknames};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::ClientReply,
+        testing::TestEnvBuilder
+    };
+
+    /// Nicknames replace the client name, and clients without a nickname are
+    /// unchanged
+    #[test]
+    fn apply_nickname() {
+        let nicknames = ClientNicknames::default();
+        nicknames.insert("10.1.1.1", "Living Room TV");
+
+        let mut client = ClientReply {
+            name: "tv.lan".to_owned(),
+            ip: "10.1.1.1".to_owned()
+        };
+        let mut other = ClientReply {
+            name: "laptop.lan".to_owned(),
+            ip: "10.1.1.2".to_owned()
+        };
+
+        nicknames.apply(&mut client);
+        nicknames.apply(&mut other);
+
+        assert_eq!(client.name, "Living Room TV");
+        assert_eq!(other.name, "laptop.lan");
+    }
+
+    /// Nicknames are read by lowercase MAC address and may contain spaces
+    #[test]
+    fn read() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::ClientNicknames,
+                    "AA:BB:CC:DD:EE:FF Living Room TV\n\
+                     invalid\n"
+                )
+                .build()
+        );
+        let nicknames = read_nicknames(&env).unwrap();
+
+        assert_eq!(nicknames.len(), 1);
+        assert_eq!(nicknames["aa:bb:cc:dd:ee:ff"], "Living Room TV");
+    }
+
+    /// Setting a nickname replaces the existing one
+    #[test]
+    fn write_replaces() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::ClientNicknames,
+            "aa:bb:cc:dd:ee:ff Old Name\n\
+             00:11:22:33:44:55 Laptop\n",
+            "00:11:22:33:44:55 Laptop\n\
+             aa:bb:cc:dd:ee:ff New Name\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        write_nickname(&env, "AA:BB:CC:DD:EE:FF", Some("New Name")).unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+
+    /// Comments follow the nickname after a tab, and are kept when the
+    /// nickname changes
+    #[test]
+    fn comments() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::ClientNicknames,
+            "aa:bb:cc:dd:ee:ff Old Name\tUpstairs\n",
+            "aa:bb:cc:dd:ee:ff New Name\tUpstairs\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            read_aliases(&env).unwrap(),
+            vec![ClientAlias {
+                mac: "aa:bb:cc:dd:ee:ff".to_owned(),
+                name: "Old Name".to_owned(),
+                comment: "Upstairs".to_owned()
+            }]
+        );
+
+        write_nickname(&env, "aa:bb:cc:dd:ee:ff", Some("New Name")).unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+}
@@ -0,0 +1,83 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Cross-Origin Resource Sharing
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::env::Config;
+use rocket::http::Method;
+use rocket_cors::{AllowedHeaders, AllowedOrigins, Cors};
+use std::str::FromStr;
+
+/// Create the CORS fairing from the `cors` section of the config. The
+/// origins and methods were checked when the config was parsed, so invalid
+/// values are skipped here.
+pub fn cors_fairing(config: &Config) -> Cors {
+    let allowed_origins = if config.cors_allowed_origins().iter().any(|o| o == "*") {
+        AllowedOrigins::all()
+    } else {
+        let origins: Vec<&str> = config
+            .cors_allowed_origins()
+            .iter()
+            .map(String::as_str)
+            .collect();
+
+        AllowedOrigins::some(&origins).0
+    };
+
+    let allowed_headers = if config.cors_allowed_headers().iter().any(|h| h == "*") {
+        AllowedHeaders::all()
+    } else {
+        let headers: Vec<&str> = config
+            .cors_allowed_headers()
+            .iter()
+            .map(String::as_str)
+            .collect();
+
+        AllowedHeaders::some(&headers)
+    };
+
+    Cors {
+        allowed_origins,
+        allowed_methods: config
+            .cors_allowed_methods()
+            .iter()
+            .filter_map(|method| Method::from_str(method).ok())
+            .map(From::from)
+            .collect(),
+        allowed_headers,
+        allow_credentials: config.cors_allow_credentials(),
+        ..Cors::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+    use rocket::http::{Header, Status};
+    use serde_json::Value;
+
+    /// By default, cross-origin requests are allowed from any origin
+    #[test]
+    fn default_allows_origin() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(false)
+            .header(Header::new("Origin", "https://dashboard.lan"))
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .expect_header("Access-Control-Allow-Origin", "https://dashboard.lan")
+            .expect_header("Access-Control-Allow-Credentials", "true")
+            .test();
+    }
+}
@@ -8,7 +8,7 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::ftl::{FtlDnssecType, FtlQueryReplyType};
+use crate::ftl::{FtlDnssecType, FtlQueryReplyType, FtlQueryStatus, FtlQueryType};
 use rocket_contrib::json::JsonValue;
 
 #[database("ftl_database")]
@@ -27,6 +27,48 @@ pub enum CounterTableEntry {
     BlockedQueries
 }
 
+/// The value of an enum column in the database. FTL can add new values
+/// before the API knows about them, so those are kept as their number
+/// instead of being an error.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DbEnum<T> {
+    Known(T),
+    Unknown(u8)
+}
+
+/// A query type from the database
+pub type DbQueryType = DbEnum<FtlQueryType>;
+
+/// A query status from the database
+pub type DbQueryStatus = DbEnum<FtlQueryStatus>;
+
+impl DbQueryType {
+    /// Get the query type from its number in the database
+    pub fn from_db(num: i32) -> Self {
+        FtlQueryType::from_number(num as isize)
+            .map(DbEnum::Known)
+            .unwrap_or_else(|| DbEnum::Unknown(num as u8))
+    }
+
+    /// Get the name of the query type. Unknown types are named by their
+    /// number.
+    pub fn get_name(self) -> String {
+        match self {
+            DbEnum::Known(query_type) => query_type.get_name(),
+            DbEnum::Unknown(num) => format!("UNKNOWN ({})", num)
+        }
+    }
+}
+
+impl DbQueryStatus {
+    /// Get the query status from its number in the database
+    pub fn from_db(num: i32) -> Self {
+        FtlQueryStatus::from_number(num as isize)
+            .map(DbEnum::Known)
+            .unwrap_or_else(|| DbEnum::Unknown(num as u8))
+    }
+}
+
 #[cfg_attr(test, derive(PartialEq, Debug))]
 #[derive(Queryable)]
 pub struct FtlDbQuery {
@@ -53,3 +95,27 @@ impl Into<JsonValue> for FtlDbQuery {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DbEnum, DbQueryStatus, DbQueryType};
+    use crate::ftl::{FtlQueryStatus, FtlQueryType};
+
+    /// Known values are mapped to their enum variant
+    #[test]
+    fn known_values() {
+        assert_eq!(DbQueryType::from_db(6), DbEnum::Known(FtlQueryType::PTR));
+        assert_eq!(
+            DbQueryStatus::from_db(3),
+            DbEnum::Known(FtlQueryStatus::Cache)
+        );
+    }
+
+    /// Values added by newer FTL versions are kept as their number
+    #[test]
+    fn unknown_values() {
+        assert_eq!(DbQueryType::from_db(16), DbEnum::Unknown(16));
+        assert_eq!(DbQueryStatus::from_db(12), DbEnum::Unknown(12));
+        assert_eq!(DbQueryType::from_db(16).get_name(), "UNKNOWN (16)");
+    }
+}
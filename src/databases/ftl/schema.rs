@@ -8,6 +8,30 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+// Notes about queries and time ranges, added by the API. See
+// `routes::stats::annotations`.
+table! {
+    api_annotations (id) {
+        id -> Integer,
+        from_timestamp -> Integer,
+        until_timestamp -> Integer,
+        domain -> Nullable<Text>,
+        note -> Text,
+    }
+}
+
+// Query counts rolled up by the API, not by FTL. See
+// `services::query_rollup`.
+table! {
+    api_rollups (period, timestamp, kind, key) {
+        period -> Text,
+        timestamp -> Integer,
+        kind -> Text,
+        key -> Text,
+        count -> BigInt,
+    }
+}
+
 table! {
     counters (id) {
         id -> Integer,
@@ -50,4 +74,11 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(counters, ftl, network, queries,);
+allow_tables_to_appear_in_same_query!(
+    api_annotations,
+    api_rollups,
+    counters,
+    ftl,
+    network,
+    queries,
+);
@@ -0,0 +1,391 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Long-Term Statistics Storage
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{databases::ftl::FtlDatabase, env::Config};
+use diesel::{sqlite::SqliteConnection, Connection};
+use rocket::{
+    request::{self, FromRequest},
+    Outcome, Request, Rocket, State
+};
+
+#[cfg(feature = "mysql")]
+use diesel::mysql::MysqlConnection;
+#[cfg(feature = "postgres")]
+use diesel::pg::PgConnection;
+
+/// The database which the long-term statistics are read from. By default
+/// this is FTL's SQLite database. Large installs can configure a PostgreSQL
+/// or MySQL database instead, if the API was built with the `postgres` or
+/// `mysql` feature. FTL only writes to its SQLite database, so the other
+/// databases need a `queries` table with the same columns, which is filled
+/// by importing FTL's queries.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StoreBackend {
+    Sqlite,
+    #[cfg(feature = "postgres")]
+    Postgres,
+    #[cfg(feature = "mysql")]
+    Mysql
+}
+
+impl StoreBackend {
+    /// Get the backend of a database URL. URLs of backends which the API was
+    /// not built with are not recognized.
+    pub fn from_url(url: &str) -> Option<StoreBackend> {
+        match url.find("://").map(|end| &url[..end]) {
+            #[cfg(feature = "postgres")]
+            Some("postgres") | Some("postgresql") => Some(StoreBackend::Postgres),
+            #[cfg(feature = "mysql")]
+            Some("mysql") => Some(StoreBackend::Mysql),
+            _ => None
+        }
+    }
+
+    /// Get the configured backend. The config has already been checked, so
+    /// the URL is of a known backend.
+    pub fn from_config(config: &Config) -> StoreBackend {
+        config
+            .long_term_database()
+            .and_then(StoreBackend::from_url)
+            .unwrap_or(StoreBackend::Sqlite)
+    }
+
+    /// Attach the fairing which manages the connection pool of the backend.
+    /// SQLite uses FTL's database, which is attached separately.
+    pub fn attach(self, server: Rocket) -> Rocket {
+        match self {
+            StoreBackend::Sqlite => server,
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres => server.attach(PostgresDatabase::fairing()),
+            #[cfg(feature = "mysql")]
+            StoreBackend::Mysql => server.attach(MysqlDatabase::fairing())
+        }
+    }
+}
+
+/// The SQL which differs between the backends. The statistics queries are
+/// written with Diesel, which generates the right SQL for each backend,
+/// except for these snippets of raw SQL.
+pub trait LongTermStore: Connection {
+    /// SQL for the weekday of the query's timestamp in UTC, from 0 (Sunday)
+    /// to 6
+    fn weekday_sql(&self) -> &'static str;
+
+    /// SQL for the hour of the query's timestamp in UTC
+    fn hour_sql(&self) -> &'static str;
+
+    /// SQL for the timestamp of the interval which the query is in
+    fn interval_sql(&self, interval: usize) -> String;
+}
+
+impl LongTermStore for SqliteConnection {
+    fn weekday_sql(&self) -> &'static str {
+        "CAST(strftime('%w', timestamp, 'unixepoch') AS INTEGER)"
+    }
+
+    fn hour_sql(&self) -> &'static str {
+        "CAST(strftime('%H', timestamp, 'unixepoch') AS INTEGER)"
+    }
+
+    fn interval_sql(&self, interval: usize) -> String {
+        format!("(timestamp / {interval}) * {interval}", interval = interval)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl LongTermStore for PgConnection {
+    fn weekday_sql(&self) -> &'static str {
+        "CAST(EXTRACT(DOW FROM TO_TIMESTAMP(timestamp) AT TIME ZONE 'UTC') AS INTEGER)"
+    }
+
+    fn hour_sql(&self) -> &'static str {
+        "CAST(EXTRACT(HOUR FROM TO_TIMESTAMP(timestamp) AT TIME ZONE 'UTC') AS INTEGER)"
+    }
+
+    fn interval_sql(&self, interval: usize) -> String {
+        format!("(timestamp / {interval}) * {interval}", interval = interval)
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl LongTermStore for MysqlConnection {
+    // The session's time zone is not used, so the times are always in UTC
+    fn weekday_sql(&self) -> &'static str {
+        "DAYOFWEEK(DATE_ADD('1970-01-01', INTERVAL timestamp SECOND)) - 1"
+    }
+
+    fn hour_sql(&self) -> &'static str {
+        "HOUR(DATE_ADD('1970-01-01', INTERVAL timestamp SECOND))"
+    }
+
+    fn interval_sql(&self, interval: usize) -> String {
+        // `/` is not integer division in MySQL
+        format!(
+            "(timestamp DIV {interval}) * {interval}",
+            interval = interval
+        )
+    }
+}
+
+/// A connection to the long-term statistics database. Diesel queries are
+/// typed by their backend, so they are built and run once for each variant
+/// using [`with_store!`].
+///
+/// [`with_store!`]: ../../macro.with_store.html
+#[derive(Clone, Copy)]
+pub enum StoreConnection<'a> {
+    Sqlite(&'a SqliteConnection),
+    #[cfg(feature = "postgres")]
+    Postgres(&'a PgConnection),
+    #[cfg(feature = "mysql")]
+    Mysql(&'a MysqlConnection)
+}
+
+/// Evaluate the expression with `$conn` bound to the connection of the
+/// `StoreConnection`. The expression is compiled for each backend, so it can
+/// build and run Diesel queries as if `$conn` was a single connection type,
+/// and use the `LongTermStore` SQL snippets.
+macro_rules! with_store {
+    ($store:expr, |$conn:ident| $body:expr) => {{
+        #[allow(unused_imports)]
+        use $crate::databases::long_term::LongTermStore;
+
+        match $store {
+            $crate::databases::long_term::StoreConnection::Sqlite($conn) => $body,
+            #[cfg(feature = "postgres")]
+            $crate::databases::long_term::StoreConnection::Postgres($conn) => $body,
+            #[cfg(feature = "mysql")]
+            $crate::databases::long_term::StoreConnection::Mysql($conn) => $body
+        }
+    }};
+}
+
+/// The PostgreSQL long-term statistics database
+#[cfg(feature = "postgres")]
+#[database("long_term_database")]
+pub struct PostgresDatabase(PgConnection);
+
+/// The MySQL long-term statistics database
+#[cfg(feature = "mysql")]
+#[database("long_term_database")]
+pub struct MysqlDatabase(MysqlConnection);
+
+/// A request guard for a connection to the configured long-term statistics
+/// database
+pub enum LongTermDatabase {
+    Ftl(FtlDatabase),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresDatabase),
+    #[cfg(feature = "mysql")]
+    Mysql(MysqlDatabase)
+}
+
+impl LongTermDatabase {
+    /// Get the connection to run queries with
+    pub fn connection(&self) -> StoreConnection {
+        match self {
+            LongTermDatabase::Ftl(db) => StoreConnection::Sqlite(db),
+            #[cfg(feature = "postgres")]
+            LongTermDatabase::Postgres(db) => StoreConnection::Postgres(db),
+            #[cfg(feature = "mysql")]
+            LongTermDatabase::Mysql(db) => StoreConnection::Mysql(db)
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for LongTermDatabase {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let backend = match request.guard::<State<StoreBackend>>() {
+            Outcome::Success(backend) => *backend,
+            Outcome::Failure(failure) => return Outcome::Failure(failure),
+            Outcome::Forward(()) => return Outcome::Forward(())
+        };
+
+        match backend {
+            StoreBackend::Sqlite => request.guard::<FtlDatabase>().map(LongTermDatabase::Ftl),
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres => request
+                .guard::<PostgresDatabase>()
+                .map(LongTermDatabase::Postgres),
+            #[cfg(feature = "mysql")]
+            StoreBackend::Mysql => request
+                .guard::<MysqlDatabase>()
+                .map(LongTermDatabase::Mysql)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LongTermDatabase, LongTermStore, StoreBackend, StoreConnection};
+    use crate::databases::{
+        ftl::{connect_to_test_db, queries::dsl::*, FtlDatabase},
+        load_test_databases
+    };
+    use diesel::{dsl::sql, prelude::*, sql_types::Integer};
+    use rocket::{
+        config::{Config, Environment},
+        http::Status,
+        local::Client
+    };
+
+    /// Get the backend which the long-term database guard connected to
+    #[get("/")]
+    fn connected_backend(db: LongTermDatabase) -> &'static str {
+        match db.connection() {
+            StoreConnection::Sqlite(_) => "sqlite",
+            #[cfg(feature = "postgres")]
+            StoreConnection::Postgres(_) => "postgres",
+            #[cfg(feature = "mysql")]
+            StoreConnection::Mysql(_) => "mysql"
+        }
+    }
+
+    /// Create a client which reads the long-term statistics from the backend.
+    /// FTL's database is always attached, so a guard which fell back to it
+    /// would succeed. The backend's connection pool is only attached if its
+    /// URL is given.
+    fn backend_client(backend: StoreBackend, long_term_database: Option<&str>) -> Client {
+        let config = Config::build(Environment::Development)
+            .extra("databases", load_test_databases(long_term_database))
+            .finalize()
+            .unwrap();
+        let server = rocket::custom(config)
+            .attach(FtlDatabase::fairing())
+            .manage(backend)
+            .mount("/", routes![connected_backend]);
+        let server = if long_term_database.is_some() {
+            backend.attach(server)
+        } else {
+            server
+        };
+
+        Client::new(server).unwrap()
+    }
+
+    /// Check which backend the guard connects to
+    fn assert_connected_backend(api: &Client, expected: &str) {
+        let mut response = api.get("/").dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.body_string().unwrap(), expected);
+    }
+
+    /// Without another database, the guard connects to FTL's database
+    #[test]
+    fn guard_sqlite() {
+        assert_connected_backend(&backend_client(StoreBackend::Sqlite, None), "sqlite");
+    }
+
+    /// When PostgreSQL is configured, the guard does not fall back to FTL's
+    /// database if the PostgreSQL pool is missing
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn guard_postgres_without_pool() {
+        let api = backend_client(StoreBackend::Postgres, None);
+
+        assert_eq!(
+            api.get("/").dispatch().status(),
+            Status::InternalServerError
+        );
+    }
+
+    /// When MySQL is configured, the guard does not fall back to FTL's
+    /// database if the MySQL pool is missing
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn guard_mysql_without_pool() {
+        let api = backend_client(StoreBackend::Mysql, None);
+
+        assert_eq!(
+            api.get("/").dispatch().status(),
+            Status::InternalServerError
+        );
+    }
+
+    /// The guard connects to the PostgreSQL database at the URL in
+    /// `PIHOLE_API_TEST_POSTGRES`. This needs a running server, so it is only
+    /// run with `cargo test --features postgres -- --ignored`.
+    #[cfg(feature = "postgres")]
+    #[test]
+    #[ignore]
+    fn guard_postgres() {
+        let url = std::env::var("PIHOLE_API_TEST_POSTGRES").unwrap();
+
+        assert_connected_backend(
+            &backend_client(StoreBackend::Postgres, Some(&url)),
+            "postgres"
+        );
+    }
+
+    /// The guard connects to the MySQL database at the URL in
+    /// `PIHOLE_API_TEST_MYSQL`. This needs a running server, so it is only
+    /// run with `cargo test --features mysql -- --ignored`.
+    #[cfg(feature = "mysql")]
+    #[test]
+    #[ignore]
+    fn guard_mysql() {
+        let url = std::env::var("PIHOLE_API_TEST_MYSQL").unwrap();
+
+        assert_connected_backend(&backend_client(StoreBackend::Mysql, Some(&url)), "mysql");
+    }
+
+    /// Only URLs of the backends which the API was built with are recognized
+    #[test]
+    fn backend_from_url() {
+        assert_eq!(StoreBackend::from_url("/etc/pihole/pihole-FTL.db"), None);
+        assert_eq!(
+            StoreBackend::from_url("sqlite:///etc/pihole/pihole-FTL.db"),
+            None
+        );
+        assert_eq!(
+            StoreBackend::from_url("postgres://pihole@localhost/pihole").is_some(),
+            cfg!(feature = "postgres")
+        );
+        assert_eq!(
+            StoreBackend::from_url("mysql://pihole@localhost/pihole").is_some(),
+            cfg!(feature = "mysql")
+        );
+    }
+
+    /// The weekday and hour are in UTC, with weekdays starting on Sunday
+    #[test]
+    fn sqlite_dialect() {
+        let db = connect_to_test_db();
+        let (weekday, hour): (i32, i32) = with_store!(StoreConnection::Sqlite(&db), |conn| {
+            queries
+                .select((
+                    sql::<Integer>(conn.weekday_sql()),
+                    sql::<Integer>(conn.hour_sql())
+                ))
+                .filter(timestamp.eq(177_180))
+                .first(conn)
+                .unwrap()
+        });
+
+        // 177,180 is Saturday, 3 January 1970 01:13 UTC
+        assert_eq!((weekday, hour), (6, 1));
+    }
+
+    /// The interval of a timestamp starts at a multiple of the interval
+    #[test]
+    fn sqlite_interval() {
+        let db = connect_to_test_db();
+        let start: i32 = queries
+            .select(sql::<Integer>(&db.interval_sql(600)))
+            .filter(timestamp.eq(177_180))
+            .first(&db)
+            .unwrap();
+
+        assert_eq!(start, 177_000);
+    }
+}
@@ -20,6 +20,8 @@ use std::collections::HashMap;
 use crate::databases::ftl::TEST_FTL_DATABASE_PATH;
 
 pub mod ftl;
+#[macro_use]
+pub mod long_term;
 
 /// Load the database URLs from the API config into the Rocket config format
 pub fn load_databases(env: &Env) -> Result<HashMap<&str, HashMap<&str, Value>>, Error> {
@@ -29,17 +31,36 @@ pub fn load_databases(env: &Env) -> Result<HashMap<&str, HashMap<&str, Value>>,
     ftl_database.insert("url", Value::from(FtlConfEntry::DbFile.read(env)?));
     databases.insert("ftl_database", ftl_database);
 
+    // The long-term statistics are read from FTL's database unless another
+    // database is configured
+    if let Some(url) = env.config().long_term_database() {
+        let mut long_term_database = HashMap::new();
+
+        long_term_database.insert("url", Value::from(url));
+        databases.insert("long_term_database", long_term_database);
+    }
+
     Ok(databases)
 }
 
-/// Load test database URLs into the Rocket config format
+/// Load test database URLs into the Rocket config format. The long-term
+/// statistics database is only registered if its URL is given.
 #[cfg(test)]
-pub fn load_test_databases() -> HashMap<&'static str, HashMap<&'static str, Value>> {
+pub fn load_test_databases(
+    long_term_database: Option<&str>
+) -> HashMap<&'static str, HashMap<&'static str, Value>> {
     let mut databases = HashMap::new();
     let mut ftl_database = HashMap::new();
 
     ftl_database.insert("url", Value::from(TEST_FTL_DATABASE_PATH));
     databases.insert("ftl_database", ftl_database);
 
+    if let Some(url) = long_term_database {
+        let mut long_term = HashMap::new();
+
+        long_term.insert("url", Value::from(url));
+        databases.insert("long_term_database", long_term);
+    }
+
     databases
 }
@@ -0,0 +1,178 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Dynamic Library Plugins
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    plugins::{Plugin, QueryEvent, PLUGIN_API_VERSION},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use libloading::{Library, Symbol};
+use rocket::Route;
+use std::{
+    ffi::CStr,
+    fs,
+    os::raw::c_char,
+    path::{Path, PathBuf}
+};
+
+/// The function which a plugin library exports to create its plugin. It is
+/// defined by the `declare_plugin!` macro.
+const CONSTRUCTOR_SYMBOL: &[u8] = b"_pihole_api_create_plugin";
+
+/// The function which a plugin library exports to get the plugin interface
+/// version it was built with. It is defined by the `declare_plugin!` macro.
+const VERSION_SYMBOL: &[u8] = b"_pihole_api_plugin_version";
+
+/// The extension of the files in the plugin directory which are loaded
+const LIBRARY_EXTENSION: &str = "so";
+
+/// The type of the function which creates a plugin
+type PluginConstructor = fn() -> Box<dyn Plugin>;
+
+/// The type of the function which gets the plugin interface version. It uses
+/// the C ABI, so it can be called whichever compiler built the library.
+type PluginVersion = extern "C" fn() -> *const c_char;
+
+/// A plugin loaded from a dynamic library. The library stays loaded for as
+/// long as the plugin, because the plugin's code is in the library.
+struct DylibPlugin {
+    /// This is dropped before the library is unloaded, because fields are
+    /// dropped in order
+    plugin: Box<dyn Plugin>,
+    _library: Library
+}
+
+impl Plugin for DylibPlugin {
+    fn name(&self) -> &str {
+        self.plugin.name()
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        self.plugin.routes()
+    }
+
+    fn on_query(&self, query: &QueryEvent) {
+        self.plugin.on_query(query)
+    }
+}
+
+/// Load the plugin of each library in the directory, in order of file name.
+/// The libraries are trusted like the API itself, because loading them runs
+/// their code.
+pub fn load_plugins(plugin_dir: &str) -> Result<Vec<Box<dyn Plugin>>, Error> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(plugin_dir)
+        .context(ErrorKind::PluginLoad(plugin_dir.to_owned()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == LIBRARY_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|path| load_plugin(path)).collect()
+}
+
+/// Load the library and create its plugin. The library's version is checked
+/// first, because calling the constructor of a library built with a
+/// different compiler or version of this crate is undefined behaviour.
+fn load_plugin(path: &Path) -> Result<Box<dyn Plugin>, Error> {
+    let error = || ErrorKind::PluginLoad(path.display().to_string());
+    let library = Library::new(path).context(error())?;
+
+    // The symbol is defined by `declare_plugin!`, which returns a pointer to
+    // a NUL terminated string
+    let is_compatible = unsafe {
+        let version: Symbol<PluginVersion> = library.get(VERSION_SYMBOL).context(error())?;
+
+        is_compatible_version(CStr::from_ptr(version()))
+    };
+
+    if !is_compatible {
+        return Err(Error::from(ErrorKind::PluginVersion(
+            path.display().to_string()
+        )));
+    }
+
+    // The symbol is defined by `declare_plugin!`, so it has the expected type
+    let plugin = unsafe {
+        let constructor: Symbol<PluginConstructor> =
+            library.get(CONSTRUCTOR_SYMBOL).context(error())?;
+
+        constructor()
+    };
+
+    Ok(Box::new(DylibPlugin {
+        plugin,
+        _library: library
+    }))
+}
+
+/// Check if a library was built with the plugin interface version of the API
+fn is_compatible_version(version: &CStr) -> bool {
+    version.to_bytes_with_nul() == PLUGIN_API_VERSION.as_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_compatible_version, load_plugins};
+    use crate::{plugins::PLUGIN_API_VERSION, util::ErrorKind};
+    use std::{ffi::CStr, fs};
+    use tempfile::tempdir;
+
+    /// Only libraries built with the same version of the API and compiler
+    /// are compatible
+    #[test]
+    fn compatible_version() {
+        let own_version = CStr::from_bytes_with_nul(PLUGIN_API_VERSION.as_bytes()).unwrap();
+        let other_version = CStr::from_bytes_with_nul(b"0.1.0 rustc 1.0.0\0").unwrap();
+
+        assert!(is_compatible_version(own_version));
+        assert!(!is_compatible_version(other_version));
+    }
+
+    /// Files which are not libraries are ignored
+    #[test]
+    fn only_libraries() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("README.md"), "Not a plugin").unwrap();
+
+        assert!(load_plugins(dir.path().to_str().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Libraries which can not be loaded are an error, instead of being
+    /// skipped
+    #[test]
+    fn invalid_library() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("broken.so");
+        fs::write(&path, "Not a library").unwrap();
+
+        assert_eq!(
+            load_plugins(dir.path().to_str().unwrap())
+                .map(|_| ())
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::PluginLoad(path.display().to_string()))
+        );
+    }
+
+    /// A missing plugin directory is an error
+    #[test]
+    fn missing_dir() {
+        assert_eq!(
+            load_plugins("/nonexistent/plugins")
+                .map(|_| ())
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::PluginLoad("/nonexistent/plugins".to_owned()))
+        );
+    }
+}
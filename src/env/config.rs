@@ -9,17 +9,22 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    databases::long_term::StoreBackend,
     env::PiholeFile,
     util::{Error, ErrorKind}
 };
 use failure::{err_msg, Fail, ResultExt};
-use rocket::config::LoggingLevel;
+use rocket::{
+    config::{Limits, LoggingLevel},
+    http::Method
+};
 use std::{
     fs::File,
     io::{self, prelude::*},
-    net::Ipv4Addr,
+    net::IpAddr,
     path::Path,
-    str::FromStr
+    str::FromStr,
+    time::Duration
 };
 use toml;
 
@@ -29,7 +34,21 @@ pub struct Config {
     #[serde(default)]
     general: General,
     #[serde(default)]
-    file_locations: Files
+    file_locations: Files,
+    #[serde(default)]
+    limits: RequestLimits,
+    #[serde(default)]
+    web: Web,
+    #[serde(default)]
+    cors: CrossOrigin,
+    #[serde(default)]
+    proxy_auth: ProxyAuth,
+    #[serde(default)]
+    rate_limit: RateLimit,
+    #[serde(default)]
+    logging: Logging,
+    #[serde(default)]
+    listeners: Vec<Listener>
 }
 
 impl Config {
@@ -65,40 +84,206 @@ impl Config {
 
     /// Check if the config settings are valid
     pub fn is_valid(&self) -> bool {
-        self.general.is_valid() && self.file_locations.is_valid()
+        self.general.is_valid()
+            && self.file_locations.is_valid()
+            && self.limits.is_valid()
+            && self.cors.is_valid()
+            && self.proxy_auth.is_valid()
+            && self.rate_limit.is_valid()
+            && self.logging.is_valid()
+            && self.listeners.iter().all(Listener::is_valid)
     }
 
     /// Get the configured location of a file
     pub fn file_location(&self, file: PiholeFile) -> &str {
-        match file {
-            PiholeFile::DnsmasqConfig => &self.file_locations.dnsmasq_config,
-            PiholeFile::Whitelist => &self.file_locations.whitelist,
-            PiholeFile::Blacklist => &self.file_locations.blacklist,
-            PiholeFile::Regexlist => &self.file_locations.regexlist,
-            PiholeFile::SetupVars => &self.file_locations.setup_vars,
-            PiholeFile::FtlConfig => &self.file_locations.ftl_config,
-            PiholeFile::LocalVersions => &self.file_locations.local_versions,
-            PiholeFile::LocalBranches => &self.file_locations.local_branches,
-            PiholeFile::AuditLog => &self.file_locations.audit_log,
-            PiholeFile::Gravity => &self.file_locations.gravity,
-            PiholeFile::GravityBackup => &self.file_locations.gravity_backup,
-            PiholeFile::BlackList => &self.file_locations.black_list,
-            PiholeFile::BlackListBackup => &self.file_locations.black_list_backup
-        }
+        self.file_locations.location(file)
     }
 
-    pub fn address(&self) -> &str {
-        &self.general.address
-    }
-
-    pub fn port(&self) -> usize {
-        self.general.port
+    /// Get the addresses to listen on. If no listeners are configured, the
+    /// general address and port are used without TLS.
+    pub fn listeners(&self) -> Vec<Listener> {
+        if self.listeners.is_empty() {
+            vec![Listener {
+                address: self.general.address.clone(),
+                port: self.general.port,
+                tls: None
+            }]
+        } else {
+            self.listeners.clone()
+        }
     }
 
     pub fn log_level(&self) -> Result<LoggingLevel, Error> {
         LoggingLevel::from_str(&self.general.log_level)
             .map_err(|e| Error::from(err_msg(e).context(ErrorKind::ConfigParsingError)))
     }
+
+    /// Get the number of worker threads. If it is not configured, Rocket's
+    /// default (based on the number of CPUs) should be used.
+    pub fn workers(&self) -> Option<u16> {
+        self.general.workers
+    }
+
+    /// Get the keep-alive timeout in seconds. Zero disables keep-alive.
+    pub fn keep_alive(&self) -> u32 {
+        self.general.keep_alive
+    }
+
+    /// Get the threshold above which requests are logged as slow
+    pub fn slow_request_threshold(&self) -> Duration {
+        Duration::from_millis(self.general.slow_request_threshold)
+    }
+
+    /// Get the threshold above which holding the shared memory lock during a
+    /// request is logged
+    pub fn lock_hold_threshold(&self) -> Duration {
+        Duration::from_millis(self.general.lock_hold_threshold)
+    }
+
+    /// Get how long shared memory snapshots are shared between requests. Zero
+    /// disables snapshots.
+    pub fn shm_snapshot_ttl(&self) -> Duration {
+        Duration::from_millis(self.general.shm_snapshot_ttl)
+    }
+
+    /// Get the maximum number of points in over time replies from the
+    /// database. Longer ranges are downsampled. Zero disables downsampling.
+    pub fn max_over_time_points(&self) -> usize {
+        self.general.max_over_time_points
+    }
+
+    /// Get the location of the request log, which slow requests are written
+    /// to. If it is not configured, slow requests are only counted.
+    pub fn request_log(&self) -> Option<&str> {
+        if self.general.request_log.is_empty() {
+            None
+        } else {
+            Some(&self.general.request_log)
+        }
+    }
+
+    /// Get the directory which plugin libraries and WebAssembly modules are
+    /// loaded from. The API does not start if it is set without the
+    /// `dylib-plugins` or `wasm-plugins` feature.
+    pub fn plugin_dir(&self) -> Option<&str> {
+        if self.general.plugin_dir.is_empty() {
+            None
+        } else {
+            Some(&self.general.plugin_dir)
+        }
+    }
+
+    /// Get the URL of the database which the long-term statistics are read
+    /// from, if it is not FTL's database. It is a PostgreSQL or MySQL URL,
+    /// depending on the features the API was built with.
+    pub fn long_term_database(&self) -> Option<&str> {
+        if self.general.long_term_database.is_empty() {
+            None
+        } else {
+            Some(&self.general.long_term_database)
+        }
+    }
+
+    /// Check if faults can be injected into requests, for testing error
+    /// handling. This is not meant to be enabled on real installs.
+    pub fn fault_injection(&self) -> bool {
+        self.general.fault_injection
+    }
+
+    /// Get the Content-Security-Policy header value. An empty value disables
+    /// the header.
+    pub fn content_security_policy(&self) -> &str {
+        &self.web.content_security_policy
+    }
+
+    /// Get the X-Frame-Options header value. An empty value disables the
+    /// header.
+    pub fn frame_options(&self) -> &str {
+        &self.web.frame_options
+    }
+
+    /// Check if the `X-Content-Type-Options: nosniff` header should be sent
+    pub fn content_type_options(&self) -> bool {
+        self.web.content_type_options
+    }
+
+    /// Get the Referrer-Policy header value. An empty value disables the
+    /// header.
+    pub fn referrer_policy(&self) -> &str {
+        &self.web.referrer_policy
+    }
+
+    /// Get the origins which can make cross-origin requests to the API. `*`
+    /// allows every origin.
+    pub fn cors_allowed_origins(&self) -> &[String] {
+        &self.cors.allowed_origins
+    }
+
+    /// Get the methods which cross-origin requests can use
+    pub fn cors_allowed_methods(&self) -> &[String] {
+        &self.cors.allowed_methods
+    }
+
+    /// Get the headers which cross-origin requests can send. `*` allows
+    /// every header.
+    pub fn cors_allowed_headers(&self) -> &[String] {
+        &self.cors.allowed_headers
+    }
+
+    /// Check if cross-origin requests can include credentials, such as the
+    /// session cookie
+    pub fn cors_allow_credentials(&self) -> bool {
+        self.cors.allow_credentials
+    }
+
+    /// Get the reverse proxy authentication settings, if it is enabled
+    pub fn proxy_auth(&self) -> Option<&ProxyAuth> {
+        if self.proxy_auth.trusted_proxies.is_empty() {
+            None
+        } else {
+            Some(&self.proxy_auth)
+        }
+    }
+
+    /// Get the number of API requests each client can make per second, on
+    /// average. Zero disables rate limiting.
+    pub fn rate_limit(&self) -> f64 {
+        self.rate_limit.requests_per_second
+    }
+
+    /// Get the number of API requests each client can make at once before
+    /// being rate limited
+    pub fn rate_limit_burst(&self) -> u32 {
+        self.rate_limit.burst
+    }
+
+    /// Get the level of the API's own request log: `none`, `error`,
+    /// `warning`, `info`, or `debug`
+    pub fn api_log_level(&self) -> &str {
+        &self.logging.level
+    }
+
+    /// Get the number of recent entries kept in the request log
+    pub fn api_log_entries(&self) -> usize {
+        self.logging.entries
+    }
+
+    /// Get the location of the file the request log entries are also written
+    /// to, if one is configured
+    pub fn api_log_file(&self) -> Option<&str> {
+        if self.logging.file.is_empty() {
+            None
+        } else {
+            Some(&self.logging.file)
+        }
+    }
+
+    /// Get the request body size limits
+    pub fn limits(&self) -> Limits {
+        Limits::new()
+            .limit("forms", self.limits.forms)
+            .limit("json", self.limits.json)
+    }
 }
 
 /// Defines the deserialization of the "file_locations" section of the config
@@ -130,7 +315,47 @@ pub struct Files {
     #[serde(default = "default_black_list")]
     black_list: String,
     #[serde(default = "default_black_list_backup")]
-    black_list_backup: String
+    black_list_backup: String,
+    #[serde(default = "default_client_nicknames")]
+    client_nicknames: String,
+    #[serde(default = "default_regex_whitelist")]
+    regex_whitelist: String,
+    #[serde(default = "default_ftl_pid")]
+    ftl_pid: String,
+    #[serde(default = "default_saved_views")]
+    saved_views: String,
+    #[serde(default = "default_adlists")]
+    adlists: String,
+    #[serde(default = "default_adlist_status")]
+    adlist_status: String,
+    #[serde(default = "default_adlist_checksums")]
+    adlist_checksums: String,
+    #[serde(default = "default_threat_categories")]
+    threat_categories: String,
+    #[serde(default = "default_groups")]
+    groups: String,
+    #[serde(default = "default_api_state")]
+    api_state: String,
+    #[serde(default = "default_dhcp_leases")]
+    dhcp_leases: String,
+    #[serde(default = "default_static_dhcp_leases")]
+    static_dhcp_leases: String,
+    #[serde(default = "default_custom_list")]
+    custom_list: String,
+    #[serde(default = "default_custom_cnames")]
+    custom_cnames: String,
+    #[serde(default = "default_api_users")]
+    api_users: String,
+    #[serde(default = "default_api_keys")]
+    api_keys: String,
+    #[serde(default = "default_dhcp_options")]
+    dhcp_options: String,
+    #[serde(default = "default_alert_rules")]
+    alert_rules: String,
+    #[serde(default = "default_list_details")]
+    list_details: String,
+    #[serde(default = "default_session_key")]
+    session_key: String
 }
 
 impl Default for Files {
@@ -148,30 +373,75 @@ impl Default for Files {
             gravity: default_gravity(),
             gravity_backup: default_gravity_backup(),
             black_list: default_black_list(),
-            black_list_backup: default_black_list_backup()
+            black_list_backup: default_black_list_backup(),
+            client_nicknames: default_client_nicknames(),
+            regex_whitelist: default_regex_whitelist(),
+            ftl_pid: default_ftl_pid(),
+            saved_views: default_saved_views(),
+            adlists: default_adlists(),
+            adlist_status: default_adlist_status(),
+            adlist_checksums: default_adlist_checksums(),
+            threat_categories: default_threat_categories(),
+            groups: default_groups(),
+            api_state: default_api_state(),
+            dhcp_leases: default_dhcp_leases(),
+            static_dhcp_leases: default_static_dhcp_leases(),
+            custom_list: default_custom_list(),
+            custom_cnames: default_custom_cnames(),
+            api_users: default_api_users(),
+            api_keys: default_api_keys(),
+            dhcp_options: default_dhcp_options(),
+            alert_rules: default_alert_rules(),
+            list_details: default_list_details(),
+            session_key: default_session_key()
         }
     }
 }
 
 impl Files {
     fn is_valid(&self) -> bool {
-        [
-            &self.dnsmasq_config,
-            &self.whitelist,
-            &self.blacklist,
-            &self.regexlist,
-            &self.setup_vars,
-            &self.ftl_config,
-            &self.local_versions,
-            &self.local_branches,
-            &self.audit_log,
-            &self.gravity,
-            &self.gravity_backup,
-            &self.black_list,
-            &self.black_list_backup
-        ]
-        .iter()
-        .all(|file| Path::new(file).is_absolute())
+        PiholeFile::ALL
+            .iter()
+            .all(|&file| Path::new(self.location(file)).is_absolute())
+    }
+
+    /// Get the configured location of a file
+    fn location(&self, file: PiholeFile) -> &str {
+        match file {
+            PiholeFile::DnsmasqConfig => &self.dnsmasq_config,
+            PiholeFile::Whitelist => &self.whitelist,
+            PiholeFile::Blacklist => &self.blacklist,
+            PiholeFile::Regexlist => &self.regexlist,
+            PiholeFile::SetupVars => &self.setup_vars,
+            PiholeFile::FtlConfig => &self.ftl_config,
+            PiholeFile::LocalVersions => &self.local_versions,
+            PiholeFile::LocalBranches => &self.local_branches,
+            PiholeFile::AuditLog => &self.audit_log,
+            PiholeFile::Gravity => &self.gravity,
+            PiholeFile::GravityBackup => &self.gravity_backup,
+            PiholeFile::BlackList => &self.black_list,
+            PiholeFile::BlackListBackup => &self.black_list_backup,
+            PiholeFile::ClientNicknames => &self.client_nicknames,
+            PiholeFile::RegexWhitelist => &self.regex_whitelist,
+            PiholeFile::FtlPid => &self.ftl_pid,
+            PiholeFile::SavedViews => &self.saved_views,
+            PiholeFile::Adlists => &self.adlists,
+            PiholeFile::AdlistStatus => &self.adlist_status,
+            PiholeFile::AdlistChecksums => &self.adlist_checksums,
+            PiholeFile::ThreatCategories => &self.threat_categories,
+            PiholeFile::Groups => &self.groups,
+            PiholeFile::ApiState => &self.api_state,
+            PiholeFile::DhcpLeases => &self.dhcp_leases,
+            PiholeFile::StaticDhcpLeases => &self.static_dhcp_leases,
+            PiholeFile::CustomList => &self.custom_list,
+            PiholeFile::CustomCnames => &self.custom_cnames,
+            PiholeFile::ApiUsers => &self.api_users,
+            PiholeFile::ApiKeys => &self.api_keys,
+            PiholeFile::DhcpOptions => &self.dhcp_options,
+            PiholeFile::AlertRules => &self.alert_rules,
+            PiholeFile::ListDetails => &self.list_details,
+            PiholeFile::SessionKey => &self.session_key
+        }
     }
 }
 
@@ -197,6 +467,26 @@ default!(default_gravity, Gravity);
 default!(default_gravity_backup, GravityBackup);
 default!(default_black_list, BlackList);
 default!(default_black_list_backup, BlackListBackup);
+default!(default_client_nicknames, ClientNicknames);
+default!(default_regex_whitelist, RegexWhitelist);
+default!(default_ftl_pid, FtlPid);
+default!(default_saved_views, SavedViews);
+default!(default_adlists, Adlists);
+default!(default_adlist_status, AdlistStatus);
+default!(default_adlist_checksums, AdlistChecksums);
+default!(default_threat_categories, ThreatCategories);
+default!(default_groups, Groups);
+default!(default_api_state, ApiState);
+default!(default_dhcp_leases, DhcpLeases);
+default!(default_static_dhcp_leases, StaticDhcpLeases);
+default!(default_custom_list, CustomList);
+default!(default_custom_cnames, CustomCnames);
+default!(default_api_users, ApiUsers);
+default!(default_api_keys, ApiKeys);
+default!(default_dhcp_options, DhcpOptions);
+default!(default_alert_rules, AlertRules);
+default!(default_list_details, ListDetails);
+default!(default_session_key, SessionKey);
 
 /// General config settings
 #[derive(Deserialize, Clone)]
@@ -206,7 +496,31 @@ struct General {
     #[serde(default = "default_port")]
     port: usize,
     #[serde(default = "default_log_level")]
-    log_level: String
+    log_level: String,
+    #[serde(default)]
+    workers: Option<u16>,
+    #[serde(default = "default_keep_alive")]
+    keep_alive: u32,
+    /// In milliseconds
+    #[serde(default = "default_slow_request_threshold")]
+    slow_request_threshold: u64,
+    /// In milliseconds
+    #[serde(default = "default_lock_hold_threshold")]
+    lock_hold_threshold: u64,
+    /// In milliseconds
+    #[serde(default = "default_shm_snapshot_ttl")]
+    shm_snapshot_ttl: u64,
+    #[serde(default = "default_max_over_time_points")]
+    max_over_time_points: usize,
+    #[serde(default)]
+    request_log: String,
+    #[serde(default)]
+    plugin_dir: String,
+    #[serde(default)]
+    long_term_database: String,
+    /// Hidden setting for testing error handling
+    #[serde(default)]
+    fault_injection: bool
 }
 
 impl Default for General {
@@ -214,19 +528,32 @@ impl Default for General {
         General {
             address: default_address(),
             port: default_port(),
-            log_level: default_log_level()
+            log_level: default_log_level(),
+            workers: None,
+            keep_alive: default_keep_alive(),
+            slow_request_threshold: default_slow_request_threshold(),
+            lock_hold_threshold: default_lock_hold_threshold(),
+            shm_snapshot_ttl: default_shm_snapshot_ttl(),
+            max_over_time_points: default_max_over_time_points(),
+            request_log: String::new(),
+            plugin_dir: String::new(),
+            long_term_database: String::new(),
+            fault_injection: false
         }
     }
 }
 
 impl General {
     fn is_valid(&self) -> bool {
-        Ipv4Addr::from_str(&self.address).is_ok()
+        IpAddr::from_str(&self.address).is_ok()
             && self.port <= 65535
             && match self.log_level.as_str() {
                 "debug" | "normal" | "critical" => true,
                 _ => false
             }
+            && self.workers.map_or(true, |workers| workers > 0)
+            && (self.long_term_database.is_empty()
+                || StoreBackend::from_url(&self.long_term_database).is_some())
     }
 }
 
@@ -242,9 +569,365 @@ fn default_log_level() -> String {
     "critical".to_owned()
 }
 
+fn default_keep_alive() -> u32 {
+    5
+}
+
+fn default_slow_request_threshold() -> u64 {
+    1000
+}
+
+fn default_lock_hold_threshold() -> u64 {
+    100
+}
+
+fn default_shm_snapshot_ttl() -> u64 {
+    1000
+}
+
+fn default_max_over_time_points() -> usize {
+    1000
+}
+
+/// An address to listen on, from the "listeners" list of the config file.
+/// IPv6 and IPv4 addresses can both be used, such as `::` and `0.0.0.0` for a
+/// dual-stack setup. On systems where IPv6 sockets also accept IPv4
+/// connections, `::` alone is dual-stack and cannot share its port with
+/// `0.0.0.0`.
+#[derive(Deserialize, Clone)]
+pub struct Listener {
+    address: String,
+    #[serde(default = "default_port")]
+    port: usize,
+    #[serde(default)]
+    tls: Option<ListenerTls>
+}
+
+impl Listener {
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn port(&self) -> usize {
+        self.port
+    }
+
+    /// Get the TLS options, if the listener uses HTTPS
+    pub fn tls(&self) -> Option<&ListenerTls> {
+        self.tls.as_ref()
+    }
+
+    fn is_valid(&self) -> bool {
+        IpAddr::from_str(&self.address).is_ok()
+            && self.port <= 65535
+            && self.tls.as_ref().map_or(true, ListenerTls::is_valid)
+    }
+}
+
+/// The TLS options of a listener
+#[derive(Deserialize, Clone)]
+pub struct ListenerTls {
+    /// The PEM certificate chain
+    certs: String,
+    /// The PEM private key
+    key: String
+}
+
+impl ListenerTls {
+    pub fn certs(&self) -> &str {
+        &self.certs
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn is_valid(&self) -> bool {
+        Path::new(&self.certs).is_absolute() && Path::new(&self.key).is_absolute()
+    }
+}
+
+/// Request body size limits, in bytes
+#[derive(Deserialize, Clone)]
+struct RequestLimits {
+    #[serde(default = "default_forms_limit")]
+    forms: u64,
+    #[serde(default = "default_json_limit")]
+    json: u64
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            forms: default_forms_limit(),
+            json: default_json_limit()
+        }
+    }
+}
+
+impl RequestLimits {
+    fn is_valid(&self) -> bool {
+        self.forms > 0 && self.json > 0
+    }
+}
+
+/// Web interface settings, including the security headers sent with every
+/// response
+#[derive(Deserialize, Clone)]
+struct Web {
+    #[serde(default = "default_content_security_policy")]
+    content_security_policy: String,
+    #[serde(default = "default_frame_options")]
+    frame_options: String,
+    #[serde(default = "default_content_type_options")]
+    content_type_options: bool,
+    #[serde(default = "default_referrer_policy")]
+    referrer_policy: String
+}
+
+impl Default for Web {
+    fn default() -> Self {
+        Web {
+            content_security_policy: default_content_security_policy(),
+            frame_options: default_frame_options(),
+            content_type_options: default_content_type_options(),
+            referrer_policy: default_referrer_policy()
+        }
+    }
+}
+
+fn default_content_security_policy() -> String {
+    "default-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; \
+     frame-ancestors 'none'"
+        .to_owned()
+}
+
+fn default_frame_options() -> String {
+    "DENY".to_owned()
+}
+
+fn default_content_type_options() -> bool {
+    true
+}
+
+fn default_referrer_policy() -> String {
+    "same-origin".to_owned()
+}
+
+/// Cross-origin resource sharing (CORS), which lets web pages on other hosts
+/// use the API from the browser. By default every origin is allowed.
+#[derive(Deserialize, Clone)]
+struct CrossOrigin {
+    #[serde(default = "default_cors_wildcard")]
+    allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_wildcard")]
+    allowed_headers: Vec<String>,
+    #[serde(default = "default_cors_allow_credentials")]
+    allow_credentials: bool
+}
+
+impl Default for CrossOrigin {
+    fn default() -> Self {
+        CrossOrigin {
+            allowed_origins: default_cors_wildcard(),
+            allowed_methods: default_cors_methods(),
+            allowed_headers: default_cors_wildcard(),
+            allow_credentials: default_cors_allow_credentials()
+        }
+    }
+}
+
+impl CrossOrigin {
+    fn is_valid(&self) -> bool {
+        let origins: Vec<&str> = self
+            .allowed_origins
+            .iter()
+            .map(String::as_str)
+            .filter(|&origin| origin != "*")
+            .collect();
+
+        rocket_cors::AllowedOrigins::some(&origins).1.is_empty()
+            && self
+                .allowed_methods
+                .iter()
+                .all(|method| Method::from_str(method).is_ok())
+    }
+}
+
+fn default_cors_wildcard() -> Vec<String> {
+    vec!["*".to_owned()]
+}
+
+fn default_cors_methods() -> Vec<String> {
+    ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+        .iter()
+        .map(|&method| method.to_owned())
+        .collect()
+}
+
+fn default_cors_allow_credentials() -> bool {
+    true
+}
+
+/// API request rate limits, applied to each client IP
+#[derive(Deserialize, Clone)]
+struct RateLimit {
+    #[serde(default = "default_requests_per_second")]
+    requests_per_second: f64,
+    #[serde(default = "default_burst")]
+    burst: u32
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            requests_per_second: default_requests_per_second(),
+            burst: default_burst()
+        }
+    }
+}
+
+impl RateLimit {
+    fn is_valid(&self) -> bool {
+        self.requests_per_second.is_finite() && self.requests_per_second >= 0.0 && self.burst > 0
+    }
+}
+
+fn default_requests_per_second() -> f64 {
+    10.0
+}
+
+fn default_burst() -> u32 {
+    60
+}
+
+/// The log of the requests the API handled. The most recent entries are
+/// kept in memory, and can also be written to a file.
+#[derive(Deserialize, Clone)]
+struct Logging {
+    #[serde(default = "default_api_log_level")]
+    level: String,
+    #[serde(default = "default_api_log_entries")]
+    entries: usize,
+    #[serde(default)]
+    file: String
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Logging {
+            level: default_api_log_level(),
+            entries: default_api_log_entries(),
+            file: String::new()
+        }
+    }
+}
+
+impl Logging {
+    fn is_valid(&self) -> bool {
+        match self.level.as_str() {
+            "none" | "error" | "warning" | "info" | "debug" => true,
+            _ => false
+        }
+    }
+}
+
+fn default_api_log_level() -> String {
+    "info".to_owned()
+}
+
+fn default_api_log_entries() -> usize {
+    1000
+}
+
+/// Authentication by a reverse proxy, such as Authelia or Keycloak's
+/// Gatekeeper, which logs the user in and passes their name and groups in
+/// headers. It is disabled unless trusted proxies are configured.
+#[derive(Deserialize, Clone)]
+pub struct ProxyAuth {
+    /// The addresses of the proxies which are trusted to set the headers
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+    #[serde(default = "default_user_header")]
+    user_header: String,
+    /// The header with the user's groups, separated by commas
+    #[serde(default = "default_groups_header")]
+    groups_header: String,
+    /// The groups which give the admin role. If none are configured, every
+    /// user is an admin.
+    #[serde(default)]
+    admin_groups: Vec<String>
+}
+
+impl Default for ProxyAuth {
+    fn default() -> Self {
+        ProxyAuth {
+            trusted_proxies: Vec::new(),
+            user_header: default_user_header(),
+            groups_header: default_groups_header(),
+            admin_groups: Vec::new()
+        }
+    }
+}
+
+impl ProxyAuth {
+    pub fn user_header(&self) -> &str {
+        &self.user_header
+    }
+
+    pub fn groups_header(&self) -> &str {
+        &self.groups_header
+    }
+
+    /// Check if the request came directly from a trusted proxy
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|proxy| IpAddr::from_str(proxy).ok() == Some(ip))
+    }
+
+    /// Check if the user's groups give them the admin role
+    pub fn is_admin(&self, groups: &str) -> bool {
+        self.admin_groups.is_empty()
+            || groups
+                .split(',')
+                .any(|group| self.admin_groups.iter().any(|admin| admin == group.trim()))
+    }
+
+    fn is_valid(&self) -> bool {
+        self.trusted_proxies
+            .iter()
+            .all(|proxy| IpAddr::from_str(proxy).is_ok())
+            && !self.user_header.is_empty()
+            && !self.groups_header.is_empty()
+    }
+}
+
+fn default_user_header() -> String {
+    "Remote-User".to_owned()
+}
+
+fn default_groups_header() -> String {
+    "Remote-Groups".to_owned()
+}
+
+fn default_forms_limit() -> u64 {
+    32 * 1024
+}
+
+fn default_json_limit() -> u64 {
+    1024 * 1024
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Config, Files, General};
+    use super::{
+        Config, CrossOrigin, Files, General, Listener, ListenerTls, Logging, ProxyAuth, RateLimit,
+        RequestLimits
+    };
+    use crate::env::PiholeFile;
 
     #[test]
     fn valid_config() {
@@ -264,6 +947,20 @@ mod test {
         assert!(general.is_valid());
     }
 
+    /// Every file's location is set by its config key
+    #[test]
+    fn file_config_keys() {
+        let locations: String = PiholeFile::ALL
+            .iter()
+            .map(|file| format!("{} = \"/tmp/{}\"\n", file.config_key(), file.config_key()))
+            .collect();
+        let files: Files = toml::from_str(&locations).unwrap();
+
+        for &file in PiholeFile::ALL {
+            assert_eq!(files.location(file), format!("/tmp/{}", file.config_key()));
+        }
+    }
+
     #[test]
     fn invalid_file() {
         let files = Files {
@@ -282,6 +979,15 @@ mod test {
         assert!(!general.is_valid());
     }
 
+    #[test]
+    fn valid_general_ipv6_address() {
+        let general = General {
+            address: "::".to_owned(),
+            ..General::default()
+        };
+        assert!(general.is_valid());
+    }
+
     #[test]
     fn invalid_general_port() {
         let general = General {
@@ -291,6 +997,24 @@ mod test {
         assert!(!general.is_valid());
     }
 
+    #[test]
+    fn invalid_general_workers() {
+        let general = General {
+            workers: Some(0),
+            ..General::default()
+        };
+        assert!(!general.is_valid());
+    }
+
+    #[test]
+    fn invalid_limits() {
+        let limits = RequestLimits {
+            json: 0,
+            ..RequestLimits::default()
+        };
+        assert!(!limits.is_valid());
+    }
+
     #[test]
     fn invalid_general_log_level() {
         let general = General {
@@ -299,4 +1023,143 @@ mod test {
         };
         assert!(!general.is_valid());
     }
+
+    #[test]
+    fn invalid_general_long_term_database() {
+        let general = General {
+            long_term_database: "/etc/pihole/pihole-FTL.db".to_owned(),
+            ..General::default()
+        };
+        assert!(!general.is_valid());
+    }
+
+    #[test]
+    fn default_listener() {
+        let listeners = Config::default().listeners();
+
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].address(), "0.0.0.0");
+        assert_eq!(listeners[0].port(), 80);
+        assert!(listeners[0].tls().is_none());
+    }
+
+    #[test]
+    fn parse_listeners() {
+        let config: Config = toml::from_str(
+            "[[listeners]]\n\
+             address = \"::\"\n\
+             port = 8080\n\
+             \n\
+             [[listeners]]\n\
+             address = \"0.0.0.0\"\n\
+             port = 8443\n\
+             tls = { certs = \"/etc/pihole/api.crt\", key = \"/etc/pihole/api.key\" }\n"
+        )
+        .unwrap();
+        let listeners = config.listeners();
+
+        assert!(config.is_valid());
+        assert_eq!(listeners.len(), 2);
+        assert_eq!(listeners[0].address(), "::");
+        assert!(listeners[0].tls().is_none());
+        assert_eq!(listeners[1].port(), 8443);
+        assert_eq!(
+            listeners[1].tls().map(ListenerTls::certs),
+            Some("/etc/pihole/api.crt")
+        );
+    }
+
+    #[test]
+    fn invalid_listener_address() {
+        let listener = Listener {
+            address: "localhost".to_owned(),
+            port: 80,
+            tls: None
+        };
+        assert!(!listener.is_valid());
+    }
+
+    #[test]
+    fn invalid_listener_tls() {
+        let listener = Listener {
+            address: "::1".to_owned(),
+            port: 443,
+            tls: Some(ListenerTls {
+                certs: "api.crt".to_owned(),
+                key: "/etc/pihole/api.key".to_owned()
+            })
+        };
+        assert!(!listener.is_valid());
+    }
+
+    /// Proxy authentication is disabled by default
+    #[test]
+    fn proxy_auth_disabled() {
+        assert!(Config::default().proxy_auth().is_none());
+    }
+
+    /// Only the configured proxies are trusted, and only the admin groups
+    /// give the admin role
+    #[test]
+    fn parse_proxy_auth() {
+        let config: Config = toml::from_str(
+            "[proxy_auth]\n\
+             trusted_proxies = [\"127.0.0.1\"]\n\
+             admin_groups = [\"admins\"]\n"
+        )
+        .unwrap();
+        let proxy_auth = config.proxy_auth().unwrap();
+
+        assert!(config.is_valid());
+        assert_eq!(proxy_auth.user_header(), "Remote-User");
+        assert!(proxy_auth.is_trusted("127.0.0.1".parse().unwrap()));
+        assert!(!proxy_auth.is_trusted("10.0.0.1".parse().unwrap()));
+        assert!(proxy_auth.is_admin("users, admins"));
+        assert!(!proxy_auth.is_admin("users"));
+    }
+
+    #[test]
+    fn invalid_trusted_proxy() {
+        let proxy_auth = ProxyAuth {
+            trusted_proxies: vec!["proxy".to_owned()],
+            ..ProxyAuth::default()
+        };
+        assert!(!proxy_auth.is_valid());
+    }
+
+    #[test]
+    fn invalid_rate_limit() {
+        let rate_limit = RateLimit {
+            burst: 0,
+            ..RateLimit::default()
+        };
+        assert!(!rate_limit.is_valid());
+    }
+
+    #[test]
+    fn invalid_api_log_level() {
+        let logging = Logging {
+            level: "verbose".to_owned(),
+            ..Logging::default()
+        };
+        assert!(!logging.is_valid());
+    }
+
+    #[test]
+    fn invalid_cors_origin() {
+        let cors = CrossOrigin {
+            allowed_origins: vec!["https://dashboard.lan".to_owned(), "not a url".to_owned()],
+            ..CrossOrigin::default()
+        };
+        assert!(!cors.is_valid());
+    }
+
+    #[test]
+    fn invalid_cors_method() {
+        let cors = CrossOrigin {
+            allowed_methods: vec!["FETCH".to_owned()],
+            ..CrossOrigin::default()
+        };
+        assert!(!cors.is_valid());
+    }
 }
@@ -14,16 +14,16 @@ use crate::{
 };
 use failure::ResultExt;
 use std::{
-    fs::{self, File, OpenOptions},
+    fs::{self, File, OpenOptions, Permissions},
     io::{BufRead, BufReader},
-    os::unix::fs::OpenOptionsExt,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
     path::Path
 };
 
 #[cfg(test)]
 use std::{
     collections::HashMap,
-    io::{Read, Write}
+    io::{Read, Seek, SeekFrom, Write}
 };
 #[cfg(test)]
 use tempfile::{tempfile, NamedTempFile};
@@ -116,12 +116,14 @@ impl Env {
             }
             #[cfg(test)]
             Env::Test(_, map) => {
-                let file = match map.get(&file) {
+                let mut file = match map.get(&file) {
                     Some(file) => file.reopen().context(ErrorKind::Unknown)?,
                     None => return tempfile().context(ErrorKind::Unknown).map_err(Error::from)
                 };
 
-                if !append {
+                if append {
+                    file.seek(SeekFrom::End(0)).context(ErrorKind::Unknown)?;
+                } else {
                     file.set_len(0).context(ErrorKind::Unknown)?;
                 }
 
@@ -130,6 +132,48 @@ impl Env {
         }
     }
 
+    /// Open a file which only its owner may read for writing, truncating it.
+    /// The permissions of an existing file are tightened too, because the
+    /// mode is only used when the file is created.
+    pub fn write_private_file(&self, file: PiholeFile) -> Result<File, Error> {
+        match self {
+            Env::Production(_) => {
+                let file_location = self.file_location(file);
+                let opened = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(file_location)
+                    .context(ErrorKind::FileWrite(file_location.to_owned()))?;
+
+                opened
+                    .set_permissions(Permissions::from_mode(0o600))
+                    .context(ErrorKind::FileWrite(file_location.to_owned()))?;
+
+                Ok(opened)
+            }
+            #[cfg(test)]
+            Env::Test(_, _) => self.write_file(file, false)
+        }
+    }
+
+    /// Make a file readable only by its owner
+    pub fn restrict_file(&self, file: PiholeFile) -> Result<(), Error> {
+        match self {
+            Env::Production(_) => {
+                let file_location = self.file_location(file);
+
+                fs::set_permissions(file_location, Permissions::from_mode(0o600))
+                    .context(ErrorKind::FileWrite(file_location.to_owned()))?;
+
+                Ok(())
+            }
+            #[cfg(test)]
+            Env::Test(_, _) => Ok(())
+        }
+    }
+
     /// Rename (move) a file from `from` to `to`
     pub fn rename_file(&self, from: PiholeFile, to: PiholeFile) -> Result<(), Error> {
         match self {
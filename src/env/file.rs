@@ -23,10 +23,108 @@ pub enum PiholeFile {
     Gravity,
     GravityBackup,
     BlackList,
-    BlackListBackup
+    BlackListBackup,
+    ClientNicknames,
+    RegexWhitelist,
+    FtlPid,
+    SavedViews,
+    Adlists,
+    AdlistStatus,
+    AdlistChecksums,
+    ThreatCategories,
+    Groups,
+    ApiState,
+    DhcpLeases,
+    StaticDhcpLeases,
+    CustomList,
+    CustomCnames,
+    ApiUsers,
+    ApiKeys,
+    DhcpOptions,
+    AlertRules,
+    ListDetails,
+    SessionKey
 }
 
 impl PiholeFile {
+    /// Every file. New files must be added here too.
+    pub const ALL: &'static [PiholeFile] = &[
+        PiholeFile::DnsmasqConfig,
+        PiholeFile::Whitelist,
+        PiholeFile::Blacklist,
+        PiholeFile::Regexlist,
+        PiholeFile::SetupVars,
+        PiholeFile::FtlConfig,
+        PiholeFile::LocalVersions,
+        PiholeFile::LocalBranches,
+        PiholeFile::AuditLog,
+        PiholeFile::Gravity,
+        PiholeFile::GravityBackup,
+        PiholeFile::BlackList,
+        PiholeFile::BlackListBackup,
+        PiholeFile::ClientNicknames,
+        PiholeFile::RegexWhitelist,
+        PiholeFile::FtlPid,
+        PiholeFile::SavedViews,
+        PiholeFile::Adlists,
+        PiholeFile::AdlistStatus,
+        PiholeFile::AdlistChecksums,
+        PiholeFile::ThreatCategories,
+        PiholeFile::Groups,
+        PiholeFile::ApiState,
+        PiholeFile::DhcpLeases,
+        PiholeFile::StaticDhcpLeases,
+        PiholeFile::CustomList,
+        PiholeFile::CustomCnames,
+        PiholeFile::ApiUsers,
+        PiholeFile::ApiKeys,
+        PiholeFile::DhcpOptions,
+        PiholeFile::AlertRules,
+        PiholeFile::ListDetails,
+        PiholeFile::SessionKey
+    ];
+
+    /// Get the key of the file's location in the `file_locations` section of
+    /// the config file
+    #[cfg(any(test, feature = "test-harness"))]
+    pub fn config_key(self) -> &'static str {
+        match self {
+            PiholeFile::DnsmasqConfig => "dnsmasq_config",
+            PiholeFile::Whitelist => "whitelist",
+            PiholeFile::Blacklist => "blacklist",
+            PiholeFile::Regexlist => "regexlist",
+            PiholeFile::SetupVars => "setup_vars",
+            PiholeFile::FtlConfig => "ftl_config",
+            PiholeFile::LocalVersions => "local_versions",
+            PiholeFile::LocalBranches => "local_branches",
+            PiholeFile::AuditLog => "audit_log",
+            PiholeFile::Gravity => "gravity",
+            PiholeFile::GravityBackup => "gravity_backup",
+            PiholeFile::BlackList => "black_list",
+            PiholeFile::BlackListBackup => "black_list_backup",
+            PiholeFile::ClientNicknames => "client_nicknames",
+            PiholeFile::RegexWhitelist => "regex_whitelist",
+            PiholeFile::FtlPid => "ftl_pid",
+            PiholeFile::SavedViews => "saved_views",
+            PiholeFile::Adlists => "adlists",
+            PiholeFile::AdlistStatus => "adlist_status",
+            PiholeFile::AdlistChecksums => "adlist_checksums",
+            PiholeFile::ThreatCategories => "threat_categories",
+            PiholeFile::Groups => "groups",
+            PiholeFile::ApiState => "api_state",
+            PiholeFile::DhcpLeases => "dhcp_leases",
+            PiholeFile::StaticDhcpLeases => "static_dhcp_leases",
+            PiholeFile::CustomList => "custom_list",
+            PiholeFile::CustomCnames => "custom_cnames",
+            PiholeFile::ApiUsers => "api_users",
+            PiholeFile::ApiKeys => "api_keys",
+            PiholeFile::DhcpOptions => "dhcp_options",
+            PiholeFile::AlertRules => "alert_rules",
+            PiholeFile::ListDetails => "list_details",
+            PiholeFile::SessionKey => "session_key"
+        }
+    }
+
     /// Get the default location of the file
     pub fn default_location(self) -> &'static str {
         match self {
@@ -42,7 +140,27 @@ impl PiholeFile {
             PiholeFile::Gravity => "/etc/pihole/gravity.list",
             PiholeFile::GravityBackup => "/etc/pihole/gravity.list.bck",
             PiholeFile::BlackList => "/etc/pihole/black.list",
-            PiholeFile::BlackListBackup => "/etc/pihole/black.list.bck"
+            PiholeFile::BlackListBackup => "/etc/pihole/black.list.bck",
+            PiholeFile::ClientNicknames => "/etc/pihole/client_nicknames.list",
+            PiholeFile::RegexWhitelist => "/etc/pihole/regex_whitelist.list",
+            PiholeFile::FtlPid => "/var/run/pihole-FTL.pid",
+            PiholeFile::SavedViews => "/etc/pihole/saved_views.json",
+            PiholeFile::Adlists => "/etc/pihole/adlists.list",
+            PiholeFile::AdlistStatus => "/etc/pihole/adlist_status.json",
+            PiholeFile::AdlistChecksums => "/etc/pihole/adlist_checksums.list",
+            PiholeFile::ThreatCategories => "/etc/pihole/threat_categories.list",
+            PiholeFile::Groups => "/etc/pihole/groups.json",
+            PiholeFile::ApiState => "/etc/pihole/api_state.json",
+            PiholeFile::DhcpLeases => "/etc/pihole/dhcp.leases",
+            PiholeFile::StaticDhcpLeases => "/etc/pihole/static_dhcp.list",
+            PiholeFile::CustomList => "/etc/pihole/custom.list",
+            PiholeFile::CustomCnames => "/etc/pihole/custom_cnames.list",
+            PiholeFile::ApiUsers => "/etc/pihole/api_users.json",
+            PiholeFile::ApiKeys => "/etc/pihole/api_keys.json",
+            PiholeFile::DhcpOptions => "/etc/pihole/dhcp_options.list",
+            PiholeFile::AlertRules => "/etc/pihole/alert_rules.json",
+            PiholeFile::ListDetails => "/etc/pihole/list_details.json",
+            PiholeFile::SessionKey => "/etc/pihole/api_session_key"
         }
     }
 }
@@ -0,0 +1,168 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Fault Injection For Testing Error Handling
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::util::{Error, ErrorKind};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Data, Request
+};
+use std::cell::Cell;
+
+/// The header which selects the faults to inject, as a comma separated list
+const FAULT_HEADER: &str = "X-Pi-hole-Fault";
+
+thread_local! {
+    /// The faults injected into the request currently being handled by this
+    /// thread. Rocket handles each request on a single worker thread, so the
+    /// faults can be checked without access to the request.
+    static FAULTS: Cell<Faults> = Cell::new(Faults::default());
+}
+
+/// The faults which can be injected
+#[derive(Copy, Clone)]
+pub enum Fault {
+    /// Opening shared memory fails
+    SharedMemory,
+    /// The database is busy
+    Database,
+    /// The FTL socket times out
+    FtlSocket
+}
+
+/// The faults injected into a request
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+struct Faults {
+    shared_memory: bool,
+    database: bool,
+    ftl_socket: bool
+}
+
+impl Faults {
+    /// Parse the faults from the header value. Unknown faults are ignored.
+    fn parse(value: &str) -> Faults {
+        let mut faults = Faults::default();
+
+        for fault in value.split(',').map(str::trim) {
+            match fault {
+                "shared_memory" => faults.shared_memory = true,
+                "database" => faults.database = true,
+                "ftl_socket" => faults.ftl_socket = true,
+                _ => ()
+            }
+        }
+
+        faults
+    }
+
+    /// Check if the fault is injected
+    fn contains(self, fault: Fault) -> bool {
+        match fault {
+            Fault::SharedMemory => self.shared_memory,
+            Fault::Database => self.database,
+            Fault::FtlSocket => self.ftl_socket
+        }
+    }
+}
+
+/// Return the error of the fault if it is injected into the current request
+pub fn inject_fault(fault: Fault) -> Result<(), Error> {
+    if !FAULTS.with(Cell::get).contains(fault) {
+        return Ok(());
+    }
+
+    Err(Error::from(match fault {
+        Fault::SharedMemory => ErrorKind::SharedMemoryOpen("Injected fault".to_owned()),
+        Fault::Database => ErrorKind::FtlDatabase,
+        Fault::FtlSocket => ErrorKind::FtlConnectionFail
+    }))
+}
+
+/// Injects faults into requests which have the `X-Pi-hole-Fault` header, so
+/// that error handling can be tested without breaking FTL or the database.
+/// This is only enabled by the hidden `fault_injection` config setting.
+pub struct FaultInjection {
+    enabled: bool
+}
+
+impl FaultInjection {
+    pub fn new(enabled: bool) -> FaultInjection {
+        FaultInjection { enabled }
+    }
+}
+
+impl Fairing for FaultInjection {
+    fn info(&self) -> Info {
+        Info {
+            name: "Fault Injection",
+            kind: Kind::Request
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        // Always set the faults, to clear any left by the previous request
+        let faults = if self.enabled {
+            request
+                .headers()
+                .get_one(FAULT_HEADER)
+                .map(Faults::parse)
+                .unwrap_or_default()
+        } else {
+            Faults::default()
+        };
+
+        FAULTS.with(|current| current.set(faults));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{inject_fault, Fault, Faults, FAULTS};
+    use crate::{testing::TestBuilder, util::ErrorKind};
+    use rocket::http::Header;
+
+    /// Faults are parsed from a comma separated list
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Faults::parse("database, ftl_socket,unknown"),
+            Faults {
+                shared_memory: false,
+                database: true,
+                ftl_socket: true
+            }
+        );
+    }
+
+    /// Only the injected faults return an error
+    #[test]
+    fn inject() {
+        FAULTS.with(|faults| faults.set(Faults::parse("database")));
+
+        assert_eq!(
+            inject_fault(Fault::Database).unwrap_err().kind(),
+            ErrorKind::FtlDatabase
+        );
+        assert!(inject_fault(Fault::FtlSocket).is_ok());
+
+        FAULTS.with(|faults| faults.set(Faults::default()));
+        assert!(inject_fault(Fault::Database).is_ok());
+    }
+
+    /// The header is ignored when fault injection is disabled
+    #[test]
+    fn disabled() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/recent_blocked")
+            .header(Header::new("X-Pi-hole-Fault", "shared_memory"))
+            .expect_json(json!([]))
+            .test();
+    }
+}
@@ -12,7 +12,7 @@ use crate::ftl::memory_model::{over_time::OVERTIME_SLOTS, strings::FtlStrings};
 use libc;
 use std::hash::{Hash, Hasher};
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 use crate::ftl::memory_model::MAGIC_BYTE;
 #[cfg(test)]
 use std::fmt::{
@@ -50,7 +50,7 @@ pub struct FtlClient {
 }
 
 impl FtlClient {
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-harness"))]
     pub fn new(
         query_count: usize,
         blocked_count: usize,
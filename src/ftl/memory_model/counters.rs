@@ -13,8 +13,8 @@ use rocket::{http::RawStr, request::FromFormValue};
 
 /// The FTL counters stored in shared memory
 #[repr(C)]
-#[cfg_attr(test, derive(Default))]
-#[derive(Copy, Clone)]
+#[cfg_attr(any(test, feature = "test-harness"), derive(Default))]
+#[derive(Copy, Clone, PartialEq, Serialize)]
 pub struct FtlCounters {
     pub total_queries: libc::c_int,
     pub blocked_queries: libc::c_int,
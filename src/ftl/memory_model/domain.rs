@@ -11,7 +11,7 @@
 use crate::ftl::FtlStrings;
 use libc;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 use crate::ftl::memory_model::MAGIC_BYTE;
 
 /// The domain struct stored in shared memory
@@ -27,7 +27,7 @@ pub struct FtlDomain {
 }
 
 impl FtlDomain {
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-harness"))]
     pub fn new(
         total_count: usize,
         blocked_count: usize,
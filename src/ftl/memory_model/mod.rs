@@ -8,11 +8,11 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 use libc;
 
 /// Used by FTL to check memory integrity in various structs
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 pub const MAGIC_BYTE: libc::c_uchar = 0x57;
 
 mod client;
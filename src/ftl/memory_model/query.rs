@@ -80,6 +80,37 @@ impl FtlQueryStatus {
             _ => None
         }
     }
+
+    /// A list of all `FtlQueryStatus` variants
+    pub fn variants() -> &'static [FtlQueryStatus] {
+        &[
+            FtlQueryStatus::Unknown,
+            FtlQueryStatus::Gravity,
+            FtlQueryStatus::Forward,
+            FtlQueryStatus::Cache,
+            FtlQueryStatus::Wildcard,
+            FtlQueryStatus::Blacklist,
+            FtlQueryStatus::ExternalBlock
+        ]
+    }
+
+    /// Get the name of the status, as used in the API
+    pub fn get_name(self) -> &'static str {
+        match self {
+            FtlQueryStatus::Unknown => "unknown",
+            FtlQueryStatus::Gravity => "gravity",
+            FtlQueryStatus::Forward => "forward",
+            FtlQueryStatus::Cache => "cache",
+            FtlQueryStatus::Wildcard => "wildcard",
+            FtlQueryStatus::Blacklist => "blacklist",
+            FtlQueryStatus::ExternalBlock => "external_block"
+        }
+    }
+
+    /// Check if queries with the status are blocked
+    pub fn is_blocked(self) -> bool {
+        BLOCKED_STATUSES.contains(&(self as i32))
+    }
 }
 
 impl<'v> FromFormValue<'v> for FtlQueryStatus {
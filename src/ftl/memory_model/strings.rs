@@ -10,9 +10,9 @@
 
 use libc;
 use shmem::Array;
-use std::{ffi::CStr, marker::PhantomData};
+use std::{ffi::CStr, marker::PhantomData, sync::Arc};
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 use std::collections::HashMap;
 
 /// A safe wrapper around FTL's strings. It is used to access the strings
@@ -24,7 +24,9 @@ pub enum FtlStrings<'test> {
     // Use `PhantomData` because when not in testing mode, the 'test lifetime will be unused and
     // will cause an error. The `PhantomData` is zero-sized, so it will not actually exist.
     Production(Array<libc::c_char>, PhantomData<&'test bool>),
-    #[cfg(test)]
+    /// A copy of the strings, from a shared memory snapshot
+    Snapshot(Arc<[libc::c_char]>),
+    #[cfg(any(test, feature = "test-harness"))]
     Test(&'test HashMap<usize, String>)
 }
 
@@ -36,7 +38,8 @@ impl<'test> FtlStrings<'test> {
     pub fn get_str(&self, id: usize) -> Option<&str> {
         match self {
             FtlStrings::Production(strings, ..) => Self::get_str_prod(strings, id),
-            #[cfg(test)]
+            FtlStrings::Snapshot(strings) => Self::get_str_prod(strings, id),
+            #[cfg(any(test, feature = "test-harness"))]
             FtlStrings::Test(strings) => {
                 if id == 0 {
                     Some("")
@@ -47,7 +50,8 @@ impl<'test> FtlStrings<'test> {
         }
     }
 
-    /// This function is used for `FtlStrings::Production`. It checks to see
+    /// This function is used for `FtlStrings::Production` and
+    /// `FtlStrings::Snapshot`. It checks to see
     /// if the string exists, and then creates a `CStr` from a pointer. It
     /// is assumed that the string has a null terminator. Then the `CStr` is
     /// converted into `&str`. If the conversion fails, `None` is returned.
@@ -11,7 +11,7 @@
 use crate::ftl::FtlStrings;
 use libc;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 use crate::ftl::memory_model::MAGIC_BYTE;
 
 /// The upstream (forward destination) struct stored in shared memory
@@ -27,7 +27,7 @@ pub struct FtlUpstream {
 }
 
 impl FtlUpstream {
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-harness"))]
     pub fn new(
         query_count: usize,
         failed_count: usize,
@@ -12,6 +12,7 @@ mod lock_thread;
 mod memory_model;
 mod shared_lock;
 mod shared_memory;
+mod snapshot;
 mod socket;
 
 pub use self::{
@@ -9,7 +9,11 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    ftl::lock_thread::{LockRequest, LockThread, RequestType},
+    ftl::{
+        lock_thread::{LockRequest, LockThread, RequestType},
+        snapshot::FtlSnapshot
+    },
+    metrics::record_lock_hold,
     util::{Error, ErrorKind}
 };
 use failure::{Fail, ResultExt};
@@ -17,9 +21,10 @@ use nix::errno::Errno;
 use std::{
     sync::{
         mpsc::{channel, Sender},
-        Mutex
+        Arc, Mutex
     },
-    thread
+    thread,
+    time::Instant
 };
 
 /// A lock for coordinating shared memory access with FTL. It locks a mutex in
@@ -57,7 +62,10 @@ impl ShmLock {
     /// guard (return value) lives.
     pub fn read(&self) -> Result<ShmLockGuard, Error> {
         self.send_request(RequestType::Lock)?;
-        Ok(ShmLockGuard::Production { lock: self })
+        Ok(ShmLockGuard::Production {
+            lock: self,
+            acquired: Instant::now()
+        })
     }
 
     /// Send a request to the lock thread. This will block until the request
@@ -91,21 +99,28 @@ impl ShmLock {
 }
 
 /// A RAII type lock guard which keeps the lock active until it is dropped.
+/// The time the lock was held is added to the request's timings. A snapshot
+/// guard does not hold the lock, and reads from a copy of shared memory
+/// instead.
 pub enum ShmLockGuard<'lock> {
     Production {
-        lock: &'lock ShmLock
+        lock: &'lock ShmLock,
+        acquired: Instant
     },
-    #[cfg(test)]
+    Snapshot(Arc<FtlSnapshot>),
+    #[cfg(any(test, feature = "test-harness"))]
     Test
 }
 
 impl<'lock> Drop for ShmLockGuard<'lock> {
     fn drop(&mut self) {
         match self {
-            ShmLockGuard::Production { lock } => {
+            ShmLockGuard::Production { lock, acquired } => {
                 lock.send_request(RequestType::Unlock).unwrap();
+                record_lock_hold(acquired.elapsed());
             }
-            #[cfg(test)]
+            ShmLockGuard::Snapshot(_) => (),
+            #[cfg(any(test, feature = "test-harness"))]
             ShmLockGuard::Test => ()
         }
     }
@@ -9,17 +9,26 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    fault_injection::{inject_fault, Fault},
     ftl::{
+        snapshot::{FtlSnapshot, SnapshotCache, SnapshotRef},
         FtlClient, FtlCounters, FtlDomain, FtlOverTime, FtlQuery, FtlStrings, FtlUpstream, ShmLock,
         ShmLockGuard
     },
+    metrics::record_lock_wait,
     util::Error
 };
 use shmem::{Array, Map, Object};
-use std::{marker::PhantomData, ops::Deref};
+use std::{
+    marker::PhantomData,
+    ops::Deref,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant}
+};
 
 use crate::{ftl::memory_model::FtlSettings, util::ErrorKind};
-#[cfg(test)]
+#[cfg(any(test, feature = "test-harness"))]
 use std::collections::HashMap;
 
 const FTL_SHM_VERSION: usize = 4;
@@ -33,6 +42,9 @@ const FTL_SHM_STRINGS: &str = "/FTL-strings";
 const FTL_SHM_COUNTERS: &str = "/FTL-counters";
 const FTL_SHM_SETTINGS: &str = "/FTL-settings";
 
+/// How often the counters are checked while waiting for them to change
+const COUNTER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
 /// A wrapper for accessing FTL's shared memory.
 ///
 /// - Production mode connects to the real FTL shared memory.
@@ -40,9 +52,10 @@ const FTL_SHM_SETTINGS: &str = "/FTL-settings";
 #[allow(clippy::large_enum_variant)]
 pub enum FtlMemory {
     Production {
-        lock: ShmLock
+        lock: ShmLock,
+        snapshots: SnapshotCache
     },
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-harness"))]
     Test {
         clients: Vec<FtlClient>,
         domains: Vec<FtlDomain>,
@@ -59,7 +72,19 @@ impl FtlMemory {
     /// Create a production instance of `FtlMemory`
     pub fn production() -> FtlMemory {
         FtlMemory::Production {
-            lock: ShmLock::new()
+            lock: ShmLock::new(),
+            snapshots: SnapshotCache::default()
+        }
+    }
+
+    /// Create a production instance of `FtlMemory` which shares snapshots of
+    /// shared memory for `snapshot_ttl`. See [`snapshot`].
+    ///
+    /// [`snapshot`]: #method.snapshot
+    pub fn production_with_snapshots(snapshot_ttl: Duration) -> FtlMemory {
+        FtlMemory::Production {
+            lock: ShmLock::new(),
+            snapshots: SnapshotCache::new(snapshot_ttl)
         }
     }
 
@@ -68,9 +93,13 @@ impl FtlMemory {
     ///
     /// [`ShmLockGuard`]: ../shared_lock/enum.ShmLockGuard.html
     pub fn lock(&self) -> Result<ShmLockGuard, Error> {
+        inject_fault(Fault::SharedMemory)?;
+
         match self {
-            FtlMemory::Production { lock } => {
+            FtlMemory::Production { lock, .. } => {
+                let start = Instant::now();
                 let guard = lock.read()?;
+                record_lock_wait(start.elapsed());
 
                 // Check the version of shared memory, in case it is not the
                 // same version used by this API
@@ -86,23 +115,58 @@ impl FtlMemory {
                     )))
                 }
             }
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { .. } => Ok(ShmLockGuard::Test)
         }
     }
 
+    /// Get a guard for reading the counters, domains, clients, and strings.
+    /// If snapshots are enabled, the data comes from a snapshot which is
+    /// shared by requests until it expires, so the lock is not taken for
+    /// every request. Other data can not be read with a snapshot guard.
+    pub fn snapshot(&self) -> Result<ShmLockGuard, Error> {
+        match self {
+            FtlMemory::Production { snapshots, .. } if snapshots.is_enabled() => {
+                Ok(ShmLockGuard::Snapshot(snapshots.get_or_take(|| {
+                    let lock = self.lock()?;
+                    self.take_snapshot(&lock)
+                })?))
+            }
+            _ => self.lock()
+        }
+    }
+
+    /// Copy the counters, domains, clients, and strings out of shared memory
+    fn take_snapshot(&self, lock_guard: &ShmLockGuard) -> Result<FtlSnapshot, Error> {
+        let strings: Array<libc::c_char> = Array::new(Object::open(FTL_SHM_STRINGS)?)?;
+
+        Ok(FtlSnapshot {
+            counters: **self.counters(lock_guard)?,
+            domains: self.domains(lock_guard)?.to_vec(),
+            clients: self.clients(lock_guard)?.to_vec(),
+            strings: Arc::from(&strings[..]),
+            taken: Instant::now()
+        })
+    }
+
     /// Get the FTL shared memory client data. The resulting trait object can
     /// dereference into `&[FtlClient]`.
     pub fn clients<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = [FtlClient]> + 'lock>, Error> {
+        if let ShmLockGuard::Snapshot(snapshot) = lock_guard {
+            return Ok(Box::new(SnapshotRef::new(snapshot, |snapshot| {
+                snapshot.clients.as_slice()
+            })));
+        }
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(
                 // Load the shared memory
                 Array::new(Object::open(FTL_SHM_CLIENTS)?)?
             ),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { clients, .. } => Box::new(clients.as_slice())
         })
     }
@@ -111,14 +175,20 @@ impl FtlMemory {
     /// dereference into `&[FtlDomain]`.
     pub fn domains<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = [FtlDomain]> + 'lock>, Error> {
+        if let ShmLockGuard::Snapshot(snapshot) = lock_guard {
+            return Ok(Box::new(SnapshotRef::new(snapshot, |snapshot| {
+                snapshot.domains.as_slice()
+            })));
+        }
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(
                 // Load the shared memory
                 Array::new(Object::open(FTL_SHM_DOMAINS)?)?
             ),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { domains, .. } => Box::new(domains.as_slice())
         })
     }
@@ -127,14 +197,16 @@ impl FtlMemory {
     /// dereference into `&[FtlOverTime]`.
     pub fn over_time<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = [FtlOverTime]> + 'lock>, Error> {
+        require_lock(lock_guard)?;
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(
                 // Load the shared memory
                 Array::new(Object::open(FTL_SHM_OVERTIME)?)?
             ),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { over_time, .. } => Box::new(over_time.as_slice())
         })
     }
@@ -143,14 +215,16 @@ impl FtlMemory {
     /// dereference into `&[FtlUpstream]`.
     pub fn upstreams<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = [FtlUpstream]> + 'lock>, Error> {
+        require_lock(lock_guard)?;
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(
                 // Load the shared memory
                 Array::new(Object::open(FTL_SHM_FORWARDED)?)?
             ),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { upstreams, .. } => Box::new(upstreams.as_slice())
         })
     }
@@ -159,14 +233,16 @@ impl FtlMemory {
     /// dereference into `&[FtlQuery]`.
     pub fn queries<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = [FtlQuery]> + 'lock>, Error> {
+        require_lock(lock_guard)?;
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(
                 // Load the shared memory
                 Array::new(Object::open(FTL_SHM_QUERIES)?)?
             ),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { queries, .. } => Box::new(queries.as_slice())
         })
     }
@@ -174,13 +250,17 @@ impl FtlMemory {
     /// Get the FTL shared memory string data
     pub fn strings<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<FtlStrings<'lock>, Error> {
+        if let ShmLockGuard::Snapshot(snapshot) = lock_guard {
+            return Ok(FtlStrings::Snapshot(Arc::clone(&snapshot.strings)));
+        }
+
         Ok(match self {
             FtlMemory::Production { .. } => {
                 FtlStrings::Production(Array::new(Object::open(FTL_SHM_STRINGS)?)?, PhantomData)
             }
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { strings, .. } => FtlStrings::Test(&strings)
         })
     }
@@ -189,25 +269,73 @@ impl FtlMemory {
     /// dereference into `&FtlCounters`.
     pub fn counters<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = FtlCounters> + 'lock>, Error> {
+        if let ShmLockGuard::Snapshot(snapshot) = lock_guard {
+            return Ok(Box::new(SnapshotRef::new(snapshot, |snapshot| {
+                &snapshot.counters
+            })));
+        }
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(Map::new(Object::open(FTL_SHM_COUNTERS)?)?),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { counters, .. } => Box::new(counters)
         })
     }
 
+    /// Block until the counters change or the timeout passes, and return if
+    /// they changed. FTL does not keep a change counter in shared memory, so
+    /// the counters are copied and compared every `COUNTER_POLL_INTERVAL`.
+    /// The counters are read with [`snapshot`], so the lock is not taken more
+    /// often than for normal requests.
+    ///
+    /// [`snapshot`]: #method.snapshot
+    pub fn wait_for_counter_change(&self, timeout: Duration) -> Result<bool, Error> {
+        let read_counters = || -> Result<FtlCounters, Error> {
+            let lock = self.snapshot()?;
+            let counters = **self.counters(&lock)?;
+            Ok(counters)
+        };
+
+        let start = Instant::now();
+        let initial = read_counters()?;
+
+        loop {
+            let remaining = match timeout.checked_sub(start.elapsed()) {
+                Some(remaining) if remaining > Duration::from_secs(0) => remaining,
+                _ => return Ok(false)
+            };
+
+            thread::sleep(COUNTER_POLL_INTERVAL.min(remaining));
+
+            if read_counters()? != initial {
+                return Ok(true);
+            }
+        }
+    }
+
     /// Get the FTL shared memory settings data. The resulting trait object can
     /// dereference into `&FtlSettings`.
     pub fn settings<'lock>(
         &'lock self,
-        _lock_guard: &ShmLockGuard<'lock>
+        lock_guard: &ShmLockGuard<'lock>
     ) -> Result<Box<dyn Deref<Target = FtlSettings> + 'lock>, Error> {
+        require_lock(lock_guard)?;
+
         Ok(match self {
             FtlMemory::Production { .. } => Box::new(Map::new(Object::open(FTL_SHM_SETTINGS)?)?),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-harness"))]
             FtlMemory::Test { settings, .. } => Box::new(settings)
         })
     }
 }
+
+/// Make sure the guard holds the shared memory lock. Snapshot guards can only
+/// be used for the data in the snapshot.
+fn require_lock(lock_guard: &ShmLockGuard) -> Result<(), Error> {
+    match lock_guard {
+        ShmLockGuard::Snapshot(_) => Err(Error::from(ErrorKind::SharedMemoryLock)),
+        _ => Ok(())
+    }
+}
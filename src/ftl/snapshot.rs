@@ -0,0 +1,157 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Shared Memory Snapshots
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{FtlClient, FtlCounters, FtlDomain},
+    util::Error
+};
+use libc;
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+/// A copy of the counters, domains, clients, and strings in shared memory.
+/// Requests share the same snapshot until it is older than the snapshot TTL,
+/// so that bursts of requests do not each take the shared memory lock.
+pub struct FtlSnapshot {
+    pub counters: FtlCounters,
+    pub domains: Vec<FtlDomain>,
+    pub clients: Vec<FtlClient>,
+    pub strings: Arc<[libc::c_char]>,
+    pub taken: Instant
+}
+
+/// Keeps the latest snapshot, and takes a new one when it has expired
+#[derive(Default)]
+pub struct SnapshotCache {
+    ttl: Duration,
+    latest: Mutex<Option<Arc<FtlSnapshot>>>
+}
+
+impl SnapshotCache {
+    /// Create a cache which keeps snapshots for `ttl`. A TTL of zero disables
+    /// snapshots.
+    pub fn new(ttl: Duration) -> SnapshotCache {
+        SnapshotCache {
+            ttl,
+            latest: Mutex::new(None)
+        }
+    }
+
+    /// Check if snapshots are used
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > Duration::default()
+    }
+
+    /// Get the latest snapshot, or take a new one with `take` if it has
+    /// expired. Requests which arrive while a snapshot is being taken wait
+    /// for it instead of taking their own.
+    pub fn get_or_take(
+        &self,
+        take: impl FnOnce() -> Result<FtlSnapshot, Error>
+    ) -> Result<Arc<FtlSnapshot>, Error> {
+        let mut latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(ref snapshot) = *latest {
+            if snapshot.taken.elapsed() < self.ttl {
+                return Ok(Arc::clone(snapshot));
+            }
+        }
+
+        let snapshot = Arc::new(take()?);
+        *latest = Some(Arc::clone(&snapshot));
+
+        Ok(snapshot)
+    }
+}
+
+/// A reference to part of a snapshot, which keeps the snapshot alive. It is
+/// used to return snapshot data from the `FtlMemory` accessors.
+pub struct SnapshotRef<T: ?Sized> {
+    snapshot: Arc<FtlSnapshot>,
+    get: fn(&FtlSnapshot) -> &T
+}
+
+impl<T: ?Sized> SnapshotRef<T> {
+    /// Reference the part of the snapshot returned by `get`
+    pub fn new(snapshot: &Arc<FtlSnapshot>, get: fn(&FtlSnapshot) -> &T) -> SnapshotRef<T> {
+        SnapshotRef {
+            snapshot: Arc::clone(snapshot),
+            get
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for SnapshotRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.get)(&self.snapshot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FtlSnapshot, SnapshotCache};
+    use crate::{ftl::FtlCounters, util::Error};
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant}
+    };
+
+    /// Create an empty snapshot with the number of queries
+    fn snapshot(total_queries: i32) -> Result<FtlSnapshot, Error> {
+        Ok(FtlSnapshot {
+            counters: FtlCounters {
+                total_queries,
+                ..FtlCounters::default()
+            },
+            domains: Vec::new(),
+            clients: Vec::new(),
+            strings: Arc::from(Vec::new()),
+            taken: Instant::now()
+        })
+    }
+
+    /// The snapshot is reused until it expires
+    #[test]
+    fn reuse_until_expired() {
+        let cache = SnapshotCache::new(Duration::from_secs(60));
+
+        let first = cache.get_or_take(|| snapshot(1)).unwrap();
+        let second = cache.get_or_take(|| snapshot(2)).unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(second.counters.total_queries, 1);
+
+        let cache = SnapshotCache::new(Duration::from_nanos(1));
+
+        cache.get_or_take(|| snapshot(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(
+            cache
+                .get_or_take(|| snapshot(2))
+                .unwrap()
+                .counters
+                .total_queries,
+            2
+        );
+    }
+
+    /// A TTL of zero disables snapshots
+    #[test]
+    fn disabled() {
+        assert!(!SnapshotCache::new(Duration::default()).is_enabled());
+        assert!(SnapshotCache::new(Duration::from_secs(1)).is_enabled());
+    }
+}
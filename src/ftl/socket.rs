@@ -8,15 +8,19 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::util::{Error, ErrorKind};
+use crate::{
+    fault_injection::{inject_fault, Fault},
+    util::{Error, ErrorKind}
+};
 use failure::{Fail, ResultExt};
 use rmp::{
-    decode::{self, DecodeStringError, ValueReadError},
+    decode::{self, ValueReadError},
     Marker
 };
 use std::{
-    io::{prelude::*, BufReader},
-    os::unix::net::UnixStream
+    io::{self, prelude::*, BufReader},
+    os::unix::net::UnixStream,
+    sync::Mutex
 };
 
 #[cfg(test)]
@@ -27,53 +31,200 @@ use std::io::Cursor;
 /// The location of the FTL socket
 const SOCKET_LOCATION: &str = "/var/run/pihole/FTL.sock";
 
-/// A wrapper around the FTL socket to easily read in data. It takes a
-/// Box<Read> so that it can be tested with fake data from a Vec<u8>
-pub struct FtlConnection<'test>(Box<dyn Read + 'test>);
+/// The maximum length of a string read from FTL. Longer strings are treated as
+/// corrupt data.
+pub const MAX_STRING_LENGTH: u32 = 4096;
+
+/// The maximum number of elements in an array read from FTL. Larger arrays are
+/// treated as corrupt data.
+pub const MAX_ARRAY_LENGTH: u32 = 100_000;
+
+/// The maximum number of entries in a map read from FTL. Larger maps are
+/// treated as corrupt data.
+pub const MAX_MAP_LENGTH: u32 = 10_000;
+
+/// The maximum number of idle connections kept open in a pool
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// A wrapper around the FTL socket to easily read in data. Socket connections
+/// come from a [`SocketPool`], and are returned to it when dropped if the
+/// whole reply was read. Otherwise it reads from a `Box<Read>` so that it can
+/// be tested with fake data from a `Vec<u8>`.
+///
+/// [`SocketPool`]: struct.SocketPool.html
+pub struct FtlConnection<'test> {
+    reader: FtlReader<'test>,
+    /// If the reply has been read up to the EOM, so the socket can be reused
+    complete: bool
+}
+
+/// The source of the data read by an [`FtlConnection`]
+///
+/// [`FtlConnection`]: struct.FtlConnection.html
+enum FtlReader<'test> {
+    /// A socket from the pool. It is only `None` while being returned.
+    Pooled {
+        stream: Option<BufReader<UnixStream>>,
+        pool: &'test SocketPool
+    },
+    /// Fake reply data, only constructed in tests
+    #[cfg_attr(not(test), allow(dead_code))]
+    Data(Box<dyn Read + 'test>)
+}
+
+impl<'test> Read for FtlReader<'test> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FtlReader::Pooled { stream, .. } => match stream {
+                Some(stream) => stream.read(buf),
+                None => Ok(0)
+            },
+            FtlReader::Data(data) => data.read(buf)
+        }
+    }
+}
+
+impl<'test> Drop for FtlConnection<'test> {
+    fn drop(&mut self) {
+        // A socket with unread data would give the next command the wrong
+        // reply, so only sockets which were read to the end are reused
+        if let FtlReader::Pooled { stream, pool } = &mut self.reader {
+            if let (true, Some(stream)) = (self.complete, stream.take()) {
+                pool.release(stream);
+            }
+        }
+    }
+}
+
+/// A pool of persistent connections to the FTL socket. FTL keeps a socket
+/// connection open after replying to a command, so the connection can be used
+/// for the next command instead of connecting again.
+#[derive(Default)]
+pub struct SocketPool {
+    idle: Mutex<Vec<BufReader<UnixStream>>>
+}
+
+impl SocketPool {
+    /// Get a connection and send the command. Idle connections are checked
+    /// before they are used. If sending the command on an idle connection
+    /// fails, FTL has closed it and a new connection is made.
+    fn send(&self, command: &str) -> Result<BufReader<UnixStream>, Error> {
+        let message = format!(">{}\n", command);
+
+        while let Some(mut stream) = self.take_idle() {
+            if stream.get_mut().write_all(message.as_bytes()).is_ok() {
+                return Ok(stream);
+            }
+        }
+
+        // Try to connect to FTL
+        let mut stream = match UnixStream::connect(SOCKET_LOCATION) {
+            Ok(s) => s,
+            Err(_) => return Err(Error::from(ErrorKind::FtlConnectionFail))
+        };
+
+        // Send the command
+        stream
+            .write_all(message.as_bytes())
+            .context(ErrorKind::FtlConnectionFail)?;
+
+        Ok(BufReader::new(stream))
+    }
+
+    /// Take a healthy idle connection, if there is one. Unhealthy connections
+    /// are closed.
+    fn take_idle(&self) -> Option<BufReader<UnixStream>> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+
+        while let Some(stream) = idle.pop() {
+            if is_healthy(stream.get_ref()) {
+                return Some(stream);
+            }
+        }
+
+        None
+    }
+
+    /// Return a connection to the pool, or close it if the pool is full
+    fn release(&self, stream: BufReader<UnixStream>) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(stream);
+        }
+    }
+
+    /// The number of idle connections in the pool
+    #[cfg(test)]
+    fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// Check if an idle socket is still usable. It must still be open, and there
+/// must not be any data waiting, which would be left over from another reply.
+fn is_healthy(stream: &UnixStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let mut buffer = [0u8; 1];
+    let healthy = match (&*stream).read(&mut buffer) {
+        // No data is waiting, and the socket is open
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => true,
+        // The socket was closed, has unexpected data, or is broken
+        _ => false
+    };
+
+    healthy && stream.set_nonblocking(false).is_ok()
+}
 
 /// A marker for the type of FTL connection to make.
 ///
-/// - Socket refers to the normal Unix socket connection.
+/// - Socket refers to the normal Unix socket connection, using a pool of
+/// connections.
 /// - Test is for testing, so that a test can pass in arbitrary MessagePack
 /// data to be processed.   The map in Test maps FTL commands to data.
 pub enum FtlConnectionType {
-    Socket,
+    Socket(SocketPool),
     #[cfg(test)]
     Test(HashMap<String, Vec<u8>>)
 }
 
 impl FtlConnectionType {
+    /// Create a socket connection type with an empty pool
+    pub fn socket() -> FtlConnectionType {
+        FtlConnectionType::Socket(SocketPool::default())
+    }
+
     /// Connect to FTL and run the specified command
     pub fn connect(&self, command: &str) -> Result<FtlConnection, Error> {
+        inject_fault(Fault::FtlSocket)?;
+
         // Determine the type of connection to create
-        match *self {
-            FtlConnectionType::Socket => {
-                // Try to connect to FTL
-                let mut stream = match UnixStream::connect(SOCKET_LOCATION) {
-                    Ok(s) => s,
-                    Err(_) => return Err(Error::from(ErrorKind::FtlConnectionFail))
-                };
-
-                // Send the command
-                stream
-                    .write_all(format!(">{}\n", command).as_bytes())
-                    .context(ErrorKind::FtlConnectionFail)?;
-
-                // Return the connection so the API can read the response
-                Ok(FtlConnection(Box::new(BufReader::new(stream))))
-            }
+        let reader = match *self {
+            FtlConnectionType::Socket(ref pool) => FtlReader::Pooled {
+                stream: Some(pool.send(command)?),
+                pool
+            },
             #[cfg(test)]
             FtlConnectionType::Test(ref map) => {
-                // Return a connection reading the testing data
-                Ok(FtlConnection(Box::new(Cursor::new(
+                // Read the testing data
+                FtlReader::Data(Box::new(Cursor::new(
                     // Try to get the testing data for this command
                     match map.get(command) {
                         Some(data) => data,
                         None => return Err(Error::from(ErrorKind::FtlConnectionFail))
                     }
-                ))))
+                )))
             }
-        }
+        };
+
+        // Return the connection so the API can read the response
+        Ok(FtlConnection {
+            reader,
+            complete: false
+        })
     }
 }
 
@@ -91,26 +242,13 @@ impl<'test> FtlConnection<'test> {
         })
     }
 
-    fn handle_eom_str<T>(result: Result<T, DecodeStringError>) -> Result<T, Error> {
-        result.map_err(|e| {
-            if let DecodeStringError::TypeMismatch(ref marker) = e {
-                if *marker == Marker::Reserved {
-                    // Received EOM
-                    return Error::from(ErrorKind::FtlEomError);
-                }
-            }
-
-            Error::from(ErrorKind::FtlReadError)
-        })
-    }
-
     /// We expect an end of message (EOM) response when FTL has finished
     /// sending data
     pub fn expect_eom(&mut self) -> Result<(), Error> {
         let mut buffer: [u8; 1] = [0];
 
         // Read exactly 1 byte
-        match self.0.read_exact(&mut buffer) {
+        match self.reader.read_exact(&mut buffer) {
             Ok(_) => (),
             Err(e) => return Err(Error::from(e.context(ErrorKind::FtlReadError)))
         }
@@ -120,21 +258,331 @@ impl<'test> FtlConnection<'test> {
             return Err(Error::from(ErrorKind::FtlReadError));
         }
 
+        self.complete = true;
         Ok(())
     }
 
     /// Read in an i32 (signed int) value
     pub fn read_i32(&mut self) -> Result<i32, Error> {
-        FtlConnection::handle_eom_value(decode::read_i32(&mut self.0))
+        FtlConnection::handle_eom_value(decode::read_i32(&mut self.reader))
     }
 
     /// Read in an i64 (signed long int) value
     pub fn read_i64(&mut self) -> Result<i64, Error> {
-        FtlConnection::handle_eom_value(decode::read_i64(&mut self.0))
+        FtlConnection::handle_eom_value(decode::read_i64(&mut self.reader))
+    }
+
+    /// Read in the number of elements of an array. The length sent by FTL is
+    /// checked against [`MAX_ARRAY_LENGTH`], so it is safe to allocate for.
+    ///
+    /// [`MAX_ARRAY_LENGTH`]: constant.MAX_ARRAY_LENGTH.html
+    pub fn read_array_len(&mut self) -> Result<u32, Error> {
+        let len = FtlConnection::handle_eom_value(decode::read_array_len(&mut self.reader))?;
+
+        if len > MAX_ARRAY_LENGTH {
+            return Err(Error::from(ErrorKind::FtlReadError));
+        }
+
+        Ok(len)
+    }
+
+    /// Read in the number of entries of a map. The length sent by FTL is
+    /// checked against [`MAX_MAP_LENGTH`], so it is safe to allocate for.
+    ///
+    /// [`MAX_MAP_LENGTH`]: constant.MAX_MAP_LENGTH.html
+    pub fn read_map_len(&mut self) -> Result<u32, Error> {
+        let len = FtlConnection::handle_eom_value(decode::read_map_len(&mut self.reader))?;
+
+        if len > MAX_MAP_LENGTH {
+            return Err(Error::from(ErrorKind::FtlReadError));
+        }
+
+        Ok(len)
+    }
+
+    /// Read in an owned string. The length sent by FTL is checked against
+    /// [`MAX_STRING_LENGTH`] before any memory is allocated.
+    ///
+    /// [`MAX_STRING_LENGTH`]: constant.MAX_STRING_LENGTH.html
+    pub fn read_string(&mut self) -> Result<String, Error> {
+        let len = FtlConnection::handle_eom_value(decode::read_str_len(&mut self.reader))?;
+
+        if len > MAX_STRING_LENGTH {
+            return Err(Error::from(ErrorKind::FtlReadError));
+        }
+
+        let mut buffer = vec![0u8; len as usize];
+        self.reader
+            .read_exact(&mut buffer)
+            .context(ErrorKind::FtlReadError)?;
+
+        Ok(String::from_utf8(buffer).context(ErrorKind::FtlReadError)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        FtlConnection, FtlConnectionType, FtlReader, SocketPool, MAX_ARRAY_LENGTH, MAX_MAP_LENGTH,
+        MAX_STRING_LENGTH
+    };
+    use crate::{testing::write_eom, util::ErrorKind};
+    use rmp::encode;
+    use std::{
+        io::{prelude::*, BufReader, Cursor},
+        os::unix::net::UnixStream
+    };
+
+    /// Create a connection which reads the data
+    fn connection(data: Vec<u8>) -> FtlConnection<'static> {
+        FtlConnection {
+            reader: FtlReader::Data(Box::new(Cursor::new(data))),
+            complete: false
+        }
+    }
+
+    /// Strings within the limit are read
+    #[test]
+    fn read_string_valid() {
+        let mut data = Vec::new();
+        encode::write_str(&mut data, "v4.2").unwrap();
+        write_eom(&mut data);
+
+        let mut con = connection(data);
+
+        assert_eq!(con.read_string().unwrap(), "v4.2");
+        assert!(con.expect_eom().is_ok());
+    }
+
+    /// A string length over the limit is rejected without reading the string
+    #[test]
+    fn read_string_too_long() {
+        let mut data = Vec::new();
+        encode::write_str_len(&mut data, MAX_STRING_LENGTH + 1).unwrap();
+
+        assert_eq!(
+            connection(data).read_string().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlReadError)
+        );
+    }
+
+    /// A string which is shorter than its length marker is an error
+    #[test]
+    fn read_string_truncated() {
+        let mut data = Vec::new();
+        encode::write_str_len(&mut data, 10).unwrap();
+        data.extend_from_slice(b"abc");
+
+        assert_eq!(
+            connection(data).read_string().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlReadError)
+        );
+    }
+
+    /// Invalid UTF-8 is an error
+    #[test]
+    fn read_string_invalid_utf8() {
+        let mut data = Vec::new();
+        encode::write_str_len(&mut data, 2).unwrap();
+        data.extend_from_slice(&[0xc3, 0x28]);
+
+        assert_eq!(
+            connection(data).read_string().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlReadError)
+        );
+    }
+
+    /// An EOM instead of a string is reported as an EOM error
+    #[test]
+    fn read_string_eom() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        assert_eq!(
+            connection(data).read_string().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlEomError)
+        );
+    }
+
+    /// Array and map lengths within the limits are read
+    #[test]
+    fn read_lengths_valid() {
+        let mut data = Vec::new();
+        encode::write_array_len(&mut data, MAX_ARRAY_LENGTH).unwrap();
+        encode::write_map_len(&mut data, MAX_MAP_LENGTH).unwrap();
+        write_eom(&mut data);
+
+        let mut con = connection(data);
+
+        assert_eq!(con.read_array_len().unwrap(), MAX_ARRAY_LENGTH);
+        assert_eq!(con.read_map_len().unwrap(), MAX_MAP_LENGTH);
+        assert!(con.expect_eom().is_ok());
+    }
+
+    /// Array and map lengths over the limits are rejected
+    #[test]
+    fn read_lengths_too_long() {
+        let mut data = Vec::new();
+        encode::write_array_len(&mut data, MAX_ARRAY_LENGTH + 1).unwrap();
+
+        assert_eq!(
+            connection(data).read_array_len().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlReadError)
+        );
+
+        let mut data = Vec::new();
+        encode::write_map_len(&mut data, MAX_MAP_LENGTH + 1).unwrap();
+
+        assert_eq!(
+            connection(data).read_map_len().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlReadError)
+        );
+    }
+
+    /// An EOM instead of a length is reported as an EOM error
+    #[test]
+    fn read_lengths_eom() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        assert_eq!(
+            connection(data.clone()).read_array_len().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlEomError)
+        );
+        assert_eq!(
+            connection(data).read_map_len().map_err(|e| e.kind()),
+            Err(ErrorKind::FtlEomError)
+        );
     }
 
-    /// Read in a string using the buffer
-    pub fn read_str<'a>(&mut self, buffer: &'a mut [u8]) -> Result<&'a str, Error> {
-        FtlConnection::handle_eom_str(decode::read_str(&mut self.0, buffer))
+    /// Feed pseudo-random data into every read function. Corrupt data must
+    /// result in errors, not panics or huge allocations.
+    #[test]
+    fn fuzz_corrupt_data() {
+        // A simple linear congruential generator, so the test is reproducible
+        let mut seed: u32 = 0x5eed;
+        let mut next_byte = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        };
+
+        for len in 0..2000 {
+            let data: Vec<u8> = (0..len % 64).map(|_| next_byte()).collect();
+            let mut con = connection(data);
+
+            for _ in 0..8 {
+                let _ = con.read_string();
+                let _ = con.read_array_len();
+                let _ = con.read_map_len();
+                let _ = con.read_i32();
+                let _ = con.read_i64();
+                let _ = con.expect_eom();
+            }
+        }
+    }
+
+    /// Huge length prefixes of every size are rejected before anything is
+    /// allocated, even when no data follows them
+    #[test]
+    fn fuzz_huge_lengths() {
+        let lengths = [1 << 17, 1 << 24, u32::max_value() / 2, u32::max_value()];
+
+        for &len in lengths.iter() {
+            let mut data = Vec::new();
+            encode::write_str_len(&mut data, len).unwrap();
+            assert_eq!(
+                connection(data).read_string().map_err(|e| e.kind()),
+                Err(ErrorKind::FtlReadError)
+            );
+
+            let mut data = Vec::new();
+            encode::write_array_len(&mut data, len).unwrap();
+            assert_eq!(
+                connection(data).read_array_len().map_err(|e| e.kind()),
+                Err(ErrorKind::FtlReadError)
+            );
+
+            let mut data = Vec::new();
+            encode::write_map_len(&mut data, len).unwrap();
+            assert_eq!(
+                connection(data).read_map_len().map_err(|e| e.kind()),
+                Err(ErrorKind::FtlReadError)
+            );
+        }
+    }
+
+    /// Create a pool with one idle connection, and get the other end of it
+    fn pool_with_connection() -> (SocketPool, UnixStream) {
+        let (api_end, ftl_end) = UnixStream::pair().unwrap();
+        let pool = SocketPool::default();
+        pool.release(BufReader::new(api_end));
+
+        (pool, ftl_end)
+    }
+
+    /// Reply to a command with an i32 and an EOM
+    fn reply(ftl_end: &mut UnixStream, command: &str, value: i32) {
+        let mut buffer = vec![0u8; command.len() + 2];
+        ftl_end.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, format!(">{}\n", command).into_bytes());
+
+        let mut data = Vec::new();
+        encode::write_i32(&mut data, value).unwrap();
+        write_eom(&mut data);
+        ftl_end.write_all(&data).unwrap();
+    }
+
+    /// A connection whose reply was read to the end is reused
+    #[test]
+    fn pool_reuses_connection() {
+        let (pool, mut ftl_end) = pool_with_connection();
+        let ftl = FtlConnectionType::Socket(pool);
+
+        for value in 1..=2 {
+            let mut con = ftl.connect("stats").unwrap();
+            reply(&mut ftl_end, "stats", value);
+
+            assert_eq!(con.read_i32().unwrap(), value);
+            assert!(con.expect_eom().is_ok());
+        }
+
+        match ftl {
+            FtlConnectionType::Socket(ref pool) => assert_eq!(pool.idle_count(), 1),
+            _ => unreachable!()
+        }
+    }
+
+    /// A connection which was not read to the end is closed instead of
+    /// being reused
+    #[test]
+    fn pool_discards_unfinished_connection() {
+        let (pool, mut ftl_end) = pool_with_connection();
+        let ftl = FtlConnectionType::Socket(pool);
+
+        let mut con = ftl.connect("stats").unwrap();
+        reply(&mut ftl_end, "stats", 1);
+        assert_eq!(con.read_i32().unwrap(), 1);
+        drop(con);
+
+        match ftl {
+            FtlConnectionType::Socket(ref pool) => assert_eq!(pool.idle_count(), 0),
+            _ => unreachable!()
+        }
+    }
+
+    /// Idle connections which were closed by FTL or have unread data are not
+    /// used
+    #[test]
+    fn pool_health_check() {
+        let (pool, ftl_end) = pool_with_connection();
+        drop(ftl_end);
+        assert!(pool.take_idle().is_none());
+
+        let (pool, mut ftl_end) = pool_with_connection();
+        ftl_end.write_all(&[0xc1]).unwrap();
+        assert!(pool.take_idle().is_none());
+
+        let (pool, _ftl_end) = pool_with_connection();
+        assert!(pool.take_idle().is_some());
     }
 }
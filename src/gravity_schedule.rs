@@ -0,0 +1,457 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Update Scheduler
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    api_state::ApiState,
+    env::{Env, PiholeFile},
+    services::{fetch_adlists, GravityBuilder, GravityJob},
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{current_time, Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    fs,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+/// How often the schedule is checked. Setting changes take effect on the next
+/// check.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long to wait before trying again after skipping an update because the
+/// connection is metered
+const METERED_RETRY_SECS: u64 = 60 * 60;
+
+/// The most hours allowed between updates, which is one year
+pub const MAX_FREQUENCY: u64 = 365 * 24;
+
+/// The most minutes of jitter allowed, which is the longest time between
+/// updates
+pub const MAX_JITTER: u64 = MAX_FREQUENCY * 60;
+
+/// Runs periodic Gravity updates. The time between updates is configurable,
+/// and a random delay (jitter) is added to each update so that installs do not
+/// all download lists at the same time. Scheduled updates are disabled by
+/// default, because the weekly `pihole -g` cron job also updates Gravity. The
+/// cron job should be removed when they are enabled. Scheduled updates run in
+/// the same job slot as updates requested through the API.
+#[derive(Clone)]
+pub struct GravitySchedule {
+    data: Arc<Mutex<ScheduleData>>,
+    builder: GravityBuilder,
+    job: GravityJob
+}
+
+/// The mutable schedule data
+#[derive(Default)]
+struct ScheduleData {
+    /// When Gravity was last updated, as a Unix timestamp
+    last_update: Option<u64>,
+    /// The random delay added to the next update, in seconds. It is limited
+    /// by the configured jitter when the next update time is calculated.
+    jitter_offset: u64,
+    /// When an update should be tried again after being skipped
+    retry_at: Option<u64>,
+    last_status: Option<UpdateStatus>
+}
+
+/// The outcome of a scheduled update
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    Success,
+    Failed,
+    SkippedMetered,
+    /// Another update was already running, so it counts as this update
+    SkippedRunning
+}
+
+/// The scheduled update settings, read from setupVars.conf
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ScheduleSettings {
+    /// The hours between updates. Zero disables scheduled updates, which is
+    /// the default.
+    pub frequency: u64,
+    /// The maximum random delay added to each update, in minutes
+    pub jitter: u64,
+    /// Skip updates while on a metered connection
+    pub skip_metered: bool,
+    /// Build Gravity in the API instead of with `pihole -g`
+    #[serde(default)]
+    pub native_build: bool
+}
+
+/// The reply format of the schedule status
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ScheduleStatus {
+    pub last_update: Option<u64>,
+    pub next_update: Option<u64>,
+    pub last_status: Option<UpdateStatus>
+}
+
+impl ScheduleSettings {
+    /// Read the schedule settings
+    pub fn read(env: &Env) -> Result<ScheduleSettings, Error> {
+        Ok(ScheduleSettings {
+            frequency: SetupVarsEntry::GravityUpdateFrequency.read_as(env)?,
+            jitter: SetupVarsEntry::GravityUpdateJitter.read_as(env)?,
+            skip_metered: SetupVarsEntry::GravitySkipMetered.is_true(env)?,
+            native_build: SetupVarsEntry::GravityNativeBuild.is_true(env)?
+        })
+    }
+
+    /// Write the schedule settings
+    pub fn write(&self, env: &Env) -> Result<(), Error> {
+        SetupVarsEntry::GravityUpdateFrequency.write(&self.frequency.to_string(), env)?;
+        SetupVarsEntry::GravityUpdateJitter.write(&self.jitter.to_string(), env)?;
+        SetupVarsEntry::GravitySkipMetered.write(&self.skip_metered.to_string(), env)?;
+        SetupVarsEntry::GravityNativeBuild.write(&self.native_build.to_string(), env)
+    }
+}
+
+impl GravitySchedule {
+    /// Create a schedule which runs its updates in `job`
+    pub fn new(job: GravityJob) -> GravitySchedule {
+        GravitySchedule {
+            data: Arc::default(),
+            builder: GravityBuilder::default(),
+            job
+        }
+    }
+
+    /// Get the status of the schedule, using the current settings to calculate
+    /// the next update
+    pub fn status(&self, settings: &ScheduleSettings) -> ScheduleStatus {
+        let data = self.lock();
+
+        ScheduleStatus {
+            last_update: data.last_update,
+            next_update: data.next_update(settings),
+            last_status: data.last_status
+        }
+    }
+
+    /// Get the builder used when Gravity is built in the API, to read its
+    /// progress
+    pub fn builder(&self) -> GravityBuilder {
+        self.builder.clone()
+    }
+
+    /// Start checking the schedule in a background thread. The last update
+    /// time is taken from the Gravity list, so restarting the API does not
+    /// delay updates. If there is no Gravity list, the schedule starts now.
+    /// A retry of a skipped update is also kept across restarts.
+    pub fn start(&self, env: Env) {
+        {
+            let mut data = self.lock();
+            data.last_update = Some(gravity_modified(&env).unwrap_or_else(current_time));
+            data.jitter_offset = random_secs();
+            data.retry_at = ApiState::read(&env)
+                .ok()
+                .and_then(|state| state.gravity_retry_at);
+        }
+
+        let schedule = self.clone();
+
+        thread::spawn(move || loop {
+            if let Err(e) = schedule.check(&env) {
+                e.print_stacktrace();
+            }
+
+            thread::sleep(CHECK_INTERVAL);
+        });
+    }
+
+    /// Run an update if one is due
+    fn check(&self, env: &Env) -> Result<(), Error> {
+        let settings = ScheduleSettings::read(env)?;
+        let now = current_time();
+
+        match self.lock().next_update(&settings) {
+            Some(next_update) if next_update <= now => (),
+            _ => return Ok(())
+        }
+
+        if settings.skip_metered && is_metered() {
+            let retry_at = now + METERED_RETRY_SECS;
+            {
+                let mut data = self.lock();
+                data.retry_at = Some(retry_at);
+                data.last_status = Some(UpdateStatus::SkippedMetered);
+            }
+
+            // Save the retry so it is not lost if the API restarts
+            return ApiState::update(env, |state| state.gravity_retry_at = Some(retry_at));
+        }
+
+        // Only one update runs at a time. An update which is already running
+        // counts as the scheduled update.
+        if self.job.begin().is_err() {
+            return self.record_update(env, now, UpdateStatus::SkippedRunning);
+        }
+
+        let result = self.update_gravity(env, settings.native_build);
+        self.job.finish(result.is_ok());
+
+        self.record_update(
+            env,
+            now,
+            if result.is_ok() {
+                UpdateStatus::Success
+            } else {
+                UpdateStatus::Failed
+            }
+        )?;
+
+        result
+    }
+
+    /// Record an update which started at `now`, and clear any retry of a
+    /// skipped update
+    fn record_update(&self, env: &Env, now: u64, status: UpdateStatus) -> Result<(), Error> {
+        let had_retry = {
+            let mut data = self.lock();
+            data.last_update = Some(now);
+            data.jitter_offset = random_secs();
+            data.last_status = Some(status);
+            data.retry_at.take().is_some()
+        };
+
+        if had_retry {
+            ApiState::update(env, |state| state.gravity_retry_at = None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Update Gravity. The adlists are downloaded by the adlist fetcher, so
+    /// Gravity only has to be built from the downloaded lists, either in the
+    /// API or by `pihole -g`.
+    fn update_gravity(&self, env: &Env, native_build: bool) -> Result<(), Error> {
+        // Don't actually update Gravity during testing
+        if env.is_test() {
+            return Ok(());
+        }
+
+        fetch_adlists(env)?;
+
+        if native_build {
+            return self.builder.build(env);
+        }
+
+        let status = Command::new("sudo")
+            .arg("pihole")
+            .arg("-g")
+            .arg("--skip-download")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context(ErrorKind::GravityError)?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::GravityError))
+        }
+    }
+
+    /// Lock the schedule data. Ignore the poison error because the data is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<ScheduleData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ScheduleData {
+    /// Calculate when the next update should run. If scheduled updates are
+    /// disabled or the last update time is unknown, `None` is returned. The
+    /// settings may have been edited by hand, so overflowing values are
+    /// limited instead of trusted.
+    fn next_update(&self, settings: &ScheduleSettings) -> Option<u64> {
+        if settings.frequency == 0 {
+            return None;
+        }
+
+        let jitter = match settings.jitter.checked_mul(60).unwrap_or(u64::max_value()) {
+            0 => 0,
+            max_jitter => self.jitter_offset % max_jitter
+        };
+        let frequency = settings
+            .frequency
+            .checked_mul(60 * 60)
+            .unwrap_or(u64::max_value());
+        let scheduled = self
+            .last_update?
+            .saturating_add(frequency)
+            .saturating_add(jitter);
+
+        Some(match self.retry_at {
+            Some(retry_at) => scheduled.max(retry_at),
+            None => scheduled
+        })
+    }
+}
+
+/// Get the last modification time of the Gravity list, as a Unix timestamp
+pub fn gravity_modified(env: &Env) -> Option<u64> {
+    fs::metadata(env.file_location(PiholeFile::Gravity))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+}
+
+/// Check if NetworkManager reports a metered connection. If NetworkManager is
+/// not available, the connection is assumed to not be metered.
+fn is_metered() -> bool {
+    Command::new("nmcli")
+        .args(&["-t", "-f", "GENERAL.METERED", "device", "show"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .any(|line| line.starts_with("GENERAL.METERED:yes"))
+        })
+        .unwrap_or(false)
+}
+
+/// Get a pseudo-random number of seconds for the jitter. It only needs to
+/// differ between installs, so the sub-second part of the clock is enough.
+fn random_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GravitySchedule, ScheduleData, ScheduleSettings, UpdateStatus};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        services::{GravityJob, JobState},
+        testing::TestEnvBuilder
+    };
+
+    const SETTINGS: ScheduleSettings = ScheduleSettings {
+        frequency: 24,
+        jitter: 10,
+        skip_metered: true,
+        native_build: false
+    };
+
+    /// The next update is the frequency plus the jitter after the last update
+    #[test]
+    fn next_update_with_jitter() {
+        let data = ScheduleData {
+            last_update: Some(1000),
+            jitter_offset: 700,
+            ..ScheduleData::default()
+        };
+
+        assert_eq!(data.next_update(&SETTINGS), Some(1000 + 24 * 3600 + 100));
+    }
+
+    /// A frequency of zero disables scheduled updates
+    #[test]
+    fn disabled() {
+        let data = ScheduleData {
+            last_update: Some(1000),
+            ..ScheduleData::default()
+        };
+        let settings = ScheduleSettings {
+            frequency: 0,
+            ..SETTINGS
+        };
+
+        assert_eq!(data.next_update(&settings), None);
+    }
+
+    /// The next update is unknown until the last update is known
+    #[test]
+    fn unknown_last_update() {
+        assert_eq!(ScheduleData::default().next_update(&SETTINGS), None);
+    }
+
+    /// Skipped updates are retried later
+    #[test]
+    fn retry_after_skip() {
+        let data = ScheduleData {
+            last_update: Some(1000),
+            retry_at: Some(1_000_000),
+            ..ScheduleData::default()
+        };
+
+        assert_eq!(data.next_update(&SETTINGS), Some(1_000_000));
+    }
+
+    /// Settings too large to calculate with push the next update to the end
+    /// of time instead of overflowing
+    #[test]
+    fn overflowing_settings() {
+        let data = ScheduleData {
+            last_update: Some(1000),
+            jitter_offset: 700,
+            ..ScheduleData::default()
+        };
+        let settings = ScheduleSettings {
+            frequency: u64::max_value(),
+            jitter: u64::max_value(),
+            ..SETTINGS
+        };
+
+        assert_eq!(data.next_update(&settings), Some(u64::max_value()));
+    }
+
+    /// A due update runs in the shared job slot, unless another update is
+    /// already running there
+    #[test]
+    fn shared_job_slot() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::SetupVars,
+                    "GRAVITY_UPDATE_FREQUENCY=24\n\
+                     GRAVITY_UPDATE_JITTER=0\n\
+                     GRAVITY_SKIP_METERED=false\n"
+                )
+                .build()
+        );
+        let job = GravityJob::default();
+        let schedule = GravitySchedule::new(job.clone());
+
+        job.begin().unwrap();
+        schedule.lock().last_update = Some(0);
+        schedule.check(&env).unwrap();
+
+        assert_eq!(job.status().state, JobState::Running);
+        assert_eq!(
+            schedule.lock().last_status,
+            Some(UpdateStatus::SkippedRunning)
+        );
+
+        job.finish(true);
+        schedule.lock().last_update = Some(0);
+        schedule.check(&env).unwrap();
+
+        assert_eq!(job.status().state, JobState::Success);
+        assert_eq!(schedule.lock().last_status, Some(UpdateStatus::Success));
+    }
+}
@@ -10,6 +10,7 @@
 
 #![feature(proc_macro_hygiene, decl_macro)]
 #![allow(clippy::cast_lossless)]
+#![recursion_limit = "128"]
 
 #[macro_use]
 extern crate diesel;
@@ -22,15 +23,55 @@ extern crate rocket_contrib;
 #[macro_use]
 extern crate rust_embed;
 
-pub use crate::setup::start;
+pub use crate::{
+    plugins::{Plugin, QueryEvent, PLUGIN_API_VERSION},
+    setup::{start, start_with_config, start_with_plugins},
+    test_data::generate_test_data
+};
 
+/// The test shared memory and the Pi-hole files, for the integration tests
+#[cfg(feature = "test-harness")]
+pub use crate::{
+    env::PiholeFile,
+    ftl::{
+        FtlClient, FtlCounters, FtlDnssecType, FtlDomain, FtlMemory, FtlQuery, FtlQueryReplyType,
+        FtlQueryStatus, FtlQueryType, FtlRegexMatch, FtlSettings, FtlUpstream, MAGIC_BYTE
+    },
+    setup::start_with_ftl_memory
+};
+
+mod alert_thresholds;
+mod allowed_methods;
+mod api_keys;
+mod api_state;
+mod client_nicknames;
+mod cors;
+#[macro_use]
 mod databases;
+#[cfg(feature = "dylib-plugins")]
+mod dylib_plugins;
 mod env;
+mod fault_injection;
 mod ftl;
+mod gravity_schedule;
+mod log_rotation;
+mod metrics;
+mod notifications;
+mod permissions;
+mod plugins;
+mod process_info;
+mod query_purge;
+mod rate_limit;
 mod routes;
+mod security_headers;
+mod services;
 mod settings;
 mod setup;
+mod test_data;
+mod users;
 mod util;
+#[cfg(feature = "wasm-plugins")]
+mod wasm_plugins;
 
 #[cfg(test)]
 mod testing;
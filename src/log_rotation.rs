@@ -0,0 +1,234 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Log Rotation
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Config, Env},
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    thread,
+    time::{Duration, Instant}
+};
+
+/// How often the logs are checked for rotation
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// The log rotation settings, read from setupVars.conf
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct LogSettings {
+    /// The size in KiB at which a log is rotated. Zero disables size based
+    /// rotation.
+    pub max_size: u64,
+    /// The age in days at which a log is rotated. Zero disables age based
+    /// rotation.
+    pub max_age: u64,
+    /// The number of rotated logs to keep
+    pub retention: u64
+}
+
+/// The reply format of a log file
+#[derive(Serialize)]
+pub struct LogFile {
+    pub path: String,
+    pub size: u64,
+    pub rotated: usize
+}
+
+impl LogSettings {
+    /// Read the log rotation settings
+    pub fn read(env: &Env) -> Result<LogSettings, Error> {
+        Ok(LogSettings {
+            max_size: SetupVarsEntry::ApiLogMaxSize.read_as(env)?,
+            max_age: SetupVarsEntry::ApiLogMaxAge.read_as(env)?,
+            retention: SetupVarsEntry::ApiLogRetention.read_as(env)?
+        })
+    }
+
+    /// Write the log rotation settings
+    pub fn write(&self, env: &Env) -> Result<(), Error> {
+        SetupVarsEntry::ApiLogMaxSize.write(&self.max_size.to_string(), env)?;
+        SetupVarsEntry::ApiLogMaxAge.write(&self.max_age.to_string(), env)?;
+        SetupVarsEntry::ApiLogRetention.write(&self.retention.to_string(), env)
+    }
+
+    /// Check if a log of this size and age should be rotated
+    fn should_rotate(&self, size: u64, age: Duration) -> bool {
+        (self.max_size > 0 && size >= self.max_size.saturating_mul(1024))
+            || (self.max_age > 0 && age.as_secs() >= self.max_age.saturating_mul(24 * 60 * 60))
+    }
+}
+
+/// Get the locations of the logs written by the API
+pub fn api_logs(config: &Config) -> Vec<String> {
+    config
+        .request_log()
+        .into_iter()
+        .chain(config.api_log_file())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Get the size and number of rotated logs of each API log
+pub fn log_files(config: &Config) -> Vec<LogFile> {
+    api_logs(config)
+        .into_iter()
+        .map(|path| LogFile {
+            size: fs::metadata(&path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0),
+            rotated: (1..)
+                .take_while(|i| Path::new(&rotated_path(&path, *i)).exists())
+                .count(),
+            path
+        })
+        .collect()
+}
+
+/// Periodically rotate the API logs, in a background thread. The age of a log
+/// is measured from when the API started or the log was last rotated.
+pub fn start_log_rotation(env: Env) {
+    thread::spawn(move || {
+        let mut started: HashMap<String, Instant> = HashMap::new();
+
+        loop {
+            if let Err(e) = check_logs(&env, &mut started) {
+                e.print_stacktrace();
+            }
+
+            thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+/// Rotate the logs which are too large or too old
+fn check_logs(env: &Env, started: &mut HashMap<String, Instant>) -> Result<(), Error> {
+    let settings = LogSettings::read(env)?;
+
+    for path in api_logs(env.config()) {
+        let size = match fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            // The log has not been written yet
+            Err(_) => continue
+        };
+        let age = started
+            .entry(path.clone())
+            .or_insert_with(Instant::now)
+            .elapsed();
+
+        if settings.should_rotate(size, age) {
+            rotate(&path, settings.retention).context(ErrorKind::FileWrite(path.clone()))?;
+            started.insert(path, Instant::now());
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the location of a rotated log. The most recent is number 1.
+fn rotated_path(path: &str, number: u64) -> String {
+    format!("{}.{}", path, number)
+}
+
+/// Rotate the log, keeping `retention` rotated logs. Older logs are deleted.
+fn rotate(path: &str, retention: u64) -> io::Result<()> {
+    // Delete the logs which would be past the retention after rotating,
+    // including any left over from a higher retention setting
+    let mut number = retention.max(1);
+    while Path::new(&rotated_path(path, number)).exists() {
+        fs::remove_file(rotated_path(path, number))?;
+        number += 1;
+    }
+
+    if retention == 0 {
+        return fs::remove_file(path);
+    }
+
+    for number in (1..retention).rev() {
+        let from = rotated_path(path, number);
+
+        if Path::new(&from).exists() {
+            fs::rename(from, rotated_path(path, number + 1))?;
+        }
+    }
+
+    fs::rename(path, rotated_path(path, 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rotate, rotated_path, LogSettings};
+    use std::{fs, path::Path, time::Duration};
+    use tempfile::tempdir;
+
+    const SETTINGS: LogSettings = LogSettings {
+        max_size: 1,
+        max_age: 1,
+        retention: 2
+    };
+
+    /// Logs are rotated when they are too large or too old
+    #[test]
+    fn should_rotate() {
+        assert!(!SETTINGS.should_rotate(1023, Duration::from_secs(60)));
+        assert!(SETTINGS.should_rotate(1024, Duration::from_secs(60)));
+        assert!(SETTINGS.should_rotate(0, Duration::from_secs(24 * 60 * 60)));
+    }
+
+    /// A limit of zero disables that kind of rotation
+    #[test]
+    fn should_rotate_disabled() {
+        let settings = LogSettings {
+            max_size: 0,
+            max_age: 0,
+            retention: 2
+        };
+
+        assert!(!settings.should_rotate(u64::max_value(), Duration::from_secs(u64::max_value())));
+    }
+
+    /// Rotating shifts the rotated logs and deletes those past the retention
+    #[test]
+    fn rotate_with_retention() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("api.log");
+        let path = path.to_str().unwrap();
+
+        for contents in &["first", "second", "third"] {
+            fs::write(path, contents).unwrap();
+            rotate(path, 2).unwrap();
+        }
+
+        assert!(!Path::new(path).exists());
+        assert_eq!(fs::read_to_string(rotated_path(path, 1)).unwrap(), "third");
+        assert_eq!(fs::read_to_string(rotated_path(path, 2)).unwrap(), "second");
+        assert!(!Path::new(&rotated_path(path, 3)).exists());
+    }
+
+    /// With no retention, the log is deleted
+    #[test]
+    fn rotate_without_retention() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("api.log");
+        let path = path.to_str().unwrap();
+
+        fs::write(path, "log").unwrap();
+        fs::write(rotated_path(path, 1), "old").unwrap();
+        rotate(path, 0).unwrap();
+
+        assert!(!Path::new(path).exists());
+        assert!(!Path::new(&rotated_path(path, 1)).exists());
+    }
+}
@@ -8,8 +8,17 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+use std::env;
+
 fn main() {
-    if let Err(e) = pihole_api::start() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("generate-test-data") => pihole_api::generate_test_data(&args[1..]),
+        _ => pihole_api::start()
+    };
+
+    if let Err(e) = result {
         e.print_stacktrace();
     }
 }
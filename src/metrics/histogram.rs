@@ -0,0 +1,94 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Latency Histogram
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use std::time::Duration;
+
+/// The upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// A latency histogram with fixed buckets (see [`LATENCY_BUCKETS`]). Latencies
+/// above the largest bucket are only included in the total count.
+///
+/// [`LATENCY_BUCKETS`]: constant.LATENCY_BUCKETS.html
+#[derive(Clone, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct LatencyHistogram {
+    buckets: [u64; 10],
+    count: u64,
+    sum: Duration
+}
+
+impl LatencyHistogram {
+    /// Record a latency in the histogram
+    pub fn observe(&mut self, latency: Duration) {
+        let seconds = duration_secs(latency);
+
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[i] += 1;
+        }
+
+        self.count += 1;
+        self.sum += latency;
+    }
+
+    /// Get the cumulative count of each bucket, paired with the bucket's upper
+    /// bound in seconds
+    pub fn cumulative_buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+
+        LATENCY_BUCKETS
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(&bound, &count)| {
+                total += count;
+                (bound, total)
+            })
+            .collect()
+    }
+
+    /// The number of recorded latencies
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of all recorded latencies, in seconds
+    pub fn sum_secs(&self) -> f64 {
+        duration_secs(self.sum)
+    }
+}
+
+/// Convert a duration into fractional seconds
+pub fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::LatencyHistogram;
+    use std::time::Duration;
+
+    /// Latencies are counted in the first bucket which can hold them, and the
+    /// cumulative counts include all smaller buckets
+    #[test]
+    fn cumulative_counts() {
+        let mut histogram = LatencyHistogram::default();
+
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(5));
+
+        let buckets = histogram.cumulative_buckets();
+
+        assert_eq!(buckets[0].1, 1);
+        assert_eq!(buckets[3].1, 2);
+        assert_eq!(buckets[9].1, 2);
+        assert_eq!(histogram.count(), 3);
+    }
+}
@@ -0,0 +1,18 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Server Metrics
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+mod histogram;
+mod prometheus;
+mod request_stats;
+mod request_timings;
+
+pub use self::{
+    histogram::LatencyHistogram, prometheus::render_metrics, request_stats::*, request_timings::*
+};
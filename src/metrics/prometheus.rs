@@ -0,0 +1,202 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Prometheus Metrics Exporter
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::metrics::{LatencyHistogram, RequestStatsReply};
+use std::fmt::Write;
+
+/// Render the request statistics in the Prometheus text exposition format.
+/// `routes` has the latency histogram of each route, and `lock_holds` has how
+/// long each route held the shared memory lock.
+pub fn render_metrics(
+    stats: &RequestStatsReply,
+    routes: &[(String, LatencyHistogram)],
+    lock_holds: &[(String, LatencyHistogram)]
+) -> String {
+    let mut output = String::new();
+
+    write_metric(
+        &mut output,
+        "pihole_api_requests_total",
+        "counter",
+        "Total number of requests handled",
+        stats.total_requests
+    );
+    write_metric(
+        &mut output,
+        "pihole_api_slow_requests_total",
+        "counter",
+        "Total number of requests which exceeded the slow request threshold",
+        stats.slow_requests
+    );
+    write_metric(
+        &mut output,
+        "pihole_api_long_lock_holds_total",
+        "counter",
+        "Total number of requests which held the shared memory lock longer than the lock hold \
+         threshold",
+        stats.long_lock_holds
+    );
+    write_metric(
+        &mut output,
+        "pihole_api_active_requests",
+        "gauge",
+        "Number of requests currently being handled",
+        stats.workers.active
+    );
+    write_metric(
+        &mut output,
+        "pihole_api_workers",
+        "gauge",
+        "Number of worker threads",
+        stats.workers.total
+    );
+    write_histograms(
+        &mut output,
+        "pihole_api_request_duration_seconds",
+        "Request latency by route",
+        routes
+    );
+    write_histograms(
+        &mut output,
+        "pihole_api_lock_hold_seconds",
+        "Time the shared memory lock was held by route",
+        lock_holds
+    );
+
+    output
+}
+
+/// Write a histogram metric, with a histogram for each route
+fn write_histograms(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    routes: &[(String, LatencyHistogram)]
+) {
+    // Writing to a string can not fail
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} histogram", name).unwrap();
+
+    for (route, histogram) in routes {
+        let route = escape_label(route);
+
+        for (bound, count) in histogram.cumulative_buckets() {
+            writeln!(
+                output,
+                "{}_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                name, route, bound, count
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            output,
+            "{}_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+            name,
+            route,
+            histogram.count()
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "{}_sum{{route=\"{}\"}} {}",
+            name,
+            route,
+            histogram.sum_secs()
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "{}_count{{route=\"{}\"}} {}",
+            name,
+            route,
+            histogram.count()
+        )
+        .unwrap();
+    }
+}
+
+/// Write a metric which has a single value
+fn write_metric(output: &mut String, name: &str, metric_type: &str, help: &str, value: usize) {
+    // Writing to a string can not fail
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} {}", name, metric_type).unwrap();
+    writeln!(output, "{} {}", name, value).unwrap();
+}
+
+/// Escape a label value, as required by the exposition format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_label, render_metrics};
+    use crate::metrics::{LatencyHistogram, LatencyReply, RequestStatsReply, WorkersReply};
+    use std::time::Duration;
+
+    /// Route histograms are rendered with cumulative buckets, sum, and count
+    #[test]
+    fn render_route_histogram() {
+        let stats = RequestStatsReply {
+            uptime: 10,
+            total_requests: 1,
+            requests_per_second: 0.1,
+            slow_requests: 0,
+            long_lock_holds: 0,
+            latency: LatencyReply {
+                samples: 1,
+                p50: 2000,
+                p90: 2000,
+                p99: 2000,
+                max: 2000
+            },
+            workers: WorkersReply {
+                total: 2,
+                active: 0,
+                peak: 1,
+                saturation: 0.0
+            }
+        };
+        let mut histogram = LatencyHistogram::default();
+        histogram.observe(Duration::from_millis(2));
+
+        let output = render_metrics(
+            &stats,
+            &[("GET /admin/api/version".to_owned(), histogram.clone())],
+            &[("GET /admin/api/version".to_owned(), histogram)]
+        );
+
+        assert!(output.contains("pihole_api_requests_total 1\n"));
+        assert!(output.contains("pihole_api_workers 2\n"));
+        assert!(output.contains(
+            "pihole_api_request_duration_seconds_bucket{route=\"GET \
+             /admin/api/version\",le=\"0.001\"} 0\n"
+        ));
+        assert!(output.contains(
+            "pihole_api_request_duration_seconds_bucket{route=\"GET \
+             /admin/api/version\",le=\"0.005\"} 1\n"
+        ));
+        assert!(output.contains(
+            "pihole_api_request_duration_seconds_count{route=\"GET /admin/api/version\"} 1\n"
+        ));
+        assert!(output
+            .contains("pihole_api_lock_hold_seconds_count{route=\"GET /admin/api/version\"} 1\n"));
+    }
+
+    /// Quotes and backslashes in labels are escaped
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,498 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Request Statistics
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    metrics::{histogram::duration_secs, LatencyHistogram, RequestTimings},
+    routes::auth::RequestUser
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Method,
+    Data, Request, Response, Rocket
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::OpenOptions,
+    io::Write,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant}
+};
+
+/// The number of latency samples kept for calculating percentiles
+const LATENCY_SAMPLES: usize = 1000;
+
+/// The route name used for requests which did not match a route
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// The shortest time between warnings about long lock holds by the same route,
+/// so a slow route does not flood the log
+const LOCK_HOLD_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Collects request throughput, latency, and worker saturation statistics.
+/// It is attached as a fairing to time the requests, and it is managed by
+/// Rocket so the statistics can be reported by the API. Requests which take
+/// longer than the slow request threshold are written to the request log if
+/// one is configured. Requests which hold the shared memory lock longer than
+/// the lock hold threshold are counted, written to the request log, and
+/// warned about on stderr at most once a minute per route.
+/// Successful changes are written to the request log with the name of the
+/// user who made them.
+#[derive(Clone)]
+pub struct RequestStats {
+    start_time: Instant,
+    slow_request_threshold: Duration,
+    lock_hold_threshold: Duration,
+    request_log: Option<String>,
+    data: Arc<Mutex<StatsData>>
+}
+
+/// The time when a request was received. It is stored in the request-local
+/// cache so the response fairing can calculate the latency.
+struct RequestStart(Instant);
+
+/// The mutable statistics data
+#[derive(Default)]
+struct StatsData {
+    workers: usize,
+    total_requests: usize,
+    active_requests: usize,
+    peak_active_requests: usize,
+    slow_requests: usize,
+    long_lock_holds: usize,
+    /// The most recent request latencies, in microseconds
+    latencies: VecDeque<u64>,
+    /// Latency histograms of each route
+    routes: HashMap<String, LatencyHistogram>,
+    /// Histograms of how long each route held the shared memory lock. Only
+    /// requests which took the lock are included.
+    lock_holds: HashMap<String, LatencyHistogram>,
+    /// When each route was last warned about for a long lock hold
+    lock_hold_warnings: HashMap<String, Instant>
+}
+
+/// The reply format of the request statistics
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct RequestStatsReply {
+    pub uptime: u64,
+    pub total_requests: usize,
+    pub requests_per_second: f64,
+    pub slow_requests: usize,
+    /// Requests which held the shared memory lock longer than the lock hold
+    /// threshold
+    pub long_lock_holds: usize,
+    pub latency: LatencyReply,
+    pub workers: WorkersReply
+}
+
+/// Latency percentiles in microseconds
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct LatencyReply {
+    pub samples: usize,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64
+}
+
+/// Worker thread usage. Saturation is the ratio of active requests to workers.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct WorkersReply {
+    pub total: usize,
+    pub active: usize,
+    pub peak: usize,
+    pub saturation: f64
+}
+
+impl RequestStats {
+    /// Create a new `RequestStats` with no recorded requests. Requests which
+    /// take longer than `slow_request_threshold`, or hold the shared memory
+    /// lock longer than `lock_hold_threshold`, will be logged.
+    pub fn new(
+        slow_request_threshold: Duration,
+        lock_hold_threshold: Duration,
+        request_log: Option<String>
+    ) -> RequestStats {
+        RequestStats {
+            start_time: Instant::now(),
+            slow_request_threshold,
+            lock_hold_threshold,
+            request_log,
+            data: Arc::new(Mutex::new(StatsData::default()))
+        }
+    }
+
+    /// Get a snapshot of the statistics in the reply format
+    pub fn reply(&self) -> RequestStatsReply {
+        self.lock().reply(self.start_time.elapsed())
+    }
+
+    /// Get a snapshot of the latency histogram of each route, sorted by route
+    pub fn route_histograms(&self) -> Vec<(String, LatencyHistogram)> {
+        sorted_histograms(&self.lock().routes)
+    }
+
+    /// Get a snapshot of the shared memory lock hold histogram of each route,
+    /// sorted by route
+    pub fn lock_hold_histograms(&self) -> Vec<(String, LatencyHistogram)> {
+        sorted_histograms(&self.lock().lock_holds)
+    }
+
+    /// Append the message to the request log, if there is one. Failures are
+    /// ignored so that logging can not fail the request.
+    fn write_request_log(&self, message: &str) {
+        if let Some(ref request_log) = self.request_log {
+            let _ = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(request_log)
+                .and_then(|mut file| writeln!(file, "{}", message));
+        }
+    }
+
+    /// Lock the statistics data. Ignore the poison error because the
+    /// statistics are still consistent enough to report.
+    fn lock(&self) -> MutexGuard<StatsData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Fairing for RequestStats {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Statistics",
+            kind: Kind::Attach | Kind::Request | Kind::Response
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        self.lock().workers = rocket.config().workers as usize;
+        Ok(rocket)
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| RequestStart(Instant::now()));
+        RequestTimings::reset();
+        self.lock().start_request();
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        let latency = start.0.elapsed();
+        let route = request
+            .route()
+            .map(|route| format!("{} {}", route.method, route.uri.path()))
+            .unwrap_or_else(|| UNMATCHED_ROUTE.to_owned());
+        let is_slow = latency > self.slow_request_threshold;
+        let timings = RequestTimings::current();
+
+        if is_slow {
+            let message = format!(
+                "Slow request: route=\"{}\" uri=\"{}\" status={} total_ms={:.1} \
+                 lock_wait_ms={:.1} db_ms={:.1}",
+                route,
+                request.uri(),
+                response.status().code,
+                duration_secs(latency) * 1000.0,
+                duration_secs(timings.lock_wait) * 1000.0,
+                duration_secs(timings.database) * 1000.0
+            );
+
+            self.write_request_log(&message);
+        }
+
+        let is_long_lock_hold = timings.lock_hold > self.lock_hold_threshold;
+
+        if is_long_lock_hold {
+            let message = format!(
+                "Long lock hold: route=\"{}\" uri=\"{}\" lock_hold_ms={:.1}",
+                route,
+                request.uri(),
+                duration_secs(timings.lock_hold) * 1000.0
+            );
+
+            self.write_request_log(&message);
+
+            if self.lock().should_warn_lock_hold(&route, Instant::now()) {
+                eprintln!(
+                    "Warning: {} held the shared memory lock for {:.1} ms",
+                    route,
+                    duration_secs(timings.lock_hold) * 1000.0
+                );
+            }
+        }
+
+        let is_change = request.method() != Method::Get && request.method() != Method::Head;
+
+        if let RequestUser(Some(ref user)) = *request.local_cache(|| RequestUser(None)) {
+            if is_change && response.status().class().is_success() {
+                self.write_request_log(&format!(
+                    "Change: user=\"{}\" route=\"{}\" uri=\"{}\" status={}",
+                    user,
+                    route,
+                    request.uri(),
+                    response.status().code
+                ));
+            }
+        }
+
+        self.lock().finish_request(
+            route,
+            latency,
+            timings.lock_hold,
+            is_slow,
+            is_long_lock_hold
+        );
+    }
+}
+
+impl StatsData {
+    /// Record that a request has started processing
+    fn start_request(&mut self) {
+        self.total_requests += 1;
+        self.active_requests += 1;
+
+        if self.active_requests > self.peak_active_requests {
+            self.peak_active_requests = self.active_requests;
+        }
+    }
+
+    /// Record that a request has finished processing, and save its latency
+    /// and how long it held the shared memory lock
+    fn finish_request(
+        &mut self,
+        route: String,
+        latency: Duration,
+        lock_hold: Duration,
+        is_slow: bool,
+        is_long_lock_hold: bool
+    ) {
+        self.active_requests = self.active_requests.saturating_sub(1);
+
+        if is_slow {
+            self.slow_requests += 1;
+        }
+
+        if is_long_lock_hold {
+            self.long_lock_holds += 1;
+        }
+
+        if lock_hold > Duration::default() {
+            self.lock_holds
+                .entry(route.clone())
+                .or_default()
+                .observe(lock_hold);
+        }
+
+        self.routes.entry(route).or_default().observe(latency);
+
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+
+        self.latencies
+            .push_back(latency.as_secs() * 1_000_000 + latency.subsec_micros() as u64);
+    }
+
+    /// Check if a long lock hold by the route should be warned about at
+    /// `now`. Each route is warned about at most once per
+    /// `LOCK_HOLD_WARNING_INTERVAL`.
+    fn should_warn_lock_hold(&mut self, route: &str, now: Instant) -> bool {
+        match self.lock_hold_warnings.get(route) {
+            Some(&last) if now.duration_since(last) < LOCK_HOLD_WARNING_INTERVAL => false,
+            _ => {
+                self.lock_hold_warnings.insert(route.to_owned(), now);
+                true
+            }
+        }
+    }
+
+    /// Create the reply format, given how long the API has been running
+    fn reply(&self, uptime: Duration) -> RequestStatsReply {
+        let mut latencies: Vec<u64> = self.latencies.iter().cloned().collect();
+        latencies.sort();
+
+        let uptime_secs = uptime.as_secs() as f64 + uptime.subsec_millis() as f64 / 1000.0;
+        let requests_per_second = if uptime_secs > 0.0 {
+            self.total_requests as f64 / uptime_secs
+        } else {
+            0.0
+        };
+
+        let saturation = if self.workers > 0 {
+            self.active_requests as f64 / self.workers as f64
+        } else {
+            0.0
+        };
+
+        RequestStatsReply {
+            uptime: uptime.as_secs(),
+            total_requests: self.total_requests,
+            requests_per_second,
+            slow_requests: self.slow_requests,
+            long_lock_holds: self.long_lock_holds,
+            latency: LatencyReply {
+                samples: latencies.len(),
+                p50: percentile(&latencies, 50),
+                p90: percentile(&latencies, 90),
+                p99: percentile(&latencies, 99),
+                max: latencies.last().cloned().unwrap_or_default()
+            },
+            workers: WorkersReply {
+                total: self.workers,
+                active: self.active_requests,
+                peak: self.peak_active_requests,
+                saturation
+            }
+        }
+    }
+}
+
+/// Copy the histograms, sorted by route
+fn sorted_histograms(
+    histograms: &HashMap<String, LatencyHistogram>
+) -> Vec<(String, LatencyHistogram)> {
+    let mut histograms: Vec<(String, LatencyHistogram)> = histograms
+        .iter()
+        .map(|(route, histogram)| (route.to_owned(), histogram.clone()))
+        .collect();
+
+    histograms.sort_by(|a, b| a.0.cmp(&b.0));
+    histograms
+}
+
+/// Get the value at the percentile using the nearest-rank method. The values
+/// must be sorted. If there are no values, zero is returned.
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (percent * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{percentile, StatsData, LOCK_HOLD_WARNING_INTERVAL};
+    use std::{
+        f64,
+        time::{Duration, Instant}
+    };
+
+    /// Percentiles use the nearest-rank method
+    #[test]
+    fn nearest_rank_percentile() {
+        let values: Vec<u64> = (1..=100).collect();
+
+        assert_eq!(percentile(&values, 50), 50);
+        assert_eq!(percentile(&values, 90), 90);
+        assert_eq!(percentile(&values, 99), 99);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+
+    /// Active requests, peak usage, and saturation are tracked as requests
+    /// start and finish
+    #[test]
+    fn worker_saturation() {
+        let mut data = StatsData {
+            workers: 4,
+            ..StatsData::default()
+        };
+
+        data.start_request();
+        data.start_request();
+        data.finish_request(
+            "GET /".to_owned(),
+            Duration::from_millis(3),
+            Duration::default(),
+            true,
+            false
+        );
+
+        let reply = data.reply(Duration::from_secs(2));
+
+        assert_eq!(reply.total_requests, 2);
+        assert_eq!(reply.slow_requests, 1);
+        assert!((reply.requests_per_second - 1.0).abs() < f64::EPSILON);
+        assert_eq!(reply.workers.active, 1);
+        assert_eq!(reply.workers.peak, 2);
+        assert!((reply.workers.saturation - 0.25).abs() < f64::EPSILON);
+        assert_eq!(reply.latency.samples, 1);
+        assert_eq!(reply.latency.max, 3000);
+    }
+
+    /// Only the most recent latency samples are kept
+    #[test]
+    fn latency_samples_bounded() {
+        let mut data = StatsData::default();
+
+        for _ in 0..super::LATENCY_SAMPLES + 10 {
+            data.start_request();
+            data.finish_request(
+                "GET /".to_owned(),
+                Duration::from_micros(10),
+                Duration::default(),
+                false,
+                false
+            );
+        }
+
+        assert_eq!(data.latencies.len(), super::LATENCY_SAMPLES);
+        assert_eq!(
+            data.routes["GET /"].count(),
+            super::LATENCY_SAMPLES as u64 + 10
+        );
+    }
+
+    /// Lock hold times are only recorded for requests which took the lock,
+    /// and long lock holds are counted
+    #[test]
+    fn lock_hold_histograms() {
+        let mut data = StatsData::default();
+
+        data.start_request();
+        data.finish_request(
+            "GET /stats".to_owned(),
+            Duration::from_millis(5),
+            Duration::from_millis(2),
+            false,
+            true
+        );
+        data.start_request();
+        data.finish_request(
+            "GET /version".to_owned(),
+            Duration::from_millis(5),
+            Duration::default(),
+            false,
+            false
+        );
+
+        assert_eq!(data.lock_holds.len(), 1);
+        assert_eq!(data.lock_holds["GET /stats"].count(), 1);
+        assert_eq!(data.reply(Duration::from_secs(1)).long_lock_holds, 1);
+    }
+
+    /// Long lock holds are warned about at most once per interval for each
+    /// route
+    #[test]
+    fn lock_hold_warnings_rate_limited() {
+        let mut data = StatsData::default();
+        let now = Instant::now();
+
+        assert!(data.should_warn_lock_hold("GET /stats", now));
+        assert!(!data.should_warn_lock_hold("GET /stats", now + Duration::from_secs(1)));
+        assert!(data.should_warn_lock_hold("GET /version", now));
+        assert!(data.should_warn_lock_hold("GET /stats", now + LOCK_HOLD_WARNING_INTERVAL));
+    }
+}
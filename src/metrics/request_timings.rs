@@ -0,0 +1,109 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Request Timing Components
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    fault_injection::{inject_fault, Fault},
+    util::Error
+};
+use std::{
+    cell::Cell,
+    time::{Duration, Instant}
+};
+
+thread_local! {
+    /// The timings of the request currently being handled by this thread.
+    /// Rocket handles each request on a single worker thread, so the timings
+    /// can be recorded without access to the request.
+    static TIMINGS: Cell<RequestTimings> = Cell::new(RequestTimings::default());
+}
+
+/// The time spent on different parts of a request
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct RequestTimings {
+    /// Time spent waiting for the shared memory lock
+    pub lock_wait: Duration,
+    /// Time spent holding the shared memory lock
+    pub lock_hold: Duration,
+    /// Time spent querying the database
+    pub database: Duration
+}
+
+impl RequestTimings {
+    /// Reset the timings of this thread. This is done when a new request is
+    /// received.
+    pub fn reset() {
+        TIMINGS.with(|timings| timings.set(RequestTimings::default()));
+    }
+
+    /// Get the timings recorded by this thread since the last reset
+    pub fn current() -> RequestTimings {
+        TIMINGS.with(Cell::get)
+    }
+}
+
+/// Add to the time spent waiting for the shared memory lock
+pub fn record_lock_wait(duration: Duration) {
+    TIMINGS.with(|timings| {
+        let mut current = timings.get();
+        current.lock_wait += duration;
+        timings.set(current);
+    });
+}
+
+/// Add to the time spent holding the shared memory lock
+pub fn record_lock_hold(duration: Duration) {
+    TIMINGS.with(|timings| {
+        let mut current = timings.get();
+        current.lock_hold += duration;
+        timings.set(current);
+    });
+}
+
+/// Run the database work in `f` and add its run time to the database time
+pub fn time_database<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    inject_fault(Fault::Database)?;
+
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+
+    TIMINGS.with(|timings| {
+        let mut current = timings.get();
+        current.database += elapsed;
+        timings.set(current);
+    });
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{record_lock_hold, record_lock_wait, time_database, RequestTimings};
+    use std::time::Duration;
+
+    /// Recorded timings accumulate until they are reset
+    #[test]
+    fn accumulate_and_reset() {
+        RequestTimings::reset();
+
+        record_lock_wait(Duration::from_millis(2));
+        record_lock_wait(Duration::from_millis(3));
+        record_lock_hold(Duration::from_millis(4));
+        assert_eq!(time_database(|| Ok(7)).unwrap(), 7);
+
+        let timings = RequestTimings::current();
+        assert_eq!(timings.lock_wait, Duration::from_millis(5));
+        assert_eq!(timings.lock_hold, Duration::from_millis(4));
+
+        RequestTimings::reset();
+        assert_eq!(RequestTimings::current(), RequestTimings::default());
+    }
+}
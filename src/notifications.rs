@@ -0,0 +1,591 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Login, New Client, Threat, And Threshold Notifications
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    alert_thresholds::{Metric, ThresholdEvent, ThresholdMonitor, ThresholdRule},
+    env::Env,
+    ftl::FtlMemory,
+    services::ThreatCategories,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{current_time, Error}
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex, MutexGuard
+    },
+    thread,
+    time::Duration
+};
+
+/// The number of alerts kept for the alerts endpoint
+const MAX_ALERTS: usize = 100;
+
+/// The number of login IPs remembered. When there are more, the IP which
+/// logged in least recently is forgotten.
+const MAX_LOGIN_IPS: usize = 1000;
+
+/// How long a login IP is remembered after its last login, in seconds
+const LOGIN_IP_EXPIRY: u64 = 30 * 24 * 60 * 60;
+
+/// The number of alerts which can wait to be sent to the webhook. Alerts
+/// raised while the queue is full are not sent.
+const WEBHOOK_QUEUE_SIZE: usize = 20;
+
+/// How often FTL's clients are checked for new clients
+const CLIENT_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often FTL's queries are checked for blocked threats
+const QUERY_SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the metrics of the threshold rules are checked
+const THRESHOLD_SCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Keeps track of the login IPs and clients which have been seen, and raises
+/// an alert when a new one appears, when a domain in the threat feed is
+/// blocked, or when a threshold rule is triggered or resolved. Alerts are kept
+/// in memory for the API to report, and are sent to the webhook if one is
+/// configured. Seen IPs and clients are not saved, so they are forgotten when
+/// the API restarts. Login IPs are also forgotten after `LOGIN_IP_EXPIRY`.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    data: Arc<Mutex<NotifierData>>
+}
+
+/// The mutable notifier data
+#[derive(Default)]
+struct NotifierData {
+    /// The time of the last login from each IP
+    login_ips: HashMap<String, u64>,
+    /// This is `None` until the first scan of clients, which only records the
+    /// existing clients
+    clients: Option<HashSet<String>>,
+    /// The most recent alerts, oldest first
+    alerts: VecDeque<Alert>,
+    /// The queue of alerts to send to the webhook. It is created when the
+    /// first alert is sent.
+    webhook_queue: Option<SyncSender<(String, Alert)>>
+}
+
+/// The kinds of notifications
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    NewLogin,
+    NewClient,
+    ThreatBlocked,
+    ThresholdExceeded,
+    ThresholdResolved
+}
+
+/// A notification of a new login IP or client, of a blocked threat, or of a
+/// metric crossing a threshold
+#[derive(Serialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// Threshold alerts are not about an IP, so they have no IP
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub ip: String,
+    pub timestamp: u64,
+    /// The blocked domain of a threat alert
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// The threat category of the blocked domain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// The metric of a threshold alert
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metric: Option<Metric>,
+    /// The value of the metric when the threshold was crossed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    /// The threshold which was crossed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>
+}
+
+impl Alert {
+    /// Create an alert for the IP, raised now
+    fn new(kind: AlertKind, ip: String) -> Alert {
+        Alert {
+            kind,
+            ip,
+            timestamp: current_time(),
+            domain: None,
+            category: None,
+            metric: None,
+            value: None,
+            threshold: None
+        }
+    }
+}
+
+impl Notifier {
+    /// Record a successful login, and raise an alert if the IP has not logged
+    /// in recently
+    pub fn login_succeeded(&self, ip: Option<IpAddr>, env: &Env) {
+        let ip = ip.map_or_else(|| "unknown".to_owned(), |ip| ip.to_string());
+
+        if !self.lock().login_seen(ip.clone(), current_time()) {
+            return;
+        }
+
+        if SetupVarsEntry::ApiNotifyNewLogin
+            .is_true(env)
+            .unwrap_or(false)
+        {
+            self.notify(Alert::new(AlertKind::NewLogin, ip), env);
+        }
+    }
+
+    /// Record the clients currently known to FTL, and raise an alert for each
+    /// client which has not been seen before. The first time this is called,
+    /// the clients are recorded without raising alerts.
+    pub fn clients_observed<I: IntoIterator<Item = String>>(&self, ips: I, env: &Env) {
+        let new_clients: Vec<String> = {
+            let mut data = self.lock();
+
+            match data.clients {
+                Some(ref mut clients) => ips
+                    .into_iter()
+                    .filter(|ip| clients.insert(ip.clone()))
+                    .collect(),
+                None => {
+                    data.clients = Some(ips.into_iter().collect());
+                    return;
+                }
+            }
+        };
+
+        if new_clients.is_empty()
+            || !SetupVarsEntry::ApiNotifyNewClient
+                .is_true(env)
+                .unwrap_or(false)
+        {
+            return;
+        }
+
+        for ip in new_clients {
+            self.notify(Alert::new(AlertKind::NewClient, ip), env);
+        }
+    }
+
+    /// Raise an alert for each blocked query of a domain in the threat feed.
+    /// The queries are the client IP and domain of each blocked query.
+    pub fn blocked_queries_observed<I: IntoIterator<Item = (String, String)>>(
+        &self,
+        queries: I,
+        threat_categories: &ThreatCategories,
+        env: &Env
+    ) {
+        if !SetupVarsEntry::ApiNotifyThreat
+            .is_true(env)
+            .unwrap_or(false)
+        {
+            return;
+        }
+
+        for (ip, domain) in queries {
+            if let Some(category) = threat_categories.category(&domain) {
+                self.notify(
+                    Alert {
+                        domain: Some(domain),
+                        category: Some(category),
+                        ..Alert::new(AlertKind::ThreatBlocked, ip)
+                    },
+                    env
+                );
+            }
+        }
+    }
+
+    /// Raise an alert for each threshold rule which was triggered or resolved
+    pub fn thresholds_crossed<I: IntoIterator<Item = ThresholdEvent>>(&self, events: I, env: &Env) {
+        for event in events {
+            let kind = if event.triggered {
+                AlertKind::ThresholdExceeded
+            } else {
+                AlertKind::ThresholdResolved
+            };
+
+            self.notify(
+                Alert {
+                    metric: Some(event.metric),
+                    value: Some(event.value),
+                    threshold: Some(event.threshold),
+                    ..Alert::new(kind, String::new())
+                },
+                env
+            );
+        }
+    }
+
+    /// Get the recent alerts, newest first
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.lock().alerts.iter().rev().cloned().collect()
+    }
+
+    /// Save the alert and queue it to be sent to the webhook
+    fn notify(&self, alert: Alert, env: &Env) {
+        let webhook = SetupVarsEntry::ApiNotifyWebhook
+            .read(env)
+            .unwrap_or_default();
+        let mut data = self.lock();
+
+        if data.alerts.len() == MAX_ALERTS {
+            data.alerts.pop_front();
+        }
+
+        data.alerts.push_back(alert.clone());
+
+        // Don't actually send anything during a test
+        if webhook.is_empty() || env.is_test() {
+            return;
+        }
+
+        let queue = data.webhook_queue.get_or_insert_with(start_webhook_sender);
+
+        if queue.try_send((webhook, alert)).is_err() {
+            eprintln!("Too many notifications are waiting, not sending to the webhook");
+        }
+    }
+
+    /// Lock the notifier data. Ignore the poison error because the data is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<NotifierData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl NotifierData {
+    /// Record a login from the IP at the time, and check if the IP has not
+    /// logged in within `LOGIN_IP_EXPIRY`. Expired IPs are removed, and if
+    /// there are still too many, the IP which logged in least recently is
+    /// removed.
+    fn login_seen(&mut self, ip: String, now: u64) -> bool {
+        self.login_ips
+            .retain(|_, &mut last_login| last_login.saturating_add(LOGIN_IP_EXPIRY) > now);
+
+        if self.login_ips.insert(ip, now).is_some() {
+            return false;
+        }
+
+        if self.login_ips.len() > MAX_LOGIN_IPS {
+            let oldest = self
+                .login_ips
+                .iter()
+                .min_by_key(|&(_, &last_login)| last_login)
+                .map(|(ip, _)| ip.clone());
+
+            if let Some(oldest) = oldest {
+                self.login_ips.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+/// Periodically check FTL's clients for new clients, in a background thread
+pub fn watch_clients(notifier: Notifier, ftl_memory: FtlMemory, env: Env) {
+    thread::spawn(move || loop {
+        match client_ips(&ftl_memory) {
+            Ok(ips) => notifier.clients_observed(ips, &env),
+            Err(e) => e.print_stacktrace()
+        }
+
+        thread::sleep(CLIENT_SCAN_INTERVAL);
+    });
+}
+
+/// Periodically check FTL's new queries for blocked threats, in a background
+/// thread. The first check only records the number of queries, so queries
+/// from before the API started do not raise alerts.
+pub fn watch_threats(
+    notifier: Notifier,
+    threat_categories: ThreatCategories,
+    ftl_memory: FtlMemory,
+    env: Env
+) {
+    thread::spawn(move || {
+        let mut seen = None;
+
+        loop {
+            match blocked_queries(&ftl_memory, seen) {
+                Ok((total, queries)) => {
+                    if seen.is_some() {
+                        notifier.blocked_queries_observed(queries, &threat_categories, &env);
+                    }
+
+                    seen = Some(total);
+                }
+                Err(e) => e.print_stacktrace()
+            }
+
+            thread::sleep(QUERY_SCAN_INTERVAL);
+        }
+    });
+}
+
+/// Periodically check the metrics of the threshold rules, in a background
+/// thread. The rules are read on every check, so changes take effect without
+/// a restart.
+pub fn watch_thresholds(notifier: Notifier, env: Env) {
+    thread::spawn(move || {
+        let mut monitor = ThresholdMonitor::default();
+
+        loop {
+            match ThresholdRule::read_all(&env) {
+                Ok(rules) => notifier
+                    .thresholds_crossed(monitor.check(&rules, current_time(), Metric::read), &env),
+                Err(e) => e.print_stacktrace()
+            }
+
+            thread::sleep(THRESHOLD_SCAN_INTERVAL);
+        }
+    });
+}
+
+/// Get the client IP and domain of the blocked queries after the first `seen`
+/// queries, along with the total number of queries. If FTL has fewer queries
+/// than were seen, because old queries were removed from memory, all of its
+/// queries are checked.
+fn blocked_queries(
+    ftl_memory: &FtlMemory,
+    seen: Option<usize>
+) -> Result<(usize, Vec<(String, String)>), Error> {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let total = counters.total_queries as usize;
+    let seen = match seen {
+        Some(seen) if seen <= total => seen,
+        Some(_) => 0,
+        None => return Ok((total, Vec::new()))
+    };
+
+    let queries = ftl_memory.queries(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    Ok((
+        total,
+        queries
+            .iter()
+            .take(total)
+            .skip(seen)
+            .filter(|query| query.is_blocked())
+            .map(|query| {
+                (
+                    clients[query.client_id as usize]
+                        .get_ip(&strings)
+                        .to_owned(),
+                    domains[query.domain_id as usize]
+                        .get_domain(&strings)
+                        .to_owned()
+                )
+            })
+            .collect()
+    ))
+}
+
+/// Get the IP addresses of FTL's clients
+fn client_ips(ftl_memory: &FtlMemory) -> Result<Vec<String>, Error> {
+    let lock = ftl_memory.lock()?;
+    let clients = ftl_memory.clients(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+    let counters = ftl_memory.counters(&lock)?;
+
+    Ok(clients
+        .iter()
+        .take(counters.total_clients as usize)
+        .map(|client| client.get_ip(&strings).to_owned())
+        .collect())
+}
+
+/// Start the thread which sends the alerts to the webhook, and get the queue
+/// which feeds it. The alerts are sent one at a time, so that a burst of
+/// alerts does not start a curl process for each of them.
+fn start_webhook_sender() -> SyncSender<(String, Alert)> {
+    let (sender, receiver) = mpsc::sync_channel::<(String, Alert)>(WEBHOOK_QUEUE_SIZE);
+
+    thread::spawn(move || {
+        for (url, alert) in receiver {
+            send_webhook(url, &alert);
+        }
+    });
+
+    sender
+}
+
+/// POST the alert as JSON to the webhook URL with curl, and wait for it to
+/// finish. Failures are only logged.
+fn send_webhook(url: String, alert: &Alert) {
+    let body = match serde_json::to_string(alert) {
+        Ok(body) => body,
+        Err(_) => return
+    };
+
+    let status = Command::new("curl")
+        .args(&["-s", "-m", "10", "-X", "POST"])
+        .args(&["-H", "Content-Type: application/json"])
+        .arg("-d")
+        .arg(body)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    if !status.map(|status| status.success()).unwrap_or(false) {
+        eprintln!("Failed to send notification to the webhook");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AlertKind, Notifier, NotifierData, LOGIN_IP_EXPIRY, MAX_ALERTS, MAX_LOGIN_IPS};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        services::ThreatCategories,
+        testing::TestEnvBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Create a test environment with the notification settings
+    fn test_env(setup_vars: &str) -> Env {
+        Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, setup_vars)
+                .build()
+        )
+    }
+
+    /// Only the first login from an IP raises an alert
+    #[test]
+    fn new_login() {
+        let env = test_env("API_NOTIFY_NEW_LOGIN=true\n");
+        let notifier = Notifier::default();
+
+        notifier.login_succeeded(Some([10, 1, 1, 1].into()), &env);
+        notifier.login_succeeded(Some([10, 1, 1, 1].into()), &env);
+        notifier.login_succeeded(Some([10, 1, 1, 2].into()), &env);
+
+        let alerts = notifier.alerts();
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].kind, AlertKind::NewLogin);
+        assert_eq!(alerts[0].ip, "10.1.1.2");
+        assert_eq!(alerts[1].ip, "10.1.1.1");
+    }
+
+    /// Login IPs are forgotten after they expire, so their next login is new
+    /// again
+    #[test]
+    fn login_ip_expiry() {
+        let mut data = NotifierData::default();
+
+        assert!(data.login_seen("10.1.1.1".to_owned(), 1000));
+        assert!(!data.login_seen("10.1.1.1".to_owned(), 2000));
+        assert!(!data.login_seen("10.1.1.1".to_owned(), 2000 + LOGIN_IP_EXPIRY - 1));
+        assert!(data.login_seen("10.1.1.1".to_owned(), 2000 + 2 * LOGIN_IP_EXPIRY));
+    }
+
+    /// Only a limited number of login IPs are remembered, and the IP which
+    /// logged in least recently is forgotten first
+    #[test]
+    fn login_ips_bounded() {
+        let mut data = NotifierData::default();
+
+        for i in 0..=MAX_LOGIN_IPS as u64 {
+            assert!(data.login_seen(format!("ip{}", i), 1000 + i));
+        }
+
+        assert_eq!(data.login_ips.len(), MAX_LOGIN_IPS);
+        assert!(data.login_seen("ip0".to_owned(), 5000));
+        assert!(!data.login_seen(format!("ip{}", MAX_LOGIN_IPS), 5000));
+    }
+
+    /// No alerts are raised when the notification is disabled
+    #[test]
+    fn new_login_disabled() {
+        let env = test_env("API_NOTIFY_NEW_LOGIN=false\n");
+        let notifier = Notifier::default();
+
+        notifier.login_succeeded(Some([10, 1, 1, 1].into()), &env);
+
+        assert!(notifier.alerts().is_empty());
+    }
+
+    /// The first scan of clients only records the existing clients, and later
+    /// scans raise alerts for the new clients
+    #[test]
+    fn new_client() {
+        let env = test_env("API_NOTIFY_NEW_CLIENT=true\n");
+        let notifier = Notifier::default();
+
+        notifier.clients_observed(vec!["10.1.1.1".to_owned()], &env);
+        assert!(notifier.alerts().is_empty());
+
+        notifier.clients_observed(vec!["10.1.1.1".to_owned(), "10.1.1.3".to_owned()], &env);
+
+        let alerts = notifier.alerts();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::NewClient);
+        assert_eq!(alerts[0].ip, "10.1.1.3");
+    }
+
+    /// Only the most recent alerts are kept
+    #[test]
+    fn alerts_bounded() {
+        let env = test_env("API_NOTIFY_NEW_LOGIN=true\n");
+        let notifier = Notifier::default();
+
+        for i in 0..MAX_ALERTS + 5 {
+            notifier.login_succeeded(Some([10, 0, (i / 256) as u8, i as u8].into()), &env);
+        }
+
+        assert_eq!(notifier.alerts().len(), MAX_ALERTS);
+    }
+
+    /// Blocked queries of domains in the threat feed raise an alert with the
+    /// domain and its category
+    #[test]
+    fn threat_blocked() {
+        let env = test_env("API_NOTIFY_THREAT=true\n");
+        let notifier = Notifier::default();
+        let mut categories = HashMap::new();
+        categories.insert("malware.example.com".to_owned(), "malware".to_owned());
+
+        notifier.blocked_queries_observed(
+            vec![
+                ("10.1.1.1".to_owned(), "ads.example.com".to_owned()),
+                ("10.1.1.2".to_owned(), "malware.example.com".to_owned()),
+            ],
+            &ThreatCategories::from(categories),
+            &env
+        );
+
+        let alerts = notifier.alerts();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::ThreatBlocked);
+        assert_eq!(alerts[0].ip, "10.1.1.2");
+        assert_eq!(alerts[0].domain, Some("malware.example.com".to_owned()));
+        assert_eq!(alerts[0].category, Some("malware".to_owned()));
+    }
+}
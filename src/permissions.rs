@@ -0,0 +1,227 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Route Permissions
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{api_keys::Scope, users::Role};
+use rocket::http::Method;
+
+/// The path which the API routes are mounted on
+const API_PATH: &str = "/admin/api";
+
+/// The permissions needed to use each group of routes. The first permission
+/// which matches a request applies to it, so more specific paths are listed
+/// first.
+pub const PERMISSIONS: &[Permission] = &[
+    Permission {
+        name: "accounts",
+        access: Access::Any,
+        paths: &["/auth/users", "/auth/keys"],
+        role: Role::Admin,
+        scope: None
+    },
+    Permission {
+        name: "preferences",
+        access: Access::Change,
+        paths: &["/auth/preferences"],
+        role: Role::Viewer,
+        scope: None
+    },
+    Permission {
+        name: "session",
+        access: Access::Change,
+        paths: &["/auth"],
+        role: Role::Viewer,
+        scope: None
+    },
+    Permission {
+        name: "cache",
+        access: Access::Change,
+        paths: &["/dns/cache"],
+        role: Role::Admin,
+        scope: None
+    },
+    Permission {
+        name: "check",
+        access: Access::Any,
+        paths: &["/dns/check"],
+        role: Role::Viewer,
+        scope: Some(Scope::Read)
+    },
+    Permission {
+        name: "lists",
+        access: Access::Change,
+        paths: &["/dns", "/groups"],
+        role: Role::Admin,
+        scope: Some(Scope::Lists)
+    },
+    Permission {
+        name: "teleporter",
+        access: Access::Any,
+        paths: &["/settings/teleporter"],
+        role: Role::Admin,
+        scope: None
+    },
+    Permission {
+        name: "settings",
+        access: Access::Change,
+        paths: &["/settings"],
+        role: Role::Admin,
+        scope: Some(Scope::Settings)
+    },
+    Permission {
+        name: "read",
+        access: Access::Read,
+        paths: &[],
+        role: Role::Viewer,
+        scope: Some(Scope::Read)
+    },
+    Permission {
+        name: "changes",
+        access: Access::Change,
+        paths: &[],
+        role: Role::Admin,
+        scope: None
+    }
+];
+
+/// The request methods which a permission applies to
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// GET and HEAD requests
+    Read,
+    /// Requests with any other method
+    Change,
+    /// Requests with any method
+    Any
+}
+
+/// The role and API key scope needed to use a group of routes
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Permission {
+    pub name: &'static str,
+    pub access: Access,
+    /// The paths of the routes, relative to the API. A path also matches the
+    /// paths below it. If there are no paths, every route matches.
+    pub paths: &'static [&'static str],
+    /// The role needed to use the routes
+    pub role: Role,
+    /// The scope which allows named API keys to use the routes. If there is
+    /// none, named API keys can not use them.
+    pub scope: Option<Scope>
+}
+
+impl Access {
+    /// Check if the method is included
+    fn matches(self, method: Method) -> bool {
+        let is_read = method == Method::Get || method == Method::Head;
+
+        match self {
+            Access::Read => is_read,
+            Access::Change => !is_read,
+            Access::Any => true
+        }
+    }
+}
+
+impl Permission {
+    /// Find the permission which applies to a request with the method to the
+    /// path. The path must be the routed path, without empty segments.
+    pub fn find(method: Method, path: &str) -> Option<&'static Permission> {
+        let path = path.trim_start_matches(API_PATH);
+
+        PERMISSIONS.iter().find(|permission| {
+            permission.access.matches(method)
+                && (permission.paths.is_empty()
+                    || permission
+                        .paths
+                        .iter()
+                        .any(|prefix| path_matches(prefix, path)))
+        })
+    }
+
+    /// Check if a user with the role may use the routes. If the user used a
+    /// named API key, the key must also have the scope.
+    pub fn allows(&self, role: Role, key_scopes: Option<&[Scope]>) -> bool {
+        let role_allows = role == Role::Admin || self.role == Role::Viewer;
+        let key_allows = match key_scopes {
+            Some(scopes) => self.scope.map_or(false, |scope| scopes.contains(&scope)),
+            None => true
+        };
+
+        role_allows && key_allows
+    }
+}
+
+/// Check if the path is the prefix path or below it
+fn path_matches(prefix: &str, path: &str) -> bool {
+    path == prefix || (path.starts_with(prefix) && path[prefix.len()..].starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Permission;
+    use crate::{api_keys::Scope, users::Role};
+    use rocket::http::Method;
+
+    /// Requests use the first permission which matches them
+    #[test]
+    fn find() {
+        let name = |method, path| Permission::find(method, path).map(|permission| permission.name);
+
+        assert_eq!(name(Method::Get, "/admin/api/auth/users"), Some("accounts"));
+        assert_eq!(
+            name(Method::Put, "/admin/api/auth/preferences"),
+            Some("preferences")
+        );
+        assert_eq!(
+            name(Method::Get, "/admin/api/auth/preferences"),
+            Some("read")
+        );
+        assert_eq!(
+            name(Method::Post, "/admin/api/dns/whitelist"),
+            Some("lists")
+        );
+        assert_eq!(name(Method::Delete, "/admin/api/dns/cache"), Some("cache"));
+        assert_eq!(name(Method::Post, "/admin/api/dns/check"), Some("check"));
+        assert_eq!(
+            name(Method::Put, "/admin/api/settings/dns"),
+            Some("settings")
+        );
+        assert_eq!(
+            name(Method::Get, "/admin/api/settings/teleporter"),
+            Some("teleporter")
+        );
+        assert_eq!(
+            name(Method::Post, "/admin/api/stats/history/views"),
+            Some("changes")
+        );
+    }
+
+    /// Paths only match whole path segments
+    #[test]
+    fn partial_segment() {
+        assert_eq!(
+            Permission::find(Method::Post, "/admin/api/dnsx").map(|permission| permission.name),
+            Some("changes")
+        );
+    }
+
+    /// Viewers and named API keys are limited by the permission
+    #[test]
+    fn allows() {
+        let lists = Permission::find(Method::Post, "/admin/api/groups").unwrap();
+
+        assert!(lists.allows(Role::Admin, None));
+        assert!(!lists.allows(Role::Viewer, None));
+        assert!(lists.allows(Role::Admin, Some(&[Scope::Lists][..])));
+        assert!(!lists.allows(Role::Admin, Some(&[Scope::Read][..])));
+    }
+}
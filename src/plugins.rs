@@ -0,0 +1,338 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Plugins
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::FtlMemory,
+    util::{Error, ErrorKind}
+};
+use rocket::{Rocket, Route};
+use std::{collections::HashSet, sync::Arc, thread, time::Duration};
+
+/// How often FTL's new queries are sent to the plugins
+const QUERY_SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The path which each plugin's routes are mounted under, followed by the
+/// plugin's name
+const PLUGIN_PATH: &str = "/admin/api/plugins";
+
+/// An extension of the API, which is compiled into a program using this
+/// crate as a library and passed to `start_with_plugins`. When the API is
+/// built with the `dylib-plugins` feature, plugins can also be loaded from
+/// dynamic libraries which export them with [`declare_plugin!`]. With the
+/// `wasm-plugins` feature, sandboxed plugins can be loaded from WebAssembly
+/// modules.
+///
+/// [`declare_plugin!`]: ../macro.declare_plugin.html
+pub trait Plugin: Send + Sync {
+    /// The name of the plugin. Its routes are mounted under
+    /// `/admin/api/plugins/<name>`, so it can only contain letters, digits,
+    /// `-`, and `_`.
+    fn name(&self) -> &str;
+
+    /// Get the routes to mount for the plugin
+    fn routes(&self) -> Vec<Route> {
+        Vec::new()
+    }
+
+    /// Handle a query which FTL has answered. Queries are sent in batches
+    /// from a background thread, so this should not block for long.
+    fn on_query(&self, _query: &QueryEvent) {}
+}
+
+/// The version of the plugin interface which a plugin library was built
+/// with. Rust does not have a stable ABI, so it is the version of this crate
+/// and of the compiler. It ends with a NUL byte so that the API can read a
+/// library's version as a C string, which does not depend on the ABI.
+#[doc(hidden)]
+pub const PLUGIN_API_VERSION: &str =
+    concat!(env!("CARGO_PKG_VERSION"), " ", env!("RUSTC_VERSION"), "\0");
+
+/// Export a plugin from a dynamic library, so that the API can load it from
+/// the plugin directory. The argument is a function which creates the plugin.
+/// The library must be built as a `dylib` with the same compiler and version
+/// of this crate as the API, because Rust does not have a stable ABI. The
+/// API checks the version the library was built with before creating the
+/// plugin, and does not load libraries built differently.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($constructor:path) => {
+        #[no_mangle]
+        pub extern "C" fn _pihole_api_plugin_version() -> *const u8 {
+            $crate::PLUGIN_API_VERSION.as_ptr()
+        }
+
+        #[no_mangle]
+        pub fn _pihole_api_create_plugin() -> Box<dyn $crate::Plugin> {
+            let constructor: fn() -> Box<dyn $crate::Plugin> = $constructor;
+            constructor()
+        }
+    };
+}
+
+/// A query sent to the plugins
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct QueryEvent {
+    pub timestamp: i64,
+    pub domain: String,
+    pub client: String,
+    /// The name of the query status, as used in the API
+    pub status: &'static str,
+    pub blocked: bool
+}
+
+/// The plugins which the API was started with
+#[derive(Clone, Default)]
+pub struct Plugins {
+    plugins: Arc<Vec<Box<dyn Plugin>>>
+}
+
+impl Plugins {
+    /// Check the plugins' names and wrap them. The names must be valid and
+    /// unique, because they are used in the paths of the plugins' routes.
+    pub fn new(plugins: Vec<Box<dyn Plugin>>) -> Result<Plugins, Error> {
+        let mut names = HashSet::new();
+
+        for plugin in &plugins {
+            let name = plugin.name();
+            let is_valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+            if !is_valid || !names.insert(name) {
+                return Err(Error::from(ErrorKind::InvalidPlugin(name.to_owned())));
+            }
+        }
+
+        Ok(Plugins {
+            plugins: Arc::new(plugins)
+        })
+    }
+
+    /// Mount each plugin's routes under its own path
+    pub fn mount(&self, server: Rocket) -> Rocket {
+        self.plugins.iter().fold(server, |server, plugin| {
+            let routes = plugin.routes();
+
+            if routes.is_empty() {
+                server
+            } else {
+                server.mount(&format!("{}/{}", PLUGIN_PATH, plugin.name()), routes)
+            }
+        })
+    }
+
+    /// Periodically send FTL's new queries to the plugins, in a background
+    /// thread. The first check only records the number of queries, so
+    /// queries from before the API started are not sent. Nothing is started
+    /// if there are no plugins.
+    pub fn watch_queries(&self, ftl_memory: FtlMemory) {
+        if self.plugins.is_empty() {
+            return;
+        }
+
+        let plugins = self.clone();
+
+        thread::spawn(move || {
+            let mut seen = None;
+
+            loop {
+                match new_queries(&ftl_memory, seen) {
+                    Ok((total, queries)) => {
+                        if seen.is_some() {
+                            plugins.queries_observed(&queries);
+                        }
+
+                        seen = Some(total);
+                    }
+                    Err(e) => e.print_stacktrace()
+                }
+
+                thread::sleep(QUERY_SCAN_INTERVAL);
+            }
+        });
+    }
+
+    /// Send the queries to every plugin
+    fn queries_observed(&self, queries: &[QueryEvent]) {
+        for plugin in self.plugins.iter() {
+            for query in queries {
+                plugin.on_query(query);
+            }
+        }
+    }
+}
+
+/// Get the queries after the first `seen` queries, along with the total
+/// number of queries. If FTL has fewer queries than were seen, because old
+/// queries were removed from memory, all of its queries are sent.
+fn new_queries(
+    ftl_memory: &FtlMemory,
+    seen: Option<usize>
+) -> Result<(usize, Vec<QueryEvent>), Error> {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let total = counters.total_queries as usize;
+    let seen = match seen {
+        Some(seen) if seen <= total => seen,
+        Some(_) => 0,
+        None => return Ok((total, Vec::new()))
+    };
+
+    let queries = ftl_memory.queries(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    Ok((
+        total,
+        queries
+            .iter()
+            .take(total)
+            .skip(seen)
+            .map(|query| QueryEvent {
+                timestamp: query.timestamp as i64,
+                domain: domains[query.domain_id as usize]
+                    .get_domain(&strings)
+                    .to_owned(),
+                client: clients[query.client_id as usize]
+                    .get_ip(&strings)
+                    .to_owned(),
+                status: query.status.get_name(),
+                blocked: query.is_blocked()
+            })
+            .collect()
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{new_queries, Plugin, Plugins, QueryEvent};
+    use crate::routes::stats::testing::test_memory;
+    use rocket::{
+        config::{Config, Environment},
+        http::Status,
+        local::Client,
+        Route
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[get("/hello")]
+    fn hello() -> &'static str {
+        "Hello"
+    }
+
+    /// A plugin which mounts one route and records the queries it is sent
+    #[derive(Default)]
+    struct TestPlugin {
+        name: &'static str,
+        queries: Arc<Mutex<Vec<QueryEvent>>>
+    }
+
+    impl Plugin for TestPlugin {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn routes(&self) -> Vec<Route> {
+            routes![hello]
+        }
+
+        fn on_query(&self, query: &QueryEvent) {
+            self.queries.lock().unwrap().push(query.clone());
+        }
+    }
+
+    /// Create a boxed test plugin with the name
+    fn plugin(name: &'static str) -> Box<dyn Plugin> {
+        Box::new(TestPlugin {
+            name,
+            ..TestPlugin::default()
+        })
+    }
+
+    /// Plugins with invalid or duplicate names are rejected
+    #[test]
+    fn invalid_names() {
+        assert!(Plugins::new(vec![plugin("stats-export"), plugin("extra_2")]).is_ok());
+        assert!(Plugins::new(vec![plugin("")]).is_err());
+        assert!(Plugins::new(vec![plugin("a/b")]).is_err());
+        assert!(Plugins::new(vec![plugin("extra"), plugin("extra")]).is_err());
+    }
+
+    /// A plugin's routes are mounted under its name
+    #[test]
+    fn mount_routes() {
+        let plugins = Plugins::new(vec![plugin("extra")]).unwrap();
+        let server = plugins.mount(rocket::custom(Config::new(Environment::Development)));
+        let client = Client::new(server).unwrap();
+
+        let mut response = client.get("/admin/api/plugins/extra/hello").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.body_string(), Some("Hello".to_owned()));
+
+        let response = client.get("/hello").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    /// Only the queries after the ones already seen are sent
+    #[test]
+    fn only_new_queries() {
+        let ftl_memory = test_memory();
+
+        assert_eq!(new_queries(&ftl_memory, None).unwrap(), (9, Vec::new()));
+
+        let (total, queries) = new_queries(&ftl_memory, Some(7)).unwrap();
+        assert_eq!(total, 9);
+        assert_eq!(
+            queries,
+            vec![
+                QueryEvent {
+                    timestamp: 263_586,
+                    domain: "domain5.com".to_owned(),
+                    client: "192.168.1.12".to_owned(),
+                    status: "external_block",
+                    blocked: true
+                },
+                QueryEvent {
+                    timestamp: 263_587,
+                    domain: "hidden".to_owned(),
+                    client: "0.0.0.0".to_owned(),
+                    status: "forward",
+                    blocked: false
+                },
+            ]
+        );
+    }
+
+    /// Every plugin is sent every query
+    #[test]
+    fn queries_observed() {
+        let first = TestPlugin {
+            name: "first",
+            ..TestPlugin::default()
+        };
+        let second = TestPlugin {
+            name: "second",
+            ..TestPlugin::default()
+        };
+        let first_queries = first.queries.clone();
+        let second_queries = second.queries.clone();
+        let (_, queries) = new_queries(&test_memory(), Some(7)).unwrap();
+
+        Plugins::new(vec![Box::new(first), Box::new(second)])
+            .unwrap()
+            .queries_observed(&queries);
+
+        assert_eq!(*first_queries.lock().unwrap(), queries);
+        assert_eq!(*second_queries.lock().unwrap(), queries);
+    }
+}
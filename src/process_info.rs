@@ -0,0 +1,168 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// API And FTL Process Information
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::env::{Env, PiholeFile};
+use std::{fs, time::Instant};
+
+/// Tracks how long the API and FTL have been running. FTL's shared memory
+/// does not include its start time, so it is read from procfs using FTL's PID
+/// file.
+pub enum ProcessInfo {
+    Production {
+        api_start: Instant
+    },
+    #[cfg(test)]
+    Test {
+        api_uptime: u64,
+        ftl_uptime: Option<u64>
+    }
+}
+
+impl ProcessInfo {
+    /// Create the process info, with the API starting now
+    pub fn production() -> ProcessInfo {
+        ProcessInfo::Production {
+            api_start: Instant::now()
+        }
+    }
+
+    /// Get the API uptime in seconds
+    pub fn api_uptime(&self) -> u64 {
+        match self {
+            ProcessInfo::Production { api_start } => api_start.elapsed().as_secs(),
+            #[cfg(test)]
+            ProcessInfo::Test { api_uptime, .. } => *api_uptime
+        }
+    }
+
+    /// Get the FTL uptime in seconds. If FTL is not running or the uptime
+    /// can not be read, `None` is returned.
+    pub fn ftl_uptime(&self, env: &Env) -> Option<u64> {
+        match self {
+            ProcessInfo::Production { .. } => {
+                let instance = FtlInstance::read(env.file_location(PiholeFile::FtlPid))?;
+                let system_uptime = fs::read_to_string("/proc/uptime").ok()?;
+
+                process_uptime(instance.start_ticks, &system_uptime, clock_ticks()?)
+            }
+            #[cfg(test)]
+            ProcessInfo::Test { ftl_uptime, .. } => *ftl_uptime
+        }
+    }
+}
+
+/// Identifies a run of FTL. A PID can be reused after FTL exits, so the
+/// process start time is included, which is different for each run.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct FtlInstance {
+    pub pid: u32,
+    /// The process start time, in clock ticks since boot
+    pub start_ticks: u64
+}
+
+impl FtlInstance {
+    /// Read the running FTL instance using FTL's PID file. If FTL is not
+    /// running or its process can not be read, `None` is returned.
+    pub fn read(pid_file: &str) -> Option<FtlInstance> {
+        let pid = fs::read_to_string(pid_file)
+            .ok()?
+            .trim()
+            .parse::<u32>()
+            .ok()?;
+        let process_stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+
+        Some(FtlInstance {
+            pid,
+            start_ticks: start_ticks(&process_stat)?
+        })
+    }
+}
+
+/// Get a process's start time in clock ticks since boot from its
+/// `/proc/<pid>/stat` file
+fn start_ticks(process_stat: &str) -> Option<u64> {
+    // The process name is in parentheses and may contain spaces, so skip past
+    // it. The start time is the 20th field after the name.
+    process_stat
+        .get(process_stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()
+}
+
+/// Calculate a process's uptime in seconds from its start time in clock ticks
+/// since boot and `/proc/uptime`
+fn process_uptime(start_ticks: u64, system_uptime: &str, clock_ticks: u64) -> Option<u64> {
+    let system_uptime: f64 = system_uptime.split_whitespace().next()?.parse().ok()?;
+
+    Some((system_uptime as u64).saturating_sub(start_ticks / clock_ticks))
+}
+
+/// Get the number of clock ticks per second
+fn clock_ticks() -> Option<u64> {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => Some(ticks as u64),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{process_uptime, start_ticks, FtlInstance};
+    use std::{fs, process};
+    use tempfile::NamedTempFile;
+
+    /// The start time is read from the stat file
+    #[test]
+    fn stat_start_ticks() {
+        let process_stat = "1234 (pihole-FTL) S 1 1234 1234 0 -1 4194560 1000 0 0 0 50 30 0 0 \
+                            20 0 3 0 50000 123456789 1000 18446744073709551615";
+
+        assert_eq!(start_ticks(process_stat), Some(50000));
+    }
+
+    /// Spaces in the process name do not change the fields
+    #[test]
+    fn name_with_spaces() {
+        let process_stat = "1234 (pihole FTL) S 1 1234 1234 0 -1 4194560 1000 0 0 0 50 30 0 0 \
+                            20 0 3 0 50000 123456789 1000 18446744073709551615";
+
+        assert_eq!(start_ticks(process_stat), Some(50000));
+    }
+
+    /// Invalid stat files have no start time
+    #[test]
+    fn invalid() {
+        assert_eq!(start_ticks("1234 (pihole-FTL) S 1"), None);
+    }
+
+    /// The uptime is the system uptime minus the process start time
+    #[test]
+    fn uptime() {
+        assert_eq!(process_uptime(50000, "1500.25 3000.50\n", 100), Some(1000));
+        assert_eq!(process_uptime(50000, "", 100), None);
+    }
+
+    /// The instance is read from the process in the PID file
+    #[test]
+    fn read_instance() {
+        let pid_file = NamedTempFile::new().unwrap();
+        let path = pid_file.path().to_str().unwrap();
+
+        fs::write(path, format!("{}\n", process::id())).unwrap();
+        let instance = FtlInstance::read(path).unwrap();
+        assert_eq!(instance.pid, process::id());
+        assert_eq!(FtlInstance::read(path), Some(instance));
+
+        fs::write(path, "not a pid").unwrap();
+        assert_eq!(FtlInstance::read(path), None);
+    }
+}
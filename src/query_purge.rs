@@ -0,0 +1,294 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Per Client Query Log Purge
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::queries,
+    env::Env,
+    settings::{ClientRetention, ConfigEntry, FtlConfEntry, SetupVarsEntry},
+    util::{current_time, Error, ErrorKind}
+};
+use diesel::{prelude::*, SqliteConnection};
+use failure::ResultExt;
+use std::{
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration
+};
+
+/// How often the time is checked for the nightly purge
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// The local hour when the nightly purge runs
+const PURGE_HOUR: i32 = 3;
+
+/// The minimum time between purges, in seconds. This stops the purge from
+/// running more than once during the purge hour.
+const MIN_PURGE_INTERVAL: u64 = 12 * 60 * 60;
+
+/// Deletes queries from the FTL database every night for clients with a
+/// shorter retention than `MAXDBDAYS`, such as guest network clients. FTL
+/// still deletes everything older than `MAXDBDAYS`, so a retention can only
+/// shorten how long queries are kept.
+#[derive(Clone, Default)]
+pub struct QueryPurge {
+    data: Arc<Mutex<PurgeData>>
+}
+
+/// The result of the last purge
+#[derive(Default)]
+struct PurgeData {
+    /// When the last purge ran, as a Unix timestamp
+    last_purge: Option<u64>,
+    /// The number of queries deleted by the last purge
+    deleted: usize,
+    last_status: Option<PurgeStatus>
+}
+
+/// The outcome of a purge
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeStatus {
+    Success,
+    Failed
+}
+
+/// The reply format of the purge status
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct PurgeReply {
+    pub last_purge: Option<u64>,
+    pub deleted: usize,
+    pub last_status: Option<PurgeStatus>
+}
+
+impl QueryPurge {
+    /// Get the status of the last purge
+    pub fn status(&self) -> PurgeReply {
+        let data = self.lock();
+
+        PurgeReply {
+            last_purge: data.last_purge,
+            deleted: data.deleted,
+            last_status: data.last_status
+        }
+    }
+
+    /// Start checking for the nightly purge in a background thread
+    pub fn start(&self, env: Env) {
+        let purge = self.clone();
+
+        thread::spawn(move || loop {
+            if let Err(e) = purge.check(&env) {
+                e.print_stacktrace();
+            }
+
+            thread::sleep(CHECK_INTERVAL);
+        });
+    }
+
+    /// Run the purge if it is the purge hour and it has not run yet tonight
+    fn check(&self, env: &Env) -> Result<(), Error> {
+        let now = current_time();
+
+        if local_hour(now) != Some(PURGE_HOUR) {
+            return Ok(());
+        }
+
+        if let Some(last_purge) = self.lock().last_purge {
+            if now.saturating_sub(last_purge) < MIN_PURGE_INTERVAL {
+                return Ok(());
+            }
+        }
+
+        let retentions = read_retentions(env)?;
+        let result = if retentions.is_empty() {
+            Ok(0)
+        } else {
+            connect(env).and_then(|db| purge(&db, &retentions, now))
+        };
+
+        let mut data = self.lock();
+        data.last_purge = Some(now);
+        data.deleted = *result.as_ref().unwrap_or(&0);
+        data.last_status = Some(if result.is_ok() {
+            PurgeStatus::Success
+        } else {
+            PurgeStatus::Failed
+        });
+
+        result.map(|_| ())
+    }
+
+    /// Lock the purge data. Ignore the poison error because the data is still
+    /// consistent.
+    fn lock(&self) -> MutexGuard<PurgeData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Read the client retentions from setupVars.conf
+pub fn read_retentions(env: &Env) -> Result<Vec<ClientRetention>, Error> {
+    SetupVarsEntry::ApiClientRetention
+        .read_list(env)?
+        .iter()
+        .map(|retention| retention.parse())
+        .collect()
+}
+
+/// Write the client retentions to setupVars.conf
+pub fn write_retentions(env: &Env, retentions: &[ClientRetention]) -> Result<(), Error> {
+    let value = retentions
+        .iter()
+        .map(ClientRetention::to_string)
+        .collect::<Vec<String>>()
+        .join(",");
+
+    SetupVarsEntry::ApiClientRetention.write(&value, env)
+}
+
+/// Open a connection to the FTL database
+fn connect(env: &Env) -> Result<SqliteConnection, Error> {
+    Ok(
+        SqliteConnection::establish(&FtlConfEntry::DbFile.read(env)?)
+            .context(ErrorKind::FtlDatabase)?
+    )
+}
+
+/// Delete the queries of each client which are older than its retention,
+/// returning the number of deleted queries. If more than one retention covers
+/// a client, the shortest is used.
+fn purge(db: &SqliteConnection, retentions: &[ClientRetention], now: u64) -> Result<usize, Error> {
+    // Use the Diesel DSL of this table for easy querying
+    use self::queries::dsl::*;
+
+    let clients: Vec<String> = queries
+        .select(client)
+        .distinct()
+        .load(db)
+        .context(ErrorKind::FtlDatabase)?;
+    let mut deleted = 0;
+
+    for ip in clients {
+        let hours = match retentions
+            .iter()
+            .filter(|retention| retention.matches(&ip))
+            .map(|retention| retention.hours)
+            .min()
+        {
+            Some(hours) => hours,
+            None => continue
+        };
+        let cutoff = now.saturating_sub(hours.saturating_mul(60 * 60)) as i32;
+
+        deleted += diesel::delete(queries.filter(client.eq(&ip)).filter(timestamp.lt(cutoff)))
+            .execute(db)
+            .context(ErrorKind::FtlDatabase)?;
+    }
+
+    Ok(deleted)
+}
+
+/// Get the local hour of the Unix timestamp
+fn local_hour(time: u64) -> Option<i32> {
+    unsafe {
+        let time = time as libc::time_t;
+        let mut local: libc::tm = mem::zeroed();
+
+        if libc::localtime_r(&time, &mut local).is_null() {
+            None
+        } else {
+            Some(local.tm_hour)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{purge, read_retentions, write_retentions};
+    use crate::{
+        databases::ftl::{connect_to_test_db, queries::dsl::*},
+        env::{Config, Env, PiholeFile},
+        settings::ClientRetention,
+        testing::TestEnvBuilder
+    };
+    use diesel::{prelude::*, result::Error};
+
+    /// The time of the last query in the test database
+    const LAST_QUERY: u64 = 177_180;
+
+    /// Parse the retentions for the tests
+    fn retentions(retentions: &[&str]) -> Vec<ClientRetention> {
+        retentions
+            .iter()
+            .map(|retention| retention.parse().unwrap())
+            .collect()
+    }
+
+    /// Only the queries of covered clients which are older than the
+    /// retention are deleted
+    #[test]
+    fn purge_old_queries() {
+        let db = connect_to_test_db();
+
+        db.test_transaction::<_, Error, _>(|| {
+            let deleted = purge(
+                &db,
+                &retentions(&["127.0.0.1@1", "10.0.0.0/8@24"]),
+                LAST_QUERY + 60 * 60
+            )
+            .unwrap();
+
+            assert_eq!(deleted, 91);
+            assert_eq!(queries.count().get_result::<i64>(&db)?, 3);
+            Ok(())
+        });
+    }
+
+    /// The shortest retention covering a client is used
+    #[test]
+    fn purge_shortest_retention() {
+        let db = connect_to_test_db();
+
+        db.test_transaction::<_, Error, _>(|| {
+            let deleted = purge(
+                &db,
+                &retentions(&["127.0.0.0/8@48", "127.0.0.1@1"]),
+                LAST_QUERY + 60 * 60
+            )
+            .unwrap();
+
+            assert_eq!(deleted, 91);
+            Ok(())
+        });
+    }
+
+    /// The retentions are read from and written to setupVars.conf
+    #[test]
+    fn read_write_retentions() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::SetupVars,
+            "API_CLIENT_RETENTION=10.0.1.0/24@24\n",
+            "API_CLIENT_RETENTION=10.0.1.0/24@24,fd00::5@48\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        let mut saved = read_retentions(&env).unwrap();
+        assert_eq!(saved, retentions(&["10.0.1.0/24@24"]));
+
+        saved.push("fd00::5@48".parse().unwrap());
+        write_retentions(&env, &saved).unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+}
@@ -0,0 +1,228 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// API Request Rate Limiting
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Config, ProxyAuth},
+    util::{routed_path, Error, ErrorKind}
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{uri::Origin, Header},
+    response::Responder,
+    Data, Request, Response
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant
+};
+
+/// Only requests to the API are limited, not the web interface
+const API_PATH: &str = "/admin/api";
+
+/// Limited requests are routed here so that no route handles them
+const LIMITED_PATH: &str = "/rate_limited";
+
+/// The number of clients above which full buckets are removed
+const MAX_BUCKETS: usize = 10_000;
+
+/// Limits the API requests of each client IP using a token bucket. Each
+/// request takes a token, and tokens are refilled at a constant rate up to
+/// the burst size. Requests without a token are answered with
+/// `429 Too Many Requests` without being handled. Clones share the buckets,
+/// so a client has the same limit on every listener.
+#[derive(Clone)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    /// The client IP of requests from these proxies is taken from the
+    /// `X-Real-IP` header
+    proxy_auth: Option<ProxyAuth>,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>
+}
+
+/// The tokens of a client
+struct Bucket {
+    tokens: f64,
+    updated: Instant
+}
+
+/// Marks a request which was rate limited, and how many seconds until the
+/// client can try again. It is stored in the request-local cache.
+struct RateLimited(Option<u64>);
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> RateLimiter {
+        RateLimiter {
+            requests_per_second: config.rate_limit(),
+            burst: f64::from(config.rate_limit_burst()),
+            proxy_auth: config.proxy_auth().cloned(),
+            buckets: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    /// Take a token from the client's bucket. If there are none, the number
+    /// of seconds until there is one is returned.
+    fn take(&self, ip: IpAddr, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.lock();
+
+        if buckets.len() >= MAX_BUCKETS {
+            self.remove_full(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.requests_per_second).ceil() as u64)
+        }
+    }
+
+    /// Get the number of tokens the bucket has after refilling it
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+
+        (bucket.tokens + elapsed * self.requests_per_second).min(self.burst)
+    }
+
+    /// Remove the buckets which have refilled, because those clients have
+    /// not made requests recently
+    fn remove_full(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+    }
+
+    /// Lock the buckets. Ignore the poison error because the buckets are
+    /// still usable.
+    fn lock(&self) -> MutexGuard<HashMap<IpAddr, Bucket>> {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Get the IP address of the client. Requests from a trusted proxy use the IP
+/// in the `X-Real-IP` header, which the proxy sets. Otherwise the header is
+/// ignored, because clients could change it for each request.
+pub fn client_ip(request: &Request, proxy_auth: Option<&ProxyAuth>) -> Option<IpAddr> {
+    let remote = request.remote()?.ip();
+
+    match proxy_auth {
+        Some(proxy_auth) if proxy_auth.is_trusted(remote) => {
+            Some(request.real_ip().unwrap_or(remote))
+        }
+        _ => Some(remote)
+    }
+}
+
+impl Fairing for RateLimiter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limiter",
+            kind: Kind::Request | Kind::Response
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        // Rocket skips empty path segments when routing, so the routed path is
+        // checked instead of the raw path
+        if self.requests_per_second <= 0.0 || !routed_path(request).starts_with(API_PATH) {
+            return;
+        }
+
+        let ip = match client_ip(request, self.proxy_auth.as_ref()) {
+            Some(ip) => ip,
+            None => return
+        };
+
+        if let Err(retry_after) = self.take(ip, Instant::now()) {
+            request.local_cache(|| RateLimited(Some(retry_after)));
+
+            // Fairings can not answer requests, so route the request to a
+            // path without routes. The response is replaced below.
+            request.set_uri(Origin::parse(LIMITED_PATH).unwrap());
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let retry_after = match *request.local_cache(|| RateLimited(None)) {
+            RateLimited(Some(retry_after)) => retry_after,
+            RateLimited(None) => return
+        };
+
+        if let Ok(error_response) = Error::from(ErrorKind::RateLimited).respond_to(request) {
+            response.merge(error_response);
+        }
+
+        response.set_header(Header::new("Retry-After", retry_after.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RateLimiter;
+    use crate::{env::Config, setup, testing::empty_ftl_memory};
+    use rocket::http::Status;
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, SocketAddr},
+        time::{Duration, Instant}
+    };
+
+    /// The burst can be used at once, and then tokens are refilled over time
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter::new(&Config::default());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        for _ in 0..60 {
+            assert_eq!(limiter.take(ip, start), Ok(()));
+        }
+
+        assert_eq!(limiter.take(ip, start), Err(1));
+        assert_eq!(limiter.take(ip, start + Duration::from_millis(100)), Ok(()));
+        assert_eq!(limiter.take(ip, start + Duration::from_millis(100)), Err(1));
+    }
+
+    /// Each client has its own bucket
+    #[test]
+    fn separate_clients() {
+        let limiter = RateLimiter::new(&Config::default());
+        let start = Instant::now();
+
+        for _ in 0..61 {
+            let _ = limiter.take("10.0.0.1".parse().unwrap(), start);
+        }
+
+        assert_eq!(limiter.take("10.0.0.2".parse().unwrap(), start), Ok(()));
+    }
+
+    /// Requests with empty path segments are routed to the API, so they are
+    /// limited like any other API request
+    #[test]
+    fn doubled_slashes_limited() {
+        let client = setup::test(HashMap::new(), empty_ftl_memory(), HashMap::new(), false);
+        let remote: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let status = || client.get("//admin/api/auth").remote(remote).dispatch().status();
+
+        for _ in 0..60 {
+            assert_eq!(status(), Status::Unauthorized);
+        }
+
+        assert_eq!(status(), Status::TooManyRequests);
+    }
+}
@@ -0,0 +1,182 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Named API Key Routes
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    api_keys::{ApiKey, ApiKeyInfo, Scope},
+    env::Env,
+    routes::auth::User,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// The input when creating a named API key
+#[derive(Deserialize)]
+pub struct NewApiKey {
+    name: String,
+    scopes: Vec<Scope>
+}
+
+/// Get the named API keys, without the keys themselves
+#[get("/auth/keys")]
+pub fn get_api_keys(_auth: User, env: State<Env>) -> Reply {
+    let keys: Vec<ApiKeyInfo> = ApiKey::read_all(&env)?.iter().map(ApiKey::info).collect();
+
+    reply_data(keys)
+}
+
+/// Create a named API key. The key is only shown in this reply.
+#[post("/auth/keys", data = "<input>")]
+pub fn add_api_key(_auth: User, env: State<Env>, input: Json<NewApiKey>) -> Reply {
+    let input = input.into_inner();
+    let mut keys = ApiKey::read_all(&env)?;
+
+    if keys.iter().any(|key| key.name == input.name) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    let (api_key, key) = ApiKey::generate(input.name, input.scopes)?;
+    let reply = json!({
+        "name": api_key.name,
+        "scopes": api_key.scopes,
+        "key": key
+    });
+
+    keys.push(api_key);
+    ApiKey::write_all(&env, &keys)?;
+
+    reply_data(reply)
+}
+
+/// Revoke a named API key
+#[delete("/auth/keys/<name>")]
+pub fn delete_api_key(_auth: User, env: State<Env>, name: String) -> Reply {
+    let mut keys = ApiKey::read_all(&env)?;
+    let count = keys.len();
+
+    keys.retain(|key| key.name != name);
+
+    if keys.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    ApiKey::write_all(&env, &keys)?;
+
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Header, Method, Status};
+
+    /// A key named `script` with the read and lists scopes. The key is
+    /// `script-key`.
+    const SCRIPT_KEY: &str = "[{\"name\":\"script\",\"scopes\":[\"read\",\"lists\"],\
+                              \"created\":1570000000,\
+                              \"key_hash\":\"ndDeGTfhKPcRTTvVKzYSWtnnGy3ooihODmxXSp+BE3Y=\"}]\n";
+
+    /// Authenticate with the script key
+    fn script_key(builder: TestBuilder) -> TestBuilder {
+        builder
+            .should_auth(false)
+            .header(Header::new("X-Pi-hole-Authenticate", "script-key"))
+    }
+
+    /// The keys are listed without their hashes
+    #[test]
+    fn get_keys() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/keys")
+            .file(PiholeFile::ApiKeys, SCRIPT_KEY)
+            .expect_json(json!([{
+                "name": "script",
+                "scopes": ["read", "lists"],
+                "created": 1_570_000_000
+            }]))
+            .test();
+    }
+
+    /// Keys can make requests in their scopes
+    #[test]
+    fn key_in_scope() {
+        script_key(TestBuilder::new())
+            .endpoint("/admin/api/auth")
+            .file(PiholeFile::ApiKeys, SCRIPT_KEY)
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Keys can not make requests outside of their scopes
+    #[test]
+    fn key_out_of_scope() {
+        script_key(TestBuilder::new())
+            .endpoint("/admin/api/settings/web")
+            .method(Method::Put)
+            .file(PiholeFile::ApiKeys, SCRIPT_KEY)
+            .body(json!({}))
+            .expect_status(Status::Forbidden)
+            .expect_json(json!({
+                "error": {
+                    "key": "forbidden",
+                    "message": "Forbidden",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Keys can not manage keys, even with the read scope
+    #[test]
+    fn key_not_admin() {
+        script_key(TestBuilder::new())
+            .endpoint("/admin/api/auth/keys")
+            .file(PiholeFile::ApiKeys, SCRIPT_KEY)
+            .expect_status(Status::Forbidden)
+            .expect_json(json!({
+                "error": {
+                    "key": "forbidden",
+                    "message": "Forbidden",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Keys must have at least one scope
+    #[test]
+    fn add_key_without_scopes() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/keys")
+            .method(Method::Post)
+            .file(PiholeFile::ApiKeys, "")
+            .body(json!({ "name": "script", "scopes": [] }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Revoked keys are removed from the file
+    #[test]
+    fn delete_key() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/keys/script")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::ApiKeys, SCRIPT_KEY, "[]\n")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
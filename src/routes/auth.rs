@@ -8,79 +8,231 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use crate::util::{reply_success, Error, ErrorKind, Reply};
+use crate::{
+    api_keys::ApiKey,
+    env::Env,
+    notifications::Notifier,
+    permissions::{Permission, PERMISSIONS},
+    rate_limit::client_ip,
+    users::{Account, Role},
+    util::{reply_data, reply_success, routed_path, Error, ErrorKind, Reply}
+};
 use rocket::{
     http::{Cookie, Cookies},
     outcome::IntoOutcome,
     request::{self, FromRequest, Request, State},
     Outcome
 };
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc
+};
 
 const USER_ATTR: &str = "user_id";
 const AUTH_HEADER: &str = "X-Pi-hole-Authenticate";
+const USER_HEADER: &str = "X-Pi-hole-User";
+
+/// The scheme of the `Authorization` header, which can carry the key instead
+/// of the authentication header for clients such as Prometheus which can
+/// only send a bearer token
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// The name used for the user in the request log when the API key was used
+const API_KEY_USER: &str = "(api key)";
 
-/// When used as a request guard, requests must be authenticated
+/// When used as a request guard, requests must be authenticated, and the
+/// user must have the route's permission. See [`PERMISSIONS`].
+///
+/// [`PERMISSIONS`]: ../../permissions/constant.PERMISSIONS.html
 pub struct User {
-    pub id: usize
+    pub id: usize,
+    /// The name of the account, or `None` if an API key was used
+    pub name: Option<String>,
+    pub role: Role,
+    /// The named API key which was used, which limits the user to its scopes
+    pub api_key: Option<ApiKey>
 }
 
-/// Stores the API key in the server state
+/// The name of the authenticated user, stored in the request-local cache so
+/// that changes can be attributed to the user in the request log
+pub struct RequestUser(pub Option<String>);
+
+/// Stores the API key in the server state. Clones share the user IDs, so
+/// IDs are unique across listeners.
+#[derive(Clone)]
 pub struct AuthData {
     key: String,
-    next_id: AtomicUsize
+    next_id: Arc<AtomicUsize>
 }
 
 impl User {
-    /// Try to authenticate the user using `input_key`. If it succeeds, a new
-    /// cookie will be created.
+    /// Try to authenticate the user using `input_key`. If the user header is
+    /// set, the key is the password of that account. Otherwise, it is the API
+    /// key or a named API key. If it succeeds with a password or the API key,
+    /// a new cookie will be created. Named API keys are meant for scripts, so
+    /// they are sent with every request instead.
     fn authenticate(request: &Request, input_key: &str) -> request::Outcome<Self, Error> {
         let auth_data: State<AuthData> = match request.guard().succeeded() {
             Some(auth_data) => auth_data,
             None => return Error::from(ErrorKind::Unknown).into_outcome()
         };
+        let env: State<Env> = match request.guard().succeeded() {
+            Some(env) => env,
+            None => return Error::from(ErrorKind::Unknown).into_outcome()
+        };
 
-        if auth_data.key_matches(input_key) {
-            let user = auth_data.create_user();
+        let authenticated = match request.headers().get_one(USER_HEADER) {
+            Some(name) => match Account::find(&env, name) {
+                Ok(Some(ref account)) if account.password_matches(input_key) => {
+                    Some((Some(account.name.clone()), account.role))
+                }
+                Ok(_) => None,
+                Err(e) => return e.into_outcome()
+            },
+            None if auth_data.key_matches(input_key) => Some((None, Role::Admin)),
+            None => None
+        };
+
+        if let Some((name, role)) = authenticated {
+            let user = auth_data.create_user(name, role);
 
-            // Set a new encrypted cookie with the user's ID
+            // Set a new encrypted cookie with the user's ID and account name
             request.cookies().add_private(
-                Cookie::build(USER_ATTR, user.id.to_string())
+                Cookie::build(USER_ATTR, user.cookie_value())
                     // Allow the web interface to read the cookie
                     .http_only(false)
                     .finish()
             );
 
+            // Raise an alert if this is a login from a new IP. The IP is only
+            // taken from X-Real-IP if the request came from a trusted proxy.
+            let notifier: Option<State<Notifier>> = request.guard().succeeded();
+
+            if let Some(notifier) = notifier {
+                notifier.login_succeeded(client_ip(request, env.config().proxy_auth()), &env);
+            }
+
             Outcome::Success(user)
+        } else if request.headers().get_one(USER_HEADER).is_none() {
+            match ApiKey::find_by_key(&env, input_key) {
+                Ok(Some(api_key)) => Outcome::Success(User {
+                    id: 0,
+                    name: None,
+                    role: Role::Admin,
+                    api_key: Some(api_key)
+                }),
+                Ok(None) => Error::from(ErrorKind::Unauthorized).into_outcome(),
+                Err(e) => e.into_outcome()
+            }
         } else {
             Error::from(ErrorKind::Unauthorized).into_outcome()
         }
     }
 
-    /// Try to get the user ID from cookies. An error is returned if none are
-    /// found.
-    fn check_cookies(mut cookies: Cookies) -> request::Outcome<Self, Error> {
-        cookies
-            .get_private(USER_ATTR)
-            .and_then(|cookie| cookie.value().parse().ok())
-            .map(|id| User { id })
-            .into_outcome((
+    /// Try to get the user from cookies. An error is returned if none are
+    /// found, or if the user's account has been deleted. The role is read
+    /// from the account so that role changes apply to existing sessions.
+    fn check_cookies(request: &Request) -> request::Outcome<Self, Error> {
+        let unauthorized = || {
+            (
                 ErrorKind::Unauthorized.status(),
                 Error::from(ErrorKind::Unauthorized)
-            ))
+            )
+        };
+        let cookie = match request.cookies().get_private(USER_ATTR) {
+            Some(cookie) => cookie,
+            None => return Outcome::Failure(unauthorized())
+        };
+
+        // The cookie is either `<id>` or `<id>:<account name>`
+        let mut parts = cookie.value().splitn(2, ':');
+        let id = match parts.next().and_then(|id| id.parse().ok()) {
+            Some(id) => id,
+            None => return Outcome::Failure(unauthorized())
+        };
+
+        match parts.next() {
+            Some(name) => {
+                let env: State<Env> = match request.guard().succeeded() {
+                    Some(env) => env,
+                    None => return Error::from(ErrorKind::Unknown).into_outcome()
+                };
+
+                match Account::find(&env, name) {
+                    Ok(account) => account
+                        .map(|account| User {
+                            id,
+                            name: Some(account.name),
+                            role: account.role,
+                            api_key: None
+                        })
+                        .into_outcome(unauthorized()),
+                    Err(e) => e.into_outcome()
+                }
+            }
+            None => Outcome::Success(User {
+                id,
+                name: None,
+                role: Role::Admin,
+                api_key: None
+            })
+        }
     }
 
-    /// Log the user out by removing the cookie
-    fn logout(&self, mut cookies: Cookies) {
-        cookies.remove_private(Cookie::named(USER_ATTR));
+    /// Try to get the user from the headers set by a trusted reverse proxy.
+    /// The headers are only trusted if the request came directly from one of
+    /// the configured proxies. The user's groups decide their role.
+    fn check_proxy(request: &Request) -> Option<Self> {
+        let env: State<Env> = request.guard().succeeded()?;
+        let proxy_auth = env.config().proxy_auth()?;
+
+        if !proxy_auth.is_trusted(request.remote()?.ip()) {
+            return None;
+        }
+
+        let name = request
+            .headers()
+            .get_one(proxy_auth.user_header())
+            .map(str::trim)
+            .filter(|name| !name.is_empty())?;
+        let groups = request
+            .headers()
+            .get_one(proxy_auth.groups_header())
+            .unwrap_or_default();
+        let role = if proxy_auth.is_admin(groups) {
+            Role::Admin
+        } else {
+            Role::Viewer
+        };
+
+        // The proxy authenticates every request, so there is no session
+        Some(User {
+            id: 0,
+            name: Some(name.to_owned()),
+            role,
+            api_key: None
+        })
     }
-}
 
-impl<'a, 'r> FromRequest<'a, 'r> for User {
-    type Error = Error;
+    /// Authenticate the request using a trusted proxy's headers, the
+    /// authentication headers, or cookies, without checking permissions
+    fn authenticated(request: &Request) -> request::Outcome<Self, Error> {
+        let outcome = if let Some(user) = User::check_proxy(request) {
+            Outcome::Success(user)
+        } else {
+            User::from_credentials(request)
+        };
 
-    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
-        match request.headers().get_one(AUTH_HEADER) {
+        if let Outcome::Success(ref user) = outcome {
+            request.local_cache(|| RequestUser(Some(user.log_name())));
+        }
+
+        outcome
+    }
+
+    /// Authenticate the request using the authentication headers or cookies
+    fn from_credentials(request: &Request) -> request::Outcome<Self, Error> {
+        match User::input_key(request) {
             // Try to authenticate, and if that fails check cookies
             Some(key) => {
                 let auth_result = User::authenticate(request, key);
@@ -88,11 +240,74 @@ impl<'a, 'r> FromRequest<'a, 'r> for User {
                 if auth_result.is_success() {
                     auth_result
                 } else {
-                    User::check_cookies(request.cookies())
+                    User::check_cookies(request)
                 }
             }
             // No attempt to authenticate, so check cookies
-            None => User::check_cookies(request.cookies())
+            None => User::check_cookies(request)
+        }
+    }
+
+    /// Get the cookie value, which identifies the session and account
+    fn cookie_value(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("{}:{}", self.id, name),
+            None => self.id.to_string()
+        }
+    }
+
+    /// Get the name used for the user in the request log
+    fn log_name(&self) -> String {
+        match (&self.name, &self.api_key) {
+            (Some(name), _) => name.clone(),
+            (None, Some(api_key)) => format!("key:{}", api_key.name),
+            (None, None) => API_KEY_USER.to_owned()
+        }
+    }
+
+    /// Check if the user has the permission. Named API keys are limited to
+    /// their scopes.
+    fn has_permission(&self, permission: &Permission) -> bool {
+        let key_scopes = self
+            .api_key
+            .as_ref()
+            .map(|api_key| api_key.scopes.as_slice());
+
+        permission.allows(self.role, key_scopes)
+    }
+
+    /// Get the key from the authentication header, or from a bearer token in
+    /// the `Authorization` header
+    fn input_key<'r>(request: &'r Request) -> Option<&'r str> {
+        request.headers().get_one(AUTH_HEADER).or_else(|| {
+            request
+                .headers()
+                .get_one("Authorization")
+                .filter(|value| value.starts_with(BEARER_PREFIX))
+                .map(|value| &value[BEARER_PREFIX.len()..])
+        })
+    }
+
+    /// Log the user out by removing the cookie
+    fn logout(&self, mut cookies: Cookies) {
+        cookies.remove_private(Cookie::named(USER_ATTR));
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for User {
+    type Error = Error;
+
+    /// Authenticate the request, and check that the user has the permission
+    /// of the route in the permissions table
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let user = match User::authenticated(request) {
+            Outcome::Success(user) => user,
+            outcome => return outcome
+        };
+
+        match Permission::find(request.method(), &routed_path(request)) {
+            Some(permission) if user.has_permission(permission) => Outcome::Success(user),
+            _ => Error::from(ErrorKind::Forbidden).into_outcome()
         }
     }
 }
@@ -102,7 +317,7 @@ impl AuthData {
     pub fn new(key: String) -> AuthData {
         AuthData {
             key,
-            next_id: AtomicUsize::new(1)
+            next_id: Arc::new(AtomicUsize::new(1))
         }
     }
 
@@ -112,9 +327,12 @@ impl AuthData {
     }
 
     /// Create a new user and increment `next_id`
-    fn create_user(&self) -> User {
+    fn create_user(&self, name: Option<String>, role: Role) -> User {
         User {
-            id: self.next_id.fetch_add(1, Ordering::Relaxed)
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            name,
+            role,
+            api_key: None
         }
     }
 }
@@ -132,6 +350,29 @@ pub fn logout(user: User, cookies: Cookies) -> Reply {
     reply_success()
 }
 
+/// A permission, and whether the current user has it
+#[derive(Serialize)]
+pub struct PermissionReply {
+    #[serde(flatten)]
+    permission: &'static Permission,
+    allowed: bool
+}
+
+/// Get the permissions table, and which permissions the current user has, so
+/// that the web interface can hide what the user can not use
+#[get("/auth/permissions")]
+pub fn get_permissions(user: User) -> Reply {
+    let permissions: Vec<PermissionReply> = PERMISSIONS
+        .iter()
+        .map(|permission| PermissionReply {
+            permission,
+            allowed: user.has_permission(permission)
+        })
+        .collect();
+
+    reply_data(permissions)
+}
+
 #[cfg(test)]
 mod test {
     use crate::testing::TestBuilder;
@@ -167,6 +408,35 @@ mod test {
             .test()
     }
 
+    /// The API key can be sent as a bearer token
+    #[test]
+    fn bearer_token() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(false)
+            .header(Header::new("Authorization", "Bearer test_key"))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Only the bearer scheme of the `Authorization` header is accepted
+    #[test]
+    fn basic_auth() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(false)
+            .header(Header::new("Authorization", "Basic dGVzdF9rZXk="))
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
     /// Providing incorrect authorization should not authorize the request
     #[test]
     fn wrong_password() {
@@ -187,4 +457,82 @@ mod test {
             }))
             .test();
     }
+
+    /// The permissions table is shown with the permissions the user has
+    #[test]
+    fn permissions() {
+        let permission = |name: &str,
+                          access: &str,
+                          paths: &[&str],
+                          role: &str,
+                          scope: Option<&str>,
+                          allowed: bool| {
+            json!({
+                "name": name,
+                "access": access,
+                "paths": paths,
+                "role": role,
+                "scope": scope,
+                "allowed": allowed
+            })
+        };
+
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/permissions")
+            .expect_json(json!([
+                permission(
+                    "accounts",
+                    "any",
+                    &["/auth/users", "/auth/keys"],
+                    "admin",
+                    None,
+                    true
+                ),
+                permission(
+                    "preferences",
+                    "change",
+                    &["/auth/preferences"],
+                    "viewer",
+                    None,
+                    true
+                ),
+                permission("session", "change", &["/auth"], "viewer", None, true),
+                permission("cache", "change", &["/dns/cache"], "admin", None, true),
+                permission(
+                    "check",
+                    "any",
+                    &["/dns/check"],
+                    "viewer",
+                    Some("read"),
+                    true
+                ),
+                permission(
+                    "lists",
+                    "change",
+                    &["/dns", "/groups"],
+                    "admin",
+                    Some("lists"),
+                    true
+                ),
+                permission(
+                    "teleporter",
+                    "any",
+                    &["/settings/teleporter"],
+                    "admin",
+                    None,
+                    true
+                ),
+                permission(
+                    "settings",
+                    "change",
+                    &["/settings"],
+                    "admin",
+                    Some("settings"),
+                    true
+                ),
+                permission("read", "read", &[], "viewer", Some("read"), true),
+                permission("changes", "change", &[], "admin", None, true)
+            ]))
+            .test();
+    }
 }
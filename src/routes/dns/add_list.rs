@@ -10,48 +10,89 @@
 
 use crate::{
     env::Env,
-    ftl::FtlConnectionType,
+    ftl::{FtlConnectionType, FtlMemory},
     routes::{
         auth::User,
-        dns::{common::reload_gravity, list::List}
+        dns::{
+            changes::{ChangeAction, ListChanges},
+            gravity_reload::GravityReloader,
+            list::List,
+            whitelist_impact::whitelist_impact
+        }
     },
-    util::{reply_success, Reply}
+    util::{current_time, reply_data, reply_success, Reply}
 };
+use regex::Regex;
 use rocket::State;
 use rocket_contrib::json::Json;
 
-/// Represents an API input containing a domain
+/// Represents an API input containing a domain, and optionally a comment
 #[derive(Deserialize)]
 pub struct DomainInput {
-    domain: String
+    domain: String,
+    #[serde(default)]
+    comment: Option<String>
 }
 
-/// Add a domain to the whitelist
+/// Add a domain to the whitelist. The reply includes the recent blocked
+/// queries which the domain will now allow, as `impact`.
 #[post("/dns/whitelist", data = "<domain_input>")]
-pub fn add_whitelist(_auth: User, env: State<Env>, domain_input: Json<DomainInput>) -> Reply {
-    let domain = &domain_input.0.domain;
+pub fn add_whitelist(
+    _auth: User,
+    env: State<Env>,
+    ftl_memory: State<FtlMemory>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    domain_input: Json<DomainInput>
+) -> Reply {
+    let DomainInput { domain, comment } = domain_input.into_inner();
+    let domain = &domain;
 
     // We need to add it to the whitelist and remove it from the blacklist
     List::White.add(domain, &env)?;
-    List::Black.try_remove(domain, &env)?;
+    List::White.record_added(domain, comment, &env, current_time())?;
+    changes.record(&List::White, ChangeAction::Add, domain);
+
+    if List::Black.try_remove(domain, &env)? {
+        changes.record(&List::Black, ChangeAction::Remove, domain);
+    }
+
+    let impact = whitelist_impact(&env, &ftl_memory, |queried| queried == domain)?;
 
     // At this point, since we haven't hit an error yet, reload gravity
-    reload_gravity(List::White, &env)?;
-    reply_success()
+    reply_data(json!({
+        "status": "success",
+        "reload": reloader.request(List::White, &env),
+        "impact": impact
+    }))
 }
 
 /// Add a domain to the blacklist
 #[post("/dns/blacklist", data = "<domain_input>")]
-pub fn add_blacklist(_auth: User, env: State<Env>, domain_input: Json<DomainInput>) -> Reply {
-    let domain = &domain_input.0.domain;
+pub fn add_blacklist(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    domain_input: Json<DomainInput>
+) -> Reply {
+    let DomainInput { domain, comment } = domain_input.into_inner();
+    let domain = &domain;
 
     // We need to add it to the blacklist and remove it from the whitelist
     List::Black.add(domain, &env)?;
-    List::White.try_remove(domain, &env)?;
+    List::Black.record_added(domain, comment, &env, current_time())?;
+    changes.record(&List::Black, ChangeAction::Add, domain);
+
+    if List::White.try_remove(domain, &env)? {
+        changes.record(&List::White, ChangeAction::Remove, domain);
+    }
 
     // At this point, since we haven't hit an error yet, reload gravity
-    reload_gravity(List::Black, &env)?;
-    reply_success()
+    reply_data(json!({
+        "status": "success",
+        "reload": reloader.request(List::Black, &env)
+    }))
 }
 
 /// Add a domain to the regex list
@@ -59,26 +100,63 @@ pub fn add_blacklist(_auth: User, env: State<Env>, domain_input: Json<DomainInpu
 pub fn add_regexlist(
     _auth: User,
     env: State<Env>,
+    changes: State<ListChanges>,
     ftl: State<FtlConnectionType>,
     domain_input: Json<DomainInput>
 ) -> Reply {
-    let domain = &domain_input.0.domain;
+    let DomainInput { domain, comment } = domain_input.into_inner();
+    let domain = &domain;
 
     // We only need to add it to the regex list
     List::Regex.add(domain, &env)?;
+    List::Regex.record_added(domain, comment, &env, current_time())?;
+    changes.record(&List::Regex, ChangeAction::Add, domain);
 
     // At this point, since we haven't hit an error yet, tell FTL to recompile regex
     ftl.connect("recompile-regex")?.expect_eom()?;
     reply_success()
 }
 
+/// Add a domain to the regex whitelist. The reply includes the recent blocked
+/// queries which the regex will now allow, as `impact`.
+#[post("/dns/regex_whitelist", data = "<domain_input>")]
+pub fn add_regex_whitelist(
+    _auth: User,
+    env: State<Env>,
+    ftl_memory: State<FtlMemory>,
+    changes: State<ListChanges>,
+    ftl: State<FtlConnectionType>,
+    domain_input: Json<DomainInput>
+) -> Reply {
+    let DomainInput { domain, comment } = domain_input.into_inner();
+    let domain = &domain;
+
+    // We only need to add it to the regex whitelist
+    List::RegexWhite.add(domain, &env)?;
+    List::RegexWhite.record_added(domain, comment, &env, current_time())?;
+    changes.record(&List::RegexWhite, ChangeAction::Add, domain);
+
+    // At this point, since we haven't hit an error yet, tell FTL to recompile regex
+    ftl.connect("recompile-regex")?.expect_eom()?;
+
+    // The regex was checked when it was added, so it is valid
+    let regex = Regex::new(domain).unwrap();
+    let impact = whitelist_impact(&env, &ftl_memory, |queried| regex.is_match(queried))?;
+
+    reply_data(json!({
+        "status": "success",
+        "impact": impact
+    }))
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
         env::PiholeFile,
+        routes::stats::testing::test_memory,
         testing::{write_eom, TestBuilder}
     };
-    use rocket::http::Method;
+    use rocket::http::{Method, Status};
 
     #[test]
     fn test_add_whitelist() {
@@ -90,7 +168,36 @@ mod test {
             .file(PiholeFile::Regexlist, "")
             .file(PiholeFile::SetupVars, "")
             .body(json!({ "domain": "example.com" }))
-            .expect_json(json!({ "status": "success" }))
+            .expect_json(json!({
+                "status": "success",
+                "reload": "pending",
+                "impact": { "blocked_queries": 0, "clients": [] }
+            }))
+            .test();
+    }
+
+    /// The blocked queries of the domain are in the reply
+    #[test]
+    fn test_add_whitelist_impact() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist")
+            .method(Method::Post)
+            .ftl_memory(test_memory())
+            .file_expect(PiholeFile::Whitelist, "", "domain2.com\n")
+            .file(PiholeFile::Blacklist, "")
+            .file(PiholeFile::Regexlist, "")
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({ "domain": "domain2.com" }))
+            .expect_json(json!({
+                "status": "success",
+                "reload": "pending",
+                "impact": {
+                    "blocked_queries": 1,
+                    "clients": [
+                        { "name": "", "ip": "192.168.1.11", "blocked_queries": 1 }
+                    ]
+                }
+            }))
             .test();
     }
 
@@ -104,7 +211,7 @@ mod test {
             .file(PiholeFile::Regexlist, "")
             .file(PiholeFile::SetupVars, "")
             .body(json!({ "domain": "example.com" }))
-            .expect_json(json!({ "status": "success" }))
+            .expect_json(json!({ "status": "success", "reload": "pending" }))
             .test();
     }
 
@@ -125,4 +232,40 @@ mod test {
             .expect_json(json!({ "status": "success" }))
             .test();
     }
+
+    #[test]
+    fn test_add_regex_whitelist() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regex_whitelist")
+            .method(Method::Post)
+            .ftl("recompile-regex", data)
+            .file_expect(PiholeFile::RegexWhitelist, "", "^.*example.com$\n")
+            .body(json!({ "domain": "^.*example.com$" }))
+            .expect_json(json!({
+                "status": "success",
+                "impact": { "blocked_queries": 0, "clients": [] }
+            }))
+            .test();
+    }
+
+    #[test]
+    fn test_add_regex_whitelist_invalid() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regex_whitelist")
+            .method(Method::Post)
+            .file(PiholeFile::RegexWhitelist, "")
+            .body(json!({ "domain": "example(" }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_domain",
+                    "message": "Invalid domain",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
 }
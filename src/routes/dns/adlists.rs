@@ -0,0 +1,312 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Adlist Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    services::{
+        read_adlists, read_checksum_urls, read_fetch_states, write_checksum_url, Adlist, FetchState
+    },
+    settings::ValueType,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the adlists, including disabled adlists, with the result of their
+/// last fetch. The fetch state includes the error if the fetch failed and
+/// whether the download matched its checksum. Disabled adlists are not
+/// fetched.
+#[get("/dns/adlists")]
+pub fn get_adlists(_auth: User, env: State<Env>) -> Reply {
+    let states = read_fetch_states(&env)?;
+    let checksum_urls = read_checksum_urls(&env)?;
+
+    let adlists: Vec<AdlistReply> = Adlist::read_all(&env)?
+        .into_iter()
+        .map(|adlist| {
+            let state = states
+                .iter()
+                .find(|state| state.url == adlist.url)
+                .cloned()
+                .unwrap_or_else(|| FetchState {
+                    checksum_url: checksum_urls.get(&adlist.url).cloned(),
+                    ..FetchState::new(&adlist.url)
+                });
+
+            AdlistReply {
+                enabled: adlist.enabled,
+                comment: adlist.comment,
+                state
+            }
+        })
+        .collect();
+
+    reply_data(adlists)
+}
+
+/// Add an adlist. Gravity uses it the next time it is built.
+#[post("/dns/adlists", data = "<adlist>")]
+pub fn add_adlist(_auth: User, env: State<Env>, adlist: Json<Adlist>) -> Reply {
+    adlist.add(&env)?;
+
+    reply_success()
+}
+
+/// Enable or disable an adlist, or change its comment
+#[put("/dns/adlists", data = "<adlist>")]
+pub fn update_adlist(_auth: User, env: State<Env>, adlist: Json<Adlist>) -> Reply {
+    adlist.update(&env)?;
+
+    reply_success()
+}
+
+/// Remove an adlist, along with its checksum URL
+#[delete("/dns/adlists?<url>")]
+pub fn delete_adlist(_auth: User, env: State<Env>, url: String) -> Reply {
+    Adlist::remove(&env, &url)?;
+
+    if read_checksum_urls(&env)?.contains_key(&url) {
+        write_checksum_url(&env, &url, None)?;
+    }
+
+    reply_success()
+}
+
+/// Set the checksum URL of an adlist. Downloads of the adlist are only used
+/// if they match the SHA-256 checksum at this URL. A `null` checksum URL
+/// turns off verification.
+#[put("/dns/adlists/checksum", data = "<input>")]
+pub fn put_adlist_checksum(_auth: User, env: State<Env>, input: Json<ChecksumInput>) -> Reply {
+    if !read_adlists(&env)?.contains(&input.url) {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    if let Some(ref checksum_url) = input.checksum_url {
+        if !ValueType::Url.is_valid(checksum_url) {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+    }
+
+    write_checksum_url(
+        &env,
+        &input.url,
+        input.checksum_url.as_ref().map(String::as_str)
+    )?;
+
+    reply_success()
+}
+
+/// An adlist with its fetch state
+#[derive(Serialize)]
+pub struct AdlistReply {
+    enabled: bool,
+    comment: String,
+    #[serde(flatten)]
+    state: FetchState
+}
+
+/// The input when setting the checksum URL of an adlist
+#[derive(Deserialize)]
+pub struct ChecksumInput {
+    url: String,
+    checksum_url: Option<String>
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// Adlists are returned with their fetch status. Adlists which have not
+    /// been fetched, such as disabled adlists, have no status.
+    #[test]
+    fn get_adlists() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists")
+            .file(
+                PiholeFile::Adlists,
+                "https://example.com/hosts\n#https://example.net/hosts # Disabled\n"
+            )
+            .file(
+                PiholeFile::AdlistStatus,
+                "[{\"url\":\"https://example.com/hosts\",\"etag\":null,\
+                 \"last_modified\":null,\"checksum\":null,\"size\":0,\
+                 \"last_fetch\":100,\"status\":\"failed\",\
+                 \"last_error\":\"The list does not match its checksum\",\
+                 \"verification\":\"mismatch\"}]\n"
+            )
+            .file(
+                PiholeFile::AdlistChecksums,
+                "https://example.com/hosts https://example.com/hosts.sha256\n"
+            )
+            .expect_json(json!([
+                {
+                    "url": "https://example.com/hosts",
+                    "enabled": true,
+                    "comment": "",
+                    "etag": null,
+                    "last_modified": null,
+                    "checksum": null,
+                    "size": 0,
+                    "last_fetch": 100,
+                    "status": "failed",
+                    "last_error": "The list does not match its checksum",
+                    "checksum_url": "https://example.com/hosts.sha256",
+                    "verification": "mismatch"
+                },
+                {
+                    "url": "https://example.net/hosts",
+                    "enabled": false,
+                    "comment": "Disabled",
+                    "etag": null,
+                    "last_modified": null,
+                    "checksum": null,
+                    "size": 0,
+                    "last_fetch": null,
+                    "status": null,
+                    "last_error": null,
+                    "checksum_url": null,
+                    "verification": null
+                }
+            ]))
+            .test();
+    }
+
+    /// Checksum URLs are saved by adlist URL
+    #[test]
+    fn put_checksum() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists/checksum")
+            .method(Method::Put)
+            .file(PiholeFile::Adlists, "https://example.com/hosts\n")
+            .file_expect(
+                PiholeFile::AdlistChecksums,
+                "",
+                "https://example.com/hosts https://example.com/hosts.sha256\n"
+            )
+            .body(json!({
+                "url": "https://example.com/hosts",
+                "checksum_url": "https://example.com/hosts.sha256"
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// A null checksum URL removes the checksum URL
+    #[test]
+    fn remove_checksum() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists/checksum")
+            .method(Method::Put)
+            .file(PiholeFile::Adlists, "https://example.com/hosts\n")
+            .file_expect(
+                PiholeFile::AdlistChecksums,
+                "https://example.com/hosts https://example.com/hosts.sha256\n",
+                ""
+            )
+            .body(json!({ "url": "https://example.com/hosts", "checksum_url": null }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Checksum URLs can only be set for existing adlists
+    #[test]
+    fn put_checksum_unknown_adlist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists/checksum")
+            .method(Method::Put)
+            .file(PiholeFile::Adlists, "https://example.com/hosts\n")
+            .body(json!({
+                "url": "https://example.net/hosts",
+                "checksum_url": "https://example.net/hosts.sha256"
+            }))
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Adlists are added to the end of the adlists file
+    #[test]
+    fn add_adlist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists")
+            .method(Method::Post)
+            .file_expect(
+                PiholeFile::Adlists,
+                "# Blocklists\nhttps://example.com/hosts\n",
+                "# Blocklists\nhttps://example.com/hosts\nhttps://example.net/hosts # Trackers\n"
+            )
+            .body(json!({ "url": "https://example.net/hosts", "comment": "Trackers" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An adlist can only be added once
+    #[test]
+    fn add_duplicate_adlist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists")
+            .method(Method::Post)
+            .file(PiholeFile::Adlists, "#https://example.com/hosts\n")
+            .body(json!({ "url": "https://example.com/hosts" }))
+            .expect_status(Status::Conflict)
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Disabling an adlist comments it out
+    #[test]
+    fn disable_adlist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::Adlists,
+                "https://example.com/hosts\nhttps://example.net/hosts\n",
+                "https://example.com/hosts\n#https://example.net/hosts\n"
+            )
+            .body(json!({ "url": "https://example.net/hosts", "enabled": false }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Removing an adlist also removes its checksum URL
+    #[test]
+    fn delete_adlist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlists?url=https%3A%2F%2Fexample.com%2Fhosts")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::Adlists,
+                "https://example.com/hosts\nhttps://example.net/hosts\n",
+                "https://example.net/hosts\n"
+            )
+            .file_expect(
+                PiholeFile::AdlistChecksums,
+                "https://example.com/hosts https://example.com/hosts.sha256\n",
+                ""
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
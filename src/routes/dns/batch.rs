@@ -0,0 +1,377 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Batch Domain List Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlConnectionType,
+    routes::{
+        auth::User,
+        dns::{
+            changes::{ChangeAction, ListChanges},
+            gravity_reload::GravityReloader,
+            list::List
+        }
+    },
+    util::{reply_data, reply_success, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::collections::HashSet;
+
+/// Represents an API input containing many domains
+#[derive(Deserialize)]
+pub struct BatchInput {
+    domains: Vec<String>
+}
+
+impl BatchInput {
+    /// Get the domains without duplicates, in their original order
+    fn unique_domains(self) -> Vec<String> {
+        let mut seen = HashSet::new();
+
+        self.domains
+            .into_iter()
+            .filter(|domain| seen.insert(domain.clone()))
+            .collect()
+    }
+}
+
+/// Add domains to the whitelist
+#[post("/dns/whitelist/batch", data = "<input>")]
+pub fn add_whitelist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    input: Json<BatchInput>
+) -> Reply {
+    add_batch(
+        List::White,
+        List::Black,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &reloader
+    )
+}
+
+/// Add domains to the blacklist
+#[post("/dns/blacklist/batch", data = "<input>")]
+pub fn add_blacklist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    input: Json<BatchInput>
+) -> Reply {
+    add_batch(
+        List::Black,
+        List::White,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &reloader
+    )
+}
+
+/// Add domains to the regex list
+#[post("/dns/regexlist/batch", data = "<input>")]
+pub fn add_regexlist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    ftl: State<FtlConnectionType>,
+    input: Json<BatchInput>
+) -> Reply {
+    add_regex_batch(
+        List::Regex,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &ftl
+    )
+}
+
+/// Add domains to the regex whitelist
+#[post("/dns/regex_whitelist/batch", data = "<input>")]
+pub fn add_regex_whitelist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    ftl: State<FtlConnectionType>,
+    input: Json<BatchInput>
+) -> Reply {
+    add_regex_batch(
+        List::RegexWhite,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &ftl
+    )
+}
+
+/// Delete domains from the whitelist
+#[delete("/dns/whitelist/batch", data = "<input>")]
+pub fn delete_whitelist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    input: Json<BatchInput>
+) -> Reply {
+    delete_batch(
+        List::White,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &reloader
+    )
+}
+
+/// Delete domains from the blacklist
+#[delete("/dns/blacklist/batch", data = "<input>")]
+pub fn delete_blacklist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    input: Json<BatchInput>
+) -> Reply {
+    delete_batch(
+        List::Black,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &reloader
+    )
+}
+
+/// Delete domains from the regex list
+#[delete("/dns/regexlist/batch", data = "<input>")]
+pub fn delete_regexlist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    ftl: State<FtlConnectionType>,
+    input: Json<BatchInput>
+) -> Reply {
+    delete_regex_batch(
+        List::Regex,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &ftl
+    )
+}
+
+/// Delete domains from the regex whitelist
+#[delete("/dns/regex_whitelist/batch", data = "<input>")]
+pub fn delete_regex_whitelist_batch(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    ftl: State<FtlConnectionType>,
+    input: Json<BatchInput>
+) -> Reply {
+    delete_regex_batch(
+        List::RegexWhite,
+        &input.into_inner().unique_domains(),
+        &env,
+        &changes,
+        &ftl
+    )
+}
+
+/// Add the domains to the whitelist or blacklist and remove them from the
+/// other list, then reload Gravity once
+fn add_batch(
+    list: List,
+    opposite: List,
+    domains: &[String],
+    env: &Env,
+    changes: &ListChanges,
+    reloader: &GravityReloader
+) -> Reply {
+    list.add_all(domains, env)?;
+    for domain in domains {
+        changes.record(&list, ChangeAction::Add, domain);
+    }
+
+    for domain in opposite.try_remove_all(domains, env)? {
+        changes.record(&opposite, ChangeAction::Remove, &domain);
+    }
+
+    reply_data(json!({
+        "status": "success",
+        "reload": reloader.request(list, env)
+    }))
+}
+
+/// Delete the domains from the whitelist or blacklist, then reload Gravity
+/// once
+fn delete_batch(
+    list: List,
+    domains: &[String],
+    env: &Env,
+    changes: &ListChanges,
+    reloader: &GravityReloader
+) -> Reply {
+    list.remove_all(domains, env)?;
+    for domain in domains {
+        changes.record(&list, ChangeAction::Remove, domain);
+    }
+
+    reply_data(json!({
+        "status": "success",
+        "reload": reloader.request(list, env)
+    }))
+}
+
+/// Add the domains to a regex list, then tell FTL to recompile the regexes
+/// once
+fn add_regex_batch(
+    list: List,
+    domains: &[String],
+    env: &Env,
+    changes: &ListChanges,
+    ftl: &FtlConnectionType
+) -> Reply {
+    list.add_all(domains, env)?;
+    for domain in domains {
+        changes.record(&list, ChangeAction::Add, domain);
+    }
+
+    ftl.connect("recompile-regex")?.expect_eom()?;
+    reply_success()
+}
+
+/// Delete the domains from a regex list, then tell FTL to recompile the
+/// regexes once
+fn delete_regex_batch(
+    list: List,
+    domains: &[String],
+    env: &Env,
+    changes: &ListChanges,
+    ftl: &FtlConnectionType
+) -> Reply {
+    list.remove_all(domains, env)?;
+    for domain in domains {
+        changes.record(&list, ChangeAction::Remove, domain);
+    }
+
+    ftl.connect("recompile-regex")?.expect_eom()?;
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        testing::{write_eom, TestBuilder}
+    };
+    use rocket::http::{Method, Status};
+
+    /// The domains are added to the whitelist and removed from the blacklist.
+    /// Duplicate domains are only added once.
+    #[test]
+    fn add_whitelist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist/batch")
+            .method(Method::Post)
+            .file_expect(
+                PiholeFile::Whitelist,
+                "a.example.com\n",
+                "a.example.com\nb.example.com\nc.example.com\n"
+            )
+            .file_expect(
+                PiholeFile::Blacklist,
+                "c.example.com\nd.example.com\n",
+                "d.example.com\n"
+            )
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({
+                "domains": ["b.example.com", "c.example.com", "b.example.com"]
+            }))
+            .expect_json(json!({ "status": "success", "reload": "pending" }))
+            .test();
+    }
+
+    /// No domains are added if one of them is invalid
+    #[test]
+    fn add_invalid() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/blacklist/batch")
+            .method(Method::Post)
+            .file_expect(PiholeFile::Blacklist, "a.example.com\n", "a.example.com\n")
+            .file(PiholeFile::Whitelist, "")
+            .body(json!({ "domains": ["b.example.com", "not a domain"] }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_domain",
+                    "message": "Invalid domain",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The domains are deleted from the blacklist
+    #[test]
+    fn delete_blacklist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/blacklist/batch")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::Blacklist,
+                "a.example.com\nb.example.com\nc.example.com\n",
+                "b.example.com\n"
+            )
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({ "domains": ["a.example.com", "c.example.com"] }))
+            .expect_json(json!({ "status": "success", "reload": "pending" }))
+            .test();
+    }
+
+    /// No domains are deleted if one of them is not in the list
+    #[test]
+    fn delete_missing() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist/batch")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::Whitelist, "a.example.com\n", "a.example.com\n")
+            .body(json!({ "domains": ["a.example.com", "b.example.com"] }))
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The regexes are added, and FTL recompiles them once
+    #[test]
+    fn add_regexlist() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regexlist/batch")
+            .method(Method::Post)
+            .ftl("recompile-regex", data)
+            .file_expect(PiholeFile::Regexlist, "", "^ads\\.\n^tracker\\.\n")
+            .body(json!({ "domains": ["^ads\\.", "^tracker\\."] }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
@@ -0,0 +1,87 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// DNS Cache Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::FtlConnectionType,
+    routes::auth::User,
+    util::{reply_data, reply_success, Reply}
+};
+use rocket::State;
+
+/// Get the DNS cache statistics from FTL
+#[get("/dns/cache")]
+pub fn get_cache(ftl: State<FtlConnectionType>, _auth: User) -> Reply {
+    let mut con = ftl.connect("cacheinfo")?;
+
+    // Read in the cache size and how many entries have been inserted and
+    // evicted. Entries are only evicted if the cache is full before they
+    // expire.
+    let size = con.read_i32()?;
+    let evictions = con.read_i32()?;
+    let insertions = con.read_i32()?;
+    con.expect_eom()?;
+
+    reply_data(json!({
+        "size": size,
+        "insertions": insertions,
+        "evictions": evictions
+    }))
+}
+
+/// Flush the DNS cache. FTL clears its cache, so new queries are resolved
+/// again by the upstream servers.
+#[delete("/dns/cache")]
+pub fn flush_cache(ftl: State<FtlConnectionType>, _auth: User) -> Reply {
+    let mut con = ftl.connect("flushcache")?;
+    con.expect_eom()?;
+
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::{write_eom, TestBuilder};
+    use rmp::encode;
+    use rocket::http::Method;
+
+    /// The cache statistics are read from FTL
+    #[test]
+    fn get_cache() {
+        let mut data = Vec::new();
+        encode::write_i32(&mut data, 10_000).unwrap();
+        encode::write_i32(&mut data, 12).unwrap();
+        encode::write_i32(&mut data, 4_567).unwrap();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/cache")
+            .ftl("cacheinfo", data)
+            .expect_json(json!({
+                "size": 10_000,
+                "insertions": 4_567,
+                "evictions": 12
+            }))
+            .test();
+    }
+
+    /// FTL is told to flush the cache
+    #[test]
+    fn flush_cache() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/cache")
+            .method(Method::Delete)
+            .ftl("flushcache", data)
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
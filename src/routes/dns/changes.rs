@@ -0,0 +1,334 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Incremental Domain List Changes
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    routes::{auth::User, dns::list::List},
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+/// The maximum number of changes to remember, across all lists
+const MAX_CHANGES: usize = 1000;
+
+/// Get the changes made to a list since the sequence number
+#[get("/dns/<list>/changes?<since>")]
+pub fn get_list_changes(
+    _auth: User,
+    changes: State<ListChanges>,
+    list: String,
+    since: Option<u64>
+) -> Reply {
+    reply_result(get_list_changes_impl(&changes, &list, since.unwrap_or(0)))
+}
+
+/// Get the changes to the list with the given name
+fn get_list_changes_impl(
+    changes: &ListChanges,
+    name: &str,
+    since: u64
+) -> Result<ChangesReply, Error> {
+    let list = List::from_name(name).ok_or(ErrorKind::NotFound)?;
+
+    Ok(changes.since(&list, since))
+}
+
+/// Remembers the recent changes to the lists, so that sync clients can pull
+/// only what changed since their last sync. Each change gets the next
+/// sequence number.
+///
+/// The changes are not saved, so the sequence starts at the current time in
+/// microseconds. Sequence numbers from before a restart are then always older
+/// than the remembered changes, and clients are told to do a full sync.
+#[derive(Clone)]
+pub struct ListChanges {
+    data: Arc<Mutex<ChangeLog>>
+}
+
+/// The mutable change data
+struct ChangeLog {
+    /// The sequence number of the last change
+    seq: u64,
+    /// The sequence number which the remembered changes start after
+    base_seq: u64,
+    /// The sequence number at which each list was last replaced as a whole
+    resets: HashMap<&'static str, u64>,
+    changes: VecDeque<Change>
+}
+
+/// A change to a list
+struct Change {
+    seq: u64,
+    list: &'static str,
+    action: ChangeAction,
+    domain: String
+}
+
+/// The type of change made to a list
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Add,
+    Remove
+}
+
+/// Represents the reply structure for list changes
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ChangesReply {
+    /// The sequence number to use for the next request
+    pub seq: u64,
+    /// If the changes since the sequence number are not known, so the client
+    /// must get the full list instead
+    pub full_sync: bool,
+    pub changes: Vec<ChangeItemReply>
+}
+
+/// Represents the reply structure for a single change
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ChangeItemReply {
+    pub seq: u64,
+    pub action: ChangeAction,
+    pub domain: String
+}
+
+impl Default for ListChanges {
+    fn default() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros()))
+            .unwrap_or_default();
+
+        ListChanges::new(now)
+    }
+}
+
+impl ListChanges {
+    /// Create an empty change log with the sequence starting after `start`
+    fn new(start: u64) -> ListChanges {
+        ListChanges {
+            data: Arc::new(Mutex::new(ChangeLog {
+                seq: start,
+                base_seq: start,
+                resets: HashMap::new(),
+                changes: VecDeque::new()
+            }))
+        }
+    }
+
+    /// Record a change to the list
+    pub fn record(&self, list: &List, action: ChangeAction, domain: &str) {
+        let mut data = self.lock();
+
+        data.seq += 1;
+
+        let change = Change {
+            seq: data.seq,
+            list: list.name(),
+            action,
+            domain: domain.to_owned()
+        };
+        data.changes.push_back(change);
+
+        // Forget the oldest changes
+        while data.changes.len() > MAX_CHANGES {
+            if let Some(change) = data.changes.pop_front() {
+                data.base_seq = change.seq;
+            }
+        }
+    }
+
+    /// Record that the whole list was replaced, such as by a teleporter
+    /// restore. The changes are not known, so clients which synced before the
+    /// list was replaced must do a full sync.
+    pub fn reset(&self, list: &List) {
+        let mut data = self.lock();
+
+        data.seq += 1;
+
+        let seq = data.seq;
+        data.resets.insert(list.name(), seq);
+        data.changes.retain(|change| change.list != list.name());
+    }
+
+    /// Get the changes to the list made after the sequence number
+    pub fn since(&self, list: &List, since: u64) -> ChangesReply {
+        let data = self.lock();
+        let reset_seq = data.resets.get(list.name()).cloned().unwrap_or_default();
+
+        // The changes are unknown if they were forgotten, the list was
+        // replaced, or the sequence number is from before a restart
+        if since < data.base_seq || since < reset_seq || since > data.seq {
+            return ChangesReply {
+                seq: data.seq,
+                full_sync: true,
+                changes: Vec::new()
+            };
+        }
+
+        ChangesReply {
+            seq: data.seq,
+            full_sync: false,
+            changes: data
+                .changes
+                .iter()
+                .filter(|change| change.seq > since && change.list == list.name())
+                .map(|change| ChangeItemReply {
+                    seq: change.seq,
+                    action: change.action,
+                    domain: change.domain.clone()
+                })
+                .collect()
+        }
+    }
+
+    /// Lock the change data. Ignore the poison error because the data is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<ChangeLog> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        get_list_changes_impl, ChangeAction, ChangeItemReply, ChangesReply, ListChanges,
+        MAX_CHANGES
+    };
+    use crate::{routes::dns::list::List, testing::TestBuilder, util::ErrorKind};
+    use rocket::http::Status;
+
+    /// Only the changes to the list after the sequence number are returned
+    #[test]
+    fn changes_since() {
+        let changes = ListChanges::new(100);
+        changes.record(&List::White, ChangeAction::Add, "example.com");
+        changes.record(&List::Black, ChangeAction::Add, "example.net");
+        changes.record(&List::White, ChangeAction::Remove, "example.com");
+
+        assert_eq!(
+            changes.since(&List::White, 101),
+            ChangesReply {
+                seq: 103,
+                full_sync: false,
+                changes: vec![ChangeItemReply {
+                    seq: 103,
+                    action: ChangeAction::Remove,
+                    domain: "example.com".to_owned()
+                }]
+            }
+        );
+    }
+
+    /// The latest sequence number has no changes
+    #[test]
+    fn up_to_date() {
+        let changes = ListChanges::new(100);
+        changes.record(&List::White, ChangeAction::Add, "example.com");
+
+        assert_eq!(
+            changes.since(&List::White, 101),
+            ChangesReply {
+                seq: 101,
+                full_sync: false,
+                changes: Vec::new()
+            }
+        );
+    }
+
+    /// Sequence numbers from before a restart need a full sync
+    #[test]
+    fn unknown_seq() {
+        let changes = ListChanges::new(100);
+
+        assert!(changes.since(&List::White, 50).full_sync);
+        assert!(changes.since(&List::White, 150).full_sync);
+        assert!(!changes.since(&List::White, 100).full_sync);
+    }
+
+    /// Forgotten changes need a full sync
+    #[test]
+    fn forgotten_changes() {
+        let changes = ListChanges::new(0);
+
+        for _ in 0..=MAX_CHANGES {
+            changes.record(&List::Black, ChangeAction::Add, "example.com");
+        }
+
+        assert!(changes.since(&List::Black, 0).full_sync);
+        assert!(!changes.since(&List::Black, 1).full_sync);
+    }
+
+    /// Sequence numbers from before a list was replaced need a full sync,
+    /// but the other lists keep their changes
+    #[test]
+    fn reset_list() {
+        let changes = ListChanges::new(100);
+        changes.record(&List::White, ChangeAction::Add, "example.com");
+        changes.record(&List::Black, ChangeAction::Add, "example.net");
+        changes.reset(&List::White);
+
+        assert!(changes.since(&List::White, 101).full_sync);
+        assert_eq!(
+            changes.since(&List::White, 103),
+            ChangesReply {
+                seq: 103,
+                full_sync: false,
+                changes: Vec::new()
+            }
+        );
+        assert_eq!(
+            changes.since(&List::Black, 101),
+            ChangesReply {
+                seq: 103,
+                full_sync: false,
+                changes: vec![ChangeItemReply {
+                    seq: 102,
+                    action: ChangeAction::Add,
+                    domain: "example.net".to_owned()
+                }]
+            }
+        );
+    }
+
+    /// Unknown lists are not found
+    #[test]
+    fn unknown_list() {
+        assert_eq!(
+            get_list_changes_impl(&ListChanges::new(0), "greylist", 0)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    /// Changes are only shown to authenticated users
+    #[test]
+    fn requires_auth() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist/changes?since=0")
+            .should_auth(false)
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,173 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Bulk Domain Check Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::{
+        auth::User,
+        dns::{
+            common::is_valid_domain,
+            rules::{DomainRules, Verdict}
+        }
+    },
+    services::GravityIndex,
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// The maximum number of domains which can be checked in one request
+const MAX_CHECK_DOMAINS: usize = 1000;
+
+/// Predict whether each domain would be blocked, and by which rule. The lists
+/// are only read once for all of the domains.
+#[post("/dns/check", data = "<input>")]
+pub fn check_domains(
+    _auth: User,
+    env: State<Env>,
+    gravity: State<GravityIndex>,
+    input: Json<CheckInput>
+) -> Reply {
+    reply_data(check(input.into_inner().domains, &env, &gravity)?)
+}
+
+/// The domains to check
+#[derive(Deserialize)]
+pub struct CheckInput {
+    domains: Vec<String>
+}
+
+/// The verdict for a domain
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct DomainCheck {
+    pub domain: String,
+    #[serde(flatten)]
+    pub verdict: Verdict
+}
+
+/// Check the domains, in their original order. If any domain is invalid, no
+/// domains are checked.
+fn check(
+    domains: Vec<String>,
+    env: &Env,
+    gravity: &GravityIndex
+) -> Result<Vec<DomainCheck>, Error> {
+    if domains.len() > MAX_CHECK_DOMAINS {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let domains: Vec<String> = domains
+        .into_iter()
+        .map(|domain| domain.trim().to_lowercase())
+        .collect();
+
+    if !domains.iter().all(|domain| is_valid_domain(domain)) {
+        return Err(Error::from(ErrorKind::InvalidDomain));
+    }
+
+    let rules = DomainRules::load(env, gravity)?;
+
+    Ok(domains
+        .into_iter()
+        .map(|domain| DomainCheck {
+            verdict: rules.evaluate(&domain),
+            domain
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::MAX_CHECK_DOMAINS;
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// Each domain gets a verdict, in the order they were given
+    #[test]
+    fn verdicts() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/check")
+            .method(Method::Post)
+            .file(PiholeFile::Whitelist, "")
+            .file(PiholeFile::Blacklist, "tracker.net\n")
+            .file(PiholeFile::RegexWhitelist, "")
+            .file(PiholeFile::Regexlist, "^ads\\.\n")
+            .file(PiholeFile::Gravity, "example.com\n")
+            .body(json!({
+                "domains": ["Example.com", "ads.pi-hole.net", "tracker.net", "pi-hole.net"]
+            }))
+            .expect_json(json!([
+                {
+                    "domain": "example.com",
+                    "blocked": true,
+                    "list": "gravity",
+                    "rule": "example.com"
+                },
+                {
+                    "domain": "ads.pi-hole.net",
+                    "blocked": true,
+                    "list": "regexlist",
+                    "rule": "^ads\\."
+                },
+                {
+                    "domain": "tracker.net",
+                    "blocked": true,
+                    "list": "blacklist",
+                    "rule": "tracker.net"
+                },
+                {
+                    "domain": "pi-hole.net",
+                    "blocked": false,
+                    "list": null,
+                    "rule": null
+                }
+            ]))
+            .test();
+    }
+
+    /// Requests with an invalid domain are rejected
+    #[test]
+    fn invalid_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/check")
+            .method(Method::Post)
+            .body(json!({ "domains": ["example.com", "exa mple.com"] }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_domain",
+                    "message": "Invalid domain",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Requests with too many domains are rejected
+    #[test]
+    fn too_many_domains() {
+        let domains = vec!["example.com"; MAX_CHECK_DOMAINS + 1];
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/check")
+            .method(Method::Post)
+            .body(json!({ "domains": domains }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -40,23 +40,27 @@ pub fn is_valid_regex(regex_str: &str) -> bool {
     Regex::new(regex_str).is_ok()
 }
 
-/// Reload Gravity to activate changes in lists
-pub fn reload_gravity(list: List, env: &Env) -> Result<(), Error> {
+/// Reload Gravity to activate changes in lists. If a list is given, only that
+/// list is reloaded, otherwise all of the lists are reloaded.
+pub fn reload_gravity(list: Option<List>, env: &Env) -> Result<(), Error> {
     // Don't actually reload Gravity during testing
     if env.is_test() {
         return Ok(());
     }
 
-    let status = Command::new("sudo")
-        .arg("pihole")
-        .arg("-g")
-        .arg("--skip-download")
-        // Based on what list we modified, only reload what is necessary
-        .arg(match list {
+    let mut command = Command::new("sudo");
+    command.arg("pihole").arg("-g").arg("--skip-download");
+
+    // Based on what list we modified, only reload what is necessary
+    if let Some(list) = list {
+        command.arg(match list {
             List::White => "--whitelist-only",
             List::Black => "--blacklist-only",
             _ => return Err(Error::from(ErrorKind::Unknown))
-        })
+        });
+    }
+
+    let status = command
         // Ignore stdin, stdout, and stderr
         .stdin(Stdio::null())
         .stdout(Stdio::null())
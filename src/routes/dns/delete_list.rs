@@ -13,26 +13,48 @@ use crate::{
     ftl::FtlConnectionType,
     routes::{
         auth::User,
-        dns::{common::reload_gravity, list::List}
+        dns::{
+            changes::{ChangeAction, ListChanges},
+            gravity_reload::GravityReloader,
+            list::List
+        }
     },
-    util::{reply_success, Reply}
+    util::{reply_data, reply_success, Reply}
 };
 use rocket::State;
 
 /// Delete a domain from the whitelist
 #[delete("/dns/whitelist/<domain>")]
-pub fn delete_whitelist(_auth: User, env: State<Env>, domain: String) -> Reply {
+pub fn delete_whitelist(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    domain: String
+) -> Reply {
     List::White.remove(&domain, &env)?;
-    reload_gravity(List::White, &env)?;
-    reply_success()
+    changes.record(&List::White, ChangeAction::Remove, &domain);
+    reply_data(json!({
+        "status": "success",
+        "reload": reloader.request(List::White, &env)
+    }))
 }
 
 /// Delete a domain from the blacklist
 #[delete("/dns/blacklist/<domain>")]
-pub fn delete_blacklist(_auth: User, env: State<Env>, domain: String) -> Reply {
+pub fn delete_blacklist(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    domain: String
+) -> Reply {
     List::Black.remove(&domain, &env)?;
-    reload_gravity(List::Black, &env)?;
-    reply_success()
+    changes.record(&List::Black, ChangeAction::Remove, &domain);
+    reply_data(json!({
+        "status": "success",
+        "reload": reloader.request(List::Black, &env)
+    }))
 }
 
 /// Delete a domain from the regex list
@@ -40,10 +62,27 @@ pub fn delete_blacklist(_auth: User, env: State<Env>, domain: String) -> Reply {
 pub fn delete_regexlist(
     _auth: User,
     env: State<Env>,
+    changes: State<ListChanges>,
     ftl: State<FtlConnectionType>,
     domain: String
 ) -> Reply {
     List::Regex.remove(&domain, &env)?;
+    changes.record(&List::Regex, ChangeAction::Remove, &domain);
+    ftl.connect("recompile-regex")?.expect_eom()?;
+    reply_success()
+}
+
+/// Delete a domain from the regex whitelist
+#[delete("/dns/regex_whitelist/<domain>")]
+pub fn delete_regex_whitelist(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    ftl: State<FtlConnectionType>,
+    domain: String
+) -> Reply {
+    List::RegexWhite.remove(&domain, &env)?;
+    changes.record(&List::RegexWhite, ChangeAction::Remove, &domain);
     ftl.connect("recompile-regex")?.expect_eom()?;
     reply_success()
 }
@@ -62,7 +101,7 @@ mod test {
             .endpoint("/admin/api/dns/whitelist/example.com")
             .method(Method::Delete)
             .file_expect(PiholeFile::Whitelist, "example.com\n", "")
-            .expect_json(json!({ "status": "success" }))
+            .expect_json(json!({ "status": "success", "reload": "pending" }))
             .test();
     }
 
@@ -72,7 +111,7 @@ mod test {
             .endpoint("/admin/api/dns/blacklist/example.com")
             .method(Method::Delete)
             .file_expect(PiholeFile::Blacklist, "example.com\n", "")
-            .expect_json(json!({ "status": "success" }))
+            .expect_json(json!({ "status": "success", "reload": "pending" }))
             .test();
     }
 
@@ -89,4 +128,18 @@ mod test {
             .expect_json(json!({ "status": "success" }))
             .test();
     }
+
+    #[test]
+    fn test_delete_regex_whitelist() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regex_whitelist/%5E.%2Aexample.com%24")
+            .method(Method::Delete)
+            .ftl("recompile-regex", data)
+            .file_expect(PiholeFile::RegexWhitelist, "^.*example.com$\n", "")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
 }
@@ -0,0 +1,86 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Comments, Flags, And Dates Of List Entries
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::io::{Read, Write};
+
+/// The details of a list entry which the list files can not hold. Disabled
+/// entries are taken out of their list file, so they are only found here.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct EntryDetails {
+    /// The name of the list, as used in its endpoints
+    pub list: String,
+    pub domain: String,
+    pub comment: Option<String>,
+    pub enabled: bool,
+    pub date_added: Option<u64>,
+    pub date_modified: Option<u64>
+}
+
+/// An entry of a list, as returned by the list endpoints
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ListEntry {
+    pub domain: String,
+    pub comment: Option<String>,
+    pub enabled: bool,
+    pub date_added: Option<u64>,
+    pub date_modified: Option<u64>
+}
+
+impl ListEntry {
+    /// Create the entry of a domain from its details, if it has any
+    pub fn new(domain: String, enabled: bool, details: Option<&EntryDetails>) -> ListEntry {
+        ListEntry {
+            domain,
+            comment: details.and_then(|details| details.comment.clone()),
+            enabled,
+            date_added: details.and_then(|details| details.date_added),
+            date_modified: details.and_then(|details| details.date_modified)
+        }
+    }
+}
+
+/// Read the details of the entries of every list. If the file does not
+/// exist, no entries have details.
+pub fn read_details(env: &Env) -> Result<Vec<EntryDetails>, Error> {
+    if !env.file_exists(PiholeFile::ListDetails) {
+        return Ok(Vec::new());
+    }
+
+    let file_location = env.file_location(PiholeFile::ListDetails).to_owned();
+    let mut json = String::new();
+    env.read_file(PiholeFile::ListDetails)?
+        .read_to_string(&mut json)
+        .context(ErrorKind::FileRead(file_location.clone()))?;
+
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+}
+
+/// Save the details of the entries, replacing the previously saved details
+pub fn write_details(env: &Env, details: &[EntryDetails]) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::ListDetails).to_owned();
+    let mut file = env.write_file(PiholeFile::ListDetails, false)?;
+
+    serde_json::to_writer(&mut file, details)
+        .context(ErrorKind::FileWrite(file_location.clone()))?;
+    writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
@@ -15,34 +15,99 @@ use crate::{
 };
 use rocket::State;
 
-/// Get the Whitelist domains
+/// Get the Whitelist entries, with their comments, flags, and dates
 #[get("/dns/whitelist")]
 pub fn get_whitelist(env: State<Env>) -> Reply {
-    reply_result(List::White.get(&env))
+    reply_result(List::White.get_entries(&env))
 }
 
-/// Get the Blacklist domains
+/// Get the Blacklist entries, with their comments, flags, and dates
 #[get("/dns/blacklist")]
 pub fn get_blacklist(env: State<Env>) -> Reply {
-    reply_result(List::Black.get(&env))
+    reply_result(List::Black.get_entries(&env))
 }
 
-/// Get the Regex list domains
+/// Get the Regex list entries, with their comments, flags, and dates
 #[get("/dns/regexlist")]
 pub fn get_regexlist(env: State<Env>) -> Reply {
-    reply_result(List::Regex.get(&env))
+    reply_result(List::Regex.get_entries(&env))
+}
+
+/// Get the regex whitelist entries, with their comments, flags, and dates
+#[get("/dns/regex_whitelist")]
+pub fn get_regex_whitelist(env: State<Env>) -> Reply {
+    reply_result(List::RegexWhite.get_entries(&env))
 }
 
 #[cfg(test)]
 mod test {
     use crate::{env::PiholeFile, testing::TestBuilder};
+    use serde_json::Value;
 
+    /// An entry without details
+    fn entry(domain: &str) -> Value {
+        json!({
+            "domain": domain,
+            "comment": null,
+            "enabled": true,
+            "date_added": null,
+            "date_modified": null
+        })
+        .into()
+    }
+
+    /// Entries have their details, and disabled entries come last
     #[test]
     fn test_get_whitelist() {
         TestBuilder::new()
             .endpoint("/admin/api/dns/whitelist")
             .file(PiholeFile::Whitelist, "example.com\nexample.net\n")
-            .expect_json(json!(["example.com", "example.net"]))
+            .file(
+                PiholeFile::ListDetails,
+                r#"[
+                    {
+                        "list": "whitelist",
+                        "domain": "example.net",
+                        "comment": "Needed for updates",
+                        "enabled": true,
+                        "date_added": 1000,
+                        "date_modified": 2000
+                    },
+                    {
+                        "list": "whitelist",
+                        "domain": "example.org",
+                        "comment": null,
+                        "enabled": false,
+                        "date_added": 1000,
+                        "date_modified": 3000
+                    },
+                    {
+                        "list": "blacklist",
+                        "domain": "example.com",
+                        "comment": "Not this list",
+                        "enabled": false,
+                        "date_added": null,
+                        "date_modified": null
+                    }
+                ]"#
+            )
+            .expect_json(json!([
+                entry("example.com"),
+                {
+                    "domain": "example.net",
+                    "comment": "Needed for updates",
+                    "enabled": true,
+                    "date_added": 1000,
+                    "date_modified": 2000
+                },
+                {
+                    "domain": "example.org",
+                    "comment": null,
+                    "enabled": false,
+                    "date_added": 1000,
+                    "date_modified": 3000
+                }
+            ]))
             .test();
     }
 
@@ -51,7 +116,7 @@ mod test {
         TestBuilder::new()
             .endpoint("/admin/api/dns/blacklist")
             .file(PiholeFile::Blacklist, "example.com\nexample.net\n")
-            .expect_json(json!(["example.com", "example.net"]))
+            .expect_json(json!([entry("example.com"), entry("example.net")]))
             .test();
     }
 
@@ -60,7 +125,16 @@ mod test {
         TestBuilder::new()
             .endpoint("/admin/api/dns/regexlist")
             .file(PiholeFile::Regexlist, "^.*example.com$\nexample.net\n")
-            .expect_json(json!(["^.*example.com$", "example.net"]))
+            .expect_json(json!([entry("^.*example.com$"), entry("example.net")]))
+            .test();
+    }
+
+    #[test]
+    fn test_get_regex_whitelist() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regex_whitelist")
+            .file(PiholeFile::RegexWhitelist, "^.*example.com$\n")
+            .expect_json(json!([entry("^.*example.com$")]))
             .test();
     }
 }
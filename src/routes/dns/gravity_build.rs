@@ -0,0 +1,46 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Build Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    routes::auth::User,
+    services::GravityBuilder,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// Get the progress of the current or last Gravity build. Gravity is only
+/// built in the API when the native build is enabled in the Gravity schedule.
+#[get("/dns/gravity_build")]
+pub fn get_gravity_build(_auth: User, builder: State<GravityBuilder>) -> Reply {
+    reply_data(builder.progress())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+
+    /// The build is idle until the first build runs
+    #[test]
+    fn idle() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/gravity_build")
+            .expect_json(json!({
+                "stage": "idle",
+                "lists_read": 0,
+                "lists_total": 0,
+                "domains": 0,
+                "invalid": 0,
+                "added": 0,
+                "removed": 0,
+                "duration": null
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,130 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Lookup Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::{
+        auth::User,
+        dns::{common::is_valid_domain, list::List, rules::RegexList}
+    },
+    services::GravityIndex,
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use rocket::State;
+
+/// Check if a domain is in Gravity, either itself or through a parent domain,
+/// or if it matches a regex. This uses the Gravity index, so it is cheap
+/// enough to call before each navigation.
+#[get("/dns/gravity/contains/<domain>")]
+pub fn get_gravity_contains(
+    domain: String,
+    _auth: User,
+    env: State<Env>,
+    gravity: State<GravityIndex>
+) -> Reply {
+    reply_data(gravity_lookup(&domain.to_lowercase(), &env, &gravity)?)
+}
+
+/// Where the domain is found in Gravity and the regex lists
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct GravityLookup {
+    pub domain: String,
+    /// If the domain is in Gravity or matches a blocking regex
+    pub contains: bool,
+    /// The Gravity entry which has the domain, which is the domain itself or
+    /// one of its parent domains
+    pub gravity: Option<String>,
+    /// If the Gravity entry is the domain itself
+    pub exact: bool,
+    /// The regexes which match the domain
+    pub regexlist: Vec<String>,
+    /// The whitelist regexes which match the domain
+    pub regex_whitelist: Vec<String>
+}
+
+/// Look up the lowercase domain in Gravity and the regex lists
+fn gravity_lookup(domain: &str, env: &Env, gravity: &GravityIndex) -> Result<GravityLookup, Error> {
+    if !is_valid_domain(domain) {
+        return Err(Error::from(ErrorKind::InvalidDomain));
+    }
+
+    let entry = gravity.find(env, domain)?;
+    let regexlist = RegexList::load(List::Regex, env)?.matches(domain);
+
+    Ok(GravityLookup {
+        domain: domain.to_owned(),
+        contains: entry.is_some() || !regexlist.is_empty(),
+        exact: entry.as_ref().map_or(false, |entry| entry == domain),
+        gravity: entry,
+        regexlist,
+        regex_whitelist: RegexList::load(List::RegexWhite, env)?.matches(domain)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::Status;
+
+    /// A subdomain of a Gravity entry is found through its parent domain
+    #[test]
+    fn parent_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/gravity/contains/Ads.Example.com")
+            .file(PiholeFile::Gravity, "example.com\n")
+            .file(PiholeFile::Regexlist, "^ads\\.\n(\n")
+            .file(PiholeFile::RegexWhitelist, "")
+            .expect_json(json!({
+                "domain": "ads.example.com",
+                "contains": true,
+                "gravity": "example.com",
+                "exact": false,
+                "regexlist": ["^ads\\."],
+                "regex_whitelist": []
+            }))
+            .test();
+    }
+
+    /// A domain which is not listed anywhere is not contained
+    #[test]
+    fn not_contained() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/gravity/contains/pi-hole.net")
+            .file(PiholeFile::Gravity, "example.com\n")
+            .file(PiholeFile::Regexlist, "")
+            .file(PiholeFile::RegexWhitelist, "")
+            .expect_json(json!({
+                "domain": "pi-hole.net",
+                "contains": false,
+                "gravity": null,
+                "exact": false,
+                "regexlist": [],
+                "regex_whitelist": []
+            }))
+            .test();
+    }
+
+    /// Invalid domains are rejected
+    #[test]
+    fn invalid_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/gravity/contains/exa%20mple.com")
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_domain",
+                    "message": "Invalid domain",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,246 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Debounced Gravity Reloads
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::{
+        auth::User,
+        dns::{common::reload_gravity, list::List}
+    },
+    util::{reply_data, Reply}
+};
+use rocket::State;
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant}
+};
+
+/// How long to wait for more list changes before reloading Gravity
+const DEBOUNCE_TIME: Duration = Duration::from_secs(2);
+
+/// Get the status of the Gravity reload
+#[get("/dns/gravity_reload")]
+pub fn get_gravity_reload(_auth: User, reloader: State<GravityReloader>) -> Reply {
+    reply_data(json!({ "reload": reloader.status() }))
+}
+
+/// Reloads Gravity after list changes. Changes made within a short time of
+/// each other (ex. deleting several domains) are reloaded together, so only
+/// one `pihole -g` runs. Changes made while Gravity is reloading are reloaded
+/// afterwards.
+#[derive(Clone, Default)]
+pub struct GravityReloader {
+    data: Arc<Mutex<ReloadData>>
+}
+
+/// The mutable reload data
+#[derive(Default)]
+struct ReloadData {
+    /// If the whitelist is waiting to be reloaded
+    whitelist: bool,
+    /// If the blacklist is waiting to be reloaded
+    blacklist: bool,
+    /// When the last reload was requested
+    last_request: Option<Instant>,
+    /// If the background thread is waiting to reload or reloading
+    worker_active: bool,
+    running: bool,
+    /// The status of the last reload
+    last_status: ReloadStatus
+}
+
+/// The status of the Gravity reload
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum ReloadStatus {
+    /// No reload has been requested yet
+    Idle,
+    /// A reload has been requested, but has not started
+    Pending,
+    Running,
+    Complete,
+    Failed
+}
+
+impl Default for ReloadStatus {
+    fn default() -> Self {
+        ReloadStatus::Idle
+    }
+}
+
+impl ReloadData {
+    /// Check if a list is waiting to be reloaded
+    fn is_pending(&self) -> bool {
+        self.whitelist || self.blacklist
+    }
+
+    /// Take the lists waiting to be reloaded. If both are waiting, `None` is
+    /// returned so that all lists are reloaded.
+    fn take_lists(&mut self) -> Option<List> {
+        let list = match (self.whitelist, self.blacklist) {
+            (true, false) => Some(List::White),
+            (false, true) => Some(List::Black),
+            _ => None
+        };
+
+        self.whitelist = false;
+        self.blacklist = false;
+
+        list
+    }
+}
+
+impl GravityReloader {
+    /// Request a reload of the list. The reload happens in the background
+    /// once no more changes have been requested for a short time.
+    pub fn request(&self, list: List, env: &Env) -> ReloadStatus {
+        let mut data = self.lock();
+
+        match list {
+            List::White => data.whitelist = true,
+            List::Black => data.blacklist = true,
+            // The other lists do not need Gravity to be reloaded
+            _ => return data.last_status
+        }
+
+        data.last_request = Some(Instant::now());
+
+        // Don't actually start the reload during testing
+        if !data.worker_active && !env.is_test() {
+            data.worker_active = true;
+
+            let reloader = self.clone();
+            let env = Env::Production(env.config().clone());
+
+            thread::spawn(move || reloader.run(&env));
+        }
+
+        ReloadStatus::Pending
+    }
+
+    /// Get the current reload status
+    pub fn status(&self) -> ReloadStatus {
+        let data = self.lock();
+
+        if data.running {
+            ReloadStatus::Running
+        } else if data.is_pending() {
+            ReloadStatus::Pending
+        } else {
+            data.last_status
+        }
+    }
+
+    /// Wait for the changes to stop, then reload Gravity. This repeats until
+    /// there are no more changes waiting to be reloaded.
+    fn run(&self, env: &Env) {
+        loop {
+            thread::sleep(DEBOUNCE_TIME);
+
+            let list = {
+                let mut data = self.lock();
+
+                // Wait longer if there was a recent change
+                match data.last_request {
+                    Some(last_request) if last_request.elapsed() < DEBOUNCE_TIME => continue,
+                    _ => ()
+                }
+
+                data.running = true;
+                data.take_lists()
+            };
+
+            let result = reload_gravity(list, env);
+
+            if let Err(ref e) = result {
+                e.print_stacktrace();
+            }
+
+            let mut data = self.lock();
+            data.running = false;
+            data.last_status = if result.is_ok() {
+                ReloadStatus::Complete
+            } else {
+                ReloadStatus::Failed
+            };
+
+            if !data.is_pending() {
+                data.worker_active = false;
+                return;
+            }
+        }
+    }
+
+    /// Lock the reload data. Ignore the poison error because the data is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<ReloadData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GravityReloader, ReloadData, ReloadStatus};
+    use crate::{
+        env::{Config, Env},
+        routes::dns::list::List,
+        testing::TestBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Requesting a reload marks it as pending
+    #[test]
+    fn request_pending() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let reloader = GravityReloader::default();
+
+        assert_eq!(reloader.status(), ReloadStatus::Idle);
+        assert_eq!(reloader.request(List::White, &env), ReloadStatus::Pending);
+        assert_eq!(reloader.status(), ReloadStatus::Pending);
+    }
+
+    /// Changes to both lists are reloaded together
+    #[test]
+    fn take_both_lists() {
+        let mut data = ReloadData {
+            whitelist: true,
+            blacklist: true,
+            ..ReloadData::default()
+        };
+
+        assert!(data.take_lists().is_none());
+        assert!(!data.is_pending());
+    }
+
+    /// Changes to one list only reload that list
+    #[test]
+    fn take_one_list() {
+        let mut data = ReloadData {
+            blacklist: true,
+            ..ReloadData::default()
+        };
+
+        match data.take_lists() {
+            Some(List::Black) => (),
+            _ => panic!("Expected only the blacklist")
+        }
+    }
+
+    /// No reload has been requested
+    #[test]
+    fn get_idle() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/gravity_reload")
+            .expect_json(json!({ "reload": "idle" }))
+            .test();
+    }
+}
@@ -0,0 +1,50 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Update Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    services::GravityJob,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// Start a full Gravity update (`pihole -g`) in the background. The update's
+/// progress can be followed with the status endpoint.
+#[post("/dns/gravity/update")]
+pub fn update_gravity(_auth: User, env: State<Env>, job: State<GravityJob>) -> Reply {
+    reply_data(job.start(&env)?)
+}
+
+/// Get the progress of the current Gravity update, or the outcome of the
+/// last one
+#[get("/dns/gravity/status")]
+pub fn get_gravity_status(_auth: User, job: State<GravityJob>) -> Reply {
+    reply_data(job.status())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+
+    /// No update has run yet
+    #[test]
+    fn status_idle() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/gravity/status")
+            .expect_json(json!({
+                "state": "idle",
+                "started": null,
+                "finished": null,
+                "progress": null
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,177 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Domain List Content Hashes
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::dns::list::List,
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Get the hash of a list's contents, so sync clients can check if the list
+/// changed without downloading it
+#[get("/dns/<list>/hash")]
+pub fn get_list_hash(env: State<Env>, list: String) -> Reply {
+    reply_result(get_list_hash_impl(&env, &list))
+}
+
+/// Get the combined hash of all the lists, along with the hash of each list
+#[get("/dns/hash")]
+pub fn get_lists_hash(env: State<Env>) -> Reply {
+    reply_result(get_lists_hash_impl(&env))
+}
+
+/// Represents the reply structure for a list hash
+#[derive(Serialize)]
+pub struct ListHashReply {
+    pub list: &'static str,
+    pub hash: String,
+    pub count: usize
+}
+
+/// Represents the reply structure for the combined list hash
+#[derive(Serialize)]
+pub struct ListsHashReply {
+    pub hash: String,
+    pub lists: HashMap<&'static str, String>
+}
+
+/// Get the hash of the list with the given name
+fn get_list_hash_impl(env: &Env, name: &str) -> Result<ListHashReply, Error> {
+    let list = List::from_name(name).ok_or(ErrorKind::NotFound)?;
+    let domains = list.get(env)?;
+
+    Ok(ListHashReply {
+        list: list.name(),
+        hash: hash_domains(domains.clone()),
+        count: domains.len()
+    })
+}
+
+/// Get the hash of each list, and the hash of those hashes
+fn get_lists_hash_impl(env: &Env) -> Result<ListsHashReply, Error> {
+    let mut combined = Sha256::new();
+    let mut lists = HashMap::new();
+
+    for list in List::ALL.iter() {
+        let hash = hash_domains(list.get(env)?);
+
+        combined.input(format!("{} {}\n", list.name(), hash));
+        lists.insert(list.name(), hash);
+    }
+
+    Ok(ListsHashReply {
+        hash: to_hex(&combined.result()),
+        lists
+    })
+}
+
+/// Hash the domains as a hex encoded SHA-256 digest. The domains are sorted
+/// first, so the hash only changes when the contents of the list change.
+fn hash_domains(mut domains: Vec<String>) -> String {
+    domains.sort();
+
+    let mut hasher = Sha256::new();
+
+    for domain in domains {
+        hasher.input(domain);
+        hasher.input("\n");
+    }
+
+    to_hex(&hasher.result())
+}
+
+/// Encode the bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::hash_domains;
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::Status;
+
+    /// The hash does not depend on the order of the domains
+    #[test]
+    fn order_independent() {
+        assert_eq!(
+            hash_domains(vec!["example.net".to_owned(), "example.com".to_owned()]),
+            hash_domains(vec!["example.com".to_owned(), "example.net".to_owned()])
+        );
+    }
+
+    /// The hash of a list is the SHA-256 digest of its sorted domains
+    #[test]
+    fn list_hash() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist/hash")
+            .file(PiholeFile::Whitelist, "example.net\nexample.com\n")
+            .expect_json(json!({
+                "list": "whitelist",
+                "hash": "e7d44192e6546ff5e986842e0cacb78424456229b7da80627ed47d355ad43db3",
+                "count": 2
+            }))
+            .test();
+    }
+
+    /// An empty list has the hash of no data
+    #[test]
+    fn empty_list_hash() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regex_whitelist/hash")
+            .file(PiholeFile::RegexWhitelist, "")
+            .expect_json(json!({
+                "list": "regex_whitelist",
+                "hash": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                "count": 0
+            }))
+            .test();
+    }
+
+    /// Unknown lists are not found
+    #[test]
+    fn unknown_list() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/greylist/hash")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The combined hash covers every list
+    #[test]
+    fn combined_hash() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/hash")
+            .file(PiholeFile::Whitelist, "example.com\nexample.net\n")
+            .file(PiholeFile::Blacklist, "")
+            .file(PiholeFile::Regexlist, "")
+            .file(PiholeFile::RegexWhitelist, "")
+            .expect_json(json!({
+                "hash": "45e600129c488ccd1cab978e887220fda304ec2aae621eb4b8ab97bdaab0dc6f",
+                "lists": {
+                    "whitelist": "e7d44192e6546ff5e986842e0cacb78424456229b7da80627ed47d355ad43db3",
+                    "blacklist": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                    "regexlist": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                    "regex_whitelist": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                }
+            }))
+            .test();
+    }
+}
@@ -10,32 +10,64 @@
 
 use crate::{
     env::{Env, PiholeFile},
-    routes::dns::common::{is_valid_domain, is_valid_regex},
+    routes::dns::{
+        common::{is_valid_domain, is_valid_regex},
+        entry_details::{read_details, write_details, EntryDetails, ListEntry}
+    },
     util::{Error, ErrorKind}
 };
 use failure::ResultExt;
-use std::io::{prelude::*, BufWriter};
+use std::{
+    collections::HashSet,
+    io::{prelude::*, BufWriter}
+};
 
 pub enum List {
     White,
     Black,
-    Regex
+    Regex,
+    RegexWhite
 }
 
 impl List {
+    /// All of the lists, in the order they are shown
+    pub const ALL: [List; 4] = [List::White, List::Black, List::Regex, List::RegexWhite];
+
+    /// Get the list from the name used in its endpoints
+    pub fn from_name(name: &str) -> Option<List> {
+        match name {
+            "whitelist" => Some(List::White),
+            "blacklist" => Some(List::Black),
+            "regexlist" => Some(List::Regex),
+            "regex_whitelist" => Some(List::RegexWhite),
+            _ => None
+        }
+    }
+
+    /// Get the name used in the list's endpoints
+    pub fn name(&self) -> &'static str {
+        match *self {
+            List::White => "whitelist",
+            List::Black => "blacklist",
+            List::Regex => "regexlist",
+            List::RegexWhite => "regex_whitelist"
+        }
+    }
+
     /// Get the associated `PiholeFile`
     fn file(&self) -> PiholeFile {
         match *self {
             List::White => PiholeFile::Whitelist,
             List::Black => PiholeFile::Blacklist,
-            List::Regex => PiholeFile::Regexlist
+            List::Regex => PiholeFile::Regexlist,
+            List::RegexWhite => PiholeFile::RegexWhitelist
         }
     }
 
     /// Check if the list accepts the domain as valid
     fn accepts(&self, domain: &str) -> bool {
         match *self {
-            List::Regex => is_valid_regex(domain),
+            List::Regex | List::RegexWhite => is_valid_regex(domain),
             _ => is_valid_domain(domain)
         }
     }
@@ -60,6 +92,133 @@ impl List {
             .collect())
     }
 
+    /// Read in the entries of the list with their comments, flags, and dates.
+    /// Disabled entries come after the enabled ones.
+    pub fn get_entries(&self, env: &Env) -> Result<Vec<ListEntry>, Error> {
+        let details: Vec<EntryDetails> = read_details(env)?
+            .into_iter()
+            .filter(|details| details.list == self.name())
+            .collect();
+        let domains = self.get(env)?;
+
+        let mut entries: Vec<ListEntry> = domains
+            .iter()
+            .map(|domain| {
+                let domain_details = details.iter().find(|details| &details.domain == domain);
+                ListEntry::new(domain.to_owned(), true, domain_details)
+            })
+            .collect();
+
+        entries.extend(
+            details
+                .iter()
+                .filter(|details| !details.enabled && !domains.contains(&details.domain))
+                .map(|details| ListEntry::new(details.domain.clone(), false, Some(details)))
+        );
+
+        Ok(entries)
+    }
+
+    /// Remember when the domain was added to the list, and its comment
+    pub fn record_added(
+        &self,
+        domain: &str,
+        comment: Option<String>,
+        env: &Env,
+        now: u64
+    ) -> Result<(), Error> {
+        let mut details = read_details(env)?;
+        details.retain(|details| !(details.list == self.name() && details.domain == domain));
+        details.push(EntryDetails {
+            list: self.name().to_owned(),
+            domain: domain.to_owned(),
+            comment: comment.filter(|comment| !comment.is_empty()),
+            enabled: true,
+            date_added: Some(now),
+            date_modified: Some(now)
+        });
+
+        write_details(env, &details)
+    }
+
+    /// Change the comment or the enabled flag of an entry. An empty comment
+    /// removes the comment. A disabled entry is taken out of the list file,
+    /// and put back when it is enabled. Returns if the entry was enabled or
+    /// disabled.
+    pub fn update_entry(
+        &self,
+        domain: &str,
+        comment: Option<String>,
+        enabled: Option<bool>,
+        env: &Env,
+        now: u64
+    ) -> Result<bool, Error> {
+        if !self.accepts(domain) {
+            return Err(Error::from(ErrorKind::InvalidDomain));
+        }
+
+        let mut details = read_details(env)?;
+        let domains = self.get(env)?;
+        let was_enabled = domains.iter().any(|item| item == domain);
+        let position = details
+            .iter()
+            .position(|details| details.list == self.name() && details.domain == domain);
+
+        // The entry is either in the list file or disabled
+        let index = match position {
+            Some(index) if was_enabled || !details[index].enabled => index,
+            None if was_enabled => {
+                details.push(EntryDetails {
+                    list: self.name().to_owned(),
+                    domain: domain.to_owned(),
+                    comment: None,
+                    enabled: true,
+                    date_added: None,
+                    date_modified: None
+                });
+                details.len() - 1
+            }
+            _ => return Err(Error::from(ErrorKind::NotFound))
+        };
+
+        let enabled = enabled.unwrap_or(was_enabled);
+        let entry = &mut details[index];
+
+        if let Some(comment) = comment {
+            entry.comment = Some(comment).filter(|comment| !comment.is_empty());
+        }
+
+        entry.enabled = enabled;
+        entry.date_modified = Some(now);
+
+        if enabled && !was_enabled {
+            self.add(domain, env)?;
+        } else if !enabled && was_enabled {
+            self.write_without(domains, &[domain.to_owned()], env)?;
+        }
+
+        write_details(env, &details)?;
+
+        Ok(enabled != was_enabled)
+    }
+
+    /// Forget the details of the domains. Returns true if any of the domains
+    /// had details.
+    fn forget_details(&self, domains: &[String], env: &Env) -> Result<bool, Error> {
+        let mut details = read_details(env)?;
+        let count = details.len();
+
+        details
+            .retain(|details| !(details.list == self.name() && domains.contains(&details.domain)));
+
+        if details.len() == count {
+            return Ok(false);
+        }
+
+        write_details(env, &details)?;
+        Ok(true)
+    }
+
     /// Add a domain to the list
     pub fn add(&self, domain: &str, env: &Env) -> Result<(), Error> {
         // Check if it's a valid domain before doing anything
@@ -84,15 +243,15 @@ impl List {
     }
 
     /// Try to remove a domain from the list, but it is not an error if the
-    /// domain does not exist
-    pub fn try_remove(&self, domain: &str, env: &Env) -> Result<(), Error> {
+    /// domain does not exist. Returns true if the domain was removed.
+    pub fn try_remove(&self, domain: &str, env: &Env) -> Result<bool, Error> {
         match self.remove(domain, env) {
             // Pass through successful results
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(true),
             Err(e) => {
                 // Ignore NotFound errors
                 if e.kind() == ErrorKind::NotFound {
-                    Ok(())
+                    Ok(false)
                 } else {
                     Err(e)
                 }
@@ -107,10 +266,15 @@ impl List {
             return Err(Error::from(ErrorKind::InvalidDomain));
         }
 
-        // Check if the domain is not in the list
+        // Check if the domain is not in the list. A disabled entry is only
+        // in the details, so it is removed by forgetting them.
         let domains = self.get(env)?;
         if !domains.contains(&domain.to_owned()) {
-            return Err(Error::from(ErrorKind::NotFound));
+            return if self.forget_details(&[domain.to_owned()], env)? {
+                Ok(())
+            } else {
+                Err(Error::from(ErrorKind::NotFound))
+            };
         }
 
         // Open the list file (and create it if it doesn't exist). This will truncate
@@ -125,6 +289,197 @@ impl List {
             ))?;
         }
 
+        self.forget_details(&[domain.to_owned()], env)?;
+
+        Ok(())
+    }
+
+    /// Add the domains to the list. All of the domains are checked before the
+    /// list is changed, so either all of them are added or none are.
+    pub fn add_all(&self, domains: &[String], env: &Env) -> Result<(), Error> {
+        if !domains.iter().all(|domain| self.accepts(domain)) {
+            return Err(Error::from(ErrorKind::InvalidDomain));
+        }
+
+        let existing = self.get(env)?;
+        if domains.iter().any(|domain| existing.contains(domain)) {
+            return Err(Error::from(ErrorKind::AlreadyExists));
+        }
+
+        // Append all of the domains with a single write
+        let mut writer = BufWriter::new(env.write_file(self.file(), true)?);
+
+        for domain in domains {
+            writeln!(writer, "{}", domain).context(ErrorKind::FileWrite(
+                env.file_location(self.file()).to_owned()
+            ))?;
+        }
+
+        writer.flush().context(ErrorKind::FileWrite(
+            env.file_location(self.file()).to_owned()
+        ))?;
+
         Ok(())
     }
+
+    /// Remove the domains from the list. All of the domains are checked
+    /// before the list is changed, so either all of them are removed or none
+    /// are.
+    pub fn remove_all(&self, domains: &[String], env: &Env) -> Result<(), Error> {
+        if !domains.iter().all(|domain| self.accepts(domain)) {
+            return Err(Error::from(ErrorKind::InvalidDomain));
+        }
+
+        let existing = self.get(env)?;
+        if !domains.iter().all(|domain| existing.contains(domain)) {
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+
+        self.write_without(existing, domains, env)?;
+        self.forget_details(domains, env)?;
+
+        Ok(())
+    }
+
+    /// Remove the domains which are in the list, and return them. Domains
+    /// which are not in the list are ignored.
+    pub fn try_remove_all(&self, domains: &[String], env: &Env) -> Result<Vec<String>, Error> {
+        let existing = self.get(env)?;
+        let removed: Vec<String> = domains
+            .iter()
+            .filter(|domain| existing.contains(domain))
+            .cloned()
+            .collect();
+
+        if !removed.is_empty() {
+            self.write_without(existing, &removed, env)?;
+            self.forget_details(&removed, env)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Replace the list with the existing domains, except the removed domains
+    fn write_without(
+        &self,
+        existing: Vec<String>,
+        removed: &[String],
+        env: &Env
+    ) -> Result<(), Error> {
+        let removed: HashSet<&String> = removed.iter().collect();
+        let mut writer = BufWriter::new(env.write_file(self.file(), false)?);
+
+        for domain in existing
+            .into_iter()
+            .filter(|domain| !removed.contains(domain))
+        {
+            writeln!(writer, "{}", domain).context(ErrorKind::FileWrite(
+                env.file_location(self.file()).to_owned()
+            ))?;
+        }
+
+        writer.flush().context(ErrorKind::FileWrite(
+            env.file_location(self.file()).to_owned()
+        ))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// Run the test with an environment built from the builder, then check
+    /// the files have the expected contents
+    fn with_env<F: FnOnce(&Env)>(env_builder: TestEnvBuilder, test: F) {
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        test(&env);
+
+        let mut buffer = String::new();
+        for test_file in &mut test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// A disabled entry is taken out of the list and keeps its details
+    #[test]
+    fn disable_entry() {
+        with_env(
+            TestEnvBuilder::new()
+                .file_expect(
+                    PiholeFile::Whitelist,
+                    "example.com\nexample.net\n",
+                    "example.net\n"
+                )
+                .file_expect(
+                    PiholeFile::ListDetails,
+                    "",
+                    "[{\"list\":\"whitelist\",\"domain\":\"example.com\",\
+                     \"comment\":\"Needed for updates\",\"enabled\":false,\
+                     \"date_added\":null,\"date_modified\":1000}]\n"
+                ),
+            |env| {
+                assert!(List::White
+                    .update_entry(
+                        "example.com",
+                        Some("Needed for updates".to_owned()),
+                        Some(false),
+                        env,
+                        1000
+                    )
+                    .unwrap());
+            }
+        );
+    }
+
+    /// An enabled entry is put back in the list, and its comment is kept
+    #[test]
+    fn enable_entry() {
+        with_env(
+            TestEnvBuilder::new()
+                .file_expect(
+                    PiholeFile::Whitelist,
+                    "example.net\n",
+                    "example.net\nexample.com\n"
+                )
+                .file_expect(
+                    PiholeFile::ListDetails,
+                    "[{\"list\":\"whitelist\",\"domain\":\"example.com\",\
+                     \"comment\":\"Needed for updates\",\"enabled\":false,\
+                     \"date_added\":500,\"date_modified\":1000}]\n",
+                    "[{\"list\":\"whitelist\",\"domain\":\"example.com\",\
+                     \"comment\":\"Needed for updates\",\"enabled\":true,\
+                     \"date_added\":500,\"date_modified\":2000}]\n"
+                ),
+            |env| {
+                assert!(List::White
+                    .update_entry("example.com", None, Some(true), env, 2000)
+                    .unwrap());
+            }
+        );
+    }
+
+    /// Removing a disabled entry forgets its details
+    #[test]
+    fn remove_disabled_entry() {
+        with_env(
+            TestEnvBuilder::new()
+                .file(PiholeFile::Whitelist, "example.net\n")
+                .file_expect(
+                    PiholeFile::ListDetails,
+                    "[{\"list\":\"whitelist\",\"domain\":\"example.com\",\
+                     \"comment\":null,\"enabled\":false,\
+                     \"date_added\":null,\"date_modified\":1000}]\n",
+                    "[]\n"
+                ),
+            |env| List::White.remove("example.com", env).unwrap()
+        );
+    }
 }
@@ -9,10 +9,45 @@
 // Please see LICENSE file for your rights under this license.
 
 mod add_list;
+mod adlists;
+mod batch;
+mod cache;
+mod changes;
+mod check;
 mod common;
 mod delete_list;
+mod entry_details;
 mod get_list;
+mod gravity_build;
+mod gravity_contains;
+mod gravity_reload;
+mod gravity_update;
+mod hash;
 mod list;
+mod rules;
 mod status;
+mod threat_feed;
+mod update_list;
+mod whitelist_impact;
 
-pub use self::{add_list::*, delete_list::*, get_list::*, status::*};
+pub use self::{
+    add_list::*,
+    adlists::*,
+    batch::*,
+    cache::*,
+    changes::*,
+    check::*,
+    common::{is_valid_domain, is_valid_regex, reload_dns},
+    delete_list::*,
+    entry_details::EntryDetails,
+    get_list::*,
+    gravity_build::*,
+    gravity_contains::*,
+    gravity_reload::*,
+    gravity_update::*,
+    hash::*,
+    list::List,
+    status::*,
+    threat_feed::*,
+    update_list::*
+};
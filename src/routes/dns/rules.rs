@@ -0,0 +1,196 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Domain Rule Evaluation
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::dns::{common::is_valid_regex, list::List},
+    services::{GravityIndex, IndexedGravity},
+    util::Error
+};
+use regex::RegexSet;
+use std::{collections::HashSet, sync::Arc};
+
+/// The regexes of a list, compiled into one set so that a domain is only
+/// scanned once. Invalid regexes are skipped, like FTL does.
+pub struct RegexList {
+    regexes: Vec<String>,
+    set: Option<RegexSet>
+}
+
+impl RegexList {
+    /// Read and compile the regex list
+    pub fn load(list: List, env: &Env) -> Result<RegexList, Error> {
+        let regexes: Vec<String> = list
+            .get(env)?
+            .into_iter()
+            .filter(|regex| is_valid_regex(regex))
+            .collect();
+        let set = RegexSet::new(&regexes).ok();
+
+        Ok(RegexList { regexes, set })
+    }
+
+    /// Get the regexes which match the domain
+    pub fn matches(&self, domain: &str) -> Vec<String> {
+        match self.set {
+            Some(ref set) => set
+                .matches(domain)
+                .into_iter()
+                .map(|index| self.regexes[index].clone())
+                .collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+/// The list which decided a verdict
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum RuleList {
+    Whitelist,
+    RegexWhitelist,
+    Blacklist,
+    Gravity,
+    Regexlist
+}
+
+/// Whether a domain would be blocked, and by which rule. If no rule matches,
+/// the domain is allowed and there is no list or rule.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Verdict {
+    pub blocked: bool,
+    pub list: Option<RuleList>,
+    /// The list entry or regex which matched
+    pub rule: Option<String>
+}
+
+/// The lists which decide whether a domain is blocked, loaded once so that
+/// many domains can be evaluated against them
+pub struct DomainRules {
+    whitelist: HashSet<String>,
+    blacklist: HashSet<String>,
+    regex_whitelist: RegexList,
+    regexlist: RegexList,
+    gravity: Arc<IndexedGravity>
+}
+
+impl DomainRules {
+    /// Load the lists and the current Gravity index
+    pub fn load(env: &Env, gravity: &GravityIndex) -> Result<DomainRules, Error> {
+        Ok(DomainRules {
+            whitelist: List::White.get(env)?.into_iter().collect(),
+            blacklist: List::Black.get(env)?.into_iter().collect(),
+            regex_whitelist: RegexList::load(List::RegexWhite, env)?,
+            regexlist: RegexList::load(List::Regex, env)?,
+            gravity: gravity.current(env)?
+        })
+    }
+
+    /// Predict the verdict for the lowercase domain. The allowing lists are
+    /// checked first, so they override the blocking lists. Gravity and the
+    /// blacklist only block the exact domain, like the DNS server does.
+    pub fn evaluate(&self, domain: &str) -> Verdict {
+        if self.whitelist.contains(domain) {
+            return Verdict::matched(false, RuleList::Whitelist, domain);
+        }
+
+        if let Some(regex) = self.regex_whitelist.matches(domain).into_iter().next() {
+            return Verdict::matched(false, RuleList::RegexWhitelist, &regex);
+        }
+
+        if self.blacklist.contains(domain) {
+            return Verdict::matched(true, RuleList::Blacklist, domain);
+        }
+
+        if self.gravity.contains(domain) {
+            return Verdict::matched(true, RuleList::Gravity, domain);
+        }
+
+        if let Some(regex) = self.regexlist.matches(domain).into_iter().next() {
+            return Verdict::matched(true, RuleList::Regexlist, &regex);
+        }
+
+        Verdict {
+            blocked: false,
+            list: None,
+            rule: None
+        }
+    }
+}
+
+impl Verdict {
+    /// Create a verdict decided by a rule of the list
+    fn matched(blocked: bool, list: RuleList, rule: &str) -> Verdict {
+        Verdict {
+            blocked,
+            list: Some(list),
+            rule: Some(rule.to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DomainRules, RuleList, Verdict};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        services::GravityIndex,
+        testing::TestEnvBuilder
+    };
+
+    /// Allowing lists override blocking lists, and Gravity only blocks exact
+    /// domains
+    #[test]
+    fn evaluate() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::Whitelist, "ads.example.com\n")
+                .file(PiholeFile::Blacklist, "tracker.net\n")
+                .file(PiholeFile::RegexWhitelist, "^cdn\\.\n")
+                .file(PiholeFile::Regexlist, "^ad[sx]\\.\n")
+                .file(PiholeFile::Gravity, "example.com\ncdn.example.com\n")
+                .build()
+        );
+        let rules = DomainRules::load(&env, &GravityIndex::default()).unwrap();
+        let verdict = |domain| rules.evaluate(domain);
+
+        assert_eq!(
+            verdict("ads.example.com"),
+            Verdict::matched(false, RuleList::Whitelist, "ads.example.com")
+        );
+        assert_eq!(
+            verdict("cdn.example.com"),
+            Verdict::matched(false, RuleList::RegexWhitelist, "^cdn\\.")
+        );
+        assert_eq!(
+            verdict("tracker.net"),
+            Verdict::matched(true, RuleList::Blacklist, "tracker.net")
+        );
+        assert_eq!(
+            verdict("example.com"),
+            Verdict::matched(true, RuleList::Gravity, "example.com")
+        );
+        assert_eq!(
+            verdict("adx.pi-hole.net"),
+            Verdict::matched(true, RuleList::Regexlist, "^ad[sx]\\.")
+        );
+        assert_eq!(
+            verdict("www.example.com"),
+            Verdict {
+                blocked: false,
+                list: None,
+                rule: None
+            }
+        );
+    }
+}
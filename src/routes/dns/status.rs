@@ -9,14 +9,15 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    api_state::ApiState,
     env::{Env, PiholeFile},
     routes::dns::common::reload_dns,
     settings::{ConfigEntry, SetupVarsEntry},
-    util::{reply_data, reply_error, reply_success, Error, ErrorKind, Reply}
+    util::{current_time, reply_data, reply_error, reply_success, Error, ErrorKind, Reply}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use task_scheduler::Scheduler;
 
 /// Get the DNS blocking status
@@ -35,7 +36,7 @@ pub fn status(env: State<Env>) -> Reply {
 #[post("/dns/status", data = "<data>")]
 pub fn change_status(
     env: State<Env>,
-    scheduler: State<Scheduler>,
+    scheduler: State<Arc<Scheduler>>,
     data: Json<ChangeStatus>
 ) -> Reply {
     match (data.action.as_str(), data.time) {
@@ -66,6 +67,11 @@ fn enable(env: &Env) -> Result<(), Error> {
     // Update the blocking status
     SetupVarsEntry::BlockingEnabled.write("true", env)?;
 
+    // Forget about any timed re-enable, since blocking is enabled now
+    if ApiState::read(env)?.blocking_reenable_at.is_some() {
+        ApiState::update(env, |state| state.blocking_reenable_at = None)?;
+    }
+
     reload_dns(env)
 }
 
@@ -109,32 +115,63 @@ fn disable(env: &Env, time: Option<usize>, scheduler: Option<&Scheduler>) -> Res
 
         // Check if we should re-enable after a specified timeout
         if let Some(time) = time {
-            // Make a copy of the Env to move to the scheduler thread
-            let env_copy = env.clone();
-
-            // Re-enable blocking after the timeout
-            scheduler
-                .unwrap()
-                .after_duration(Duration::from_secs(time as u64), move || {
-                    // Handle the result of enabling, so that if it's an error
-                    // the thread does not panic
-                    if let Err(e) = enable(&env_copy) {
-                        if e.kind() == ErrorKind::BadRequest {
-                            // If it was a bad request, blocking was probably
-                            // already re-enabled. This is a fairly common
-                            // scenario, so no error should be logged.
-                            return;
-                        }
-
-                        e.print_stacktrace();
-                    }
-                });
+            // Save when blocking is re-enabled, so it is still re-enabled if
+            // the API restarts before then
+            ApiState::update(env, |state| {
+                state.blocking_reenable_at = Some(current_time() + time as u64)
+            })?;
+
+            schedule_enable(env, Duration::from_secs(time as u64), scheduler.unwrap());
         }
     }
 
     Ok(())
 }
 
+/// Re-enable blocking after the timeout, using a copy of the Env in the
+/// scheduler thread
+fn schedule_enable(env: &Env, timeout: Duration, scheduler: &Scheduler) {
+    let env_copy = env.clone();
+
+    scheduler.after_duration(timeout, move || {
+        // Handle the result of enabling, so that if it's an error the thread
+        // does not panic
+        if let Err(e) = enable(&env_copy) {
+            if e.kind() == ErrorKind::BadRequest {
+                // If it was a bad request, blocking was probably already
+                // re-enabled. This is a fairly common scenario, so no error
+                // should be logged.
+                return;
+            }
+
+            e.print_stacktrace();
+        }
+    });
+}
+
+/// Resume a timed disable which was saved before the API restarted. If the
+/// timeout has already passed, blocking is re-enabled now.
+pub fn resume_blocking_pause(env: &Env, scheduler: &Scheduler) -> Result<(), Error> {
+    let reenable_at = match ApiState::read(env)?.blocking_reenable_at {
+        Some(reenable_at) => reenable_at,
+        None => return Ok(())
+    };
+
+    // Blocking was enabled some other way while the API was not running
+    if SetupVarsEntry::BlockingEnabled.is_true(env)? {
+        return ApiState::update(env, |state| state.blocking_reenable_at = None);
+    }
+
+    let now = current_time();
+
+    if reenable_at <= now {
+        enable(env)
+    } else {
+        schedule_enable(env, Duration::from_secs(reenable_at - now), scheduler);
+        Ok(())
+    }
+}
+
 /// Represents the API input for changing the DNS blocking status
 #[derive(Deserialize)]
 pub struct ChangeStatus {
@@ -148,13 +185,14 @@ pub struct ChangeStatus {
 
 #[cfg(test)]
 mod test {
-    use super::{disable, enable};
+    use super::{disable, enable, resume_blocking_pause};
     use crate::{
         env::{Config, Env, PiholeFile},
         testing::{TestBuilder, TestEnvBuilder},
         util::ErrorKind
     };
     use rocket::http::Method;
+    use task_scheduler::Scheduler;
 
     /// Return enabled status if blocking is enabled
     #[test]
@@ -257,4 +295,50 @@ mod test {
             Err(ErrorKind::BadRequest)
         );
     }
+
+    /// A saved timed disable which has already passed re-enables blocking
+    #[test]
+    fn resume_passed_pause() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::SetupVars,
+                "BLOCKING_ENABLED=false\n",
+                "BLOCKING_ENABLED=true\n"
+            )
+            .file_expect(
+                PiholeFile::ApiState,
+                "{\"blocking_reenable_at\":1}\n",
+                "{\"blocking_reenable_at\":null,\"gravity_retry_at\":null}\n"
+            );
+        let test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        resume_blocking_pause(&env, &Scheduler::new()).unwrap();
+
+        let mut buffer = String::new();
+        for mut test_file in test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// A saved timed disable is forgotten if blocking is already enabled
+    #[test]
+    fn resume_already_enabled() {
+        let env_builder = TestEnvBuilder::new()
+            .file(PiholeFile::SetupVars, "BLOCKING_ENABLED=true\n")
+            .file_expect(
+                PiholeFile::ApiState,
+                "{\"blocking_reenable_at\":1}\n",
+                "{\"blocking_reenable_at\":null,\"gravity_retry_at\":null}\n"
+            );
+        let test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        resume_blocking_pause(&env, &Scheduler::new()).unwrap();
+
+        let mut buffer = String::new();
+        for mut test_file in test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
 }
@@ -0,0 +1,115 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Threat Feed Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    services::ThreatCategories,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the threat feed URL and the result of its last download. An empty URL
+/// means the threat feed is disabled.
+#[get("/dns/threat_feed")]
+pub fn get_threat_feed(
+    _auth: User,
+    env: State<Env>,
+    threat_categories: State<ThreatCategories>
+) -> Reply {
+    let status = threat_categories.status();
+
+    reply_data(json!({
+        "url": SetupVarsEntry::ThreatFeedUrl.read(&env)?,
+        "domains": status.domains,
+        "last_fetch": status.last_fetch,
+        "last_error": status.last_error
+    }))
+}
+
+/// Set the threat feed URL. The feed is downloaded on the next periodic
+/// update.
+#[put("/dns/threat_feed", data = "<input>")]
+pub fn put_threat_feed(_auth: User, env: State<Env>, input: Json<ThreatFeedInput>) -> Reply {
+    if !SetupVarsEntry::ThreatFeedUrl.is_valid(&input.url) {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    SetupVarsEntry::ThreatFeedUrl.write(&input.url, &env)?;
+
+    reply_success()
+}
+
+/// The input when setting the threat feed URL
+#[derive(Deserialize)]
+pub struct ThreatFeedInput {
+    url: String
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The feed URL is returned with the download status
+    #[test]
+    fn get_feed() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/threat_feed")
+            .file(
+                PiholeFile::SetupVars,
+                "THREAT_FEED_URL=https://example.com/threats.txt\n"
+            )
+            .expect_json(json!({
+                "url": "https://example.com/threats.txt",
+                "domains": 0,
+                "last_fetch": null,
+                "last_error": null
+            }))
+            .test();
+    }
+
+    /// The feed URL is written to setupVars.conf
+    #[test]
+    fn put_feed() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/threat_feed")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "THREAT_FEED_URL=https://example.com/threats.txt\n"
+            )
+            .body(json!({ "url": "https://example.com/threats.txt" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An invalid feed URL is rejected
+    #[test]
+    fn put_invalid_feed() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/threat_feed")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({ "url": "not a url" }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,144 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Endpoint For Updating List Entries
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlConnectionType,
+    routes::{
+        auth::User,
+        dns::{
+            changes::{ChangeAction, ListChanges},
+            gravity_reload::GravityReloader,
+            list::List
+        }
+    },
+    util::{current_time, reply_data, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// The changes to make to a list entry. Fields which are not given are kept.
+#[derive(Deserialize)]
+pub struct EntryUpdate {
+    /// The new comment. An empty comment removes the comment.
+    comment: Option<String>,
+    enabled: Option<bool>
+}
+
+/// Change the comment of a list entry, or enable or disable it. Disabled
+/// entries are kept with their details, but are not used for blocking.
+#[put("/dns/<list>/<domain>", data = "<update>")]
+#[allow(clippy::too_many_arguments)]
+pub fn update_list_entry(
+    _auth: User,
+    env: State<Env>,
+    changes: State<ListChanges>,
+    reloader: State<GravityReloader>,
+    ftl: State<FtlConnectionType>,
+    list: String,
+    domain: String,
+    update: Json<EntryUpdate>
+) -> Reply {
+    let list = List::from_name(&list).ok_or(ErrorKind::NotFound)?;
+    let EntryUpdate { comment, enabled } = update.into_inner();
+
+    let toggled = list.update_entry(&domain, comment, enabled, &env, current_time())?;
+
+    if !toggled {
+        return reply_data(json!({
+            "status": "success",
+            "reload": null
+        }));
+    }
+
+    let action = if enabled == Some(true) {
+        ChangeAction::Add
+    } else {
+        ChangeAction::Remove
+    };
+    changes.record(&list, action, &domain);
+
+    // The lists which are not part of Gravity are used by FTL directly
+    match list {
+        List::White | List::Black => reply_data(json!({
+            "status": "success",
+            "reload": reloader.request(list, &env)
+        })),
+        List::Regex | List::RegexWhite => {
+            ftl.connect("recompile-regex")?.expect_eom()?;
+            reply_data(json!({
+                "status": "success",
+                "reload": null
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        testing::{write_eom, TestBuilder}
+    };
+    use rocket::http::{Method, Status};
+
+    /// Disabling a regex takes it out of the list and recompiles the regexes
+    #[test]
+    fn disable_regex() {
+        let mut data = Vec::new();
+        write_eom(&mut data);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/regexlist/%5Eads%5C.")
+            .method(Method::Put)
+            .ftl("recompile-regex", data)
+            .file_expect(PiholeFile::Regexlist, "^ads\\.\n^track\\.\n", "^track\\.\n")
+            .body(json!({ "enabled": false }))
+            .expect_json(json!({ "status": "success", "reload": null }))
+            .test();
+    }
+
+    /// Entries which are not in the list can not be updated
+    #[test]
+    fn not_found() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/whitelist/example.com")
+            .method(Method::Put)
+            .file(PiholeFile::Whitelist, "example.net\n")
+            .body(json!({ "comment": "Needed for updates" }))
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Unknown lists are not found
+    #[test]
+    fn unknown_list() {
+        TestBuilder::new()
+            .endpoint("/admin/api/dns/adlist/example.com")
+            .method(Method::Put)
+            .body(json!({ "enabled": true }))
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
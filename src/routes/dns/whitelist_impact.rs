@@ -0,0 +1,155 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Whitelist Impact Advisory
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{env::Env, ftl::FtlMemory, routes::stats::PrivacyPolicy, util::Error};
+use std::collections::HashMap;
+
+/// The recent blocked queries which a new whitelist entry will allow
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct WhitelistImpact {
+    pub blocked_queries: usize,
+    /// The clients of the queries, with the most queries first
+    pub clients: Vec<ImpactedClient>
+}
+
+/// A client whose blocked queries will be allowed
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ImpactedClient {
+    pub name: String,
+    pub ip: String,
+    pub blocked_queries: usize
+}
+
+/// Find the blocked queries in FTL's memory (the last 24 hours) with a domain
+/// matching the whitelist entry. If domains (and so clients) are private,
+/// there is no impact to show.
+pub fn whitelist_impact<F: Fn(&str) -> bool>(
+    env: &Env,
+    ftl_memory: &FtlMemory,
+    matches: F
+) -> Result<Option<WhitelistImpact>, Error> {
+    if !PrivacyPolicy::read(env)?.shows_domains() {
+        return Ok(None);
+    }
+
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    // Count the blocked queries of each client, by client ID
+    let mut client_counts: HashMap<usize, usize> = HashMap::new();
+
+    for query in queries
+        .iter()
+        .take(counters.total_queries as usize)
+        .filter(|query| !query.is_private && query.is_blocked())
+        .filter(|query| matches(domains[query.domain_id as usize].get_domain(&strings)))
+    {
+        *client_counts.entry(query.client_id as usize).or_insert(0) += 1;
+    }
+
+    let blocked_queries = client_counts.values().sum();
+
+    let mut impacted_clients: Vec<ImpactedClient> = client_counts
+        .into_iter()
+        .map(|(client_id, count)| {
+            let client = &clients[client_id];
+
+            ImpactedClient {
+                name: client.get_name(&strings).unwrap_or_default().to_owned(),
+                ip: client.get_ip(&strings).to_owned(),
+                blocked_queries: count
+            }
+        })
+        .collect();
+
+    impacted_clients.sort_by(|a, b| {
+        b.blocked_queries
+            .cmp(&a.blocked_queries)
+            .then_with(|| a.ip.cmp(&b.ip))
+    });
+
+    Ok(Some(WhitelistImpact {
+        blocked_queries,
+        clients: impacted_clients
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{whitelist_impact, ImpactedClient, WhitelistImpact};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        routes::stats::testing::test_memory,
+        testing::TestEnvBuilder
+    };
+
+    /// Create an environment with the privacy level
+    fn env_with_privacy(level: &str) -> Env {
+        Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::FtlConfig, &format!("PRIVACYLEVEL={}", level))
+                .build()
+        )
+    }
+
+    /// The blocked queries of matching domains are counted by client
+    #[test]
+    fn blocked_queries() {
+        let impact = whitelist_impact(&env_with_privacy("0"), &test_memory(), |domain| {
+            domain == "domain2.com" || domain == "domain3.com"
+        })
+        .unwrap();
+
+        assert_eq!(
+            impact,
+            Some(WhitelistImpact {
+                blocked_queries: 2,
+                clients: vec![ImpactedClient {
+                    name: "".to_owned(),
+                    ip: "192.168.1.11".to_owned(),
+                    blocked_queries: 2
+                }]
+            })
+        );
+    }
+
+    /// Allowed queries of the domain are not counted
+    #[test]
+    fn allowed_queries() {
+        let impact = whitelist_impact(&env_with_privacy("0"), &test_memory(), |domain| {
+            domain == "domain1.com"
+        })
+        .unwrap();
+
+        assert_eq!(
+            impact,
+            Some(WhitelistImpact {
+                blocked_queries: 0,
+                clients: Vec::new()
+            })
+        );
+    }
+
+    /// Nothing is shown when domains are private
+    #[test]
+    fn privacy() {
+        assert_eq!(
+            whitelist_impact(&env_with_privacy("1"), &test_memory(), |_| true).unwrap(),
+            None
+        );
+    }
+}
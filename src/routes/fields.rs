@@ -0,0 +1,117 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Reply Field Selection
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use rocket::{http::RawStr, request::FromFormValue};
+use rocket_contrib::json::JsonValue;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// The fields to keep in each item of a reply, from the comma separated
+/// `fields` parameter. Selected fields which an item does not have are
+/// ignored.
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct FieldSelection(Vec<String>);
+
+impl<'v> FromFormValue<'v> for FieldSelection {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Self, Self::Error> {
+        let decoded = form_value.url_decode().map_err(|_| form_value)?;
+        let fields: Vec<String> = decoded
+            .split(',')
+            .map(|field| field.trim().to_owned())
+            .collect();
+
+        if fields.iter().any(String::is_empty) {
+            return Err(form_value);
+        }
+
+        Ok(FieldSelection(fields))
+    }
+}
+
+impl FieldSelection {
+    /// Remove the fields which were not selected from each item. Items which
+    /// are not objects are left as they are.
+    pub fn select(&self, items: &mut [JsonValue]) {
+        for item in items {
+            if let Some(object) = item.as_object_mut() {
+                let selected: Map<String, Value> = self
+                    .0
+                    .iter()
+                    .filter_map(|field| object.remove(field).map(|value| (field.clone(), value)))
+                    .collect();
+
+                *object = selected;
+            }
+        }
+    }
+}
+
+/// Serialize the items, keeping only the selected fields of each if fields
+/// were selected
+pub fn select_fields<T: Serialize>(
+    items: Vec<T>,
+    fields: Option<&FieldSelection>
+) -> Vec<JsonValue> {
+    let mut items: Vec<JsonValue> = items.into_iter().map(|item| json!(item)).collect();
+
+    if let Some(fields) = fields {
+        fields.select(&mut items);
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod test {
+    use super::FieldSelection;
+    use rocket::{http::RawStr, request::FromFormValue};
+
+    /// The fields are parsed from a comma separated list, which can be URL
+    /// encoded
+    #[test]
+    fn parse_fields() {
+        assert_eq!(
+            FieldSelection::from_form_value(RawStr::from_str("timestamp,%20domain")),
+            Ok(FieldSelection(vec![
+                "timestamp".to_owned(),
+                "domain".to_owned()
+            ]))
+        );
+        assert!(FieldSelection::from_form_value(RawStr::from_str("timestamp,,domain")).is_err());
+        assert!(FieldSelection::from_form_value(RawStr::from_str("")).is_err());
+    }
+
+    /// Only the selected fields are kept, and unknown fields are ignored
+    #[test]
+    fn select() {
+        let mut items = vec![
+            json!({ "timestamp": 1, "domain": "example.com", "client": "10.1.1.1" }),
+            json!({ "timestamp": 2, "client": "10.1.1.2" }),
+        ];
+
+        FieldSelection(vec![
+            "timestamp".to_owned(),
+            "domain".to_owned(),
+            "unknown".to_owned(),
+        ])
+        .select(&mut items);
+
+        assert_eq!(
+            items,
+            vec![
+                json!({ "timestamp": 1, "domain": "example.com" }),
+                json!({ "timestamp": 2 }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Group Client Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::repository::{find_group, read_groups, write_groups};
+use crate::{
+    env::Env,
+    routes::auth::User,
+    util::{reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::{net::IpAddr, str::FromStr};
+
+/// Add a client to a group. A client can be in many groups.
+#[post("/groups/<id>/clients", data = "<input>")]
+pub fn add_group_client(
+    _auth: User,
+    env: State<Env>,
+    id: u32,
+    input: Json<GroupClientInput>
+) -> Reply {
+    // Store the address in its normal form, so it can be found again
+    let client = IpAddr::from_str(&input.client)
+        .map_err(|_| Error::from(ErrorKind::BadRequest))?
+        .to_string();
+    let mut groups = read_groups(&env)?;
+    let group = find_group(&mut groups, id)?;
+
+    if group.clients.contains(&client) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    group.clients.push(client);
+    write_groups(&env, &groups)?;
+
+    reply_success()
+}
+
+/// Remove a client from a group
+#[delete("/groups/<id>/clients/<client>")]
+pub fn delete_group_client(_auth: User, env: State<Env>, id: u32, client: String) -> Reply {
+    let mut groups = read_groups(&env)?;
+    let group = find_group(&mut groups, id)?;
+    let count = group.clients.len();
+
+    group.clients.retain(|saved| *saved != client);
+
+    if group.clients.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    write_groups(&env, &groups)?;
+
+    reply_success()
+}
+
+/// The input when adding a client to a group
+#[derive(Deserialize)]
+pub struct GroupClientInput {
+    /// The IP address of the client
+    client: String
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// A group with one client
+    const GROUPS: &str = "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                          \"clients\":[\"10.0.0.5\"],\"domains\":[]}]\n";
+
+    /// The client is added to the group. IPv6 addresses are normalized.
+    #[test]
+    fn add_client() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1/clients")
+            .method(Method::Post)
+            .file_expect(
+                PiholeFile::Groups,
+                GROUPS,
+                "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                 \"clients\":[\"10.0.0.5\",\"fd00::5\"],\"domains\":[]}]\n"
+            )
+            .body(json!({ "client": "FD00:0:0::5" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Clients must be IP addresses
+    #[test]
+    fn add_invalid_client() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1/clients")
+            .method(Method::Post)
+            .file_expect(PiholeFile::Groups, GROUPS, GROUPS)
+            .body(json!({ "client": "laptop" }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The client is removed from the group
+    #[test]
+    fn delete_client() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1/clients/10.0.0.5")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::Groups,
+                GROUPS,
+                "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                 \"clients\":[],\"domains\":[]}]\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
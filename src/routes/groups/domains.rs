@@ -0,0 +1,152 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Group Domain Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::repository::{find_group, read_groups, write_groups, GroupDomain};
+use crate::{
+    env::Env,
+    routes::{auth::User, dns::List},
+    util::{reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Assign a whitelist, blacklist, or regex entry to a group. The entry must
+/// already be in the list.
+#[post("/groups/<id>/domains", data = "<input>")]
+pub fn add_group_domain(
+    _auth: User,
+    env: State<Env>,
+    id: u32,
+    input: Json<GroupDomainInput>
+) -> Reply {
+    let input = input.into_inner();
+    let list = List::from_name(&input.list).ok_or(ErrorKind::BadRequest)?;
+
+    if !list.get(&env)?.contains(&input.domain) {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    let domain = GroupDomain {
+        list: list.name().to_owned(),
+        domain: input.domain
+    };
+    let mut groups = read_groups(&env)?;
+    let group = find_group(&mut groups, id)?;
+
+    if group.domains.contains(&domain) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    group.domains.push(domain);
+    write_groups(&env, &groups)?;
+
+    reply_success()
+}
+
+/// Remove a list entry from a group
+#[delete("/groups/<id>/domains/<list>/<domain>")]
+pub fn delete_group_domain(
+    _auth: User,
+    env: State<Env>,
+    id: u32,
+    list: String,
+    domain: String
+) -> Reply {
+    let mut groups = read_groups(&env)?;
+    let group = find_group(&mut groups, id)?;
+    let count = group.domains.len();
+
+    group
+        .domains
+        .retain(|saved| saved.list != list || saved.domain != domain);
+
+    if group.domains.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    write_groups(&env, &groups)?;
+
+    reply_success()
+}
+
+/// The input when assigning a list entry to a group
+#[derive(Deserialize)]
+pub struct GroupDomainInput {
+    /// The name of the list, as used in its endpoints
+    list: String,
+    domain: String
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// A group with one blacklist entry
+    const GROUPS: &str = "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                          \"clients\":[],\"domains\":[{\"list\":\"blacklist\",\
+                          \"domain\":\"games.example.com\"}]}]\n";
+
+    /// The list entry is assigned to the group
+    #[test]
+    fn add_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1/domains")
+            .method(Method::Post)
+            .file(PiholeFile::Regexlist, "^ads\\.\n")
+            .file_expect(
+                PiholeFile::Groups,
+                GROUPS,
+                "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                 \"clients\":[],\"domains\":[{\"list\":\"blacklist\",\
+                 \"domain\":\"games.example.com\"},{\"list\":\"regexlist\",\
+                 \"domain\":\"^ads\\\\.\"}]}]\n"
+            )
+            .body(json!({ "list": "regexlist", "domain": "^ads\\." }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Only entries which are in the list can be assigned
+    #[test]
+    fn add_missing_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1/domains")
+            .method(Method::Post)
+            .file(PiholeFile::Whitelist, "")
+            .file_expect(PiholeFile::Groups, GROUPS, GROUPS)
+            .body(json!({ "list": "whitelist", "domain": "example.com" }))
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The list entry is removed from the group
+    #[test]
+    fn delete_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1/domains/blacklist/games.example.com")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::Groups,
+                GROUPS,
+                "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                 \"clients\":[],\"domains\":[]}]\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
@@ -0,0 +1,274 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Group Management Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::repository::{find_group, next_group_id, read_groups, write_groups, Group};
+use crate::{
+    env::Env,
+    routes::auth::User,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// The maximum length of a group name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The maximum length of a group description
+const MAX_DESCRIPTION_LENGTH: usize = 256;
+
+/// Get all of the groups
+#[get("/groups")]
+pub fn get_groups(_auth: User, env: State<Env>) -> Reply {
+    reply_data(read_groups(&env)?)
+}
+
+/// Get a group by ID
+#[get("/groups/<id>")]
+pub fn get_group(_auth: User, env: State<Env>, id: u32) -> Reply {
+    let mut groups = read_groups(&env)?;
+
+    reply_data(find_group(&mut groups, id)?)
+}
+
+/// Create a group without any clients or domains. The new group is returned.
+#[post("/groups", data = "<input>")]
+pub fn add_group(_auth: User, env: State<Env>, input: Json<GroupInput>) -> Reply {
+    let input = input.into_inner();
+    let mut groups = read_groups(&env)?;
+
+    input.validate(&groups, None)?;
+
+    let group = Group {
+        id: next_group_id(&groups),
+        name: input.name,
+        description: input.description,
+        enabled: input.enabled,
+        clients: Vec::new(),
+        domains: Vec::new()
+    };
+
+    groups.push(group);
+    write_groups(&env, &groups)?;
+
+    reply_data(groups.last())
+}
+
+/// Update the name, description, and status of a group
+#[put("/groups/<id>", data = "<input>")]
+pub fn update_group(_auth: User, env: State<Env>, id: u32, input: Json<GroupInput>) -> Reply {
+    let input = input.into_inner();
+    let mut groups = read_groups(&env)?;
+
+    input.validate(&groups, Some(id))?;
+
+    let group = find_group(&mut groups, id)?;
+    group.name = input.name;
+    group.description = input.description;
+    group.enabled = input.enabled;
+
+    write_groups(&env, &groups)?;
+
+    reply_success()
+}
+
+/// Delete a group. Its clients and domains are not changed otherwise.
+#[delete("/groups/<id>")]
+pub fn delete_group(_auth: User, env: State<Env>, id: u32) -> Reply {
+    let mut groups = read_groups(&env)?;
+    let count = groups.len();
+
+    groups.retain(|group| group.id != id);
+
+    if groups.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    write_groups(&env, &groups)?;
+
+    reply_success()
+}
+
+/// The input when creating or updating a group
+#[derive(Deserialize)]
+pub struct GroupInput {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default = "default_enabled")]
+    enabled: bool
+}
+
+impl GroupInput {
+    /// Check that the name and description are valid, and that no other group
+    /// has the name. `id` is the group being updated, if any.
+    fn validate(&self, groups: &[Group], id: Option<u32>) -> Result<(), Error> {
+        let valid_name = !self.name.trim().is_empty()
+            && self.name.chars().count() <= MAX_NAME_LENGTH
+            && !self.name.chars().any(char::is_control);
+        let valid_description = self.description.chars().count() <= MAX_DESCRIPTION_LENGTH
+            && !self.description.chars().any(char::is_control);
+
+        if !valid_name || !valid_description {
+            return Err(Error::from(ErrorKind::InvalidGroup));
+        }
+
+        if groups
+            .iter()
+            .any(|group| group.name == self.name && Some(group.id) != id)
+        {
+            return Err(Error::from(ErrorKind::AlreadyExists));
+        }
+
+        Ok(())
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// A group with a client and a domain
+    const GROUPS: &str = "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                          \"clients\":[\"10.0.0.5\"],\"domains\":[{\"list\":\"blacklist\",\
+                          \"domain\":\"games.example.com\"}]}]\n";
+
+    /// All groups are returned
+    #[test]
+    fn get_groups() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups")
+            .file(PiholeFile::Groups, GROUPS)
+            .expect_json(json!([{
+                "id": 1,
+                "name": "Kids",
+                "description": "",
+                "enabled": true,
+                "clients": ["10.0.0.5"],
+                "domains": [{ "list": "blacklist", "domain": "games.example.com" }]
+            }]))
+            .test();
+    }
+
+    /// An unknown group is not found
+    #[test]
+    fn get_missing_group() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/2")
+            .file(PiholeFile::Groups, GROUPS)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// A new group gets the next ID and is enabled by default
+    #[test]
+    fn add_group() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups")
+            .method(Method::Post)
+            .file_expect(
+                PiholeFile::Groups,
+                GROUPS,
+                "[{\"id\":1,\"name\":\"Kids\",\"description\":\"\",\"enabled\":true,\
+                 \"clients\":[\"10.0.0.5\"],\"domains\":[{\"list\":\"blacklist\",\
+                 \"domain\":\"games.example.com\"}]},{\"id\":2,\"name\":\"Guests\",\
+                 \"description\":\"Guest Wi-Fi\",\"enabled\":true,\"clients\":[],\
+                 \"domains\":[]}]\n"
+            )
+            .body(json!({ "name": "Guests", "description": "Guest Wi-Fi" }))
+            .expect_json(json!({
+                "id": 2,
+                "name": "Guests",
+                "description": "Guest Wi-Fi",
+                "enabled": true,
+                "clients": [],
+                "domains": []
+            }))
+            .test();
+    }
+
+    /// Group names are unique
+    #[test]
+    fn add_duplicate_group() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups")
+            .method(Method::Post)
+            .file_expect(PiholeFile::Groups, GROUPS, GROUPS)
+            .body(json!({ "name": "Kids" }))
+            .expect_status(Status::Conflict)
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Empty group names are rejected
+    #[test]
+    fn add_invalid_group() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups")
+            .method(Method::Post)
+            .file(PiholeFile::Groups, GROUPS)
+            .body(json!({ "name": " " }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_group",
+                    "message": "Invalid group",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Updating a group keeps its clients and domains
+    #[test]
+    fn update_group() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::Groups,
+                GROUPS,
+                "[{\"id\":1,\"name\":\"Children\",\"description\":\"\",\"enabled\":false,\
+                 \"clients\":[\"10.0.0.5\"],\"domains\":[{\"list\":\"blacklist\",\
+                 \"domain\":\"games.example.com\"}]}]\n"
+            )
+            .body(json!({ "name": "Children", "enabled": false }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Deleting a group removes it from the file
+    #[test]
+    fn delete_group() {
+        TestBuilder::new()
+            .endpoint("/admin/api/groups/1")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::Groups, GROUPS, "[]\n")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
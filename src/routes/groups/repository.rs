@@ -0,0 +1,137 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Group Storage
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::io::{Read, Write};
+
+/// A group of clients, with the list entries which apply to them
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Group {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub enabled: bool,
+    /// The IP addresses of the clients in the group
+    #[serde(default)]
+    pub clients: Vec<String>,
+    /// The list entries assigned to the group
+    #[serde(default)]
+    pub domains: Vec<GroupDomain>
+}
+
+/// A whitelist, blacklist, or regex entry assigned to a group. The list uses
+/// the name from its endpoints, such as `whitelist`.
+#[derive(Serialize, Deserialize, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct GroupDomain {
+    pub list: String,
+    pub domain: String
+}
+
+/// Read the groups. If the file does not exist, there are no groups.
+pub fn read_groups(env: &Env) -> Result<Vec<Group>, Error> {
+    if !env.file_exists(PiholeFile::Groups) {
+        return Ok(Vec::new());
+    }
+
+    let file_location = env.file_location(PiholeFile::Groups).to_owned();
+    let mut json = String::new();
+    env.read_file(PiholeFile::Groups)?
+        .read_to_string(&mut json)
+        .context(ErrorKind::FileRead(file_location.clone()))?;
+
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+}
+
+/// Save the groups, replacing the previously saved groups
+pub fn write_groups(env: &Env, groups: &[Group]) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::Groups).to_owned();
+    let mut file = env.write_file(PiholeFile::Groups, false)?;
+
+    serde_json::to_writer(&mut file, groups)
+        .context(ErrorKind::FileWrite(file_location.clone()))?;
+    writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+/// Find the group with the ID
+pub fn find_group(groups: &mut [Group], id: u32) -> Result<&mut Group, Error> {
+    groups
+        .iter_mut()
+        .find(|group| group.id == id)
+        .ok_or_else(|| Error::from(ErrorKind::NotFound))
+}
+
+/// Get the ID for a new group, which is one more than the highest ID
+pub fn next_group_id(groups: &[Group]) -> u32 {
+    groups.iter().map(|group| group.id).max().unwrap_or(0) + 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::{next_group_id, read_groups, Group};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// IDs continue after the highest ID, even if lower IDs were deleted
+    #[test]
+    fn next_id() {
+        let group = |id| Group {
+            id,
+            name: format!("Group {}", id),
+            description: String::new(),
+            enabled: true,
+            clients: Vec::new(),
+            domains: Vec::new()
+        };
+
+        assert_eq!(next_group_id(&[]), 1);
+        assert_eq!(next_group_id(&[group(3), group(1)]), 4);
+    }
+
+    /// Missing fields of a saved group use their defaults
+    #[test]
+    fn read_defaults() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::Groups,
+                    "[{\"id\":1,\"name\":\"Kids\",\"enabled\":true}]\n"
+                )
+                .build()
+        );
+
+        assert_eq!(
+            read_groups(&env).unwrap(),
+            vec![Group {
+                id: 1,
+                name: "Kids".to_owned(),
+                description: String::new(),
+                enabled: true,
+                clients: Vec::new(),
+                domains: Vec::new()
+            }]
+        );
+    }
+}
@@ -8,9 +8,14 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+pub mod api_keys;
 pub mod auth;
 pub mod dns;
+pub mod fields;
+pub mod groups;
+pub mod search;
 pub mod settings;
 pub mod stats;
+pub mod users;
 pub mod version;
 pub mod web;
@@ -0,0 +1,165 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Domain Search Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlMemory,
+    routes::{auth::User, dns::List, stats::PrivacyPolicy},
+    services::{adlists_containing, gravity_contains},
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use regex::Regex;
+use rocket::State;
+
+/// Find where a domain is listed and how often it has been queried, like
+/// `pihole -q`
+#[get("/search?<domain>")]
+pub fn search(domain: String, _auth: User, env: State<Env>, ftl_memory: State<FtlMemory>) -> Reply {
+    reply_data(search_domain(&domain.to_lowercase(), &env, &ftl_memory)?)
+}
+
+/// The lists which have the domain, and its queries in FTL's memory
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct SearchReply {
+    pub whitelist: bool,
+    pub blacklist: bool,
+    /// The regexes which match the domain
+    pub regexlist: Vec<String>,
+    /// The whitelist regexes which match the domain
+    pub regex_whitelist: Vec<String>,
+    pub gravity: bool,
+    /// The URLs of the adlists which have the domain
+    pub adlists: Vec<String>,
+    /// The number of queries, which is `None` if domains are private
+    pub queries: Option<QueryCounts>
+}
+
+/// The number of recent queries of a domain
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct QueryCounts {
+    pub total: usize,
+    pub blocked: usize
+}
+
+/// Search the lists and FTL's memory for the domain
+fn search_domain(domain: &str, env: &Env, ftl_memory: &FtlMemory) -> Result<SearchReply, Error> {
+    if domain.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidDomain));
+    }
+
+    Ok(SearchReply {
+        whitelist: List::White.get(env)?.iter().any(|entry| entry == domain),
+        blacklist: List::Black.get(env)?.iter().any(|entry| entry == domain),
+        regexlist: matching_regexes(List::Regex, domain, env)?,
+        regex_whitelist: matching_regexes(List::RegexWhite, domain, env)?,
+        gravity: gravity_contains(env, domain)?,
+        adlists: adlists_containing(env, domain)?,
+        queries: query_counts(domain, env, ftl_memory)?
+    })
+}
+
+/// Get the regexes of the list which match the domain. Invalid regexes are
+/// skipped.
+fn matching_regexes(list: List, domain: &str, env: &Env) -> Result<Vec<String>, Error> {
+    Ok(list
+        .get(env)?
+        .into_iter()
+        .filter(|regex| {
+            Regex::new(regex)
+                .ok()
+                .map_or(false, |regex| regex.is_match(domain))
+        })
+        .collect())
+}
+
+/// Count the domain's queries in FTL's memory. If domains are private, the
+/// counts are not shown.
+fn query_counts(
+    domain: &str,
+    env: &Env,
+    ftl_memory: &FtlMemory
+) -> Result<Option<QueryCounts>, Error> {
+    if !PrivacyPolicy::read(env)?.shows_domains() {
+        return Ok(None);
+    }
+
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    Ok(Some(
+        domains
+            .iter()
+            .take(counters.total_domains as usize)
+            .find(|ftl_domain| ftl_domain.get_domain(&strings) == domain)
+            .map_or(
+                QueryCounts {
+                    total: 0,
+                    blocked: 0
+                },
+                |ftl_domain| QueryCounts {
+                    total: ftl_domain.query_count as usize,
+                    blocked: ftl_domain.blocked_count as usize
+                }
+            )
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, routes::stats::testing::test_memory, testing::TestBuilder};
+
+    /// The lists with the domain and its query counts are found
+    #[test]
+    fn listed_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/search?domain=Domain1.com")
+            .ftl_memory(test_memory())
+            .file(PiholeFile::Whitelist, "domain1.com\n")
+            .file(PiholeFile::Blacklist, "example.com\n")
+            .file(PiholeFile::Regexlist, "^domain[0-9]\\.com$\n^ads\\.\n")
+            .file(PiholeFile::RegexWhitelist, "")
+            .file(PiholeFile::Gravity, "domain1.com\n")
+            .file(PiholeFile::Adlists, "")
+            .file(PiholeFile::FtlConfig, "")
+            .expect_json(json!({
+                "whitelist": true,
+                "blacklist": false,
+                "regexlist": ["^domain[0-9]\\.com$"],
+                "regex_whitelist": [],
+                "gravity": true,
+                "adlists": [],
+                "queries": { "total": 4, "blocked": 0 }
+            }))
+            .test();
+    }
+
+    /// Query counts are hidden when domains are private
+    #[test]
+    fn private_domains() {
+        TestBuilder::new()
+            .endpoint("/admin/api/search?domain=example.com")
+            .ftl_memory(test_memory())
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=1\n")
+            .expect_json(json!({
+                "whitelist": false,
+                "blacklist": false,
+                "regexlist": [],
+                "regex_whitelist": [],
+                "gravity": false,
+                "adlists": [],
+                "queries": null
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,107 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Settings Snapshot
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    settings::{ConfigEntry, FtlConfEntry, SetupVarsEntry},
+    util::{reply_data, Error, Reply}
+};
+use rocket::State;
+
+/// Get every setting, grouped by the file it is in. Secrets are redacted.
+#[get("/settings/all")]
+pub fn get_all_settings(env: State<Env>, _auth: User) -> Reply {
+    reply_data(read_all_settings(&env)?)
+}
+
+/// Read every setting, redacting the secrets
+fn read_all_settings(env: &Env) -> Result<AllSettings, Error> {
+    Ok(AllSettings {
+        setup_vars: SetupVarsEntry::all(env)?
+            .into_iter()
+            .map(|entry| SettingValue::read(entry, entry.is_secret(), env))
+            .collect::<Result<_, Error>>()?,
+        ftl: FtlConfEntry::ALL
+            .iter()
+            .map(|&entry| SettingValue::read(entry, false, env))
+            .collect::<Result<_, Error>>()?
+    })
+}
+
+/// The settings of each config file
+#[derive(Serialize)]
+pub struct AllSettings {
+    setup_vars: Vec<SettingValue>,
+    ftl: Vec<SettingValue>
+}
+
+/// The value of a setting. The value of a secret is not shown.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct SettingValue {
+    key: String,
+    value: Option<String>,
+    redacted: bool
+}
+
+impl SettingValue {
+    /// Read the value of the entry, unless it is a secret
+    fn read<T: ConfigEntry>(entry: T, secret: bool, env: &Env) -> Result<Self, Error> {
+        Ok(SettingValue {
+            key: entry.key().into_owned(),
+            value: if secret { None } else { Some(entry.read(env)?) },
+            redacted: secret
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_all_settings, SettingValue};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// Create the expected value of a setting
+    fn setting(key: &str, value: Option<&str>) -> SettingValue {
+        SettingValue {
+            key: key.to_owned(),
+            value: value.map(ToOwned::to_owned),
+            redacted: value.is_none()
+        }
+    }
+
+    /// Secrets are redacted, and other settings are shown with their value
+    /// or default
+    #[test]
+    fn redact_secrets() {
+        let env_builder = TestEnvBuilder::new()
+            .file(
+                PiholeFile::SetupVars,
+                "PIHOLE_DNS_1=8.8.8.8\n\
+                 WEBPASSWORD=841001982B9C2B4ADD4AA4C4E2B4B5F3A13E8A7E1E1A1B6AB2C8D5D9F7B3A0C2\n"
+            )
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2\n");
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        let settings = read_all_settings(&env).unwrap();
+
+        assert!(settings.setup_vars.contains(&setting("WEBPASSWORD", None)));
+        assert!(settings
+            .setup_vars
+            .contains(&setting("PIHOLE_DNS_1", Some("8.8.8.8"))));
+        assert!(settings
+            .setup_vars
+            .contains(&setting("BLOCKING_ENABLED", Some("true"))));
+        assert!(settings.ftl.contains(&setting("PRIVACYLEVEL", Some("2"))));
+    }
+}
@@ -0,0 +1,59 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Batched Settings Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::{
+        auth::User,
+        settings::{common::restart_dns, lint::reply_success_with_warnings}
+    },
+    settings::SettingsBatch,
+    util::Reply
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Update several setupVars.conf and FTL settings at once. Either all of the
+/// changes are applied or none are, and the DNS server is restarted once.
+#[put("/settings/batch", data = "<batch>")]
+pub fn put_settings_batch(env: State<Env>, _auth: User, batch: Json<SettingsBatch>) -> Reply {
+    batch.into_inner().apply(&env)?;
+    restart_dns(&env)?;
+    reply_success_with_warnings(&env)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// An invalid value rejects the whole batch, so no settings are changed
+    #[test]
+    fn invalid_batch() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/batch")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "DNSSEC=false\n")
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=0\n")
+            .body(json!({
+                "setup_vars": { "DNSSEC": "true" },
+                "ftl": { "PRIVACYLEVEL": "9" }
+            }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+}
@@ -0,0 +1,280 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Alias Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::nicknames::is_valid_nickname;
+use crate::{
+    client_nicknames::{read_aliases, write_aliases, ClientAlias, ClientNicknames},
+    databases::ftl::{network, FtlDatabase},
+    env::Env,
+    metrics::time_database,
+    routes::{
+        auth::User,
+        fields::{select_fields, FieldSelection}
+    },
+    settings::ValueType,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use diesel::{prelude::*, SqliteConnection};
+use failure::ResultExt;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::collections::BTreeMap;
+
+/// The maximum length of a comment
+const MAX_COMMENT_LENGTH: usize = 256;
+
+/// Get every known client: the devices in the network table, and the devices
+/// which have an alias. Clients are keyed by MAC address. With `fields`, only
+/// the selected fields of each client are included.
+#[get("/settings/clients?<fields>")]
+pub fn get_clients(
+    _auth: User,
+    env: State<Env>,
+    db: FtlDatabase,
+    fields: Option<FieldSelection>
+) -> Reply {
+    let clients = time_database(|| load_clients(&env, &db as &SqliteConnection))?;
+
+    reply_data(select_fields(clients, fields.as_ref()))
+}
+
+/// Get a client by MAC address
+#[get("/settings/clients/<mac>")]
+pub fn get_client(_auth: User, env: State<Env>, db: FtlDatabase, mac: String) -> Reply {
+    let mac = mac.to_lowercase();
+    let client = time_database(|| load_clients(&env, &db as &SqliteConnection))?
+        .into_iter()
+        .find(|client| client.mac == mac)
+        .ok_or(ErrorKind::NotFound)?;
+
+    reply_data(client)
+}
+
+/// Set the alias and comment of a client. The alias is used instead of the
+/// client's hostname in the stats.
+#[put("/settings/clients/<mac>", data = "<data>")]
+pub fn put_client(
+    _auth: User,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>,
+    db: FtlDatabase,
+    mac: String,
+    data: Json<ClientAliasInput>
+) -> Reply {
+    let input = data.into_inner();
+    let alias = ClientAlias {
+        mac: mac.to_lowercase(),
+        name: input.name.trim().to_owned(),
+        comment: input.comment.trim().to_owned()
+    };
+
+    if !is_valid_alias(&alias) {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    let mut aliases = read_aliases(&env)?;
+    aliases.retain(|saved| saved.mac != alias.mac);
+    aliases.push(alias);
+
+    write_aliases(&env, &aliases)?;
+    time_database(|| nicknames.reload(&env, &db))?;
+
+    reply_success()
+}
+
+/// Remove the alias and comment of a client
+#[delete("/settings/clients/<mac>")]
+pub fn delete_client(
+    _auth: User,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>,
+    db: FtlDatabase,
+    mac: String
+) -> Reply {
+    let mac = mac.to_lowercase();
+    let mut aliases = read_aliases(&env)?;
+    let count = aliases.len();
+
+    aliases.retain(|alias| alias.mac != mac);
+
+    if aliases.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    write_aliases(&env, &aliases)?;
+    time_database(|| nicknames.reload(&env, &db))?;
+
+    reply_success()
+}
+
+/// The alias and comment to set for a client
+#[derive(Deserialize)]
+pub struct ClientAliasInput {
+    name: String,
+    #[serde(default)]
+    comment: String
+}
+
+/// A client, with what the network table knows about it and its alias
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientInfo {
+    pub mac: String,
+    /// The IP addresses of the device in the network table
+    pub ips: Vec<String>,
+    /// The hostname found by FTL
+    pub hostname: Option<String>,
+    pub alias: Option<String>,
+    pub comment: Option<String>
+}
+
+/// Check if the alias is valid. A comment is saved after the name on the
+/// same line, so it can not contain line breaks.
+fn is_valid_alias(alias: &ClientAlias) -> bool {
+    ValueType::MacAddress.is_valid(&alias.mac)
+        && !alias.name.is_empty()
+        && is_valid_nickname(&alias.name)
+        && alias.comment.chars().count() <= MAX_COMMENT_LENGTH
+        && !alias.comment.contains(|c| c == '\n' || c == '\r')
+}
+
+/// Load the clients in the network table and the clients with an alias,
+/// sorted by MAC address
+fn load_clients(env: &Env, db: &SqliteConnection) -> Result<Vec<ClientInfo>, Error> {
+    let devices: Vec<(String, String, Option<String>)> = network::table
+        .select((network::ip, network::hwaddr, network::name))
+        .order(network::id)
+        .load(db)
+        .context(ErrorKind::FtlDatabase)?;
+    let mut clients: BTreeMap<String, ClientInfo> = BTreeMap::new();
+
+    for (ip, hwaddr, name) in devices {
+        let mac = hwaddr.to_lowercase();
+        let client = clients.entry(mac.clone()).or_insert_with(|| ClientInfo {
+            mac,
+            ips: Vec::new(),
+            hostname: None,
+            alias: None,
+            comment: None
+        });
+
+        client.ips.push(ip);
+
+        if client.hostname.is_none() {
+            client.hostname = name.filter(|name| !name.is_empty());
+        }
+    }
+
+    for alias in read_aliases(env)? {
+        let mac = alias.mac;
+        let client = clients.entry(mac.clone()).or_insert_with(|| ClientInfo {
+            mac,
+            ips: Vec::new(),
+            hostname: None,
+            alias: None,
+            comment: None
+        });
+
+        client.alias = Some(alias.name);
+
+        if !alias.comment.is_empty() {
+            client.comment = Some(alias.comment);
+        }
+    }
+
+    Ok(clients.into_iter().map(|(_, client)| client).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// Devices in the network table and devices with an alias are listed
+    #[test]
+    fn list() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/clients")
+            .need_database(true)
+            .file(
+                PiholeFile::ClientNicknames,
+                "00:00:00:00:00:00 Router\tIn the hallway\n\
+                 aa:bb:cc:dd:ee:ff Laptop\n"
+            )
+            .expect_json(json!([
+                {
+                    "mac": "00:00:00:00:00:00",
+                    "ips": ["10.1.1.1"],
+                    "hostname": "gateway",
+                    "alias": "Router",
+                    "comment": "In the hallway"
+                },
+                {
+                    "mac": "aa:bb:cc:dd:ee:ff",
+                    "ips": [],
+                    "hostname": null,
+                    "alias": "Laptop",
+                    "comment": null
+                }
+            ]))
+            .test();
+    }
+
+    /// Only the selected fields of each client are included
+    #[test]
+    fn list_fields() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/clients?fields=mac,alias")
+            .need_database(true)
+            .file(PiholeFile::ClientNicknames, "aa:bb:cc:dd:ee:ff Laptop\n")
+            .expect_json(json!([
+                { "mac": "00:00:00:00:00:00", "alias": null },
+                { "mac": "aa:bb:cc:dd:ee:ff", "alias": "Laptop" }
+            ]))
+            .test();
+    }
+
+    /// The alias and comment are saved by lowercase MAC address
+    #[test]
+    fn put() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/clients/AA:BB:CC:DD:EE:FF")
+            .method(Method::Put)
+            .need_database(true)
+            .file_expect(
+                PiholeFile::ClientNicknames,
+                "00:00:00:00:00:00 Router\n",
+                "00:00:00:00:00:00 Router\n\
+                 aa:bb:cc:dd:ee:ff Laptop\tWork laptop\n"
+            )
+            .body(json!({ "name": "Laptop", "comment": "Work laptop" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Removing a client without an alias is an error
+    #[test]
+    fn delete_missing() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/clients/aa:bb:cc:dd:ee:ff")
+            .method(Method::Delete)
+            .need_database(true)
+            .file(PiholeFile::ClientNicknames, "00:00:00:00:00:00 Router\n")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
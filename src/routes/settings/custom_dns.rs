@@ -0,0 +1,248 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Custom DNS Record Settings
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::{auth::User, dns::reload_dns, settings::common::restart_dns},
+    settings::{generate_dnsmasq_config, CnameRecord, DnsRecord},
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the custom A and AAAA records
+#[get("/settings/dns/records")]
+pub fn get_dns_records(env: State<Env>, _auth: User) -> Reply {
+    reply_data(DnsRecord::read_all(&env)?)
+}
+
+/// Add a custom A or AAAA record. The records are read by dnsmasq as a hosts
+/// file, so only a reload is needed.
+#[post("/settings/dns/records", data = "<data>")]
+pub fn add_dns_record(env: State<Env>, _auth: User, data: Json<DnsRecord>) -> Reply {
+    let mut record = data.into_inner();
+    record.domain = record.domain.to_lowercase();
+
+    if !record.is_valid() {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    let mut records = DnsRecord::read_all(&env)?;
+
+    if records
+        .iter()
+        .any(|existing| existing.domain == record.domain && existing.ip == record.ip)
+    {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    records.push(record);
+    DnsRecord::write_all(&env, &records)?;
+
+    reload_dns(&env)?;
+    reply_success()
+}
+
+/// Delete a custom A or AAAA record
+#[delete("/settings/dns/records/<domain>/<ip>")]
+pub fn delete_dns_record(env: State<Env>, _auth: User, domain: String, ip: String) -> Reply {
+    let domain = domain.to_lowercase();
+    let mut records = DnsRecord::read_all(&env)?;
+    let count = records.len();
+
+    records.retain(|record| record.domain != domain || record.ip != ip);
+
+    if records.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    DnsRecord::write_all(&env, &records)?;
+
+    reload_dns(&env)?;
+    reply_success()
+}
+
+/// Get the custom CNAME records
+#[get("/settings/dns/cnames")]
+pub fn get_cname_records(env: State<Env>, _auth: User) -> Reply {
+    reply_data(CnameRecord::read_all(&env)?)
+}
+
+/// Add a custom CNAME record. CNAME records are part of the dnsmasq config,
+/// so the DNS server is restarted.
+#[post("/settings/dns/cnames", data = "<data>")]
+pub fn add_cname_record(env: State<Env>, _auth: User, data: Json<CnameRecord>) -> Reply {
+    let mut record = data.into_inner();
+    record.domain = record.domain.to_lowercase();
+    record.target = record.target.to_lowercase();
+
+    if !record.is_valid() {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    let mut records = CnameRecord::read_all(&env)?;
+
+    // A domain can only have one CNAME record
+    if records
+        .iter()
+        .any(|existing| existing.domain == record.domain)
+    {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    records.push(record);
+    CnameRecord::write_all(&env, &records)?;
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success()
+}
+
+/// Delete the custom CNAME record of a domain
+#[delete("/settings/dns/cnames/<domain>")]
+pub fn delete_cname_record(env: State<Env>, _auth: User, domain: String) -> Reply {
+    let domain = domain.to_lowercase();
+    let mut records = CnameRecord::read_all(&env)?;
+    let count = records.len();
+
+    records.retain(|record| record.domain != domain);
+
+    if records.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    CnameRecord::write_all(&env, &records)?;
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The custom records are read from the hosts file
+    #[test]
+    fn get_records() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/records")
+            .file(
+                PiholeFile::CustomList,
+                "192.168.1.10 nas.lan\nfd00::10 nas.lan\n"
+            )
+            .expect_json(json!([
+                { "domain": "nas.lan", "ip": "192.168.1.10" },
+                { "domain": "nas.lan", "ip": "fd00::10" }
+            ]))
+            .test();
+    }
+
+    /// A record is added to the hosts file
+    #[test]
+    fn add_record() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/records")
+            .method(Method::Post)
+            .file_expect(
+                PiholeFile::CustomList,
+                "192.168.1.10 nas.lan\n",
+                "192.168.1.10 nas.lan\n192.168.1.11 printer.lan\n"
+            )
+            .body(json!({ "domain": "Printer.lan", "ip": "192.168.1.11" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Records must have a valid IP address
+    #[test]
+    fn add_invalid_record() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/records")
+            .method(Method::Post)
+            .file(PiholeFile::CustomList, "")
+            .body(json!({ "domain": "nas.lan", "ip": "nas" }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Only the record with the domain and IP address is deleted
+    #[test]
+    fn delete_record() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/records/nas.lan/fd00::10")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::CustomList,
+                "192.168.1.10 nas.lan\nfd00::10 nas.lan\n",
+                "192.168.1.10 nas.lan\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// A CNAME record is added
+    #[test]
+    fn add_cname() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/cnames")
+            .method(Method::Post)
+            .file(PiholeFile::SetupVars, "")
+            .file_expect(PiholeFile::CustomCnames, "", "files.lan,nas.lan\n")
+            .body(json!({ "domain": "files.lan", "target": "nas.lan" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// A domain can only have one CNAME record
+    #[test]
+    fn add_duplicate_cname() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/cnames")
+            .method(Method::Post)
+            .file(PiholeFile::CustomCnames, "files.lan,nas.lan\n")
+            .body(json!({ "domain": "files.lan", "target": "backup.lan" }))
+            .expect_status(Status::Conflict)
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Deleting a CNAME record which does not exist is an error
+    #[test]
+    fn delete_missing_cname() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dns/cnames/files.lan")
+            .method(Method::Delete)
+            .file(PiholeFile::CustomCnames, "")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -10,12 +10,19 @@
 
 use crate::{
     env::Env,
-    routes::{auth::User, settings::common::restart_dns},
-    settings::{generate_dnsmasq_config, ConfigEntry, SetupVarsEntry},
+    routes::{
+        auth::User,
+        settings::{common::restart_dns, lint::reply_success_with_warnings}
+    },
+    settings::{
+        generate_dnsmasq_config, ConfigEntry, DhcpLease, DhcpOption, LeaseTime, SetupVarsEntry,
+        StaticLease, Subnet, ValueType
+    },
     util::{reply_data, reply_success, Error, ErrorKind, Reply}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
+use std::net::Ipv6Addr;
 
 #[derive(Serialize, Deserialize)]
 pub struct DhcpSettings {
@@ -23,20 +30,50 @@ pub struct DhcpSettings {
     ip_start: String,
     ip_end: String,
     router_ip: String,
-    lease_time: usize,
+    /// A duration such as `12h` or `2d`, or `infinite`
+    lease_time: String,
     domain: String,
-    ipv6_support: bool
+    /// IPv4 can be turned off for IPv6-only networks
+    #[serde(default = "default_ipv4_support")]
+    ipv4_support: bool,
+    ipv6_support: bool,
+    /// Additional IPv4 ranges for other subnets, such as VLANs or networks
+    /// behind a DHCP relay. They use the lease time of the main range.
+    #[serde(default)]
+    ranges: Vec<DhcpRange>
+}
+
+fn default_ipv4_support() -> bool {
+    true
+}
+
+/// An additional IPv4 range, and the router of its subnet
+#[derive(Serialize, Deserialize)]
+pub struct DhcpRange {
+    ip_start: String,
+    ip_end: String,
+    router_ip: String
+}
+
+impl DhcpRange {
+    /// Get the value of the range's `SetupVarsEntry::DhcpRange`
+    fn entry_value(&self) -> String {
+        format!("{},{},{}", self.ip_start, self.ip_end, self.router_ip)
+    }
 }
 
 impl DhcpSettings {
     /// Check if all settings are valid
     fn is_valid(&self) -> bool {
-        // If DHCP is to be turned on, no settings may be empty
+        // If DHCP is to be turned on, no settings may be empty, and it must
+        // serve at least one protocol
         if self.active
-            && (self.ip_start.is_empty()
-                || self.ip_end.is_empty()
-                || self.router_ip.is_empty()
-                || self.domain.is_empty())
+            && (self.domain.is_empty()
+                || !(self.ipv4_support || self.ipv6_support)
+                || (self.ipv4_support
+                    && (self.ip_start.is_empty()
+                        || self.ip_end.is_empty()
+                        || self.router_ip.is_empty())))
         {
             return false;
         }
@@ -45,9 +82,86 @@ impl DhcpSettings {
             && SetupVarsEntry::DhcpEnd.is_valid(&self.ip_end)
             && SetupVarsEntry::DhcpRouter.is_valid(&self.router_ip)
             && SetupVarsEntry::PiholeDomain.is_valid(&self.domain)
+            && self.lease_time.parse::<LeaseTime>().is_ok()
+            && self
+                .ranges
+                .iter()
+                .all(|range| SetupVarsEntry::DhcpRange(0).is_valid(&range.entry_value()))
     }
 }
 
+/// The router advertisement and DHCPv6 settings, used when IPv6 support is
+/// enabled
+#[derive(Serialize, Deserialize)]
+pub struct Dhcpv6Settings {
+    /// One of `ra-only`, `stateless`, `slaac`, or `stateful`
+    mode: String,
+    /// The prefix to advertise. If empty, the prefix of the interface is
+    /// used.
+    prefix: String,
+    /// The DHCPv6 range, as addresses in the prefix such as `::100`
+    range_start: String,
+    range_end: String,
+    /// The seconds between router advertisements. Zero uses the default.
+    ra_interval: usize,
+    /// The seconds which clients use Pi-hole as their router. Zero means
+    /// Pi-hole is not a router.
+    router_lifetime: usize,
+    /// The lease time of DHCPv6 addresses. If empty, the IPv4 lease time is
+    /// used.
+    #[serde(default)]
+    lease_time: String
+}
+
+impl Dhcpv6Settings {
+    /// Check if all settings are valid. SLAAC needs a /64 prefix, and a
+    /// DHCPv6 range needs a prefix of at least /64.
+    fn is_valid(&self) -> bool {
+        let valid_range = ValueType::Ipv6.is_valid(&self.range_start)
+            && ValueType::Ipv6.is_valid(&self.range_end)
+            && self.range_start.parse::<Ipv6Addr>().ok() <= self.range_end.parse::<Ipv6Addr>().ok();
+        let valid_prefix = self.prefix.is_empty()
+            || self.prefix.parse::<Subnet>().ok().map_or(false, |subnet| {
+                subnet.is_ipv6()
+                    && if self.mode == "stateful" {
+                        subnet.prefix() >= 64
+                    } else {
+                        subnet.prefix() == 64
+                    }
+            });
+
+        !self.mode.is_empty()
+            && SetupVarsEntry::DhcpIpv6Mode.is_valid(&self.mode)
+            && valid_prefix
+            && valid_range
+            && (self.ra_interval == 0 || (self.ra_interval >= 4 && self.ra_interval <= 1800))
+            && self.router_lifetime <= 9000
+            && (self.lease_time.is_empty() || self.lease_time.parse::<LeaseTime>().is_ok())
+    }
+}
+
+/// Get the additional DHCP ranges
+fn get_dhcp_ranges(env: &State<Env>) -> Result<Vec<DhcpRange>, Error> {
+    let mut ranges = Vec::new();
+
+    for num in 1.. {
+        let range = SetupVarsEntry::DhcpRange(num).read(&env)?;
+
+        if range.is_empty() {
+            break;
+        }
+
+        let mut split = range.splitn(3, ',');
+        ranges.push(DhcpRange {
+            ip_start: split.next().unwrap_or_default().to_owned(),
+            ip_end: split.next().unwrap_or_default().to_owned(),
+            router_ip: split.next().unwrap_or_default().to_owned()
+        });
+    }
+
+    Ok(ranges)
+}
+
 /// Get DHCP Configuration
 #[get("/settings/dhcp")]
 pub fn get_dhcp(env: State<Env>, _auth: User) -> Reply {
@@ -56,9 +170,13 @@ pub fn get_dhcp(env: State<Env>, _auth: User) -> Reply {
         ip_start: SetupVarsEntry::DhcpStart.read(&env)?,
         ip_end: SetupVarsEntry::DhcpEnd.read(&env)?,
         router_ip: SetupVarsEntry::DhcpRouter.read(&env)?,
-        lease_time: SetupVarsEntry::DhcpLeasetime.read_as(&env)?,
+        lease_time: SetupVarsEntry::DhcpLeasetime
+            .read_as::<LeaseTime>(&env)?
+            .to_string(),
         domain: SetupVarsEntry::PiholeDomain.read(&env)?,
-        ipv6_support: SetupVarsEntry::DhcpIpv6.is_true(&env)?
+        ipv4_support: SetupVarsEntry::DhcpIpv4.is_true(&env)?,
+        ipv6_support: SetupVarsEntry::DhcpIpv6.is_true(&env)?,
+        ranges: get_dhcp_ranges(&env)?
     };
 
     reply_data(dhcp_settings)
@@ -73,14 +191,204 @@ pub fn put_dhcp(env: State<Env>, _auth: User, data: Json<DhcpSettings>) -> Reply
         return Err(Error::from(ErrorKind::InvalidSettingValue));
     }
 
+    let lease_time: LeaseTime = settings.lease_time.parse()?;
+
     SetupVarsEntry::DhcpActive.write(&settings.active.to_string(), &env)?;
     SetupVarsEntry::DhcpStart.write(&settings.ip_start, &env)?;
     SetupVarsEntry::DhcpEnd.write(&settings.ip_end, &env)?;
     SetupVarsEntry::DhcpRouter.write(&settings.router_ip, &env)?;
-    SetupVarsEntry::DhcpLeasetime.write(&settings.lease_time.to_string(), &env)?;
+    SetupVarsEntry::DhcpLeasetime.write(&lease_time.setup_vars_value(), &env)?;
     SetupVarsEntry::PiholeDomain.write(&settings.domain, &env)?;
+    SetupVarsEntry::DhcpIpv4.write(&settings.ipv4_support.to_string(), &env)?;
     SetupVarsEntry::DhcpIpv6.write(&settings.ipv6_support.to_string(), &env)?;
 
+    // Replace the previous additional ranges
+    SetupVarsEntry::delete_dhcp_ranges(&env)?;
+
+    for (i, range) in settings.ranges.iter().enumerate() {
+        SetupVarsEntry::DhcpRange(i + 1).write(&range.entry_value(), &env)?;
+    }
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success_with_warnings(&env)
+}
+
+/// Get the router advertisement and DHCPv6 settings
+#[get("/settings/dhcp/ipv6")]
+pub fn get_dhcpv6(env: State<Env>, _auth: User) -> Reply {
+    let settings = Dhcpv6Settings {
+        mode: SetupVarsEntry::DhcpIpv6Mode.read(&env)?,
+        prefix: SetupVarsEntry::DhcpIpv6Prefix.read(&env)?,
+        range_start: SetupVarsEntry::DhcpIpv6Start.read(&env)?,
+        range_end: SetupVarsEntry::DhcpIpv6End.read(&env)?,
+        ra_interval: SetupVarsEntry::DhcpIpv6RaInterval.read_as(&env)?,
+        router_lifetime: SetupVarsEntry::DhcpIpv6RouterLifetime.read_as(&env)?,
+        lease_time: match SetupVarsEntry::DhcpIpv6Leasetime.read(&env)?.as_str() {
+            "" => String::new(),
+            lease_time => lease_time.parse::<LeaseTime>()?.to_string()
+        }
+    };
+
+    reply_data(settings)
+}
+
+/// Update the router advertisement and DHCPv6 settings
+#[put("/settings/dhcp/ipv6", data = "<data>")]
+pub fn put_dhcpv6(env: State<Env>, _auth: User, data: Json<Dhcpv6Settings>) -> Reply {
+    let settings = data.into_inner();
+
+    if !settings.is_valid() {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    SetupVarsEntry::DhcpIpv6Mode.write(&settings.mode, &env)?;
+    SetupVarsEntry::DhcpIpv6Prefix.write(&settings.prefix, &env)?;
+    SetupVarsEntry::DhcpIpv6Start.write(&settings.range_start, &env)?;
+    SetupVarsEntry::DhcpIpv6End.write(&settings.range_end, &env)?;
+    SetupVarsEntry::DhcpIpv6RaInterval.write(&settings.ra_interval.to_string(), &env)?;
+    SetupVarsEntry::DhcpIpv6RouterLifetime.write(&settings.router_lifetime.to_string(), &env)?;
+
+    if settings.lease_time.is_empty() {
+        SetupVarsEntry::DhcpIpv6Leasetime.delete(&env)?;
+    } else {
+        let lease_time: LeaseTime = settings.lease_time.parse()?;
+        SetupVarsEntry::DhcpIpv6Leasetime.write(&lease_time.setup_vars_value(), &env)?;
+    }
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success()
+}
+
+/// Get the leases handed out by the DHCP server
+#[get("/settings/dhcp/leases")]
+pub fn get_dhcp_leases(env: State<Env>, _auth: User) -> Reply {
+    reply_data(DhcpLease::read_all(&env)?)
+}
+
+/// Remove the lease of a device, so its address can be handed out again
+#[delete("/settings/dhcp/leases/<mac>")]
+pub fn delete_dhcp_lease(env: State<Env>, _auth: User, mac: String) -> Reply {
+    DhcpLease::remove(&env, &mac)?;
+
+    restart_dns(&env)?;
+    reply_success()
+}
+
+/// Get the static leases
+#[get("/settings/dhcp/static_leases")]
+pub fn get_static_leases(env: State<Env>, _auth: User) -> Reply {
+    reply_data(StaticLease::read_all(&env)?)
+}
+
+/// Add a static lease, giving a device a fixed address
+#[post("/settings/dhcp/static_leases", data = "<data>")]
+pub fn add_static_lease(env: State<Env>, _auth: User, data: Json<StaticLease>) -> Reply {
+    let mut lease = data.into_inner();
+    lease.mac = lease.mac.to_lowercase();
+
+    if !lease.is_valid() {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    let mut leases = StaticLease::read_all(&env)?;
+
+    // A device can only have one static lease, and an address can only be
+    // given to one device
+    if leases
+        .iter()
+        .any(|existing| existing.mac == lease.mac || existing.ip == lease.ip)
+    {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    leases.push(lease);
+    StaticLease::write_all(&env, &leases)?;
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success()
+}
+
+/// Delete the static lease of a device
+#[delete("/settings/dhcp/static_leases/<mac>")]
+pub fn delete_static_lease(env: State<Env>, _auth: User, mac: String) -> Reply {
+    let mac = mac.to_lowercase();
+    let mut leases = StaticLease::read_all(&env)?;
+    let count = leases.len();
+
+    leases.retain(|lease| lease.mac != mac);
+
+    if leases.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    StaticLease::write_all(&env, &leases)?;
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success()
+}
+
+/// Get the custom DHCP options
+#[get("/settings/dhcp/options")]
+pub fn get_dhcp_options(env: State<Env>, _auth: User) -> Reply {
+    reply_data(DhcpOption::read_all(&env)?)
+}
+
+/// Add a custom DHCP option
+#[post("/settings/dhcp/options", data = "<data>")]
+pub fn add_dhcp_option(env: State<Env>, _auth: User, data: Json<DhcpOption>) -> Reply {
+    let mut option = data.into_inner();
+    option.mac = option.mac.to_lowercase();
+
+    if !option.is_valid() {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    let mut options = DhcpOption::read_all(&env)?;
+
+    // dnsmasq only sends one value of an option to a client
+    if options.iter().any(|existing| existing.same_scope(&option)) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    options.push(option);
+    DhcpOption::write_all(&env, &options)?;
+
+    generate_dnsmasq_config(&env)?;
+    restart_dns(&env)?;
+    reply_success()
+}
+
+/// Delete a custom DHCP option. The tag or MAC address select the option
+/// which is limited to them.
+#[delete("/settings/dhcp/options/<number>?<tag>&<mac>")]
+pub fn delete_dhcp_option(
+    env: State<Env>,
+    _auth: User,
+    number: u8,
+    tag: Option<String>,
+    mac: Option<String>
+) -> Reply {
+    let target = DhcpOption {
+        number,
+        value: String::new(),
+        tag: tag.unwrap_or_default(),
+        mac: mac.unwrap_or_default().to_lowercase()
+    };
+    let mut options = DhcpOption::read_all(&env)?;
+    let count = options.len();
+
+    options.retain(|option| !option.same_scope(&target));
+
+    if options.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    DhcpOption::write_all(&env, &options)?;
+
     generate_dnsmasq_config(&env)?;
     restart_dns(&env)?;
     reply_success()
@@ -88,8 +396,12 @@ pub fn put_dhcp(env: State<Env>, _auth: User, data: Json<DhcpSettings>) -> Reply
 
 #[cfg(test)]
 mod test {
-    use crate::{env::PiholeFile, routes::settings::dhcp::DhcpSettings, testing::TestBuilder};
-    use rocket::http::Method;
+    use crate::{
+        env::PiholeFile,
+        routes::settings::dhcp::{DhcpRange, DhcpSettings},
+        testing::TestBuilder
+    };
+    use rocket::http::{Method, Status};
 
     /// Verify that having active DHCP and missing settings is invalid
     #[test]
@@ -99,9 +411,11 @@ mod test {
             ip_start: "".to_owned(),
             ip_end: "".to_owned(),
             router_ip: "".to_owned(),
-            lease_time: 24,
+            lease_time: "24h".to_owned(),
             domain: "".to_owned(),
-            ipv6_support: false
+            ipv4_support: true,
+            ipv6_support: false,
+            ranges: Vec::new()
         };
 
         assert_eq!(settings.is_valid(), false);
@@ -115,9 +429,11 @@ mod test {
             ip_start: "".to_owned(),
             ip_end: "".to_owned(),
             router_ip: "".to_owned(),
-            lease_time: 24,
+            lease_time: "24h".to_owned(),
             domain: "".to_owned(),
-            ipv6_support: false
+            ipv4_support: true,
+            ipv6_support: false,
+            ranges: Vec::new()
         };
 
         assert_eq!(settings.is_valid(), true);
@@ -131,14 +447,38 @@ mod test {
             ip_start: "192.168.1.50".to_owned(),
             ip_end: "192.168.1.150".to_owned(),
             router_ip: "192.168.1.1".to_owned(),
-            lease_time: 24,
+            lease_time: "24h".to_owned(),
             domain: "lan".to_owned(),
-            ipv6_support: false
+            ipv4_support: true,
+            ipv6_support: false,
+            ranges: Vec::new()
         };
 
         assert_eq!(settings.is_valid(), true);
     }
 
+    /// IPv6-only DHCP does not need the IPv4 settings, but DHCP must serve
+    /// at least one protocol
+    #[test]
+    fn ipv6_only() {
+        let mut settings = DhcpSettings {
+            active: true,
+            ip_start: "".to_owned(),
+            ip_end: "".to_owned(),
+            router_ip: "".to_owned(),
+            lease_time: "24h".to_owned(),
+            domain: "lan".to_owned(),
+            ipv4_support: false,
+            ipv6_support: true,
+            ranges: Vec::new()
+        };
+
+        assert_eq!(settings.is_valid(), true);
+
+        settings.ipv6_support = false;
+        assert_eq!(settings.is_valid(), false);
+    }
+
     /// Verify that having invalid settings is invalid
     #[test]
     fn invalid_if_setting_invalid() {
@@ -147,9 +487,58 @@ mod test {
             ip_start: "not an IP".to_owned(),
             ip_end: "not an IP".to_owned(),
             router_ip: "not an IP".to_owned(),
-            lease_time: 24,
+            lease_time: "24h".to_owned(),
             domain: "not a domain".to_owned(),
-            ipv6_support: false
+            ipv4_support: true,
+            ipv6_support: false,
+            ranges: Vec::new()
+        };
+
+        assert_eq!(settings.is_valid(), false);
+    }
+
+    /// Additional ranges need a router, and must not end before they start
+    #[test]
+    fn invalid_if_range_invalid() {
+        let range = |ip_start: &str, ip_end: &str, router_ip: &str| DhcpRange {
+            ip_start: ip_start.to_owned(),
+            ip_end: ip_end.to_owned(),
+            router_ip: router_ip.to_owned()
+        };
+        let mut settings = DhcpSettings {
+            active: false,
+            ip_start: "".to_owned(),
+            ip_end: "".to_owned(),
+            router_ip: "".to_owned(),
+            lease_time: "24h".to_owned(),
+            domain: "".to_owned(),
+            ipv4_support: true,
+            ipv6_support: false,
+            ranges: vec![range("192.168.2.50", "192.168.2.150", "192.168.2.1")]
+        };
+
+        assert_eq!(settings.is_valid(), true);
+
+        settings.ranges = vec![range("192.168.2.50", "192.168.2.150", "")];
+        assert_eq!(settings.is_valid(), false);
+
+        settings.ranges = vec![range("192.168.2.150", "192.168.2.50", "192.168.2.1")];
+        assert_eq!(settings.is_valid(), false);
+    }
+
+    /// Lease times must be a duration or `infinite`
+    #[test]
+    fn invalid_if_lease_time_invalid() {
+        let settings = DhcpSettings {
+            active: false,
+            ip_start: "".to_owned(),
+            ip_end: "".to_owned(),
+            router_ip: "".to_owned(),
+            lease_time: "24 hours".to_owned(),
+            domain: "".to_owned(),
+            ipv4_support: true,
+            ipv6_support: false,
+            ranges: Vec::new()
         };
 
         assert_eq!(settings.is_valid(), false);
@@ -168,16 +557,25 @@ mod test {
                  DHCP_LEASETIME=24\n\
                  PIHOLE_DOMAIN=lan\n\
                  DHCP_IPv6=false\n\
-                 DHCP_ACTIVE=false\n"
+                 DHCP_ACTIVE=false\n\
+                 DHCP_RANGE_1=10.0.0.100,10.0.0.200,10.0.0.1\n"
             )
             .expect_json(json!({
                 "active": false,
                 "ip_start": "192.168.1.201",
                 "ip_end": "192.168.1.251",
                 "router_ip": "192.168.1.1",
-                "lease_time": 24,
+                "lease_time": "1d",
                 "domain": "lan",
+                "ipv4_support": true,
                 "ipv6_support": false,
+                "ranges": [
+                    {
+                        "ip_start": "10.0.0.100",
+                        "ip_end": "10.0.0.200",
+                        "router_ip": "10.0.0.1"
+                    }
+                ]
             }))
             .test();
     }
@@ -193,9 +591,11 @@ mod test {
                 "ip_start": "",
                 "ip_end": "",
                 "router_ip": "",
-                "lease_time": 24,
+                "lease_time": "1d",
                 "domain": "",
+                "ipv4_support": true,
                 "ipv6_support": false,
+                "ranges": []
             }))
             .test();
     }
@@ -219,17 +619,20 @@ mod test {
                  DHCP_ROUTER=192.168.1.1\n\
                  DHCP_LEASETIME=24\n\
                  PIHOLE_DOMAIN=lan\n\
-                 DHCP_IPv6=true\n"
+                 DHCP_IPv4=true\n\
+                 DHCP_IPv6=true\n\
+                 DHCP_RANGE_1=192.168.2.50,192.168.2.150,192.168.2.1\n"
             )
             .file_expect(
                 PiholeFile::DnsmasqConfig,
                 "",
-                "################################################################\n\
-                 #       THIS FILE IS AUTOMATICALLY GENERATED BY PI-HOLE.       #\n\
-                 #          ANY CHANGES MADE TO THIS FILE WILL BE LOST.         #\n\
+                "#### BEGIN PI-HOLE MANAGED BLOCK ####\n\
+                 ################################################################\n\
+                 #      THIS BLOCK IS AUTOMATICALLY GENERATED BY PI-HOLE.       #\n\
+                 #       ANY CHANGES MADE INSIDE THIS BLOCK WILL BE LOST.       #\n\
                  #                                                              #\n\
-                 #  NEW CONFIG SETTINGS MUST BE MADE IN A SEPARATE CONFIG FILE  #\n\
-                 #                OR IN /etc/dnsmasq.conf                       #\n\
+                 #     YOUR OWN CONFIG SETTINGS CAN BE ADDED ABOVE OR BELOW     #\n\
+                 #        THIS BLOCK, AND ARE KEPT WHEN IT IS REWRITTEN         #\n\
                  ################################################################\n\
                  \n\
                  localise-queries\n\
@@ -239,6 +642,7 @@ mod test {
                  addn-hosts=/etc/pihole/gravity.list\n\
                  addn-hosts=/etc/pihole/black.list\n\
                  addn-hosts=/etc/pihole/local.list\n\
+                 addn-hosts=/etc/pihole/custom.list\n\
                  domain-needed\n\
                  bogus-priv\n\
                  local-service\n\
@@ -246,24 +650,282 @@ mod test {
                  dhcp-leasefile=/etc/pihole/dhcp.leases\n\
                  dhcp-range=192.168.1.50,192.168.1.150,24h\n\
                  dhcp-option=option:router,192.168.1.1\n\
+                 dhcp-range=set:range1,192.168.2.50,192.168.2.150,24h\n\
+                 dhcp-option=tag:range1,option:router,192.168.2.1\n\
                  dhcp-name-match=set:wpad-ignore,wpad\n\
                  dhcp-ignore-names=tag:wpad-ignore\n\
                  dhcp-option=option6:dns-server,[::]\n\
                  dhcp-range=::100,::1ff,constructor:eth0,ra-names,slaac,24h\n\
-                 ra-param=*,0,0\n"
+                 ra-param=*,0,0\n\
+                 #### END PI-HOLE MANAGED BLOCK ####\n"
             )
             .body(json!({
                 "active": true,
                 "ip_start": "192.168.1.50",
                 "ip_end": "192.168.1.150",
                 "router_ip": "192.168.1.1",
-                "lease_time": 24,
+                "lease_time": "24h",
                 "domain": "lan",
-                "ipv6_support": true
+                "ipv6_support": true,
+                "ranges": [
+                    {
+                        "ip_start": "192.168.2.50",
+                        "ip_end": "192.168.2.150",
+                        "router_ip": "192.168.2.1"
+                    }
+                ]
+            }))
+            .expect_json(json!({
+                "status": "success",
+                "warnings": []
+            }))
+            .test();
+    }
+
+    /// The default IPv6 settings match the previous fixed settings
+    #[test]
+    fn get_dhcpv6_defaults() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/ipv6")
+            .file(PiholeFile::SetupVars, "")
+            .expect_json(json!({
+                "mode": "slaac",
+                "prefix": "",
+                "range_start": "::100",
+                "range_end": "::1ff",
+                "ra_interval": 0,
+                "router_lifetime": 0,
+                "lease_time": ""
+            }))
+            .test();
+    }
+
+    /// The IPv6 settings are stored
+    #[test]
+    fn put_dhcpv6() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/ipv6")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "DHCP_IPv6_MODE=stateless\n\
+                 DHCP_IPv6_PREFIX=fd00:1::/64\n\
+                 DHCP_IPv6_START=::100\n\
+                 DHCP_IPv6_END=::1ff\n\
+                 DHCP_IPv6_RA_INTERVAL=30\n\
+                 DHCP_IPv6_RA_LIFETIME=1800\n\
+                 DHCP_IPv6_LEASETIME=12\n"
+            )
+            .body(json!({
+                "mode": "stateless",
+                "prefix": "fd00:1::/64",
+                "range_start": "::100",
+                "range_end": "::1ff",
+                "ra_interval": 30,
+                "router_lifetime": 1800,
+                "lease_time": "12h"
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// SLAAC needs a /64 prefix
+    #[test]
+    fn put_dhcpv6_invalid_prefix() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/ipv6")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({
+                "mode": "slaac",
+                "prefix": "fd00:1::/48",
+                "range_start": "::100",
+                "range_end": "::1ff",
+                "ra_interval": 0,
+                "router_lifetime": 0
+            }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The active leases are read from the lease file
+    #[test]
+    fn get_leases() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/leases")
+            .file(
+                PiholeFile::DhcpLeases,
+                "1570000000 00:11:22:33:44:55 192.168.1.50 laptop 01:00:11:22:33:44:55\n\
+                 0 66:77:88:99:aa:bb 192.168.1.51 * *\n\
+                 duid 00:01:00:01:25:d3:a2:f1\n"
+            )
+            .expect_json(json!([
+                {
+                    "expires": 1_570_000_000,
+                    "mac": "00:11:22:33:44:55",
+                    "ip": "192.168.1.50",
+                    "hostname": "laptop",
+                    "client_id": "01:00:11:22:33:44:55"
+                },
+                {
+                    "expires": 0,
+                    "mac": "66:77:88:99:aa:bb",
+                    "ip": "192.168.1.51",
+                    "hostname": "",
+                    "client_id": ""
+                }
+            ]))
+            .test();
+    }
+
+    /// The lease is removed from the lease file
+    #[test]
+    fn delete_lease() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/leases/66:77:88:99:AA:BB")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::DhcpLeases,
+                "1570000000 00:11:22:33:44:55 192.168.1.50 laptop *\n\
+                 0 66:77:88:99:aa:bb 192.168.1.51 * *\n",
+                "1570000000 00:11:22:33:44:55 192.168.1.50 laptop *\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Removing a lease which does not exist is an error
+    #[test]
+    fn delete_missing_lease() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/leases/66:77:88:99:aa:bb")
+            .method(Method::Delete)
+            .file(PiholeFile::DhcpLeases, "")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// A static lease is added with a lowercase MAC address
+    #[test]
+    fn add_static_lease() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/static_leases")
+            .method(Method::Post)
+            .file(PiholeFile::SetupVars, "")
+            .file_expect(
+                PiholeFile::StaticDhcpLeases,
+                "00:11:22:33:44:55,192.168.1.10,nas\n",
+                "00:11:22:33:44:55,192.168.1.10,nas\n\
+                 66:77:88:99:aa:bb,192.168.1.11,\n"
+            )
+            .body(json!({ "mac": "66:77:88:99:AA:BB", "ip": "192.168.1.11" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An address can only be given to one device
+    #[test]
+    fn add_duplicate_static_lease() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/static_leases")
+            .method(Method::Post)
+            .file(
+                PiholeFile::StaticDhcpLeases,
+                "00:11:22:33:44:55,192.168.1.10,nas\n"
+            )
+            .body(json!({ "mac": "66:77:88:99:aa:bb", "ip": "192.168.1.10" }))
+            .expect_status(Status::Conflict)
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
             }))
+            .test();
+    }
+
+    /// The static lease is deleted
+    #[test]
+    fn delete_static_lease() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/static_leases/00:11:22:33:44:55")
+            .method(Method::Delete)
+            .file(PiholeFile::SetupVars, "")
+            .file_expect(
+                PiholeFile::StaticDhcpLeases,
+                "00:11:22:33:44:55,192.168.1.10,nas\n\
+                 66:77:88:99:aa:bb,192.168.1.11,\n",
+                "66:77:88:99:aa:bb,192.168.1.11,\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// A custom option is added
+    #[test]
+    fn add_dhcp_option() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/options")
+            .method(Method::Post)
+            .file(PiholeFile::SetupVars, "")
+            .file_expect(
+                PiholeFile::DhcpOptions,
+                "42,,,192.168.1.1\n",
+                "42,,,192.168.1.1\n67,pxe,,pxelinux.0\n"
+            )
+            .body(json!({ "number": 67, "value": "pxelinux.0", "tag": "pxe" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Values of well known options must have the type of the option
+    #[test]
+    fn add_invalid_dhcp_option() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/options")
+            .method(Method::Post)
+            .file(PiholeFile::DhcpOptions, "")
+            .body(json!({ "number": 42, "value": "ntp.lan" }))
+            .expect_status(Status::BadRequest)
             .expect_json(json!({
-                "status": "success"
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
             }))
             .test();
     }
+
+    /// Only the option for the client is deleted
+    #[test]
+    fn delete_client_dhcp_option() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/dhcp/options/42?mac=00:11:22:33:44:55")
+            .method(Method::Delete)
+            .file(PiholeFile::SetupVars, "")
+            .file_expect(
+                PiholeFile::DhcpOptions,
+                "42,,,192.168.1.1\n42,,00:11:22:33:44:55,192.168.1.2\n",
+                "42,,,192.168.1.1\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
 }
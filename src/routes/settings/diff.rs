@@ -0,0 +1,99 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Settings Differences From Defaults
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    settings::{ConfigEntry, FtlConfEntry, SetupVarsEntry},
+    util::{reply_data, Error, Reply}
+};
+use rocket::State;
+
+/// Get the settings which are not set to their default value, such as for
+/// support requests and backups
+#[get("/settings/diff")]
+pub fn get_settings_diff(env: State<Env>, _auth: User) -> Reply {
+    reply_data(json!({
+        "setup_vars": setup_vars_diff(&env)?,
+        "ftl": diff_entries(FtlConfEntry::ALL, &env)?
+    }))
+}
+
+/// A setting which differs from its default value
+#[derive(Serialize)]
+pub struct SettingDiff {
+    key: String,
+    value: String,
+    default: String
+}
+
+/// Get the changed setupVars.conf entries. Secrets such as the password
+/// hash are left out.
+fn setup_vars_diff(env: &Env) -> Result<Vec<SettingDiff>, Error> {
+    let entries: Vec<SetupVarsEntry> = SetupVarsEntry::all(env)?
+        .into_iter()
+        .filter(|entry| !entry.is_secret())
+        .collect();
+
+    diff_entries(&entries, env)
+}
+
+/// Get the entries whose value differs from their default value
+fn diff_entries<T: ConfigEntry>(entries: &[T], env: &Env) -> Result<Vec<SettingDiff>, Error> {
+    let mut diff = Vec::new();
+
+    for entry in entries {
+        let value = entry.read(env)?;
+
+        if value != entry.get_default() {
+            diff.push(SettingDiff {
+                key: entry.key().into_owned(),
+                value,
+                default: entry.get_default().to_owned()
+            });
+        }
+    }
+
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+
+    /// Only settings which differ from their default are returned, and
+    /// secrets are left out
+    #[test]
+    fn changed_settings() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/diff")
+            .file(
+                PiholeFile::SetupVars,
+                "BLOCKING_ENABLED=true\n\
+                 DNSSEC=true\n\
+                 PIHOLE_DNS_1=8.8.8.8\n\
+                 PIHOLE_DNS_2=8.8.4.4\n\
+                 WEBPASSWORD=841001982B9C2B4ADD4AA4C4E2B4B5F3A13E8A7E1E1A1B6AB2C8D5D9F7B3A0C2\n\
+                 API_NOTIFY_WEBHOOK=https://hooks.example.com/token\n"
+            )
+            .file(PiholeFile::FtlConfig, "MAXDBDAYS=365\nPRIVACYLEVEL=2\n")
+            .expect_json(json!({
+                "setup_vars": [
+                    { "key": "DNSSEC", "value": "true", "default": "false" },
+                    { "key": "PIHOLE_DNS_1", "value": "8.8.8.8", "default": "" },
+                    { "key": "PIHOLE_DNS_2", "value": "8.8.4.4", "default": "" }
+                ],
+                "ftl": [
+                    { "key": "PRIVACYLEVEL", "value": "2", "default": "0" }
+                ]
+            }))
+            .test();
+    }
+}
@@ -10,9 +10,12 @@
 
 use crate::{
     env::Env,
-    routes::{auth::User, settings::common::restart_dns},
+    routes::{
+        auth::User,
+        settings::{common::restart_dns, lint::reply_success_with_warnings}
+    },
     settings::{generate_dnsmasq_config, ConfigEntry, SetupVarsEntry},
-    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+    util::{reply_data, Error, ErrorKind, Reply}
 };
 use rocket::State;
 use rocket_contrib::json::Json;
@@ -56,7 +59,26 @@ impl DnsOptions {
 pub struct DnsConditionalForwarding {
     enabled: bool,
     router_ip: String,
-    domain: String
+    domain: String,
+    /// Additional domains to forward to their own servers. These are used
+    /// even if `enabled` is false.
+    #[serde(default)]
+    rules: Vec<DnsForwardingRule>
+}
+
+/// A conditional forwarding rule, which forwards queries for the domain to
+/// the server
+#[derive(Serialize, Deserialize)]
+pub struct DnsForwardingRule {
+    domain: String,
+    server: String
+}
+
+impl DnsForwardingRule {
+    /// Get the value of the rule's `SetupVarsEntry::ConditionalForwardingRule`
+    fn entry_value(&self) -> String {
+        format!("{},{}", self.domain, self.server)
+    }
 }
 
 impl DnsConditionalForwarding {
@@ -71,6 +93,11 @@ impl DnsConditionalForwarding {
         // a boolean
         SetupVarsEntry::DhcpRouter.is_valid(&self.router_ip)
             && SetupVarsEntry::ConditionalForwardingDomain.is_valid(&self.domain)
+            && self.rules.iter().all(|rule| {
+                !rule.domain.is_empty()
+                    && !rule.server.is_empty()
+                    && SetupVarsEntry::ConditionalForwardingRule(0).is_valid(&rule.entry_value())
+            })
     }
 }
 
@@ -91,6 +118,27 @@ fn get_upstream_dns(env: &State<Env>) -> Result<Vec<String>, Error> {
     Ok(upstream_dns)
 }
 
+/// Get the conditional forwarding rules
+fn get_forwarding_rules(env: &State<Env>) -> Result<Vec<DnsForwardingRule>, Error> {
+    let mut rules = Vec::new();
+
+    for num in 1.. {
+        let rule = SetupVarsEntry::ConditionalForwardingRule(num).read(&env)?;
+
+        if rule.is_empty() {
+            break;
+        }
+
+        let mut split = rule.splitn(2, ',');
+        rules.push(DnsForwardingRule {
+            domain: split.next().unwrap_or_default().to_owned(),
+            server: split.next().unwrap_or_default().to_owned()
+        });
+    }
+
+    Ok(rules)
+}
+
 /// Get DNS Configuration
 #[get("/settings/dns")]
 pub fn get_dns(env: State<Env>, _auth: User) -> Reply {
@@ -105,7 +153,8 @@ pub fn get_dns(env: State<Env>, _auth: User) -> Reply {
         conditional_forwarding: DnsConditionalForwarding {
             enabled: SetupVarsEntry::ConditionalForwarding.is_true(&env)?,
             router_ip: SetupVarsEntry::ConditionalForwardingIp.read(&env)?,
-            domain: SetupVarsEntry::ConditionalForwardingDomain.read(&env)?
+            domain: SetupVarsEntry::ConditionalForwardingDomain.read(&env)?,
+            rules: get_forwarding_rules(&env)?
         }
     };
 
@@ -160,9 +209,16 @@ pub fn put_dns(env: State<Env>, _auth: User, data: Json<DnsSettings>) -> Reply {
         SetupVarsEntry::ConditionalForwardingDomain.delete(&env)?;
     }
 
+    // Replace the previous conditional forwarding rules
+    SetupVarsEntry::delete_forwarding_rules(&env)?;
+
+    for (i, rule) in settings.conditional_forwarding.rules.iter().enumerate() {
+        SetupVarsEntry::ConditionalForwardingRule(i + 1).write(&rule.entry_value(), &env)?;
+    }
+
     generate_dnsmasq_config(&env)?;
     restart_dns(&env)?;
-    reply_success()
+    reply_success_with_warnings(&env)
 }
 
 #[cfg(test)]
@@ -192,13 +248,17 @@ mod test {
                  CONDITIONAL_FORWARDING=true\n\
                  CONDITIONAL_FORWARDING_IP=192.168.1.1\n\
                  CONDITIONAL_FORWARDING_DOMAIN=hub\n\
-                 CONDITIONAL_FORWARDING_REVERSE=1.168.192.in-addr.arpa\n"
+                 CONDITIONAL_FORWARDING_REVERSE=1.168.192.in-addr.arpa\n\
+                 CONDITIONAL_FORWARDING_RULE_1=corp,10.0.0.1\n"
             )
             .expect_json(json!({
                 "conditional_forwarding": {
                     "domain": "hub",
                     "enabled": true,
-                    "router_ip": "192.168.1.1"
+                    "router_ip": "192.168.1.1",
+                    "rules": [
+                        { "domain": "corp", "server": "10.0.0.1" }
+                    ]
                 },
                 "options": {
                     "bogus_priv": true,
@@ -230,7 +290,8 @@ mod test {
                 "conditional_forwarding": {
                     "domain": "",
                     "enabled": false,
-                    "router_ip": ""
+                    "router_ip": "",
+                    "rules": []
                 },
                 "options": {
                     "bogus_priv": true,
@@ -261,17 +322,19 @@ mod test {
                 CONDITIONAL_FORWARDING=true\n\
                 CONDITIONAL_FORWARDING_REVERSE=1.168.192.in-addr.arpa\n\
                 CONDITIONAL_FORWARDING_IP=192.168.1.1\n\
-                CONDITIONAL_FORWARDING_DOMAIN=local\n"
+                CONDITIONAL_FORWARDING_DOMAIN=local\n\
+                CONDITIONAL_FORWARDING_RULE_1=corp,10.0.0.1\n"
             )
             .file_expect(
                 PiholeFile::DnsmasqConfig,
                 "",
-                "################################################################\n\
-                    #       THIS FILE IS AUTOMATICALLY GENERATED BY PI-HOLE.       #\n\
-                    #          ANY CHANGES MADE TO THIS FILE WILL BE LOST.         #\n\
+                "#### BEGIN PI-HOLE MANAGED BLOCK ####\n\
+                    ################################################################\n\
+                    #      THIS BLOCK IS AUTOMATICALLY GENERATED BY PI-HOLE.       #\n\
+                    #       ANY CHANGES MADE INSIDE THIS BLOCK WILL BE LOST.       #\n\
                     #                                                              #\n\
-                    #  NEW CONFIG SETTINGS MUST BE MADE IN A SEPARATE CONFIG FILE  #\n\
-                    #                OR IN /etc/dnsmasq.conf                       #\n\
+                    #     YOUR OWN CONFIG SETTINGS CAN BE ADDED ABOVE OR BELOW     #\n\
+                    #        THIS BLOCK, AND ARE KEPT WHEN IT IS REWRITTEN         #\n\
                     ################################################################\n\
                     \n\
                     localise-queries\n\
@@ -282,6 +345,7 @@ mod test {
                     addn-hosts=/etc/pihole/gravity.list\n\
                     addn-hosts=/etc/pihole/black.list\n\
                     addn-hosts=/etc/pihole/local.list\n\
+                    addn-hosts=/etc/pihole/custom.list\n\
                     domain-needed\n\
                     bogus-priv\n\
                     dnssec\n\
@@ -289,7 +353,9 @@ mod test {
                     trust-anchor=.,20326,8,2,E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D\n\
                     local-service\n\
                     server=/local/192.168.1.1\n\
-                    server=/1.168.192.in-addr.arpa/192.168.1.1\n"
+                    server=/1.168.192.in-addr.arpa/192.168.1.1\n\
+                    server=/corp/10.0.0.1\n\
+                    #### END PI-HOLE MANAGED BLOCK ####\n"
             )
             .body(json!({
                 "upstream_dns": [
@@ -298,7 +364,10 @@ mod test {
                 "conditional_forwarding": {
                     "domain": "local",
                     "enabled": true,
-                    "router_ip": "192.168.1.1"
+                    "router_ip": "192.168.1.1",
+                    "rules": [
+                        { "domain": "corp", "server": "10.0.0.1" }
+                    ]
                 },
                 "options": {
                     "bogus_priv": true,
@@ -308,7 +377,8 @@ mod test {
                 }
             }))
             .expect_json(json!({
-                "status": "success"
+                "status": "success",
+                "warnings": []
             }))
             .test();
     }
@@ -0,0 +1,47 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// API Settings - Request Log
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    routes::auth::User,
+    services::RequestLogger,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// The number of entries returned if no limit is given
+const DEFAULT_LIMIT: usize = 100;
+
+/// Get the most recent requests the API handled, newest first, along with
+/// the configured log level
+#[get("/settings/api/logs?<limit>")]
+pub fn get_api_logs(_auth: User, logger: State<RequestLogger>, limit: Option<usize>) -> Reply {
+    reply_data(json!({
+        "level": logger.level().get_name(),
+        "entries": logger.recent(limit.unwrap_or(DEFAULT_LIMIT))
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+
+    /// The request for the log is not in the log, because it is recorded
+    /// after it is answered
+    #[test]
+    fn no_entries() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/api/logs")
+            .expect_json(json!({
+                "level": "info",
+                "entries": []
+            }))
+            .test();
+    }
+}
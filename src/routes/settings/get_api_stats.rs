@@ -0,0 +1,49 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// API Settings - Request Statistics
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    metrics::{render_metrics, RequestStats},
+    routes::auth::User,
+    util::{reply_data, Reply}
+};
+use rocket::{response::content, State};
+
+/// Get the API's request throughput, latency, and worker saturation, along
+/// with the configured server limits
+#[get("/settings/api/stats")]
+pub fn get_api_stats(_auth: User, stats: State<RequestStats>, env: State<Env>) -> Reply {
+    let limits = env.config().limits();
+
+    reply_data(json!({
+        "requests": stats.reply(),
+        "config": {
+            "workers": env.config().workers(),
+            "keep_alive": env.config().keep_alive(),
+            "limits": {
+                "forms": limits.get("forms"),
+                "json": limits.get("json")
+            }
+        }
+    }))
+}
+
+/// Get the request statistics in the Prometheus text format, including the
+/// latency and shared memory lock hold histograms of each route. Prometheus
+/// can authenticate by sending the API key, or a named API key, as a bearer
+/// token.
+#[get("/settings/api/metrics")]
+pub fn get_api_metrics(_auth: User, stats: State<RequestStats>) -> content::Plain<String> {
+    content::Plain(render_metrics(
+        &stats.reply(),
+        &stats.route_histograms(),
+        &stats.lock_hold_histograms()
+    ))
+}
@@ -0,0 +1,104 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Counters Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::FtlMemory,
+    routes::auth::User,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// Get FTL's raw counters, and for each table the number of valid entries
+/// compared to its capacity and allocated size. This is used for debugging
+/// differences between the dashboard and FTL.
+#[get("/settings/ftl/counters")]
+pub fn get_ftl_counters(_auth: User, ftl_memory: State<FtlMemory>) -> Reply {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let upstreams = ftl_memory.upstreams(&lock)?;
+
+    reply_data(json!({
+        "counters": **counters,
+        "tables": {
+            "queries": {
+                "valid": counters.total_queries,
+                "capacity": counters.query_capacity,
+                "allocated": queries.len()
+            },
+            "domains": {
+                "valid": counters.total_domains,
+                "capacity": counters.domain_capacity,
+                "allocated": domains.len()
+            },
+            "clients": {
+                "valid": counters.total_clients,
+                "capacity": counters.client_capacity,
+                "allocated": clients.len()
+            },
+            "upstreams": {
+                "valid": counters.total_upstreams,
+                "capacity": counters.upstream_capacity,
+                "allocated": upstreams.len()
+            },
+            "strings": {
+                "capacity": counters.string_capacity
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{routes::stats::testing::test_memory, testing::TestBuilder};
+
+    /// The counters and table sizes are reported
+    #[test]
+    fn counters() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/ftl/counters")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "counters": {
+                    "total_queries": 9,
+                    "blocked_queries": 0,
+                    "cached_queries": 0,
+                    "unknown_queries": 0,
+                    "total_upstreams": 2,
+                    "total_clients": 4,
+                    "total_domains": 6,
+                    "query_capacity": 0,
+                    "upstream_capacity": 0,
+                    "client_capacity": 0,
+                    "domain_capacity": 0,
+                    "string_capacity": 0,
+                    "gravity_size": 0,
+                    "gravity_conf": 0,
+                    "query_type_counters": [0, 0, 0, 0, 0, 0, 0],
+                    "forwarded_queries": 0,
+                    "reply_count_nodata": 0,
+                    "reply_count_nxdomain": 0,
+                    "reply_count_cname": 0,
+                    "reply_count_ip": 0,
+                    "reply_count_domain": 0
+                },
+                "tables": {
+                    "queries": { "valid": 9, "capacity": 0, "allocated": 9 },
+                    "domains": { "valid": 6, "capacity": 0, "allocated": 6 },
+                    "clients": { "valid": 4, "capacity": 0, "allocated": 4 },
+                    "upstreams": { "valid": 2, "capacity": 0, "allocated": 2 },
+                    "strings": { "capacity": 0 }
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,175 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// FTL Shared Memory Usage Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::{FtlClient, FtlDomain, FtlMemory, FtlQuery, FtlUpstream},
+    routes::auth::User,
+    settings::{ConfigEntry, FtlConfEntry},
+    util::{current_time, reply_data, Error, Reply}
+};
+use rocket::State;
+use std::mem::size_of;
+
+/// Get the size of FTL's shared memory segments, how much of each is used,
+/// and how many queries are expected in the `MAXLOGAGE` window at the current
+/// query rate. This helps to choose `MAXLOGAGE`.
+#[get("/settings/ftl/memory")]
+pub fn get_ftl_memory(_auth: User, env: State<Env>, ftl_memory: State<FtlMemory>) -> Reply {
+    reply_data(memory_usage(&env, &ftl_memory, current_time())?)
+}
+
+/// The usage of a shared memory table
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct TableUsage {
+    /// The number of slots in use
+    pub used: usize,
+    /// The number of slots FTL has allocated
+    pub allocated: usize,
+    /// The size of the segment, in bytes
+    pub bytes: usize
+}
+
+impl TableUsage {
+    /// Get the usage of a table of `T`
+    fn of<T>(used: i32, allocated: usize) -> Self {
+        TableUsage {
+            used: used.max(0) as usize,
+            allocated,
+            bytes: allocated * size_of::<T>()
+        }
+    }
+}
+
+/// The in-memory window of queries, and how it is expected to fill at the
+/// current query rate
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct QueryWindow {
+    /// `MAXLOGAGE`, in hours
+    pub max_log_age: f64,
+    /// The timestamp of the oldest query in memory
+    pub oldest_query: Option<u64>,
+    pub queries_per_hour: f64,
+    /// The number of queries expected in memory once the window is full
+    pub projected_queries: u64,
+    /// The number of seconds until the allocated query slots are used up, if
+    /// FTL does not have to grow the segment first
+    pub full_in: Option<u64>
+}
+
+/// The reply of the memory usage endpoint
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct MemoryUsageReply {
+    pub queries: TableUsage,
+    pub domains: TableUsage,
+    pub clients: TableUsage,
+    pub upstreams: TableUsage,
+    pub strings: TableUsage,
+    pub window: QueryWindow
+}
+
+/// Get the memory usage at the time `now`
+fn memory_usage(env: &Env, ftl_memory: &FtlMemory, now: u64) -> Result<MemoryUsageReply, Error> {
+    let max_log_age: f64 = FtlConfEntry::MaxLogAge.read_as(env)?;
+
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let settings = ftl_memory.settings(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let upstreams = ftl_memory.upstreams(&lock)?;
+
+    let total_queries = counters.total_queries.max(0) as usize;
+    let oldest_query = queries
+        .iter()
+        .take(total_queries)
+        .map(|query| query.timestamp as u64)
+        .min();
+
+    // The query rate is measured over the time the queries in memory span
+    let queries_per_hour = match oldest_query {
+        Some(oldest) => total_queries as f64 * 3600.0 / now.saturating_sub(oldest).max(1) as f64,
+        None => 0.0
+    };
+    let free_slots = queries.len().saturating_sub(total_queries);
+
+    Ok(MemoryUsageReply {
+        queries: TableUsage::of::<FtlQuery>(counters.total_queries, queries.len()),
+        domains: TableUsage::of::<FtlDomain>(counters.total_domains, domains.len()),
+        clients: TableUsage::of::<FtlClient>(counters.total_clients, clients.len()),
+        upstreams: TableUsage::of::<FtlUpstream>(counters.total_upstreams, upstreams.len()),
+        strings: TableUsage::of::<libc::c_char>(
+            settings.next_str_pos as i32,
+            counters.string_capacity.max(0) as usize
+        ),
+        window: QueryWindow {
+            max_log_age,
+            oldest_query,
+            queries_per_hour,
+            projected_queries: (queries_per_hour * max_log_age).round() as u64,
+            full_in: if queries_per_hour > 0.0 {
+                Some((free_slots as f64 * 3600.0 / queries_per_hour) as u64)
+            } else {
+                None
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{memory_usage, QueryWindow, TableUsage};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::FtlQuery,
+        routes::stats::testing::test_memory,
+        testing::TestEnvBuilder
+    };
+    use std::mem::size_of;
+
+    /// The used and allocated slots are reported for each table, and the
+    /// query rate is measured since the oldest query
+    #[test]
+    fn usage() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::FtlConfig, "MAXLOGAGE=2\n")
+                .build()
+        );
+
+        let usage = memory_usage(&env, &test_memory(), 263_581 + 3600).unwrap();
+
+        assert_eq!(
+            usage.queries,
+            TableUsage {
+                used: 9,
+                allocated: 9,
+                bytes: 9 * size_of::<FtlQuery>()
+            }
+        );
+        assert_eq!(usage.domains.used, 6);
+        assert_eq!(usage.clients.allocated, 4);
+        assert_eq!(
+            usage.window,
+            QueryWindow {
+                max_log_age: 2.0,
+                oldest_query: Some(263_581),
+                queries_per_hour: 9.0,
+                projected_queries: 18,
+                full_in: Some(0)
+            }
+        );
+    }
+}
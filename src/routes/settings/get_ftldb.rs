@@ -23,8 +23,7 @@ pub fn get_ftldb(ftl: State<FtlConnectionType>, _auth: User) -> Reply {
     // Read in FTL's database stats
     let db_queries = con.read_i32()?;
     let db_filesize = con.read_i64()?;
-    let mut version_buffer = [0u8; 64];
-    let db_sqlite_version = con.read_str(&mut version_buffer)?;
+    let db_sqlite_version = con.read_string()?;
     con.expect_eom()?;
 
     reply_data(json!({
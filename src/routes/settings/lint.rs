@@ -0,0 +1,58 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Settings Conflict Warnings
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    settings::lint_settings,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// Get warnings about settings which conflict with each other
+#[get("/settings/lint")]
+pub fn get_settings_lint(env: State<Env>, _auth: User) -> Reply {
+    reply_data(json!({ "warnings": lint_settings(&env)? }))
+}
+
+/// Reply with success after settings were changed, including warnings about
+/// any conflicts the new settings have with the other settings
+pub fn reply_success_with_warnings(env: &Env) -> Reply {
+    reply_data(json!({
+        "status": "success",
+        "warnings": lint_settings(env)?
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+
+    /// Conflicting settings are reported with the rule they break
+    #[test]
+    fn get_warnings() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/lint")
+            .file(
+                PiholeFile::SetupVars,
+                "CONDITIONAL_FORWARDING=true\n\
+                 CONDITIONAL_FORWARDING_DOMAIN=lan\n"
+            )
+            .expect_json(json!({
+                "warnings": [
+                    {
+                        "rule": "conditional_forwarding_ip",
+                        "message": "Conditional forwarding is enabled, but the router IP is not set"
+                    }
+                ]
+            }))
+            .test();
+    }
+}
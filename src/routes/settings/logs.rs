@@ -0,0 +1,81 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Log Rotation Settings Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    log_rotation::{log_files, LogSettings},
+    routes::auth::User,
+    util::{reply_data, reply_success, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the log rotation settings and the logs written by the API
+#[get("/settings/logs")]
+pub fn get_logs(_auth: User, env: State<Env>) -> Reply {
+    reply_data(json!({
+        "settings": LogSettings::read(&env)?,
+        "logs": log_files(env.config())
+    }))
+}
+
+/// Update the log rotation settings
+#[put("/settings/logs", data = "<settings>")]
+pub fn put_logs(_auth: User, env: State<Env>, settings: Json<LogSettings>) -> Reply {
+    settings.into_inner().write(&env)?;
+
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::Method;
+
+    /// The defaults are used if the settings are not set, and there is no
+    /// request log by default
+    #[test]
+    fn get_defaults() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/logs")
+            .file(PiholeFile::SetupVars, "")
+            .expect_json(json!({
+                "settings": {
+                    "max_size": 1024,
+                    "max_age": 7,
+                    "retention": 3
+                },
+                "logs": []
+            }))
+            .test();
+    }
+
+    /// The settings are written to setupVars.conf
+    #[test]
+    fn put_settings() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/logs")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "API_LOG_MAX_SIZE=512\n\
+                 API_LOG_MAX_AGE=0\n\
+                 API_LOG_RETENTION=1\n"
+            )
+            .body(json!({
+                "max_size": 512,
+                "max_age": 0,
+                "retention": 1
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
@@ -8,12 +8,36 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+mod all;
+mod batch;
+mod clients;
 mod common;
+mod custom_dns;
 mod dhcp;
+mod diff;
 mod dns;
+mod get_api_logs;
+mod get_api_stats;
 mod get_ftl;
+mod get_ftl_counters;
+mod get_ftl_memory;
 mod get_ftldb;
 mod get_network;
+mod lint;
+mod logs;
+mod nicknames;
+mod noise_domains;
+mod notifications;
+mod privacy;
+mod schedule;
+mod subnets;
+mod teleporter;
+mod time;
 mod web;
 
-pub use self::{common::*, dhcp::*, dns::*, get_ftl::*, get_ftldb::*, get_network::*, web::*};
+pub use self::{
+    all::*, batch::*, clients::*, common::*, custom_dns::*, dhcp::*, diff::*, dns::*,
+    get_api_logs::*, get_api_stats::*, get_ftl::*, get_ftl_counters::*, get_ftl_memory::*,
+    get_ftldb::*, get_network::*, lint::*, logs::*, nicknames::*, noise_domains::*,
+    notifications::*, privacy::*, schedule::*, subnets::*, teleporter::*, time::*, web::*
+};
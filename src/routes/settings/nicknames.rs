@@ -0,0 +1,127 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Device Nickname Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    client_nicknames::{write_nickname, ClientNicknames},
+    databases::ftl::FtlDatabase,
+    env::Env,
+    metrics::time_database,
+    routes::auth::User,
+    util::{reply_success, Error, ErrorKind, Reply}
+};
+use regex::Regex;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// The maximum length of a nickname
+const MAX_NICKNAME_LENGTH: usize = 64;
+
+/// Set the nickname of a network device. An empty name removes the nickname.
+#[put("/settings/network/devices/<mac>/name", data = "<device_name>")]
+pub fn put_device_name(
+    _auth: User,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>,
+    db: FtlDatabase,
+    mac: String,
+    device_name: Json<DeviceName>
+) -> Reply {
+    let name = device_name.name.trim();
+
+    if !is_valid_mac(&mac) || !is_valid_nickname(name) {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    write_nickname(&env, &mac, if name.is_empty() { None } else { Some(name) })?;
+    time_database(|| nicknames.reload(&env, &db))?;
+
+    reply_success()
+}
+
+/// Check if the MAC address is valid
+fn is_valid_mac(mac: &str) -> bool {
+    Regex::new(r"^([0-9a-fA-F]{2}:){5}[0-9a-fA-F]{2}$")
+        .unwrap()
+        .is_match(mac)
+}
+
+/// Check if the nickname is valid. Nicknames are saved one per line and are
+/// followed by a tab if there is a comment, so they can not contain line
+/// breaks or tabs.
+pub fn is_valid_nickname(name: &str) -> bool {
+    name.chars().count() <= MAX_NICKNAME_LENGTH
+        && !name.contains(|c| c == '\n' || c == '\r' || c == '\t')
+}
+
+#[derive(Deserialize)]
+pub struct DeviceName {
+    name: String
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The nickname is saved by lowercase MAC address
+    #[test]
+    fn set_nickname() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/network/devices/AA:BB:CC:DD:EE:FF/name")
+            .method(Method::Put)
+            .need_database(true)
+            .file_expect(
+                PiholeFile::ClientNicknames,
+                "00:11:22:33:44:55 Laptop\n",
+                "00:11:22:33:44:55 Laptop\n\
+                 aa:bb:cc:dd:ee:ff Living Room TV\n"
+            )
+            .body(json!({ "name": "Living Room TV" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An empty name removes the nickname
+    #[test]
+    fn remove_nickname() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/network/devices/00:11:22:33:44:55/name")
+            .method(Method::Put)
+            .need_database(true)
+            .file_expect(
+                PiholeFile::ClientNicknames,
+                "00:11:22:33:44:55 Laptop\n",
+                ""
+            )
+            .body(json!({ "name": "" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An invalid MAC address is rejected
+    #[test]
+    fn invalid_mac() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/network/devices/not-a-mac/name")
+            .method(Method::Put)
+            .need_database(true)
+            .file(PiholeFile::ClientNicknames, "")
+            .body(json!({ "name": "Laptop" }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+}
@@ -0,0 +1,99 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Noise Domain Settings Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    settings::{ConfigEntry, SetupVarsEntry, DEFAULT_NOISE_DOMAINS},
+    util::{reply_data, reply_success, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the noise domains hidden by `hide_noise`. The default noise domains
+/// can not be changed, but more domains can be added.
+#[get("/settings/noise_domains")]
+pub fn get_noise_domains(_auth: User, env: State<Env>) -> Reply {
+    reply_data(json!({
+        "defaults": DEFAULT_NOISE_DOMAINS,
+        "domains": SetupVarsEntry::ApiNoiseDomains.read_list(&env)?
+    }))
+}
+
+/// Update the noise domains added to the defaults
+#[put("/settings/noise_domains", data = "<settings>")]
+pub fn put_noise_domains(
+    _auth: User,
+    env: State<Env>,
+    settings: Json<NoiseDomainSettings>
+) -> Reply {
+    SetupVarsEntry::ApiNoiseDomains.write(&settings.domains.join(","), &env)?;
+
+    reply_success()
+}
+
+#[derive(Deserialize)]
+pub struct NoiseDomainSettings {
+    domains: Vec<String>
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, settings::DEFAULT_NOISE_DOMAINS, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The default and added noise domains are returned
+    #[test]
+    fn get_noise_domains() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/noise_domains")
+            .file(PiholeFile::SetupVars, "API_NOISE_DOMAINS=example.com\n")
+            .expect_json(json!({
+                "defaults": DEFAULT_NOISE_DOMAINS,
+                "domains": ["example.com"]
+            }))
+            .test();
+    }
+
+    /// The added noise domains are written to setupVars.conf
+    #[test]
+    fn put_noise_domains() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/noise_domains")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "API_NOISE_DOMAINS=example.com,example.net\n"
+            )
+            .body(json!({ "domains": ["example.com", "example.net"] }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Noise domains must be valid domains
+    #[test]
+    fn put_invalid_domain() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/noise_domains")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({ "domains": ["not a domain"] }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+}
@@ -0,0 +1,228 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Notification Settings Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    alert_thresholds::ThresholdRule,
+    env::Env,
+    notifications::Notifier,
+    routes::auth::User,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the notification settings
+#[get("/settings/notifications")]
+pub fn get_notifications(_auth: User, env: State<Env>) -> Reply {
+    let settings = NotificationSettings {
+        new_login: SetupVarsEntry::ApiNotifyNewLogin.is_true(&env)?,
+        new_client: SetupVarsEntry::ApiNotifyNewClient.is_true(&env)?,
+        threat: SetupVarsEntry::ApiNotifyThreat.is_true(&env)?,
+        webhook: SetupVarsEntry::ApiNotifyWebhook.read(&env)?
+    };
+
+    reply_data(settings)
+}
+
+/// Update the notification settings
+#[put("/settings/notifications", data = "<settings>")]
+pub fn put_notifications(
+    _auth: User,
+    env: State<Env>,
+    settings: Json<NotificationSettings>
+) -> Reply {
+    let settings = settings.into_inner();
+
+    if !settings.is_valid() {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    SetupVarsEntry::ApiNotifyNewLogin.write(&settings.new_login.to_string(), &env)?;
+    SetupVarsEntry::ApiNotifyNewClient.write(&settings.new_client.to_string(), &env)?;
+    SetupVarsEntry::ApiNotifyThreat.write(&settings.threat.to_string(), &env)?;
+    SetupVarsEntry::ApiNotifyWebhook.write(&settings.webhook, &env)?;
+
+    reply_success()
+}
+
+/// Get the recent login, new client, and blocked threat alerts
+#[get("/settings/notifications/alerts")]
+pub fn get_alerts(_auth: User, notifier: State<Notifier>) -> Reply {
+    reply_data(notifier.alerts())
+}
+
+/// Get the threshold rules, such as "load above 2 for 5 minutes"
+#[get("/settings/notifications/thresholds")]
+pub fn get_thresholds(_auth: User, env: State<Env>) -> Reply {
+    reply_data(ThresholdRule::read_all(&env)?)
+}
+
+/// Replace the threshold rules. The rules are validated, and the error
+/// describes the first invalid rule.
+#[put("/settings/notifications/thresholds", data = "<rules>")]
+pub fn put_thresholds(_auth: User, env: State<Env>, rules: Json<Vec<ThresholdRule>>) -> Reply {
+    ThresholdRule::write_all(&env, &rules.into_inner())?;
+
+    reply_success()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NotificationSettings {
+    new_login: bool,
+    new_client: bool,
+    /// Notify when a domain in the threat feed is blocked
+    #[serde(default)]
+    threat: bool,
+    webhook: String
+}
+
+impl NotificationSettings {
+    /// Check if all the notification settings are valid
+    fn is_valid(&self) -> bool {
+        SetupVarsEntry::ApiNotifyWebhook.is_valid(&self.webhook)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The defaults are used if the settings are not set
+    #[test]
+    fn get_defaults() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications")
+            .file(PiholeFile::SetupVars, "")
+            .expect_json(json!({
+                "new_login": false,
+                "new_client": false,
+                "threat": false,
+                "webhook": ""
+            }))
+            .test();
+    }
+
+    /// The settings are written to setupVars.conf
+    #[test]
+    fn put_settings() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "API_NOTIFY_NEW_LOGIN=true\n\
+                 API_NOTIFY_NEW_CLIENT=false\n\
+                 API_NOTIFY_THREAT=true\n\
+                 API_NOTIFY_WEBHOOK=https://example.com/hook\n"
+            )
+            .body(json!({
+                "new_login": true,
+                "new_client": false,
+                "threat": true,
+                "webhook": "https://example.com/hook"
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An invalid webhook URL is rejected
+    #[test]
+    fn put_invalid_webhook() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({
+                "new_login": true,
+                "new_client": true,
+                "webhook": "not a url"
+            }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+
+    /// The saved threshold rules are returned
+    #[test]
+    fn get_thresholds() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications/thresholds")
+            .file(
+                PiholeFile::AlertRules,
+                "[{\"metric\":\"load\",\"above\":2.0,\"clear_below\":1.5,\"duration\":300}]\n"
+            )
+            .expect_json(json!([{
+                "metric": "load",
+                "above": 2.0,
+                "clear_below": 1.5,
+                "duration": 300
+            }]))
+            .test();
+    }
+
+    /// Valid threshold rules are saved, with the defaults filled in
+    #[test]
+    fn put_thresholds() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications/thresholds")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::AlertRules,
+                "",
+                "[{\"metric\":\"temperature\",\"above\":70.0,\"clear_below\":null,\"duration\":0}]\n"
+            )
+            .body(json!([{ "metric": "temperature", "above": 70.0 }]))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// An invalid threshold rule is rejected with the reason
+    #[test]
+    fn put_invalid_threshold() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications/thresholds")
+            .method(Method::Put)
+            .file(PiholeFile::AlertRules, "")
+            .body(json!([
+                { "metric": "load", "above": 2.0 },
+                { "metric": "load", "above": 2.0, "clear_below": 3.0 }
+            ]))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_alert_rule",
+                    "message": "Invalid alert rule: rule 2: clear_below (3) must not be more \
+                                than above (2)",
+                    "data": {
+                        "reason": "rule 2: clear_below (3) must not be more than above (2)"
+                    }
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+
+    /// No alerts have been raised yet
+    #[test]
+    fn get_alerts_empty() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/notifications/alerts")
+            .expect_json(json!([]))
+            .test();
+    }
+}
@@ -0,0 +1,122 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Privacy Settings Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    query_purge::{read_retentions, write_retentions, QueryPurge},
+    routes::auth::User,
+    settings::{ClientRetention, ConfigEntry, FtlConfEntry},
+    util::{reply_data, reply_success, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the privacy settings. `client_retention` lists clients whose queries
+/// are deleted from the database sooner than `max_db_days`, by a purge which
+/// runs every night. Each client is an IP address or a subnet, and its
+/// retention is in hours.
+#[get("/settings/privacy")]
+pub fn get_privacy(_auth: User, env: State<Env>, purge: State<QueryPurge>) -> Reply {
+    let privacy_level: i32 = FtlConfEntry::PrivacyLevel.read_as(&env)?;
+    let max_db_days: i32 = FtlConfEntry::MaxDbDays.read_as(&env)?;
+
+    reply_data(json!({
+        "privacy_level": privacy_level,
+        "max_db_days": max_db_days,
+        "client_retention": read_retentions(&env)?,
+        "purge": purge.status()
+    }))
+}
+
+/// Update the client retentions. If more than one retention covers a client,
+/// the shortest is used.
+#[put("/settings/privacy", data = "<settings>")]
+pub fn put_privacy(_auth: User, env: State<Env>, settings: Json<PrivacySettings>) -> Reply {
+    write_retentions(&env, &settings.client_retention)?;
+
+    reply_success()
+}
+
+#[derive(Deserialize)]
+pub struct PrivacySettings {
+    client_retention: Vec<ClientRetention>
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The privacy settings and the status of the purge are returned
+    #[test]
+    fn get_privacy() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/privacy")
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=1\nMAXDBDAYS=30\n")
+            .file(
+                PiholeFile::SetupVars,
+                "API_CLIENT_RETENTION=10.0.1.0/24@24,10.0.0.5@72\n"
+            )
+            .expect_json(json!({
+                "privacy_level": 1,
+                "max_db_days": 30,
+                "client_retention": [
+                    { "client": "10.0.1.0/24", "hours": 24 },
+                    { "client": "10.0.0.5", "hours": 72 }
+                ],
+                "purge": {
+                    "last_purge": null,
+                    "deleted": 0,
+                    "last_status": null
+                }
+            }))
+            .test();
+    }
+
+    /// The client retentions are written to setupVars.conf
+    #[test]
+    fn put_privacy() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/privacy")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "API_CLIENT_RETENTION=10.0.1.0/24@24,fd00::5@48\n"
+            )
+            .body(json!({
+                "client_retention": [
+                    { "client": "10.0.1.0/24", "hours": 24 },
+                    { "client": "fd00::5", "hours": 48 }
+                ]
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Retentions must have a valid client and at least one hour
+    #[test]
+    fn put_invalid_retention() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/privacy")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({ "client_retention": [{ "client": "laptop", "hours": 24 }] }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+}
@@ -0,0 +1,156 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Scheduled Gravity Update Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    gravity_schedule::{GravitySchedule, ScheduleSettings, MAX_FREQUENCY, MAX_JITTER},
+    routes::auth::User,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the scheduled Gravity update settings and status
+#[get("/settings/schedule/gravity")]
+pub fn get_gravity_schedule(
+    _auth: User,
+    env: State<Env>,
+    schedule: State<GravitySchedule>
+) -> Reply {
+    let settings = ScheduleSettings::read(&env)?;
+    let status = schedule.status(&settings);
+
+    reply_data(json!({
+        "settings": settings,
+        "status": status
+    }))
+}
+
+/// Update the scheduled Gravity update settings
+#[put("/settings/schedule/gravity", data = "<settings>")]
+pub fn put_gravity_schedule(
+    _auth: User,
+    env: State<Env>,
+    settings: Json<ScheduleSettings>
+) -> Reply {
+    let settings = settings.into_inner();
+
+    // Limit the jitter so that it can not delay an update past the next one.
+    // The frequency is checked first, so the jitter's limit can not overflow.
+    if settings.frequency > MAX_FREQUENCY
+        || settings.jitter > MAX_JITTER
+        || (settings.frequency > 0 && settings.jitter > settings.frequency * 60)
+    {
+        return Err(Error::from(ErrorKind::InvalidSettingValue));
+    }
+
+    settings.write(&env)?;
+
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The defaults are used if the settings are not set, and the schedule has
+    /// not started during tests
+    #[test]
+    fn get_defaults() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/schedule/gravity")
+            .file(PiholeFile::SetupVars, "")
+            .expect_json(json!({
+                "settings": {
+                    "frequency": 0,
+                    "jitter": 60,
+                    "skip_metered": true,
+                    "native_build": false
+                },
+                "status": {
+                    "last_update": null,
+                    "next_update": null,
+                    "last_status": null
+                }
+            }))
+            .test();
+    }
+
+    /// The settings are written to setupVars.conf
+    #[test]
+    fn put_settings() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/schedule/gravity")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "GRAVITY_UPDATE_FREQUENCY=24\n\
+                 GRAVITY_UPDATE_JITTER=30\n\
+                 GRAVITY_SKIP_METERED=false\n\
+                 GRAVITY_NATIVE_BUILD=true\n"
+            )
+            .body(json!({
+                "frequency": 24,
+                "jitter": 30,
+                "skip_metered": false,
+                "native_build": true
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Jitter longer than the time between updates is rejected
+    #[test]
+    fn put_jitter_too_long() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/schedule/gravity")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({
+                "frequency": 1,
+                "jitter": 61,
+                "skip_metered": true
+            }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+
+    /// A frequency longer than a year is rejected
+    #[test]
+    fn put_frequency_too_long() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/schedule/gravity")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({
+                "frequency": u64::max_value(),
+                "jitter": 0,
+                "skip_metered": true
+            }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+}
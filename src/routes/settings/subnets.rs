@@ -0,0 +1,93 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Subnet Settings Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{reply_data, reply_success, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Get the subnets which stats are grouped by
+#[get("/settings/subnets")]
+pub fn get_subnets(_auth: User, env: State<Env>) -> Reply {
+    reply_data(SubnetSettings {
+        subnets: SetupVarsEntry::ApiSubnets.read_list(&env)?
+    })
+}
+
+/// Update the subnets which stats are grouped by
+#[put("/settings/subnets", data = "<settings>")]
+pub fn put_subnets(_auth: User, env: State<Env>, settings: Json<SubnetSettings>) -> Reply {
+    SetupVarsEntry::ApiSubnets.write(&settings.subnets.join(","), &env)?;
+
+    reply_success()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SubnetSettings {
+    subnets: Vec<String>
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The subnets are read from setupVars.conf
+    #[test]
+    fn get_subnets() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/subnets")
+            .file(
+                PiholeFile::SetupVars,
+                "API_SUBNETS=192.168.1.0/24,fd00::/64\n"
+            )
+            .expect_json(json!({ "subnets": ["192.168.1.0/24", "fd00::/64"] }))
+            .test();
+    }
+
+    /// The subnets are written to setupVars.conf
+    #[test]
+    fn put_subnets() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/subnets")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SetupVars,
+                "",
+                "API_SUBNETS=192.168.1.0/24,10.0.0.0/8\n"
+            )
+            .body(json!({ "subnets": ["192.168.1.0/24", "10.0.0.0/8"] }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Subnets must be in CIDR notation
+    #[test]
+    fn put_invalid_subnet() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/subnets")
+            .method(Method::Put)
+            .file(PiholeFile::SetupVars, "")
+            .body(json!({ "subnets": ["192.168.1.0"] }))
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_setting_value",
+                    "message": "Invalid setting value",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::BadRequest)
+            .test();
+    }
+}
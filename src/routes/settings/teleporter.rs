@@ -0,0 +1,190 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Teleporter Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::{
+        auth::User,
+        dns::{GravityReloader, List, ListChanges},
+        settings::common::restart_dns
+    },
+    services::{export_archive, restore_archive, RestoreSummary, TeleporterSection},
+    settings::generate_dnsmasq_config,
+    util::{current_time, reply_data, Error, Reply, TarGzFile}
+};
+use rocket::{request::Form, Data, State};
+use std::io::Read;
+
+/// The largest archive which can be restored, in bytes
+const MAX_ARCHIVE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Download a backup of the settings, domain lists, adlists, and groups as a
+/// gzipped tar archive
+#[get("/settings/teleporter")]
+pub fn get_teleporter(_auth: User, env: State<Env>) -> Result<TarGzFile, Error> {
+    Ok(TarGzFile {
+        name: format!("pi-hole-teleporter_{}.tar.gz", current_time()),
+        data: export_archive(&env)?
+    })
+}
+
+/// Restore a backup made by the teleporter. The sections to restore can be
+/// chosen with the query parameters, and all of them are restored by default.
+#[post("/settings/teleporter?<flags..>", data = "<archive>")]
+pub fn post_teleporter(
+    _auth: User,
+    env: State<Env>,
+    reloader: State<GravityReloader>,
+    changes: State<ListChanges>,
+    flags: Form<RestoreFlags>,
+    archive: Data
+) -> Reply {
+    let summary = restore_archive(
+        &env,
+        archive.open().take(MAX_ARCHIVE_SIZE),
+        &flags.into_inner().sections()
+    )?;
+    let restored = |section| summary.sections.contains(&section);
+
+    if restored(TeleporterSection::Settings) {
+        generate_dnsmasq_config(&env)?;
+    }
+
+    // FTL reads the regex lists when the DNS server restarts
+    if restored(TeleporterSection::Settings) || restored(TeleporterSection::Lists) {
+        restart_dns(&env)?;
+    }
+
+    reset_list_changes(&changes, &summary);
+
+    if restored(TeleporterSection::Lists) {
+        reloader.request(List::White, &env);
+        reloader.request(List::Black, &env);
+    }
+
+    reply_data(summary)
+}
+
+/// If the lists were restored, tell sync clients to get them again. The lists
+/// were replaced without recording their changes.
+fn reset_list_changes(changes: &ListChanges, summary: &RestoreSummary) {
+    if summary.sections.contains(&TeleporterSection::Lists) {
+        for list in &List::ALL {
+            changes.reset(list);
+        }
+    }
+}
+
+/// Which sections of the backup to restore. Sections which are not given are
+/// restored.
+#[derive(FromForm, Default)]
+pub struct RestoreFlags {
+    settings: Option<bool>,
+    lists: Option<bool>,
+    adlists: Option<bool>,
+    groups: Option<bool>
+}
+
+impl RestoreFlags {
+    /// Get the sections to restore
+    fn sections(&self) -> Vec<TeleporterSection> {
+        [
+            (self.settings, TeleporterSection::Settings),
+            (self.lists, TeleporterSection::Lists),
+            (self.adlists, TeleporterSection::Adlists),
+            (self.groups, TeleporterSection::Groups)
+        ]
+        .iter()
+        .filter(|(flag, _)| flag.unwrap_or(true))
+        .map(|&(_, section)| section)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{reset_list_changes, RestoreFlags};
+    use crate::{
+        routes::dns::{ChangeAction, List, ListChanges},
+        services::{RestoreSummary, TeleporterSection},
+        testing::TestBuilder
+    };
+    use rocket::http::{Method, Status};
+
+    /// Sections are restored unless they are turned off
+    #[test]
+    fn flags() {
+        let flags = RestoreFlags {
+            lists: Some(false),
+            groups: Some(true),
+            ..RestoreFlags::default()
+        };
+
+        assert_eq!(
+            flags.sections(),
+            vec![
+                TeleporterSection::Settings,
+                TeleporterSection::Adlists,
+                TeleporterSection::Groups
+            ]
+        );
+    }
+
+    /// Restoring the lists makes sync clients do a full sync, and restoring
+    /// other sections does not
+    #[test]
+    fn restored_lists_need_full_sync() {
+        let changes = ListChanges::default();
+        let seq = changes.since(&List::White, 0).seq;
+        changes.record(&List::White, ChangeAction::Add, "example.com");
+
+        reset_list_changes(
+            &changes,
+            &RestoreSummary {
+                sections: vec![TeleporterSection::Settings],
+                files: vec!["setupVars.conf".to_owned()]
+            }
+        );
+
+        for list in &List::ALL {
+            assert!(!changes.since(list, seq).full_sync);
+        }
+
+        reset_list_changes(
+            &changes,
+            &RestoreSummary {
+                sections: vec![TeleporterSection::Lists],
+                files: vec!["whitelist.txt".to_owned()]
+            }
+        );
+
+        for list in &List::ALL {
+            assert!(changes.since(list, seq).full_sync);
+        }
+    }
+
+    /// Uploads which are not archives are rejected
+    #[test]
+    fn not_an_archive() {
+        TestBuilder::new()
+            .endpoint("/admin/api/settings/teleporter?groups=false")
+            .method(Method::Post)
+            .body(json!({ "settings": true }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
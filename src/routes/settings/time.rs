@@ -0,0 +1,98 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Time Settings Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::util::{reply_data, Reply};
+use std::{fs, mem, path::Path};
+
+/// The file containing the configured timezone name on Debian based systems
+const TIMEZONE_FILE: &str = "/etc/timezone";
+
+/// The symlink to the timezone data of the configured timezone
+const LOCALTIME_FILE: &str = "/etc/localtime";
+
+/// Get the server's timezone, its current offset from UTC in seconds, and the
+/// current time as a Unix timestamp
+#[get("/settings/time")]
+pub fn get_time() -> Reply {
+    let (time, utc_offset) = current_time();
+
+    reply_data(json!({
+        "timezone": timezone(),
+        "utc_offset": utc_offset,
+        "time": time
+    }))
+}
+
+/// Get the name of the server's timezone, if it can be found
+fn timezone() -> Option<String> {
+    fs::read_to_string(TIMEZONE_FILE)
+        .ok()
+        .map(|timezone| timezone.trim().to_owned())
+        .filter(|timezone| !timezone.is_empty())
+        .or_else(|| {
+            fs::read_link(LOCALTIME_FILE)
+                .ok()
+                .and_then(|target| timezone_from_path(&target))
+        })
+}
+
+/// Get the timezone name from the location of its timezone data, for example
+/// `/usr/share/zoneinfo/Europe/Berlin` is `Europe/Berlin`
+fn timezone_from_path(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    let start = path.find("zoneinfo/")? + "zoneinfo/".len();
+
+    match &path[start..] {
+        "" => None,
+        timezone => Some(timezone.to_owned())
+    }
+}
+
+/// Get the current Unix timestamp and the local offset from UTC in seconds.
+/// `time_t` and `tm_gmtoff` are 32 bits wide on some platforms, such as ARMv7.
+#[allow(clippy::identity_conversion)]
+fn current_time() -> (i64, i64) {
+    unsafe {
+        let time = libc::time(std::ptr::null_mut());
+        let mut local: libc::tm = mem::zeroed();
+
+        if libc::localtime_r(&time, &mut local).is_null() {
+            (i64::from(time), 0)
+        } else {
+            (i64::from(time), i64::from(local.tm_gmtoff))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::timezone_from_path;
+    use std::path::Path;
+
+    /// The timezone name is the path after the zoneinfo directory
+    #[test]
+    fn timezone_name() {
+        assert_eq!(
+            timezone_from_path(Path::new("/usr/share/zoneinfo/Europe/Berlin")),
+            Some("Europe/Berlin".to_owned())
+        );
+        assert_eq!(
+            timezone_from_path(Path::new("../usr/share/zoneinfo/UTC")),
+            Some("UTC".to_owned())
+        );
+    }
+
+    /// Paths outside of a zoneinfo directory have no timezone name
+    #[test]
+    fn timezone_name_invalid() {
+        assert_eq!(timezone_from_path(Path::new("/etc/localtime")), None);
+        assert_eq!(timezone_from_path(Path::new("/usr/share/zoneinfo/")), None);
+    }
+}
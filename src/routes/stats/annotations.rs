@@ -0,0 +1,262 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Log Annotations
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::{api_annotations, FtlDatabase},
+    routes::auth::User,
+    settings::ValueType,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_query,
+    sql_types::{Bool, Integer},
+    sqlite::SqliteConnection
+};
+use failure::ResultExt;
+use rocket_contrib::json::{Json, JsonValue};
+
+/// The maximum length of a note, in characters
+const MAX_NOTE_LENGTH: usize = 500;
+
+/// Get the annotations which overlap the time range
+#[get("/stats/annotations?<from>&<until>")]
+pub fn get_annotations(from: u64, until: u64, _auth: User, db: FtlDatabase) -> Reply {
+    reply_data(read_annotations(from, until, &db as &SqliteConnection)?)
+}
+
+/// Add a note to a query or to a time range, optionally only for a domain. A
+/// note for a single query has the same `from` and `until` as the query's
+/// timestamp.
+#[post("/stats/annotations", data = "<input>")]
+pub fn add_annotation(_auth: User, db: FtlDatabase, input: Json<AnnotationInput>) -> Reply {
+    let id = insert_annotation(&input.into_inner(), &db as &SqliteConnection)?;
+
+    reply_data(json!({ "id": id }))
+}
+
+/// Delete an annotation
+#[delete("/stats/annotations/<id>")]
+pub fn delete_annotation(id: i32, _auth: User, db: FtlDatabase) -> Reply {
+    let db = &db as &SqliteConnection;
+    create_annotations_table(db)?;
+
+    let deleted = diesel::delete(api_annotations::table.filter(api_annotations::id.eq(id)))
+        .execute(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    if deleted == 0 {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    reply_success()
+}
+
+/// A note about a time range, or about a domain during a time range
+#[derive(Serialize, Queryable)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Annotation {
+    pub id: i32,
+    pub from: i32,
+    pub until: i32,
+    pub domain: Option<String>,
+    pub note: String
+}
+
+/// The input of a new annotation. `until` defaults to `from`.
+#[derive(Deserialize)]
+pub struct AnnotationInput {
+    from: u64,
+    until: Option<u64>,
+    domain: Option<String>,
+    note: String
+}
+
+impl AnnotationInput {
+    /// Check if the annotation is valid. The note must not be empty, the range
+    /// must not end before it starts, and the domain must be valid.
+    fn is_valid(&self) -> bool {
+        let note = self.note.trim();
+
+        !note.is_empty()
+            && note.chars().count() <= MAX_NOTE_LENGTH
+            && self.until.unwrap_or(self.from) >= self.from
+            && self
+                .domain
+                .as_ref()
+                .map_or(true, |domain| ValueType::Domain.is_valid(domain))
+    }
+}
+
+/// Create the annotations table if it does not exist yet
+fn create_annotations_table(db: &SqliteConnection) -> Result<(), Error> {
+    sql_query(
+        "CREATE TABLE IF NOT EXISTS api_annotations (\
+         id INTEGER PRIMARY KEY AUTOINCREMENT, \
+         from_timestamp INTEGER NOT NULL, \
+         until_timestamp INTEGER NOT NULL, \
+         domain TEXT, \
+         note TEXT NOT NULL)"
+    )
+    .execute(db)
+    .context(ErrorKind::FtlDatabase)?;
+
+    Ok(())
+}
+
+/// Save the annotation, and get its ID
+fn insert_annotation(input: &AnnotationInput, db: &SqliteConnection) -> Result<i32, Error> {
+    if !input.is_valid() {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    create_annotations_table(db)?;
+
+    Ok(db
+        .transaction::<_, diesel::result::Error, _>(|| {
+            diesel::insert_into(api_annotations::table)
+                .values((
+                    api_annotations::from_timestamp.eq(input.from as i32),
+                    api_annotations::until_timestamp.eq(input.until.unwrap_or(input.from) as i32),
+                    api_annotations::domain.eq(input.domain.as_ref().map(|d| d.to_lowercase())),
+                    api_annotations::note.eq(input.note.trim())
+                ))
+                .execute(db)?;
+
+            diesel::select(sql::<Integer>("last_insert_rowid()")).get_result(db)
+        })
+        .context(ErrorKind::FtlDatabase)?)
+}
+
+/// Read the annotations which overlap the time range. If no annotations have
+/// been added, the table does not exist yet and there are no annotations.
+fn read_annotations(
+    from: u64,
+    until: u64,
+    db: &SqliteConnection
+) -> Result<Vec<Annotation>, Error> {
+    let table_exists: bool = diesel::select(sql::<Bool>(
+        "EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'api_annotations')"
+    ))
+    .get_result(db)
+    .context(ErrorKind::FtlDatabase)?;
+
+    if !table_exists {
+        return Ok(Vec::new());
+    }
+
+    Ok(api_annotations::table
+        .filter(api_annotations::until_timestamp.ge(from as i32))
+        .filter(api_annotations::from_timestamp.le(until as i32))
+        .order((api_annotations::from_timestamp, api_annotations::id))
+        .load(db)
+        .context(ErrorKind::FtlDatabase)?)
+}
+
+/// Get the annotations for a page of the query history. These are the
+/// annotations which overlap the time range of the page, leaving out the
+/// annotations for domains which are not in the page.
+pub fn history_annotations(
+    history: &[JsonValue],
+    db: &SqliteConnection
+) -> Result<Vec<Annotation>, Error> {
+    let timestamps = history
+        .iter()
+        .filter_map(|query| query["timestamp"].as_u64());
+    let (from, until) = match (timestamps.clone().min(), timestamps.max()) {
+        (Some(from), Some(until)) => (from, until),
+        _ => return Ok(Vec::new())
+    };
+
+    Ok(read_annotations(from, until, db)?
+        .into_iter()
+        .filter(|annotation| match annotation.domain {
+            Some(ref domain) => history
+                .iter()
+                .any(|query| query["domain"].as_str() == Some(domain.as_str())),
+            None => true
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{history_annotations, insert_annotation, read_annotations, AnnotationInput};
+    use crate::databases::ftl::connect_to_test_db;
+    use diesel::{result::Error, Connection};
+
+    /// Create an annotation input
+    fn input(from: u64, until: Option<u64>, domain: Option<&str>, note: &str) -> AnnotationInput {
+        AnnotationInput {
+            from,
+            until,
+            domain: domain.map(ToOwned::to_owned),
+            note: note.to_owned()
+        }
+    }
+
+    /// Annotations are read if they overlap the time range
+    #[test]
+    fn read_overlapping() {
+        let db = connect_to_test_db();
+
+        db.test_transaction::<_, Error, _>(|| {
+            let tv = insert_annotation(&input(100, Some(200), None, "New TV"), &db).unwrap();
+            insert_annotation(&input(300, None, Some("Example.com"), "Odd query"), &db).unwrap();
+
+            let annotations = read_annotations(150, 250, &db).unwrap();
+
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].id, tv);
+            assert_eq!(annotations[0].note, "New TV");
+
+            let annotations = read_annotations(300, 300, &db).unwrap();
+
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].until, 300);
+            assert_eq!(annotations[0].domain, Some("example.com".to_owned()));
+
+            Ok(())
+        });
+    }
+
+    /// Annotations for a domain are only shown with a history page which has
+    /// the domain
+    #[test]
+    fn history_domain() {
+        let db = connect_to_test_db();
+
+        db.test_transaction::<_, Error, _>(|| {
+            insert_annotation(&input(100, Some(200), None, "New TV"), &db).unwrap();
+            insert_annotation(&input(150, None, Some("example.com"), "Odd query"), &db).unwrap();
+
+            let history = vec![
+                json!({ "timestamp": 120, "domain": "example.net" }),
+                json!({ "timestamp": 160, "domain": "example.org" }),
+            ];
+            let annotations = history_annotations(&history, &db).unwrap();
+
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].note, "New TV");
+
+            Ok(())
+        });
+    }
+
+    /// Empty notes, backwards ranges, and invalid domains are rejected
+    #[test]
+    fn invalid_input() {
+        assert!(!input(100, None, None, " ").is_valid());
+        assert!(!input(100, Some(50), None, "Note").is_valid());
+        assert!(!input(100, None, Some("not a domain"), "Note").is_valid());
+        assert!(input(100, Some(100), Some("example.com"), "Note").is_valid());
+    }
+}
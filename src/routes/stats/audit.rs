@@ -0,0 +1,186 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Audit Log Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::auth::User,
+    settings::ValueType,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use failure::ResultExt;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::io::{prelude::*, BufWriter};
+
+/// Get the audited domains. Audited domains are left out of the top domains
+/// when the `audit` parameter is set.
+#[get("/stats/audit")]
+pub fn get_audit(_auth: User, env: State<Env>) -> Reply {
+    reply_data(json!({ "domains": read_audit_log(&env)? }))
+}
+
+/// Mark a domain as audited
+#[post("/stats/audit", data = "<input>")]
+pub fn add_audit(_auth: User, env: State<Env>, input: Json<AuditInput>) -> Reply {
+    let domain = input.0.domain.to_lowercase();
+
+    if !ValueType::Domain.is_valid(&domain) {
+        return Err(Error::from(ErrorKind::InvalidDomain));
+    }
+
+    let mut domains = read_audit_log(&env)?;
+
+    if domains.contains(&domain) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    domains.push(domain);
+    write_audit_log(&domains, &env)?;
+
+    reply_success()
+}
+
+/// Remove a domain from the audited domains
+#[delete("/stats/audit/<domain>")]
+pub fn delete_audit(_auth: User, env: State<Env>, domain: String) -> Reply {
+    let domain = domain.to_lowercase();
+    let mut domains = read_audit_log(&env)?;
+    let count = domains.len();
+
+    domains.retain(|audited| *audited != domain);
+
+    if domains.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    write_audit_log(&domains, &env)?;
+
+    reply_success()
+}
+
+/// The domain to mark as audited
+#[derive(Deserialize)]
+pub struct AuditInput {
+    domain: String
+}
+
+/// Read the audited domains. If the audit log does not exist, no domains
+/// have been audited.
+fn read_audit_log(env: &Env) -> Result<Vec<String>, Error> {
+    if !env.file_exists(PiholeFile::AuditLog) {
+        return Ok(Vec::new());
+    }
+
+    Ok(env
+        .read_file_lines(PiholeFile::AuditLog)?
+        .into_iter()
+        .filter(|domain| !domain.is_empty())
+        .collect())
+}
+
+/// Replace the audited domains
+fn write_audit_log(domains: &[String], env: &Env) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::AuditLog).to_owned();
+    let mut writer = BufWriter::new(env.write_file(PiholeFile::AuditLog, false)?);
+
+    for domain in domains {
+        writeln!(writer, "{}", domain).context(ErrorKind::FileWrite(file_location.clone()))?;
+    }
+
+    writer
+        .flush()
+        .context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// The audited domains are read from the audit log
+    #[test]
+    fn get_audit() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/audit")
+            .file(PiholeFile::AuditLog, "example.com\nexample.net\n")
+            .expect_json(json!({ "domains": ["example.com", "example.net"] }))
+            .test();
+    }
+
+    /// Audited domains are added to the audit log
+    #[test]
+    fn add_audit() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/audit")
+            .method(Method::Post)
+            .file_expect(
+                PiholeFile::AuditLog,
+                "example.com\n",
+                "example.com\nexample.net\n"
+            )
+            .body(json!({ "domain": "Example.net" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// A domain can only be audited once
+    #[test]
+    fn add_audit_duplicate() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/audit")
+            .method(Method::Post)
+            .file(PiholeFile::AuditLog, "example.com\n")
+            .body(json!({ "domain": "example.com" }))
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::Conflict)
+            .test();
+    }
+
+    /// Audited domains are removed from the audit log
+    #[test]
+    fn delete_audit() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/audit/example.com")
+            .method(Method::Delete)
+            .file_expect(
+                PiholeFile::AuditLog,
+                "example.com\nexample.net\n",
+                "example.net\n"
+            )
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Removing a domain which was not audited is an error
+    #[test]
+    fn delete_audit_missing() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/audit/example.org")
+            .method(Method::Delete)
+            .file(PiholeFile::AuditLog, "example.com\n")
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .expect_status(Status::NotFound)
+            .test();
+    }
+}
@@ -0,0 +1,114 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Cache Hit Ratio Over Time Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::FtlMemory,
+    routes::stats::common::get_current_over_time_slot,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+
+/// Get how many of the answered queries were served from the cache instead
+/// of being forwarded, over time
+#[get("/stats/cache_hit_ratio")]
+pub fn cache_hit_ratio(ftl_memory: State<FtlMemory>) -> Reply {
+    let lock = ftl_memory.lock()?;
+    let over_time = ftl_memory.over_time(&lock)?;
+
+    let ratio_data: Vec<CacheHitRatioItem> = over_time
+        .iter()
+        // Take all of the slots including the current slot
+        .take(get_current_over_time_slot(&over_time) + 1)
+        // Skip the overTime slots without any data
+        .skip_while(|time| time.cached_queries <= 0 && time.forwarded_queries <= 0)
+        .map(|time| {
+            CacheHitRatioItem::new(
+                time.timestamp as u64,
+                time.cached_queries.max(0) as usize,
+                time.forwarded_queries.max(0) as usize
+            )
+        })
+        .collect();
+
+    reply_data(ratio_data)
+}
+
+/// The cache hit ratio of an overTime slot
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct CacheHitRatioItem {
+    pub timestamp: u64,
+    pub cached_queries: usize,
+    pub forwarded_queries: usize,
+    /// The part of the cached and forwarded queries which were cached, from 0
+    /// to 1. It is `null` if there were no such queries.
+    pub ratio: Option<f64>
+}
+
+impl CacheHitRatioItem {
+    /// Calculate the ratio of the slot
+    pub fn new(timestamp: u64, cached_queries: usize, forwarded_queries: usize) -> Self {
+        let answered = cached_queries + forwarded_queries;
+
+        CacheHitRatioItem {
+            timestamp,
+            cached_queries,
+            forwarded_queries,
+            ratio: if answered == 0 {
+                None
+            } else {
+                Some(cached_queries as f64 / answered as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ftl::{FtlCounters, FtlMemory, FtlOverTime, FtlSettings},
+        testing::TestBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Data for testing cache_hit_ratio
+    fn test_data() -> FtlMemory {
+        FtlMemory::Test {
+            over_time: vec![
+                FtlOverTime::new(1, 1, 1, 0, 0, [0; 7]),
+                FtlOverTime::new(2, 4, 0, 1, 3, [0; 7]),
+                FtlOverTime::new(3, 1, 1, 0, 0, [0; 7]),
+                FtlOverTime::new(4, 2, 0, 2, 0, [0; 7]),
+            ],
+            counters: FtlCounters::default(),
+            clients: Vec::new(),
+            upstreams: Vec::new(),
+            strings: HashMap::new(),
+            domains: Vec::new(),
+            queries: Vec::new(),
+            settings: FtlSettings::default()
+        }
+    }
+
+    /// Slots are skipped until the first one with answered queries, and slots
+    /// without answered queries have no ratio
+    #[test]
+    fn ratio_over_time() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/cache_hit_ratio")
+            .ftl_memory(test_data())
+            .expect_json(json!([
+                { "timestamp": 2, "cached_queries": 1, "forwarded_queries": 3, "ratio": 0.25 },
+                { "timestamp": 3, "cached_queries": 0, "forwarded_queries": 0, "ratio": null },
+                { "timestamp": 4, "cached_queries": 2, "forwarded_queries": 0, "ratio": 1.0 }
+            ]))
+            .test();
+    }
+}
@@ -0,0 +1,344 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Data Export And Removal Endpoints
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    client_nicknames::{read_nicknames, write_nickname, ClientNicknames},
+    databases::ftl::{api_rollups, network, queries, FtlDatabase},
+    env::Env,
+    routes::{auth::User, stats::PrivacyPolicy},
+    services::{create_rollup_table, ClientExportJob, JobState},
+    util::{
+        reply, reply_data, reply_success, Error, ErrorKind, HeadReply, Reply, SetStatus,
+        TarGzFile
+    }
+};
+use diesel::{prelude::*, SqliteConnection};
+use failure::ResultExt;
+use rocket::{
+    http::{ContentType, Status},
+    response::{self, Responder},
+    Request, State
+};
+use rocket_contrib::json::JsonValue;
+use std::net::IpAddr;
+
+/// Start exporting everything stored about a client as a gzipped tar archive:
+/// its nickname, its entries in the network table, and its queries in the
+/// database. This is used to answer a household member's request for their
+/// data. The export runs in the background, and its archive is downloaded
+/// once the status shows it succeeded.
+#[post("/stats/clients/<ip>/export")]
+pub fn export_client_data(
+    ip: String,
+    _auth: User,
+    env: State<Env>,
+    job: State<ClientExportJob>,
+    nicknames: State<ClientNicknames>
+) -> Reply {
+    check_client_ip(&ip)?;
+
+    reply_data(job.start(&env, &ip, nicknames.get(&ip))?)
+}
+
+/// Get everything stored about a client as a gzipped tar archive. If the last
+/// export was of this client and succeeded, its archive is downloaded.
+/// Otherwise an export of the client is started, unless it is already
+/// running, and its status is shown with `202 Accepted`. The request can be
+/// repeated until the archive is downloaded.
+#[get("/stats/clients/<ip>/export")]
+pub fn get_client_export(
+    ip: String,
+    _auth: User,
+    env: State<Env>,
+    job: State<ClientExportJob>,
+    nicknames: State<ClientNicknames>
+) -> Result<ClientExportReply, Error> {
+    check_client_ip(&ip)?;
+
+    if let Some((name, data)) = job.client_archive(&ip) {
+        return Ok(ClientExportReply::Archive(TarGzFile { name, data }));
+    }
+
+    let status = job.status();
+    let status = if status.state == JobState::Running && status.client.as_ref() == Some(&ip) {
+        status
+    } else {
+        job.start(&env, &ip, nicknames.get(&ip))?
+    };
+
+    reply(Ok(status), Status::Accepted).map(ClientExportReply::Status)
+}
+
+/// The reply of the client export endpoint: the archive once the export has
+/// succeeded, otherwise the status of the export
+pub enum ClientExportReply {
+    Status(SetStatus<JsonValue>),
+    Archive(TarGzFile)
+}
+
+impl<'r> Responder<'r> for ClientExportReply {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            ClientExportReply::Status(reply) => reply.respond_to(request),
+            ClientExportReply::Archive(file) => file.respond_to(request)
+        }
+    }
+}
+
+/// Get the progress of the current client export, or the outcome of the last
+/// one
+#[get("/stats/clients/export")]
+pub fn get_client_export_status(_auth: User, job: State<ClientExportJob>) -> Reply {
+    reply_data(job.status())
+}
+
+/// Download the archive of the last client export
+#[get("/stats/clients/export/archive")]
+pub fn get_client_export_archive(
+    _auth: User,
+    job: State<ClientExportJob>
+) -> Result<TarGzFile, Error> {
+    let (name, data) = job.archive().ok_or(ErrorKind::NotFound)?;
+
+    Ok(TarGzFile { name, data })
+}
+
+/// Answer a HEAD request for the archive of the last client export, without
+/// copying the archive
+#[head("/stats/clients/export/archive")]
+pub fn get_client_export_archive_head(
+    _auth: User,
+    job: State<ClientExportJob>
+) -> Result<HeadReply, Error> {
+    Ok(HeadReply {
+        content_type: ContentType::new("application", "gzip"),
+        file_name: Some(job.archive_file_name().ok_or(ErrorKind::NotFound)?)
+    })
+}
+
+/// Erase everything stored about a client: its queries and rolled up counts
+/// in the database, its entries in the network table, and its nickname. The
+/// queries in FTL's memory are kept until FTL removes them. The number of
+/// deleted items is not shown if the privacy level hides clients.
+#[delete("/stats/clients/<ip>")]
+pub fn delete_client_data(
+    ip: String,
+    _auth: User,
+    db: FtlDatabase,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>
+) -> Reply {
+    check_client_ip(&ip)?;
+
+    let deleted = delete_client(&ip, &env, &db as &SqliteConnection)?;
+    nicknames.reload(&env, &db as &SqliteConnection)?;
+
+    if PrivacyPolicy::read(&env)?.shows_clients() {
+        reply_data(deleted)
+    } else {
+        reply_success()
+    }
+}
+
+/// Erase everything stored about a client, from the path of its export. This
+/// is the same as `DELETE /stats/clients/<ip>`.
+#[delete("/stats/clients/<ip>/export")]
+pub fn delete_client_export(
+    ip: String,
+    auth: User,
+    db: FtlDatabase,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>
+) -> Reply {
+    delete_client_data(ip, auth, db, env, nicknames)
+}
+
+/// The number of stored items which were deleted
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct DeletedClientData {
+    pub queries: usize,
+    pub rollups: usize,
+    pub network: usize,
+    pub nicknames: usize
+}
+
+/// Only IP addresses are accepted, since the database stores clients by IP
+fn check_client_ip(ip: &str) -> Result<(), Error> {
+    ip.parse::<IpAddr>()
+        .map(|_| ())
+        .map_err(|_| Error::from(ErrorKind::BadRequest))
+}
+
+/// Delete the data stored about the client. FTL writes to the same database,
+/// so the database is changed in one transaction, and the client is either
+/// erased completely or not at all. Nicknames are saved by MAC address, so
+/// the MAC addresses of the client's devices are read from the network table
+/// before the devices are removed. The nicknames are only removed after the
+/// transaction is committed.
+fn delete_client(ip: &str, env: &Env, db: &SqliteConnection) -> Result<DeletedClientData, Error> {
+    // The rollup table is created by the API, so it may not exist yet
+    create_rollup_table(db)?;
+
+    let (hwaddrs, queries, rollups, network) = db
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let hwaddrs: Vec<String> = network::table
+                .select(network::hwaddr)
+                .filter(network::ip.eq(ip))
+                .load(db)?;
+            let queries =
+                diesel::delete(queries::table.filter(queries::client.eq(ip))).execute(db)?;
+            let rollups = diesel::delete(
+                api_rollups::table
+                    .filter(api_rollups::kind.eq("client"))
+                    .filter(api_rollups::key.eq(ip))
+            )
+            .execute(db)?;
+            let network = diesel::delete(network::table.filter(network::ip.eq(ip))).execute(db)?;
+
+            Ok((hwaddrs, queries, rollups, network))
+        })
+        .context(ErrorKind::FtlDatabase)?;
+
+    let saved_nicknames = read_nicknames(env)?;
+    let mut removed_nicknames = 0;
+
+    for hwaddr in &hwaddrs {
+        if saved_nicknames.contains_key(&hwaddr.to_lowercase()) {
+            write_nickname(env, hwaddr, None)?;
+            removed_nicknames += 1;
+        }
+    }
+
+    Ok(DeletedClientData {
+        queries,
+        rollups,
+        network,
+        nicknames: removed_nicknames
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{delete_client, DeletedClientData};
+    use crate::{
+        databases::ftl::{connect_to_test_db, network, queries},
+        env::{Config, Env, PiholeFile},
+        testing::{TestBuilder, TestEnvBuilder}
+    };
+    use diesel::{prelude::*, result::Error};
+    use rocket::http::{Method, Status};
+
+    /// The client's queries, network entries, and nicknames are deleted, and
+    /// other clients are kept
+    #[test]
+    fn delete() {
+        let db = connect_to_test_db();
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::ClientNicknames,
+            "00:00:00:00:00:00 Router\n11:22:33:44:55:66 Laptop\n",
+            "11:22:33:44:55:66 Laptop\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        db.test_transaction::<_, Error, _>(|| {
+            assert_eq!(
+                delete_client("10.1.1.1", &env, &db).unwrap(),
+                DeletedClientData {
+                    queries: 1,
+                    rollups: 0,
+                    network: 1,
+                    nicknames: 1
+                }
+            );
+            assert_eq!(queries::table.count().get_result::<i64>(&db)?, 93);
+            assert_eq!(network::table.count().get_result::<i64>(&db)?, 0);
+            Ok(())
+        });
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+
+    /// Clients are only looked up by IP address
+    #[test]
+    fn invalid_ip() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/laptop/export")
+            .method(Method::Post)
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Clients are only exported by IP address
+    #[test]
+    fn get_export_invalid_ip() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/laptop/export")
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// No export has run yet
+    #[test]
+    fn export_status_idle() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/export")
+            .expect_json(json!({
+                "state": "idle",
+                "client": null,
+                "started": null,
+                "finished": null
+            }))
+            .test();
+    }
+
+    /// There is no archive to download before an export succeeds
+    #[test]
+    fn no_archive() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/export/archive")
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// HEAD requests for the archive are not found before an export succeeds
+    #[test]
+    fn no_archive_head() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/export/archive")
+            .method(Method::Head)
+            .expect_status(Status::NotFound)
+            .expect_empty_body()
+            .test();
+    }
+}
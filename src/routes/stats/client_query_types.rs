@@ -0,0 +1,259 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Query Types Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    client_nicknames::ClientNicknames,
+    env::Env,
+    ftl::{FtlMemory, FtlQueryType},
+    routes::{
+        auth::User,
+        stats::clients::{filter_ftl_clients, ClientParams}
+    },
+    util::{reply_result, Error, Reply}
+};
+use rocket::State;
+use std::collections::HashMap;
+
+/// Get the number of queries of each query type made by each client, as a
+/// matrix of clients and query types. The time range is optional.
+#[get("/stats/clients/query_types?<from>&<until>")]
+pub fn client_query_types(
+    _auth: User,
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>,
+    from: Option<u64>,
+    until: Option<u64>
+) -> Reply {
+    reply_result(client_query_types_impl(
+        &ftl_memory,
+        &env,
+        &nicknames,
+        from,
+        until
+    ))
+}
+
+/// Represents the reply structure for the client query type matrix
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientQueryTypes {
+    /// The names of the query types, in the order of each client's counts
+    pub query_types: Vec<String>,
+    pub clients: Vec<ClientQueryTypeItem>
+}
+
+/// The query type counts of one client
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientQueryTypeItem {
+    pub name: String,
+    pub ip: String,
+    pub counts: Vec<usize>
+}
+
+impl ClientQueryTypes {
+    /// Create the matrix from the clients and their counts. Clients without
+    /// any queries are left out, and the clients with the most queries are
+    /// first.
+    pub fn new(mut clients: Vec<ClientQueryTypeItem>) -> ClientQueryTypes {
+        clients.retain(|client| client.counts.iter().any(|&count| count > 0));
+        clients.sort_by(|a, b| {
+            let total_a: usize = a.counts.iter().sum();
+            let total_b: usize = b.counts.iter().sum();
+
+            total_b.cmp(&total_a).then_with(|| a.ip.cmp(&b.ip))
+        });
+
+        ClientQueryTypes {
+            query_types: FtlQueryType::variants()
+                .iter()
+                .map(|variant| variant.get_name())
+                .collect(),
+            clients
+        }
+    }
+}
+
+/// Get the index of the query type in the counts
+pub fn query_type_index(query_type: FtlQueryType) -> usize {
+    query_type as usize - 1
+}
+
+/// Count the query types of each client from the queries in memory
+fn client_query_types_impl(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    nicknames: &ClientNicknames,
+    from: Option<u64>,
+    until: Option<u64>
+) -> Result<ClientQueryTypes, Error> {
+    let lock = ftl_memory.lock()?;
+    let strings = ftl_memory.strings(&lock)?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let ftl_clients = ftl_memory.clients(&lock)?;
+
+    // Count the query types by client IP
+    let mut counts: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    for query in queries
+        .iter()
+        .take(counters.total_queries as usize)
+        .filter(|query| !query.is_private)
+        .filter(|query| from.map_or(true, |from| query.timestamp as u64 >= from))
+        .filter(|query| until.map_or(true, |until| query.timestamp as u64 <= until))
+    {
+        let ip = ftl_clients[query.client_id as usize].get_ip(&strings);
+        let client_counts = counts
+            .entry(ip)
+            .or_insert_with(|| vec![0; FtlQueryType::variants().len()]);
+
+        client_counts[query_type_index(query.query_type)] += 1;
+    }
+
+    // Only show the clients which are not hidden or excluded
+    let clients = filter_ftl_clients(
+        ftl_memory,
+        &lock,
+        &ftl_clients,
+        env,
+        ClientParams::default()
+    )?
+    .into_iter()
+    .filter_map(|client| {
+        let client_counts = counts.remove(client.get_ip(&strings))?;
+        let reply = nicknames.client_reply(client, &strings);
+
+        Some(ClientQueryTypeItem {
+            name: reply.name,
+            ip: reply.ip,
+            counts: client_counts
+        })
+    })
+    .collect();
+
+    Ok(ClientQueryTypes::new(clients))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        ftl::{
+            FtlClient, FtlCounters, FtlDnssecType, FtlMemory, FtlQuery, FtlQueryReplyType,
+            FtlQueryStatus, FtlQueryType, FtlSettings, MAGIC_BYTE
+        },
+        testing::TestBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Shorthand for making `FtlQuery` structs
+    macro_rules! query {
+        ($id:expr, $query_type:ident, $client:expr, $timestamp:expr) => {
+            FtlQuery {
+                magic: MAGIC_BYTE,
+                id: $id,
+                database_id: 0,
+                timestamp: $timestamp,
+                time_index: 1,
+                response_time: 1,
+                domain_id: 0,
+                client_id: $client,
+                upstream_id: 0,
+                query_type: FtlQueryType::$query_type,
+                status: FtlQueryStatus::Forward,
+                reply_type: FtlQueryReplyType::IP,
+                dnssec_type: FtlDnssecType::Unspecified,
+                is_complete: true,
+                is_private: false,
+                ad_bit: false
+            }
+        };
+    }
+
+    /// 2 clients with 5 queries
+    fn test_memory() -> FtlMemory {
+        let mut strings = HashMap::new();
+        strings.insert(1, "10.1.1.1".to_owned());
+        strings.insert(2, "client1".to_owned());
+        strings.insert(3, "10.1.1.2".to_owned());
+
+        FtlMemory::Test {
+            queries: vec![
+                query!(1, A, 0, 100),
+                query!(2, AAAA, 0, 100),
+                query!(3, A, 1, 200),
+                query!(4, PTR, 1, 200),
+                query!(5, A, 1, 300),
+            ],
+            domains: Vec::new(),
+            over_time: Vec::new(),
+            strings,
+            clients: vec![
+                FtlClient::new(2, 0, 1, Some(2)),
+                FtlClient::new(3, 0, 3, None),
+            ],
+            upstreams: Vec::new(),
+            counters: FtlCounters {
+                total_queries: 5,
+                total_clients: 2,
+                ..FtlCounters::default()
+            },
+            settings: FtlSettings::default()
+        }
+    }
+
+    /// The clients with the most queries are first
+    #[test]
+    fn all_queries() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/query_types")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "query_types": ["A", "AAAA", "ANY", "SRV", "SOA", "PTR", "TXT"],
+                "clients": [
+                    { "name": "", "ip": "10.1.1.2", "counts": [2, 0, 0, 0, 0, 1, 0] },
+                    { "name": "client1", "ip": "10.1.1.1", "counts": [1, 1, 0, 0, 0, 0, 0] }
+                ]
+            }))
+            .test();
+    }
+
+    /// Only queries in the time range are counted, and clients without
+    /// queries are left out
+    #[test]
+    fn time_range() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/query_types?from=150&until=250")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "query_types": ["A", "AAAA", "ANY", "SRV", "SOA", "PTR", "TXT"],
+                "clients": [
+                    { "name": "", "ip": "10.1.1.2", "counts": [1, 0, 0, 0, 0, 1, 0] }
+                ]
+            }))
+            .test();
+    }
+
+    /// Clients are hidden by the privacy level
+    #[test]
+    fn privacy() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients/query_types")
+            .ftl_memory(test_memory())
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2")
+            .expect_json(json!({
+                "query_types": ["A", "AAAA", "ANY", "SRV", "SOA", "PTR", "TXT"],
+                "clients": []
+            }))
+            .test();
+    }
+}
@@ -9,14 +9,18 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    client_nicknames::ClientNicknames,
     env::Env,
     ftl::{ClientReply, FtlClient, FtlMemory, ShmLockGuard},
     routes::{
         auth::User,
-        stats::common::{remove_excluded_clients, remove_hidden_clients}
+        fields::{select_fields, FieldSelection},
+        stats::{
+            common::remove_excluded_clients,
+            privacy::{remove_hidden_clients, PrivacyPolicy}
+        }
     },
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
-    util::{reply_result, Error, Reply}
+    util::{reply_data, Error, Reply}
 };
 use rocket::{request::Form, State};
 
@@ -26,21 +30,29 @@ pub fn clients(
     _auth: User,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
+    nicknames: State<ClientNicknames>,
     params: Form<ClientParams>
 ) -> Reply {
-    reply_result(get_clients(&ftl_memory, &env, params.into_inner()))
+    let mut params = params.into_inner();
+    let fields = params.fields.take();
+    let clients = get_clients(&ftl_memory, &env, &nicknames, params)?;
+
+    reply_data(select_fields(clients, fields.as_ref()))
 }
 
 /// The possible GET parameters for `/stats/clients`
 #[derive(FromForm, Default)]
 pub struct ClientParams {
-    inactive: Option<bool>
+    inactive: Option<bool>,
+    /// The fields to include in each client
+    fields: Option<FieldSelection>
 }
 
 /// Get client data for API output according to the parameters
 fn get_clients(
     ftl_memory: &FtlMemory,
     env: &Env,
+    nicknames: &ClientNicknames,
     params: ClientParams
 ) -> Result<Vec<ClientReply>, Error> {
     let lock = ftl_memory.lock()?;
@@ -50,7 +62,7 @@ fn get_clients(
     Ok(
         filter_ftl_clients(ftl_memory, &lock, &clients, env, params)?
             .iter()
-            .map(|client| client.as_reply(&strings))
+            .map(|client| nicknames.client_reply(client, &strings))
             .collect::<Vec<ClientReply>>()
     )
 }
@@ -65,9 +77,7 @@ pub fn filter_ftl_clients<'a>(
     params: ClientParams
 ) -> Result<Vec<&'a FtlClient>, Error> {
     // Check if client details are private
-    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)?
-        >= FtlPrivacyLevel::HideDomainsAndClients
-    {
+    if !PrivacyPolicy::read(&env)?.shows_clients() {
         return Ok(Vec::new());
     }
 
@@ -177,6 +187,21 @@ mod test {
             .test();
     }
 
+    /// Only the selected fields are included
+    #[test]
+    fn fields() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/clients?fields=ip")
+            .ftl_memory(test_data())
+            .expect_json(json!([
+                { "ip": "10.1.1.1" },
+                { "ip": "10.1.1.2" },
+                { "ip": "10.1.1.3" },
+                { "ip": "10.1.1.4" }
+            ]))
+            .test();
+    }
+
     /// Excluded clients are not shown
     #[test]
     fn excluded_clients() {
@@ -10,9 +10,9 @@
 
 use crate::{
     env::Env,
-    ftl::{FtlClient, FtlDomain, FtlOverTime, FtlStrings, OVERTIME_SLOTS},
+    ftl::{FtlClient, FtlDomain, FtlOverTime, FtlStrings, OVERTIME_INTERVAL, OVERTIME_SLOTS},
     settings::{ConfigEntry, SetupVarsEntry},
-    util::Error
+    util::{Error, ErrorKind}
 };
 use std::{
     collections::HashSet,
@@ -90,30 +90,6 @@ pub fn get_excluded_domains(env: &Env) -> Result<Vec<String>, Error> {
         .collect())
 }
 
-/// Remove clients from the `clients` vector if they are marked as hidden due
-/// to the privacy level.
-pub fn remove_hidden_clients(clients: &mut Vec<&FtlClient>, strings: &FtlStrings) {
-    let hidden_client_ip = get_hidden_client_ip();
-    clients.retain(|client| client.get_ip(strings) != hidden_client_ip);
-}
-
-/// Get the hidden client IP address
-pub fn get_hidden_client_ip() -> &'static str {
-    "0.0.0.0"
-}
-
-/// Remove domains from the `domains` vector if they are marked as hidden due
-/// to the privacy level.
-pub fn remove_hidden_domains(domains: &mut Vec<&FtlDomain>, strings: &FtlStrings) {
-    let hidden_domain = get_hidden_domain();
-    domains.retain(|domain| domain.get_domain(strings) != hidden_domain);
-}
-
-/// Get the designated hidden domain
-pub fn get_hidden_domain() -> &'static str {
-    "hidden"
-}
-
 /// Get the current overTime slot index, based on the current time. If all of
 /// the slots are in the past, then the last slot index will be returned.
 pub fn get_current_over_time_slot(over_time: &[FtlOverTime]) -> usize {
@@ -137,11 +113,56 @@ pub fn get_current_over_time_slot(over_time: &[FtlOverTime]) -> usize {
         .unwrap_or(OVERTIME_SLOTS - 1)
 }
 
+/// Get the requested overTime interval. FTL's slots can only be merged, so
+/// the interval must be a multiple of FTL's interval. The default is FTL's
+/// interval.
+pub fn get_over_time_interval(interval: Option<usize>) -> Result<usize, Error> {
+    match interval {
+        None => Ok(OVERTIME_INTERVAL),
+        Some(interval) if interval > 0 && interval % OVERTIME_INTERVAL == 0 => Ok(interval),
+        Some(_) => Err(Error::from(ErrorKind::BadRequest))
+    }
+}
+
+/// Merge FTL's overTime slots into slots of `interval` seconds, which start
+/// at multiples of the interval. The slots are given to `merge` with the
+/// timestamp of the merged slot, which is centered in the slot like FTL's
+/// timestamps.
+pub fn merge_over_time_slots<T>(
+    slots: Vec<T>,
+    interval: usize,
+    timestamp: impl Fn(&T) -> u64,
+    merge: impl Fn(&[T], u64) -> T
+) -> Vec<T> {
+    if interval == OVERTIME_INTERVAL {
+        return slots;
+    }
+
+    let interval = interval as u64;
+    // FTL's timestamps are centered in their slots
+    let slot_start = |slot: &T| timestamp(slot).saturating_sub((OVERTIME_INTERVAL / 2) as u64);
+    let mut merged = Vec::new();
+    let mut start = 0;
+
+    while start < slots.len() {
+        let bucket = slot_start(&slots[start]) / interval;
+        let end = slots[start..]
+            .iter()
+            .position(|slot| slot_start(slot) / interval != bucket)
+            .map_or(slots.len(), |len| start + len);
+
+        merged.push(merge(&slots[start..end], bucket * interval + interval / 2));
+        start = end;
+    }
+
+    merged
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
-        remove_excluded_clients, remove_excluded_domains, remove_hidden_clients,
-        remove_hidden_domains
+        get_over_time_interval, merge_over_time_slots, remove_excluded_clients,
+        remove_excluded_domains
     };
     use crate::{
         env::{Config, Env, PiholeFile},
@@ -247,35 +268,29 @@ mod tests {
         );
     }
 
-    /// Clients marked as hidden are removed
+    /// The interval must be a multiple of FTL's interval
     #[test]
-    fn hidden_clients() {
-        let ftl_memory = test_data();
-        let lock_guard = ShmLockGuard::Test;
-
-        let clients = ftl_memory.clients(&lock_guard).unwrap();
-        let mut clients: Vec<&FtlClient> = clients.iter().collect();
-        let mut clients_clone = clients.clone();
-        clients_clone.remove(2);
-
-        remove_hidden_clients(&mut clients, &ftl_memory.strings(&lock_guard).unwrap());
-
-        assert_eq!(clients, clients_clone);
+    fn over_time_interval() {
+        assert_eq!(get_over_time_interval(None).unwrap(), 600);
+        assert_eq!(get_over_time_interval(Some(3600)).unwrap(), 3600);
+        assert!(get_over_time_interval(Some(0)).is_err());
+        assert!(get_over_time_interval(Some(300)).is_err());
+        assert!(get_over_time_interval(Some(900)).is_err());
     }
 
-    /// Domains marked as hidden are removed
+    /// Slots are merged into slots which start at multiples of the interval,
+    /// and the merged timestamps are centered
     #[test]
-    fn hidden_domains() {
-        let ftl_memory = test_data();
-        let lock_guard = ShmLockGuard::Test;
-
-        let domains = ftl_memory.domains(&lock_guard).unwrap();
-        let mut domains: Vec<&FtlDomain> = domains.iter().collect();
-        let mut domains_clone = domains.clone();
-        domains_clone.remove(2);
-
-        remove_hidden_domains(&mut domains, &ftl_memory.strings(&lock_guard).unwrap());
+    fn merge_slots() {
+        let slots = vec![(3300, 1), (3900, 2), (4500, 3), (5100, 4), (7500, 5)];
+
+        let merged = merge_over_time_slots(
+            slots,
+            3600,
+            |slot| slot.0,
+            |chunk, timestamp| (timestamp, chunk.iter().map(|slot| slot.1).sum())
+        );
 
-        assert_eq!(domains, domains_clone);
+        assert_eq!(merged, vec![(1800, 1), (5400, 9), (9000, 5)]);
     }
 }
@@ -0,0 +1,180 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Compact Summary Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlMemory,
+    process_info::ProcessInfo,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{reply_result, Cached, Error, Reply}
+};
+use rocket::State;
+
+/// How long clients may cache the compact summary, in seconds
+const CACHE_TIME: u32 = 30;
+
+/// Get a small summary for widgets and other clients which poll often. Only
+/// the FTL counters are read, and clients are allowed to cache the reply.
+#[get("/stats/summary/compact")]
+pub fn get_compact_summary(
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    process_info: State<ProcessInfo>
+) -> Cached<Reply> {
+    Cached(
+        reply_result(get_compact_summary_impl(&ftl_memory, &env, &process_info)),
+        CACHE_TIME
+    )
+}
+
+/// Represents the response of the compact summary endpoint
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct CompactSummary {
+    pub total_queries: usize,
+    pub blocked_queries: usize,
+    pub gravity_size: usize,
+    pub status: &'static str,
+    /// The API uptime in seconds
+    pub uptime: u64
+}
+
+/// Get the compact summary. The shared memory lock is only held while the
+/// counters are copied.
+fn get_compact_summary_impl(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    process_info: &ProcessInfo
+) -> Result<CompactSummary, Error> {
+    let (total_queries, blocked_queries, gravity_size) = {
+        let lock = ftl_memory.lock()?;
+        let counters = ftl_memory.counters(&lock)?;
+
+        (
+            counters.total_queries as usize,
+            counters.blocked_queries as usize,
+            counters.gravity_size as usize
+        )
+    };
+
+    let status = if SetupVarsEntry::BlockingEnabled.is_true(env)? {
+        "enabled"
+    } else {
+        "disabled"
+    };
+
+    Ok(CompactSummary {
+        total_queries,
+        blocked_queries,
+        gravity_size,
+        status,
+        uptime: process_info.api_uptime()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_compact_summary_impl, CompactSummary};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::{FtlCounters, FtlMemory, FtlSettings},
+        process_info::ProcessInfo,
+        testing::{TestBuilder, TestEnvBuilder}
+    };
+    use std::collections::HashMap;
+
+    /// There are 7 queries, 2 of them blocked
+    fn test_data() -> FtlMemory {
+        FtlMemory::Test {
+            clients: Vec::new(),
+            domains: Vec::new(),
+            over_time: Vec::new(),
+            strings: HashMap::new(),
+            upstreams: Vec::new(),
+            queries: Vec::new(),
+            counters: FtlCounters {
+                gravity_size: 100_000,
+                total_queries: 7,
+                blocked_queries: 2,
+                ..FtlCounters::default()
+            },
+            settings: FtlSettings::default()
+        }
+    }
+
+    /// Create a test environment with the blocking status
+    fn test_env(setup_vars: &str) -> Env {
+        Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, setup_vars)
+                .build()
+        )
+    }
+
+    /// The counters, blocking status, and uptime are returned
+    #[test]
+    fn enabled() {
+        assert_eq!(
+            get_compact_summary_impl(
+                &test_data(),
+                &test_env("BLOCKING_ENABLED=true\n"),
+                &ProcessInfo::Test {
+                    api_uptime: 90,
+                    ftl_uptime: None
+                }
+            )
+            .unwrap(),
+            CompactSummary {
+                total_queries: 7,
+                blocked_queries: 2,
+                gravity_size: 100_000,
+                status: "enabled",
+                uptime: 90
+            }
+        );
+    }
+
+    /// The blocking status is shown when blocking is disabled
+    #[test]
+    fn disabled() {
+        assert_eq!(
+            get_compact_summary_impl(
+                &test_data(),
+                &test_env("BLOCKING_ENABLED=false\n"),
+                &ProcessInfo::Test {
+                    api_uptime: 0,
+                    ftl_uptime: None
+                }
+            )
+            .unwrap()
+            .status,
+            "disabled"
+        );
+    }
+
+    /// Clients are allowed to cache the reply
+    #[test]
+    fn cache_header() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/summary/compact")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "BLOCKING_ENABLED=true\n")
+            .expect_header("Cache-Control", "public, max-age=30")
+            .expect_json(json!({
+                "total_queries": 7,
+                "blocked_queries": 2,
+                "gravity_size": 100_000,
+                "status": "enabled",
+                "uptime": 3600
+            }))
+            .test();
+    }
+}
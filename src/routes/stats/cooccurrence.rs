@@ -0,0 +1,339 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Domain Co-occurrence Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlMemory,
+    routes::{
+        auth::User,
+        stats::{
+            common::get_excluded_domains,
+            privacy::{get_hidden_domain, PrivacyPolicy}
+        }
+    },
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use rocket::{request::Form, State};
+use std::collections::{HashMap, HashSet};
+
+/// The default window after the domain is queried, in seconds
+const DEFAULT_WINDOW: u64 = 10;
+
+/// The maximum window after the domain is queried, in seconds
+const MAX_WINDOW: u64 = 300;
+
+/// Get the domains which are most often queried by the same client shortly
+/// after the given domain. This shows which domains load together, such as
+/// tracker bundles, and what else may break when one of them is blocked.
+#[get("/stats/cooccurrence?<params..>")]
+pub fn domain_cooccurrence(
+    _auth: User,
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    params: Form<CooccurrenceParams>
+) -> Reply {
+    reply_result(get_cooccurrence(&ftl_memory, &env, params.into_inner()))
+}
+
+/// Represents the possible GET parameters on `/stats/cooccurrence`
+#[derive(FromForm)]
+pub struct CooccurrenceParams {
+    domain: String,
+    window: Option<u64>,
+    limit: Option<usize>
+}
+
+/// Represents the reply structure for the co-occurrence endpoint
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct CooccurrenceReply {
+    pub domain: String,
+    pub window: u64,
+    /// The number of times the domain was queried
+    pub occurrences: usize,
+    pub domains: Vec<CooccurrenceItem>
+}
+
+/// A domain which was queried after the given domain, and the number of
+/// times it was queried within the window
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct CooccurrenceItem {
+    pub domain: String,
+    pub count: usize
+}
+
+/// Count the domains queried within the window after each query of the given
+/// domain by the same client. A domain is counted at most once per window.
+fn get_cooccurrence(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    params: CooccurrenceParams
+) -> Result<CooccurrenceReply, Error> {
+    let window = params.window.unwrap_or(DEFAULT_WINDOW);
+    let limit = params.limit.unwrap_or(10);
+    let target = params.domain.to_lowercase();
+
+    if window == 0 || window > MAX_WINDOW {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let mut reply = CooccurrenceReply {
+        domain: target.clone(),
+        window,
+        occurrences: 0,
+        domains: Vec::new()
+    };
+
+    // Domains can not be shared if they are private
+    if !PrivacyPolicy::read(env)?.shows_domains() {
+        return Ok(reply);
+    }
+
+    // Excluded and hidden domains are not shown
+    let mut ignored_domains = get_excluded_domains(env)?;
+    ignored_domains.push(get_hidden_domain().to_owned());
+
+    if ignored_domains.contains(&target) {
+        return Ok(reply);
+    }
+
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    // Group the queries by client. Queries are stored in the order they were
+    // made, so each client's queries are too.
+    let mut client_queries: HashMap<i32, Vec<(u64, i32)>> = HashMap::new();
+
+    for query in queries
+        .iter()
+        .take(counters.total_queries as usize)
+        .filter(|query| !query.is_private)
+    {
+        client_queries
+            .entry(query.client_id)
+            .or_default()
+            .push((query.timestamp as u64, query.domain_id));
+    }
+
+    let domain_name = |domain_id: i32| domains[domain_id as usize].get_domain(&strings);
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+
+    for client_queries in client_queries.values() {
+        for (index, &(start, domain_id)) in client_queries.iter().enumerate() {
+            if domain_name(domain_id) != target {
+                continue;
+            }
+
+            reply.occurrences += 1;
+
+            // Count each domain once in this window
+            let window_domains: HashSet<i32> = client_queries[index + 1..]
+                .iter()
+                .take_while(|&&(timestamp, _)| timestamp <= start + window)
+                .map(|&(_, domain_id)| domain_id)
+                .filter(|&domain_id| domain_name(domain_id) != target)
+                .collect();
+
+            for domain_id in window_domains {
+                *counts.entry(domain_id).or_default() += 1;
+            }
+        }
+    }
+
+    let mut cooccurring: Vec<CooccurrenceItem> = counts
+        .into_iter()
+        .map(|(domain_id, count)| CooccurrenceItem {
+            domain: domain_name(domain_id).to_owned(),
+            count
+        })
+        .filter(|item| !ignored_domains.contains(&item.domain))
+        .collect();
+
+    // The most frequent domains are first
+    cooccurring.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.domain.cmp(&b.domain)));
+    cooccurring.truncate(limit);
+    reply.domains = cooccurring;
+
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        ftl::{
+            FtlClient, FtlCounters, FtlDnssecType, FtlDomain, FtlMemory, FtlQuery,
+            FtlQueryReplyType, FtlQueryStatus, FtlQueryType, FtlRegexMatch, FtlSettings,
+            MAGIC_BYTE
+        },
+        testing::TestBuilder
+    };
+    use rocket::http::Status;
+    use std::collections::HashMap;
+
+    /// Shorthand for making `FtlQuery` structs
+    macro_rules! query {
+        ($id:expr, $domain:expr, $client:expr, $timestamp:expr) => {
+            FtlQuery {
+                magic: MAGIC_BYTE,
+                id: $id,
+                database_id: 0,
+                timestamp: $timestamp,
+                time_index: 1,
+                response_time: 1,
+                domain_id: $domain,
+                client_id: $client,
+                upstream_id: 0,
+                query_type: FtlQueryType::A,
+                status: FtlQueryStatus::Forward,
+                reply_type: FtlQueryReplyType::IP,
+                dnssec_type: FtlDnssecType::Unspecified,
+                is_complete: true,
+                is_private: false,
+                ad_bit: false
+            }
+        };
+    }
+
+    /// Two clients querying a site and its trackers
+    fn test_memory() -> FtlMemory {
+        let mut strings = HashMap::new();
+        strings.insert(1, "site.com".to_owned());
+        strings.insert(2, "tracker.com".to_owned());
+        strings.insert(3, "cdn.com".to_owned());
+        strings.insert(4, "other.com".to_owned());
+        strings.insert(5, "10.1.1.1".to_owned());
+        strings.insert(6, "10.1.1.2".to_owned());
+
+        FtlMemory::Test {
+            queries: vec![
+                // Client 0 loads the site twice
+                query!(1, 0, 0, 100),
+                query!(2, 1, 0, 101),
+                query!(3, 2, 0, 102),
+                query!(4, 1, 0, 103),
+                query!(5, 3, 0, 150),
+                query!(6, 0, 0, 200),
+                query!(7, 1, 0, 205),
+                // Client 1 queries the site's CDN without the site
+                query!(8, 2, 1, 100),
+                query!(9, 3, 1, 101),
+            ],
+            domains: vec![
+                FtlDomain::new(2, 0, 1, FtlRegexMatch::NotBlocked),
+                FtlDomain::new(3, 0, 2, FtlRegexMatch::NotBlocked),
+                FtlDomain::new(2, 0, 3, FtlRegexMatch::NotBlocked),
+                FtlDomain::new(2, 0, 4, FtlRegexMatch::NotBlocked),
+            ],
+            over_time: Vec::new(),
+            strings,
+            clients: vec![FtlClient::new(7, 0, 5, None), FtlClient::new(2, 0, 6, None)],
+            upstreams: Vec::new(),
+            counters: FtlCounters {
+                total_queries: 9,
+                total_domains: 4,
+                total_clients: 2,
+                ..FtlCounters::default()
+            },
+            settings: FtlSettings::default()
+        }
+    }
+
+    /// Domains are counted once per window, and only for the same client
+    #[test]
+    fn cooccurrence() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/cooccurrence?domain=site.com")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "domain": "site.com",
+                "window": 10,
+                "occurrences": 2,
+                "domains": [
+                    { "domain": "tracker.com", "count": 2 },
+                    { "domain": "cdn.com", "count": 1 }
+                ]
+            }))
+            .test();
+    }
+
+    /// A longer window includes later queries
+    #[test]
+    fn longer_window() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/cooccurrence?domain=site.com&window=60&limit=2")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "domain": "site.com",
+                "window": 60,
+                "occurrences": 2,
+                "domains": [
+                    { "domain": "tracker.com", "count": 2 },
+                    { "domain": "cdn.com", "count": 1 }
+                ]
+            }))
+            .test();
+    }
+
+    /// Excluded domains are not shown
+    #[test]
+    fn excluded() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/cooccurrence?domain=site.com")
+            .ftl_memory(test_memory())
+            .file(PiholeFile::SetupVars, "API_EXCLUDE_DOMAINS=tracker.com")
+            .expect_json(json!({
+                "domain": "site.com",
+                "window": 10,
+                "occurrences": 2,
+                "domains": [
+                    { "domain": "cdn.com", "count": 1 }
+                ]
+            }))
+            .test();
+    }
+
+    /// Domains are not shown if they are private
+    #[test]
+    fn privacy() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/cooccurrence?domain=site.com")
+            .ftl_memory(test_memory())
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=1")
+            .expect_json(json!({
+                "domain": "site.com",
+                "window": 10,
+                "occurrences": 0,
+                "domains": []
+            }))
+            .test();
+    }
+
+    /// The window can not be empty or too long
+    #[test]
+    fn invalid_window() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/cooccurrence?domain=site.com&window=0")
+            .ftl_memory(test_memory())
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,227 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Query Types Endpoint - DB Version
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    client_nicknames::ClientNicknames,
+    databases::long_term::{LongTermDatabase, StoreConnection},
+    env::Env,
+    ftl::FtlQueryType,
+    metrics::time_database,
+    routes::{
+        auth::User,
+        stats::{
+            client_query_types::{query_type_index, ClientQueryTypeItem, ClientQueryTypes},
+            database::get_ignored_clients,
+            privacy::PrivacyPolicy
+        }
+    },
+    settings::ValueType,
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
+use failure::ResultExt;
+use rocket::State;
+use std::collections::HashMap;
+
+/// Get the number of queries of each query type made by each client in the
+/// time range, as a matrix of clients and query types
+#[get("/stats/database/clients/query_types?<from>&<until>")]
+pub fn client_query_types_db(
+    _auth: User,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>,
+    db: LongTermDatabase,
+    from: u64,
+    until: u64
+) -> Reply {
+    reply_result(time_database(|| {
+        client_query_types_db_impl(&env, &nicknames, db.connection(), from, until)
+    }))
+}
+
+/// Count the query types of each client from the database
+fn client_query_types_db_impl(
+    env: &Env,
+    nicknames: &ClientNicknames,
+    db: StoreConnection,
+    from: u64,
+    until: u64
+) -> Result<ClientQueryTypes, Error> {
+    // Check if the client details are private
+    if !PrivacyPolicy::read(env)?.shows_clients() {
+        return Ok(ClientQueryTypes::new(Vec::new()));
+    }
+
+    let ignored_clients = get_ignored_clients(env)?;
+    let mut counts: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for (client_identifier, query_type, count) in
+        execute_client_query_types_query(db, from, until, ignored_clients)?
+    {
+        // Skip query types which are not known
+        let query_type = match FtlQueryType::from_number(query_type as isize) {
+            Some(query_type) => query_type,
+            None => continue
+        };
+
+        counts
+            .entry(client_identifier)
+            .or_insert_with(|| vec![0; FtlQueryType::variants().len()])
+            [query_type_index(query_type)] += count as usize;
+    }
+
+    let clients = counts
+        .into_iter()
+        .map(|(client_identifier, counts)| {
+            if ValueType::Ipv4.is_valid(&client_identifier)
+                || ValueType::Ipv6.is_valid(&client_identifier)
+            {
+                // If the identifier is an IP address, use it as the client IP
+                ClientQueryTypeItem {
+                    name: nicknames.get(&client_identifier).unwrap_or_default(),
+                    ip: client_identifier,
+                    counts
+                }
+            } else {
+                // If the identifier is not an IP address, use it as the name
+                ClientQueryTypeItem {
+                    name: client_identifier,
+                    ip: "".to_owned(),
+                    counts
+                }
+            }
+        })
+        .collect();
+
+    Ok(ClientQueryTypes::new(clients))
+}
+
+/// Create and execute the database query to count the queries of each client
+/// and query type. The returned Vec contains the client identifier, query
+/// type, and count.
+fn execute_client_query_types_query(
+    db: StoreConnection,
+    from: u64,
+    until: u64,
+    ignored_clients: Vec<String>
+) -> Result<Vec<(String, i32, i64)>, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    Ok(with_store!(db, |db| {
+        queries
+            .select((client, query_type, sql::<BigInt>("COUNT(*)")))
+            // Only consider queries in the time interval
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.le(until as i32))
+            // Filter out ignored clients
+            .filter(client.ne_all(ignored_clients))
+            // Group queries by client and query type
+            .group_by((client, query_type))
+            .load::<(String, i32, i64)>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::client_query_types_db_impl;
+    use crate::{
+        client_nicknames::ClientNicknames,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
+        env::{Config, Env, PiholeFile},
+        routes::stats::client_query_types::ClientQueryTypes,
+        testing::TestEnvBuilder
+    };
+    use std::collections::HashMap;
+
+    const FROM_TIMESTAMP: u64 = 0;
+    const UNTIL_TIMESTAMP: u64 = 177_180;
+
+    /// The query types of each client are counted, with the most active
+    /// client first
+    #[test]
+    fn all_clients() {
+        let db = connect_to_test_db();
+        let env = Env::Test(Config::default(), HashMap::new());
+        let actual = client_query_types_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
+
+        assert_eq!(actual.query_types[0], "A");
+        assert_eq!(actual.clients.len(), 2);
+        assert_eq!(actual.clients[0].ip, "127.0.0.1");
+        assert_eq!(actual.clients[0].counts.iter().sum::<usize>(), 93);
+        assert_eq!(actual.clients[1].ip, "10.1.1.1");
+        assert_eq!(actual.clients[1].counts.iter().sum::<usize>(), 1);
+
+        // The counts of each query type add up to the query type totals
+        let totals: Vec<usize> = (0..7)
+            .map(|index| {
+                actual
+                    .clients
+                    .iter()
+                    .map(|client| client.counts[index])
+                    .sum()
+            })
+            .collect();
+        assert_eq!(totals, vec![36, 35, 0, 0, 0, 23, 0]);
+    }
+
+    /// Privacy level 2 does not show any clients
+    #[test]
+    fn privacy() {
+        let db = connect_to_test_db();
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2")
+                .build()
+        );
+        let actual = client_query_types_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
+
+        assert_eq!(actual, ClientQueryTypes::new(Vec::new()));
+    }
+
+    /// Excluded clients are not shown
+    #[test]
+    fn excluded_clients() {
+        let db = connect_to_test_db();
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, "API_EXCLUDE_CLIENTS=127.0.0.1")
+                .build()
+        );
+        let actual = client_query_types_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
+
+        assert_eq!(actual.clients.len(), 1);
+        assert_eq!(actual.clients[0].ip, "10.1.1.1");
+        assert_eq!(actual.clients[0].counts.iter().sum::<usize>(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Clients Endpoint - DB Version
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    client_nicknames::ClientNicknames,
+    databases::long_term::{LongTermDatabase, StoreConnection},
+    env::Env,
+    ftl::BLOCKED_STATUSES,
+    metrics::time_database,
+    routes::{
+        auth::User,
+        fields::{select_fields, FieldSelection},
+        stats::{database::get_ignored_clients, privacy::PrivacyPolicy}
+    },
+    settings::ValueType,
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
+use failure::ResultExt;
+use rocket::State;
+
+/// Get the query counts of every client between `from` and `until`, from the
+/// database. With `fields`, only the selected fields of each client are
+/// included.
+#[get("/stats/database/clients?<from>&<until>&<fields>")]
+pub fn clients_db(
+    from: u64,
+    until: u64,
+    fields: Option<FieldSelection>,
+    _auth: User,
+    db: LongTermDatabase,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>
+) -> Reply {
+    let clients =
+        time_database(|| clients_db_impl(from, until, db.connection(), &env, &nicknames))?;
+
+    reply_data(select_fields(clients, fields.as_ref()))
+}
+
+/// A client and its query counts in the requested time range
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientCountsItem {
+    pub name: String,
+    pub ip: String,
+    pub total_queries: usize,
+    pub blocked_queries: usize
+}
+
+/// Get the query counts of every client between `from` and `until`, with
+/// the busiest clients first
+fn clients_db_impl(
+    from: u64,
+    until: u64,
+    db: StoreConnection,
+    env: &Env,
+    nicknames: &ClientNicknames
+) -> Result<Vec<ClientCountsItem>, Error> {
+    if from > until {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    // Check if client details are private
+    if !PrivacyPolicy::read(env)?.shows_clients() {
+        return Ok(Vec::new());
+    }
+
+    let ignored_clients = get_ignored_clients(env)?;
+
+    Ok(get_client_counts(db, from, until, ignored_clients)?
+        .into_iter()
+        .map(|(client_identifier, total, blocked)| {
+            // Older databases store the client's name instead of its IP
+            // address if it had one
+            let (name, ip) = if ValueType::Ipv4.is_valid(&client_identifier)
+                || ValueType::Ipv6.is_valid(&client_identifier)
+            {
+                (
+                    nicknames.get(&client_identifier).unwrap_or_default(),
+                    client_identifier
+                )
+            } else {
+                (client_identifier, "".to_owned())
+            };
+
+            ClientCountsItem {
+                name,
+                ip,
+                total_queries: total as usize,
+                blocked_queries: blocked as usize
+            }
+        })
+        .collect())
+}
+
+/// Count the total and blocked queries of each client in the time range.
+/// The returned Vec contains each client's identifier, total count, and
+/// blocked count, sorted by the total count.
+fn get_client_counts(
+    db: StoreConnection,
+    from: u64,
+    until: u64,
+    ignored_clients: Vec<String>
+) -> Result<Vec<(String, i64, i64)>, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    // The blocked queries are counted, because SUM would be a decimal in MySQL
+    let blocked_sql = format!(
+        "COUNT(CASE WHEN status IN ({}) THEN 1 END)",
+        BLOCKED_STATUSES
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+
+    Ok(with_store!(db, |db| {
+        queries
+            // The raw SQL is used because Diesel does not support mixing
+            // aggregate and non-aggregate data with group_by
+            .select((
+                client,
+                sql::<BigInt>("COUNT(*)"),
+                sql::<BigInt>(&blocked_sql)
+            ))
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.le(until as i32))
+            .filter(client.ne_all(ignored_clients))
+            .group_by(client)
+            .order((sql::<BigInt>("COUNT(*)").desc(), client))
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{clients_db_impl, ClientCountsItem};
+    use crate::{
+        client_nicknames::ClientNicknames,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+    use std::collections::HashMap;
+
+    const FROM_TIMESTAMP: u64 = 0;
+    const UNTIL_TIMESTAMP: u64 = 177_180;
+
+    /// Every client in the range is counted, busiest first
+    #[test]
+    fn client_counts() {
+        let expected = vec![
+            ClientCountsItem {
+                name: "".to_owned(),
+                ip: "127.0.0.1".to_owned(),
+                total_queries: 93,
+                blocked_queries: 0
+            },
+            ClientCountsItem {
+                name: "".to_owned(),
+                ip: "10.1.1.1".to_owned(),
+                total_queries: 1,
+                blocked_queries: 0
+            },
+        ];
+
+        let db = connect_to_test_db();
+        let env = Env::Test(Config::default(), HashMap::new());
+        let actual = clients_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db),
+            &env,
+            &ClientNicknames::default()
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    /// Excluded clients are not counted
+    #[test]
+    fn excluded_clients() {
+        let db = connect_to_test_db();
+        let env_builder =
+            TestEnvBuilder::new().file(PiholeFile::SetupVars, "API_EXCLUDE_CLIENTS=127.0.0.1");
+        let env = Env::Test(Config::default(), env_builder.build());
+        let actual = clients_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db),
+            &env,
+            &ClientNicknames::default()
+        )
+        .unwrap();
+
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].ip, "10.1.1.1");
+    }
+
+    /// No clients are shown if client details are private
+    #[test]
+    fn privacy_level() {
+        let db = connect_to_test_db();
+        let env_builder = TestEnvBuilder::new().file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2");
+        let env = Env::Test(Config::default(), env_builder.build());
+        let actual = clients_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db),
+            &env,
+            &ClientNicknames::default()
+        )
+        .unwrap();
+
+        assert!(actual.is_empty());
+    }
+}
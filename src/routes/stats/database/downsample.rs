@@ -0,0 +1,133 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Over Time Downsampling
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use rocket::{http::RawStr, request::FromFormValue};
+
+/// How the counts of merged over time slots are combined
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum DownsampleMode {
+    Avg,
+    Max
+}
+
+impl Default for DownsampleMode {
+    fn default() -> Self {
+        DownsampleMode::Avg
+    }
+}
+
+impl<'v> FromFormValue<'v> for DownsampleMode {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Self, Self::Error> {
+        match form_value.as_str() {
+            "avg" => Ok(DownsampleMode::Avg),
+            "max" => Ok(DownsampleMode::Max),
+            _ => Err(form_value)
+        }
+    }
+}
+
+impl DownsampleMode {
+    /// Combine the counts of the merged slots into one count. The average is
+    /// rounded to the nearest count.
+    pub fn combine(self, counts: impl Iterator<Item = usize>) -> usize {
+        match self {
+            DownsampleMode::Avg => {
+                let (sum, len) = counts.fold((0, 0), |(sum, len), count| (sum + count, len + 1));
+
+                if len == 0 {
+                    0
+                } else {
+                    (sum as f64 / len as f64).round() as usize
+                }
+            }
+            DownsampleMode::Max => counts.max().unwrap_or(0)
+        }
+    }
+}
+
+/// Get the number of slots to merge so that the (aligned) range has at most
+/// `max_points` slots. Zero `max_points` disables downsampling.
+pub fn merge_factor(from: u64, until: u64, interval: usize, max_points: usize) -> usize {
+    let slots = (until - from) as usize / interval;
+
+    if max_points == 0 || slots <= max_points {
+        1
+    } else {
+        (slots + max_points - 1) / max_points
+    }
+}
+
+/// Merge every `factor` slots into one slot with `merge`, which is given the
+/// slots to merge and the timestamp of the merged slot. The timestamps are
+/// centered in their slots, so the merged timestamp is centered in the
+/// merged slot.
+pub fn downsample<T>(
+    slots: Vec<T>,
+    factor: usize,
+    interval: usize,
+    timestamp: impl Fn(&T) -> u64,
+    merge: impl Fn(&[T], u64) -> T
+) -> Vec<T> {
+    if factor <= 1 {
+        return slots;
+    }
+
+    slots
+        .chunks(factor)
+        .map(|chunk| {
+            let start = timestamp(&chunk[0]) - (interval / 2) as u64;
+            merge(chunk, start + (factor * interval / 2) as u64)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{downsample, merge_factor, DownsampleMode};
+
+    /// The range is only downsampled if it has too many slots, and then by
+    /// the smallest factor which fits
+    #[test]
+    fn factor() {
+        assert_eq!(merge_factor(0, 6000, 600, 10), 1);
+        assert_eq!(merge_factor(0, 6600, 600, 10), 2);
+        assert_eq!(merge_factor(0, 60_000, 600, 10), 10);
+        assert_eq!(merge_factor(0, 60_000, 600, 0), 1);
+    }
+
+    /// Merged slots are averaged or use the maximum, and are centered in the
+    /// merged interval
+    #[test]
+    fn merge_slots() {
+        let slots = vec![(50, 1), (150, 4), (250, 2), (350, 0), (450, 7)];
+
+        let merge = |mode: DownsampleMode| {
+            downsample(
+                slots.clone(),
+                2,
+                100,
+                |slot| slot.0,
+                |chunk, timestamp| (timestamp, mode.combine(chunk.iter().map(|slot| slot.1)))
+            )
+        };
+
+        assert_eq!(
+            merge(DownsampleMode::Avg),
+            vec![(100, 3), (300, 1), (500, 7)]
+        );
+        assert_eq!(
+            merge(DownsampleMode::Max),
+            vec![(100, 4), (300, 2), (500, 7)]
+        );
+    }
+}
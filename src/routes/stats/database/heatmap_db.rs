@@ -0,0 +1,120 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Weekly Query Heatmap Endpoint - DB Version
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::long_term::{LongTermDatabase, StoreConnection},
+    ftl::BLOCKED_STATUSES,
+    metrics::time_database,
+    routes::auth::User,
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{BigInt, Integer}
+};
+use failure::ResultExt;
+
+/// Get the number of queries and blocked queries in each hour of the week,
+/// from the database
+#[get("/stats/database/heatmap?<from>&<until>")]
+pub fn heatmap_db(from: u64, until: u64, _auth: User, db: LongTermDatabase) -> Reply {
+    reply_result(time_database(|| {
+        heatmap_db_impl(from, until, db.connection())
+    }))
+}
+
+/// Count the queries between `from` and `until` by weekday and hour. The
+/// weekdays start on Sunday, and the times are in UTC.
+fn heatmap_db_impl(from: u64, until: u64, db: StoreConnection) -> Result<HeatmapReply, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    if from >= until {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    // COUNT is used instead of SUM, because SUM is a decimal in MySQL
+    let blocked_sql = format!(
+        "COUNT(CASE WHEN status IN ({}) THEN 1 END)",
+        BLOCKED_STATUSES
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+
+    let rows: Vec<(i32, i32, i64, i64)> = with_store!(db, |db| {
+        // SQL snippets for calculating the weekday and hour of the query
+        let weekday_sql = sql::<Integer>(db.weekday_sql());
+        let hour_sql = sql::<Integer>(db.hour_sql());
+
+        queries
+            .select((
+                &weekday_sql,
+                &hour_sql,
+                sql::<BigInt>("COUNT(*)"),
+                sql::<BigInt>(&blocked_sql)
+            ))
+            .filter(status.ne(0))
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.le(until as i32))
+            .group_by((&weekday_sql, &hour_sql))
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
+
+    let mut heatmap = HeatmapReply::default();
+
+    for (weekday, hour, total, blocked) in rows {
+        let (weekday, hour) = (weekday as usize, hour as usize);
+
+        heatmap.queries[weekday][hour] = total;
+        heatmap.blocked[weekday][hour] = blocked;
+    }
+
+    Ok(heatmap)
+}
+
+/// The query counts, indexed by weekday (Sunday first) and then by hour
+#[derive(Serialize, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct HeatmapReply {
+    pub queries: [[i64; 24]; 7],
+    pub blocked: [[i64; 24]; 7]
+}
+
+#[cfg(test)]
+mod test {
+    use super::heatmap_db_impl;
+    use crate::databases::{ftl::connect_to_test_db, long_term::StoreConnection};
+
+    /// Queries are counted in the hour and weekday they were made. The test
+    /// queries are on Friday and Saturday, January 2nd and 3rd 1970.
+    #[test]
+    fn heatmap() {
+        let db = connect_to_test_db();
+        let heatmap = heatmap_db_impl(164_400, 176_999, StoreConnection::Sqlite(&db)).unwrap();
+
+        assert_eq!(heatmap.queries[5][21], 33);
+        assert_eq!(heatmap.queries[5][22], 3);
+        assert_eq!(heatmap.queries[5][23], 3);
+        assert_eq!(heatmap.queries[6][0], 11);
+        assert_eq!(heatmap.queries.iter().flatten().sum::<i64>(), 50);
+        assert_eq!(heatmap.blocked.iter().flatten().sum::<i64>(), 0);
+    }
+
+    /// The range must not be empty
+    #[test]
+    fn empty_range() {
+        let db = connect_to_test_db();
+
+        assert!(heatmap_db_impl(200, 100, StoreConnection::Sqlite(&db)).is_err());
+    }
+}
@@ -8,15 +8,22 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+mod client_query_types_db;
+mod clients_db;
+mod downsample;
+mod heatmap_db;
 mod over_time_clients_db;
 mod over_time_history_db;
+mod query_statuses_db;
 mod query_types_db;
+mod rollup_db;
 mod summary_db;
 mod top_clients_db;
 mod top_domains_db;
 mod upstreams_db;
 
 pub use self::{
-    over_time_clients_db::*, over_time_history_db::*, query_types_db::*, summary_db::*,
+    client_query_types_db::*, clients_db::*, heatmap_db::*, over_time_clients_db::*,
+    over_time_history_db::*, query_statuses_db::*, query_types_db::*, rollup_db::*, summary_db::*,
     top_clients_db::*, top_domains_db::*, upstreams_db::*
 };
@@ -9,53 +9,84 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    client_nicknames::ClientNicknames,
+    databases::long_term::{LongTermDatabase, StoreConnection},
     env::Env,
     ftl::ClientReply,
+    metrics::time_database,
     routes::{
         auth::User,
         stats::{
-            common::{get_excluded_clients, get_hidden_client_ip},
-            database::over_time_history_db::align_from_until,
-            over_time_clients::{OverTimeClientItem, OverTimeClients}
+            common::get_excluded_clients,
+            database::{
+                downsample::{downsample, merge_factor, DownsampleMode},
+                over_time_history_db::align_from_until
+            },
+            over_time_clients::{OverTimeClientItem, OverTimeClients},
+            privacy::{get_hidden_client_ip, PrivacyPolicy}
         }
     },
     settings::ValueType,
     util::{reply_result, Error, ErrorKind, Reply}
 };
-use diesel::{dsl::sql, prelude::*, sql_types::BigInt, SqliteConnection};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{BigInt, Integer}
+};
 use failure::ResultExt;
 use rocket::State;
 use std::collections::HashMap;
 
-/// Get the clients queries over time data from the database
-#[get("/stats/database/overTime/clients?<from>&<until>&<interval>")]
+/// Get the clients queries over time data from the database. Like the
+/// overTime history, long ranges are downsampled.
+#[get("/stats/database/overTime/clients?<from>&<until>&<interval>&<downsample>")]
+#[allow(clippy::too_many_arguments)]
 pub fn over_time_clients_db(
     from: u64,
     until: u64,
     interval: Option<usize>,
+    downsample: Option<DownsampleMode>,
     _auth: User,
-    db: FtlDatabase,
-    env: State<Env>
+    db: LongTermDatabase,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>
 ) -> Reply {
-    reply_result(over_time_clients_db_impl(
-        from,
-        until,
-        interval.unwrap_or(600),
-        &db as &SqliteConnection,
-        &env
-    ))
+    reply_result(time_database(|| {
+        over_time_clients_db_impl(
+            from,
+            until,
+            interval.unwrap_or(600),
+            downsample.unwrap_or_default(),
+            db.connection(),
+            &env,
+            &nicknames
+        )
+    }))
 }
 
-/// Get the clients queries over time data from the database
+/// Get the clients queries over time data from the database, merging slots
+/// so there are at most the configured maximum of them
 fn over_time_clients_db_impl(
     from: u64,
     until: u64,
     interval: usize,
-    db: &SqliteConnection,
-    env: &Env
+    mode: DownsampleMode,
+    db: StoreConnection,
+    env: &Env,
+    nicknames: &ClientNicknames
 ) -> Result<OverTimeClients, Error> {
     let (from, until) = align_from_until(from, until, interval as u64)?;
+    let factor = merge_factor(from, until, interval, env.config().max_over_time_points());
+
+    // Check if client details are private
+    if !PrivacyPolicy::read(env)?.shows_clients() {
+        return Ok(OverTimeClients {
+            over_time: Vec::new(),
+            clients: Vec::new(),
+            interval: Some(interval * factor)
+        });
+    }
 
     // Load the clients (names or IP addresses)
     let client_identifiers = get_client_identifiers(from, until, db, env)?;
@@ -66,13 +97,19 @@ fn over_time_clients_db_impl(
         .map(|timestamp| (timestamp, vec![0; client_identifiers.len()]))
         .collect();
 
-    for (client_index, client_identifier) in client_identifiers.iter().enumerate() {
-        // For each client, get the overTime data
-        let client_over_time = get_client_over_time(from, until, interval, client_identifier, db)?;
+    // The position of each client in the overTime data
+    let client_indices: HashMap<&str, usize> = client_identifiers
+        .iter()
+        .enumerate()
+        .map(|(index, client_identifier)| (client_identifier.as_str(), index))
+        .collect();
 
-        // Add the client's data to the overTime map
-        for (timestamp, value) in client_over_time {
-            let client_data = over_time_data.get_mut(&(timestamp as u64)).unwrap();
+    // Add the clients' data to the overTime map
+    for (timestamp, client_identifier, value) in get_clients_over_time(from, until, interval, db)? {
+        if let (Some(client_data), Some(&client_index)) = (
+            over_time_data.get_mut(&(timestamp as u64)),
+            client_indices.get(client_identifier.as_str())
+        ) {
             client_data[client_index] = value as usize;
         }
     }
@@ -90,6 +127,19 @@ fn over_time_clients_db_impl(
     // Make sure we return the overTime data in sorted order (by timestamp)
     over_time.sort();
 
+    let over_time = downsample(
+        over_time,
+        factor,
+        interval,
+        |slot| slot.timestamp,
+        |slots, timestamp| OverTimeClientItem {
+            timestamp,
+            data: (0..client_identifiers.len())
+                .map(|index| mode.combine(slots.iter().map(|slot| slot.data[index])))
+                .collect()
+        }
+    );
+
     // Convert the client identifiers into the output format
     let clients = client_identifiers
         .into_iter()
@@ -99,7 +149,7 @@ fn over_time_clients_db_impl(
             {
                 // If the identifier is an IP address, use it as the client IP
                 ClientReply {
-                    name: "".to_owned(),
+                    name: nicknames.get(&client_identifier).unwrap_or_default(),
                     ip: client_identifier
                 }
             } else {
@@ -112,7 +162,11 @@ fn over_time_clients_db_impl(
         })
         .collect();
 
-    Ok(OverTimeClients { over_time, clients })
+    Ok(OverTimeClients {
+        over_time,
+        clients,
+        interval: Some(interval * factor)
+    })
 }
 
 /// Get clients which made queries during the interval. The values may be either
@@ -120,7 +174,7 @@ fn over_time_clients_db_impl(
 fn get_client_identifiers(
     from: u64,
     until: u64,
-    db: &SqliteConnection,
+    db: StoreConnection,
     env: &Env
 ) -> Result<Vec<String>, Error> {
     use crate::databases::ftl::queries::dsl::*;
@@ -129,59 +183,60 @@ fn get_client_identifiers(
     let mut ignored_clients = get_excluded_clients(env)?;
     ignored_clients.push(get_hidden_client_ip().to_owned());
 
-    let client_identifiers = queries
-        .select(client)
-        .distinct()
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.lt(until as i32))
-        .filter(client.ne_all(ignored_clients))
-        .load(db)
-        .context(ErrorKind::FtlDatabase)?;
+    let client_identifiers = with_store!(db, |db| {
+        queries
+            .select(client)
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.lt(until as i32))
+            .filter(client.ne_all(ignored_clients))
+            .group_by(client)
+            // Order the clients by their first query, so the order is the
+            // same in every database
+            .order((sql::<Integer>("MIN(timestamp)"), client))
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
 
     Ok(client_identifiers)
 }
 
-/// Get the overTime data for the client in the specified interval
-fn get_client_over_time(
+/// Get the overTime data of all clients in the specified interval, as the
+/// interval timestamp, client identifier, and query count
+fn get_clients_over_time(
     from: u64,
     until: u64,
     interval: usize,
-    client_identifier: &str,
-    db: &SqliteConnection
-) -> Result<HashMap<i32, i64>, Error> {
+    db: StoreConnection
+) -> Result<Vec<(i32, String, i64)>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    // SQL snippet for calculating the interval timestamp of the query
-    let interval_sql = sql(&format!(
-        "(timestamp / {interval}) * {interval}",
-        interval = interval
-    ));
-
-    // Create SQL query
-    let sql_query = queries
-        .select((&interval_sql, sql::<BigInt>("COUNT(*)")))
-        .filter(client.eq(client_identifier))
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.lt(until as i32))
-        .group_by(&interval_sql);
-
-    // Execute SQL query
-    Ok(sql_query
-        .load(db)
-        .context(ErrorKind::FtlDatabase)?
-        // Convert to HashMap
-        .into_iter()
-        .collect())
+    Ok(with_store!(db, |db| {
+        // SQL snippet for calculating the interval timestamp of the query
+        let interval_sql = sql::<Integer>(&db.interval_sql(interval));
+
+        // Create and execute the SQL query
+        queries
+            .select((&interval_sql, client, sql::<BigInt>("COUNT(*)")))
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.lt(until as i32))
+            .group_by((&interval_sql, client))
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?)
 }
 
 #[cfg(test)]
 mod test {
-    use super::{get_client_identifiers, get_client_over_time, over_time_clients_db_impl};
+    use super::{get_client_identifiers, get_clients_over_time, over_time_clients_db_impl};
     use crate::{
-        databases::ftl::connect_to_test_db,
+        client_nicknames::ClientNicknames,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
         env::{Config, Env, PiholeFile},
         ftl::ClientReply,
-        routes::stats::over_time_clients::{OverTimeClientItem, OverTimeClients},
+        routes::stats::{
+            database::downsample::DownsampleMode,
+            over_time_clients::{OverTimeClientItem, OverTimeClients}
+        },
         testing::TestEnvBuilder
     };
     use std::collections::HashMap;
@@ -217,14 +272,22 @@ mod test {
                     timestamp: 165_900,
                     data: vec![0, 0]
                 },
-            ]
+            ],
+            interval: Some(INTERVAL)
         };
 
         let db = connect_to_test_db();
         let env = Env::Test(Config::default(), HashMap::new());
-        let actual =
-            over_time_clients_db_impl(FROM_TIMESTAMP, UNTIL_TIMESTAMP, INTERVAL, &db, &env)
-                .unwrap();
+        let actual = over_time_clients_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            INTERVAL,
+            DownsampleMode::Avg,
+            StoreConnection::Sqlite(&db),
+            &env,
+            &ClientNicknames::default()
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -237,7 +300,13 @@ mod test {
 
         let db = connect_to_test_db();
         let env = Env::Test(Config::default(), HashMap::new());
-        let actual = get_client_identifiers(FROM_TIMESTAMP, UNTIL_TIMESTAMP, &db, &env).unwrap();
+        let actual = get_client_identifiers(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db),
+            &env
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -254,24 +323,65 @@ mod test {
                 .file(PiholeFile::SetupVars, "API_EXCLUDE_CLIENTS=10.1.1.1")
                 .build()
         );
-        let actual = get_client_identifiers(FROM_TIMESTAMP, UNTIL_TIMESTAMP, &db, &env).unwrap();
+        let actual = get_client_identifiers(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db),
+            &env
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
 
-    /// The client-specific overTime data is retrieved correctly for a specified
-    /// time interval
+    /// The overTime data of each client is retrieved correctly for a
+    /// specified time interval
     #[test]
-    fn client_over_time() {
-        let mut expected: HashMap<i32, i64> = HashMap::new();
-        expected.insert(164_400, 25);
-        expected.insert(165_000, 7);
+    fn clients_over_time() {
+        let mut expected = vec![
+            (164_400, "10.1.1.1".to_owned(), 1),
+            (164_400, "127.0.0.1".to_owned(), 25),
+            (165_000, "127.0.0.1".to_owned(), 7),
+        ];
 
         let db = connect_to_test_db();
-        let actual =
-            get_client_over_time(FROM_TIMESTAMP, UNTIL_TIMESTAMP, INTERVAL, "127.0.0.1", &db)
-                .unwrap();
-
+        let mut actual = get_clients_over_time(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            INTERVAL,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
+
+        expected.sort();
+        actual.sort();
         assert_eq!(actual, expected);
     }
+
+    /// No clients are shown if client details are private
+    #[test]
+    fn privacy_level() {
+        let db = connect_to_test_db();
+        let env_builder = TestEnvBuilder::new().file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2");
+        let env = Env::Test(Config::default(), env_builder.build());
+        let actual = over_time_clients_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            INTERVAL,
+            DownsampleMode::Avg,
+            StoreConnection::Sqlite(&db),
+            &env,
+            &ClientNicknames::default()
+        )
+        .unwrap();
+
+        assert_eq!(
+            actual,
+            OverTimeClients {
+                over_time: Vec::new(),
+                clients: Vec::new(),
+                interval: Some(INTERVAL)
+            }
+        );
+    }
 }
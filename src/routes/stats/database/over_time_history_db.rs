@@ -9,41 +9,76 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    databases::long_term::{LongTermDatabase, StoreConnection},
+    env::Env,
     ftl::BLOCKED_STATUSES,
-    routes::{auth::User, stats::over_time_history::OverTimeItem},
+    metrics::time_database,
+    routes::{
+        auth::User,
+        stats::{
+            database::downsample::{downsample, merge_factor, DownsampleMode},
+            over_time_history::OverTimeItem
+        }
+    },
     util::{reply_result, Error, ErrorKind, Reply}
 };
-use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{BigInt, Integer}
+};
 use failure::ResultExt;
+use rocket::State;
 use std::collections::HashMap;
 
 /// Get the query history over time from the database
-/// (separated into blocked and not blocked)
-#[get("/stats/database/overTime/history?<from>&<until>&<interval>")]
+/// (separated into blocked and not blocked). If the range has more slots
+/// than the configured maximum, neighbouring slots are merged using
+/// `downsample` (`avg` or `max`), and the merged interval is returned.
+#[get("/stats/database/overTime/history?<from>&<until>&<interval>&<downsample>")]
 pub fn over_time_history_db(
     from: u64,
     until: u64,
     interval: Option<usize>,
+    downsample: Option<DownsampleMode>,
     _auth: User,
-    db: FtlDatabase
+    db: LongTermDatabase,
+    env: State<Env>
 ) -> Reply {
-    reply_result(over_time_history_db_impl(
-        from,
-        until,
-        interval.unwrap_or(600),
-        &db as &SqliteConnection
-    ))
+    reply_result(time_database(|| {
+        over_time_history_db_impl(
+            from,
+            until,
+            interval.unwrap_or(600),
+            env.config().max_over_time_points(),
+            downsample.unwrap_or_default(),
+            db.connection()
+        )
+    }))
+}
+
+/// The reply of the database overTime history endpoint
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct OverTimeHistory {
+    pub over_time: Vec<OverTimeItem>,
+    /// The interval of the slots, which is larger than the requested interval
+    /// if the slots were merged
+    pub interval: usize
 }
 
-/// Get the over time data from the database
+/// Get the over time data from the database, merging slots so there are at
+/// most `max_points` of them
 fn over_time_history_db_impl(
     from: u64,
     until: u64,
     interval: usize,
-    db: &SqliteConnection
-) -> Result<Vec<OverTimeItem>, Error> {
+    max_points: usize,
+    mode: DownsampleMode,
+    db: StoreConnection
+) -> Result<OverTimeHistory, Error> {
     let (from, until) = align_from_until(from, until, interval as u64)?;
+    let factor = merge_factor(from, until, interval, max_points);
 
     // Get the overTime data
     let total_intervals = get_total_intervals(from, until, interval, db)?;
@@ -65,17 +100,33 @@ fn over_time_history_db_impl(
         });
     }
 
-    Ok(over_time)
+    let over_time = downsample(
+        over_time,
+        factor,
+        interval,
+        |slot| slot.timestamp,
+        |slots, timestamp| OverTimeItem {
+            timestamp,
+            total_queries: mode.combine(slots.iter().map(|slot| slot.total_queries)),
+            blocked_queries: mode.combine(slots.iter().map(|slot| slot.blocked_queries))
+        }
+    );
+
+    Ok(OverTimeHistory {
+        over_time,
+        interval: interval * factor
+    })
 }
 
 /// Align `from` and `until` with the interval. Also check that the time
-/// interval is increasing from `from` to `until`. If it is not, an error is
-/// returned.
+/// interval is increasing from `from` to `until` and that the interval is not
+/// zero. If it is not, an error is returned.
 pub fn align_from_until(from: u64, until: u64, interval: u64) -> Result<(u64, u64), Error> {
     let is_range_increasing = from < until;
 
-    if !is_range_increasing {
-        // The timestamps should increase from "from" to "until"
+    if !is_range_increasing || interval == 0 {
+        // The timestamps should increase from "from" to "until", and the
+        // intervals can not be empty
         return Err(Error::from(ErrorKind::BadRequest));
     }
 
@@ -91,28 +142,24 @@ fn get_total_intervals(
     from: u64,
     until: u64,
     interval: usize,
-    db: &SqliteConnection
+    db: StoreConnection
 ) -> Result<HashMap<i32, i64>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    // SQL snippet for calculating the interval timestamp of the query
-    let interval_sql = sql(&format!(
-        "(timestamp / {interval}) * {interval}",
-        interval = interval
-    ));
-
-    // Create SQL query
-    let sql_query = queries
-        .select((&interval_sql, sql::<BigInt>("COUNT(*)")))
-        .filter(status.ne(0))
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.lt(until as i32))
-        .group_by(&interval_sql);
-
-    // Execute SQL query
-    Ok(sql_query
-        .load(&db as &SqliteConnection)
-        .context(ErrorKind::FtlDatabase)?
+    Ok(with_store!(db, |db| {
+        // SQL snippet for calculating the interval timestamp of the query
+        let interval_sql = sql::<Integer>(&db.interval_sql(interval));
+
+        // Create and execute the SQL query
+        queries
+            .select((&interval_sql, sql::<BigInt>("COUNT(*)")))
+            .filter(status.ne(0))
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.lt(until as i32))
+            .group_by(&interval_sql)
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?
         // Convert to HashMap
         .into_iter()
         .collect())
@@ -123,28 +170,24 @@ fn get_blocked_intervals(
     from: u64,
     until: u64,
     interval: usize,
-    db: &SqliteConnection
+    db: StoreConnection
 ) -> Result<HashMap<i32, i64>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    // SQL snippet for calculating the interval timestamp of the query
-    let interval_sql = sql(&format!(
-        "(timestamp / {interval}) * {interval}",
-        interval = interval
-    ));
-
-    // Create SQL query
-    let sql_query = queries
-        .select((&interval_sql, sql::<BigInt>("COUNT(*)")))
-        .filter(status.eq_any(&BLOCKED_STATUSES))
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.lt(until as i32))
-        .group_by(&interval_sql);
-
-    // Execute SQL query
-    Ok(sql_query
-        .load(&db as &SqliteConnection)
-        .context(ErrorKind::FtlDatabase)?
+    Ok(with_store!(db, |db| {
+        // SQL snippet for calculating the interval timestamp of the query
+        let interval_sql = sql::<Integer>(&db.interval_sql(interval));
+
+        // Create and execute the SQL query
+        queries
+            .select((&interval_sql, sql::<BigInt>("COUNT(*)")))
+            .filter(status.eq_any(&BLOCKED_STATUSES))
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.lt(until as i32))
+            .group_by(&interval_sql)
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?
         // Convert to HashMap
         .into_iter()
         .collect())
@@ -152,9 +195,13 @@ fn get_blocked_intervals(
 
 #[cfg(test)]
 mod test {
-    use super::{get_blocked_intervals, get_total_intervals, over_time_history_db_impl};
+    use super::{
+        align_from_until, get_blocked_intervals, get_total_intervals, over_time_history_db_impl,
+        OverTimeHistory
+    };
     use crate::{
-        databases::ftl::connect_to_test_db, routes::stats::over_time_history::OverTimeItem
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
+        routes::stats::{database::downsample::DownsampleMode, over_time_history::OverTimeItem}
     };
     use std::collections::HashMap;
 
@@ -165,26 +212,71 @@ mod test {
     /// Verify the over time data is retrieved correctly
     #[test]
     fn over_time_history_impl() {
-        let expected = vec![
-            OverTimeItem {
-                timestamp: 164_700,
-                total_queries: 26,
-                blocked_queries: 0
-            },
-            OverTimeItem {
-                timestamp: 165_300,
-                total_queries: 7,
-                blocked_queries: 0
-            },
-            OverTimeItem {
-                timestamp: 165_900,
-                total_queries: 0,
-                blocked_queries: 0
-            },
-        ];
+        let expected = OverTimeHistory {
+            over_time: vec![
+                OverTimeItem {
+                    timestamp: 164_700,
+                    total_queries: 26,
+                    blocked_queries: 0
+                },
+                OverTimeItem {
+                    timestamp: 165_300,
+                    total_queries: 7,
+                    blocked_queries: 0
+                },
+                OverTimeItem {
+                    timestamp: 165_900,
+                    total_queries: 0,
+                    blocked_queries: 0
+                },
+            ],
+            interval: INTERVAL
+        };
 
         let db = connect_to_test_db();
-        let actual = over_time_history_db_impl(164_400, 165_600, INTERVAL, &db).unwrap();
+        let actual = over_time_history_db_impl(
+            164_400,
+            165_600,
+            INTERVAL,
+            1000,
+            DownsampleMode::Avg,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    /// Ranges with more slots than the maximum are downsampled, and the
+    /// merged interval is reported
+    #[test]
+    fn downsampled() {
+        let expected = OverTimeHistory {
+            over_time: vec![
+                OverTimeItem {
+                    timestamp: 165_000,
+                    total_queries: 26,
+                    blocked_queries: 0
+                },
+                OverTimeItem {
+                    timestamp: 166_200,
+                    total_queries: 0,
+                    blocked_queries: 0
+                },
+            ],
+            interval: 2 * INTERVAL
+        };
+
+        let db = connect_to_test_db();
+        let actual = over_time_history_db_impl(
+            164_400,
+            165_600,
+            INTERVAL,
+            2,
+            DownsampleMode::Max,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -201,7 +293,13 @@ mod test {
         expected.insert(175_800, 3);
 
         let db = connect_to_test_db();
-        let actual = get_total_intervals(FROM_TIMESTAMP, UNTIL_TIMESTAMP, INTERVAL, &db).unwrap();
+        let actual = get_total_intervals(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            INTERVAL,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -212,8 +310,20 @@ mod test {
         let expected = HashMap::new();
 
         let db = connect_to_test_db();
-        let actual = get_blocked_intervals(FROM_TIMESTAMP, UNTIL_TIMESTAMP, INTERVAL, &db).unwrap();
+        let actual = get_blocked_intervals(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            INTERVAL,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
+
+    /// An interval of zero is rejected instead of never ending
+    #[test]
+    fn zero_interval() {
+        assert!(align_from_until(FROM_TIMESTAMP, UNTIL_TIMESTAMP, 0).is_err());
+    }
 }
@@ -0,0 +1,138 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Statuses Endpoint - DB Version
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::{
+        ftl::{DbEnum, DbQueryStatus},
+        long_term::{LongTermDatabase, StoreConnection}
+    },
+    ftl::FtlQueryStatus,
+    metrics::time_database,
+    routes::{auth::User, stats::query_statuses::QueryStatusReply},
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
+use failure::ResultExt;
+use std::collections::HashMap;
+
+/// Get query status counts from the database
+#[get("/stats/database/query_statuses?<from>&<until>")]
+pub fn query_statuses_db(from: u64, until: u64, _auth: User, db: LongTermDatabase) -> Reply {
+    reply_result(time_database(|| {
+        query_statuses_db_impl(from, until, db.connection())
+    }))
+}
+
+/// Get query status counts from the database. Statuses which the API does
+/// not know are listed after the known statuses, and are not counted as
+/// blocked.
+fn query_statuses_db_impl(
+    from: u64,
+    until: u64,
+    db: StoreConnection
+) -> Result<Vec<QueryStatusReply>, Error> {
+    let counts = get_query_status_counts(db, from, until)?;
+
+    let mut unknown_statuses: Vec<(u8, usize)> = counts
+        .iter()
+        .filter_map(|(&num, &count)| match DbQueryStatus::from_db(num) {
+            DbEnum::Unknown(num) => Some((num, count)),
+            DbEnum::Known(_) => None
+        })
+        .collect();
+    unknown_statuses.sort();
+
+    Ok(FtlQueryStatus::variants()
+        .iter()
+        .map(|&status| {
+            QueryStatusReply::new(status, counts.get(&(status as i32)).cloned().unwrap_or(0))
+        })
+        .chain(
+            unknown_statuses
+                .into_iter()
+                .map(|(num, count)| QueryStatusReply {
+                    name: format!("unknown ({})", num),
+                    status: i32::from(num),
+                    blocked: false,
+                    count
+                })
+        )
+        .collect())
+}
+
+/// Get the number of queries with each status in the specified time range,
+/// by status number. Statuses which are not in the database are left out.
+pub fn get_query_status_counts(
+    db: StoreConnection,
+    from: u64,
+    until: u64
+) -> Result<HashMap<i32, usize>, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    Ok(with_store!(db, |db| {
+        queries
+            // The raw SQL is used because Diesel does not support mixing
+            // aggregate and non-aggregate data with group_by
+            .select((status, sql::<BigInt>("COUNT(*)")))
+            .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
+            .group_by(status)
+            .get_results::<(i32, i64)>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?
+    .into_iter()
+    .map(|(query_status, count)| (query_status, count as usize))
+    .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_query_status_counts, query_statuses_db_impl};
+    use crate::databases::{ftl::connect_to_test_db, long_term::StoreConnection};
+    use std::collections::HashMap;
+
+    const FROM_TIMESTAMP: u64 = 0;
+    const UNTIL_TIMESTAMP: u64 = 177_180;
+
+    /// Verify the query status counts are accurate
+    #[test]
+    fn query_status_counts() {
+        let mut expected = HashMap::new();
+        expected.insert(0, 40);
+        expected.insert(2, 26);
+        expected.insert(3, 28);
+
+        let db = connect_to_test_db();
+        let actual = get_query_status_counts(
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    /// Every status is in the reply, with zero for statuses without queries
+    #[test]
+    fn all_statuses() {
+        let db = connect_to_test_db();
+        let reply = query_statuses_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
+
+        assert_eq!(reply.len(), 7);
+        assert_eq!(reply[1].name, "gravity");
+        assert_eq!(reply[1].count, 0);
+        assert_eq!(reply[2].count, 26);
+    }
+}
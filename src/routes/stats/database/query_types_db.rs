@@ -9,74 +9,92 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    databases::{
+        ftl::{DbEnum, DbQueryType},
+        long_term::{LongTermDatabase, StoreConnection}
+    },
     ftl::FtlQueryType,
+    metrics::time_database,
     routes::{auth::User, stats::query_types::QueryTypeReply},
     util::{reply_result, Error, ErrorKind, Reply}
 };
-use diesel::{dsl::sql, prelude::*, sql_types::BigInt, sqlite::SqliteConnection};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
 use failure::ResultExt;
 use std::collections::HashMap;
 
 /// Get query type counts from the database
 #[get("/stats/database/query_types?<from>&<until>")]
-pub fn query_types_db(from: u64, until: u64, _auth: User, db: FtlDatabase) -> Reply {
-    reply_result(query_types_db_impl(from, until, &db as &SqliteConnection))
+pub fn query_types_db(from: u64, until: u64, _auth: User, db: LongTermDatabase) -> Reply {
+    reply_result(time_database(|| {
+        query_types_db_impl(from, until, db.connection())
+    }))
 }
 
-/// Get query type counts from the database
+/// Get query type counts from the database. Query types which the API does
+/// not know are listed after the known types.
 fn query_types_db_impl(
     from: u64,
     until: u64,
-    db: &SqliteConnection
+    db: StoreConnection
 ) -> Result<Vec<QueryTypeReply>, Error> {
     let query_types = get_query_type_counts(db, from, until)?;
 
+    let mut unknown_types: Vec<u8> = query_types
+        .keys()
+        .filter_map(|query_type| match query_type {
+            DbEnum::Unknown(num) => Some(*num),
+            DbEnum::Known(_) => None
+        })
+        .collect();
+    unknown_types.sort();
+
     Ok(FtlQueryType::variants()
         .iter()
-        .map(|variant| QueryTypeReply {
-            name: variant.get_name(),
-            count: query_types[variant]
+        .map(|&variant| DbEnum::Known(variant))
+        .chain(unknown_types.into_iter().map(DbEnum::Unknown))
+        .map(|query_type| QueryTypeReply {
+            name: query_type.get_name(),
+            count: query_types[&query_type]
         })
         .collect())
 }
 
-/// Get the number of queries with each query type in the specified time range
+/// Get the number of queries with each query type in the specified time
+/// range. Query types which the API does not know are counted separately.
 pub fn get_query_type_counts(
-    db: &SqliteConnection,
+    db: StoreConnection,
     from: u64,
     until: u64
-) -> Result<HashMap<FtlQueryType, usize>, Error> {
+) -> Result<HashMap<DbQueryType, usize>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    let mut counts: HashMap<FtlQueryType, usize> = queries
-        // Select the query types and their counts.
-        // The raw SQL is used due to a limitation of Diesel, in that it doesn't
-        // have full support for mixing aggregate and non-aggregate data when
-        // using group_by. See https://github.com/diesel-rs/diesel/issues/1781
-        .select((query_type, sql::<BigInt>("COUNT(*)")))
-        // Search in the specified time interval
-        .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
-        // Group the results by query type
-        .group_by(query_type)
-        // Execute the query
-        .get_results::<(i32, i64)>(db)
-        // Add error context and check for errors
-        .context(ErrorKind::FtlDatabase)?
-        // Turn the resulting Vec into an iterator
-        .into_iter()
-        // Map the values into (FtlQueryType, usize)
-        .map(|(q_type, count)| {
-            (FtlQueryType::from_number(q_type as isize).unwrap(), count as usize)
-        })
-        // Turn the iterator into a HashMap
-        .collect();
+    let rows = with_store!(db, |db| {
+        queries
+            // Select the query types and their counts.
+            // The raw SQL is used due to a limitation of Diesel, in that it
+            // doesn't have full support for mixing aggregate and non-aggregate
+            // data when using group_by.
+            // See https://github.com/diesel-rs/diesel/issues/1781
+            .select((query_type, sql::<BigInt>("COUNT(*)")))
+            // Search in the specified time interval
+            .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
+            // Group the results by query type
+            .group_by(query_type)
+            // Execute the query
+            .get_results::<(i32, i64)>(db)
+    })
+    // Add error context and check for errors
+    .context(ErrorKind::FtlDatabase)?;
+
+    // Map the values into (DbQueryType, usize)
+    let mut counts: HashMap<DbQueryType, usize> = HashMap::new();
+    for (q_type, count) in rows {
+        *counts.entry(DbQueryType::from_db(q_type)).or_insert(0) += count as usize;
+    }
 
     // Fill in the rest of the query types not found in the database
     for q_type in FtlQueryType::variants() {
-        if !counts.contains_key(q_type) {
-            counts.insert(*q_type, 0);
-        }
+        counts.entry(DbEnum::Known(*q_type)).or_insert(0);
     }
 
     Ok(counts)
@@ -85,7 +103,13 @@ pub fn get_query_type_counts(
 #[cfg(test)]
 mod test {
     use super::get_query_type_counts;
-    use crate::{databases::ftl::connect_to_test_db, ftl::FtlQueryType};
+    use crate::{
+        databases::{
+            ftl::{connect_to_test_db, DbEnum},
+            long_term::StoreConnection
+        },
+        ftl::FtlQueryType
+    };
     use std::collections::HashMap;
 
     const FROM_TIMESTAMP: u64 = 0;
@@ -95,16 +119,21 @@ mod test {
     #[test]
     fn query_type_counts() {
         let mut expected = HashMap::new();
-        expected.insert(FtlQueryType::A, 36);
-        expected.insert(FtlQueryType::AAAA, 35);
-        expected.insert(FtlQueryType::ANY, 0);
-        expected.insert(FtlQueryType::SRV, 0);
-        expected.insert(FtlQueryType::SOA, 0);
-        expected.insert(FtlQueryType::PTR, 23);
-        expected.insert(FtlQueryType::TXT, 0);
+        expected.insert(DbEnum::Known(FtlQueryType::A), 36);
+        expected.insert(DbEnum::Known(FtlQueryType::AAAA), 35);
+        expected.insert(DbEnum::Known(FtlQueryType::ANY), 0);
+        expected.insert(DbEnum::Known(FtlQueryType::SRV), 0);
+        expected.insert(DbEnum::Known(FtlQueryType::SOA), 0);
+        expected.insert(DbEnum::Known(FtlQueryType::PTR), 23);
+        expected.insert(DbEnum::Known(FtlQueryType::TXT), 0);
 
         let db = connect_to_test_db();
-        let actual = get_query_type_counts(&db, FROM_TIMESTAMP, UNTIL_TIMESTAMP).unwrap();
+        let actual = get_query_type_counts(
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
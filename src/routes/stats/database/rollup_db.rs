@@ -0,0 +1,224 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Rollups Endpoint - DB Version
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::{api_rollups, FtlDatabase},
+    env::Env,
+    metrics::time_database,
+    routes::{auth::User, stats::privacy::PrivacyPolicy},
+    services::{create_rollup_table, ROLLUP_KINDS},
+    util::{reply_result, Error, ErrorKind, Reply}
+};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use failure::ResultExt;
+use rocket::State;
+use std::collections::BTreeMap;
+
+/// Get the hourly or daily query counts rolled up from the database. The
+/// period defaults to hourly.
+#[get("/stats/database/rollup?<from>&<until>&<period>&<kind>")]
+pub fn rollup_db(
+    from: u64,
+    until: u64,
+    period: Option<String>,
+    kind: String,
+    _auth: User,
+    db: FtlDatabase,
+    env: State<Env>
+) -> Reply {
+    let period = period.unwrap_or_else(|| "hour".to_owned());
+
+    reply_result(time_database(|| {
+        rollup_db_impl(from, until, &period, &kind, &db as &SqliteConnection, &env)
+    }))
+}
+
+/// Get the rollups of a kind which start between `from` and `until`. Client
+/// rollups are empty if client details are private.
+fn rollup_db_impl(
+    from: u64,
+    until: u64,
+    period: &str,
+    kind: &str,
+    db: &SqliteConnection,
+    env: &Env
+) -> Result<RollupsReply, Error> {
+    if (period != "hour" && period != "day")
+        || !ROLLUP_KINDS
+            .iter()
+            .any(|(rollup_kind, _)| *rollup_kind == kind)
+    {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    if kind == "client" && !PrivacyPolicy::read(env)?.shows_clients() {
+        return Ok(RollupsReply {
+            rollups: Vec::new()
+        });
+    }
+
+    create_rollup_table(db)?;
+
+    let rows: Vec<(i32, String, i64)> = api_rollups::table
+        .select((api_rollups::timestamp, api_rollups::key, api_rollups::count))
+        .filter(api_rollups::period.eq(period))
+        .filter(api_rollups::kind.eq(kind))
+        .filter(api_rollups::timestamp.ge(from as i32))
+        .filter(api_rollups::timestamp.le(until as i32))
+        .order((api_rollups::timestamp, api_rollups::key))
+        .load(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    // The rows are ordered by timestamp, so each rollup's rows are together
+    let mut rollups: Vec<RollupItem> = Vec::new();
+    for (timestamp, key, count) in rows {
+        if rollups.last().map(|rollup| rollup.timestamp) != Some(timestamp) {
+            rollups.push(RollupItem {
+                timestamp,
+                counts: BTreeMap::new()
+            });
+        }
+
+        if let Some(rollup) = rollups.last_mut() {
+            rollup.counts.insert(key, count);
+        }
+    }
+
+    Ok(RollupsReply { rollups })
+}
+
+/// The rolled up counts, ordered by timestamp
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct RollupsReply {
+    pub rollups: Vec<RollupItem>
+}
+
+/// The counts of a period, by key. The timestamp is the start of the period.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct RollupItem {
+    pub timestamp: i32,
+    pub counts: BTreeMap<String, i64>
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rollup_db_impl, RollupItem, RollupsReply};
+    use crate::{
+        databases::ftl::{api_rollups, connect_to_test_db},
+        env::{Config, Env, PiholeFile},
+        services::create_rollup_table,
+        testing::TestEnvBuilder
+    };
+    use diesel::{prelude::*, result::Error};
+    use std::collections::{BTreeMap, HashMap};
+
+    /// Rollup rows are grouped by timestamp, and only the requested period
+    /// and kind are returned
+    #[test]
+    fn group_rollups() {
+        let db = connect_to_test_db();
+        let env = Env::Test(Config::default(), HashMap::new());
+
+        db.test_transaction::<_, Error, _>(|| {
+            create_rollup_table(&db).unwrap();
+
+            diesel::insert_into(api_rollups::table)
+                .values(&vec![
+                    (
+                        api_rollups::period.eq("hour"),
+                        api_rollups::timestamp.eq(0),
+                        api_rollups::kind.eq("status"),
+                        api_rollups::key.eq("1"),
+                        api_rollups::count.eq(3i64)
+                    ),
+                    (
+                        api_rollups::period.eq("hour"),
+                        api_rollups::timestamp.eq(0),
+                        api_rollups::kind.eq("status"),
+                        api_rollups::key.eq("2"),
+                        api_rollups::count.eq(5i64)
+                    ),
+                    (
+                        api_rollups::period.eq("hour"),
+                        api_rollups::timestamp.eq(3600),
+                        api_rollups::kind.eq("status"),
+                        api_rollups::key.eq("2"),
+                        api_rollups::count.eq(1i64)
+                    ),
+                    (
+                        api_rollups::period.eq("hour"),
+                        api_rollups::timestamp.eq(0),
+                        api_rollups::kind.eq("type"),
+                        api_rollups::key.eq("1"),
+                        api_rollups::count.eq(8i64)
+                    ),
+                    (
+                        api_rollups::period.eq("day"),
+                        api_rollups::timestamp.eq(0),
+                        api_rollups::kind.eq("status"),
+                        api_rollups::key.eq("2"),
+                        api_rollups::count.eq(6i64)
+                    ),
+                ])
+                .execute(&db)?;
+
+            let mut first_counts = BTreeMap::new();
+            first_counts.insert("1".to_owned(), 3);
+            first_counts.insert("2".to_owned(), 5);
+            let mut second_counts = BTreeMap::new();
+            second_counts.insert("2".to_owned(), 1);
+
+            assert_eq!(
+                rollup_db_impl(0, 3600, "hour", "status", &db, &env).unwrap(),
+                RollupsReply {
+                    rollups: vec![
+                        RollupItem {
+                            timestamp: 0,
+                            counts: first_counts
+                        },
+                        RollupItem {
+                            timestamp: 3600,
+                            counts: second_counts
+                        },
+                    ]
+                }
+            );
+
+            Ok(())
+        });
+    }
+
+    /// Unknown periods and kinds are rejected
+    #[test]
+    fn invalid_parameters() {
+        let db = connect_to_test_db();
+        let env = Env::Test(Config::default(), HashMap::new());
+
+        assert!(rollup_db_impl(0, 3600, "week", "status", &db, &env).is_err());
+        assert!(rollup_db_impl(0, 3600, "hour", "domain", &db, &env).is_err());
+    }
+
+    /// Client rollups are not shown if client details are private
+    #[test]
+    fn privacy_level() {
+        let db = connect_to_test_db();
+        let env_builder = TestEnvBuilder::new().file(PiholeFile::FtlConfig, "PRIVACYLEVEL=2");
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            rollup_db_impl(0, 3600, "hour", "client", &db, &env).unwrap(),
+            RollupsReply {
+                rollups: Vec::new()
+            }
+        );
+    }
+}
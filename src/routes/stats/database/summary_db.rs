@@ -9,9 +9,13 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    databases::{
+        ftl::DbEnum,
+        long_term::{LongTermDatabase, StoreConnection}
+    },
     env::Env,
     ftl::{FtlQueryStatus, FtlQueryType, BLOCKED_STATUSES},
+    metrics::time_database,
     routes::{
         auth::User,
         stats::{
@@ -32,15 +36,12 @@ pub fn get_summary_db(
     from: u64,
     until: u64,
     _auth: User,
-    db: FtlDatabase,
+    db: LongTermDatabase,
     env: State<Env>
 ) -> Reply {
-    reply_result(get_summary_impl(
-        from,
-        until,
-        &db as &SqliteConnection,
-        &env
-    ))
+    reply_result(time_database(|| {
+        get_summary_impl(from, until, db.connection(), &env)
+    }))
 }
 
 /// Implementation of [`get_summary_db`]
@@ -49,26 +50,27 @@ pub fn get_summary_db(
 fn get_summary_impl(
     from: u64,
     until: u64,
-    db: &SqliteConnection,
+    db: StoreConnection,
     env: &Env
 ) -> Result<Summary, Error> {
     let query_type_counts = get_query_type_counts(db, from, until)?;
 
-    let total_queries_a = *query_type_counts.get(&FtlQueryType::A).unwrap_or(&0);
-    let total_queries_aaaa = *query_type_counts.get(&FtlQueryType::AAAA).unwrap_or(&0);
-    let total_queries_any = *query_type_counts.get(&FtlQueryType::ANY).unwrap_or(&0);
-    let total_queries_srv = *query_type_counts.get(&FtlQueryType::SRV).unwrap_or(&0);
-    let total_queries_soa = *query_type_counts.get(&FtlQueryType::SOA).unwrap_or(&0);
-    let total_queries_ptr = *query_type_counts.get(&FtlQueryType::PTR).unwrap_or(&0);
-    let total_queries_txt = *query_type_counts.get(&FtlQueryType::TXT).unwrap_or(&0);
+    let count = |query_type: FtlQueryType| {
+        *query_type_counts
+            .get(&DbEnum::Known(query_type))
+            .unwrap_or(&0)
+    };
+
+    let total_queries_a = count(FtlQueryType::A);
+    let total_queries_aaaa = count(FtlQueryType::AAAA);
+    let total_queries_any = count(FtlQueryType::ANY);
+    let total_queries_srv = count(FtlQueryType::SRV);
+    let total_queries_soa = count(FtlQueryType::SOA);
+    let total_queries_ptr = count(FtlQueryType::PTR);
+    let total_queries_txt = count(FtlQueryType::TXT);
 
-    let total_queries = total_queries_a
-        + total_queries_aaaa
-        + total_queries_any
-        + total_queries_srv
-        + total_queries_soa
-        + total_queries_ptr
-        + total_queries_txt;
+    // Queries with types which the API does not know are counted too
+    let total_queries: usize = query_type_counts.values().sum();
     let blocked_queries = get_blocked_query_count(db, from, until)?;
 
     Ok(Summary {
@@ -113,35 +115,36 @@ fn get_summary_impl(
 }
 
 /// Get the number of blocked queries in the specified time range
-pub fn get_blocked_query_count(
-    db: &SqliteConnection,
-    from: u64,
-    until: u64
-) -> Result<usize, Error> {
+pub fn get_blocked_query_count(db: StoreConnection, from: u64, until: u64) -> Result<usize, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    let count = queries
-        .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
-        .filter(status.eq_any(&BLOCKED_STATUSES))
-        .count()
-        .first::<i64>(db)
-        .context(ErrorKind::FtlDatabase)?;
+    let count = with_store!(db, |db| {
+        queries
+            .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
+            .filter(status.eq_any(&BLOCKED_STATUSES))
+            .count()
+            .first::<i64>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
 
     Ok(count as usize)
 }
 
 /// Get the number of unique domains in the specified time range
-fn get_unique_domain_count(db: &SqliteConnection, from: u64, until: u64) -> Result<usize, Error> {
+fn get_unique_domain_count(db: StoreConnection, from: u64, until: u64) -> Result<usize, Error> {
     use crate::databases::ftl::queries::dsl::*;
     use diesel::{dsl::sql, sql_types::BigInt};
 
-    let count = queries
-        // Count the number of distinct (unique) domains. Diesel does not seem
-        // to support this kind of COUNT expression, so raw SQL must be used.
-        .select(sql::<BigInt>("COUNT(DISTINCT domain)"))
-        .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
-        .first::<i64>(db)
-        .context(ErrorKind::FtlDatabase)?;
+    let count = with_store!(db, |db| {
+        queries
+            // Count the number of distinct (unique) domains. Diesel does not
+            // seem to support this kind of COUNT expression, so raw SQL must
+            // be used.
+            .select(sql::<BigInt>("COUNT(DISTINCT domain)"))
+            .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
+            .first::<i64>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
 
     Ok(count as usize)
 }
@@ -149,19 +152,21 @@ fn get_unique_domain_count(db: &SqliteConnection, from: u64, until: u64) -> Resu
 /// Get the number of queries with the specified query status in the specified
 /// time range
 pub fn get_query_status_count(
-    db: &SqliteConnection,
+    db: StoreConnection,
     from: u64,
     until: u64,
     status_type: FtlQueryStatus
 ) -> Result<usize, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    let count = queries
-        .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
-        .filter(status.eq(status_type as i32))
-        .count()
-        .first::<i64>(db)
-        .context(ErrorKind::FtlDatabase)?;
+    let count = with_store!(db, |db| {
+        queries
+            .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
+            .filter(status.eq(status_type as i32))
+            .count()
+            .first::<i64>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
 
     Ok(count as usize)
 }
@@ -172,7 +177,7 @@ mod test {
         get_blocked_query_count, get_query_status_count, get_summary_impl, get_unique_domain_count
     };
     use crate::{
-        databases::ftl::connect_to_test_db,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
         env::{Config, Env},
         ftl::FtlQueryStatus,
         routes::stats::summary::{ReplyTypes, Summary, TotalQueries}
@@ -215,7 +220,13 @@ mod test {
 
         let db = connect_to_test_db();
         let env = Env::Test(Config::default(), HashMap::new());
-        let actual_summary = get_summary_impl(FROM_TIMESTAMP, UNTIL_TIMESTAMP, &db, &env).unwrap();
+        let actual_summary = get_summary_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db),
+            &env
+        )
+        .unwrap();
 
         assert_eq!(actual_summary, expected_summary);
     }
@@ -226,7 +237,12 @@ mod test {
         let expected = 0;
 
         let db = connect_to_test_db();
-        let actual = get_blocked_query_count(&db, FROM_TIMESTAMP, UNTIL_TIMESTAMP).unwrap();
+        let actual = get_blocked_query_count(
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -237,7 +253,12 @@ mod test {
         let expected = 11;
 
         let db = connect_to_test_db();
-        let actual = get_unique_domain_count(&db, FROM_TIMESTAMP, UNTIL_TIMESTAMP).unwrap();
+        let actual = get_unique_domain_count(
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -249,7 +270,7 @@ mod test {
 
         let db = connect_to_test_db();
         let actual = get_query_status_count(
-            &db,
+            StoreConnection::Sqlite(&db),
             FROM_TIMESTAMP,
             UNTIL_TIMESTAMP,
             FtlQueryStatus::Forward
@@ -9,15 +9,18 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    client_nicknames::ClientNicknames,
+    databases::long_term::{LongTermDatabase, StoreConnection},
     env::Env,
     ftl::BLOCKED_STATUSES,
+    metrics::time_database,
     routes::{
         auth::User,
         stats::{
             check_privacy_level_top_clients,
-            common::{get_excluded_clients, get_hidden_client_ip},
+            common::get_excluded_clients,
             database::{get_blocked_query_count, get_query_type_counts},
+            privacy::get_hidden_client_ip,
             top_clients::{TopClientItemReply, TopClientParams, TopClientsReply}
         }
     },
@@ -33,24 +36,29 @@ use rocket::{request::Form, State};
 pub fn top_clients_db(
     _auth: User,
     env: State<Env>,
-    db: FtlDatabase,
+    nicknames: State<ClientNicknames>,
+    db: LongTermDatabase,
     from: u64,
     until: u64,
     params: Form<TopClientParams>
 ) -> Reply {
-    reply_result(top_clients_db_impl(
-        &env,
-        &db as &SqliteConnection,
-        from,
-        until,
-        params.into_inner()
-    ))
+    reply_result(time_database(|| {
+        top_clients_db_impl(
+            &env,
+            &nicknames,
+            db.connection(),
+            from,
+            until,
+            params.into_inner()
+        )
+    }))
 }
 
 /// Get the top clients
 fn top_clients_db_impl(
     env: &Env,
-    db: &SqliteConnection,
+    nicknames: &ClientNicknames,
+    db: StoreConnection,
     from: u64,
     until: u64,
     params: TopClientParams
@@ -86,7 +94,7 @@ fn top_clients_db_impl(
                 {
                     // If the identifier is an IP address, use it as the client IP
                     TopClientItemReply {
-                        name: "".to_owned(),
+                        name: nicknames.get(&client_identifier).unwrap_or_default(),
                         ip: client_identifier,
                         count: count as usize
                     }
@@ -118,7 +126,7 @@ fn top_clients_db_impl(
 }
 
 /// Get the list of clients to ignore
-fn get_ignored_clients(env: &Env) -> Result<Vec<String>, Error> {
+pub fn get_ignored_clients(env: &Env) -> Result<Vec<String>, Error> {
     // Ignore clients excluded via SetupVars
     let mut ignored_clients = get_excluded_clients(env)?;
 
@@ -132,7 +140,7 @@ fn get_ignored_clients(env: &Env) -> Result<Vec<String>, Error> {
 /// The returned Vec contains each client's identifier and count, sorted and
 /// ordered according to the parameters.
 fn execute_top_clients_query(
-    db: &SqliteConnection,
+    db: StoreConnection,
     from: u64,
     until: u64,
     ignored_clients: Vec<String>,
@@ -142,47 +150,49 @@ fn execute_top_clients_query(
 ) -> Result<Vec<(String, i64)>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    // Create query
-    let db_query = queries
-        .select((client, sql::<BigInt>("COUNT(*)")))
-        // Only consider queries in the time interval
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.le(until as i32))
-        // Filter out ignored clients
-        .filter(client.ne_all(ignored_clients))
-        // Group queries by client
-        .group_by(client)
-        // Take into account the limit
-        .limit(limit as i64)
-        // Box the query so we can conditionally modify it
-        .into_boxed();
-
-    // Set the sort order
-    let db_query = if ascending {
-        db_query.order((sql::<BigInt>("COUNT(*)").asc(), client))
-    } else {
-        db_query.order((sql::<BigInt>("COUNT(*)").desc(), client))
-    };
+    Ok(with_store!(db, |db| {
+        // Create query
+        let db_query = queries
+            .select((client, sql::<BigInt>("COUNT(*)")))
+            // Only consider queries in the time interval
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.le(until as i32))
+            // Filter out ignored clients
+            .filter(client.ne_all(ignored_clients))
+            // Group queries by client
+            .group_by(client)
+            // Take into account the limit
+            .limit(limit as i64)
+            // Box the query so we can conditionally modify it
+            .into_boxed();
+
+        // Set the sort order
+        let db_query = if ascending {
+            db_query.order((sql::<BigInt>("COUNT(*)").asc(), client))
+        } else {
+            db_query.order((sql::<BigInt>("COUNT(*)").desc(), client))
+        };
 
-    // Filter by status
-    let db_query = if blocked {
-        db_query.filter(status.eq_any(&BLOCKED_STATUSES))
-    } else {
-        // If not blocked, use all queries
-        db_query
-    };
+        // Filter by status
+        let db_query = if blocked {
+            db_query.filter(status.eq_any(&BLOCKED_STATUSES))
+        } else {
+            // If not blocked, use all queries
+            db_query
+        };
 
-    // Execute query
-    Ok(db_query
-        .load::<(String, i64)>(db)
-        .context(ErrorKind::FtlDatabase)?)
+        // Execute query
+        db_query.load::<(String, i64)>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?)
 }
 
 #[cfg(test)]
 mod test {
     use super::top_clients_db_impl;
     use crate::{
-        databases::ftl::connect_to_test_db,
+        client_nicknames::ClientNicknames,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
         env::{Config, Env, PiholeFile},
         routes::stats::top_clients::{TopClientItemReply, TopClientParams, TopClientsReply},
         testing::TestEnvBuilder
@@ -215,8 +225,15 @@ mod test {
         let db = connect_to_test_db();
         let env = Env::Test(Config::default(), HashMap::new());
         let params = TopClientParams::default();
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -237,8 +254,15 @@ mod test {
             blocked: Some(true),
             ..TopClientParams::default()
         };
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -262,8 +286,15 @@ mod test {
             limit: Some(1),
             ..TopClientParams::default()
         };
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -294,8 +325,15 @@ mod test {
             ascending: Some(true),
             ..TopClientParams::default()
         };
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -317,8 +355,15 @@ mod test {
                 .build()
         );
         let params = TopClientParams::default();
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -344,8 +389,15 @@ mod test {
             blocked: Some(true),
             ..TopClientParams::default()
         };
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -371,8 +423,15 @@ mod test {
                 .build()
         );
         let params = TopClientParams::default();
-        let actual =
-            top_clients_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_clients_db_impl(
+            &env,
+            &ClientNicknames::default(),
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
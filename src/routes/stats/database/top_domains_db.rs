@@ -9,23 +9,27 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    databases::long_term::{LongTermDatabase, StoreConnection},
     env::{Env, PiholeFile},
     ftl::BLOCKED_STATUSES,
+    metrics::time_database,
     routes::{
         auth::User,
         stats::{
             check_privacy_level_top_domains, check_query_log_show_top_domains,
-            common::{get_excluded_domains, get_hidden_domain},
+            common::get_excluded_domains,
             database::{
                 query_types_db::get_query_type_counts, summary_db::get_blocked_query_count
             },
+            privacy::get_hidden_domain,
             top_domains::{TopDomainItemReply, TopDomainParams, TopDomainsReply}
         }
     },
+    services::ThreatCategories,
+    settings::NoiseDomains,
     util::{reply_result, Error, ErrorKind, Reply}
 };
-use diesel::{dsl::sql, prelude::*, sql_types::BigInt, sqlite::SqliteConnection};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
 use failure::ResultExt;
 use rocket::{request::Form, State};
 
@@ -34,33 +38,41 @@ use rocket::{request::Form, State};
 pub fn top_domains_db(
     _auth: User,
     env: State<Env>,
-    db: FtlDatabase,
+    db: LongTermDatabase,
     from: u64,
     until: u64,
-    params: Form<TopDomainParams>
+    params: Form<TopDomainParams>,
+    threat_categories: State<ThreatCategories>
 ) -> Reply {
-    reply_result(top_domains_db_impl(
-        &env,
-        &db as &SqliteConnection,
-        from,
-        until,
-        params.into_inner()
-    ))
+    reply_result(time_database(|| {
+        top_domains_db_impl(
+            &env,
+            db.connection(),
+            from,
+            until,
+            params.into_inner(),
+            &threat_categories
+        )
+    }))
 }
 
 /// Return the top domains
 fn top_domains_db_impl(
     env: &Env,
-    db: &SqliteConnection,
+    db: StoreConnection,
     from: u64,
     until: u64,
-    params: TopDomainParams
+    params: TopDomainParams,
+    threat_categories: &ThreatCategories
 ) -> Result<TopDomainsReply, Error> {
     // Resolve the parameters
     let limit = params.limit.unwrap_or(10);
     let audit = params.audit.unwrap_or(false);
     let ascending = params.ascending.unwrap_or(false);
     let blocked = params.blocked.unwrap_or(false);
+    let hide_noise = params.hide_noise.unwrap_or(false);
+    let filter = params.filter_regex()?;
+    let display = params.display.unwrap_or_default();
 
     // Check if we are allowed to share the top domains
     if let Some(reply) = check_query_log_show_top_domains(env, blocked)? {
@@ -83,15 +95,33 @@ fn top_domains_db_impl(
         return Ok(reply);
     }
 
-    // Find domains which should not be considered
-    let ignored_domains = get_ignored_domains(env, audit)?;
+    // Find domains which should not be considered, and noise domains to
+    // hide, which also hide their subdomains
+    let excluded = ExcludedDomains {
+        ignored: get_ignored_domains(env, audit)?,
+        noise: if hide_noise {
+            NoiseDomains::read(env)?.domains().to_vec()
+        } else {
+            Vec::new()
+        }
+    };
+
+    // SQLite can not match regexes, so the limit is applied after filtering
+    let query_limit = if filter.is_some() { None } else { Some(limit) };
 
     // Fetch the top domains and map into the reply structure
     let top_domains: Vec<TopDomainItemReply> =
-        execute_top_domains_query(db, from, until, ignored_domains, blocked, ascending, limit)?
+        execute_top_domains_query(db, from, until, excluded, blocked, ascending, query_limit)?
             .into_iter()
+            .filter(|(domain, _)| {
+                filter
+                    .as_ref()
+                    .map_or(true, |filter| filter.is_match(domain))
+            })
+            .take(limit)
             .map(|(domain, count)| TopDomainItemReply {
-                domain,
+                category: threat_categories.category(&domain),
+                domain: display.format(&domain),
                 count: count as usize
             })
             .collect();
@@ -129,62 +159,87 @@ fn get_ignored_domains(env: &Env, audit: bool) -> Result<Vec<String>, Error> {
     Ok(ignored_domains)
 }
 
+/// The domains which are left out of the top domains
+struct ExcludedDomains {
+    /// Domains which are not considered
+    ignored: Vec<String>,
+    /// Domains which are hidden along with their subdomains
+    noise: Vec<String>
+}
+
 /// Create and execute the database query to retrieve the top domain details.
 /// The returned Vec contains each domain and its count, sorted and ordered
-/// according to the parameters.
+/// according to the parameters. If there is no limit, all domains are
+/// returned.
 fn execute_top_domains_query(
-    db: &SqliteConnection,
+    db: StoreConnection,
     from: u64,
     until: u64,
-    ignored_domains: Vec<String>,
+    excluded: ExcludedDomains,
     blocked: bool,
     ascending: bool,
-    limit: usize
+    limit: Option<usize>
 ) -> Result<Vec<(String, i64)>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    // Create query
-    let db_query = queries
-        .select((domain, sql::<BigInt>("COUNT(*)")))
-        // Only consider queries in the time interval
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.le(until as i32))
-        // Filter out ignored domains
-        .filter(domain.ne_all(ignored_domains))
-        // Group queries by domain
-        .group_by(domain)
+    Ok(with_store!(db, |db| {
+        // Create query
+        let db_query = queries
+            .select((domain, sql::<BigInt>("COUNT(*)")))
+            // Only consider queries in the time interval
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.le(until as i32))
+            // Filter out ignored domains
+            .filter(domain.ne_all(excluded.ignored))
+            // Group queries by domain
+            .group_by(domain)
+            // Box the query so we can conditionally modify it
+            .into_boxed();
+
         // Take into account the limit
-        .limit(limit as i64)
-        // Box the query so we can conditionally modify it
-        .into_boxed();
+        let db_query = match limit {
+            Some(limit) => db_query.limit(limit as i64),
+            None => db_query
+        };
 
-    // Set the sort order
-    let db_query = if ascending {
-        db_query.order((sql::<BigInt>("COUNT(*)").asc(), domain))
-    } else {
-        db_query.order((sql::<BigInt>("COUNT(*)").desc(), domain))
-    };
+        // Filter out noise domains and their subdomains
+        let db_query = excluded
+            .noise
+            .into_iter()
+            .fold(db_query, |db_query, noise_domain| {
+                db_query
+                    .filter(domain.ne(noise_domain.clone()))
+                    .filter(domain.not_like(format!("%.{}", noise_domain)))
+            });
+
+        // Set the sort order
+        let db_query = if ascending {
+            db_query.order((sql::<BigInt>("COUNT(*)").asc(), domain))
+        } else {
+            db_query.order((sql::<BigInt>("COUNT(*)").desc(), domain))
+        };
 
-    // Filter by status
-    let db_query = if blocked {
-        db_query.filter(status.eq_any(&BLOCKED_STATUSES))
-    } else {
-        db_query.filter(status.ne_all(&BLOCKED_STATUSES))
-    };
+        // Filter by status
+        let db_query = if blocked {
+            db_query.filter(status.eq_any(&BLOCKED_STATUSES))
+        } else {
+            db_query.filter(status.ne_all(&BLOCKED_STATUSES))
+        };
 
-    // Execute query
-    Ok(db_query
-        .load::<(String, i64)>(db)
-        .context(ErrorKind::FtlDatabase)?)
+        // Execute query
+        db_query.load::<(String, i64)>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?)
 }
 
 #[cfg(test)]
 mod test {
     use super::top_domains_db_impl;
     use crate::{
-        databases::ftl::connect_to_test_db,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
         env::{Config, Env, PiholeFile},
         routes::stats::top_domains::{TopDomainItemReply, TopDomainParams, TopDomainsReply},
+        services::ThreatCategories,
         testing::TestEnvBuilder
     };
     use std::collections::HashMap;
@@ -200,43 +255,53 @@ mod test {
             top_domains: vec![
                 TopDomainItemReply {
                     domain: "0.ubuntu.pool.ntp.org".to_owned(),
-                    count: 14
+                    count: 14,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "1.ubuntu.pool.ntp.org".to_owned(),
-                    count: 12
+                    count: 12,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "github.com".to_owned(),
-                    count: 12
+                    count: 12,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "3.ubuntu.pool.ntp.org".to_owned(),
-                    count: 10
+                    count: 10,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "4.4.8.8.in-addr.arpa".to_owned(),
-                    count: 9
+                    count: 9,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "1.1.1.10.in-addr.arpa".to_owned(),
-                    count: 8
+                    count: 8,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "2.ubuntu.pool.ntp.org".to_owned(),
-                    count: 8
+                    count: 8,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "ntp.ubuntu.com".to_owned(),
-                    count: 8
+                    count: 8,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "8.8.8.8.in-addr.arpa".to_owned(),
-                    count: 6
+                    count: 6,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "ftl.pi-hole.net".to_owned(),
-                    count: 6
+                    count: 6,
+                    category: None
                 },
             ],
             total_queries: Some(94),
@@ -246,8 +311,15 @@ mod test {
         let db = connect_to_test_db();
         let env = Env::Test(Config::default(), HashMap::new());
         let params = TopDomainParams::default();
-        let actual =
-            top_domains_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -259,11 +331,13 @@ mod test {
             top_domains: vec![
                 TopDomainItemReply {
                     domain: "0.ubuntu.pool.ntp.org".to_owned(),
-                    count: 14
+                    count: 14,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "1.ubuntu.pool.ntp.org".to_owned(),
-                    count: 12
+                    count: 12,
+                    category: None
                 },
             ],
             total_queries: Some(94),
@@ -276,8 +350,57 @@ mod test {
             limit: Some(2),
             ..TopDomainParams::default()
         };
-        let actual =
-            top_domains_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    /// Domains in the threat feed have their category
+    #[test]
+    fn category() {
+        let expected = TopDomainsReply {
+            top_domains: vec![
+                TopDomainItemReply {
+                    domain: "0.ubuntu.pool.ntp.org".to_owned(),
+                    count: 14,
+                    category: None
+                },
+                TopDomainItemReply {
+                    domain: "1.ubuntu.pool.ntp.org".to_owned(),
+                    count: 12,
+                    category: Some("malware".to_owned())
+                },
+            ],
+            total_queries: Some(94),
+            blocked_queries: None
+        };
+
+        let db = connect_to_test_db();
+        let env = Env::Test(Config::default(), HashMap::new());
+        let params = TopDomainParams {
+            limit: Some(2),
+            ..TopDomainParams::default()
+        };
+        let mut categories = HashMap::new();
+        categories.insert("1.ubuntu.pool.ntp.org".to_owned(), "malware".to_owned());
+
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::from(categories)
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -299,8 +422,15 @@ mod test {
             blocked: Some(true),
             ..TopDomainParams::default()
         };
-        let actual =
-            top_domains_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -313,11 +443,13 @@ mod test {
             top_domains: vec![
                 TopDomainItemReply {
                     domain: "google.com".to_owned(),
-                    count: 1
+                    count: 1,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "8.8.8.8.in-addr.arpa".to_owned(),
-                    count: 6
+                    count: 6,
+                    category: None
                 },
             ],
             total_queries: Some(94),
@@ -331,8 +463,15 @@ mod test {
             limit: Some(2),
             ..TopDomainParams::default()
         };
-        let actual =
-            top_domains_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -345,11 +484,13 @@ mod test {
             top_domains: vec![
                 TopDomainItemReply {
                     domain: "0.ubuntu.pool.ntp.org".to_owned(),
-                    count: 14
+                    count: 14,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "github.com".to_owned(),
-                    count: 12
+                    count: 12,
+                    category: None
                 },
             ],
             total_queries: Some(94),
@@ -368,8 +509,15 @@ mod test {
             limit: Some(2),
             ..TopDomainParams::default()
         };
-        let actual =
-            top_domains_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -381,11 +529,13 @@ mod test {
             top_domains: vec![
                 TopDomainItemReply {
                     domain: "0.ubuntu.pool.ntp.org".to_owned(),
-                    count: 14
+                    count: 14,
+                    category: None
                 },
                 TopDomainItemReply {
                     domain: "github.com".to_owned(),
-                    count: 12
+                    count: 12,
+                    category: None
                 },
             ],
             total_queries: Some(94),
@@ -407,8 +557,98 @@ mod test {
             limit: Some(2),
             ..TopDomainParams::default()
         };
-        let actual =
-            top_domains_db_impl(&env, &db, FROM_TIMESTAMP, UNTIL_TIMESTAMP, params).unwrap();
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    /// Hide noise domains and their subdomains, including the user's noise
+    /// domains
+    #[test]
+    fn hide_noise() {
+        let expected = TopDomainsReply {
+            top_domains: vec![
+                TopDomainItemReply {
+                    domain: "github.com".to_owned(),
+                    count: 12,
+                    category: None
+                },
+                TopDomainItemReply {
+                    domain: "ftl.pi-hole.net".to_owned(),
+                    count: 6,
+                    category: None
+                },
+                TopDomainItemReply {
+                    domain: "google.com".to_owned(),
+                    count: 1,
+                    category: None
+                },
+            ],
+            total_queries: Some(94),
+            blocked_queries: None
+        };
+
+        let db = connect_to_test_db();
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, "API_NOISE_DOMAINS=ntp.ubuntu.com")
+                .build()
+        );
+        let params = TopDomainParams {
+            hide_noise: Some(true),
+            ..TopDomainParams::default()
+        };
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    /// The filter is applied before the limit
+    #[test]
+    fn filter() {
+        let expected = TopDomainsReply {
+            top_domains: vec![TopDomainItemReply {
+                domain: "1.ubuntu.pool.ntp.org".to_owned(),
+                count: 12,
+                category: None
+            }],
+            total_queries: Some(94),
+            blocked_queries: None
+        };
+
+        let db = connect_to_test_db();
+        let env = Env::Test(Config::default(), HashMap::new());
+        let params = TopDomainParams {
+            limit: Some(1),
+            filter: Some("^1\\.".to_owned()),
+            ..TopDomainParams::default()
+        };
+        let actual = top_domains_db_impl(
+            &env,
+            StoreConnection::Sqlite(&db),
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            params,
+            &ThreatCategories::default()
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
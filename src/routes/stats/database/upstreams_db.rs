@@ -9,8 +9,9 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::FtlDatabase,
+    databases::long_term::{LongTermDatabase, StoreConnection},
     ftl::FtlQueryStatus,
+    metrics::time_database,
     routes::{
         auth::User,
         stats::{
@@ -20,22 +21,20 @@ use crate::{
     },
     util::{reply_result, Error, ErrorKind, Reply}
 };
-use diesel::{dsl::sql, prelude::*, sql_types::BigInt, sqlite::SqliteConnection};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt};
 use failure::ResultExt;
 use std::collections::HashMap;
 
 /// Get upstream data from the database
 #[get("/stats/database/upstreams?<from>&<until>")]
-pub fn upstreams_db(from: u64, until: u64, _auth: User, db: FtlDatabase) -> Reply {
-    reply_result(upstreams_db_impl(from, until, &db as &SqliteConnection))
+pub fn upstreams_db(from: u64, until: u64, _auth: User, db: LongTermDatabase) -> Reply {
+    reply_result(time_database(|| {
+        upstreams_db_impl(from, until, db.connection())
+    }))
 }
 
 /// Get upstream data from the database
-fn upstreams_db_impl(
-    from: u64,
-    until: u64,
-    db: &SqliteConnection
-) -> Result<UpstreamsReply, Error> {
+fn upstreams_db_impl(from: u64, until: u64, db: StoreConnection) -> Result<UpstreamsReply, Error> {
     let upstream_counts = get_upstream_counts(from, until, db)?;
     let blocked_count = get_blocked_query_count(db, from, until)?;
     let cached_count = get_query_status_count(db, from, until, FtlQueryStatus::Cache)?;
@@ -100,21 +99,23 @@ fn upstreams_db_impl(
 fn get_upstream_counts(
     from: u64,
     until: u64,
-    db: &SqliteConnection
+    db: StoreConnection
 ) -> Result<HashMap<Option<String>, i64>, Error> {
     use crate::databases::ftl::queries::dsl::*;
 
-    Ok(queries
-        .select((upstream, sql::<BigInt>("COUNT(*)")))
-        // Search in the specified time interval
-        .filter(timestamp.ge(from as i32))
-        .filter(timestamp.le(until as i32))
-        // Group the results by upstream
-        .group_by(upstream)
-        // Execute the query
-        .get_results::<(Option<String>, i64)>(db)
-        // Add error context and check for errors
-        .context(ErrorKind::FtlDatabase)?
+    Ok(with_store!(db, |db| {
+        queries
+            .select((upstream, sql::<BigInt>("COUNT(*)")))
+            // Search in the specified time interval
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.le(until as i32))
+            // Group the results by upstream
+            .group_by(upstream)
+            // Execute the query
+            .get_results::<(Option<String>, i64)>(db)
+    })
+    // Add error context and check for errors
+    .context(ErrorKind::FtlDatabase)?
         // Turn the resulting Vec into a HashMap
         .into_iter()
         .collect())
@@ -124,7 +125,7 @@ fn get_upstream_counts(
 mod test {
     use super::{get_upstream_counts, upstreams_db_impl};
     use crate::{
-        databases::ftl::connect_to_test_db,
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
         routes::stats::upstreams::{UpstreamItemReply, UpstreamsReply}
     };
     use std::collections::HashMap;
@@ -163,7 +164,12 @@ mod test {
         };
 
         let db = connect_to_test_db();
-        let actual = upstreams_db_impl(FROM_TIMESTAMP, UNTIL_TIMESTAMP, &db).unwrap();
+        let actual = upstreams_db_impl(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -177,7 +183,12 @@ mod test {
         expected.insert(Some("8.8.8.8".to_owned()), 4);
 
         let db = connect_to_test_db();
-        let actual = get_upstream_counts(FROM_TIMESTAMP, UNTIL_TIMESTAMP, &db).unwrap();
+        let actual = get_upstream_counts(
+            FROM_TIMESTAMP,
+            UNTIL_TIMESTAMP,
+            StoreConnection::Sqlite(&db)
+        )
+        .unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -0,0 +1,217 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Domain Display Format
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use rocket::{http::RawStr, request::FromFormValue};
+use rocket_contrib::json::JsonValue;
+use std::char;
+
+/// How domains are shown in replies. Internationalized domains are stored
+/// in their ASCII (punycode) form, which is always used for filtering.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum DomainDisplay {
+    /// Show domains as they are stored
+    Ascii,
+    /// Convert punycode labels (`xn--`) back to Unicode
+    Unicode
+}
+
+impl Default for DomainDisplay {
+    fn default() -> Self {
+        DomainDisplay::Ascii
+    }
+}
+
+impl<'v> FromFormValue<'v> for DomainDisplay {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Self, Self::Error> {
+        match form_value.as_str() {
+            "ascii" => Ok(DomainDisplay::Ascii),
+            "unicode" => Ok(DomainDisplay::Unicode),
+            _ => Err(form_value)
+        }
+    }
+}
+
+impl DomainDisplay {
+    /// Format the domain for display. Labels which are not valid punycode
+    /// are left as they are.
+    pub fn format(self, domain: &str) -> String {
+        if self == DomainDisplay::Ascii {
+            return domain.to_owned();
+        }
+
+        domain
+            .split('.')
+            .map(|label| {
+                let is_punycode = label.len() > 4
+                    && label
+                        .get(..4)
+                        .map_or(false, |prefix| prefix.eq_ignore_ascii_case("xn--"));
+
+                if is_punycode {
+                    decode_punycode(&label[4..].to_ascii_lowercase())
+                        .unwrap_or_else(|| label.to_owned())
+                } else {
+                    label.to_owned()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Format the `domain` field of each query in a page of the history
+    pub fn format_history(self, history: &mut [JsonValue]) {
+        if self == DomainDisplay::Ascii {
+            return;
+        }
+
+        for query in history {
+            let domain = query["domain"].as_str().map(|domain| self.format(domain));
+
+            if let Some(domain) = domain {
+                query["domain"] = domain.into();
+            }
+        }
+    }
+}
+
+// The punycode parameters, from RFC 3492
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+/// Decode a punycode label, without the `xn--` prefix. `None` is returned if
+/// the label is not valid punycode.
+fn decode_punycode(input: &str) -> Option<String> {
+    // The basic (ASCII) code points come before the last delimiter
+    let (basic, encoded) = match input.rfind('-') {
+        Some(index) => (&input[..index], &input[index + 1..]),
+        None => ("", input)
+    };
+
+    if !basic.is_ascii() || encoded.is_empty() {
+        return None;
+    }
+
+    let mut output: Vec<char> = basic.chars().collect();
+    let mut n = INITIAL_N;
+    let mut i: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut digits = encoded.bytes().peekable();
+
+    while digits.peek().is_some() {
+        let old_i = i;
+        let mut weight: u32 = 1;
+        let mut k = BASE;
+
+        // Decode a generalized variable-length integer into the delta
+        loop {
+            let digit = digit_value(digits.next()?)?;
+            i = i.checked_add(digit.checked_mul(weight)?)?;
+
+            let threshold = if k <= bias {
+                T_MIN
+            } else if k >= bias + T_MAX {
+                T_MAX
+            } else {
+                k - bias
+            };
+
+            if digit < threshold {
+                break;
+            }
+
+            weight = weight.checked_mul(BASE - threshold)?;
+            k += BASE;
+        }
+
+        let length = output.len() as u32 + 1;
+        bias = adapt(i - old_i, length, old_i == 0);
+        n = n.checked_add(i / length)?;
+        i %= length;
+
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+
+    Some(output.into_iter().collect())
+}
+
+/// Get the value of a punycode digit
+fn digit_value(digit: u8) -> Option<u32> {
+    match digit {
+        b'a'..=b'z' => Some(u32::from(digit - b'a')),
+        b'0'..=b'9' => Some(u32::from(digit - b'0') + 26),
+        _ => None
+    }
+}
+
+/// Adapt the bias after decoding a code point
+fn adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+
+    let mut k = 0;
+
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+
+    k + ((BASE - T_MIN + 1) * delta) / (delta + SKEW)
+}
+
+#[cfg(test)]
+mod test {
+    use super::DomainDisplay;
+
+    /// Punycode labels are converted to Unicode, and other labels are not
+    /// changed
+    #[test]
+    fn unicode_display() {
+        assert_eq!(
+            DomainDisplay::Unicode.format("www.xn--mnchen-3ya.de"),
+            "www.münchen.de"
+        );
+        assert_eq!(
+            DomainDisplay::Unicode.format("XN--bcher-kva.example"),
+            "bücher.example"
+        );
+        assert_eq!(DomainDisplay::Unicode.format("example.com"), "example.com");
+    }
+
+    /// Invalid punycode is left as it is
+    #[test]
+    fn invalid_punycode() {
+        assert_eq!(
+            DomainDisplay::Unicode.format("xn--99.example"),
+            "xn--99.example"
+        );
+        assert_eq!(
+            DomainDisplay::Unicode.format("xn--_.example"),
+            "xn--_.example"
+        );
+    }
+
+    /// ASCII display does not change the domain
+    #[test]
+    fn ascii_display() {
+        assert_eq!(
+            DomainDisplay::Ascii.format("xn--mnchen-3ya.de"),
+            "xn--mnchen-3ya.de"
+        );
+    }
+}
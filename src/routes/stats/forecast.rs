@@ -0,0 +1,275 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Volume Forecast Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::long_term::{LongTermDatabase, StoreConnection},
+    metrics::time_database,
+    routes::auth::User,
+    util::{current_time, reply_result, Error, ErrorKind, Reply}
+};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{BigInt, Integer}
+};
+use failure::ResultExt;
+use std::collections::HashMap;
+
+/// The length of an hour, in seconds
+const HOUR: u64 = 60 * 60;
+
+/// The length of a day, in seconds
+const DAY: u64 = 24 * HOUR;
+
+/// The number of past days used for the forecast, if not specified
+const DEFAULT_DAYS: u64 = 14;
+
+/// The maximum number of past days used for the forecast
+const MAX_DAYS: u64 = 60;
+
+/// The z-score of the 95% confidence bounds
+const CONFIDENCE_Z: f64 = 1.96;
+
+/// Forecast the query volume of each hour of the next 24 hours, from the
+/// hourly query counts of the past days in the database. The forecast starts
+/// at the hour of `until`, which defaults to now.
+#[get("/stats/forecast?<until>&<days>")]
+pub fn get_forecast(
+    _auth: User,
+    db: LongTermDatabase,
+    until: Option<u64>,
+    days: Option<u64>
+) -> Reply {
+    let until = until.unwrap_or_else(current_time);
+
+    reply_result(time_database(|| {
+        forecast(db.connection(), until, days.unwrap_or(DEFAULT_DAYS))
+    }))
+}
+
+/// Represents the response of the forecast endpoint
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct Forecast {
+    /// The number of past days the forecast is based on
+    pub days: usize,
+    pub forecast: Vec<ForecastItem>
+}
+
+/// The forecast query volume of one hour, with its 95% confidence bounds
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct ForecastItem {
+    /// The start of the hour
+    pub timestamp: u64,
+    pub expected: f64,
+    pub lower: f64,
+    pub upper: f64
+}
+
+/// Forecast the next 24 hours from the past days of the database. Days before
+/// the first query in the database are not used, because they have no data
+/// instead of no queries.
+pub fn forecast(db: StoreConnection, until: u64, days: u64) -> Result<Forecast, Error> {
+    if days == 0 || days > MAX_DAYS {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    let start = until - until % HOUR;
+    let first_query = get_first_query(db)?;
+    let history_start = start.saturating_sub(days * DAY);
+    let hourly = get_hourly_counts(db, history_start, start)?;
+
+    // Collect the hourly counts of each full day, oldest first
+    let daily_counts: Vec<[f64; 24]> = (0..days)
+        .rev()
+        .filter_map(|day| start.checked_sub((day + 1) * DAY))
+        .filter(|&day_start| first_query.map_or(false, |first| day_start + DAY > first))
+        .map(|day_start| {
+            let mut counts = [0f64; 24];
+
+            for (hour, count) in counts.iter_mut().enumerate() {
+                let timestamp = (day_start + hour as u64 * HOUR) as i32;
+                *count = *hourly.get(&timestamp).unwrap_or(&0) as f64;
+            }
+
+            counts
+        })
+        .collect();
+
+    Ok(Forecast {
+        days: daily_counts.len(),
+        forecast: fit_seasonal(&daily_counts)
+            .iter()
+            .enumerate()
+            .map(|(hour, &(expected, deviation))| ForecastItem {
+                timestamp: start + hour as u64 * HOUR,
+                expected,
+                lower: (expected - CONFIDENCE_Z * deviation).max(0f64),
+                upper: expected + CONFIDENCE_Z * deviation
+            })
+            .collect()
+    })
+}
+
+/// Fit a seasonal model to the hourly counts of each day, oldest first, and
+/// forecast the next day. The daily totals follow a linear trend, and each
+/// hour has a fixed share of the daily total. The result is the expected
+/// count of each hour and the standard deviation of its errors in the past.
+fn fit_seasonal(daily_counts: &[[f64; 24]]) -> [(f64, f64); 24] {
+    let mut result = [(0f64, 0f64); 24];
+
+    if daily_counts.is_empty() {
+        return result;
+    }
+
+    // Fit the linear trend of the daily totals with least squares
+    let totals: Vec<f64> = daily_counts
+        .iter()
+        .map(|counts| counts.iter().sum())
+        .collect();
+    let n = totals.len() as f64;
+    let mean_x = (n - 1f64) / 2f64;
+    let mean_total = totals.iter().sum::<f64>() / n;
+    let (covariance, variance) =
+        totals
+            .iter()
+            .enumerate()
+            .fold((0f64, 0f64), |(covariance, variance), (x, total)| {
+                let dx = x as f64 - mean_x;
+                (covariance + dx * (total - mean_total), variance + dx * dx)
+            });
+    let slope = if variance == 0f64 {
+        0f64
+    } else {
+        covariance / variance
+    };
+    let trend = |x: f64| (mean_total + slope * (x - mean_x)).max(0f64);
+
+    // Each hour's share of all of the queries. Without queries, the hours
+    // share equally.
+    let grand_total: f64 = totals.iter().sum();
+    let share = |hour: usize| {
+        if grand_total == 0f64 {
+            1f64 / 24f64
+        } else {
+            daily_counts.iter().map(|counts| counts[hour]).sum::<f64>() / grand_total
+        }
+    };
+
+    let next_total = trend(n);
+
+    for (hour, item) in result.iter_mut().enumerate() {
+        let share = share(hour);
+        let squared_error: f64 = daily_counts
+            .iter()
+            .enumerate()
+            .map(|(day, counts)| {
+                let error = counts[hour] - trend(day as f64) * share;
+                error * error
+            })
+            .sum();
+
+        *item = (next_total * share, (squared_error / n).sqrt());
+    }
+
+    result
+}
+
+/// Get the timestamp of the first query in the database
+fn get_first_query(db: StoreConnection) -> Result<Option<u64>, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    let first: Option<i32> = with_store!(db, |db| {
+        queries.select(diesel::dsl::min(timestamp)).first(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
+
+    Ok(first.map(|first| first as u64))
+}
+
+/// Get the number of queries in each hour of the time range, by the start of
+/// the hour
+fn get_hourly_counts(
+    db: StoreConnection,
+    from: u64,
+    until: u64
+) -> Result<HashMap<i32, i64>, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    Ok(with_store!(db, |db| {
+        // SQL snippet for calculating the hour of the query
+        let hour_sql = sql::<Integer>(&db.interval_sql(HOUR as usize));
+
+        queries
+            .select((&hour_sql, sql::<BigInt>("COUNT(*)")))
+            .filter(status.ne(0))
+            .filter(timestamp.ge(from as i32))
+            .filter(timestamp.lt(until as i32))
+            .group_by(&hour_sql)
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?
+    .into_iter()
+    .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fit_seasonal, forecast, HOUR};
+    use crate::databases::{ftl::connect_to_test_db, long_term::StoreConnection};
+
+    /// The same counts every day are forecast exactly, without uncertainty
+    #[test]
+    fn constant_days() {
+        let mut day = [10f64; 24];
+        day[12] = 40f64;
+
+        let forecast = fit_seasonal(&[day, day, day]);
+
+        assert!((forecast[0].0 - 10f64).abs() < 1e-9);
+        assert!((forecast[12].0 - 40f64).abs() < 1e-9);
+        assert!(forecast.iter().all(|&(_, deviation)| deviation < 1e-9));
+    }
+
+    /// The daily totals follow their trend
+    #[test]
+    fn trend() {
+        let forecast = fit_seasonal(&[[10f64; 24], [20f64; 24]]);
+
+        for &(expected, deviation) in forecast.iter() {
+            assert!((expected - 30f64).abs() < 1e-9);
+            assert!(deviation < 1e-9);
+        }
+    }
+
+    /// Without any days, nothing is expected
+    #[test]
+    fn no_days() {
+        assert_eq!(fit_seasonal(&[]), [(0f64, 0f64); 24]);
+    }
+
+    /// The forecast covers the next 24 hours, and only uses days after the
+    /// first query in the database
+    #[test]
+    fn database_forecast() {
+        let db = connect_to_test_db();
+        let forecast = forecast(StoreConnection::Sqlite(&db), 177_180, 14).unwrap();
+
+        assert_eq!(forecast.days, 2);
+        assert_eq!(forecast.forecast.len(), 24);
+        assert_eq!(forecast.forecast[0].timestamp, 176_400);
+        assert_eq!(forecast.forecast[23].timestamp, 176_400 + 23 * HOUR);
+        assert!(forecast
+            .forecast
+            .iter()
+            .all(|item| item.lower <= item.expected && item.expected <= item.upper));
+    }
+}
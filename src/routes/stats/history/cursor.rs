@@ -0,0 +1,361 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History Cursor Signing
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    ftl::MAX_LOG_AGE,
+    process_info::FtlInstance,
+    routes::stats::history::endpoints::HistoryCursor,
+    util::{current_time, Error, ErrorKind}
+};
+use base64::{decode, encode};
+use failure::ResultExt;
+use hmac::{Hmac, Mac};
+use rocket::{http::RawStr, request::FromFormValue};
+use sha2::Sha256;
+use std::{
+    fs::File,
+    io::Read
+};
+
+/// The source of the random signing key
+const RANDOM_SOURCE: &str = "/dev/urandom";
+
+/// Cursors older than this many seconds are expired
+const CURSOR_MAX_AGE: u64 = MAX_LOG_AGE as u64 * 60 * 60;
+
+/// Signs history cursors and verifies the cursors sent back by clients, so
+/// that tampered cursors are rejected. The key and generation are random for
+/// each run of the API, so cursors handed out before a restart are reported as
+/// expired. Cursors into FTL's memory also record the FTL instance they were
+/// issued for, because FTL's query IDs start over when FTL restarts.
+#[derive(Clone)]
+pub struct CursorSigner {
+    key: [u8; 32],
+    generation: u32,
+    /// Get the current time as a Unix timestamp
+    clock: fn() -> u64,
+    /// FTL's PID file, which identifies the running FTL instance
+    ftl_pid_file: Option<String>
+}
+
+/// A signed cursor, as sent to and received from the client
+#[derive(Serialize, Deserialize)]
+pub struct SignedCursor {
+    id: Option<i32>,
+    db_id: Option<i64>,
+    #[serde(default)]
+    db_timestamp: Option<i64>,
+    #[serde(default)]
+    offset: Option<usize>,
+    generation: u32,
+    #[serde(default)]
+    ftl: Option<FtlInstance>,
+    issued: u64,
+    signature: String
+}
+
+impl CursorSigner {
+    /// Create a signer with a random key and generation, which reads the FTL
+    /// instance from FTL's PID file
+    pub fn random(env: &Env) -> Result<CursorSigner, Error> {
+        let mut random = [0u8; 36];
+
+        File::open(RANDOM_SOURCE)
+            .and_then(|mut file| file.read_exact(&mut random))
+            .context(ErrorKind::FileRead(RANDOM_SOURCE.to_owned()))?;
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&random[..32]);
+
+        let generation = random[32..]
+            .iter()
+            .fold(0, |generation, &byte| (generation << 8) | byte as u32);
+
+        Ok(CursorSigner {
+            key,
+            generation,
+            clock: current_time,
+            ftl_pid_file: Some(env.file_location(PiholeFile::FtlPid).to_owned())
+        })
+    }
+
+    /// Create a signer with a fixed key, generation, and time, which does not
+    /// check the FTL instance
+    #[cfg(test)]
+    pub fn test() -> CursorSigner {
+        CursorSigner {
+            key: [0; 32],
+            generation: 1,
+            clock: || 1_000_000,
+            ftl_pid_file: None
+        }
+    }
+
+    /// Sign the cursor and encode it in Base64
+    pub fn sign(&self, cursor: HistoryCursor) -> Result<String, Error> {
+        let issued = (self.clock)();
+        let ftl = self.ftl_instance(cursor);
+        let signature = self
+            .mac(cursor, self.generation, ftl, issued)?
+            .result()
+            .code();
+
+        let signed = SignedCursor {
+            id: cursor.id,
+            db_id: cursor.db_id,
+            db_timestamp: cursor.db_timestamp,
+            offset: cursor.offset,
+            generation: self.generation,
+            ftl,
+            issued,
+            signature: encode(signature.as_slice())
+        };
+        let bytes = serde_json::to_vec(&signed).context(ErrorKind::Unknown)?;
+
+        Ok(encode(&bytes))
+    }
+
+    /// Verify a cursor sent by the client. A cursor from a previous generation,
+    /// from a previous FTL instance, or which is too old is expired, and a
+    /// cursor with an invalid signature is a bad request.
+    pub fn verify(&self, signed: &SignedCursor) -> Result<HistoryCursor, Error> {
+        // The key changes with the generation, so the signature of a cursor
+        // from a previous generation can not be checked
+        if signed.generation != self.generation {
+            return Err(Error::from(ErrorKind::CursorExpired));
+        }
+
+        let cursor = HistoryCursor {
+            id: signed.id,
+            db_id: signed.db_id,
+            db_timestamp: signed.db_timestamp,
+            offset: signed.offset
+        };
+        let signature = decode(&signed.signature).context(ErrorKind::BadRequest)?;
+
+        self.mac(cursor, signed.generation, signed.ftl, signed.issued)?
+            .verify(&signature)
+            .map_err(|_| Error::from(ErrorKind::BadRequest))?;
+
+        if signed.ftl != self.ftl_instance(cursor)
+            || (self.clock)().saturating_sub(signed.issued) > CURSOR_MAX_AGE
+        {
+            return Err(Error::from(ErrorKind::CursorExpired));
+        }
+
+        Ok(cursor)
+    }
+
+    /// Get the FTL instance which the cursor belongs to. Only cursors into
+    /// FTL's memory belong to an instance, because database IDs stay valid
+    /// when FTL restarts.
+    fn ftl_instance(&self, cursor: HistoryCursor) -> Option<FtlInstance> {
+        match (cursor.id, &self.ftl_pid_file) {
+            (Some(_), Some(pid_file)) => FtlInstance::read(pid_file),
+            _ => None
+        }
+    }
+
+    /// Create a MAC which has processed the cursor data
+    fn mac(
+        &self,
+        cursor: HistoryCursor,
+        generation: u32,
+        ftl: Option<FtlInstance>,
+        issued: u64
+    ) -> Result<Hmac<Sha256>, Error> {
+        let data = serde_json::to_vec(&(
+            cursor.id,
+            cursor.db_id,
+            cursor.db_timestamp,
+            cursor.offset,
+            generation,
+            ftl,
+            issued
+        ))
+        .context(ErrorKind::Unknown)?;
+
+        // HMAC accepts keys of any size, so this will not fail
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key).unwrap();
+        mac.input(&data);
+
+        Ok(mac)
+    }
+}
+
+impl<'a> FromFormValue<'a> for SignedCursor {
+    type Error = Error;
+
+    fn from_form_value(form_value: &'a RawStr) -> Result<Self, Self::Error> {
+        // Decode from Base64
+        let decoded = decode(form_value).context(ErrorKind::BadRequest)?;
+
+        // Deserialize from JSON
+        let cursor = serde_json::from_slice(&decoded).context(ErrorKind::BadRequest)?;
+
+        Ok(cursor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CursorSigner, SignedCursor, CURSOR_MAX_AGE};
+    use crate::{routes::stats::history::endpoints::HistoryCursor, util::ErrorKind};
+    use base64::encode;
+    use rocket::{http::RawStr, request::FromFormValue};
+    use std::{fs, process};
+    use tempfile::NamedTempFile;
+
+    const CURSOR: HistoryCursor = HistoryCursor {
+        id: None,
+        db_id: Some(97),
+        db_timestamp: Some(177_161),
+        offset: None
+    };
+
+    /// Parse a signed cursor from its Base64 form
+    fn parse(encoded: &str) -> SignedCursor {
+        SignedCursor::from_form_value(RawStr::from_str(encoded)).unwrap()
+    }
+
+    /// A signed cursor verifies to the original cursor
+    #[test]
+    fn sign_and_verify() {
+        let signer = CursorSigner::test();
+        let signed = parse(&signer.sign(CURSOR).unwrap());
+
+        assert_eq!(signer.verify(&signed).unwrap(), CURSOR);
+    }
+
+    /// Changing the cursor data invalidates the signature
+    #[test]
+    fn tampered_cursor() {
+        let signer = CursorSigner::test();
+        let mut signed = parse(&signer.sign(CURSOR).unwrap());
+        signed.db_id = Some(1);
+
+        assert_eq!(
+            signer.verify(&signed).map_err(|e| e.kind()),
+            Err(ErrorKind::BadRequest)
+        );
+    }
+
+    /// Changing the timestamp of a database cursor invalidates the signature
+    #[test]
+    fn tampered_timestamp() {
+        let signer = CursorSigner::test();
+        let mut signed = parse(&signer.sign(CURSOR).unwrap());
+        signed.db_timestamp = Some(0);
+
+        assert_eq!(
+            signer.verify(&signed).map_err(|e| e.kind()),
+            Err(ErrorKind::BadRequest)
+        );
+    }
+
+    /// Changing the offset of a sorted cursor invalidates the signature
+    #[test]
+    fn tampered_offset() {
+        let signer = CursorSigner::test();
+        let mut signed = parse(
+            &signer
+                .sign(HistoryCursor {
+                    id: None,
+                    db_id: None,
+                    db_timestamp: None,
+                    offset: Some(100)
+                })
+                .unwrap()
+        );
+        signed.offset = Some(0);
+
+        assert_eq!(
+            signer.verify(&signed).map_err(|e| e.kind()),
+            Err(ErrorKind::BadRequest)
+        );
+    }
+
+    /// Cursors without a valid signature are rejected
+    #[test]
+    fn unsigned_cursor() {
+        let cursor = encode(r#"{"id":1,"db_id":null,"generation":1,"issued":0,"signature":""}"#);
+
+        assert_eq!(
+            CursorSigner::test()
+                .verify(&parse(&cursor))
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::BadRequest)
+        );
+    }
+
+    /// Cursors from a previous generation are expired
+    #[test]
+    fn previous_generation() {
+        let signer = CursorSigner::test();
+        let signed = parse(&signer.sign(CURSOR).unwrap());
+        let restarted = CursorSigner {
+            generation: 2,
+            ..CursorSigner::test()
+        };
+
+        assert_eq!(
+            restarted.verify(&signed).map_err(|e| e.kind()),
+            Err(ErrorKind::CursorExpired)
+        );
+    }
+
+    /// Cursors which are too old are expired
+    #[test]
+    fn old_cursor() {
+        let signer = CursorSigner::test();
+        let signed = parse(&signer.sign(CURSOR).unwrap());
+        let later = CursorSigner {
+            clock: || 1_000_000 + CURSOR_MAX_AGE + 1,
+            ..CursorSigner::test()
+        };
+
+        assert_eq!(
+            later.verify(&signed).map_err(|e| e.kind()),
+            Err(ErrorKind::CursorExpired)
+        );
+    }
+
+    /// Cursors into FTL's memory are expired when FTL restarts, but database
+    /// cursors stay valid
+    #[test]
+    fn ftl_restart() {
+        let pid_file = NamedTempFile::new().unwrap();
+        let path = pid_file.path().to_str().unwrap();
+        let signer = CursorSigner {
+            ftl_pid_file: Some(path.to_owned()),
+            ..CursorSigner::test()
+        };
+        let memory_cursor = HistoryCursor {
+            id: Some(50),
+            db_id: None,
+            db_timestamp: None,
+            offset: None
+        };
+
+        fs::write(path, process::id().to_string()).unwrap();
+        let memory_signed = parse(&signer.sign(memory_cursor).unwrap());
+        let db_signed = parse(&signer.sign(CURSOR).unwrap());
+        assert_eq!(signer.verify(&memory_signed).unwrap(), memory_cursor);
+
+        // FTL restarted with a different PID
+        fs::write(path, "1").unwrap();
+        assert_eq!(
+            signer.verify(&memory_signed).map_err(|e| e.kind()),
+            Err(ErrorKind::CursorExpired)
+        );
+        assert_eq!(signer.verify(&db_signed).unwrap(), CURSOR);
+    }
+}
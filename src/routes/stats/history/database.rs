@@ -14,7 +14,8 @@ use crate::{
     routes::stats::history::{
         endpoints::{HistoryCursor, HistoryParams},
         filters::*,
-        skip_to_cursor::skip_to_cursor_db
+        skip_to_cursor::skip_to_cursor_db,
+        sort::{sort_db, HistorySort}
     },
     util::{Error, ErrorKind}
 };
@@ -26,14 +27,17 @@ use failure::ResultExt;
 ///
 /// # Arguments:
 /// - `db`: A connection to the FTL database
-/// - `start_id`: The query ID to start searching from. If this is `None` then
-///   the search will start from the most recent queries
+/// - `start`: The timestamp and query ID to start searching from. If this is
+///   `None` then the search will start from the most recent queries
 /// - `params`: Parameters given to the history endpoint (filters)
+/// - `search_client_ips`: The IPs of clients whose name matches the search
+///   text, if there is one
 /// - `limit`: The maximum number of queries to load
 pub fn load_queries_from_database(
     db: &SqliteConnection,
-    start_id: Option<i64>,
+    start: Option<(i64, i64)>,
     params: &HistoryParams,
+    search_client_ips: &[String],
     env: &Env,
     limit: usize
 ) -> Result<(Vec<FtlDbQuery>, Option<HistoryCursor>), Error> {
@@ -46,34 +50,28 @@ pub fn load_queries_from_database(
         .into_boxed()
         // Take up to the limit, plus one to build the cursor
         .limit((limit + 1) as i64)
-        // Start with the most recently inserted queries
-        .order(id.desc());
+        // Start with the most recent queries. Queries with the same timestamp
+        // are ordered by ID, so the order is the same on every page.
+        .order((timestamp.desc(), id.desc()));
 
-    // If a start ID is given, ignore any queries before it
-    let db_query = skip_to_cursor_db(db_query, start_id);
+    // If a start is given, ignore any queries before it
+    let db_query = skip_to_cursor_db(db_query, start);
 
     // Apply filters
-    let db_query = filter_time_from_db(db_query, params);
-    let db_query = filter_time_until_db(db_query, params);
-    let db_query = filter_domain_db(db_query, params);
-    let db_query = filter_client_db(db_query, params);
-    let db_query = filter_upstream_db(db_query, params);
-    let db_query = filter_query_type_db(db_query, params);
-    let db_query = filter_status_db(db_query, params);
-    let db_query = filter_blocked_db(db_query, params);
-    let db_query = filter_excluded_domains_db(db_query, env)?;
-    let db_query = filter_excluded_clients_db(db_query, env)?;
-    let db_query = filter_setup_vars_setting_db(db_query, env)?;
+    let db_query = filter_db_query(db_query, params, search_client_ips, env)?;
 
     // Execute the query and load the results
     let mut results: Vec<FtlDbQuery> = execute_query(db, db_query)?;
 
     // If more queries could be loaded beyond the given limit (if we loaded
-    // limit + 1 queries), then set the cursor to use the limit + 1 query's ID.
+    // limit + 1 queries), then set the cursor to use the limit + 1 query's
+    // timestamp and ID.
     let cursor = if results.len() == limit + 1 {
         Some(HistoryCursor {
             id: None,
-            db_id: Some(results[limit].id.unwrap() as i64)
+            db_id: Some(results[limit].id.unwrap() as i64),
+            db_timestamp: Some(results[limit].timestamp as i64),
+            offset: None
         })
     } else {
         None
@@ -86,6 +84,79 @@ pub fn load_queries_from_database(
     Ok((results, cursor))
 }
 
+/// Load a page of sorted queries from the database according to the
+/// parameters. Sorted queries are paged by offset, and the number of queries
+/// skipped is `offset`. The queries are returned with whether there are more
+/// queries after them.
+pub fn load_sorted_queries_from_database(
+    db: &SqliteConnection,
+    params: &HistoryParams,
+    sort: HistorySort,
+    search_client_ips: &[String],
+    env: &Env,
+    offset: usize,
+    limit: usize
+) -> Result<(Vec<FtlDbQuery>, bool), Error> {
+    // Use the Diesel DSL of this table for easy querying
+    use crate::databases::ftl::queries::dsl::*;
+
+    // Take up to the limit, plus one to check if there are more queries
+    let db_query = queries
+        .into_boxed()
+        .offset(offset as i64)
+        .limit((limit + 1) as i64);
+    let db_query = sort_db(db_query, sort);
+    let db_query = filter_db_query(db_query, params, search_client_ips, env)?;
+
+    let mut results: Vec<FtlDbQuery> = execute_query(db, db_query)?;
+    let more = results.len() > limit;
+    results.truncate(limit);
+
+    Ok((results, more))
+}
+
+/// Count the queries in the database which match the filters. The category
+/// filter can not be applied, because categories are not stored in the
+/// database, so counting is not allowed with a category.
+pub fn count_queries_in_database(
+    db: &SqliteConnection,
+    params: &HistoryParams,
+    search_client_ips: &[String],
+    env: &Env
+) -> Result<usize, Error> {
+    let db_query = filter_db_query(queries::table.into_boxed(), params, search_client_ips, env)?;
+
+    let count: i64 = db_query
+        .count()
+        .get_result(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    Ok(count as usize)
+}
+
+/// Apply the history filters to the database query
+fn filter_db_query<'a>(
+    db_query: queries::BoxedQuery<'a, Sqlite>,
+    params: &'a HistoryParams,
+    search_client_ips: &'a [String],
+    env: &'a Env
+) -> Result<queries::BoxedQuery<'a, Sqlite>, Error> {
+    let db_query = filter_time_from_db(db_query, params);
+    let db_query = filter_time_until_db(db_query, params);
+    let db_query = filter_domain_db(db_query, params);
+    let db_query = filter_client_db(db_query, params);
+    let db_query = filter_search_db(db_query, params, search_client_ips);
+    let db_query = filter_upstream_db(db_query, params);
+    let db_query = filter_query_type_db(db_query, params);
+    let db_query = filter_status_db(db_query, params);
+    let db_query = filter_blocked_db(db_query, params);
+    let db_query = filter_excluded_domains_db(db_query, env)?;
+    let db_query = filter_excluded_clients_db(db_query, env)?;
+    let db_query = filter_setup_vars_setting_db(db_query, env)?;
+
+    Ok(db_query)
+}
+
 /// Execute a database query for DNS queries on an FTL database.
 /// The database could be real, or it could be a test database.
 pub fn execute_query(
@@ -100,7 +171,7 @@ pub fn execute_query(
 
 #[cfg(test)]
 mod test {
-    use super::load_queries_from_database;
+    use super::{count_queries_in_database, load_queries_from_database};
     use crate::{
         databases::ftl::connect_to_test_db,
         env::{Config, Env},
@@ -108,15 +179,16 @@ mod test {
     };
     use std::collections::HashMap;
 
-    /// Queries are ordered by id, descending
+    /// Queries are ordered by timestamp and id, descending
     #[test]
     fn order_by_id() {
         let env = Env::Test(Config::default(), HashMap::new());
 
         let (queries, cursor) = load_queries_from_database(
             &connect_to_test_db(),
-            Some(2),
+            Some((0, 2)),
             &HistoryParams::default(),
+            &[],
             &env,
             100
         )
@@ -133,13 +205,16 @@ mod test {
         let env = Env::Test(Config::default(), HashMap::new());
         let expected_cursor = Some(HistoryCursor {
             id: None,
-            db_id: Some(1)
+            db_id: Some(1),
+            db_timestamp: Some(0),
+            offset: None
         });
 
         let (queries, cursor) = load_queries_from_database(
             &connect_to_test_db(),
-            Some(3),
+            Some((164_431, 3)),
             &HistoryParams::default(),
+            &[],
             &env,
             2
         )
@@ -148,4 +223,59 @@ mod test {
         assert_eq!(queries.len(), 2);
         assert_eq!(cursor, expected_cursor);
     }
+
+    /// A cursor in the middle of queries with the same timestamp continues
+    /// with the rest of them before moving on to older queries
+    #[test]
+    fn same_timestamp() {
+        let env = Env::Test(Config::default(), HashMap::new());
+
+        let (queries, cursor) = load_queries_from_database(
+            &connect_to_test_db(),
+            Some((177_161, 88)),
+            &HistoryParams::default(),
+            &[],
+            &env,
+            4
+        )
+        .unwrap();
+
+        let ids: Vec<i32> = queries.iter().map(|query| query.id.unwrap()).collect();
+
+        assert_eq!(ids, vec![88, 87, 86, 85]);
+        assert_eq!(
+            cursor,
+            Some(HistoryCursor {
+                id: None,
+                db_id: Some(84),
+                db_timestamp: Some(177_160),
+                offset: None
+            })
+        );
+    }
+
+    /// The queries which match the filters are counted
+    #[test]
+    fn count() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let db = connect_to_test_db();
+
+        assert_eq!(
+            count_queries_in_database(&db, &HistoryParams::default(), &[], &env).unwrap(),
+            94
+        );
+        assert_eq!(
+            count_queries_in_database(
+                &db,
+                &HistoryParams {
+                    from: Some(177_000),
+                    ..HistoryParams::default()
+                },
+                &[],
+                &env
+            )
+            .unwrap(),
+            42
+        );
+    }
 }
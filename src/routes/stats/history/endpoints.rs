@@ -9,47 +9,212 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    client_nicknames::ClientNicknames,
     databases::ftl::FtlDatabase,
     env::Env,
     ftl::{FtlDnssecType, FtlMemory, FtlQueryReplyType, FtlQueryStatus, FtlQueryType},
-    routes::{auth::User, stats::history::get_history::get_history},
-    util::{Error, ErrorKind, Reply}
+    routes::{
+        auth::User,
+        fields::FieldSelection,
+        stats::{
+            history::{
+                cursor::{CursorSigner, SignedCursor},
+                export::{export_history, EXPORT_FILE_NAME},
+                get_history::get_history,
+                ndjson::{stream_history, AcceptsNdjson, HistoryStream},
+                sort::HistorySort
+            },
+            DomainDisplay
+        }
+    },
+    services::ThreatCategories,
+    util::{CsvFile, Error, HeadReply, SetStatus}
 };
-use base64::{decode, encode};
-use failure::ResultExt;
 use rocket::{
-    http::RawStr,
-    request::{Form, FromFormValue},
+    http::{ContentType, RawStr},
+    request::{Form, FromFormValue, Request},
+    response::{self, Responder},
     State
 };
+use rocket_contrib::json::JsonValue;
 
-/// Get the query history according to the specified parameters
+/// Get the query history according to the specified parameters. With
+/// `format=csv`, the full filtered history is downloaded as a CSV file instead
+/// of a page of JSON. With `Accept: application/x-ndjson` or `format=ndjson`,
+/// the full filtered history is streamed with one query per line.
 #[get("/stats/history?<params..>")]
-pub fn history(
+#[allow(clippy::too_many_arguments)]
+pub fn history<'r>(
+    _auth: User,
+    ftl_memory: State<'r, FtlMemory>,
+    env: State<'r, Env>,
+    params: Form<HistoryParams>,
+    db: FtlDatabase,
+    cursor_signer: State<'r, CursorSigner>,
+    nicknames: State<'r, ClientNicknames>,
+    threat_categories: State<'r, ThreatCategories>,
+    accepts_ndjson: AcceptsNdjson
+) -> Result<HistoryReply<'r>, Error> {
+    let params = params.into_inner();
+
+    match params
+        .format
+        .unwrap_or_else(|| HistoryFormat::from_accept(accepts_ndjson))
+    {
+        HistoryFormat::Ndjson => stream_history(
+            ftl_memory.inner(),
+            env.inner(),
+            params,
+            db,
+            &cursor_signer,
+            nicknames.inner(),
+            threat_categories.inner()
+        )
+        .map(HistoryReply::Ndjson),
+        HistoryFormat::Csv => export_history(
+            &ftl_memory,
+            &env,
+            params,
+            &db,
+            &cursor_signer,
+            &nicknames,
+            &threat_categories
+        )
+        .map(HistoryReply::Csv),
+        HistoryFormat::Json => get_history(
+            &ftl_memory,
+            &env,
+            params,
+            &db,
+            &cursor_signer,
+            &nicknames,
+            &threat_categories
+        )
+        .map(HistoryReply::Json)
+    }
+}
+
+/// Answer a HEAD request for the query history with the headers of the
+/// requested format, without loading the history. Only the cursor is checked,
+/// so a GET request with the same parameters can still fail on its filters.
+#[head("/stats/history?<params..>")]
+pub fn history_head(
     _auth: User,
-    ftl_memory: State<FtlMemory>,
-    env: State<Env>,
     params: Form<HistoryParams>,
-    db: FtlDatabase
-) -> Reply {
-    get_history(&ftl_memory, &env, params.into_inner(), &db)
+    cursor_signer: State<CursorSigner>,
+    accepts_ndjson: AcceptsNdjson
+) -> Result<HeadReply, Error> {
+    if let Some(ref signed) = params.cursor {
+        cursor_signer.verify(signed)?;
+    }
+
+    Ok(params
+        .format
+        .unwrap_or_else(|| HistoryFormat::from_accept(accepts_ndjson))
+        .head_reply())
+}
+
+/// The reply of the history endpoint, depending on the requested format
+pub enum HistoryReply<'r> {
+    Json(SetStatus<JsonValue>),
+    Csv(CsvFile),
+    Ndjson(HistoryStream<'r>)
+}
+
+impl<'r> Responder<'r> for HistoryReply<'r> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            HistoryReply::Json(reply) => reply.respond_to(request),
+            HistoryReply::Csv(file) => file.respond_to(request),
+            HistoryReply::Ndjson(stream) => stream.respond_to(request)
+        }
+    }
+}
+
+/// The formats the query history can be returned in
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum HistoryFormat {
+    Json,
+    Csv,
+    Ndjson
+}
+
+impl HistoryFormat {
+    /// Get the format to use when none was given as a parameter
+    pub fn from_accept(accepts_ndjson: AcceptsNdjson) -> Self {
+        if accepts_ndjson.0 {
+            HistoryFormat::Ndjson
+        } else {
+            HistoryFormat::Json
+        }
+    }
+
+    /// Get the headers of a reply in this format
+    pub fn head_reply(self) -> HeadReply {
+        match self {
+            HistoryFormat::Json => HeadReply {
+                content_type: ContentType::JSON,
+                file_name: None
+            },
+            HistoryFormat::Csv => HeadReply {
+                content_type: ContentType::CSV,
+                file_name: Some(EXPORT_FILE_NAME.to_owned())
+            },
+            HistoryFormat::Ndjson => HeadReply {
+                content_type: ContentType::new("application", "x-ndjson"),
+                file_name: None
+            }
+        }
+    }
+}
+
+impl<'v> FromFormValue<'v> for HistoryFormat {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Self, Self::Error> {
+        match form_value.as_str() {
+            "json" => Ok(HistoryFormat::Json),
+            "csv" => Ok(HistoryFormat::Csv),
+            "ndjson" => Ok(HistoryFormat::Ndjson),
+            _ => Err(form_value)
+        }
+    }
 }
 
 /// Represents the possible GET parameters on `/stats/history`
 #[derive(FromForm)]
 pub struct HistoryParams {
-    pub cursor: Option<HistoryCursor>,
+    pub cursor: Option<SignedCursor>,
     pub from: Option<u64>,
     pub until: Option<u64>,
     pub domain: Option<String>,
     pub client: Option<String>,
+    /// Search text, matched against the domain, client IP, and client name
+    pub q: Option<String>,
     pub upstream: Option<String>,
     pub query_type: Option<FtlQueryType>,
     pub status: Option<FtlQueryStatus>,
     pub blocked: Option<bool>,
     pub dnssec: Option<FtlDnssecType>,
     pub reply: Option<FtlQueryReplyType>,
-    pub limit: Option<usize>
+    /// The category of the domain in the threat feed
+    pub category: Option<String>,
+    pub limit: Option<usize>,
+    /// The number of queries to skip, for paging by offset instead of by
+    /// cursor. Only used by the database history, and not with `category`.
+    pub offset: Option<usize>,
+    /// Count the queries which match the filters. Only used by the database
+    /// history, and not with `category`.
+    pub total: Option<bool>,
+    pub sort: Option<HistorySort>,
+    pub format: Option<HistoryFormat>,
+    /// Show internationalized domains in Unicode or ASCII. The filters always
+    /// match the ASCII form. Only used by the JSON history.
+    pub display: Option<DomainDisplay>,
+    /// The fields to include in each query. Not used by the CSV export,
+    /// which always has the same columns.
+    pub fields: Option<FieldSelection>
 }
 
 impl Default for HistoryParams {
@@ -60,44 +225,39 @@ impl Default for HistoryParams {
             until: None,
             domain: None,
             client: None,
+            q: None,
             upstream: None,
             query_type: None,
             status: None,
             blocked: None,
             dnssec: None,
             reply: None,
-            limit: Some(100)
+            category: None,
+            limit: Some(100),
+            offset: None,
+            total: None,
+            sort: None,
+            format: None,
+            display: None,
+            fields: None
         }
     }
 }
 
-/// The cursor object used for history pagination
+/// The cursor object used for history pagination. It is signed by
+/// [`CursorSigner`] before being given to the client.
+///
+/// [`CursorSigner`]: ../cursor/struct.CursorSigner.html
 #[cfg_attr(test, derive(PartialEq, Debug))]
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone)]
 pub struct HistoryCursor {
     pub id: Option<i32>,
-    pub db_id: Option<i64>
-}
-
-impl HistoryCursor {
-    /// Get the Base64 representation of the cursor
-    pub fn as_base64(&self) -> Result<String, Error> {
-        let bytes = serde_json::to_vec(self).context(ErrorKind::Unknown)?;
-
-        Ok(encode(&bytes))
-    }
-}
-
-impl<'a> FromFormValue<'a> for HistoryCursor {
-    type Error = Error;
-
-    fn from_form_value(form_value: &'a RawStr) -> Result<Self, Self::Error> {
-        // Decode from Base64
-        let decoded = decode(form_value).context(ErrorKind::BadRequest)?;
-
-        // Deserialize from JSON
-        let cursor = serde_json::from_slice(&decoded).context(ErrorKind::BadRequest)?;
-
-        Ok(cursor)
-    }
+    pub db_id: Option<i64>,
+    /// The timestamp of the database query at the cursor. Database queries
+    /// are paged by timestamp and then by ID, because FTL can store queries
+    /// out of order.
+    pub db_timestamp: Option<i64>,
+    /// The number of queries already returned, when the history is sorted.
+    /// Sorted history is paged by offset instead of by query ID.
+    pub offset: Option<usize>
 }
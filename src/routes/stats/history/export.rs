@@ -0,0 +1,205 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History CSV Export
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::{cursor::CursorSigner, endpoints::HistoryParams, get_history::load_history_page};
+use crate::{
+    client_nicknames::ClientNicknames,
+    databases::ftl::FtlDatabase,
+    env::Env,
+    ftl::FtlMemory,
+    services::ThreatCategories,
+    util::{CsvFile, Error}
+};
+use rocket_contrib::json::JsonValue;
+use serde_json::Value;
+
+/// The number of queries loaded at a time while exporting
+pub(super) const EXPORT_PAGE_SIZE: usize = 1000;
+
+/// The suggested name of the CSV file
+pub(super) const EXPORT_FILE_NAME: &str = "history.csv";
+
+/// The columns of the CSV file. They are the same as the fields of the JSON
+/// history.
+const COLUMNS: [&str; 9] = [
+    "timestamp",
+    "type",
+    "status",
+    "domain",
+    "client",
+    "dnssec",
+    "reply",
+    "response_time",
+    "category"
+];
+
+/// Export the full filtered history as a CSV file, starting at the cursor if
+/// there is one. The limit is ignored, and the history is loaded a page at a
+/// time by following the cursors.
+pub fn export_history(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    mut params: HistoryParams,
+    db: &FtlDatabase,
+    cursor_signer: &CursorSigner,
+    nicknames: &ClientNicknames,
+    threat_categories: &ThreatCategories
+) -> Result<CsvFile, Error> {
+    // Make sure the cursor is valid before using it
+    let mut cursor = match params.cursor {
+        Some(ref signed) => Some(cursor_signer.verify(signed)?),
+        None => None
+    };
+
+    params.limit = Some(EXPORT_PAGE_SIZE);
+
+    let mut data = COLUMNS.join(",");
+    data.push('\n');
+
+    loop {
+        let page = load_history_page(
+            ftl_memory,
+            env,
+            &params,
+            cursor,
+            db,
+            nicknames,
+            threat_categories
+        )?;
+
+        for query in &page.history {
+            write_row(&mut data, query);
+        }
+
+        match page.cursor {
+            Some(next) => cursor = Some(next),
+            None => break
+        }
+    }
+
+    Ok(CsvFile {
+        name: EXPORT_FILE_NAME.to_owned(),
+        data
+    })
+}
+
+/// Write the query as a CSV row. Missing fields are left empty.
+fn write_row(data: &mut String, query: &JsonValue) {
+    let row: Vec<String> = COLUMNS
+        .iter()
+        .map(|&column| match query.get(column) {
+            Some(Value::String(text)) => escape_field(text),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string()
+        })
+        .collect();
+
+    data.push_str(&row.join(","));
+    data.push('\n');
+}
+
+/// Quote the field if it contains a comma, quote, or line break. Quotes inside
+/// the field are doubled.
+fn escape_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_field, write_row};
+    use crate::{
+        env::PiholeFile, routes::stats::history::testing::test_memory, testing::TestBuilder
+    };
+    use rocket::http::Method;
+
+    /// Fields with special characters are quoted
+    #[test]
+    fn escape() {
+        assert_eq!(escape_field("domain.com"), "domain.com");
+        assert_eq!(escape_field("Living room, TV"), "\"Living room, TV\"");
+        assert_eq!(escape_field("The \"laptop\""), "\"The \"\"laptop\"\"\"");
+    }
+
+    /// A query is written as a row, with missing fields left empty
+    #[test]
+    fn row() {
+        let mut data = String::new();
+        write_row(
+            &mut data,
+            &json!({
+                "timestamp": 263_581,
+                "type": 1,
+                "status": 2,
+                "domain": "domain1.com",
+                "client": "client1",
+                "dnssec": 1,
+                "reply": 3,
+                "response_time": 1
+            })
+        );
+
+        assert_eq!(data, "263581,1,2,domain1.com,client1,1,3,1,\n");
+    }
+
+    /// The history is downloaded as a CSV file, including the queries from
+    /// the database
+    #[test]
+    fn export_csv() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?from=177180&until=177181&format=csv")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_header("Content-Type", "text/csv; charset=utf-8")
+            .expect_header(
+                "Content-Disposition",
+                "attachment; filename=\"history.csv\""
+            )
+            .expect_body(
+                "timestamp,type,status,domain,client,dnssec,reply,response_time,category\n\
+                 177180,6,2,4.4.8.8.in-addr.arpa,127.0.0.1,5,0,0,\n\
+                 177180,6,3,1.1.1.10.in-addr.arpa,127.0.0.1,5,0,0,\n"
+            )
+            .test();
+    }
+
+    /// HEAD requests get the headers of the CSV file without loading the
+    /// history, so they work without the database
+    #[test]
+    fn head_csv() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?format=csv")
+            .method(Method::Head)
+            .expect_header("Content-Type", "text/csv; charset=utf-8")
+            .expect_header(
+                "Content-Disposition",
+                "attachment; filename=\"history.csv\""
+            )
+            .expect_empty_body()
+            .test();
+    }
+
+    /// Maximum privacy exports no queries
+    #[test]
+    fn privacy_max() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?format=csv")
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=3")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_body(
+                "timestamp,type,status,domain,client,dnssec,reply,response_time,category\n"
+            )
+            .test();
+    }
+}
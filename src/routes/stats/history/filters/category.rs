@@ -0,0 +1,146 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Category Filter
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::FtlDbQuery,
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    routes::stats::history::endpoints::HistoryParams,
+    services::ThreatCategories,
+    util::Error
+};
+use std::{collections::HashSet, iter};
+
+/// Only show queries of domains in the specified category. The category of
+/// each domain is looked up once, instead of once per query.
+pub fn filter_category<'a>(
+    queries_iter: Box<dyn Iterator<Item = &'a FtlQuery> + 'a>,
+    params: &HistoryParams,
+    ftl_memory: &FtlMemory,
+    ftl_lock: &ShmLockGuard<'a>,
+    threat_categories: &ThreatCategories
+) -> Result<Box<dyn Iterator<Item = &'a FtlQuery> + 'a>, Error> {
+    if let Some(ref category) = params.category {
+        // Find the domains in the category. If none are found, return an
+        // empty iterator because no query can match the category requested
+        let counters = ftl_memory.counters(ftl_lock)?;
+        let strings = ftl_memory.strings(ftl_lock)?;
+        let domains = ftl_memory.domains(ftl_lock)?;
+        let domain_ids: HashSet<usize> = domains
+            .iter()
+            .take(counters.total_domains as usize)
+            .enumerate()
+            .filter(|(_, domain)| {
+                threat_categories
+                    .category(domain.get_domain(&strings))
+                    .as_ref()
+                    == Some(category)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        if !domain_ids.is_empty() {
+            Ok(Box::new(queries_iter.filter(move |query| {
+                domain_ids.contains(&(query.domain_id as usize))
+            })))
+        } else {
+            Ok(Box::new(iter::empty()))
+        }
+    } else {
+        Ok(queries_iter)
+    }
+}
+
+/// Only keep database queries of domains in the specified category. The
+/// categories are not in the database, so this is done after the queries are
+/// loaded.
+pub fn filter_category_db(
+    db_queries: Vec<FtlDbQuery>,
+    params: &HistoryParams,
+    threat_categories: &ThreatCategories
+) -> Vec<FtlDbQuery> {
+    if let Some(ref category) = params.category {
+        db_queries
+            .into_iter()
+            .filter(|query| threat_categories.category(&query.domain).as_ref() == Some(category))
+            .collect()
+    } else {
+        db_queries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{filter_category, filter_category_db};
+    use crate::{
+        databases::ftl::FtlDbQuery,
+        ftl::{FtlQuery, ShmLockGuard},
+        routes::stats::history::{
+            endpoints::HistoryParams,
+            testing::{test_memory, test_queries}
+        },
+        services::ThreatCategories
+    };
+    use std::collections::HashMap;
+
+    /// Create the threat categories for the tests
+    fn test_categories() -> ThreatCategories {
+        let mut categories = HashMap::new();
+        categories.insert("domain2.com".to_owned(), "malware".to_owned());
+        categories.insert("domain3.com".to_owned(), "phishing".to_owned());
+
+        ThreatCategories::from(categories)
+    }
+
+    /// Only return queries of domains in the specified category
+    #[test]
+    fn simple() {
+        let queries = test_queries();
+        let expected_queries = vec![&queries[3]];
+        let filtered_queries: Vec<&FtlQuery> = filter_category(
+            Box::new(queries.iter()),
+            &HistoryParams {
+                category: Some("malware".to_owned()),
+                ..HistoryParams::default()
+            },
+            &test_memory(),
+            &ShmLockGuard::Test,
+            &test_categories()
+        )
+        .unwrap()
+        .collect();
+
+        assert_eq!(filtered_queries, expected_queries);
+    }
+
+    /// Only database queries of domains in the specified category are kept
+    #[test]
+    fn database() {
+        let db_query = |id: i32, domain: &str| FtlDbQuery {
+            id: Some(id),
+            timestamp: 0,
+            query_type: 1,
+            status: 1,
+            domain: domain.to_owned(),
+            client: "127.0.0.1".to_owned(),
+            upstream: None
+        };
+
+        let filtered_queries = filter_category_db(
+            vec![db_query(1, "domain1.com"), db_query(2, "www.domain3.com")],
+            &HistoryParams {
+                category: Some("phishing".to_owned()),
+                ..HistoryParams::default()
+            },
+            &test_categories()
+        );
+
+        assert_eq!(filtered_queries, vec![db_query(2, "www.domain3.com")]);
+    }
+}
@@ -9,6 +9,7 @@
 // Please see LICENSE file for your rights under this license.
 
 mod blocked;
+mod category;
 mod client;
 mod dnssec;
 mod domain;
@@ -17,12 +18,14 @@ mod exclude_domains;
 mod private;
 mod query_type;
 mod reply;
+mod search;
 mod setup_vars;
 mod status;
 mod time;
 mod upstream;
 
 pub use self::{
-    blocked::*, client::*, dnssec::*, domain::*, exclude_clients::*, exclude_domains::*,
-    private::*, query_type::*, reply::*, setup_vars::*, status::*, time::*, upstream::*
+    blocked::*, category::*, client::*, dnssec::*, domain::*, exclude_clients::*,
+    exclude_domains::*, private::*, query_type::*, reply::*, search::*, setup_vars::*, status::*,
+    time::*, upstream::*
 };
@@ -0,0 +1,225 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Search Filter
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::queries,
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    routes::stats::history::endpoints::HistoryParams,
+    util::Error
+};
+use diesel::{prelude::*, sqlite::Sqlite};
+use std::{collections::HashSet, iter};
+
+/// Only show queries where the domain, client IP, or client name contains
+/// the search text
+pub fn filter_search<'a>(
+    queries_iter: Box<dyn Iterator<Item = &'a FtlQuery> + 'a>,
+    params: &HistoryParams,
+    ftl_memory: &FtlMemory,
+    ftl_lock: &ShmLockGuard<'a>
+) -> Result<Box<dyn Iterator<Item = &'a FtlQuery> + 'a>, Error> {
+    if let Some(ref search) = params.q {
+        // Find the matching domains and clients. If none are found, return an
+        // empty iterator because no query can match the search
+        let counters = ftl_memory.counters(ftl_lock)?;
+        let strings = ftl_memory.strings(ftl_lock)?;
+        let domains = ftl_memory.domains(ftl_lock)?;
+        let clients = ftl_memory.clients(ftl_lock)?;
+
+        let domain_ids: HashSet<usize> = domains
+            .iter()
+            .take(counters.total_domains as usize)
+            .enumerate()
+            .filter(|(_, domain)| domain.get_domain(&strings).contains(search))
+            .map(|(i, _)| i)
+            .collect();
+        let client_ids: HashSet<usize> = clients
+            .iter()
+            .take(counters.total_clients as usize)
+            .enumerate()
+            .filter(|(_, client)| {
+                client.get_ip(&strings).contains(search)
+                    || client
+                        .get_name(&strings)
+                        .unwrap_or_default()
+                        .contains(search)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        if !domain_ids.is_empty() || !client_ids.is_empty() {
+            Ok(Box::new(queries_iter.filter(move |query| {
+                domain_ids.contains(&(query.domain_id as usize))
+                    || client_ids.contains(&(query.client_id as usize))
+            })))
+        } else {
+            Ok(Box::new(iter::empty()))
+        }
+    } else {
+        Ok(queries_iter)
+    }
+}
+
+/// Get the IPs of clients whose name contains the search text. The database
+/// only stores client IPs, so these are used to search client names in
+/// database results.
+pub fn search_client_ips(
+    params: &HistoryParams,
+    ftl_memory: &FtlMemory,
+    ftl_lock: &ShmLockGuard
+) -> Result<Vec<String>, Error> {
+    let search = match params.q {
+        Some(ref search) => search,
+        None => return Ok(Vec::new())
+    };
+
+    let counters = ftl_memory.counters(ftl_lock)?;
+    let strings = ftl_memory.strings(ftl_lock)?;
+    let clients = ftl_memory.clients(ftl_lock)?;
+
+    Ok(clients
+        .iter()
+        .take(counters.total_clients as usize)
+        .filter(|client| {
+            client
+                .get_name(&strings)
+                .unwrap_or_default()
+                .contains(search.as_str())
+        })
+        .map(|client| client.get_ip(&strings).to_owned())
+        .collect())
+}
+
+/// Only show queries where the domain or client contains the search text in
+/// database results. `client_ips` are the IPs of clients whose name matches
+/// the search, from [`search_client_ips`].
+///
+/// [`search_client_ips`]: fn.search_client_ips.html
+pub fn filter_search_db<'a>(
+    db_query: queries::BoxedQuery<'a, Sqlite>,
+    params: &HistoryParams,
+    client_ips: &[String]
+) -> queries::BoxedQuery<'a, Sqlite> {
+    // Use the Diesel DSL of this table for easy querying
+    use self::queries::dsl::*;
+
+    if let Some(ref search) = params.q {
+        let pattern = format!("%{}%", search);
+
+        db_query.filter(
+            domain
+                .like(pattern.clone())
+                .or(client.like(pattern))
+                .or(client.eq_any(client_ips.to_vec()))
+        )
+    } else {
+        db_query
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{filter_search, filter_search_db, search_client_ips};
+    use crate::{
+        databases::ftl::connect_to_test_db,
+        ftl::{FtlQuery, ShmLockGuard},
+        routes::stats::history::{
+            database::execute_query,
+            endpoints::HistoryParams,
+            testing::{test_memory, test_queries}
+        }
+    };
+    use diesel::prelude::*;
+
+    /// Filter the test queries with the search text
+    fn search(queries: &[FtlQuery], search: &str) -> Vec<usize> {
+        filter_search(
+            Box::new(queries.iter()),
+            &HistoryParams {
+                q: Some(search.to_owned()),
+                ..HistoryParams::default()
+            },
+            &test_memory(),
+            &ShmLockGuard::Test
+        )
+        .unwrap()
+        .map(|query| query.id as usize)
+        .collect()
+    }
+
+    /// Queries with a matching domain are returned
+    #[test]
+    fn domain() {
+        assert_eq!(search(&test_queries(), "domain3"), vec![6]);
+    }
+
+    /// Queries with a matching client IP are returned
+    #[test]
+    fn client_ip() {
+        assert_eq!(search(&test_queries(), "1.12"), vec![7, 8]);
+    }
+
+    /// Queries with a matching client name are returned
+    #[test]
+    fn client_name() {
+        assert_eq!(search(&test_queries(), "client1"), vec![1, 2, 3]);
+    }
+
+    /// Queries matching either the domain or the client are returned
+    #[test]
+    fn domain_or_client() {
+        assert_eq!(search(&test_queries(), "2"), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    /// No queries are returned if nothing matches
+    #[test]
+    fn no_match() {
+        assert!(search(&test_queries(), "nothing").is_empty());
+    }
+
+    /// The IPs of clients with a matching name are found
+    #[test]
+    fn client_ips() {
+        let params = HistoryParams {
+            q: Some("client".to_owned()),
+            ..HistoryParams::default()
+        };
+
+        assert_eq!(
+            search_client_ips(&params, &test_memory(), &ShmLockGuard::Test).unwrap(),
+            vec!["192.168.1.10".to_owned()]
+        );
+    }
+
+    /// Queries with a matching domain or client IP are returned. This is a
+    /// database filter.
+    #[test]
+    fn database() {
+        use crate::databases::ftl::queries::dsl::*;
+
+        let db = connect_to_test_db();
+        let search = |text: &str, client_ips: &[String]| {
+            let params = HistoryParams {
+                q: Some(text.to_owned()),
+                ..HistoryParams::default()
+            };
+
+            execute_query(
+                &db,
+                filter_search_db(queries.into_boxed(), &params, client_ips)
+            )
+            .unwrap()
+        };
+
+        assert_eq!(search("github", &[]).len(), 12);
+        assert_eq!(search("10.1", &[])[0].client, "10.1.1.1");
+        assert_eq!(search("laptop", &["10.1.1.1".to_owned()]).len(), 1);
+    }
+}
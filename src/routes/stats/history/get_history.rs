@@ -9,18 +9,26 @@
 // Please see LICENSE file for your rights under this license.
 
 use super::{
+    cursor::CursorSigner,
     endpoints::{HistoryCursor, HistoryParams},
     filters::*,
-    map_query_to_json::map_query_to_json,
-    skip_to_cursor::skip_to_cursor
+    map_query_to_json::{map_query_to_json, tag_category},
+    skip_to_cursor::{db_cursor_key, skip_to_cursor},
+    sort::{sort_queries, HistorySort}
 };
 use crate::{
-    databases::ftl::FtlDatabase,
+    client_nicknames::ClientNicknames,
+    databases::ftl::{FtlDatabase, FtlDbQuery},
     env::Env,
     ftl::{FtlMemory, FtlQuery},
-    routes::stats::history::database::load_queries_from_database,
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
-    util::{reply_data, Reply}
+    metrics::time_database,
+    routes::stats::{
+        annotations::history_annotations,
+        history::database::{load_queries_from_database, load_sorted_queries_from_database},
+        privacy::PrivacyPolicy
+    },
+    services::ThreatCategories,
+    util::{reply_data, Error, Reply}
 };
 use diesel::sqlite::SqliteConnection;
 use rocket_contrib::json::JsonValue;
@@ -31,16 +39,70 @@ pub fn get_history(
     ftl_memory: &FtlMemory,
     env: &Env,
     params: HistoryParams,
-    db: &FtlDatabase
+    db: &FtlDatabase,
+    cursor_signer: &CursorSigner,
+    nicknames: &ClientNicknames,
+    threat_categories: &ThreatCategories
 ) -> Reply {
+    // Make sure the cursor is valid before using it
+    let cursor = match params.cursor {
+        Some(ref signed) => Some(cursor_signer.verify(signed)?),
+        None => None
+    };
+
+    let mut page = load_history_page(
+        ftl_memory,
+        env,
+        &params,
+        cursor,
+        db,
+        nicknames,
+        threat_categories
+    )?;
+
+    // Include the notes about the queries in the page. The notes are matched
+    // to the stored form of the domains, so they are found before the
+    // domains are formatted.
+    let annotations = history_annotations(&page.history, db as &SqliteConnection)?;
+    params
+        .display
+        .unwrap_or_default()
+        .format_history(&mut page.history);
+
+    if let Some(ref fields) = params.fields {
+        fields.select(&mut page.history);
+    }
+
+    reply_data(json!({
+        "cursor": page.cursor.map(|cursor| cursor_signer.sign(cursor).unwrap()),
+        "history": page.history,
+        "annotations": annotations
+    }))
+}
+
+/// A page of the query history, and the cursor of the next page if there is
+/// one
+pub struct HistoryPage {
+    pub cursor: Option<HistoryCursor>,
+    pub history: Vec<JsonValue>
+}
+
+/// Load a page of the query history, starting at the (verified) cursor
+pub fn load_history_page(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    params: &HistoryParams,
+    cursor: Option<HistoryCursor>,
+    db: &FtlDatabase,
+    nicknames: &ClientNicknames,
+    threat_categories: &ThreatCategories
+) -> Result<HistoryPage, Error> {
     // Check if query details are private
-    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(env)? >= FtlPrivacyLevel::Maximum {
-        // `None::<()>` represents `null` in JSON. It needs the type parameter because
-        // it doesn't know what type of Option it is (`Option<T>`)
-        return reply_data(json!({
-            "cursor": None::<()>,
-            "history": []
-        }));
+    if !PrivacyPolicy::read(env)?.shows_queries() {
+        return Ok(HistoryPage {
+            cursor: None,
+            history: Vec::new()
+        });
     }
 
     let lock = ftl_memory.lock()?;
@@ -74,35 +136,97 @@ pub fn get_history(
     );
 
     // If there is a cursor, skip to the referenced query
-    let queries_iter = skip_to_cursor(queries_iter, &params);
+    let queries_iter = skip_to_cursor(queries_iter, cursor);
 
     // Apply filters
     let queries_iter = filter_private_queries(queries_iter);
     let queries_iter = filter_setup_vars_setting(queries_iter, env)?;
-    let queries_iter = filter_time_from(queries_iter, &params);
-    let queries_iter = filter_time_until(queries_iter, &params);
-    let queries_iter = filter_query_type(queries_iter, &params);
-    let queries_iter = filter_upstream(queries_iter, &params, ftl_memory, &lock)?;
-    let queries_iter = filter_domain(queries_iter, &params, ftl_memory, &lock)?;
-    let queries_iter = filter_client(queries_iter, &params, ftl_memory, &lock)?;
-    let queries_iter = filter_status(queries_iter, &params);
-    let queries_iter = filter_blocked(queries_iter, &params);
-    let queries_iter = filter_dnssec(queries_iter, &params);
-    let queries_iter = filter_reply(queries_iter, &params);
+    let queries_iter = filter_time_from(queries_iter, params);
+    let queries_iter = filter_time_until(queries_iter, params);
+    let queries_iter = filter_query_type(queries_iter, params);
+    let queries_iter = filter_upstream(queries_iter, params, ftl_memory, &lock)?;
+    let queries_iter = filter_domain(queries_iter, params, ftl_memory, &lock)?;
+    let queries_iter = filter_client(queries_iter, params, ftl_memory, &lock)?;
+    let queries_iter = filter_search(queries_iter, params, ftl_memory, &lock)?;
+    let queries_iter = filter_status(queries_iter, params);
+    let queries_iter = filter_blocked(queries_iter, params);
+    let queries_iter = filter_dnssec(queries_iter, params);
+    let queries_iter = filter_reply(queries_iter, params);
+    let queries_iter = filter_category(queries_iter, params, ftl_memory, &lock, threat_categories)?;
     let queries_iter = filter_excluded_domains(queries_iter, env, ftl_memory, &lock)?;
     let queries_iter = filter_excluded_clients(queries_iter, env, ftl_memory, &lock)?;
 
     // Get the limit
     let limit = params.limit.unwrap_or(100);
 
+    // Sorted history is paged by offset. It is loaded from the database if
+    // the timespan is not within the last 24 hours, otherwise from memory.
+    let sort = params.sort.unwrap_or_default();
+    if sort != HistorySort::TimestampDesc {
+        let offset = cursor.and_then(|cursor| cursor.offset).unwrap_or(0);
+
+        let (history, more) = if uses_database(params) {
+            let search_client_ips = search_client_ips(params, ftl_memory, &lock)?;
+
+            let (db_queries, more) = time_database(|| {
+                load_sorted_queries_from_database(
+                    db as &SqliteConnection,
+                    params,
+                    sort,
+                    &search_client_ips,
+                    env,
+                    offset,
+                    limit
+                )
+            })?;
+
+            // Filtering by category can leave fewer queries than the limit,
+            // but the offset still counts the queries which were loaded
+            (map_db_queries(db_queries, params, threat_categories), more)
+        } else {
+            let mut history: Vec<&FtlQuery> = queries_iter.collect();
+            sort_queries(&mut history, sort, ftl_memory, &lock)?;
+
+            let more = history.len() > offset + limit;
+            let history = history
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .map(map_query_to_json(
+                    ftl_memory,
+                    &lock,
+                    nicknames,
+                    threat_categories
+                )?)
+                .collect();
+
+            (history, more)
+        };
+
+        return Ok(HistoryPage {
+            cursor: if more {
+                Some(HistoryCursor {
+                    id: None,
+                    db_id: None,
+                    db_timestamp: None,
+                    offset: Some(offset + limit)
+                })
+            } else {
+                None
+            },
+            history
+        });
+    }
+
     // Apply the limit (plus one to get the cursor) and collect the queries
     let history: Vec<&FtlQuery> = queries_iter.take(limit + 1).collect();
 
     // Get the next cursor from the the "limit+1"-th query, which is the query
     // at index "limit".
     // If no such query exists, the cursor will be None (null in JSON).
-    // The cursor is a JSON object with either the DB ID of the query if it is
-    // non-zero, or the normal ID. Example: { id: 1, db_id: null }
+    // The cursor is a JSON object with either the DB ID and timestamp of the
+    // query if it has a DB ID, or the normal ID.
+    // Example: { id: 1, db_id: null, db_timestamp: null }
     let mut next_cursor = history.get(limit).map(|query: &&FtlQuery| {
         let db_id = if query.database_id != 0 {
             Some(query.database_id)
@@ -115,23 +239,26 @@ pub fn get_history(
             None
         };
 
-        let cursor = HistoryCursor { id, db_id };
-
-        cursor.as_base64().unwrap()
+        HistoryCursor {
+            id,
+            db_id,
+            db_timestamp: db_id.map(|_| query.timestamp as i64),
+            offset: None
+        }
     });
 
-    // Get the last database ID of the in-memory queries we found, or if we
-    // didn't find any in-memory queries, get the database ID in the cursor.
-    // This is done in case we have to query the database to get more queries.
-    // If no ID is found, then the search will start with the most recent
-    // queries in the database.
-    let last_db_id = history
+    // Get the position in the database of the last in-memory query we found,
+    // or if we didn't find any in-memory queries, get the position in the
+    // cursor. This is done in case we have to query the database to get more
+    // queries. If no position is found, then the search will start with the
+    // most recent queries in the database.
+    let last_db_key = history
         .last()
         // Subtract one from the database ID so that the database search starts
         // with the next query instead of the last one we found
-        .map(|query| query.database_id - 1)
-        // If no queries were found, then use the cursor's database ID
-        .or_else(|| params.cursor.map(|cursor| cursor.db_id).unwrap_or(None));
+        .map(|query| (query.timestamp as i64, query.database_id - 1))
+        // If no queries were found, then use the cursor's database position
+        .or_else(|| cursor.and_then(db_cursor_key));
 
     // Map the queries into the output format
     let history: Vec<JsonValue> = history
@@ -139,25 +266,41 @@ pub fn get_history(
             // Only take up to the limit this time, not including the last query,
             // because it was just used to get the cursor
             .take(limit)
-            .map(map_query_to_json(ftl_memory, &lock)?)
+            .map(map_query_to_json(
+                ftl_memory,
+                &lock,
+                nicknames,
+                threat_categories
+            )?)
             .collect();
 
     // If there are not enough queries to reach the limit (next cursor is null),
     // there is a specified timestamp, and the timespan is not entirely within
     // the last 24 hours, then search the database for more queries.
-    let history = if next_cursor.is_none()
-        && (params.from.is_some() || params.until.is_some())
-        && !is_within_24_hours(params.from, params.until)
-    {
-        // Load queries from the database
-        let (db_queries, cursor) =
-            load_queries_from_database(db as &SqliteConnection, last_db_id, &params, env, limit)?;
+    let history = if next_cursor.is_none() && uses_database(params) {
+        // The database only has client IPs, so find the clients with a name
+        // matching the search text
+        let search_client_ips = search_client_ips(params, ftl_memory, &lock)?;
 
-        // Map the queries into JSON
-        let db_queries = db_queries.into_iter().map(Into::into);
+        // Load queries from the database
+        let (db_queries, cursor) = time_database(|| {
+            load_queries_from_database(
+                db as &SqliteConnection,
+                last_db_key,
+                params,
+                &search_client_ips,
+                env,
+                limit
+            )
+        })?;
+
+        // Keep the queries in the category, if there is one, and map them into
+        // JSON. This can leave fewer queries than the limit, but the cursor
+        // still continues the search after the queries which were loaded.
+        let db_queries = map_db_queries(db_queries, params, threat_categories);
 
         // Update the cursor
-        next_cursor = cursor.map(|cursor| cursor.as_base64().unwrap());
+        next_cursor = cursor;
 
         // Extend history with the database queries
         history.into_iter().chain(db_queries).collect()
@@ -165,10 +308,37 @@ pub fn get_history(
         history
     };
 
-    reply_data(json!({
-        "cursor": next_cursor,
-        "history": history
-    }))
+    Ok(HistoryPage {
+        cursor: next_cursor,
+        history
+    })
+}
+
+/// Map the database queries into JSON, keeping the queries in the category if
+/// there is one
+pub fn map_db_queries(
+    db_queries: Vec<FtlDbQuery>,
+    params: &HistoryParams,
+    threat_categories: &ThreatCategories
+) -> Vec<JsonValue> {
+    filter_category_db(db_queries, params, threat_categories)
+        .into_iter()
+        .map(|query| {
+            let domain = query.domain.clone();
+            let mut reply: JsonValue = query.into();
+
+            tag_category(&mut reply, &domain, threat_categories);
+            reply
+        })
+        .collect()
+}
+
+/// Check if the database should be searched for queries, which is when there
+/// is a specified timestamp and the timespan is not entirely within the last
+/// 24 hours
+fn uses_database(params: &HistoryParams) -> bool {
+    (params.from.is_some() || params.until.is_some())
+        && !is_within_24_hours(params.from, params.until)
 }
 
 /// Check if the timespan is completely within the last 24 hours
@@ -197,12 +367,16 @@ fn is_within_24_hours(from: Option<u64>, until: Option<u64>) -> bool {
 #[cfg(test)]
 mod test {
     use crate::{
+        client_nicknames::ClientNicknames,
         env::PiholeFile,
         ftl::ShmLockGuard,
         routes::stats::history::{
+            cursor::CursorSigner,
+            endpoints::HistoryCursor,
             map_query_to_json::map_query_to_json,
             testing::{test_memory, test_queries}
         },
+        services::ThreatCategories,
         testing::TestBuilder
     };
     use rocket_contrib::json::JsonValue;
@@ -220,7 +394,15 @@ mod test {
         let history: Vec<JsonValue> = expected_queries
             .iter()
             .rev()
-            .map(map_query_to_json(&ftl_memory, &ShmLockGuard::Test).unwrap())
+            .map(
+                map_query_to_json(
+                    &ftl_memory,
+                    &ShmLockGuard::Test,
+                    &ClientNicknames::default(),
+                    &ThreatCategories::default()
+                )
+                .unwrap()
+            )
             .collect();
 
         TestBuilder::new()
@@ -228,6 +410,7 @@ mod test {
             .ftl_memory(ftl_memory)
             .need_database(true)
             .expect_json(json!({
+                "annotations": [],
                 "history": history,
                 "cursor": None::<()>
             }))
@@ -247,7 +430,15 @@ mod test {
             .iter()
             .rev()
             .take(5)
-            .map(map_query_to_json(&ftl_memory, &ShmLockGuard::Test).unwrap())
+            .map(
+                map_query_to_json(
+                    &ftl_memory,
+                    &ShmLockGuard::Test,
+                    &ClientNicknames::default(),
+                    &ThreatCategories::default()
+                )
+                .unwrap()
+            )
             .collect();
 
         TestBuilder::new()
@@ -255,8 +446,44 @@ mod test {
             .ftl_memory(ftl_memory)
             .need_database(true)
             .expect_json(json!({
+                "annotations": [],
                 "history": history,
-                "cursor": "eyJpZCI6bnVsbCwiZGJfaWQiOjk3fQ=="
+                "cursor": CursorSigner::test()
+                    .sign(HistoryCursor {
+                        id: None,
+                        db_id: Some(97),
+                        db_timestamp: Some(263_583),
+                        offset: None
+                    })
+                    .unwrap()
+            }))
+            .test();
+    }
+
+    /// Only the selected fields of each query are included
+    #[test]
+    fn fields() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?limit=5&fields=timestamp,domain")
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_json(json!({
+                "annotations": [],
+                "history": [
+                    { "timestamp": 263_586, "domain": "domain5.com" },
+                    { "timestamp": 263_585, "domain": "domain4.com" },
+                    { "timestamp": 263_585, "domain": "domain3.com" },
+                    { "timestamp": 263_584, "domain": "domain1.com" },
+                    { "timestamp": 263_583, "domain": "domain2.com" }
+                ],
+                "cursor": CursorSigner::test()
+                    .sign(HistoryCursor {
+                        id: None,
+                        db_id: Some(97),
+                        db_timestamp: Some(263_583),
+                        offset: None
+                    })
+                    .unwrap()
             }))
             .test();
     }
@@ -270,6 +497,7 @@ mod test {
             .ftl_memory(test_memory())
             .need_database(true)
             .expect_json(json!({
+                "annotations": [],
                 "history": [],
                 "cursor": None::<()>
             }))
@@ -284,6 +512,7 @@ mod test {
             .ftl_memory(test_memory())
             .need_database(true)
             .expect_json(json!({
+                "annotations": [],
                 "history": [
                     {
                         "timestamp": 177_180,
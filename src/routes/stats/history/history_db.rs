@@ -0,0 +1,376 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History Endpoint - DB Version
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::{
+    cursor::CursorSigner,
+    database::{
+        count_queries_in_database, load_queries_from_database, load_sorted_queries_from_database
+    },
+    endpoints::{HistoryCursor, HistoryFormat, HistoryParams, HistoryReply},
+    export::EXPORT_PAGE_SIZE,
+    filters::search_client_ips,
+    get_history::map_db_queries,
+    ndjson::{AcceptsNdjson, HistoryStream},
+    skip_to_cursor::db_cursor_key,
+    sort::HistorySort
+};
+use crate::{
+    databases::ftl::FtlDatabase,
+    env::Env,
+    ftl::FtlMemory,
+    metrics::time_database,
+    routes::{
+        auth::User,
+        stats::{annotations::history_annotations, privacy::PrivacyPolicy}
+    },
+    services::ThreatCategories,
+    util::{reply_data, Error, ErrorKind, HeadReply}
+};
+use diesel::sqlite::SqliteConnection;
+use rocket::{request::Form, State};
+use rocket_contrib::json::JsonValue;
+
+/// Get the query history from the database. The history is paged by cursor,
+/// or by `offset` if it is given or the history is sorted. With `total=true`,
+/// the number of queries matching the filters is included as
+/// `total_matches`. With `Accept: application/x-ndjson` or `format=ndjson`,
+/// the full filtered history is streamed with one query per line.
+#[get("/stats/database/history?<params..>")]
+#[allow(clippy::too_many_arguments)]
+pub fn history_db<'r>(
+    _auth: User,
+    ftl_memory: State<'r, FtlMemory>,
+    env: State<'r, Env>,
+    params: Form<HistoryParams>,
+    db: FtlDatabase,
+    cursor_signer: State<'r, CursorSigner>,
+    threat_categories: State<'r, ThreatCategories>,
+    accepts_ndjson: AcceptsNdjson
+) -> Result<HistoryReply<'r>, Error> {
+    let mut params = params.into_inner();
+
+    // Make sure the cursor is valid before using it
+    let cursor = match params.cursor {
+        Some(ref signed) => Some(cursor_signer.verify(signed)?),
+        None => None
+    };
+
+    if params
+        .format
+        .unwrap_or_else(|| HistoryFormat::from_accept(accepts_ndjson))
+        == HistoryFormat::Ndjson
+    {
+        let ftl_memory = ftl_memory.inner();
+        let env = env.inner();
+        let threat_categories = threat_categories.inner();
+
+        params.limit = Some(EXPORT_PAGE_SIZE);
+        params.total = None;
+
+        return HistoryStream::new(
+            Box::new(move |cursor| {
+                let mut page = time_database(|| {
+                    load_database_history(
+                        ftl_memory,
+                        env,
+                        &params,
+                        cursor,
+                        &db as &SqliteConnection,
+                        threat_categories
+                    )
+                })?;
+
+                // The offset is only for the first page, after which the
+                // cursors hold the offset
+                params.offset = None;
+
+                if let Some(ref fields) = params.fields {
+                    fields.select(&mut page.history);
+                }
+
+                Ok((page.history, page.cursor))
+            }),
+            cursor
+        )
+        .map(HistoryReply::Ndjson);
+    }
+
+    let mut page = time_database(|| {
+        load_database_history(
+            &ftl_memory,
+            &env,
+            &params,
+            cursor,
+            &db as &SqliteConnection,
+            &threat_categories
+        )
+    })?;
+
+    // Include the notes about the queries in the page, before the domains
+    // are formatted
+    let annotations = history_annotations(&page.history, &db as &SqliteConnection)?;
+    params
+        .display
+        .unwrap_or_default()
+        .format_history(&mut page.history);
+
+    if let Some(ref fields) = params.fields {
+        fields.select(&mut page.history);
+    }
+
+    let mut reply = json!({
+        "cursor": page.cursor.map(|cursor| cursor_signer.sign(cursor).unwrap()),
+        "history": page.history,
+        "annotations": annotations
+    });
+
+    if let Some(total_matches) = page.total_matches {
+        reply["total_matches"] = total_matches.into();
+    }
+
+    reply_data(reply).map(HistoryReply::Json)
+}
+
+/// Answer a HEAD request for the database history with the headers of the
+/// requested format, without querying the database. Only the cursor is
+/// checked.
+#[head("/stats/database/history?<params..>")]
+pub fn history_db_head(
+    _auth: User,
+    params: Form<HistoryParams>,
+    cursor_signer: State<CursorSigner>,
+    accepts_ndjson: AcceptsNdjson
+) -> Result<HeadReply, Error> {
+    if let Some(ref signed) = params.cursor {
+        cursor_signer.verify(signed)?;
+    }
+
+    // The database history is not exported as CSV
+    let format = match params
+        .format
+        .unwrap_or_else(|| HistoryFormat::from_accept(accepts_ndjson))
+    {
+        HistoryFormat::Ndjson => HistoryFormat::Ndjson,
+        _ => HistoryFormat::Json
+    };
+
+    Ok(format.head_reply())
+}
+
+/// A page of the database history, and the number of matching queries if it
+/// was requested
+struct DatabaseHistoryPage {
+    cursor: Option<HistoryCursor>,
+    history: Vec<JsonValue>,
+    total_matches: Option<usize>
+}
+
+/// Load a page of the history from the database, starting at the (verified)
+/// cursor or at the offset. Categories are not stored in the database, so they
+/// are only filtered after a page is loaded. The offset and the count would
+/// include queries outside the category, so they can not be used with it.
+fn load_database_history(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    params: &HistoryParams,
+    cursor: Option<HistoryCursor>,
+    db: &SqliteConnection,
+    threat_categories: &ThreatCategories
+) -> Result<DatabaseHistoryPage, Error> {
+    if params.category.is_some() && (params.offset.is_some() || params.total == Some(true)) {
+        return Err(Error::from(ErrorKind::BadRequest));
+    }
+
+    // Check if query details are private
+    if !PrivacyPolicy::read(env)?.shows_queries() {
+        return Ok(DatabaseHistoryPage {
+            cursor: None,
+            history: Vec::new(),
+            total_matches: if params.total == Some(true) {
+                Some(0)
+            } else {
+                None
+            }
+        });
+    }
+
+    // The database only has client IPs, so find the clients with a name
+    // matching the search text
+    let search_client_ips = {
+        let lock = ftl_memory.lock()?;
+        search_client_ips(params, ftl_memory, &lock)?
+    };
+
+    let limit = params.limit.unwrap_or(100);
+    let sort = params.sort.unwrap_or_default();
+    let offset = params
+        .offset
+        .or_else(|| cursor.and_then(|cursor| cursor.offset));
+
+    // Sorted history can only be paged by offset
+    let (db_queries, cursor) = if offset.is_some() || sort != HistorySort::TimestampDesc {
+        let offset = offset.unwrap_or(0);
+        let (db_queries, more) = load_sorted_queries_from_database(
+            db,
+            params,
+            sort,
+            &search_client_ips,
+            env,
+            offset,
+            limit
+        )?;
+        let cursor = if more {
+            Some(HistoryCursor {
+                id: None,
+                db_id: None,
+                db_timestamp: None,
+                offset: Some(offset + limit)
+            })
+        } else {
+            None
+        };
+
+        (db_queries, cursor)
+    } else {
+        load_queries_from_database(
+            db,
+            cursor.and_then(db_cursor_key),
+            params,
+            &search_client_ips,
+            env,
+            limit
+        )?
+    };
+
+    let total_matches = if params.total == Some(true) {
+        Some(count_queries_in_database(
+            db,
+            params,
+            &search_client_ips,
+            env
+        )?)
+    } else {
+        None
+    };
+
+    Ok(DatabaseHistoryPage {
+        cursor,
+        history: map_db_queries(db_queries, params, threat_categories),
+        total_matches
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::load_database_history;
+    use crate::{
+        databases::ftl::connect_to_test_db,
+        env::{Config, Env},
+        routes::stats::{history::endpoints::HistoryParams, testing::test_memory},
+        services::ThreatCategories,
+        util::ErrorKind
+    };
+    use std::collections::HashMap;
+
+    /// Without an offset, the history is paged by cursor and is not counted
+    #[test]
+    fn cursor_paging() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let params = HistoryParams {
+            limit: Some(2),
+            ..HistoryParams::default()
+        };
+
+        let page = load_database_history(
+            &test_memory(),
+            &env,
+            &params,
+            None,
+            &connect_to_test_db(),
+            &ThreatCategories::default()
+        )
+        .unwrap();
+
+        assert_eq!(page.history.len(), 2);
+        assert_eq!(page.cursor.and_then(|cursor| cursor.db_id), Some(92));
+        assert_eq!(page.total_matches, None);
+    }
+
+    /// With an offset, the queries before it are skipped, and the matching
+    /// queries are counted if requested
+    #[test]
+    fn offset_paging() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let params = HistoryParams {
+            limit: Some(10),
+            offset: Some(90),
+            total: Some(true),
+            ..HistoryParams::default()
+        };
+
+        let page = load_database_history(
+            &test_memory(),
+            &env,
+            &params,
+            None,
+            &connect_to_test_db(),
+            &ThreatCategories::default()
+        )
+        .unwrap();
+
+        assert_eq!(page.history.len(), 4);
+        assert!(page.cursor.is_none());
+        assert_eq!(page.total_matches, Some(94));
+    }
+
+    /// The category is filtered after a page is loaded, so the matching
+    /// queries can not be counted or skipped by offset with a category
+    #[test]
+    fn category_without_total_or_offset() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let load = |params: HistoryParams| {
+            load_database_history(
+                &test_memory(),
+                &env,
+                &params,
+                None,
+                &connect_to_test_db(),
+                &ThreatCategories::default()
+            )
+        };
+
+        for params in vec![
+            HistoryParams {
+                category: Some("malware".to_owned()),
+                total: Some(true),
+                ..HistoryParams::default()
+            },
+            HistoryParams {
+                category: Some("malware".to_owned()),
+                offset: Some(10),
+                ..HistoryParams::default()
+            }
+        ] {
+            assert_eq!(
+                load(params).map(|page| page.total_matches).map_err(|e| e.kind()),
+                Err(ErrorKind::BadRequest)
+            );
+        }
+
+        let page = load(HistoryParams {
+            category: Some("malware".to_owned()),
+            ..HistoryParams::default()
+        })
+        .unwrap();
+
+        assert!(page.history.is_empty());
+        assert_eq!(page.total_matches, None);
+    }
+}
@@ -9,7 +9,9 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    client_nicknames::ClientNicknames,
     ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    services::ThreatCategories,
     util::Error
 };
 use rocket_contrib::json::JsonValue;
@@ -17,7 +19,9 @@ use rocket_contrib::json::JsonValue;
 /// Create a function to map `FtlQuery` structs to JSON `Value` structs.
 pub fn map_query_to_json<'a>(
     ftl_memory: &'a FtlMemory,
-    ftl_lock: &ShmLockGuard<'a>
+    ftl_lock: &ShmLockGuard<'a>,
+    nicknames: &'a ClientNicknames,
+    threat_categories: &'a ThreatCategories
 ) -> Result<impl Fn(&FtlQuery) -> JsonValue + 'a, Error> {
     let domains = ftl_memory.domains(ftl_lock)?;
     let clients = ftl_memory.clients(ftl_lock)?;
@@ -27,10 +31,12 @@ pub fn map_query_to_json<'a>(
         let domain = domains[query.domain_id as usize].get_domain(&strings);
         let client = clients[query.client_id as usize];
 
-        // Try to get the client name first, but if it doesn't exist use the IP
-        let client = client
-            .get_name(&strings)
-            .unwrap_or_else(|| client.get_ip(&strings));
+        // Try to get the client nickname or name first, but if neither exist
+        // use the IP
+        let ip = client.get_ip(&strings);
+        let client = nicknames
+            .get(ip)
+            .unwrap_or_else(|| client.get_name(&strings).unwrap_or(ip).to_owned());
 
         // Check if response was received (response time should be smaller than 30min)
         let response_time = if query.response_time < 18_000_000 {
@@ -39,7 +45,7 @@ pub fn map_query_to_json<'a>(
             0
         };
 
-        json!({
+        let mut reply = json!({
             "timestamp": query.timestamp,
             "type": query.query_type as u8,
             "status": query.status as u8,
@@ -48,24 +54,41 @@ pub fn map_query_to_json<'a>(
             "dnssec": query.dnssec_type as u8,
             "reply": query.reply_type as u8,
             "response_time": response_time
-        })
+        });
+
+        tag_category(&mut reply, domain, threat_categories);
+        reply
     })
 }
 
+/// Add the category of the domain from the threat feed. Queries of domains
+/// without a category are not changed.
+pub fn tag_category(reply: &mut JsonValue, domain: &str, threat_categories: &ThreatCategories) {
+    if let Some(category) = threat_categories.category(domain) {
+        reply["category"] = category.into();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::map_query_to_json;
     use crate::{
+        client_nicknames::ClientNicknames,
         ftl::ShmLockGuard,
-        routes::stats::history::testing::{test_memory, test_queries}
+        routes::stats::history::testing::{test_memory, test_queries},
+        services::ThreatCategories
     };
+    use std::collections::HashMap;
 
     /// Verify that queries are mapped to JSON correctly
     #[test]
     fn test_map_query_to_json() {
         let query = test_queries()[0];
         let ftl_memory = test_memory();
-        let map_function = map_query_to_json(&ftl_memory, &ShmLockGuard::Test).unwrap();
+        let nicknames = ClientNicknames::default();
+        let categories = ThreatCategories::default();
+        let map_function =
+            map_query_to_json(&ftl_memory, &ShmLockGuard::Test, &nicknames, &categories).unwrap();
         let mapped_query = map_function(&query);
 
         assert_eq!(
@@ -82,4 +105,43 @@ mod test {
             })
         );
     }
+
+    /// The client nickname is used instead of the client name
+    #[test]
+    fn client_nickname() {
+        let query = test_queries()[0];
+        let ftl_memory = test_memory();
+        let nicknames = ClientNicknames::default();
+        nicknames.insert("192.168.1.10", "Laptop");
+        let categories = ThreatCategories::default();
+
+        let map_function =
+            map_query_to_json(&ftl_memory, &ShmLockGuard::Test, &nicknames, &categories).unwrap();
+
+        assert_eq!(map_function(&query)["client"], "Laptop");
+    }
+
+    /// Queries of domains in the threat feed have the category of the domain
+    #[test]
+    fn category() {
+        let queries = test_queries();
+        let ftl_memory = test_memory();
+        let nicknames = ClientNicknames::default();
+        let mut categories = HashMap::new();
+        categories.insert("domain1.com".to_owned(), "malware".to_owned());
+        categories.insert("domain2.com".to_owned(), "phishing".to_owned());
+        let threat_categories = ThreatCategories::from(categories);
+
+        let map_function = map_query_to_json(
+            &ftl_memory,
+            &ShmLockGuard::Test,
+            &nicknames,
+            &threat_categories
+        )
+        .unwrap();
+
+        assert_eq!(map_function(&queries[0])["category"], "malware");
+        assert_eq!(map_function(&queries[3])["category"], "phishing");
+        assert!(map_function(&queries[5]).get("category").is_none());
+    }
 }
@@ -8,14 +8,21 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+mod cursor;
 mod database;
 mod endpoints;
+mod export;
 mod filters;
 mod get_history;
+mod history_db;
 mod map_query_to_json;
+mod ndjson;
+mod saved_views;
 mod skip_to_cursor;
+mod sort;
+mod transitions;
 
 #[cfg(test)]
-mod testing;
+pub mod testing;
 
-pub use self::endpoints::*;
+pub use self::{cursor::CursorSigner, endpoints::*, history_db::*, saved_views::*, transitions::*};
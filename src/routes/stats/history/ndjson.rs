@@ -0,0 +1,236 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History NDJSON Streaming
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::{
+    cursor::CursorSigner,
+    endpoints::{HistoryCursor, HistoryParams},
+    export::EXPORT_PAGE_SIZE,
+    get_history::load_history_page
+};
+use crate::{
+    client_nicknames::ClientNicknames, databases::ftl::FtlDatabase, env::Env, ftl::FtlMemory,
+    services::ThreatCategories, util::Error
+};
+use rocket::{
+    http::{ContentType, Status},
+    request::{self, FromRequest, Request},
+    response::{self, Responder, Response},
+    Outcome
+};
+use rocket_contrib::json::JsonValue;
+use std::io::{self, Read};
+
+/// Loads a page of the history starting at the cursor, and returns the
+/// queries and the cursor of the next page
+pub type PageLoader<'r> = Box<
+    dyn FnMut(Option<HistoryCursor>) -> Result<(Vec<JsonValue>, Option<HistoryCursor>), Error> + 'r
+>;
+
+/// Checks if the client asked for NDJSON with `Accept: application/x-ndjson`
+pub struct AcceptsNdjson(pub bool);
+
+impl<'a, 'r> FromRequest<'a, 'r> for AcceptsNdjson {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let accepts = request.accept().map_or(false, |accept| {
+            accept.media_types().any(|media_type| {
+                media_type.top() == "application" && media_type.sub() == "x-ndjson"
+            })
+        });
+
+        Outcome::Success(AcceptsNdjson(accepts))
+    }
+}
+
+/// Stream the full filtered history as NDJSON, starting at the cursor if
+/// there is one. Like the CSV export, the limit is ignored and the history is
+/// loaded a page at a time by following the cursors.
+pub fn stream_history<'r>(
+    ftl_memory: &'r FtlMemory,
+    env: &'r Env,
+    mut params: HistoryParams,
+    db: FtlDatabase,
+    cursor_signer: &CursorSigner,
+    nicknames: &'r ClientNicknames,
+    threat_categories: &'r ThreatCategories
+) -> Result<HistoryStream<'r>, Error> {
+    // Make sure the cursor is valid before using it
+    let cursor = match params.cursor {
+        Some(ref signed) => Some(cursor_signer.verify(signed)?),
+        None => None
+    };
+
+    params.limit = Some(EXPORT_PAGE_SIZE);
+
+    HistoryStream::new(
+        Box::new(move |cursor| {
+            let mut page = load_history_page(
+                ftl_memory,
+                env,
+                &params,
+                cursor,
+                &db,
+                nicknames,
+                threat_categories
+            )?;
+
+            if let Some(ref fields) = params.fields {
+                fields.select(&mut page.history);
+            }
+
+            Ok((page.history, page.cursor))
+        }),
+        cursor
+    )
+}
+
+/// The full filtered history as newline delimited JSON, with one query per
+/// line. Pages are loaded as the client reads the response, so the history
+/// is never held in memory all at once. The first page is loaded before the
+/// response starts, so that errors such as an invalid filter are still
+/// reported with an error status.
+pub struct HistoryStream<'r> {
+    load_page: PageLoader<'r>,
+    /// The serialized queries which have not been read yet
+    buffer: Vec<u8>,
+    position: usize,
+    /// The cursor of the next page, if there is one
+    cursor: Option<HistoryCursor>
+}
+
+impl<'r> HistoryStream<'r> {
+    /// Start streaming the history at the (verified) cursor
+    pub fn new(
+        mut load_page: PageLoader<'r>,
+        cursor: Option<HistoryCursor>
+    ) -> Result<HistoryStream<'r>, Error> {
+        let (history, cursor) = load_page(cursor)?;
+        let mut stream = HistoryStream {
+            load_page,
+            buffer: Vec::new(),
+            position: 0,
+            cursor
+        };
+
+        stream.write_queries(&history);
+
+        Ok(stream)
+    }
+
+    /// Replace the buffer with the queries, one per line
+    fn write_queries(&mut self, history: &[JsonValue]) {
+        self.buffer.clear();
+        self.position = 0;
+
+        for query in history {
+            // Serializing a JSON value to memory can not fail
+            serde_json::to_writer(&mut self.buffer, query).unwrap();
+            self.buffer.push(b'\n');
+        }
+    }
+}
+
+impl<'r> Read for HistoryStream<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Load pages until there are queries to read, or there are no pages
+        // left. An error ends the response early, since the status has
+        // already been sent.
+        while self.position == self.buffer.len() {
+            let cursor = match self.cursor {
+                Some(cursor) => cursor,
+                None => return Ok(0)
+            };
+
+            let (history, next_cursor) = (self.load_page)(Some(cursor))
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+            self.cursor = next_cursor;
+            self.write_queries(&history);
+        }
+
+        let remaining = &self.buffer[self.position..];
+        let len = remaining.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+impl<'r> Responder<'r> for HistoryStream<'r> {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        Response::build()
+            .status(Status::Ok)
+            .header(ContentType::new("application", "x-ndjson"))
+            .streamed_body(self)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HistoryStream;
+    use crate::{
+        routes::stats::history::{endpoints::HistoryCursor, testing::test_memory},
+        testing::TestBuilder
+    };
+    use rocket::http::Header;
+    use std::io::Read;
+
+    /// Every page is read, following the cursors
+    #[test]
+    fn read_pages() {
+        let mut stream = HistoryStream::new(
+            Box::new(|cursor: Option<HistoryCursor>| {
+                let page = cursor.and_then(|cursor| cursor.offset).unwrap_or(0);
+                let next = if page < 2 {
+                    Some(HistoryCursor {
+                        id: None,
+                        db_id: None,
+                        db_timestamp: None,
+                        offset: Some(page + 1)
+                    })
+                } else {
+                    None
+                };
+
+                Ok((vec![json!({ "page": page })], next))
+            }),
+            None
+        )
+        .unwrap();
+
+        let mut body = String::new();
+        stream.read_to_string(&mut body).unwrap();
+
+        assert_eq!(body, "{\"page\":0}\n{\"page\":1}\n{\"page\":2}\n");
+    }
+
+    /// The history is streamed with one query per line when NDJSON is
+    /// accepted
+    #[test]
+    fn accept_ndjson() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?from=177180&until=177181")
+            .header(Header::new("Accept", "application/x-ndjson"))
+            .ftl_memory(test_memory())
+            .need_database(true)
+            .expect_header("Content-Type", "application/x-ndjson")
+            .expect_body(
+                "{\"client\":\"127.0.0.1\",\"dnssec\":5,\"domain\":\"4.4.8.8.in-addr.arpa\",\
+                 \"reply\":0,\"response_time\":0,\"status\":2,\"timestamp\":177180,\"type\":6}\n\
+                 {\"client\":\"127.0.0.1\",\"dnssec\":5,\"domain\":\"1.1.1.10.in-addr.arpa\",\
+                 \"reply\":0,\"response_time\":0,\"status\":3,\"timestamp\":177180,\"type\":6}\n"
+            )
+            .test();
+    }
+}
@@ -0,0 +1,360 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Saved History Views
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    ftl::{FtlDnssecType, FtlQueryReplyType, FtlQueryStatus, FtlQueryType},
+    routes::auth::User,
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use failure::ResultExt;
+use rocket::{http::RawStr, request::FromFormValue, State};
+use rocket_contrib::json::Json;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write}
+};
+
+/// The maximum length of a view name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The maximum size of a view's preferences, in bytes of JSON
+const MAX_PREFERENCES_SIZE: usize = 4096;
+
+/// The maximum number of saved views
+const MAX_VIEWS: usize = 100;
+
+/// Get all of the saved views
+#[get("/stats/history/views")]
+pub fn get_views(_auth: User, env: State<Env>) -> Reply {
+    reply_data(read_views(&env)?)
+}
+
+/// Get a saved view by name. This is ranked after the transitions endpoint,
+/// which has a matching path.
+#[get("/stats/history/views/<name>", rank = 2)]
+pub fn get_view(_auth: User, env: State<Env>, name: String) -> Reply {
+    let view = read_views(&env)?
+        .into_iter()
+        .find(|view| view.name == name)
+        .ok_or(ErrorKind::NotFound)?;
+
+    reply_data(view)
+}
+
+/// Save a new view
+#[post("/stats/history/views", data = "<view>")]
+pub fn add_view(_auth: User, env: State<Env>, view: Json<SavedView>) -> Reply {
+    let view = view.into_inner();
+    view.validate()?;
+
+    let mut views = read_views(&env)?;
+
+    if views.iter().any(|saved| saved.name == view.name) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    if views.len() >= MAX_VIEWS {
+        return Err(Error::from(ErrorKind::InvalidSavedView));
+    }
+
+    views.push(view);
+    write_views(&env, &views)?;
+
+    reply_success()
+}
+
+/// Replace the filters and preferences of a saved view
+#[put("/stats/history/views/<name>", data = "<input>")]
+pub fn update_view(_auth: User, env: State<Env>, name: String, input: Json<ViewInput>) -> Reply {
+    let input = input.into_inner();
+    let view = SavedView {
+        name,
+        filters: input.filters,
+        preferences: input.preferences
+    };
+    view.validate()?;
+
+    let mut views = read_views(&env)?;
+    let saved = views
+        .iter_mut()
+        .find(|saved| saved.name == view.name)
+        .ok_or(ErrorKind::NotFound)?;
+
+    *saved = view;
+    write_views(&env, &views)?;
+
+    reply_success()
+}
+
+/// Delete a saved view
+#[delete("/stats/history/views/<name>")]
+pub fn delete_view(_auth: User, env: State<Env>, name: String) -> Reply {
+    let mut views = read_views(&env)?;
+    let count = views.len();
+
+    views.retain(|view| view.name != name);
+
+    if views.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    write_views(&env, &views)?;
+
+    reply_success()
+}
+
+/// A named set of history filters, with the UI's chart preferences. The
+/// filters use the same names and values as the history parameters.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct SavedView {
+    pub name: String,
+    pub filters: BTreeMap<String, String>,
+    #[serde(default)]
+    pub preferences: Map<String, Value>
+}
+
+/// The input when updating a saved view. The name is taken from the path.
+#[derive(Deserialize)]
+pub struct ViewInput {
+    filters: BTreeMap<String, String>,
+    #[serde(default)]
+    preferences: Map<String, Value>
+}
+
+impl SavedView {
+    /// Check that the name, filters, and preferences are valid
+    fn validate(&self) -> Result<(), Error> {
+        let valid_name = !self.name.is_empty()
+            && self.name.chars().count() <= MAX_NAME_LENGTH
+            && !self.name.chars().any(char::is_control);
+        let valid_filters = self
+            .filters
+            .iter()
+            .all(|(name, value)| is_valid_filter(name, value));
+        let valid_preferences = serde_json::to_vec(&self.preferences)
+            .map(|json| json.len() <= MAX_PREFERENCES_SIZE)
+            .unwrap_or(false);
+
+        if valid_name && valid_filters && valid_preferences {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::InvalidSavedView))
+        }
+    }
+}
+
+/// Check if the history parameter is valid. The cursor and limit are not
+/// allowed, because they are not part of a view.
+fn is_valid_filter(name: &str, value: &str) -> bool {
+    let value = RawStr::from_str(value);
+
+    match name {
+        "from" | "until" => u64::from_form_value(value).is_ok(),
+        "domain" | "client" | "q" | "upstream" | "category" => true,
+        "query_type" => FtlQueryType::from_form_value(value).is_ok(),
+        "status" => FtlQueryStatus::from_form_value(value).is_ok(),
+        "blocked" => bool::from_form_value(value).is_ok(),
+        "dnssec" => FtlDnssecType::from_form_value(value).is_ok(),
+        "reply" => FtlQueryReplyType::from_form_value(value).is_ok(),
+        _ => false
+    }
+}
+
+/// Read the saved views. If the file does not exist, there are no views.
+fn read_views(env: &Env) -> Result<Vec<SavedView>, Error> {
+    if !env.file_exists(PiholeFile::SavedViews) {
+        return Ok(Vec::new());
+    }
+
+    let file_location = env.file_location(PiholeFile::SavedViews).to_owned();
+    let mut json = String::new();
+    env.read_file(PiholeFile::SavedViews)?
+        .read_to_string(&mut json)
+        .context(ErrorKind::FileRead(file_location.clone()))?;
+
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+}
+
+/// Save the views, replacing the previously saved views
+fn write_views(env: &Env, views: &[SavedView]) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::SavedViews).to_owned();
+    let mut file = env.write_file(PiholeFile::SavedViews, false)?;
+
+    serde_json::to_writer(&mut file, views).context(ErrorKind::FileWrite(file_location.clone()))?;
+    writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_valid_filter;
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Method, Status};
+
+    /// A saved view with filters and preferences
+    const VIEW: &str = "[{\"name\":\"Tablet\",\"filters\":{\"blocked\":\"true\",\
+                        \"client\":\"10.0.0.5\"},\"preferences\":{\"chart\":\"bar\"}}]\n";
+
+    /// Filters are checked using the history parameter types
+    #[test]
+    fn valid_filters() {
+        assert!(is_valid_filter("from", "1000"));
+        assert!(is_valid_filter("q", "example"));
+        assert!(is_valid_filter("query_type", "1"));
+        assert!(!is_valid_filter("from", "yesterday"));
+        assert!(!is_valid_filter("cursor", "abc"));
+        assert!(!is_valid_filter("unknown", "1"));
+    }
+
+    /// All saved views are returned
+    #[test]
+    fn get_views() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views")
+            .file(PiholeFile::SavedViews, VIEW)
+            .expect_json(json!([{
+                "name": "Tablet",
+                "filters": { "blocked": "true", "client": "10.0.0.5" },
+                "preferences": { "chart": "bar" }
+            }]))
+            .test();
+    }
+
+    /// There are no views if the file does not exist
+    #[test]
+    fn get_views_empty() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views")
+            .expect_json(json!([]))
+            .test();
+    }
+
+    /// A single view is returned by name
+    #[test]
+    fn get_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views/Tablet")
+            .file(PiholeFile::SavedViews, VIEW)
+            .expect_json(json!({
+                "name": "Tablet",
+                "filters": { "blocked": "true", "client": "10.0.0.5" },
+                "preferences": { "chart": "bar" }
+            }))
+            .test();
+    }
+
+    /// Views are saved to the file
+    #[test]
+    fn add_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views")
+            .method(Method::Post)
+            .file_expect(PiholeFile::SavedViews, "", VIEW)
+            .body(json!({
+                "name": "Tablet",
+                "filters": { "client": "10.0.0.5", "blocked": "true" },
+                "preferences": { "chart": "bar" }
+            }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Views can not be saved twice with the same name
+    #[test]
+    fn add_existing_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views")
+            .method(Method::Post)
+            .file(PiholeFile::SavedViews, VIEW)
+            .body(json!({ "name": "Tablet", "filters": {} }))
+            .expect_status(Status::Conflict)
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Views with invalid filters are rejected
+    #[test]
+    fn add_invalid_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views")
+            .method(Method::Post)
+            .file(PiholeFile::SavedViews, "")
+            .body(json!({ "name": "Tablet", "filters": { "from": "yesterday" } }))
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "invalid_saved_view",
+                    "message": "Invalid saved view",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Updating a view replaces its filters and preferences
+    #[test]
+    fn update_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views/Tablet")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::SavedViews,
+                VIEW,
+                "[{\"name\":\"Tablet\",\"filters\":{\"blocked\":\"false\"},\
+                 \"preferences\":{}}]\n"
+            )
+            .body(json!({ "filters": { "blocked": "false" } }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Deleting a view removes it from the file
+    #[test]
+    fn delete_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views/Tablet")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::SavedViews, VIEW, "[]\n")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Deleting a missing view is not found
+    #[test]
+    fn delete_missing_view() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/views/Laptop")
+            .method(Method::Delete)
+            .file(PiholeFile::SavedViews, VIEW)
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+}
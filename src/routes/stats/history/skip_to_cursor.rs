@@ -9,16 +9,16 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::ftl::queries, ftl::FtlQuery, routes::stats::history::endpoints::HistoryParams
+    databases::ftl::queries, ftl::FtlQuery, routes::stats::history::endpoints::HistoryCursor
 };
 use diesel::{prelude::*, sqlite::Sqlite};
 
 /// Skip iteration until the query which corresponds to the cursor.
 pub fn skip_to_cursor<'a>(
     queries_iter: Box<dyn Iterator<Item = &'a FtlQuery> + 'a>,
-    params: &HistoryParams
+    cursor: Option<HistoryCursor>
 ) -> Box<dyn Iterator<Item = &'a FtlQuery> + 'a> {
-    if let Some(cursor) = params.cursor {
+    if let Some(cursor) = cursor {
         if let Some(id) = cursor.id {
             Box::new(queries_iter.skip_while(move |query| query.id as i32 != id))
         } else if let Some(db_id) = cursor.db_id {
@@ -32,17 +32,32 @@ pub fn skip_to_cursor<'a>(
     }
 }
 
+/// Get the position of the cursor in the database, as the timestamp and ID of
+/// the query it points to
+pub fn db_cursor_key(cursor: HistoryCursor) -> Option<(i64, i64)> {
+    Some((cursor.db_timestamp?, cursor.db_id?))
+}
+
 /// Skip database queries until the query which corresponds to the cursor.
+/// The queries are ordered by timestamp and then by ID, so the start is a
+/// `(timestamp, id)` pair. FTL can insert queries out of order, so a query
+/// with a higher ID can be older than the one at the cursor.
 pub fn skip_to_cursor_db(
     db_query: queries::BoxedQuery<Sqlite>,
-    start_id: Option<i64>
+    start: Option<(i64, i64)>
 ) -> queries::BoxedQuery<Sqlite> {
     // Use the Diesel DSL of this table for easy querying
     use self::queries::dsl::*;
 
-    // If a start ID is given, ignore any queries before it
-    if let Some(start_id) = start_id {
-        db_query.filter(id.le(start_id as i32))
+    // If a start is given, ignore any queries before it
+    if let Some((start_timestamp, start_id)) = start {
+        let start_timestamp = start_timestamp as i32;
+
+        db_query.filter(
+            timestamp
+                .lt(start_timestamp)
+                .or(timestamp.eq(start_timestamp).and(id.le(start_id as i32)))
+        )
     } else {
         db_query
     }
@@ -55,9 +70,7 @@ mod test {
         databases::ftl::{connect_to_test_db, FtlDbQuery},
         ftl::FtlQuery,
         routes::stats::history::{
-            database::execute_query,
-            endpoints::{HistoryCursor, HistoryParams},
-            testing::test_queries
+            database::execute_query, endpoints::HistoryCursor, testing::test_queries
         }
     };
     use diesel::prelude::*;
@@ -69,13 +82,12 @@ mod test {
         let expected_queries: Vec<&FtlQuery> = queries.iter().skip(7).collect();
         let filtered_queries: Vec<&FtlQuery> = skip_to_cursor(
             Box::new(queries.iter()),
-            &HistoryParams {
-                cursor: Some(HistoryCursor {
-                    id: Some(8),
-                    db_id: None
-                }),
-                ..HistoryParams::default()
-            }
+            Some(HistoryCursor {
+                id: Some(8),
+                db_id: None,
+                db_timestamp: None,
+                offset: None
+            })
         )
         .collect();
 
@@ -89,13 +101,12 @@ mod test {
         let expected_queries: Vec<&FtlQuery> = queries.iter().skip(4).collect();
         let filtered_queries: Vec<&FtlQuery> = skip_to_cursor(
             Box::new(queries.iter()),
-            &HistoryParams {
-                cursor: Some(HistoryCursor {
-                    id: None,
-                    db_id: Some(99)
-                }),
-                ..HistoryParams::default()
-            }
+            Some(HistoryCursor {
+                id: None,
+                db_id: Some(99),
+                db_timestamp: None,
+                offset: None
+            })
         )
         .collect();
 
@@ -117,7 +128,7 @@ mod test {
             upstream: None
         }];
 
-        let db_query = skip_to_cursor_db(queries.into_boxed(), Some(1));
+        let db_query = skip_to_cursor_db(queries.into_boxed(), Some((0, 1)));
         let filtered_queries = execute_query(&connect_to_test_db(), db_query).unwrap();
 
         assert_eq!(filtered_queries, expected_queries);
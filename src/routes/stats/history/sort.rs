@@ -0,0 +1,211 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// History Sorting
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::queries,
+    ftl::{FtlMemory, FtlQuery, ShmLockGuard},
+    util::Error
+};
+use diesel::{prelude::*, sqlite::Sqlite};
+use rocket::{http::RawStr, request::FromFormValue};
+use std::cmp::Reverse;
+
+/// The orders the query history can be sorted in
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum HistorySort {
+    TimestampDesc,
+    TimestampAsc,
+    ResponseTimeDesc,
+    ResponseTimeAsc,
+    DomainAsc,
+    DomainDesc
+}
+
+impl Default for HistorySort {
+    /// The history is most recent first by default
+    fn default() -> Self {
+        HistorySort::TimestampDesc
+    }
+}
+
+impl<'v> FromFormValue<'v> for HistorySort {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Self, Self::Error> {
+        match form_value.as_str() {
+            "timestamp_desc" => Ok(HistorySort::TimestampDesc),
+            "timestamp_asc" => Ok(HistorySort::TimestampAsc),
+            "response_time_desc" => Ok(HistorySort::ResponseTimeDesc),
+            "response_time_asc" => Ok(HistorySort::ResponseTimeAsc),
+            "domain" | "domain_asc" => Ok(HistorySort::DomainAsc),
+            "domain_desc" => Ok(HistorySort::DomainDesc),
+            _ => Err(form_value)
+        }
+    }
+}
+
+/// Sort the in-memory queries, which start most recent first. The sort is
+/// stable, so queries with the same value stay most recent first.
+pub fn sort_queries(
+    history: &mut [&FtlQuery],
+    sort: HistorySort,
+    ftl_memory: &FtlMemory,
+    ftl_lock: &ShmLockGuard
+) -> Result<(), Error> {
+    match sort {
+        HistorySort::TimestampDesc => (),
+        HistorySort::TimestampAsc => history.reverse(),
+        HistorySort::ResponseTimeDesc => history.sort_by_key(|query| Reverse(response_time(query))),
+        HistorySort::ResponseTimeAsc => history.sort_by_key(|query| response_time(query)),
+        HistorySort::DomainAsc | HistorySort::DomainDesc => {
+            let domains = ftl_memory.domains(ftl_lock)?;
+            let strings = ftl_memory.strings(ftl_lock)?;
+            let domain = |query: &FtlQuery| domains[query.domain_id as usize].get_domain(&strings);
+
+            if sort == HistorySort::DomainAsc {
+                history.sort_by(|a, b| domain(a).cmp(domain(b)));
+            } else {
+                history.sort_by(|a, b| domain(b).cmp(domain(a)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sort the database queries. The database does not store response times,
+/// so sorting by response time keeps the most recent queries first.
+pub fn sort_db(
+    db_query: queries::BoxedQuery<Sqlite>,
+    sort: HistorySort
+) -> queries::BoxedQuery<Sqlite> {
+    // Use the Diesel DSL of this table for easy querying
+    use self::queries::dsl::*;
+
+    match sort {
+        HistorySort::TimestampDesc => db_query.order((timestamp.desc(), id.desc())),
+        HistorySort::TimestampAsc => db_query.order((timestamp.asc(), id.asc())),
+        HistorySort::ResponseTimeDesc | HistorySort::ResponseTimeAsc => db_query.order(id.desc()),
+        HistorySort::DomainAsc => db_query.order((domain.asc(), id.desc())),
+        HistorySort::DomainDesc => db_query.order((domain.desc(), id.desc()))
+    }
+}
+
+/// Get the response time of the query. Queries without a response (more than
+/// 30 minutes) have no response time, like in the history replies.
+fn response_time(query: &FtlQuery) -> u64 {
+    if query.response_time < 18_000_000 {
+        query.response_time as u64
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sort_db, sort_queries, HistorySort};
+    use crate::{
+        client_nicknames::ClientNicknames,
+        databases::ftl::connect_to_test_db,
+        ftl::{FtlQuery, ShmLockGuard},
+        routes::stats::history::{
+            cursor::CursorSigner,
+            database::execute_query,
+            endpoints::HistoryCursor,
+            map_query_to_json::map_query_to_json,
+            testing::{test_memory, test_queries}
+        },
+        services::ThreatCategories,
+        testing::TestBuilder
+    };
+    use diesel::prelude::*;
+
+    /// Sort the test queries, which start most recent first
+    fn sorted(sort: HistorySort) -> Vec<FtlQuery> {
+        let queries = test_queries();
+        let mut history: Vec<&FtlQuery> = queries.iter().rev().collect();
+
+        sort_queries(&mut history, sort, &test_memory(), &ShmLockGuard::Test).unwrap();
+        history.into_iter().cloned().collect()
+    }
+
+    /// Ascending timestamps are the oldest first
+    #[test]
+    fn timestamp_asc() {
+        assert_eq!(sorted(HistorySort::TimestampAsc), test_queries());
+    }
+
+    /// The slowest queries are first, and ties stay most recent first
+    #[test]
+    fn response_time_desc() {
+        let history = sorted(HistorySort::ResponseTimeDesc);
+
+        assert!(history
+            .windows(2)
+            .all(|pair| pair[0].response_time >= pair[1].response_time));
+    }
+
+    /// Domains are sorted alphabetically
+    #[test]
+    fn domain_asc() {
+        let history = sorted(HistorySort::DomainAsc);
+
+        assert_eq!(history.first().map(|query| query.domain_id), Some(0));
+        assert!(history
+            .windows(2)
+            .all(|pair| pair[0].domain_id <= pair[1].domain_id));
+    }
+
+    /// Database queries are sorted by domain
+    #[test]
+    fn domain_db() {
+        use crate::databases::ftl::queries::dsl::*;
+
+        let db = connect_to_test_db();
+        let db_query = sort_db(queries.into_boxed(), HistorySort::DomainAsc).limit(5);
+        let results = execute_query(&db, db_query).unwrap();
+
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].domain <= pair[1].domain));
+    }
+
+    /// The history endpoint sorts the queries and pages them by offset
+    #[test]
+    fn sorted_endpoint() {
+        let ftl_memory = test_memory();
+        let oldest = map_query_to_json(
+            &ftl_memory,
+            &ShmLockGuard::Test,
+            &ClientNicknames::default(),
+            &ThreatCategories::default()
+        )
+        .unwrap()(&test_queries()[0]);
+
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history?sort=timestamp_asc&limit=1")
+            .ftl_memory(ftl_memory)
+            .need_database(true)
+            .expect_json(json!({
+                "annotations": [],
+                "history": [oldest],
+                "cursor": CursorSigner::test()
+                    .sign(HistoryCursor {
+                        id: None,
+                        db_id: None,
+                        db_timestamp: None,
+                        offset: Some(1)
+                    })
+                    .unwrap()
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,217 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Status Transitions Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::FtlMemory,
+    routes::auth::User,
+    util::{reply_data, Error, ErrorKind, Reply}
+};
+use rocket::{request::Form, State};
+use std::{
+    thread,
+    time::{Duration, Instant}
+};
+
+/// The default time to watch a query for, in seconds
+const DEFAULT_WATCH_TIME: u64 = 2;
+
+/// The maximum time to watch a query for, in seconds. A watching request
+/// holds a worker thread and polls shared memory, so this is kept short.
+const MAX_WATCH_TIME: u64 = 5;
+
+/// How often shared memory is checked for changes to the query
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Watch an in-flight query and get the changes to its status, reply, and
+/// upstream until it completes or the timeout (in seconds) passes. This is
+/// meant for debugging queries which stay in an unknown status.
+#[get("/stats/history/<id>/transitions?<params..>")]
+pub fn query_transitions(
+    _auth: User,
+    ftl_memory: State<FtlMemory>,
+    id: i32,
+    params: Form<TransitionsParams>
+) -> Reply {
+    let timeout = params
+        .timeout
+        .unwrap_or(DEFAULT_WATCH_TIME)
+        .min(MAX_WATCH_TIME);
+
+    get_query_transitions(&ftl_memory, id, Duration::from_secs(timeout))
+}
+
+/// Represents the possible GET parameters on
+/// `/stats/history/<id>/transitions`
+#[derive(FromForm)]
+pub struct TransitionsParams {
+    timeout: Option<u64>
+}
+
+/// The state of a query at a point in time
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug))]
+struct QueryState {
+    /// Milliseconds since the query started being watched. The first state
+    /// is always seen at 0.
+    elapsed_ms: u64,
+    status: u8,
+    reply: u8,
+    upstream: Option<String>,
+    complete: bool
+}
+
+impl QueryState {
+    /// Check if the query is in the same state, ignoring when it was seen
+    fn is_same(&self, other: &QueryState) -> bool {
+        self.status == other.status
+            && self.reply == other.reply
+            && self.upstream == other.upstream
+            && self.complete == other.complete
+    }
+}
+
+/// Poll the query until it completes or the timeout passes, recording each
+/// time its state changes. Shared memory is only locked while reading the
+/// query.
+fn get_query_transitions(ftl_memory: &FtlMemory, id: i32, timeout: Duration) -> Reply {
+    let start = Instant::now();
+    let mut elapsed = Duration::from_secs(0);
+    let mut transitions: Vec<QueryState> = Vec::new();
+
+    loop {
+        let state = match read_query_state(ftl_memory, id, elapsed)? {
+            Some(state) => state,
+            None if transitions.is_empty() => return Err(Error::from(ErrorKind::NotFound)),
+            // The query is no longer in memory
+            None => break
+        };
+        let complete = state.complete;
+
+        if transitions
+            .last()
+            .map_or(true, |last| !last.is_same(&state))
+        {
+            transitions.push(state);
+        }
+
+        if complete || start.elapsed() >= timeout {
+            break;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+        elapsed = start.elapsed();
+    }
+
+    reply_data(json!({
+        "id": id,
+        "complete": transitions.last().map_or(false, |state| state.complete),
+        "transitions": transitions
+    }))
+}
+
+/// Read the current state of the query, or `None` if it is not in memory.
+/// `elapsed` is the time since the query started being watched.
+fn read_query_state(
+    ftl_memory: &FtlMemory,
+    id: i32,
+    elapsed: Duration
+) -> Result<Option<QueryState>, Error> {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let upstreams = ftl_memory.upstreams(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    let query = match queries
+        .iter()
+        .take(counters.total_queries as usize)
+        .find(|query| query.id == id)
+    {
+        Some(query) => query,
+        None => return Ok(None)
+    };
+
+    let upstream = if query.upstream_id >= 0 {
+        upstreams
+            .get(query.upstream_id as usize)
+            .map(|upstream| upstream.get_ip(&strings).to_owned())
+    } else {
+        None
+    };
+
+    Ok(Some(QueryState {
+        elapsed_ms: elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64,
+        status: query.status as u8,
+        reply: query.reply_type as u8,
+        upstream,
+        complete: query.is_complete
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::testing::test_memory, QueryState};
+    use crate::testing::TestBuilder;
+    use rocket::http::Status;
+
+    /// A completed query has a single state
+    #[test]
+    fn completed_query() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/1/transitions")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "id": 1,
+                "complete": true,
+                "transitions": [
+                    {
+                        "elapsed_ms": 0,
+                        "status": 2,
+                        "reply": 3,
+                        "upstream": "8.8.8.8",
+                        "complete": true
+                    }
+                ]
+            }))
+            .test();
+    }
+
+    /// Queries which are not in memory are not found
+    #[test]
+    fn missing_query() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/history/100/transitions")
+            .ftl_memory(test_memory())
+            .expect_status(Status::NotFound)
+            .expect_json(json!({
+                "error": {
+                    "key": "not_found",
+                    "message": "Not found",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The time a state was seen does not affect whether it is the same
+    #[test]
+    fn same_state() {
+        let state = |elapsed_ms, status| QueryState {
+            elapsed_ms,
+            status,
+            reply: 0,
+            upstream: None,
+            complete: false
+        };
+
+        assert!(state(0, 0).is_same(&state(50, 0)));
+        assert!(!state(0, 0).is_same(&state(0, 2)));
+    }
+}
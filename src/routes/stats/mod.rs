@@ -8,14 +8,28 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+mod annotations;
+mod audit;
+mod cache_hit_ratio;
+mod client_data;
+mod client_query_types;
 mod clients;
 mod common;
+mod compact_summary;
+mod cooccurrence;
+mod domain_display;
+mod forecast;
 mod history;
 mod over_time_clients;
 mod over_time_history;
+mod privacy;
+mod query_statuses;
 mod query_types;
 mod recent_blocked;
+mod response_times;
+mod subnets;
 mod summary;
+mod summary_compare;
 mod top_clients;
 mod top_domains;
 mod upstreams;
@@ -23,6 +37,9 @@ mod upstreams;
 pub mod database;
 
 pub use self::{
-    clients::*, history::*, over_time_clients::*, over_time_history::*, query_types::*,
-    recent_blocked::*, summary::*, top_clients::*, top_domains::*, upstreams::*
+    annotations::*, audit::*, cache_hit_ratio::*, client_data::*, client_query_types::*,
+    clients::*, compact_summary::*, cooccurrence::*, domain_display::DomainDisplay, forecast::*,
+    history::*, over_time_clients::*, over_time_history::*, privacy::*, query_statuses::*,
+    query_types::*, recent_blocked::*, response_times::*, subnets::*, summary::*,
+    summary_compare::*, top_clients::*, top_domains::*, upstreams::*
 };
@@ -9,31 +9,40 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    client_nicknames::ClientNicknames,
     env::Env,
     ftl::{ClientReply, FtlMemory},
     routes::{
         auth::User,
         stats::{
             clients::{filter_ftl_clients, ClientParams},
-            common::get_current_over_time_slot
+            common::{get_current_over_time_slot, get_over_time_interval, merge_over_time_slots},
+            privacy::PrivacyPolicy
         }
     },
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
     util::{reply_data, Reply}
 };
 use rocket::State;
 use std::cmp::Ordering;
 
-/// Get the client queries over time
-#[get("/stats/overTime/clients")]
-pub fn over_time_clients(_auth: User, ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
+/// Get the client queries over time. The `interval` in seconds merges FTL's
+/// slots into larger slots.
+#[get("/stats/overTime/clients?<interval>")]
+pub fn over_time_clients(
+    _auth: User,
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    nicknames: State<ClientNicknames>,
+    interval: Option<usize>
+) -> Reply {
+    let interval = get_over_time_interval(interval)?;
+
     // Check if client details are private
-    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)?
-        >= FtlPrivacyLevel::HideDomainsAndClients
-    {
+    if !PrivacyPolicy::read(&env)?.shows_clients() {
         return reply_data(OverTimeClients {
             over_time: Vec::new(),
-            clients: Vec::new()
+            clients: Vec::new(),
+            interval: None
         });
     }
 
@@ -79,13 +88,29 @@ pub fn over_time_clients(_auth: User, ftl_memory: State<FtlMemory>, env: State<E
         })
         .collect();
 
+    let over_time = merge_over_time_slots(
+        over_time,
+        interval,
+        |slot| slot.timestamp,
+        |slots, timestamp| OverTimeClientItem {
+            timestamp,
+            data: (0..clients.len())
+                .map(|i| slots.iter().map(|slot| slot.data[i]).sum())
+                .collect()
+        }
+    );
+
     // Convert clients into the output format
     let clients: Vec<ClientReply> = clients
         .into_iter()
-        .map(|client| client.as_reply(&strings))
+        .map(|client| nicknames.client_reply(client, &strings))
         .collect();
 
-    reply_data(OverTimeClients { over_time, clients })
+    reply_data(OverTimeClients {
+        over_time,
+        clients,
+        interval: None
+    })
 }
 
 /// Represents an overTime client item, which holds time and client data for an
@@ -118,7 +143,10 @@ impl Ord for OverTimeClientItem {
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub struct OverTimeClients {
     pub over_time: Vec<OverTimeClientItem>,
-    pub clients: Vec<ClientReply>
+    pub clients: Vec<ClientReply>,
+    /// The interval of the slots, if they were loaded from the database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<usize>
 }
 
 #[cfg(test)]
@@ -194,4 +222,26 @@ mod test {
             }))
             .test();
     }
+
+    /// The `interval` parameter merges the slots into larger slots, adding
+    /// up each client's queries
+    #[test]
+    fn interval() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/overTime/clients?interval=1200")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "API_EXCLUDE_CLIENTS=client1")
+            .expect_json(json!({
+                "clients": [
+                    { "name": "",        "ip": "10.1.1.2" },
+                    { "name": "client3", "ip": "10.1.1.3" },
+                    { "name": "",        "ip": "10.1.1.4" },
+                    { "name": "",        "ip": "10.1.1.5" }
+                ],
+                "over_time": [
+                    { "timestamp": 600, "data": [1, 1, 1, 1] }
+                ]
+            }))
+            .test();
+    }
 }
@@ -10,14 +10,18 @@
 
 use crate::{
     ftl::FtlMemory,
-    routes::stats::common::get_current_over_time_slot,
+    routes::stats::common::{
+        get_current_over_time_slot, get_over_time_interval, merge_over_time_slots
+    },
     util::{reply_data, Reply}
 };
 use rocket::State;
 
-/// Get the query history over time (separated into blocked and not blocked)
-#[get("/stats/overTime/history")]
-pub fn over_time_history(ftl_memory: State<FtlMemory>) -> Reply {
+/// Get the query history over time (separated into blocked and not blocked).
+/// The `interval` in seconds merges FTL's slots into larger slots.
+#[get("/stats/overTime/history?<interval>")]
+pub fn over_time_history(ftl_memory: State<FtlMemory>, interval: Option<usize>) -> Reply {
+    let interval = get_over_time_interval(interval)?;
     let lock = ftl_memory.lock()?;
     let over_time = ftl_memory.over_time(&lock)?;
 
@@ -37,6 +41,17 @@ pub fn over_time_history(ftl_memory: State<FtlMemory>) -> Reply {
         })
         .collect();
 
+    let over_time_data = merge_over_time_slots(
+        over_time_data,
+        interval,
+        |slot| slot.timestamp,
+        |slots, timestamp| OverTimeItem {
+            timestamp,
+            total_queries: slots.iter().map(|slot| slot.total_queries).sum(),
+            blocked_queries: slots.iter().map(|slot| slot.blocked_queries).sum()
+        }
+    );
+
     reply_data(over_time_data)
 }
 
@@ -54,6 +69,8 @@ mod test {
         ftl::{FtlCounters, FtlMemory, FtlOverTime, FtlSettings},
         testing::TestBuilder
     };
+    use rocket::http::Status;
+    use serde_json::Value;
     use std::collections::HashMap;
 
     /// Data for testing over_time_history
@@ -90,4 +107,47 @@ mod test {
             ]))
             .test();
     }
+
+    /// The `interval` parameter merges the slots into larger slots
+    #[test]
+    fn interval() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/overTime/history?interval=1200")
+            .ftl_memory(FtlMemory::Test {
+                over_time: vec![
+                    FtlOverTime::new(300, 1, 0, 0, 1, [0; 7]),
+                    FtlOverTime::new(900, 2, 1, 1, 0, [0; 7]),
+                    FtlOverTime::new(1500, 3, 2, 0, 0, [0; 7]),
+                ],
+                counters: FtlCounters::default(),
+                clients: Vec::new(),
+                upstreams: Vec::new(),
+                strings: HashMap::new(),
+                domains: Vec::new(),
+                queries: Vec::new(),
+                settings: FtlSettings::default()
+            })
+            .expect_json(json!([
+                { "timestamp": 600, "total_queries": 3, "blocked_queries": 1 },
+                { "timestamp": 1800, "total_queries": 3, "blocked_queries": 2 }
+            ]))
+            .test();
+    }
+
+    /// Intervals which are not a multiple of FTL's interval are rejected
+    #[test]
+    fn invalid_interval() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/overTime/history?interval=300")
+            .ftl_memory(test_data())
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
 }
@@ -0,0 +1,131 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Privacy Level Policy
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::{FtlClient, FtlDomain, FtlStrings},
+    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
+    util::Error
+};
+
+/// What the privacy level in FTL's config allows the stats to show. Every
+/// endpoint which shows domains, clients, or queries, from memory or from
+/// the database, asks the policy instead of comparing privacy levels itself.
+pub struct PrivacyPolicy {
+    level: FtlPrivacyLevel
+}
+
+impl PrivacyPolicy {
+    /// Read the policy from the privacy level in FTL's config
+    pub fn read(env: &Env) -> Result<PrivacyPolicy, Error> {
+        Ok(PrivacyPolicy {
+            level: FtlConfEntry::PrivacyLevel.read_as(env)?
+        })
+    }
+
+    /// Check if domains can be shown
+    pub fn shows_domains(&self) -> bool {
+        self.level < FtlPrivacyLevel::HideDomains
+    }
+
+    /// Check if clients can be shown
+    pub fn shows_clients(&self) -> bool {
+        self.level < FtlPrivacyLevel::HideDomainsAndClients
+    }
+
+    /// Check if individual queries can be shown
+    pub fn shows_queries(&self) -> bool {
+        self.level < FtlPrivacyLevel::Maximum
+    }
+}
+
+/// Remove clients from the `clients` vector if they are marked as hidden due
+/// to the privacy level.
+pub fn remove_hidden_clients(clients: &mut Vec<&FtlClient>, strings: &FtlStrings) {
+    let hidden_client_ip = get_hidden_client_ip();
+    clients.retain(|client| client.get_ip(strings) != hidden_client_ip);
+}
+
+/// Get the IP address FTL gives clients which are hidden by the privacy level
+pub fn get_hidden_client_ip() -> &'static str {
+    "0.0.0.0"
+}
+
+/// Remove domains from the `domains` vector if they are marked as hidden due
+/// to the privacy level.
+pub fn remove_hidden_domains(domains: &mut Vec<&FtlDomain>, strings: &FtlStrings) {
+    let hidden_domain = get_hidden_domain();
+    domains.retain(|domain| domain.get_domain(strings) != hidden_domain);
+}
+
+/// Get the domain FTL records in place of domains which are hidden by the
+/// privacy level
+pub fn get_hidden_domain() -> &'static str {
+    "hidden"
+}
+
+#[cfg(test)]
+mod test {
+    use super::{remove_hidden_clients, remove_hidden_domains, PrivacyPolicy};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        ftl::{FtlClient, FtlDomain, FtlRegexMatch, FtlStrings},
+        testing::TestEnvBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Read the policy of the privacy level
+    fn policy(level: &str) -> PrivacyPolicy {
+        let env_builder =
+            TestEnvBuilder::new().file(PiholeFile::FtlConfig, &format!("PRIVACYLEVEL={}", level));
+
+        PrivacyPolicy::read(&Env::Test(Config::default(), env_builder.build())).unwrap()
+    }
+
+    /// Each privacy level hides more than the one before it
+    #[test]
+    fn privacy_levels() {
+        let show_all = policy("0");
+        assert!(show_all.shows_domains() && show_all.shows_clients() && show_all.shows_queries());
+
+        let hide_domains = policy("1");
+        assert!(!hide_domains.shows_domains() && hide_domains.shows_clients());
+
+        let hide_clients = policy("2");
+        assert!(!hide_clients.shows_clients() && hide_clients.shows_queries());
+
+        let maximum = policy("3");
+        assert!(!maximum.shows_queries());
+    }
+
+    /// Clients and domains marked as hidden are removed
+    #[test]
+    fn hidden_entries() {
+        let mut strings = HashMap::new();
+        strings.insert(1, "10.1.1.1".to_owned());
+        strings.insert(2, "0.0.0.0".to_owned());
+        strings.insert(3, "example.com".to_owned());
+        strings.insert(4, "hidden".to_owned());
+        let strings = FtlStrings::Test(&strings);
+
+        let client_list = vec![FtlClient::new(1, 0, 1, None), FtlClient::new(1, 0, 2, None)];
+        let mut clients: Vec<&FtlClient> = client_list.iter().collect();
+        remove_hidden_clients(&mut clients, &strings);
+        assert_eq!(clients, vec![&client_list[0]]);
+
+        let domain_list = vec![
+            FtlDomain::new(1, 0, 3, FtlRegexMatch::Unknown),
+            FtlDomain::new(1, 0, 4, FtlRegexMatch::Unknown),
+        ];
+        let mut domains: Vec<&FtlDomain> = domain_list.iter().collect();
+        remove_hidden_domains(&mut domains, &strings);
+        assert_eq!(domains, vec![&domain_list[0]]);
+    }
+}
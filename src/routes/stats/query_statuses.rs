@@ -0,0 +1,87 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Statuses Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{FtlMemory, FtlQueryStatus},
+    routes::auth::User,
+    util::{reply_result, Error, Reply}
+};
+use rocket::State;
+
+/// Get the number of queries with each status, such as how many were blocked
+/// by Gravity or answered from the cache
+#[get("/stats/query_statuses")]
+pub fn query_statuses(_auth: User, ftl_memory: State<FtlMemory>) -> Reply {
+    reply_result(query_statuses_impl(&ftl_memory))
+}
+
+/// Get the number of queries in memory with each status
+fn query_statuses_impl(ftl_memory: &FtlMemory) -> Result<Vec<QueryStatusReply>, Error> {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+
+    let mut counts = [0; 7];
+
+    for query in queries.iter().take(counters.total_queries as usize) {
+        counts[query.status as usize] += 1;
+    }
+
+    Ok(FtlQueryStatus::variants()
+        .iter()
+        .map(|&status| QueryStatusReply::new(status, counts[status as usize]))
+        .collect())
+}
+
+/// Represents the reply structure for returning query status data
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct QueryStatusReply {
+    pub name: String,
+    /// The status number FTL uses, which the history uses too
+    pub status: i32,
+    pub blocked: bool,
+    pub count: usize
+}
+
+impl QueryStatusReply {
+    /// Create the reply for the number of queries with the status
+    pub fn new(status: FtlQueryStatus, count: usize) -> Self {
+        QueryStatusReply {
+            name: status.get_name().to_owned(),
+            status: status as i32,
+            blocked: status.is_blocked(),
+            count
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{routes::stats::testing::test_memory, testing::TestBuilder};
+
+    /// Every status is counted, including those without queries
+    #[test]
+    fn query_statuses() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/query_statuses")
+            .ftl_memory(test_memory())
+            .expect_json(json!([
+                { "name": "unknown", "status": 0, "blocked": false, "count": 0 },
+                { "name": "gravity", "status": 1, "blocked": true, "count": 1 },
+                { "name": "forward", "status": 2, "blocked": false, "count": 4 },
+                { "name": "cache", "status": 3, "blocked": false, "count": 1 },
+                { "name": "wildcard", "status": 4, "blocked": true, "count": 1 },
+                { "name": "blacklist", "status": 5, "blocked": true, "count": 1 },
+                { "name": "external_block", "status": 6, "blocked": true, "count": 1 }
+            ]))
+            .test();
+    }
+}
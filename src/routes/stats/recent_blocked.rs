@@ -11,8 +11,10 @@
 use crate::{
     env::Env,
     ftl::FtlMemory,
-    routes::auth::User,
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
+    routes::{
+        auth::User,
+        stats::{DomainDisplay, PrivacyPolicy}
+    },
     util::{reply_data, Reply}
 };
 use rocket::{request::Form, State};
@@ -25,20 +27,30 @@ pub fn recent_blocked(
     env: State<Env>,
     params: Form<RecentBlockedParams>
 ) -> Reply {
-    get_recent_blocked(&ftl_memory, &env, params.num.unwrap_or(1))
+    get_recent_blocked(
+        &ftl_memory,
+        &env,
+        params.num.unwrap_or(1),
+        params.display.unwrap_or_default()
+    )
 }
 
 /// Represents the possible GET parameters on `/stats/recent_blocked`
 #[derive(FromForm)]
 pub struct RecentBlockedParams {
-    num: Option<usize>
+    num: Option<usize>,
+    display: Option<DomainDisplay>
 }
 
 /// Get `num`-many most recently blocked domains
-pub fn get_recent_blocked(ftl_memory: &FtlMemory, env: &Env, num: usize) -> Reply {
+pub fn get_recent_blocked(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    num: usize,
+    display: DomainDisplay
+) -> Reply {
     // Check if client details are private
-    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)? >= FtlPrivacyLevel::HideDomains
-    {
+    if !PrivacyPolicy::read(&env)?.shows_domains() {
         return reply_data([0; 0]);
     }
 
@@ -48,7 +60,7 @@ pub fn get_recent_blocked(ftl_memory: &FtlMemory, env: &Env, num: usize) -> Repl
     let strings = ftl_memory.strings(&lock)?;
     let domains = ftl_memory.domains(&lock)?;
 
-    let recent_blocked: Vec<&str> = queries
+    let recent_blocked: Vec<String> = queries
         .iter()
         // Get the most recent queries first
         .rev()
@@ -59,7 +71,7 @@ pub fn get_recent_blocked(ftl_memory: &FtlMemory, env: &Env, num: usize) -> Repl
         // Get up to num queries
         .take(num)
         // Only return the domain
-        .map(|query| domains[query.domain_id as usize].get_domain(&strings))
+        .map(|query| display.format(domains[query.domain_id as usize].get_domain(&strings)))
         .collect();
 
     reply_data(recent_blocked)
@@ -188,4 +200,30 @@ mod test {
             ]))
             .test();
     }
+
+    /// With `display=unicode`, punycode domains are shown in Unicode
+    #[test]
+    fn unicode_display() {
+        let mut strings = test_strings();
+        strings.insert(5, "xn--bcher-kva.example".to_owned());
+
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/recent_blocked?display=unicode")
+            .ftl_memory(FtlMemory::Test {
+                queries: test_queries(),
+                domains: test_domains(),
+                over_time: Vec::new(),
+                strings,
+                clients: Vec::new(),
+                upstreams: Vec::new(),
+                counters: FtlCounters {
+                    total_queries: 6,
+                    total_domains: 5,
+                    ..FtlCounters::default()
+                },
+                settings: FtlSettings::default()
+            })
+            .expect_json(json!(["bücher.example"]))
+            .test();
+    }
 }
@@ -0,0 +1,207 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Response Time Statistics Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    ftl::{FtlMemory, FtlQuery, FtlQueryStatus},
+    routes::auth::User,
+    util::{reply_data, Reply}
+};
+use rocket::State;
+use std::collections::BTreeMap;
+
+/// Queries without a response after 30 minutes never got one
+const MAX_RESPONSE_TIME: u64 = 18_000_000;
+
+/// Get statistics about the response times of the queries in FTL's memory,
+/// overall and for each upstream. The times are in units of 1/10
+/// milliseconds, like the history.
+#[get("/stats/response_times")]
+pub fn response_times(_auth: User, ftl_memory: State<FtlMemory>) -> Reply {
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let upstreams = ftl_memory.upstreams(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    let answered: Vec<&FtlQuery> = queries
+        .iter()
+        .take(counters.total_queries as usize)
+        .filter(|query| query.is_complete && (query.response_time as u64) < MAX_RESPONSE_TIME)
+        .collect();
+
+    // Group the response times of the queries which were answered by an
+    // upstream
+    let mut upstream_times: BTreeMap<usize, Vec<u64>> = BTreeMap::new();
+    for query in &answered {
+        if query.status == FtlQueryStatus::Forward || query.status == FtlQueryStatus::ExternalBlock
+        {
+            upstream_times
+                .entry(query.upstream_id as usize)
+                .or_default()
+                .push(query.response_time as u64);
+        }
+    }
+
+    let upstreams = upstream_times
+        .into_iter()
+        .filter_map(|(upstream_id, times)| {
+            let upstream = upstreams.get(upstream_id)?;
+
+            Some(UpstreamResponseTimes {
+                name: upstream.get_name(&strings).unwrap_or_default().to_owned(),
+                ip: upstream.get_ip(&strings).to_owned(),
+                times: ResponseTimeStats::from_times(times)?
+            })
+        })
+        .collect();
+
+    reply_data(ResponseTimesReply {
+        overall: ResponseTimeStats::from_times(
+            answered
+                .iter()
+                .map(|query| query.response_time as u64)
+                .collect()
+        ),
+        upstreams
+    })
+}
+
+/// Response time statistics of a group of queries
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ResponseTimeStats {
+    pub count: usize,
+    pub min: u64,
+    pub avg: f64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64
+}
+
+impl ResponseTimeStats {
+    /// Calculate the statistics of the response times. The percentiles use
+    /// the nearest rank. There are no statistics without any times.
+    pub fn from_times(mut times: Vec<u64>) -> Option<Self> {
+        if times.is_empty() {
+            return None;
+        }
+
+        times.sort_unstable();
+
+        let percentile = |percent: usize| {
+            let rank = (percent * times.len() + 99) / 100;
+            times[rank.max(1) - 1]
+        };
+
+        Some(ResponseTimeStats {
+            count: times.len(),
+            min: times[0],
+            avg: times.iter().sum::<u64>() as f64 / times.len() as f64,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99)
+        })
+    }
+}
+
+/// The response times of an upstream
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct UpstreamResponseTimes {
+    pub name: String,
+    pub ip: String,
+    #[serde(flatten)]
+    pub times: ResponseTimeStats
+}
+
+/// The reply of the response times endpoint. `overall` is `None` if no
+/// queries have been answered.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ResponseTimesReply {
+    pub overall: Option<ResponseTimeStats>,
+    pub upstreams: Vec<UpstreamResponseTimes>
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResponseTimeStats;
+    use crate::{routes::stats::testing::test_memory, testing::TestBuilder};
+
+    /// The percentiles use the nearest rank
+    #[test]
+    fn percentiles() {
+        assert_eq!(
+            ResponseTimeStats::from_times((1..=100).rev().collect()),
+            Some(ResponseTimeStats {
+                count: 100,
+                min: 1,
+                avg: 50.5,
+                p50: 50,
+                p95: 95,
+                p99: 99
+            })
+        );
+        assert_eq!(
+            ResponseTimeStats::from_times(vec![7]),
+            Some(ResponseTimeStats {
+                count: 1,
+                min: 7,
+                avg: 7.0,
+                p50: 7,
+                p95: 7,
+                p99: 7
+            })
+        );
+        assert_eq!(ResponseTimeStats::from_times(Vec::new()), None);
+    }
+
+    /// All answered queries are counted overall, and forwarded queries are
+    /// counted for their upstream
+    #[test]
+    fn response_times() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/response_times")
+            .ftl_memory(test_memory())
+            .expect_json(json!({
+                "overall": {
+                    "count": 9,
+                    "min": 1,
+                    "avg": 1.0,
+                    "p50": 1,
+                    "p95": 1,
+                    "p99": 1
+                },
+                "upstreams": [
+                    {
+                        "name": "google-public-dns-a.google.com",
+                        "ip": "8.8.8.8",
+                        "count": 4,
+                        "min": 1,
+                        "avg": 1.0,
+                        "p50": 1,
+                        "p95": 1,
+                        "p99": 1
+                    },
+                    {
+                        "name": "google-public-dns-b.google.com",
+                        "ip": "8.8.4.4",
+                        "count": 1,
+                        "min": 1,
+                        "avg": 1.0,
+                        "p50": 1,
+                        "p95": 1,
+                        "p99": 1
+                    }
+                ]
+            }))
+            .test();
+    }
+}
@@ -0,0 +1,365 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Subnet Stats Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    ftl::FtlMemory,
+    routes::{
+        auth::User,
+        stats::{
+            privacy::{get_hidden_client_ip, get_hidden_domain, PrivacyPolicy},
+            top_domains::TopDomainItemReply
+        }
+    },
+    settings::{ConfigEntry, SetupVarsEntry, Subnet},
+    util::{reply_result, Error, Reply}
+};
+use rocket::{request::Form, State};
+use std::{collections::HashMap, net::IpAddr};
+
+/// Get the query totals and top blocked domains of each configured subnet
+#[get("/stats/subnets?<params..>")]
+pub fn subnets(
+    _auth: User,
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    params: Form<SubnetParams>
+) -> Reply {
+    reply_result(get_subnets(&ftl_memory, &env, params.into_inner()))
+}
+
+/// Represents the possible GET parameters on `/stats/subnets`
+#[derive(FromForm, Default)]
+pub struct SubnetParams {
+    /// The number of top blocked domains to show for each subnet
+    pub limit: Option<usize>
+}
+
+/// Represents the reply structure for the subnet stats
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct SubnetsReply {
+    pub subnets: Vec<SubnetItemReply>
+}
+
+/// Represents the reply structure for a single subnet
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct SubnetItemReply {
+    pub subnet: String,
+    pub clients: usize,
+    pub total_queries: usize,
+    pub blocked_queries: usize,
+    pub top_blocked: Vec<TopDomainItemReply>
+}
+
+/// Get the subnet stats. A client is counted in each subnet which contains
+/// it, so overlapping subnets share clients.
+fn get_subnets(
+    ftl_memory: &FtlMemory,
+    env: &Env,
+    params: SubnetParams
+) -> Result<SubnetsReply, Error> {
+    let limit = params.limit.unwrap_or(10);
+    let subnets: Vec<Subnet> = SetupVarsEntry::ApiSubnets
+        .read_list(env)?
+        .iter()
+        .filter_map(|subnet| subnet.parse().ok())
+        .collect();
+
+    // Domains are only shown if the privacy level allows it
+    let show_domains = PrivacyPolicy::read(env)?.shows_domains();
+
+    let lock = ftl_memory.lock()?;
+    let counters = ftl_memory.counters(&lock)?;
+    let clients = ftl_memory.clients(&lock)?;
+    let domains = ftl_memory.domains(&lock)?;
+    let queries = ftl_memory.queries(&lock)?;
+    let strings = ftl_memory.strings(&lock)?;
+
+    // Find the subnets of each client, by client ID
+    let hidden_client_ip = get_hidden_client_ip();
+    let client_subnets: Vec<Vec<usize>> = clients
+        .iter()
+        .take(counters.total_clients as usize)
+        .map(|client| {
+            let ip = client.get_ip(&strings);
+
+            if ip == hidden_client_ip {
+                return Vec::new();
+            }
+
+            match ip.parse::<IpAddr>() {
+                Ok(ip) => subnets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, subnet)| subnet.contains(&ip))
+                    .map(|(i, _)| i)
+                    .collect(),
+                Err(_) => Vec::new()
+            }
+        })
+        .collect();
+
+    let mut totals: Vec<SubnetTotals> = subnets.iter().map(|_| SubnetTotals::default()).collect();
+
+    for (client_subnets, client) in client_subnets.iter().zip(clients.iter()) {
+        for &i in client_subnets {
+            if client.query_count > 0 {
+                totals[i].clients += 1;
+            }
+        }
+    }
+
+    let hidden_domain = get_hidden_domain();
+
+    for query in queries.iter().take(counters.total_queries as usize) {
+        let client_subnets = match client_subnets.get(query.client_id as usize) {
+            Some(client_subnets) => client_subnets,
+            None => continue
+        };
+        let blocked = query.is_blocked();
+
+        for &i in client_subnets {
+            let subnet_totals = &mut totals[i];
+
+            subnet_totals.total_queries += 1;
+
+            if blocked {
+                subnet_totals.blocked_queries += 1;
+
+                let domain = domains[query.domain_id as usize].get_domain(&strings);
+
+                if show_domains && domain != hidden_domain {
+                    *subnet_totals.blocked_domains.entry(domain).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    Ok(SubnetsReply {
+        subnets: subnets
+            .iter()
+            .zip(totals)
+            .map(|(subnet, totals)| {
+                let mut top_blocked: Vec<TopDomainItemReply> = totals
+                    .blocked_domains
+                    .into_iter()
+                    .map(|(domain, count)| TopDomainItemReply {
+                        domain: domain.to_owned(),
+                        count,
+                        category: None
+                    })
+                    .collect();
+
+                // Sort by count (descending), then by domain for a stable order
+                top_blocked.sort_by(|a, b| b.count.cmp(&a.count).then(a.domain.cmp(&b.domain)));
+                top_blocked.truncate(limit);
+
+                SubnetItemReply {
+                    subnet: subnet.to_string(),
+                    clients: totals.clients,
+                    total_queries: totals.total_queries,
+                    blocked_queries: totals.blocked_queries,
+                    top_blocked
+                }
+            })
+            .collect()
+    })
+}
+
+/// The running totals of a subnet
+#[derive(Default)]
+struct SubnetTotals<'a> {
+    clients: usize,
+    total_queries: usize,
+    blocked_queries: usize,
+    blocked_domains: HashMap<&'a str, usize>
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        env::PiholeFile,
+        ftl::{
+            FtlClient, FtlCounters, FtlDnssecType, FtlDomain, FtlMemory, FtlQuery,
+            FtlQueryReplyType, FtlQueryStatus, FtlQueryType, FtlRegexMatch, FtlSettings,
+            MAGIC_BYTE
+        },
+        testing::TestBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Shorthand for making `FtlQuery` structs
+    macro_rules! query {
+        ($id:expr, $status:ident, $domain:expr, $client:expr) => {
+            FtlQuery {
+                magic: MAGIC_BYTE,
+                id: $id,
+                database_id: 0,
+                timestamp: 1,
+                time_index: 1,
+                response_time: 1,
+                domain_id: $domain,
+                client_id: $client,
+                upstream_id: 0,
+                query_type: FtlQueryType::A,
+                status: FtlQueryStatus::$status,
+                reply_type: FtlQueryReplyType::IP,
+                dnssec_type: FtlDnssecType::Unspecified,
+                is_complete: true,
+                is_private: false,
+                ad_bit: false
+            }
+        };
+    }
+
+    /// There are 3 clients, two in 192.168.1.0/24 and one in 10.0.0.0/8, and
+    /// 6 queries
+    fn test_data() -> FtlMemory {
+        let mut strings = HashMap::new();
+        strings.insert(1, "192.168.1.10".to_owned());
+        strings.insert(2, "192.168.1.20".to_owned());
+        strings.insert(3, "10.0.0.5".to_owned());
+        strings.insert(4, "example.com".to_owned());
+        strings.insert(5, "ads.example.com".to_owned());
+        strings.insert(6, "tracker.example.com".to_owned());
+
+        FtlMemory::Test {
+            clients: vec![
+                FtlClient::new(3, 2, 1, None),
+                FtlClient::new(1, 1, 2, None),
+                FtlClient::new(2, 1, 3, None),
+            ],
+            domains: vec![
+                FtlDomain::new(2, 0, 4, FtlRegexMatch::NotBlocked),
+                FtlDomain::new(3, 3, 5, FtlRegexMatch::NotBlocked),
+                FtlDomain::new(1, 1, 6, FtlRegexMatch::NotBlocked),
+            ],
+            over_time: Vec::new(),
+            strings,
+            upstreams: Vec::new(),
+            queries: vec![
+                query!(1, Forward, 0, 0),
+                query!(2, Gravity, 1, 0),
+                query!(3, Blacklist, 2, 0),
+                query!(4, Gravity, 1, 1),
+                query!(5, Cache, 0, 2),
+                query!(6, Gravity, 1, 2),
+            ],
+            counters: FtlCounters {
+                total_queries: 6,
+                blocked_queries: 4,
+                total_clients: 3,
+                total_domains: 3,
+                ..FtlCounters::default()
+            },
+            settings: FtlSettings::default()
+        }
+    }
+
+    /// Queries are totaled for each subnet, with the top blocked domains
+    #[test]
+    fn default_params() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/subnets")
+            .ftl_memory(test_data())
+            .file(
+                PiholeFile::SetupVars,
+                "API_SUBNETS=192.168.1.0/24,10.0.0.0/8,172.16.0.0/12\n"
+            )
+            .expect_json(json!({
+                "subnets": [
+                    {
+                        "subnet": "192.168.1.0/24",
+                        "clients": 2,
+                        "total_queries": 4,
+                        "blocked_queries": 3,
+                        "top_blocked": [
+                            { "domain": "ads.example.com", "count": 2 },
+                            { "domain": "tracker.example.com", "count": 1 }
+                        ]
+                    },
+                    {
+                        "subnet": "10.0.0.0/8",
+                        "clients": 1,
+                        "total_queries": 2,
+                        "blocked_queries": 1,
+                        "top_blocked": [
+                            { "domain": "ads.example.com", "count": 1 }
+                        ]
+                    },
+                    {
+                        "subnet": "172.16.0.0/12",
+                        "clients": 0,
+                        "total_queries": 0,
+                        "blocked_queries": 0,
+                        "top_blocked": []
+                    }
+                ]
+            }))
+            .test();
+    }
+
+    /// The number of top blocked domains is limited
+    #[test]
+    fn limit() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/subnets?limit=1")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "API_SUBNETS=192.168.1.0/24\n")
+            .expect_json(json!({
+                "subnets": [
+                    {
+                        "subnet": "192.168.1.0/24",
+                        "clients": 2,
+                        "total_queries": 4,
+                        "blocked_queries": 3,
+                        "top_blocked": [
+                            { "domain": "ads.example.com", "count": 2 }
+                        ]
+                    }
+                ]
+            }))
+            .test();
+    }
+
+    /// Blocked domains are not shown when the privacy level hides domains
+    #[test]
+    fn privacy_hides_domains() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/subnets")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "API_SUBNETS=10.0.0.0/8\n")
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=1\n")
+            .expect_json(json!({
+                "subnets": [
+                    {
+                        "subnet": "10.0.0.0/8",
+                        "clients": 1,
+                        "total_queries": 2,
+                        "blocked_queries": 1,
+                        "top_blocked": []
+                    }
+                ]
+            }))
+            .test();
+    }
+
+    /// No subnets are configured by default
+    #[test]
+    fn no_subnets() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/subnets")
+            .ftl_memory(test_data())
+            .expect_json(json!({ "subnets": [] }))
+            .test();
+    }
+}
@@ -11,15 +11,56 @@
 use crate::{
     env::Env,
     ftl::{FtlMemory, FtlQueryType},
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel, SetupVarsEntry},
-    util::{reply_data, Reply}
+    gravity_schedule::gravity_modified,
+    process_info::ProcessInfo,
+    routes::{auth::User, stats::PrivacyPolicy},
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{reply_data, Error, ErrorKind, Reply}
 };
 use rocket::State;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc
+    },
+    time::Duration
+};
+
+/// The longest time in seconds a summary request can wait for the counters to
+/// change. Waiting requests hold a worker thread, so they are kept short.
+const MAX_WAIT_FOR_CHANGE: u64 = 30;
+
+/// The most summary requests which can wait for the counters to change at the
+/// same time. Later requests get the summary at once, as if they had not
+/// asked to wait, so waiting requests can not take every worker thread.
+const MAX_WAITERS: usize = 4;
+
+/// Get the summary data. With `wait_for_change=<seconds>`, the reply waits
+/// until the FTL counters change or the time runs out, so dashboards can long
+/// poll instead of requesting the summary every second. Only authenticated
+/// clients can wait, for at most `MAX_WAIT_FOR_CHANGE` seconds, and at most
+/// `MAX_WAITERS` of them at once.
+#[get("/stats/summary?<wait_for_change>")]
+pub fn get_summary(
+    ftl_memory: State<FtlMemory>,
+    env: State<Env>,
+    process_info: State<ProcessInfo>,
+    waiters: State<SummaryWaiters>,
+    user: Option<User>,
+    wait_for_change: Option<u64>
+) -> Reply {
+    if let Some(seconds) = wait_for_change {
+        if user.is_none() {
+            return Err(Error::from(ErrorKind::Unauthorized));
+        }
 
-/// Get the summary data
-#[get("/stats/summary")]
-pub fn get_summary(ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
-    let lock = ftl_memory.lock()?;
+        waiters.wait(
+            &ftl_memory,
+            Duration::from_secs(seconds.min(MAX_WAIT_FOR_CHANGE))
+        )?;
+    }
+
+    let lock = ftl_memory.snapshot()?;
     let counters = ftl_memory.counters(&lock)?;
 
     let percent_blocked = if counters.total_queries == 0 {
@@ -29,9 +70,7 @@ pub fn get_summary(ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
     };
 
     let (total_clients, active_clients) = {
-        if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)?
-            >= FtlPrivacyLevel::HideDomainsAndClients
-        {
+        if !PrivacyPolicy::read(&env)?.shows_clients() {
             // If clients are supposed to be hidden, pretend there are no clients
             (0, 0)
         } else {
@@ -62,7 +101,7 @@ pub fn get_summary(ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
         "disabled"
     };
 
-    reply_data(Summary {
+    let summary = Summary {
         gravity_size: counters.gravity_size as usize,
         total_queries: TotalQueries {
             A: counters.query_type(FtlQueryType::A),
@@ -88,9 +127,51 @@ pub fn get_summary(ftl_memory: State<FtlMemory>, env: State<Env>) -> Reply {
         total_clients,
         active_clients,
         status
+    };
+
+    reply_data(SummaryReply {
+        summary,
+        api_uptime: process_info.api_uptime(),
+        ftl_uptime: process_info.ftl_uptime(&env),
+        gravity_last_updated: gravity_modified(&env)
     })
 }
 
+/// Counts the summary requests which are waiting for the counters to change.
+/// Clones share the count, so the limit covers every listener.
+#[derive(Clone, Default)]
+pub struct SummaryWaiters(Arc<AtomicUsize>);
+
+impl SummaryWaiters {
+    /// Wait until the counters change or the timeout passes. If
+    /// `MAX_WAITERS` requests are already waiting, return at once.
+    fn wait(&self, ftl_memory: &FtlMemory, timeout: Duration) -> Result<(), Error> {
+        if self.0.fetch_add(1, Ordering::SeqCst) >= MAX_WAITERS {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        let result = ftl_memory.wait_for_counter_change(timeout);
+        self.0.fetch_sub(1, Ordering::SeqCst);
+
+        result.map(|_| ())
+    }
+}
+
+/// Represents the response of the summary endpoint, which adds process
+/// information to the summary
+#[derive(Serialize)]
+pub struct SummaryReply {
+    #[serde(flatten)]
+    pub summary: Summary,
+    /// The API uptime in seconds
+    pub api_uptime: u64,
+    /// The FTL uptime in seconds, if FTL is running
+    pub ftl_uptime: Option<u64>,
+    /// When Gravity was last updated, as a Unix timestamp
+    pub gravity_last_updated: Option<u64>
+}
+
 /// Represents the response of summary endpoints
 #[derive(Serialize)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
@@ -136,12 +217,19 @@ pub struct ReplyTypes {
 
 #[cfg(test)]
 mod test {
+    use super::{SummaryWaiters, MAX_WAITERS};
     use crate::{
         env::PiholeFile,
         ftl::{FtlClient, FtlCounters, FtlMemory, FtlSettings},
         testing::TestBuilder
     };
-    use std::collections::HashMap;
+    use rocket::http::Status;
+    use serde_json::Value;
+    use std::{
+        collections::HashMap,
+        sync::atomic::Ordering,
+        time::{Duration, Instant}
+    };
 
     /// There are 6 clients, two inactive, one hidden, and two with names.
     fn test_data() -> FtlMemory {
@@ -220,11 +308,88 @@ mod test {
                 },
                 "total_clients": 5,
                 "active_clients": 4,
-                "status": "enabled"
+                "status": "enabled",
+                "api_uptime": 3600,
+                "ftl_uptime": 7200,
+                "gravity_last_updated": null
             }))
             .test();
     }
 
+    /// When the counters do not change, the summary is returned once the
+    /// time to wait runs out
+    #[test]
+    fn wait_for_change_timeout() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/summary?wait_for_change=0")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "BLOCKING_ENABLED=true")
+            .expect_json(json!({
+                "gravity_size": 100_000,
+                "total_queries": {
+                    "A": 3,
+                    "AAAA": 4,
+                    "ANY": 1,
+                    "SRV": 0,
+                    "SOA": 0,
+                    "PTR": 3,
+                    "TXT": 0
+                },
+                "blocked_queries": 2,
+                "percent_blocked": 28.571_428_571_428_573,
+                "unique_domains": 6,
+                "forwarded_queries": 3,
+                "cached_queries": 2,
+                "reply_types": {
+                    "IP": 3,
+                    "CNAME": 3,
+                    "DOMAIN": 1,
+                    "NODATA": 1,
+                    "NXDOMAIN": 2
+                },
+                "total_clients": 5,
+                "active_clients": 4,
+                "status": "enabled",
+                "api_uptime": 3600,
+                "ftl_uptime": 7200,
+                "gravity_last_updated": null
+            }))
+            .test();
+    }
+
+    /// Unauthenticated clients can get the summary, but not wait for changes
+    #[test]
+    fn wait_for_change_unauthenticated() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/summary?wait_for_change=1")
+            .should_auth(false)
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "BLOCKING_ENABLED=true")
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .test();
+    }
+
+    /// Requests over the waiter limit return at once, and do not change the
+    /// number of waiting requests
+    #[test]
+    fn waiter_limit() {
+        let waiters = SummaryWaiters::default();
+        waiters.0.store(MAX_WAITERS, Ordering::SeqCst);
+
+        let start = Instant::now();
+        waiters.wait(&test_data(), Duration::from_secs(30)).unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(waiters.0.load(Ordering::SeqCst), MAX_WAITERS);
+    }
+
     #[test]
     fn disabled_and_privacy() {
         TestBuilder::new()
@@ -257,7 +422,10 @@ mod test {
                 },
                 "total_clients": 0,
                 "active_clients": 0,
-                "status": "disabled"
+                "status": "disabled",
+                "api_uptime": 3600,
+                "ftl_uptime": 7200,
+                "gravity_last_updated": null
             }))
             .test();
     }
@@ -0,0 +1,233 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Summary Comparison Endpoint
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::long_term::{LongTermDatabase, StoreConnection},
+    metrics::time_database,
+    routes::{auth::User, stats::database::get_blocked_query_count},
+    util::{current_time, reply_result, Error, ErrorKind, Reply}
+};
+use diesel::{dsl::sql, prelude::*, sql_types::Integer};
+use failure::ResultExt;
+
+/// The length of each compared window, in seconds
+const WINDOW: u64 = 24 * 60 * 60;
+
+/// Compare the last 24 hours with the 24 hours before them, so the dashboard
+/// can show the change without querying both windows itself. The windows end
+/// at `until`, which defaults to now.
+#[get("/stats/summary/compare?<until>")]
+pub fn get_summary_compare(_auth: User, db: LongTermDatabase, until: Option<u64>) -> Reply {
+    let until = until.unwrap_or_else(current_time);
+
+    reply_result(time_database(|| {
+        get_summary_compare_impl(db.connection(), until)
+    }))
+}
+
+/// Represents the response of the summary comparison endpoint
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct SummaryComparison {
+    pub current: WindowSummary,
+    pub previous: WindowSummary,
+    pub change: SummaryChange
+}
+
+/// The metrics of one window. The window includes both timestamps.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct WindowSummary {
+    pub from: u64,
+    pub until: u64,
+    pub total_queries: usize,
+    pub blocked_queries: usize,
+    pub percent_blocked: f64,
+    /// The number of clients whose first query in the database is in the
+    /// window
+    pub new_clients: usize
+}
+
+/// The change from the previous window to the current window
+#[derive(Serialize)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct SummaryChange {
+    /// The change in percent, or `None` if the previous window had no queries
+    pub total_queries: Option<f64>,
+    /// The change in percent, or `None` if the previous window had no blocked
+    /// queries
+    pub blocked_queries: Option<f64>,
+    /// The difference between the blocked fractions
+    pub percent_blocked: f64,
+    /// The change in percent, or `None` if the previous window had no new
+    /// clients
+    pub new_clients: Option<f64>
+}
+
+/// Summarize the two windows ending at `until` and compare them
+fn get_summary_compare_impl(db: StoreConnection, until: u64) -> Result<SummaryComparison, Error> {
+    let current_from = until.saturating_sub(WINDOW - 1);
+    let previous_until = until.saturating_sub(WINDOW);
+    let previous_from = until.saturating_sub(2 * WINDOW - 1);
+
+    let first_seen = get_client_first_seen(db, until)?;
+    let current = get_window_summary(db, current_from, until, &first_seen)?;
+    let previous = get_window_summary(db, previous_from, previous_until, &first_seen)?;
+
+    let change = SummaryChange {
+        total_queries: percent_change(previous.total_queries, current.total_queries),
+        blocked_queries: percent_change(previous.blocked_queries, current.blocked_queries),
+        percent_blocked: current.percent_blocked - previous.percent_blocked,
+        new_clients: percent_change(previous.new_clients, current.new_clients)
+    };
+
+    Ok(SummaryComparison {
+        current,
+        previous,
+        change
+    })
+}
+
+/// Get the metrics of the window. `first_seen` is the time of each client's
+/// first query.
+fn get_window_summary(
+    db: StoreConnection,
+    from: u64,
+    until: u64,
+    first_seen: &[i32]
+) -> Result<WindowSummary, Error> {
+    let total_queries = get_query_count(db, from, until)?;
+    let blocked_queries = get_blocked_query_count(db, from, until)?;
+
+    Ok(WindowSummary {
+        from,
+        until,
+        total_queries,
+        blocked_queries,
+        percent_blocked: if total_queries == 0 {
+            0f64
+        } else {
+            (blocked_queries as f64) / (total_queries as f64)
+        },
+        new_clients: first_seen
+            .iter()
+            .filter(|&&time| time >= from as i32 && time <= until as i32)
+            .count()
+    })
+}
+
+/// Get the number of queries in the specified time range
+fn get_query_count(db: StoreConnection, from: u64, until: u64) -> Result<usize, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    let count = with_store!(db, |db| {
+        queries
+            .filter(timestamp.le(until as i32).and(timestamp.ge(from as i32)))
+            .count()
+            .first::<i64>(db)
+    })
+    .context(ErrorKind::FtlDatabase)?;
+
+    Ok(count as usize)
+}
+
+/// Get the time of each client's first query, up to `until`. Only queries
+/// still in the database are considered, so a client is new again once its
+/// older queries have been removed.
+fn get_client_first_seen(db: StoreConnection, until: u64) -> Result<Vec<i32>, Error> {
+    use crate::databases::ftl::queries::dsl::*;
+
+    Ok(with_store!(db, |db| {
+        queries
+            .select(sql::<Integer>("MIN(timestamp)"))
+            .filter(timestamp.le(until as i32))
+            .group_by(client)
+            .load(db)
+    })
+    .context(ErrorKind::FtlDatabase)?)
+}
+
+/// Get the change from the previous value to the current value in percent.
+/// There is no change if the previous value is zero.
+fn percent_change(previous: usize, current: usize) -> Option<f64> {
+    if previous == 0 {
+        None
+    } else {
+        Some((current as f64 - previous as f64) / previous as f64 * 100f64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_summary_compare_impl, percent_change};
+    use crate::{
+        databases::{ftl::connect_to_test_db, long_term::StoreConnection},
+        testing::TestBuilder
+    };
+
+    /// The windows are compared. Each client is new in the window of its
+    /// first query.
+    #[test]
+    fn compare() {
+        let db = connect_to_test_db();
+        let comparison = get_summary_compare_impl(StoreConnection::Sqlite(&db), 170_000).unwrap();
+
+        assert_eq!(comparison.current.from, 83_601);
+        assert_eq!(comparison.current.total_queries, 36);
+        assert_eq!(comparison.current.new_clients, 1);
+        assert_eq!(comparison.previous.from, 0);
+        assert_eq!(comparison.previous.until, 83_600);
+        assert_eq!(comparison.previous.total_queries, 2);
+        assert_eq!(comparison.previous.new_clients, 1);
+        assert_eq!(comparison.change.total_queries, Some(1700f64));
+        assert_eq!(comparison.change.blocked_queries, None);
+        assert_eq!(comparison.change.new_clients, Some(0f64));
+    }
+
+    /// There is no change from zero
+    #[test]
+    fn change_from_zero() {
+        assert_eq!(percent_change(0, 10), None);
+        assert_eq!(percent_change(10, 5), Some(-50f64));
+    }
+
+    /// The comparison is returned by the endpoint
+    #[test]
+    fn endpoint() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/summary/compare?until=170000")
+            .need_database(true)
+            .expect_json(json!({
+                "current": {
+                    "from": 83_601,
+                    "until": 170_000,
+                    "total_queries": 36,
+                    "blocked_queries": 0,
+                    "percent_blocked": 0.0,
+                    "new_clients": 1
+                },
+                "previous": {
+                    "from": 0,
+                    "until": 83_600,
+                    "total_queries": 2,
+                    "blocked_queries": 0,
+                    "percent_blocked": 0.0,
+                    "new_clients": 1
+                },
+                "change": {
+                    "total_queries": 1700.0,
+                    "blocked_queries": null,
+                    "percent_blocked": 0.0,
+                    "new_clients": 0.0
+                }
+            }))
+            .test();
+    }
+}
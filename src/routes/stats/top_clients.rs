@@ -9,13 +9,16 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
+    client_nicknames::ClientNicknames,
     env::Env,
     ftl::{FtlClient, FtlMemory},
     routes::{
         auth::User,
-        stats::common::{remove_excluded_clients, remove_hidden_clients}
+        stats::{
+            common::remove_excluded_clients,
+            privacy::{remove_hidden_clients, PrivacyPolicy}
+        }
     },
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel},
     util::{reply_result, Error, Reply}
 };
 use rocket::{request::Form, State};
@@ -26,9 +29,15 @@ pub fn top_clients(
     _auth: User,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
+    nicknames: State<ClientNicknames>,
     params: Form<TopClientParams>
 ) -> Reply {
-    reply_result(get_top_clients(&ftl_memory, &env, params.into_inner()))
+    reply_result(get_top_clients(
+        &ftl_memory,
+        &env,
+        &nicknames,
+        params.into_inner()
+    ))
 }
 
 /// Represents the possible GET parameters on `/stats/top_clients`
@@ -64,6 +73,7 @@ pub struct TopClientItemReply {
 fn get_top_clients(
     ftl_memory: &FtlMemory,
     env: &Env,
+    nicknames: &ClientNicknames,
     params: TopClientParams
 ) -> Result<TopClientsReply, Error> {
     // Resolve the parameters
@@ -72,7 +82,7 @@ fn get_top_clients(
     let ascending = params.ascending.unwrap_or(false);
     let blocked = params.blocked.unwrap_or(false);
 
-    let lock = ftl_memory.lock()?;
+    let lock = ftl_memory.snapshot()?;
     let counters = ftl_memory.counters(&lock)?;
 
     let total_count = if blocked {
@@ -127,8 +137,10 @@ fn get_top_clients(
     let top_clients: Vec<TopClientItemReply> = clients
         .into_iter()
         .map(|client| {
-            let name = client.get_name(&strings).unwrap_or_default().to_owned();
             let ip = client.get_ip(&strings).to_owned();
+            let name = nicknames
+                .get(&ip)
+                .unwrap_or_else(|| client.get_name(&strings).unwrap_or_default().to_owned());
             let count = if blocked {
                 client.blocked_count
             } else {
@@ -162,9 +174,7 @@ pub fn check_privacy_level_top_clients(
     blocked: bool,
     count: usize
 ) -> Result<Option<TopClientsReply>, Error> {
-    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)?
-        >= FtlPrivacyLevel::HideDomainsAndClients
-    {
+    if !PrivacyPolicy::read(&env)?.shows_clients() {
         return if blocked {
             Ok(Some(TopClientsReply {
                 top_clients: Vec::new(),
@@ -13,11 +13,18 @@ use crate::{
     ftl::{FtlDomain, FtlMemory},
     routes::{
         auth::User,
-        stats::common::{remove_excluded_domains, remove_hidden_domains}
+        stats::{
+            common::remove_excluded_domains,
+            privacy::{remove_hidden_domains, PrivacyPolicy},
+            DomainDisplay
+        }
     },
-    settings::{ConfigEntry, FtlConfEntry, FtlPrivacyLevel, SetupVarsEntry},
-    util::{reply_result, Error, Reply}
+    services::ThreatCategories,
+    settings::{ConfigEntry, NoiseDomains, SetupVarsEntry},
+    util::{reply_result, Error, ErrorKind, Reply}
 };
+use failure::ResultExt;
+use regex::{Regex, RegexBuilder};
 use rocket::{request::Form, State};
 
 /// Return the top domains
@@ -26,9 +33,15 @@ pub fn top_domains(
     _auth: User,
     ftl_memory: State<FtlMemory>,
     env: State<Env>,
-    params: Form<TopDomainParams>
+    params: Form<TopDomainParams>,
+    threat_categories: State<ThreatCategories>
 ) -> Reply {
-    reply_result(get_top_domains(&ftl_memory, &env, params.into_inner()))
+    reply_result(get_top_domains(
+        &ftl_memory,
+        &env,
+        params.into_inner(),
+        &threat_categories
+    ))
 }
 
 /// Represents the possible GET parameters for top (blocked) domains requests
@@ -37,7 +50,31 @@ pub struct TopDomainParams {
     pub limit: Option<usize>,
     pub audit: Option<bool>,
     pub ascending: Option<bool>,
-    pub blocked: Option<bool>
+    pub blocked: Option<bool>,
+    /// Hide noise domains, such as time servers and connectivity checks
+    pub hide_noise: Option<bool>,
+    /// Only show domains which match this regex. A substring is also a valid
+    /// regex, as long as it has no special characters.
+    pub filter: Option<String>,
+    /// Show internationalized domains in Unicode or ASCII. The filter always
+    /// matches the ASCII form.
+    pub display: Option<DomainDisplay>
+}
+
+impl TopDomainParams {
+    /// Compile the filter, if there is one. Domains are matched without
+    /// regard to case.
+    pub fn filter_regex(&self) -> Result<Option<Regex>, Error> {
+        match self.filter {
+            Some(ref filter) if !filter.is_empty() => Ok(Some(
+                RegexBuilder::new(filter)
+                    .case_insensitive(true)
+                    .build()
+                    .context(ErrorKind::BadRequest)?
+            )),
+            _ => Ok(None)
+        }
+    }
 }
 
 /// Represents the reply structure for top (blocked) domains
@@ -56,22 +93,29 @@ pub struct TopDomainsReply {
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub struct TopDomainItemReply {
     pub domain: String,
-    pub count: usize
+    pub count: usize,
+    /// The category of the domain in the threat feed, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>
 }
 
 /// Get the top domains (blocked or not)
 fn get_top_domains(
     ftl_memory: &FtlMemory,
     env: &Env,
-    params: TopDomainParams
+    params: TopDomainParams,
+    threat_categories: &ThreatCategories
 ) -> Result<TopDomainsReply, Error> {
     // Resolve the parameters
     let limit = params.limit.unwrap_or(10);
     let audit = params.audit.unwrap_or(false);
     let ascending = params.ascending.unwrap_or(false);
     let blocked = params.blocked.unwrap_or(false);
+    let hide_noise = params.hide_noise.unwrap_or(false);
+    let filter = params.filter_regex()?;
+    let display = params.display.unwrap_or_default();
 
-    let lock = ftl_memory.lock()?;
+    let lock = ftl_memory.snapshot()?;
     let counters = ftl_memory.counters(&lock)?;
 
     // Check if we are allowed to share the top domains
@@ -124,6 +168,18 @@ fn get_top_domains(
         domains.retain(|domain| !audited_domains.contains(&domain.get_domain(&strings)));
     }
 
+    // If the hide noise flag is true, remove noise domains
+    if hide_noise {
+        let noise_domains = NoiseDomains::read(env)?;
+
+        domains.retain(|domain| !noise_domains.matches(domain.get_domain(&strings)));
+    }
+
+    // If there is a filter, only include matching domains
+    if let Some(filter) = filter {
+        domains.retain(|domain| filter.is_match(domain.get_domain(&strings)));
+    }
+
     // Sort the domains (descending by default)
     match (ascending, blocked) {
         (false, false) => domains.sort_by(|a, b| {
@@ -145,7 +201,7 @@ fn get_top_domains(
     let top_domains: Vec<TopDomainItemReply> = domains
         .iter()
         .map(|domain| {
-            let name = domain.get_domain(&strings).to_owned();
+            let name = domain.get_domain(&strings);
             let count = if blocked {
                 domain.blocked_count
             } else {
@@ -153,7 +209,8 @@ fn get_top_domains(
             } as usize;
 
             TopDomainItemReply {
-                domain: name,
+                category: threat_categories.category(name),
+                domain: display.format(name),
                 count
             }
         })
@@ -214,8 +271,7 @@ pub fn check_privacy_level_top_domains(
     blocked: bool,
     count: usize
 ) -> Result<Option<TopDomainsReply>, Error> {
-    if FtlConfEntry::PrivacyLevel.read_as::<FtlPrivacyLevel>(&env)? >= FtlPrivacyLevel::HideDomains
-    {
+    if !PrivacyPolicy::read(&env)?.shows_domains() {
         if blocked {
             return Ok(Some(TopDomainsReply {
                 top_domains: Vec::new(),
@@ -241,6 +297,7 @@ mod test {
         ftl::{FtlCounters, FtlDomain, FtlMemory, FtlRegexMatch, FtlSettings},
         testing::TestBuilder
     };
+    use rocket::http::Status;
     use std::collections::HashMap;
 
     /// Four clients, one hidden, one with no queries
@@ -373,4 +430,52 @@ mod test {
             }))
             .test();
     }
+
+    /// Show permitted domains, but no noise domains or their subdomains
+    #[test]
+    fn hide_noise() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/top_domains?hide_noise=true")
+            .ftl_memory(test_data())
+            .file(PiholeFile::SetupVars, "API_NOISE_DOMAINS=example.net")
+            .expect_json(json!({
+                "top_domains": [
+                    { "domain": "github.com", "count": 20 }
+                ],
+                "total_queries": 39
+            }))
+            .test();
+    }
+
+    /// Only show domains which match the filter
+    #[test]
+    fn filter() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/top_domains?blocked=true&filter=%5Eexample%5C.N")
+            .ftl_memory(test_data())
+            .expect_json(json!({
+                "top_domains": [
+                    { "domain": "example.net", "count": 9 }
+                ],
+                "blocked_queries": 21
+            }))
+            .test();
+    }
+
+    /// Invalid filters are rejected
+    #[test]
+    fn invalid_filter() {
+        TestBuilder::new()
+            .endpoint("/admin/api/stats/top_domains?filter=%5B")
+            .ftl_memory(test_data())
+            .expect_status(Status::BadRequest)
+            .expect_json(json!({
+                "error": {
+                    "key": "bad_request",
+                    "message": "Bad request",
+                    "data": null
+                }
+            }))
+            .test();
+    }
 }
@@ -0,0 +1,296 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// User Account Routes
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    routes::auth::User,
+    users::{Account, AccountInfo, Role},
+    util::{reply_data, reply_success, Error, ErrorKind, Reply}
+};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde_json::{Map, Value};
+
+/// The input when creating an account
+#[derive(Deserialize)]
+pub struct NewAccount {
+    name: String,
+    password: String,
+    role: Role
+}
+
+/// The input when changing an account. Only the given values are changed.
+#[derive(Deserialize)]
+pub struct AccountChanges {
+    password: Option<String>,
+    role: Option<Role>
+}
+
+/// Get the accounts
+#[get("/auth/users")]
+pub fn get_users(_auth: User, env: State<Env>) -> Reply {
+    let accounts: Vec<AccountInfo> = Account::read_all(&env)?.iter().map(Account::info).collect();
+
+    reply_data(accounts)
+}
+
+/// Create an account
+#[post("/auth/users", data = "<input>")]
+pub fn add_user(_auth: User, env: State<Env>, input: Json<NewAccount>) -> Reply {
+    let input = input.into_inner();
+    let mut accounts = Account::read_all(&env)?;
+
+    if accounts.iter().any(|account| account.name == input.name) {
+        return Err(Error::from(ErrorKind::AlreadyExists));
+    }
+
+    accounts.push(Account::new(input.name, &input.password, input.role)?);
+    Account::write_all(&env, &accounts)?;
+
+    reply_success()
+}
+
+/// Change the password or role of an account. The changes apply to the
+/// account's existing sessions.
+#[put("/auth/users/<name>", data = "<input>")]
+pub fn update_user(
+    _auth: User,
+    env: State<Env>,
+    name: String,
+    input: Json<AccountChanges>
+) -> Reply {
+    let input = input.into_inner();
+    let mut accounts = Account::read_all(&env)?;
+    let account = accounts
+        .iter_mut()
+        .find(|account| account.name == name)
+        .ok_or(ErrorKind::NotFound)?;
+
+    if let Some(password) = input.password {
+        account.set_password(&password)?;
+    }
+
+    if let Some(role) = input.role {
+        account.role = role;
+    }
+
+    Account::write_all(&env, &accounts)?;
+
+    reply_success()
+}
+
+/// Delete an account. Its sessions are no longer authenticated.
+#[delete("/auth/users/<name>")]
+pub fn delete_user(_auth: User, env: State<Env>, name: String) -> Reply {
+    let mut accounts = Account::read_all(&env)?;
+    let count = accounts.len();
+
+    accounts.retain(|account| account.name != name);
+
+    if accounts.len() == count {
+        return Err(Error::from(ErrorKind::NotFound));
+    }
+
+    Account::write_all(&env, &accounts)?;
+
+    reply_success()
+}
+
+/// Get the preferences of the user's account. Sessions which used the API
+/// key do not have an account, so they do not have preferences.
+#[get("/auth/preferences")]
+pub fn get_preferences(user: User, env: State<Env>) -> Reply {
+    let name = user.name.ok_or(ErrorKind::BadRequest)?;
+    let account = Account::find(&env, &name)?.ok_or(ErrorKind::NotFound)?;
+
+    reply_data(account.preferences)
+}
+
+/// Replace the preferences of the user's account. Viewers may change their
+/// own preferences.
+#[put("/auth/preferences", data = "<preferences>")]
+pub fn put_preferences(
+    user: User,
+    env: State<Env>,
+    preferences: Json<Map<String, Value>>
+) -> Reply {
+    let name = user.name.ok_or(ErrorKind::BadRequest)?;
+    let mut accounts = Account::read_all(&env)?;
+    let account = accounts
+        .iter_mut()
+        .find(|account| account.name == name)
+        .ok_or(ErrorKind::NotFound)?;
+
+    account.set_preferences(preferences.into_inner())?;
+    Account::write_all(&env, &accounts)?;
+
+    reply_success()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{env::PiholeFile, testing::TestBuilder};
+    use rocket::http::{Header, Method, Status};
+
+    /// A viewer account with the password `viewer-password`
+    const VIEWER: &str = "[{\"name\":\"viewer\",\"role\":\"viewer\",\
+                          \"salt\":\"c2FsdHNhbHRzYWx0\",\"iterations\":1000,\
+                          \"password_hash\":\"30fhX7p84DthlH6wdbrrnzsxCBWiWwTJ5OOgOqVsH1o=\",\
+                          \"preferences\":{}}]\n";
+
+    /// Log in as the viewer account
+    fn viewer(builder: TestBuilder) -> TestBuilder {
+        builder
+            .should_auth(false)
+            .header(Header::new("X-Pi-hole-User", "viewer"))
+            .header(Header::new("X-Pi-hole-Authenticate", "viewer-password"))
+    }
+
+    /// The accounts are listed without their passwords
+    #[test]
+    fn get_users() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/users")
+            .file(PiholeFile::ApiUsers, VIEWER)
+            .expect_json(json!([{ "name": "viewer", "role": "viewer" }]))
+            .test();
+    }
+
+    /// Accounts can log in with their own password
+    #[test]
+    fn account_login() {
+        viewer(TestBuilder::new())
+            .endpoint("/admin/api/auth")
+            .file(PiholeFile::ApiUsers, VIEWER)
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Viewers can not make changes
+    #[test]
+    fn viewer_read_only() {
+        viewer(TestBuilder::new())
+            .endpoint("/admin/api/auth/users/viewer")
+            .method(Method::Delete)
+            .file(PiholeFile::ApiUsers, VIEWER)
+            .expect_status(Status::Forbidden)
+            .expect_json(json!({
+                "error": {
+                    "key": "forbidden",
+                    "message": "Forbidden",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Viewers can not see the other accounts
+    #[test]
+    fn viewer_not_admin() {
+        viewer(TestBuilder::new())
+            .endpoint("/admin/api/auth/users")
+            .file(PiholeFile::ApiUsers, VIEWER)
+            .expect_status(Status::Forbidden)
+            .expect_json(json!({
+                "error": {
+                    "key": "forbidden",
+                    "message": "Forbidden",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// Empty path segments do not hide admin routes from the permissions
+    #[test]
+    fn viewer_doubled_slashes() {
+        for endpoint in &[
+            "//admin/api/auth/users",
+            "//admin/api/auth/keys",
+            "/admin/api//auth/users",
+            "/admin/api//settings/teleporter"
+        ] {
+            viewer(TestBuilder::new())
+                .endpoint(endpoint)
+                .file(PiholeFile::ApiUsers, VIEWER)
+                .expect_status(Status::Forbidden)
+                .expect_json(json!({
+                    "error": {
+                        "key": "forbidden",
+                        "message": "Forbidden",
+                        "data": null
+                    }
+                }))
+                .test();
+        }
+    }
+
+    /// Viewers can change their own preferences
+    #[test]
+    fn put_preferences() {
+        viewer(TestBuilder::new())
+            .endpoint("/admin/api/auth/preferences")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::ApiUsers,
+                VIEWER,
+                &VIEWER.replace("{}", "{\"layout\":[\"queries\",\"clients\"]}")
+            )
+            .body(json!({ "layout": ["queries", "clients"] }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Account names are unique
+    #[test]
+    fn add_duplicate_user() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/users")
+            .method(Method::Post)
+            .file(PiholeFile::ApiUsers, VIEWER)
+            .body(json!({ "name": "viewer", "password": "password", "role": "admin" }))
+            .expect_status(Status::Conflict)
+            .expect_json(json!({
+                "error": {
+                    "key": "already_exists",
+                    "message": "Item already exists",
+                    "data": null
+                }
+            }))
+            .test();
+    }
+
+    /// The role of an account can be changed
+    #[test]
+    fn update_role() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/users/viewer")
+            .method(Method::Put)
+            .file_expect(
+                PiholeFile::ApiUsers,
+                VIEWER,
+                &VIEWER.replace("\"role\":\"viewer\"", "\"role\":\"admin\"")
+            )
+            .body(json!({ "role": "admin" }))
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+
+    /// Deleted accounts are removed from the file
+    #[test]
+    fn delete_user() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth/users/viewer")
+            .method(Method::Delete)
+            .file_expect(PiholeFile::ApiUsers, VIEWER, "[]\n")
+            .expect_json(json!({ "status": "success" }))
+            .test();
+    }
+}
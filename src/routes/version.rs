@@ -46,14 +46,13 @@ fn read_api_version() -> Version {
 /// Read FTL version information from FTL's API
 fn read_ftl_version(ftl: &FtlConnectionType) -> Result<Version, Error> {
     let mut con = ftl.connect("version")?;
-    let mut str_buffer = [0u8; 4096];
 
     // Ignore the version and date strings
-    let _hash_tag = con.read_str(&mut str_buffer)?.to_owned();
-    let tag = con.read_str(&mut str_buffer)?.to_owned();
-    let branch = con.read_str(&mut str_buffer)?.to_owned();
-    let hash = con.read_str(&mut str_buffer)?.to_owned();
-    let _date = con.read_str(&mut str_buffer)?.to_owned();
+    let _hash_tag = con.read_string()?;
+    let tag = con.read_string()?;
+    let branch = con.read_string()?;
+    let hash = con.read_string()?;
+    let _date = con.read_string()?;
     con.expect_eom()?;
 
     Ok(Version { tag, branch, hash })
@@ -66,8 +66,9 @@ pub fn web_interface_index<'r>() -> Option<Response<'r>> {
     get_file("index.html")
 }
 
-/// Return the requested page/file, if it exists.
-#[get("/admin/<path..>")]
+/// Return the requested page/file, if it exists. This is ranked after the API
+/// routes with dynamic segments, which have matching paths.
+#[get("/admin/<path..>", rank = 10)]
 pub fn web_interface<'r>(path: PathBuf) -> Option<Response<'r>> {
     get_file(&path.display().to_string())
 }
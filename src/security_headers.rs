@@ -0,0 +1,94 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Security Headers
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::env::Config;
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    Request, Response
+};
+
+/// Adds security headers to every response, including the web interface
+/// assets. The headers are configured in the `web` section of the config, and
+/// a header is not sent if its value is empty.
+pub struct SecurityHeaders {
+    headers: Vec<Header<'static>>
+}
+
+impl SecurityHeaders {
+    /// Create the security headers from the config
+    pub fn new(config: &Config) -> SecurityHeaders {
+        let content_type_options = if config.content_type_options() {
+            "nosniff"
+        } else {
+            ""
+        };
+
+        let headers: [(&'static str, &str); 4] = [
+            ("Content-Security-Policy", config.content_security_policy()),
+            ("X-Frame-Options", config.frame_options()),
+            ("X-Content-Type-Options", content_type_options),
+            ("Referrer-Policy", config.referrer_policy())
+        ];
+
+        SecurityHeaders {
+            headers: headers
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|&(name, value)| Header::new(name, value.to_owned()))
+                .collect()
+        }
+    }
+}
+
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security Headers",
+            kind: Kind::Response
+        }
+    }
+
+    fn on_response(&self, _: &Request, response: &mut Response) {
+        for header in &self.headers {
+            // Don't override headers set by the route
+            if !response.headers().contains(header.name()) {
+                response.set_header(header.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::TestBuilder;
+    use rocket::http::Status;
+    use serde_json::Value;
+
+    /// The default security headers are added to responses, including errors
+    #[test]
+    fn default_headers() {
+        TestBuilder::new()
+            .endpoint("/admin/api/auth")
+            .should_auth(false)
+            .expect_status(Status::Unauthorized)
+            .expect_json(json!({
+                "error": {
+                    "key": "unauthorized",
+                    "message": "Unauthorized",
+                    "data": Value::Null
+                }
+            }))
+            .expect_header("X-Frame-Options", "DENY")
+            .expect_header("X-Content-Type-Options", "nosniff")
+            .expect_header("Referrer-Policy", "same-origin")
+            .test();
+    }
+}
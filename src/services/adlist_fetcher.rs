@@ -0,0 +1,603 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Adlist Fetcher
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    services::Adlist,
+    util::{current_time, Error, ErrorKind}
+};
+use failure::ResultExt;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio}
+};
+use tempfile::NamedTempFile;
+
+/// The maximum size of a downloaded adlist, in bytes
+const MAX_LIST_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The maximum time to spend downloading an adlist, in seconds
+const FETCH_TIMEOUT: u64 = 120;
+
+/// The maximum size of a checksum file, in bytes
+const MAX_CHECKSUM_SIZE: u64 = 4096;
+
+/// The exit code of curl when the download is larger than `--max-filesize`
+const CURL_FILE_TOO_LARGE: i32 = 63;
+
+/// The fetch state of an adlist, saved between fetches so that unchanged
+/// lists are not downloaded again
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct FetchState {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The SHA-256 of the downloaded list, used to verify the cached copy
+    pub checksum: Option<String>,
+    /// The size of the downloaded list, in bytes
+    pub size: u64,
+    /// When the list was last fetched, as a Unix timestamp
+    pub last_fetch: Option<u64>,
+    pub status: Option<FetchStatus>,
+    pub last_error: Option<String>,
+    /// The URL of the list's SHA-256 checksum, which downloads must match
+    /// before they are used
+    #[serde(default)]
+    pub checksum_url: Option<String>,
+    /// The result of checking the last download against the checksum URL
+    #[serde(default)]
+    pub verification: Option<Verification>
+}
+
+/// The outcome of fetching an adlist
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum FetchStatus {
+    Updated,
+    NotModified,
+    Failed
+}
+
+/// The result of checking a download against its checksum URL
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    Verified,
+    Mismatch,
+    /// The checksum could not be downloaded or did not contain a checksum
+    Unavailable
+}
+
+/// A successful download. An updated list is kept in a temporary file until
+/// it is verified.
+enum Download {
+    Updated {
+        body: NamedTempFile,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        checksum: String,
+        size: u64
+    },
+    NotModified
+}
+
+impl FetchState {
+    /// Create the state of an adlist which has not been fetched
+    pub fn new(url: &str) -> FetchState {
+        FetchState {
+            url: url.to_owned(),
+            etag: None,
+            last_modified: None,
+            checksum: None,
+            size: 0,
+            last_fetch: None,
+            status: None,
+            last_error: None,
+            checksum_url: None,
+            verification: None
+        }
+    }
+}
+
+/// Read the URLs of the enabled adlists. Disabled adlists are commented out.
+pub fn read_adlists(env: &Env) -> Result<Vec<String>, Error> {
+    Ok(Adlist::read_all(env)?
+        .into_iter()
+        .filter(|adlist| adlist.enabled)
+        .map(|adlist| adlist.url)
+        .collect())
+}
+
+/// Read the checksum URLs of the adlists, by adlist URL. Each line has the
+/// adlist URL and its checksum URL, separated by a space.
+pub fn read_checksum_urls(env: &Env) -> Result<HashMap<String, String>, Error> {
+    if !env.file_exists(PiholeFile::AdlistChecksums) {
+        return Ok(HashMap::new());
+    }
+
+    Ok(env
+        .read_file_lines(PiholeFile::AdlistChecksums)?
+        .iter()
+        .filter_map(|line| {
+            let mut split = line.split_whitespace();
+
+            match (split.next(), split.next()) {
+                (Some(url), Some(checksum_url)) => Some((url.to_owned(), checksum_url.to_owned())),
+                _ => None
+            }
+        })
+        .collect())
+}
+
+/// Set or remove the checksum URL of an adlist
+pub fn write_checksum_url(env: &Env, url: &str, checksum_url: Option<&str>) -> Result<(), Error> {
+    let mut checksum_urls = read_checksum_urls(env)?;
+
+    match checksum_url {
+        Some(checksum_url) => checksum_urls.insert(url.to_owned(), checksum_url.to_owned()),
+        None => checksum_urls.remove(url)
+    };
+
+    let mut lines: Vec<String> = checksum_urls
+        .into_iter()
+        .map(|(url, checksum_url)| format!("{} {}", url, checksum_url))
+        .collect();
+    lines.sort();
+
+    let file_location = env.file_location(PiholeFile::AdlistChecksums).to_owned();
+    let mut file = env.write_file(PiholeFile::AdlistChecksums, false)?;
+
+    for line in lines {
+        writeln!(file, "{}", line).context(ErrorKind::FileWrite(file_location.clone()))?;
+    }
+
+    Ok(())
+}
+
+/// Read the fetch state of the enabled adlists. Adlists which have not been
+/// fetched have an empty state.
+pub fn read_fetch_states(env: &Env) -> Result<Vec<FetchState>, Error> {
+    let saved = read_saved_states(env)?;
+    let checksum_urls = read_checksum_urls(env)?;
+
+    Ok(read_adlists(env)?
+        .iter()
+        .map(|url| {
+            let mut state = saved
+                .iter()
+                .find(|state| state.url == *url)
+                .cloned()
+                .unwrap_or_else(|| FetchState::new(url));
+            state.checksum_url = checksum_urls.get(url).cloned();
+
+            state
+        })
+        .collect())
+}
+
+/// Download the enabled adlists to where Gravity expects its downloaded lists,
+/// so that Gravity can be built with `pihole -g --skip-download`. Lists which
+/// have not changed since the last fetch are not downloaded again. If a list
+/// can not be fetched, the previously downloaded copy is kept and the error is
+/// saved in its fetch state.
+pub fn fetch_adlists(env: &Env) -> Result<(), Error> {
+    let states: Vec<FetchState> = read_fetch_states(env)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, state)| fetch_adlist(&cache_location(env, index, &state.url), state))
+        .collect();
+
+    write_saved_states(env, &states)
+}
+
+/// Fetch the adlist into the cache location and update its state
+fn fetch_adlist(cache: &Path, mut state: FetchState) -> FetchState {
+    // Only make a conditional request if the cached copy is intact and
+    // verified, otherwise an unchanged list would not be downloaded again to
+    // replace or verify it
+    let cached = state.checksum.is_some()
+        && file_checksum(cache).ok() == state.checksum
+        && (state.checksum_url.is_none() || state.verification == Some(Verification::Verified));
+    let conditional = if cached { Some(&state) } else { None };
+
+    let result = download(&state.url, cache, conditional).and_then(|download| match download {
+        Download::Updated { ref checksum, .. } => {
+            state.verification = state
+                .checksum_url
+                .as_ref()
+                .map(|checksum_url| verify_checksum(checksum_url, checksum));
+
+            match state.verification {
+                Some(Verification::Mismatch) => {
+                    Err("The list does not match its checksum".to_owned())
+                }
+                Some(Verification::Unavailable) => {
+                    Err("The checksum of the list could not be downloaded".to_owned())
+                }
+                _ => Ok(download)
+            }
+        }
+        Download::NotModified => Ok(download)
+    });
+
+    match result {
+        Ok(Download::Updated {
+            body,
+            etag,
+            last_modified,
+            checksum,
+            size
+        }) => match body.persist(cache) {
+            Ok(_) => {
+                state.etag = etag;
+                state.last_modified = last_modified;
+                state.checksum = Some(checksum);
+                state.size = size;
+                state.status = Some(FetchStatus::Updated);
+                state.last_error = None;
+            }
+            Err(e) => {
+                state.status = Some(FetchStatus::Failed);
+                state.last_error = Some(e.to_string());
+            }
+        },
+        Ok(Download::NotModified) => {
+            state.status = Some(FetchStatus::NotModified);
+            state.last_error = None;
+        }
+        Err(error) => {
+            state.status = Some(FetchStatus::Failed);
+            state.last_error = Some(error);
+        }
+    }
+
+    state.last_fetch = Some(current_time());
+    state
+}
+
+/// Download the URL into the cache location. If the fetch state of the cached
+/// copy is given, the download is skipped when the list has not been
+/// modified. The error is a message to show the user.
+fn download(url: &str, cache: &Path, conditional: Option<&FetchState>) -> Result<Download, String> {
+    let directory = cache.parent().unwrap_or_else(|| Path::new("."));
+    let body = NamedTempFile::new_in(directory).map_err(|e| e.to_string())?;
+    let headers = NamedTempFile::new().map_err(|e| e.to_string())?;
+
+    let mut command = Command::new("curl");
+    command
+        .args(&["--silent", "--show-error", "--location"])
+        .args(&["--max-time", &FETCH_TIMEOUT.to_string()])
+        .args(&["--max-filesize", &MAX_LIST_SIZE.to_string()])
+        .args(&["--write-out", "%{http_code}"])
+        .arg("--dump-header")
+        .arg(headers.path())
+        .arg("--output")
+        .arg(body.path());
+
+    if let Some(state) = conditional {
+        if let Some(ref etag) = state.etag {
+            command
+                .arg("--header")
+                .arg(format!("If-None-Match: {}", etag));
+        }
+
+        if let Some(ref last_modified) = state.last_modified {
+            command
+                .arg("--header")
+                .arg(format!("If-Modified-Since: {}", last_modified));
+        }
+    }
+
+    let output = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.code() == Some(CURL_FILE_TOO_LARGE) {
+        return Err(format!("The list is larger than {} bytes", MAX_LIST_SIZE));
+    }
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "200" => (),
+        "304" if conditional.is_some() => return Ok(Download::NotModified),
+        code => return Err(format!("HTTP status {}", code))
+    }
+
+    // Servers which do not send the length are not limited by curl
+    let size = body.as_file().metadata().map_err(|e| e.to_string())?.len();
+    if size > MAX_LIST_SIZE {
+        return Err(format!("The list is larger than {} bytes", MAX_LIST_SIZE));
+    }
+
+    let checksum = file_checksum(body.path()).map_err(|e| e.to_string())?;
+    let (etag, last_modified) =
+        parse_headers(&fs::read_to_string(headers.path()).unwrap_or_default());
+
+    Ok(Download::Updated {
+        body,
+        etag,
+        last_modified,
+        checksum,
+        size
+    })
+}
+
+/// Download the checksum and compare it with the checksum of the download
+fn verify_checksum(checksum_url: &str, checksum: &str) -> Verification {
+    let output = Command::new("curl")
+        .args(&["--silent", "--show-error", "--location", "--fail"])
+        .args(&["--max-time", &FETCH_TIMEOUT.to_string()])
+        .args(&["--max-filesize", &MAX_CHECKSUM_SIZE.to_string()])
+        .arg(checksum_url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(ref output) if output.status.success() => {
+            match parse_checksum(&String::from_utf8_lossy(&output.stdout)) {
+                Some(ref expected) if expected == checksum => Verification::Verified,
+                Some(_) => Verification::Mismatch,
+                None => Verification::Unavailable
+            }
+        }
+        _ => Verification::Unavailable
+    }
+}
+
+/// Get the SHA-256 checksum from a checksum file. This is either only the hex
+/// encoded checksum, or the `sha256sum` format with the file name after it.
+fn parse_checksum(contents: &str) -> Option<String> {
+    contents
+        .split_whitespace()
+        .next()
+        .filter(|checksum| checksum.len() == 64 && checksum.bytes().all(|b| b.is_ascii_hexdigit()))
+        .map(|checksum| checksum.to_lowercase())
+}
+
+/// Get the ETag and Last-Modified headers of the final response. Redirects
+/// add a block of headers for each response.
+fn parse_headers(headers: &str) -> (Option<String>, Option<String>) {
+    let mut etag = None;
+    let mut last_modified = None;
+
+    for line in headers.lines() {
+        // A status line starts the headers of the next response
+        if line.starts_with("HTTP/") {
+            etag = None;
+            last_modified = None;
+            continue;
+        }
+
+        let mut split = line.splitn(2, ':');
+        let name = split.next().unwrap_or_default().trim();
+        let value = match split.next() {
+            Some(value) if !value.trim().is_empty() => value.trim().to_owned(),
+            _ => continue
+        };
+
+        if name.eq_ignore_ascii_case("etag") {
+            etag = Some(value);
+        } else if name.eq_ignore_ascii_case("last-modified") {
+            last_modified = Some(value);
+        }
+    }
+
+    (etag, last_modified)
+}
+
+/// Get the location Gravity expects the downloaded adlist to be in. This is
+/// based on the position of the adlist and the host in its URL.
+pub fn cache_location(env: &Env, index: usize, url: &str) -> PathBuf {
+    let host = url.split('/').nth(2).unwrap_or_default();
+
+    Path::new(env.file_location(PiholeFile::Adlists))
+        .with_file_name(format!("list.{}.{}.domains", index, host))
+}
+
+/// Get the hex encoded SHA-256 of the file. The file is streamed into the
+/// hash, because adlists can be large.
+fn file_checksum(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(fs::File::open(path)?), &mut hasher)?;
+
+    Ok(hasher
+        .result()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Read the saved fetch states. If the file does not exist, no adlists have
+/// been fetched.
+fn read_saved_states(env: &Env) -> Result<Vec<FetchState>, Error> {
+    if !env.file_exists(PiholeFile::AdlistStatus) {
+        return Ok(Vec::new());
+    }
+
+    let file_location = env.file_location(PiholeFile::AdlistStatus).to_owned();
+    let mut json = String::new();
+    env.read_file(PiholeFile::AdlistStatus)?
+        .read_to_string(&mut json)
+        .context(ErrorKind::FileRead(file_location.clone()))?;
+
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+}
+
+/// Save the fetch states, replacing the previously saved states
+fn write_saved_states(env: &Env, states: &[FetchState]) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::AdlistStatus).to_owned();
+    let mut file = env.write_file(PiholeFile::AdlistStatus, false)?;
+
+    serde_json::to_writer(&mut file, states)
+        .context(ErrorKind::FileWrite(file_location.clone()))?;
+    writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cache_location, file_checksum, parse_checksum, parse_headers, read_adlists,
+        read_fetch_states
+    };
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+    use std::{fs, path::Path};
+    use tempfile::tempdir;
+
+    /// Create an environment with the adlists and saved fetch states
+    fn env(adlists: &str, states: &str) -> Env {
+        Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::Adlists, adlists)
+                .file(PiholeFile::AdlistStatus, states)
+                .build()
+        )
+    }
+
+    /// Commented out adlists are disabled, and comments are not part of the
+    /// URL
+    #[test]
+    fn enabled_adlists() {
+        let env = env(
+            "https://example.com/hosts # Ads\n#https://example.net/hosts\n\n",
+            ""
+        );
+
+        assert_eq!(
+            read_adlists(&env).unwrap(),
+            vec!["https://example.com/hosts".to_owned()]
+        );
+    }
+
+    /// Saved states are matched to the adlists by URL, and adlists without a
+    /// saved state have an empty state
+    #[test]
+    fn fetch_states() {
+        let env = env(
+            "https://example.com/hosts\nhttps://example.net/hosts\n",
+            "[{\"url\":\"https://example.net/hosts\",\"etag\":\"\\\"abc\\\"\",\
+             \"last_modified\":null,\"checksum\":\"00\",\"size\":2,\
+             \"last_fetch\":100,\"status\":\"not_modified\",\"last_error\":null}]\n"
+        );
+        let states = read_fetch_states(&env).unwrap();
+
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].last_fetch, None);
+        assert_eq!(states[1].etag, Some("\"abc\"".to_owned()));
+    }
+
+    /// Checksum URLs are added to the fetch states of their adlists
+    #[test]
+    fn checksum_urls() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::Adlists,
+                    "https://example.com/hosts\nhttps://example.net/hosts\n"
+                )
+                .file(
+                    PiholeFile::AdlistChecksums,
+                    "https://example.net/hosts https://example.net/hosts.sha256\n"
+                )
+                .build()
+        );
+        let states = read_fetch_states(&env).unwrap();
+
+        assert_eq!(states[0].checksum_url, None);
+        assert_eq!(
+            states[1].checksum_url,
+            Some("https://example.net/hosts.sha256".to_owned())
+        );
+    }
+
+    /// Checksums are read alone or in the sha256sum format
+    #[test]
+    fn checksum_formats() {
+        let checksum = "391196688aa55d3321deffa736f8d103b4813470952b748e9c2c9deb17fa60f5";
+
+        assert_eq!(
+            parse_checksum(&format!("{}\n", checksum)),
+            Some(checksum.to_owned())
+        );
+        assert_eq!(
+            parse_checksum(&format!("{}  hosts\n", checksum.to_uppercase())),
+            Some(checksum.to_owned())
+        );
+        assert_eq!(parse_checksum("<html>Not Found</html>"), None);
+        assert_eq!(parse_checksum(""), None);
+    }
+
+    /// The caching headers are taken from the final response after redirects
+    #[test]
+    fn headers_after_redirect() {
+        let headers = "HTTP/1.1 301 Moved Permanently\r\n\
+                       Location: https://example.net/hosts\r\n\
+                       ETag: \"old\"\r\n\r\n\
+                       HTTP/1.1 200 OK\r\n\
+                       etag: \"abc\"\r\n\
+                       Last-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\n\r\n";
+
+        assert_eq!(
+            parse_headers(headers),
+            (
+                Some("\"abc\"".to_owned()),
+                Some("Wed, 21 Oct 2015 07:28:00 GMT".to_owned())
+            )
+        );
+    }
+
+    /// Lists are downloaded next to the adlists file, named like Gravity's
+    /// downloads
+    #[test]
+    fn cache_name() {
+        let env = env("", "");
+
+        assert_eq!(
+            cache_location(&env, 2, "https://example.com:8080/hosts.txt"),
+            Path::new("/etc/pihole/list.2.example.com:8080.domains")
+        );
+    }
+
+    /// The checksum is the hex encoded SHA-256 of the file
+    #[test]
+    fn checksum() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("list");
+        fs::write(&path, "example.com\n").unwrap();
+
+        assert_eq!(
+            file_checksum(&path).unwrap(),
+            "391196688aa55d3321deffa736f8d103b4813470952b748e9c2c9deb17fa60f5"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Adlist Subscriptions
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    settings::ValueType,
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::io::{BufWriter, Write};
+
+/// An adlist which Gravity downloads blocked domains from. Each line of the
+/// adlists file has the URL, followed by an optional `# comment`. Disabled
+/// adlists are commented out.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Adlist {
+    pub url: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub comment: String
+}
+
+/// Adlists are enabled when they are added, unless it is specified otherwise
+fn default_enabled() -> bool {
+    true
+}
+
+impl Adlist {
+    /// Read the enabled and disabled adlists
+    pub fn read_all(env: &Env) -> Result<Vec<Adlist>, Error> {
+        Ok(Adlist::read_lines(env)?
+            .iter()
+            .filter_map(|line| Adlist::parse(line))
+            .collect())
+    }
+
+    /// Add the adlist to the end of the adlists file
+    pub fn add(&self, env: &Env) -> Result<(), Error> {
+        if !self.is_valid() {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+
+        if Adlist::read_all(env)?
+            .iter()
+            .any(|adlist| adlist.url == self.url)
+        {
+            return Err(Error::from(ErrorKind::AlreadyExists));
+        }
+
+        let mut lines = Adlist::read_lines(env)?;
+        lines.push(self.line());
+
+        Adlist::write_lines(env, &lines)
+    }
+
+    /// Replace the adlist with the same URL, such as to enable or disable it
+    pub fn update(&self, env: &Env) -> Result<(), Error> {
+        if !self.is_valid() {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+
+        Adlist::replace(env, &self.url, Some(self.line()))
+    }
+
+    /// Remove the adlist with the URL
+    pub fn remove(env: &Env, url: &str) -> Result<(), Error> {
+        Adlist::replace(env, url, None)
+    }
+
+    /// Check if the URL and comment are valid. The comment must be on one
+    /// line.
+    pub fn is_valid(&self) -> bool {
+        ValueType::Url.is_valid(&self.url) && !self.comment.chars().any(char::is_control)
+    }
+
+    /// Replace the line of the adlist with the URL, or remove it. Other lines,
+    /// such as comments, are kept.
+    fn replace(env: &Env, url: &str, line: Option<String>) -> Result<(), Error> {
+        let mut lines = Adlist::read_lines(env)?;
+        let index = lines
+            .iter()
+            .position(|line| Adlist::parse(line).map_or(false, |adlist| adlist.url == url))
+            .ok_or(ErrorKind::NotFound)?;
+
+        match line {
+            Some(line) => lines[index] = line,
+            None => {
+                lines.remove(index);
+            }
+        }
+
+        Adlist::write_lines(env, &lines)
+    }
+
+    /// Get the line of the adlist in the adlists file
+    fn line(&self) -> String {
+        let mut line = if self.enabled {
+            self.url.clone()
+        } else {
+            format!("#{}", self.url)
+        };
+
+        if !self.comment.is_empty() {
+            line.push_str(" # ");
+            line.push_str(self.comment.trim());
+        }
+
+        line
+    }
+
+    /// Parse a line of the adlists file. Commented out lines are disabled
+    /// adlists if they start with a URL, and comments otherwise.
+    pub(crate) fn parse(line: &str) -> Option<Adlist> {
+        let line = line.trim();
+        let enabled = !line.starts_with('#');
+        let mut parts = line
+            .trim_start_matches('#')
+            .trim_start()
+            .splitn(2, char::is_whitespace);
+
+        let url = parts.next().filter(|url| !url.is_empty())?;
+
+        if !enabled && !ValueType::Url.is_valid(url) {
+            return None;
+        }
+
+        let comment = parts
+            .next()
+            .map(|comment| comment.trim().trim_start_matches('#').trim())
+            .unwrap_or("");
+
+        Some(Adlist {
+            url: url.to_owned(),
+            enabled,
+            comment: comment.to_owned()
+        })
+    }
+
+    /// Read every line of the adlists file
+    fn read_lines(env: &Env) -> Result<Vec<String>, Error> {
+        if !env.file_exists(PiholeFile::Adlists) {
+            return Ok(Vec::new());
+        }
+
+        env.read_file_lines(PiholeFile::Adlists)
+    }
+
+    /// Replace the lines of the adlists file
+    fn write_lines(env: &Env, lines: &[String]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::Adlists).to_owned();
+        let mut writer = BufWriter::new(env.write_file(PiholeFile::Adlists, false)?);
+
+        for line in lines {
+            writeln!(writer, "{}", line).context(ErrorKind::FileWrite(file_location.clone()))?;
+        }
+
+        writer
+            .flush()
+            .context(ErrorKind::FileWrite(file_location))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Adlist;
+
+    /// Create an adlist
+    fn adlist(url: &str, enabled: bool, comment: &str) -> Adlist {
+        Adlist {
+            url: url.to_owned(),
+            enabled,
+            comment: comment.to_owned()
+        }
+    }
+
+    /// Adlists may be disabled and have a comment, and other commented out
+    /// lines are not adlists
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Adlist::parse("https://example.com/hosts"),
+            Some(adlist("https://example.com/hosts", true, ""))
+        );
+        assert_eq!(
+            Adlist::parse("#https://example.com/hosts # Ads and trackers"),
+            Some(adlist(
+                "https://example.com/hosts",
+                false,
+                "Ads and trackers"
+            ))
+        );
+        assert_eq!(Adlist::parse("# Lists from example.com"), None);
+        assert_eq!(Adlist::parse(""), None);
+    }
+
+    /// Lines are written in the format they are parsed from
+    #[test]
+    fn line() {
+        let adlist = adlist("https://example.com/hosts", false, "Ads");
+
+        assert_eq!(adlist.line(), "#https://example.com/hosts # Ads");
+        assert_eq!(Adlist::parse(&adlist.line()), Some(adlist));
+    }
+}
@@ -0,0 +1,449 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Data Export Jobs
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::{network, queries},
+    env::Env,
+    routes::stats::{get_hidden_domain, PrivacyPolicy},
+    services::JobState,
+    settings::{ConfigEntry, FtlConfEntry},
+    util::{current_time, Error, ErrorKind}
+};
+use diesel::{prelude::*, SqliteConnection};
+use failure::ResultExt;
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread
+};
+use tar::{Builder, Header};
+
+/// Exports everything stored about a client as a gzipped tar archive, in the
+/// background. A client with a long history has many queries in the
+/// database, so the export is not built during the request. Only one export
+/// runs at a time, and the archive of the last export is kept in memory until
+/// the next one starts.
+#[derive(Clone, Default)]
+pub struct ClientExportJob {
+    data: Arc<Mutex<ClientExportData>>
+}
+
+#[derive(Default)]
+struct ClientExportData {
+    status: ClientExportStatus,
+    archive: Option<Vec<u8>>
+}
+
+/// The status of the current or last export
+#[derive(Serialize, Clone, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientExportStatus {
+    pub state: JobState,
+    /// The IP address of the exported client
+    pub client: Option<String>,
+    /// When the export started, as a Unix timestamp
+    pub started: Option<u64>,
+    /// When the export finished, as a Unix timestamp
+    pub finished: Option<u64>
+}
+
+/// The data stored about a client, except its queries
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientExport {
+    pub client: String,
+    /// When the export was made, as a Unix timestamp
+    pub exported: u64,
+    pub nickname: Option<String>,
+    pub network: Vec<NetworkEntry>
+}
+
+/// An entry of the network table, which FTL keeps for each device
+#[derive(Queryable, Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct NetworkEntry {
+    pub ip: String,
+    pub hwaddr: String,
+    pub interface: String,
+    pub name: Option<String>,
+    pub first_seen: i32,
+    pub last_query: i32,
+    pub num_queries: i32,
+    pub mac_vendor: Option<String>
+}
+
+/// A query in the database. The type and status use FTL's numbers, like the
+/// database history.
+#[derive(Queryable, Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ExportedQuery {
+    pub timestamp: i32,
+    #[serde(rename = "type")]
+    pub query_type: i32,
+    pub status: i32,
+    pub domain: String,
+    pub upstream: Option<String>
+}
+
+impl ClientExportJob {
+    /// Start exporting the client in the background. The nickname is given
+    /// by the caller, since the nicknames are kept in memory. An error is
+    /// returned if an export is already running.
+    pub fn start(
+        &self,
+        env: &Env,
+        client: &str,
+        nickname: Option<String>
+    ) -> Result<ClientExportStatus, Error> {
+        let policy = PrivacyPolicy::read(env)?;
+
+        {
+            let mut data = self.lock();
+
+            if data.status.state == JobState::Running {
+                return Err(Error::from(ErrorKind::ClientExportRunning));
+            }
+
+            *data = ClientExportData {
+                status: ClientExportStatus {
+                    state: JobState::Running,
+                    client: Some(client.to_owned()),
+                    started: Some(current_time()),
+                    finished: None
+                },
+                archive: None
+            };
+        }
+
+        // The test database is only used by the tests which run the export
+        // themselves
+        if !env.is_test() {
+            let job = self.clone();
+            let db_file = FtlConfEntry::DbFile.read(env)?;
+            let client = client.to_owned();
+
+            thread::spawn(move || {
+                let result = SqliteConnection::establish(&db_file)
+                    .context(ErrorKind::FtlDatabase)
+                    .map_err(Error::from)
+                    .and_then(|db| job.run(&db, &policy, &client, nickname));
+
+                if let Err(ref e) = result {
+                    e.print_stacktrace();
+                }
+            });
+        }
+
+        Ok(self.status())
+    }
+
+    /// Get the status of the current or last export
+    pub fn status(&self) -> ClientExportStatus {
+        self.lock().status.clone()
+    }
+
+    /// Get the archive of the last export, if it succeeded
+    pub fn archive(&self) -> Option<(String, Vec<u8>)> {
+        let data = self.lock();
+
+        match (&data.status.client, &data.archive) {
+            (Some(client), Some(archive)) => Some((archive_name(client), archive.clone())),
+            _ => None
+        }
+    }
+
+    /// Get the archive of the last export if it was of the client and
+    /// succeeded
+    pub fn client_archive(&self, client: &str) -> Option<(String, Vec<u8>)> {
+        let data = self.lock();
+
+        match (&data.status.client, &data.archive) {
+            (Some(exported), Some(archive)) if exported == client => {
+                Some((archive_name(client), archive.clone()))
+            }
+            _ => None
+        }
+    }
+
+    /// Get the file name of the last export's archive, if it succeeded,
+    /// without copying the archive
+    pub fn archive_file_name(&self) -> Option<String> {
+        let data = self.lock();
+
+        match (&data.status.client, &data.archive) {
+            (Some(client), Some(_)) => Some(archive_name(client)),
+            _ => None
+        }
+    }
+
+    /// Build the archive of the client and record the outcome
+    fn run(
+        &self,
+        db: &SqliteConnection,
+        policy: &PrivacyPolicy,
+        client: &str,
+        nickname: Option<String>
+    ) -> Result<(), Error> {
+        let result = build_archive(db, policy, client, nickname);
+        let mut data = self.lock();
+
+        data.status.finished = Some(current_time());
+
+        match result {
+            Ok(archive) => {
+                data.status.state = JobState::Success;
+                data.archive = Some(archive);
+                Ok(())
+            }
+            Err(e) => {
+                data.status.state = JobState::Failed;
+                Err(e)
+            }
+        }
+    }
+
+    /// Lock the job data. Ignore the poison error because the data is still
+    /// consistent.
+    fn lock(&self) -> MutexGuard<ClientExportData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Build the gzipped tar archive of the client. It holds `client.json`, with
+/// the nickname and network table entries, and `queries.json`, with the
+/// queries in the database. The privacy level decides what is included, like
+/// it does for the stats.
+fn build_archive(
+    db: &SqliteConnection,
+    policy: &PrivacyPolicy,
+    client: &str,
+    nickname: Option<String>
+) -> Result<Vec<u8>, Error> {
+    let (export, queries) = load_client_data(db, policy, client, nickname)?;
+    let directory = archive_name(client).replace(".tar.gz", "");
+    let now = current_time();
+    let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    for (name, data) in &[
+        (
+            "client.json",
+            serde_json::to_vec_pretty(&export).context(ErrorKind::Unknown)?
+        ),
+        (
+            "queries.json",
+            serde_json::to_vec_pretty(&queries).context(ErrorKind::Unknown)?
+        )
+    ] {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now);
+
+        builder
+            .append_data(
+                &mut header,
+                format!("{}/{}", directory, name),
+                data.as_slice()
+            )
+            .context(ErrorKind::Unknown)?;
+    }
+
+    Ok(builder
+        .into_inner()
+        .context(ErrorKind::Unknown)?
+        .finish()
+        .context(ErrorKind::Unknown)?)
+}
+
+/// Load the data stored about the client. Nothing is loaded if the privacy
+/// level hides clients, queries are left out if it hides queries, and
+/// domains are replaced if it hides domains.
+fn load_client_data(
+    db: &SqliteConnection,
+    policy: &PrivacyPolicy,
+    client: &str,
+    nickname: Option<String>
+) -> Result<(ClientExport, Vec<ExportedQuery>), Error> {
+    let mut export = ClientExport {
+        client: client.to_owned(),
+        exported: current_time(),
+        nickname: None,
+        network: Vec::new()
+    };
+
+    if !policy.shows_clients() {
+        return Ok((export, Vec::new()));
+    }
+
+    export.nickname = nickname;
+    export.network = network::table
+        .select((
+            network::ip,
+            network::hwaddr,
+            network::interface,
+            network::name,
+            network::firstSeen,
+            network::lastQuery,
+            network::numQueries,
+            network::macVendor
+        ))
+        .filter(network::ip.eq(client))
+        .load(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    if !policy.shows_queries() {
+        return Ok((export, Vec::new()));
+    }
+
+    let mut queries: Vec<ExportedQuery> = queries::table
+        .select((
+            queries::timestamp,
+            queries::query_type,
+            queries::status,
+            queries::domain,
+            queries::upstream
+        ))
+        .filter(queries::client.eq(client))
+        .order(queries::timestamp)
+        .load(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    if !policy.shows_domains() {
+        for query in &mut queries {
+            query.domain = get_hidden_domain().to_owned();
+        }
+    }
+
+    Ok((export, queries))
+}
+
+/// Get the file name of the client's archive
+fn archive_name(client: &str) -> String {
+    format!("client-{}.tar.gz", client.replace(':', "-"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{build_archive, load_client_data, ClientExportJob};
+    use crate::{
+        databases::ftl::connect_to_test_db,
+        env::{Config, Env, PiholeFile},
+        routes::stats::PrivacyPolicy,
+        services::JobState,
+        testing::TestEnvBuilder,
+        util::ErrorKind
+    };
+    use flate2::read::GzDecoder;
+    use std::{collections::HashMap, io::Read};
+    use tar::Archive;
+
+    /// Read the policy of the privacy level
+    fn policy(level: &str) -> PrivacyPolicy {
+        let env_builder =
+            TestEnvBuilder::new().file(PiholeFile::FtlConfig, &format!("PRIVACYLEVEL={}", level));
+
+        PrivacyPolicy::read(&Env::Test(Config::default(), env_builder.build())).unwrap()
+    }
+
+    /// The client's network table entries and all of its queries are exported
+    #[test]
+    fn export() {
+        let (export, queries) = load_client_data(
+            &connect_to_test_db(),
+            &policy("0"),
+            "10.1.1.1",
+            Some("Router".to_owned())
+        )
+        .unwrap();
+
+        assert_eq!(export.client, "10.1.1.1");
+        assert_eq!(export.nickname, Some("Router".to_owned()));
+        assert_eq!(export.network.len(), 1);
+        assert_eq!(export.network[0].name, Some("gateway".to_owned()));
+        assert_eq!(queries.len(), 1);
+        assert_ne!(queries[0].domain, "hidden");
+    }
+
+    /// The privacy level hides domains, queries, and then everything about
+    /// the client
+    #[test]
+    fn export_privacy() {
+        let db = connect_to_test_db();
+        let export = |level| {
+            load_client_data(&db, &policy(level), "10.1.1.1", Some("Router".to_owned())).unwrap()
+        };
+
+        let (_, queries) = export("1");
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].domain, "hidden");
+
+        let (client, queries) = export("2");
+        assert_eq!(client.nickname, None);
+        assert!(client.network.is_empty() && queries.is_empty());
+    }
+
+    /// The archive holds the client's data and its queries
+    #[test]
+    fn archive() {
+        let data = build_archive(&connect_to_test_db(), &policy("0"), "10.1.1.1", None).unwrap();
+        let mut archive = Archive::new(GzDecoder::new(data.as_slice()));
+        let mut files = HashMap::new();
+
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            files.insert(
+                entry.path().unwrap().to_string_lossy().into_owned(),
+                contents
+            );
+        }
+
+        let client: serde_json::Value =
+            serde_json::from_str(&files["client-10.1.1.1/client.json"]).unwrap();
+        let queries: serde_json::Value =
+            serde_json::from_str(&files["client-10.1.1.1/queries.json"]).unwrap();
+
+        assert_eq!(client["client"], "10.1.1.1");
+        assert_eq!(queries.as_array().unwrap().len(), 1);
+    }
+
+    /// Only one export can run at a time, and its archive can be downloaded
+    /// after it succeeds
+    #[test]
+    fn one_export_at_a_time() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let job = ClientExportJob::default();
+
+        assert_eq!(job.status().state, JobState::Idle);
+        assert_eq!(
+            job.start(&env, "10.1.1.1", None).unwrap().state,
+            JobState::Running
+        );
+        assert_eq!(
+            job.start(&env, "10.1.1.2", None)
+                .map_err(|e| e.kind())
+                .err(),
+            Some(ErrorKind::ClientExportRunning)
+        );
+        assert!(job.archive().is_none());
+        assert!(job.client_archive("10.1.1.1").is_none());
+
+        job.run(&connect_to_test_db(), &policy("0"), "10.1.1.1", None)
+            .unwrap();
+
+        let status = job.status();
+        assert_eq!(status.state, JobState::Success);
+        assert!(status.finished.is_some());
+        assert_eq!(job.archive().unwrap().0, "client-10.1.1.1.tar.gz");
+        assert!(job.client_archive("10.1.1.1").is_some());
+        assert!(job.client_archive("10.1.1.2").is_none());
+    }
+}
@@ -0,0 +1,446 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Builder
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::dns::{reload_dns, List},
+    services::{cache_location, read_adlists, read_threat_categories},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant
+};
+
+/// Entries in hosts files which are not blocked domains
+const IGNORED_DOMAINS: [&str; 4] = [
+    "localhost.localdomain",
+    "ip6-localhost.localdomain",
+    "broadcasthost.localdomain",
+    "local.localdomain"
+];
+
+/// Builds the Gravity list from the lists downloaded by the adlist fetcher and
+/// the threat feed, without running `pihole -g`. The domains are validated
+/// and deduplicated, and whitelisted domains are removed. The progress of the
+/// build can be read while it runs.
+#[derive(Clone, Default)]
+pub struct GravityBuilder {
+    data: Arc<Mutex<BuildProgress>>
+}
+
+/// The progress of the current or last build
+#[derive(Serialize, Clone, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct BuildProgress {
+    pub stage: BuildStage,
+    pub lists_read: usize,
+    pub lists_total: usize,
+    /// The number of unique domains found so far
+    pub domains: usize,
+    /// The number of entries skipped because they are not valid domains
+    pub invalid: usize,
+    /// The number of domains added to the Gravity list
+    pub added: usize,
+    /// The number of domains removed from the Gravity list
+    pub removed: usize,
+    /// How long the last finished build took, in milliseconds
+    pub duration: Option<u64>
+}
+
+/// The stage of a build
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[serde(rename_all = "snake_case")]
+pub enum BuildStage {
+    /// No build has run yet
+    Idle,
+    ReadingLists,
+    ComparingGravity,
+    WritingGravity,
+    ReloadingDns,
+    Complete,
+    Failed
+}
+
+impl Default for BuildStage {
+    fn default() -> Self {
+        BuildStage::Idle
+    }
+}
+
+impl GravityBuilder {
+    /// Get the progress of the current or last build
+    pub fn progress(&self) -> BuildProgress {
+        self.lock().clone()
+    }
+
+    /// Build the Gravity list and reload the DNS server
+    pub fn build(&self, env: &Env) -> Result<(), Error> {
+        let start = Instant::now();
+        let result = self.build_gravity(env);
+
+        let elapsed = start.elapsed();
+        let mut progress = self.lock();
+        progress.stage = if result.is_ok() {
+            BuildStage::Complete
+        } else {
+            BuildStage::Failed
+        };
+        progress.duration = Some(elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()));
+
+        result
+    }
+
+    /// Read the downloaded lists, write the Gravity list, and reload the DNS
+    /// server, updating the progress after each step. If the domains have not
+    /// changed, the Gravity list is not written and the DNS server is not
+    /// reloaded, which avoids needless writes to SD cards.
+    fn build_gravity(&self, env: &Env) -> Result<(), Error> {
+        let adlists = read_adlists(env)?;
+
+        *self.lock() = BuildProgress {
+            stage: BuildStage::ReadingLists,
+            lists_total: adlists.len(),
+            ..BuildProgress::default()
+        };
+
+        let mut domains = GravityDomains::default();
+
+        for (index, url) in adlists.iter().enumerate() {
+            // Lists which have never been downloaded are skipped
+            if let Ok(file) = File::open(cache_location(env, index, url)) {
+                domains.read_list(BufReader::new(file));
+            }
+
+            let mut progress = self.lock();
+            progress.lists_read += 1;
+            progress.domains = domains.domains.len();
+            progress.invalid = domains.invalid;
+        }
+
+        // The domains of the threat feed are blocked along with the adlists
+        domains.domains.extend(
+            read_threat_categories(env)?
+                .into_iter()
+                .map(|(domain, _)| domain)
+        );
+
+        let domains = domains.into_sorted(&List::White.get(env)?);
+
+        {
+            let mut progress = self.lock();
+            progress.stage = BuildStage::ComparingGravity;
+            progress.domains = domains.len();
+        }
+
+        let (added, removed) = gravity_delta(&read_gravity(env)?, &domains);
+
+        {
+            let mut progress = self.lock();
+            progress.added = added;
+            progress.removed = removed;
+
+            if added == 0 && removed == 0 {
+                return Ok(());
+            }
+
+            progress.stage = BuildStage::WritingGravity;
+        }
+
+        write_gravity(env, &domains)?;
+
+        self.lock().stage = BuildStage::ReloadingDns;
+        reload_dns(env)
+    }
+
+    /// Lock the build progress. Ignore the poison error because the data is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<BuildProgress> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The unique domains read from the lists
+#[derive(Default)]
+struct GravityDomains {
+    domains: HashSet<String>,
+    invalid: usize
+}
+
+impl GravityDomains {
+    /// Add the domains of a list in hosts file format or with one domain per
+    /// line. Invalid lines are counted and skipped.
+    fn read_list<R: BufRead>(&mut self, mut reader: R) {
+        let mut line = Vec::new();
+
+        // Lists are not always valid UTF-8, so read bytes and skip the
+        // invalid parts instead of failing
+        while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+            for domain in line_domains(&String::from_utf8_lossy(&line)) {
+                let domain = domain.to_lowercase();
+
+                if !is_gravity_domain(&domain) {
+                    self.invalid += 1;
+                } else if !IGNORED_DOMAINS.contains(&domain.as_str()) {
+                    self.domains.insert(domain);
+                }
+            }
+
+            line.clear();
+        }
+    }
+
+    /// Remove the whitelisted domains and sort the rest
+    fn into_sorted(self, whitelist: &[String]) -> Vec<String> {
+        let whitelist: HashSet<&str> = whitelist.iter().map(String::as_str).collect();
+        let mut domains: Vec<String> = self
+            .domains
+            .into_iter()
+            .filter(|domain| !whitelist.contains(domain.as_str()))
+            .collect();
+
+        domains.sort_unstable();
+        domains
+    }
+}
+
+/// Get the adlists whose downloaded list has the domain, like `pihole -q`.
+/// Lists which have never been downloaded are skipped.
+pub fn adlists_containing(env: &Env, domain: &str) -> Result<Vec<String>, Error> {
+    let domain = domain.to_lowercase();
+    let mut matches = Vec::new();
+
+    for (index, url) in read_adlists(env)?.into_iter().enumerate() {
+        let file = match File::open(cache_location(env, index, &url)) {
+            Ok(file) => file,
+            Err(_) => continue
+        };
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+
+        while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+            if line_domains(&String::from_utf8_lossy(&line))
+                .any(|entry| entry.eq_ignore_ascii_case(&domain))
+            {
+                matches.push(url);
+                break;
+            }
+
+            line.clear();
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Check if the domain is in the current Gravity list
+pub fn gravity_contains(env: &Env, domain: &str) -> Result<bool, Error> {
+    let domain = domain.to_lowercase();
+
+    Ok(read_gravity(env)?.iter().any(|entry| *entry == domain))
+}
+
+/// Get the domains on a list line. Comments are removed, and in hosts file
+/// format the address before the domains is skipped.
+fn line_domains(line: &str) -> impl Iterator<Item = &str> {
+    let mut fields = line
+        .split('#')
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .peekable();
+
+    if fields
+        .peek()
+        .map_or(false, |field| field.parse::<IpAddr>().is_ok())
+    {
+        fields.next();
+    }
+
+    fields
+}
+
+/// Check if the lowercase domain can be blocked. This follows the rules of
+/// `is_valid_domain`, but without regexes because lists can have millions of
+/// domains. The domain must also have more than one label, so that entries
+/// such as `localhost` are skipped.
+pub(crate) fn is_gravity_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.bytes().any(|byte| byte.is_ascii_alphanumeric())
+                && label.bytes().all(|byte| {
+                    byte.is_ascii_lowercase()
+                        || byte.is_ascii_digit()
+                        || byte == b'-'
+                        || byte == b'_'
+                })
+        })
+}
+
+/// Read the domains of the current Gravity list. If there is no Gravity list,
+/// it is empty.
+fn read_gravity(env: &Env) -> Result<Vec<String>, Error> {
+    match env.read_file_lines(PiholeFile::Gravity) {
+        Ok(domains) => Ok(domains
+            .into_iter()
+            .filter(|domain| !domain.is_empty())
+            .collect()),
+        Err(e) => {
+            if e.kind() == ErrorKind::NotFound {
+                Ok(Vec::new())
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Compare the current Gravity list with the new unique domains, returning
+/// the number of added and removed domains
+fn gravity_delta(current: &[String], domains: &[String]) -> (usize, usize) {
+    let current: HashSet<&str> = current.iter().map(String::as_str).collect();
+    let added = domains
+        .iter()
+        .filter(|domain| !current.contains(domain.as_str()))
+        .count();
+    let kept = domains.len() - added;
+
+    (added, current.len() - kept)
+}
+
+/// Replace the Gravity list with the domains
+fn write_gravity(env: &Env, domains: &[String]) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::Gravity).to_owned();
+    let mut writer = BufWriter::new(env.write_file(PiholeFile::Gravity, false)?);
+
+    for domain in domains {
+        writeln!(writer, "{}", domain).context(ErrorKind::FileWrite(file_location.clone()))?;
+    }
+
+    writer
+        .flush()
+        .context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{gravity_delta, is_gravity_domain, line_domains, write_gravity, GravityDomains};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// Domains are read from hosts file entries and plain lines
+    #[test]
+    fn domains_on_line() {
+        assert_eq!(
+            line_domains("0.0.0.0 ads.example.com tracker.example.com # ads").collect::<Vec<_>>(),
+            vec!["ads.example.com", "tracker.example.com"]
+        );
+        assert_eq!(
+            line_domains("::1 ads.example.com").collect::<Vec<_>>(),
+            vec!["ads.example.com"]
+        );
+        assert_eq!(
+            line_domains("ads.example.com\r\n").collect::<Vec<_>>(),
+            vec!["ads.example.com"]
+        );
+        assert!(line_domains("# comment").next().is_none());
+    }
+
+    /// Domains must have valid labels and more than one label
+    #[test]
+    fn valid_domains() {
+        assert!(is_gravity_domain("ads.example.com"));
+        assert!(is_gravity_domain("_dmarc.example-1.com"));
+        assert!(!is_gravity_domain("localhost"));
+        assert!(!is_gravity_domain("ads..example.com"));
+        assert!(!is_gravity_domain("ads.exa mple.com"));
+        assert!(!is_gravity_domain("ads.example.com/path"));
+        assert!(!is_gravity_domain(&format!("{}.com", "a".repeat(64))));
+    }
+
+    /// Domains are deduplicated across lists, lowercased, and whitelisted
+    /// domains are removed
+    #[test]
+    fn dedupe_and_whitelist() {
+        let mut domains = GravityDomains::default();
+        domains.read_list(
+            "0.0.0.0 B.example.com\n127.0.0.1 localhost\n0.0.0.0 a.example.com\n".as_bytes()
+        );
+        domains.read_list(
+            "a.example.com\nc.example.com\nnot/valid\nlocalhost.localdomain\n".as_bytes()
+        );
+
+        assert_eq!(domains.invalid, 2);
+        assert_eq!(
+            domains.into_sorted(&["c.example.com".to_owned()]),
+            vec!["a.example.com".to_owned(), "b.example.com".to_owned()]
+        );
+    }
+
+    /// The delta counts the added and removed domains. Duplicates in the
+    /// current list are only counted once.
+    #[test]
+    fn delta() {
+        let current = vec![
+            "a.example.com".to_owned(),
+            "b.example.com".to_owned(),
+            "b.example.com".to_owned(),
+        ];
+
+        assert_eq!(
+            gravity_delta(
+                &current,
+                &["b.example.com".to_owned(), "c.example.com".to_owned()]
+            ),
+            (1, 1)
+        );
+        assert_eq!(
+            gravity_delta(
+                &current,
+                &["a.example.com".to_owned(), "b.example.com".to_owned()]
+            ),
+            (0, 0)
+        );
+    }
+
+    /// The Gravity list is replaced with one domain per line
+    #[test]
+    fn write_list() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::Gravity,
+            "old.example.com\n",
+            "a.example.com\nb.example.com\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        write_gravity(
+            &env,
+            &["a.example.com".to_owned(), "b.example.com".to_owned()]
+        )
+        .unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+}
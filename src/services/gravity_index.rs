@@ -0,0 +1,175 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Lookup Index
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader},
+    sync::{Arc, Mutex},
+    time::SystemTime
+};
+
+/// An index of the Gravity list, so that lookups do not read the whole list.
+/// The index is rebuilt when the Gravity list changes on disk, whether it was
+/// written by the API or by `pihole -g`. Clones share the index.
+#[derive(Clone, Default)]
+pub struct GravityIndex {
+    index: Arc<Mutex<Option<Arc<IndexedGravity>>>>
+}
+
+/// The domains of the Gravity list, and the version of the list they were
+/// read from
+pub struct IndexedGravity {
+    version: FileVersion,
+    domains: HashSet<String>
+}
+
+/// Identifies a version of the Gravity list by its modification time and size
+#[derive(PartialEq)]
+struct FileVersion {
+    modified: Option<SystemTime>,
+    len: u64
+}
+
+impl GravityIndex {
+    /// Find the Gravity entry which blocks the domain. The domain itself is
+    /// checked first, then its parent domains, so the most specific entry is
+    /// returned.
+    pub fn find(&self, env: &Env, domain: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .current(env)?
+            .find(&domain.to_lowercase())
+            .map(str::to_owned))
+    }
+
+    /// Get the index of the current Gravity list, rebuilding it if the list
+    /// has changed since it was indexed. Use this to look up many domains
+    /// without checking the list for each one.
+    pub fn current(&self, env: &Env) -> Result<Arc<IndexedGravity>, Error> {
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+
+        // If the file does not exist, then nothing is blocked by Gravity
+        if !env.file_exists(PiholeFile::Gravity) {
+            let empty = Arc::new(IndexedGravity {
+                version: FileVersion {
+                    modified: None,
+                    len: 0
+                },
+                domains: HashSet::new()
+            });
+            *index = Some(Arc::clone(&empty));
+            return Ok(empty);
+        }
+
+        let file = env.read_file(PiholeFile::Gravity)?;
+        let metadata = file.metadata().context(ErrorKind::Unknown)?;
+        let version = FileVersion {
+            modified: metadata.modified().ok(),
+            len: metadata.len()
+        };
+
+        if let Some(ref indexed) = *index {
+            if indexed.version == version {
+                return Ok(Arc::clone(indexed));
+            }
+        }
+
+        let domains = BufReader::new(file)
+            .lines()
+            .filter_map(Result::ok)
+            .map(|line| line.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+        let indexed = Arc::new(IndexedGravity { version, domains });
+        *index = Some(Arc::clone(&indexed));
+
+        Ok(indexed)
+    }
+}
+
+impl IndexedGravity {
+    /// Find the Gravity entry which is the lowercase domain or one of its
+    /// parent domains, preferring the most specific entry
+    pub fn find<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        parent_domains(domain).find(|candidate| self.domains.contains(*candidate))
+    }
+
+    /// Check if the lowercase domain itself is in Gravity
+    pub fn contains(&self, domain: &str) -> bool {
+        self.domains.contains(domain)
+    }
+}
+
+/// Get the domain and each of its parent domains, from the most specific to
+/// the least. The top level domain is not included, because it is never a
+/// Gravity entry.
+fn parent_domains(domain: &str) -> impl Iterator<Item = &str> {
+    let labels = domain.split('.').count();
+
+    Some(domain).into_iter().chain(
+        domain
+            .match_indices('.')
+            .map(move |(position, _)| &domain[position + 1..])
+            .take(labels.saturating_sub(2))
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parent_domains, GravityIndex};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// The domain is followed by its parents, without the top level domain
+    #[test]
+    fn parents() {
+        assert_eq!(
+            parent_domains("a.b.example.com").collect::<Vec<_>>(),
+            vec!["a.b.example.com", "b.example.com", "example.com"]
+        );
+        assert_eq!(
+            parent_domains("example.com").collect::<Vec<_>>(),
+            vec!["example.com"]
+        );
+        assert_eq!(
+            parent_domains("localhost").collect::<Vec<_>>(),
+            vec!["localhost"]
+        );
+    }
+
+    /// Domains are found by exact match or by a parent domain in Gravity
+    #[test]
+    fn find() {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::Gravity, "example.com\nads.example.net\n")
+                .build()
+        );
+        let index = GravityIndex::default();
+
+        assert_eq!(
+            index.find(&env, "example.com").unwrap(),
+            Some("example.com".to_owned())
+        );
+        assert_eq!(
+            index.find(&env, "Tracker.Example.com").unwrap(),
+            Some("example.com".to_owned())
+        );
+        assert_eq!(index.find(&env, "example.net").unwrap(), None);
+        assert_eq!(index.find(&env, "pi-hole.net").unwrap(), None);
+    }
+}
@@ -0,0 +1,190 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Gravity Update Jobs
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    util::{current_time, Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, MutexGuard},
+    thread
+};
+
+/// Runs full Gravity updates (`pihole -g`) requested through the API, in the
+/// background. The job is also the slot which scheduled updates run in, so
+/// only one update runs at a time, whatever started it. The output of
+/// `pihole -g` is followed so the progress of the update can be reported.
+#[derive(Clone, Default)]
+pub struct GravityJob {
+    data: Arc<Mutex<JobStatus>>
+}
+
+/// The status of the current or last update
+#[derive(Serialize, Clone, Default)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct JobStatus {
+    pub state: JobState,
+    /// When the update started, as a Unix timestamp
+    pub started: Option<u64>,
+    /// When the update finished, as a Unix timestamp
+    pub finished: Option<u64>,
+    /// The last line of output from `pihole -g`
+    pub progress: Option<String>
+}
+
+/// The state of an update
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// No update has run yet
+    Idle,
+    Running,
+    Success,
+    Failed
+}
+
+impl Default for JobState {
+    fn default() -> Self {
+        JobState::Idle
+    }
+}
+
+impl GravityJob {
+    /// Start an update in the background. An error is returned if an update
+    /// is already running.
+    pub fn start(&self, env: &Env) -> Result<JobStatus, Error> {
+        self.begin()?;
+
+        // Don't actually run Gravity during testing
+        if !env.is_test() {
+            let job = self.clone();
+
+            thread::spawn(move || {
+                let result = job.run();
+
+                if let Err(ref e) = result {
+                    e.print_stacktrace();
+                }
+
+                job.finish(result.is_ok());
+            });
+        }
+
+        Ok(self.status())
+    }
+
+    /// Mark an update as running. An error is returned if an update is
+    /// already running. The caller runs the update and must call `finish`
+    /// when it is done.
+    pub fn begin(&self) -> Result<(), Error> {
+        let mut data = self.lock();
+
+        if data.state == JobState::Running {
+            return Err(Error::from(ErrorKind::GravityRunning));
+        }
+
+        *data = JobStatus {
+            state: JobState::Running,
+            started: Some(current_time()),
+            finished: None,
+            progress: None
+        };
+
+        Ok(())
+    }
+
+    /// Get the status of the current or last update
+    pub fn status(&self) -> JobStatus {
+        self.lock().clone()
+    }
+
+    /// Run `pihole -g`, recording each line of its output as the progress
+    fn run(&self) -> Result<(), Error> {
+        let mut child = Command::new("sudo")
+            .arg("pihole")
+            .arg("-g")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context(ErrorKind::GravityError)?;
+
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines() {
+                let line = line.context(ErrorKind::GravityError)?;
+                let line = line.trim();
+
+                if !line.is_empty() {
+                    self.lock().progress = Some(line.to_owned());
+                }
+            }
+        }
+
+        if child.wait().context(ErrorKind::GravityError)?.success() {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::GravityError))
+        }
+    }
+
+    /// Record the outcome of the update
+    pub fn finish(&self, success: bool) {
+        let mut data = self.lock();
+
+        data.state = if success {
+            JobState::Success
+        } else {
+            JobState::Failed
+        };
+        data.finished = Some(current_time());
+    }
+
+    /// Lock the job status. Ignore the poison error because the status is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<JobStatus> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GravityJob, JobState};
+    use crate::{
+        env::{Config, Env},
+        util::ErrorKind
+    };
+    use std::collections::HashMap;
+
+    /// Only one update can run at a time, and another can start after it
+    /// finishes
+    #[test]
+    fn one_update_at_a_time() {
+        let env = Env::Test(Config::default(), HashMap::new());
+        let job = GravityJob::default();
+
+        assert_eq!(job.status().state, JobState::Idle);
+        assert_eq!(job.start(&env).unwrap().state, JobState::Running);
+        assert_eq!(
+            job.start(&env).map_err(|e| e.kind()).err(),
+            Some(ErrorKind::GravityRunning)
+        );
+
+        job.finish(false);
+
+        let status = job.status();
+        assert_eq!(status.state, JobState::Failed);
+        assert!(status.finished.is_some());
+        assert_eq!(job.start(&env).unwrap().finished, None);
+    }
+}
@@ -0,0 +1,282 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// API Request Log
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Config, ProxyAuth},
+    rate_limit::client_ip,
+    util::current_time
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Data, Request, Response
+};
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::Write,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant
+};
+
+/// Only requests to the API are logged, not the web interface
+const API_PATH: &str = "/admin/api";
+
+/// How much of the API's requests are logged. Each level includes the
+/// levels before it.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LogLevel {
+    None,
+    /// Requests which failed with a server error
+    Error,
+    /// Requests which failed with a client error
+    Warning,
+    /// All requests
+    Info,
+    /// All requests, including their query strings
+    Debug
+}
+
+impl LogLevel {
+    /// Get the level from its name in the config file
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name {
+            "none" => Some(LogLevel::None),
+            "error" => Some(LogLevel::Error),
+            "warning" => Some(LogLevel::Warning),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None
+        }
+    }
+
+    /// Get the name of the level
+    pub fn get_name(self) -> &'static str {
+        match self {
+            LogLevel::None => "none",
+            LogLevel::Error => "error",
+            LogLevel::Warning => "warning",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug"
+        }
+    }
+
+    /// Get the level a request is logged at, from its status code
+    fn for_status(status: u16) -> LogLevel {
+        match status {
+            500..=599 => LogLevel::Error,
+            400..=499 => LogLevel::Warning,
+            _ => LogLevel::Info
+        }
+    }
+}
+
+/// A request which the API handled
+#[derive(Serialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct RequestLogEntry {
+    pub timestamp: u64,
+    pub level: &'static str,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// In microseconds
+    pub latency: u64,
+    pub client_ip: Option<String>
+}
+
+/// Records the API requests in a ring buffer of the most recent entries,
+/// and optionally appends them to a file. It is attached as a fairing to see
+/// the requests, and it is managed by Rocket so the entries can be read by
+/// the API.
+#[derive(Clone)]
+pub struct RequestLogger {
+    level: LogLevel,
+    capacity: usize,
+    file: Option<String>,
+    /// The client IP of requests from these proxies is taken from the
+    /// `X-Real-IP` header
+    proxy_auth: Option<ProxyAuth>,
+    entries: Arc<Mutex<VecDeque<RequestLogEntry>>>
+}
+
+/// The time when a request was received. It is stored in the request-local
+/// cache so the response fairing can calculate the latency.
+struct LoggedRequestStart(Instant);
+
+impl RequestLogger {
+    /// Create a logger with the level, size, and file from the config
+    pub fn new(config: &Config) -> RequestLogger {
+        RequestLogger {
+            // The level was checked when the config was parsed
+            level: LogLevel::from_name(config.api_log_level()).unwrap_or(LogLevel::Info),
+            capacity: config.api_log_entries(),
+            file: config.api_log_file().map(ToOwned::to_owned),
+            proxy_auth: config.proxy_auth().cloned(),
+            entries: Arc::new(Mutex::new(VecDeque::new()))
+        }
+    }
+
+    /// Get the level requests are logged at
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// Get up to `limit` of the most recent entries, newest first
+    pub fn recent(&self, limit: usize) -> Vec<RequestLogEntry> {
+        self.lock().iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Record a request, if it is at or below the configured level. The
+    /// oldest entry is dropped if the buffer is full.
+    fn record(&self, entry: RequestLogEntry, entry_level: LogLevel) {
+        if entry_level > self.level || self.capacity == 0 {
+            return;
+        }
+
+        self.write_file(&entry);
+
+        let mut entries = self.lock();
+
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+
+        entries.push_back(entry);
+    }
+
+    /// Append the entry to the log file, if there is one. Failures are
+    /// ignored because the entry is still kept in memory.
+    fn write_file(&self, entry: &RequestLogEntry) {
+        if let Some(ref file) = self.file {
+            let _ = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)
+                .and_then(|mut file| {
+                    writeln!(
+                        file,
+                        "{} {}: method={} path=\"{}\" status={} latency_us={} client={}",
+                        entry.timestamp,
+                        entry.level,
+                        entry.method,
+                        entry.path,
+                        entry.status,
+                        entry.latency,
+                        entry.client_ip.as_ref().map_or("-", String::as_str)
+                    )
+                });
+        }
+    }
+
+    /// Lock the entries. Ignore the poison error because the entries are
+    /// still usable.
+    fn lock(&self) -> MutexGuard<VecDeque<RequestLogEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Logger",
+            kind: Kind::Request | Kind::Response
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| LoggedRequestStart(Instant::now()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if self.level == LogLevel::None || !request.uri().path().starts_with(API_PATH) {
+            return;
+        }
+
+        let start = request.local_cache(|| LoggedRequestStart(Instant::now()));
+        let latency = start.0.elapsed();
+        let status = response.status().code;
+        let entry_level = LogLevel::for_status(status);
+
+        // The query string is only logged when debugging, because it can
+        // include domains and client addresses
+        let path = if self.level == LogLevel::Debug {
+            request.uri().to_string()
+        } else {
+            request.uri().path().to_owned()
+        };
+
+        self.record(
+            RequestLogEntry {
+                timestamp: current_time(),
+                level: entry_level.get_name(),
+                method: request.method().to_string(),
+                path,
+                status,
+                latency: latency.as_secs() * 1_000_000 + u64::from(latency.subsec_micros()),
+                client_ip: client_ip(request, self.proxy_auth.as_ref()).map(|ip| ip.to_string())
+            },
+            entry_level
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LogLevel, RequestLogEntry, RequestLogger};
+    use crate::env::Config;
+
+    /// Create an entry of a request with the status
+    fn entry(path: &str, status: u16) -> RequestLogEntry {
+        RequestLogEntry {
+            timestamp: 0,
+            level: LogLevel::for_status(status).get_name(),
+            method: "GET".to_owned(),
+            path: path.to_owned(),
+            status,
+            latency: 100,
+            client_ip: Some("10.0.0.1".to_owned())
+        }
+    }
+
+    /// Only the most recent entries are kept, and they are returned newest
+    /// first
+    #[test]
+    fn ring_buffer() {
+        let logger = RequestLogger {
+            capacity: 2,
+            ..RequestLogger::new(&Config::default())
+        };
+
+        for path in &["/a", "/b", "/c"] {
+            logger.record(entry(path, 200), LogLevel::Info);
+        }
+
+        assert_eq!(logger.recent(10), vec![entry("/c", 200), entry("/b", 200)]);
+        assert_eq!(logger.recent(1), vec![entry("/c", 200)]);
+    }
+
+    /// Requests above the configured level are not recorded
+    #[test]
+    fn level_filter() {
+        let logger = RequestLogger {
+            level: LogLevel::Warning,
+            ..RequestLogger::new(&Config::default())
+        };
+
+        logger.record(entry("/ok", 200), LogLevel::for_status(200));
+        logger.record(entry("/missing", 404), LogLevel::for_status(404));
+        logger.record(entry("/failed", 500), LogLevel::for_status(500));
+
+        assert_eq!(
+            logger.recent(10),
+            vec![entry("/failed", 500), entry("/missing", 404)]
+        );
+    }
+}
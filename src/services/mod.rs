@@ -0,0 +1,25 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Background Services
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+mod adlist_fetcher;
+mod adlists;
+mod client_export;
+mod gravity_builder;
+mod gravity_index;
+mod gravity_job;
+mod logging;
+mod query_rollup;
+mod teleporter;
+mod threat_feed;
+
+pub use self::{
+    adlist_fetcher::*, adlists::Adlist, client_export::*, gravity_builder::*, gravity_index::*,
+    gravity_job::*, logging::*, query_rollup::*, teleporter::*, threat_feed::*
+};
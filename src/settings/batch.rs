@@ -0,0 +1,180 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Batched Setting Updates
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    settings::{generate_dnsmasq_config, ConfigEntry, FtlConfEntry, SetupVarsEntry},
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::{
+    collections::BTreeMap,
+    io::{BufWriter, Write}
+};
+
+/// Setting changes which are applied together, by key. An empty value
+/// deletes the setting.
+#[derive(Deserialize)]
+pub struct SettingsBatch {
+    #[serde(default)]
+    pub setup_vars: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ftl: BTreeMap<String, String>
+}
+
+impl SettingsBatch {
+    /// Apply every change, or none of them. All of the values are validated
+    /// before anything is written. If writing fails part way, the config files
+    /// are restored to how they were. Secrets can not be changed in a batch,
+    /// because they have their own endpoints.
+    pub fn apply(&self, env: &Env) -> Result<(), Error> {
+        let setup_vars = self
+            .setup_vars
+            .iter()
+            .map(|(key, value)| match SetupVarsEntry::from_key(key) {
+                Some(entry) if !entry.is_secret() && entry.is_valid(value) => Ok((entry, value)),
+                _ => Err(Error::from(ErrorKind::InvalidSettingValue))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let ftl = self
+            .ftl
+            .iter()
+            .map(|(key, value)| match FtlConfEntry::from_key(key) {
+                Some(entry) if entry.is_valid(value) => Ok((entry, value)),
+                _ => Err(Error::from(ErrorKind::InvalidSettingValue))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let setup_vars_backup = read_backup(env, PiholeFile::SetupVars)?;
+        let ftl_backup = read_backup(env, PiholeFile::FtlConfig)?;
+
+        let result = setup_vars
+            .iter()
+            .try_for_each(|(entry, value)| entry.write(value, env))
+            .and_then(|_| {
+                ftl.iter()
+                    .try_for_each(|(entry, value)| entry.write(value, env))
+            })
+            .and_then(|_| generate_dnsmasq_config(env));
+
+        if result.is_err() {
+            restore_backup(env, PiholeFile::SetupVars, &setup_vars_backup)?;
+            restore_backup(env, PiholeFile::FtlConfig, &ftl_backup)?;
+        }
+
+        result
+    }
+}
+
+/// Read the lines of a config file, to restore it if the batch fails. A
+/// missing file has no lines.
+fn read_backup(env: &Env, file: PiholeFile) -> Result<Vec<String>, Error> {
+    if !env.file_exists(file) {
+        return Ok(Vec::new());
+    }
+
+    env.read_file_lines(file)
+}
+
+/// Replace the lines of a config file with its backup
+fn restore_backup(env: &Env, file: PiholeFile, lines: &[String]) -> Result<(), Error> {
+    let file_location = env.file_location(file).to_owned();
+    let mut writer = BufWriter::new(env.write_file(file, false)?);
+
+    for line in lines {
+        writeln!(writer, "{}", line).context(ErrorKind::FileWrite(file_location.clone()))?;
+    }
+
+    writer
+        .flush()
+        .context(ErrorKind::FileWrite(file_location))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::SettingsBatch;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder,
+        util::ErrorKind
+    };
+    use std::collections::BTreeMap;
+
+    /// Create a batch from the keys and values
+    fn batch(setup_vars: &[(&str, &str)], ftl: &[(&str, &str)]) -> SettingsBatch {
+        let map = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                .collect()
+        };
+
+        SettingsBatch {
+            setup_vars: map(setup_vars),
+            ftl: map(ftl)
+        }
+    }
+
+    /// Every change in the batch is written
+    #[test]
+    fn apply_all() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::SetupVars,
+                "DNSSEC=false\nPIHOLE_DNS_1=8.8.8.8\n",
+                "PIHOLE_DNS_1=8.8.8.8\nDNSSEC=true\nPIHOLE_DNS_2=8.8.4.4\n"
+            )
+            .file_expect(PiholeFile::FtlConfig, "", "PRIVACYLEVEL=1\n")
+            .file(PiholeFile::DnsmasqConfig, "");
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        batch(
+            &[("DNSSEC", "true"), ("PIHOLE_DNS_2", "8.8.4.4")],
+            &[("PRIVACYLEVEL", "1")]
+        )
+        .apply(&env)
+        .unwrap();
+
+        let mut buffer = String::new();
+        for test_file in test_files.iter_mut().take(2) {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+
+    /// Nothing is written if any value is invalid, or if a key is unknown or
+    /// a secret
+    #[test]
+    fn reject_invalid() {
+        let env_builder = TestEnvBuilder::new()
+            .file(PiholeFile::SetupVars, "DNSSEC=false\n")
+            .file(PiholeFile::FtlConfig, "");
+        let mut test_files = env_builder.get_test_files();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        for batch in &[
+            batch(&[("DNSSEC", "true")], &[("PRIVACYLEVEL", "9")]),
+            batch(&[("DNSSEC", "true"), ("NOT_A_SETTING", "1")], &[]),
+            batch(&[("DNSSEC", "true"), ("WEBPASSWORD", "")], &[])
+        ] {
+            assert_eq!(
+                batch.apply(&env).map_err(|e| e.kind()),
+                Err(ErrorKind::InvalidSettingValue)
+            );
+        }
+
+        let mut buffer = String::new();
+        for test_file in &mut test_files {
+            test_file.assert_expected(&mut buffer);
+        }
+    }
+}
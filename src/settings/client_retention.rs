@@ -0,0 +1,115 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Client Query Log Retention Type
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    settings::Subnet,
+    util::{Error, ErrorKind}
+};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// How long the queries of a client are kept in the FTL database, overriding
+/// `MAXDBDAYS`. The client is an IP address or a subnet in CIDR notation. In
+/// setupVars.conf it is written as `client@hours`, such as `10.0.1.0/24@24`.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ClientRetention {
+    pub client: String,
+    pub hours: u64
+}
+
+impl ClientRetention {
+    /// Check if the client IP address is covered by this retention
+    pub fn matches(&self, ip: &str) -> bool {
+        let ip: IpAddr = match ip.parse() {
+            Ok(ip) => ip,
+            Err(_) => return false
+        };
+
+        match self.client.parse::<Subnet>() {
+            Ok(subnet) => subnet.contains(&ip),
+            Err(_) => self.client.parse::<IpAddr>().ok() == Some(ip)
+        }
+    }
+}
+
+impl FromStr for ClientRetention {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut split = s.rsplitn(2, '@');
+
+        let hours: u64 = split
+            .next()
+            .and_then(|hours| hours.parse().ok())
+            .filter(|&hours| hours > 0)
+            .ok_or(ErrorKind::InvalidSettingValue)?;
+        let client = split
+            .next()
+            .filter(|client| client.parse::<IpAddr>().is_ok() || client.parse::<Subnet>().is_ok())
+            .ok_or(ErrorKind::InvalidSettingValue)?;
+
+        Ok(ClientRetention {
+            client: client.to_owned(),
+            hours
+        })
+    }
+}
+
+impl fmt::Display for ClientRetention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.client, self.hours)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClientRetention;
+
+    /// Retentions are parsed from the client and hours
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "10.0.1.0/24@24".parse::<ClientRetention>().unwrap(),
+            ClientRetention {
+                client: "10.0.1.0/24".to_owned(),
+                hours: 24
+            }
+        );
+        assert_eq!(
+            "fd00::5@48".parse::<ClientRetention>().unwrap(),
+            ClientRetention {
+                client: "fd00::5".to_owned(),
+                hours: 48
+            }
+        );
+    }
+
+    /// Retentions need a valid client and at least one hour
+    #[test]
+    fn parse_invalid() {
+        assert!("10.0.1.0/24".parse::<ClientRetention>().is_err());
+        assert!("10.0.1.0/24@0".parse::<ClientRetention>().is_err());
+        assert!("laptop@24".parse::<ClientRetention>().is_err());
+        assert!("@24".parse::<ClientRetention>().is_err());
+    }
+
+    /// Clients are matched by IP address or by subnet
+    #[test]
+    fn matches() {
+        let ip: ClientRetention = "10.0.1.5@24".parse().unwrap();
+        let subnet: ClientRetention = "10.0.1.0/24@24".parse().unwrap();
+
+        assert!(ip.matches("10.0.1.5"));
+        assert!(!ip.matches("10.0.1.6"));
+        assert!(subnet.matches("10.0.1.6"));
+        assert!(!subnet.matches("10.0.2.1"));
+        assert!(!subnet.matches("not an ip"));
+    }
+}
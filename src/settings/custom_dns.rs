@@ -0,0 +1,157 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Custom DNS Records
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    settings::ValueType,
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::io::Write;
+
+/// A local A or AAAA record, stored in a hosts file which dnsmasq reads
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct DnsRecord {
+    pub domain: String,
+    pub ip: String
+}
+
+/// A local CNAME record. dnsmasq only answers it if the target is a local
+/// record, such as a custom DNS record or a DHCP hostname.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct CnameRecord {
+    pub domain: String,
+    pub target: String
+}
+
+impl DnsRecord {
+    /// Read the records. Each line has the format `<IP> <domain>`.
+    pub fn read_all(env: &Env) -> Result<Vec<DnsRecord>, Error> {
+        if !env.file_exists(PiholeFile::CustomList) {
+            return Ok(Vec::new());
+        }
+
+        Ok(env
+            .read_file_lines(PiholeFile::CustomList)?
+            .iter()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+
+                Some(DnsRecord {
+                    ip: fields.next()?.to_owned(),
+                    domain: fields.next()?.to_owned()
+                })
+            })
+            .collect())
+    }
+
+    /// Save the records, replacing the previously saved records
+    pub fn write_all(env: &Env, records: &[DnsRecord]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::CustomList).to_owned();
+        let mut file = env.write_file(PiholeFile::CustomList, false)?;
+
+        for record in records {
+            writeln!(file, "{} {}", record.ip, record.domain)
+                .context(ErrorKind::FileWrite(file_location.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if the domain and IP address are valid. The IP address may be
+    /// IPv4 (A record) or IPv6 (AAAA record).
+    pub fn is_valid(&self) -> bool {
+        ValueType::Hostname.is_valid(&self.domain)
+            && (ValueType::Ipv4.is_valid(&self.ip) || ValueType::Ipv6.is_valid(&self.ip))
+    }
+}
+
+impl CnameRecord {
+    /// Read the CNAME records. Each line has the format `<domain>,<target>`.
+    pub fn read_all(env: &Env) -> Result<Vec<CnameRecord>, Error> {
+        if !env.file_exists(PiholeFile::CustomCnames) {
+            return Ok(Vec::new());
+        }
+
+        Ok(env
+            .read_file_lines(PiholeFile::CustomCnames)?
+            .iter()
+            .filter_map(|line| {
+                let mut fields = line.trim().split(',');
+
+                Some(CnameRecord {
+                    domain: fields
+                        .next()
+                        .filter(|domain| !domain.is_empty())?
+                        .to_owned(),
+                    target: fields.next()?.to_owned()
+                })
+            })
+            .collect())
+    }
+
+    /// Save the CNAME records, replacing the previously saved records
+    pub fn write_all(env: &Env, records: &[CnameRecord]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::CustomCnames).to_owned();
+        let mut file = env.write_file(PiholeFile::CustomCnames, false)?;
+
+        for record in records {
+            writeln!(file, "{},{}", record.domain, record.target)
+                .context(ErrorKind::FileWrite(file_location.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if the domain and target are valid, and that the record does not
+    /// point to itself
+    pub fn is_valid(&self) -> bool {
+        ValueType::Hostname.is_valid(&self.domain)
+            && ValueType::Hostname.is_valid(&self.target)
+            && self.domain != self.target
+    }
+
+    /// Get the dnsmasq option for the record
+    pub fn dnsmasq_option(&self) -> String {
+        format!("cname={},{}", self.domain, self.target)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CnameRecord, DnsRecord};
+
+    /// A and AAAA records are valid, but not records for other values
+    #[test]
+    fn record_validation() {
+        let record = |domain: &str, ip: &str| DnsRecord {
+            domain: domain.to_owned(),
+            ip: ip.to_owned()
+        };
+
+        assert!(record("nas.lan", "192.168.1.10").is_valid());
+        assert!(record("nas.lan", "fd00::10").is_valid());
+        assert!(!record("nas.lan", "nas").is_valid());
+        assert!(!record("nas .lan", "192.168.1.10").is_valid());
+    }
+
+    /// A CNAME record can not point to itself
+    #[test]
+    fn cname_to_itself() {
+        let record = CnameRecord {
+            domain: "nas.lan".to_owned(),
+            target: "nas.lan".to_owned()
+        };
+
+        assert!(!record.is_valid());
+    }
+}
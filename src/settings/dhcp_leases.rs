@@ -0,0 +1,207 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// DHCP Leases
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    settings::ValueType,
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use std::io::Write;
+
+/// A lease handed out by the DHCP server, read from the dnsmasq lease file
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct DhcpLease {
+    /// When the lease expires, as a Unix timestamp. Zero means the lease
+    /// never expires.
+    pub expires: u64,
+    pub mac: String,
+    pub ip: String,
+    pub hostname: String,
+    pub client_id: String
+}
+
+/// A fixed IP address, and optionally a hostname, for the device with the
+/// MAC address
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct StaticLease {
+    pub mac: String,
+    pub ip: String,
+    #[serde(default)]
+    pub hostname: String
+}
+
+impl DhcpLease {
+    /// Read the active leases. If there is no lease file, the DHCP server has
+    /// not handed out any leases.
+    pub fn read_all(env: &Env) -> Result<Vec<DhcpLease>, Error> {
+        if !env.file_exists(PiholeFile::DhcpLeases) {
+            return Ok(Vec::new());
+        }
+
+        Ok(env
+            .read_file_lines(PiholeFile::DhcpLeases)?
+            .iter()
+            .filter_map(|line| DhcpLease::parse(line))
+            .collect())
+    }
+
+    /// Remove the lease of the device with the MAC address from the lease
+    /// file. The DNS server must be restarted for it to forget the lease.
+    pub fn remove(env: &Env, mac: &str) -> Result<(), Error> {
+        let mac = mac.to_lowercase();
+        let lines = if env.file_exists(PiholeFile::DhcpLeases) {
+            env.read_file_lines(PiholeFile::DhcpLeases)?
+        } else {
+            Vec::new()
+        };
+
+        let (removed, kept): (Vec<String>, Vec<String>) = lines
+            .into_iter()
+            .partition(|line| DhcpLease::parse(line).map_or(false, |lease| lease.mac == mac));
+
+        if removed.is_empty() {
+            return Err(Error::from(ErrorKind::NotFound));
+        }
+
+        let file_location = env.file_location(PiholeFile::DhcpLeases).to_owned();
+        let mut file = env.write_file(PiholeFile::DhcpLeases, false)?;
+
+        for line in kept {
+            writeln!(file, "{}", line).context(ErrorKind::FileWrite(file_location.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Parse a line of the lease file, which has the format
+    /// `<expires> <MAC> <IP> <hostname> <client ID>`. Unknown values are `*`.
+    /// Lines which are not IPv4 leases, such as the DHCPv6 server DUID, are
+    /// skipped.
+    fn parse(line: &str) -> Option<DhcpLease> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.len() != 5 || !ValueType::MacAddress.is_valid(fields[1]) {
+            return None;
+        }
+
+        let known = |value: &str| {
+            if value == "*" {
+                String::new()
+            } else {
+                value.to_owned()
+            }
+        };
+
+        Some(DhcpLease {
+            expires: fields[0].parse().ok()?,
+            mac: fields[1].to_lowercase(),
+            ip: fields[2].to_owned(),
+            hostname: known(fields[3]),
+            client_id: known(fields[4])
+        })
+    }
+}
+
+impl StaticLease {
+    /// Read the static leases. Each line has the format
+    /// `<MAC>,<IP>,<hostname>`, and the hostname may be empty.
+    pub fn read_all(env: &Env) -> Result<Vec<StaticLease>, Error> {
+        if !env.file_exists(PiholeFile::StaticDhcpLeases) {
+            return Ok(Vec::new());
+        }
+
+        Ok(env
+            .read_file_lines(PiholeFile::StaticDhcpLeases)?
+            .iter()
+            .filter_map(|line| StaticLease::parse(line))
+            .collect())
+    }
+
+    /// Save the static leases, replacing the previously saved leases
+    pub fn write_all(env: &Env, leases: &[StaticLease]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::StaticDhcpLeases).to_owned();
+        let mut file = env.write_file(PiholeFile::StaticDhcpLeases, false)?;
+
+        for lease in leases {
+            writeln!(file, "{},{},{}", lease.mac, lease.ip, lease.hostname)
+                .context(ErrorKind::FileWrite(file_location.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if the MAC address, IP address, and hostname (if there is one)
+    /// are valid
+    pub fn is_valid(&self) -> bool {
+        ValueType::MacAddress.is_valid(&self.mac)
+            && ValueType::Ipv4.is_valid(&self.ip)
+            && (self.hostname.is_empty() || ValueType::Hostname.is_valid(&self.hostname))
+    }
+
+    /// Get the dnsmasq option which gives the device its fixed address
+    pub fn dnsmasq_option(&self) -> String {
+        if self.hostname.is_empty() {
+            format!("dhcp-host={},{}", self.mac, self.ip)
+        } else {
+            format!("dhcp-host={},{},{}", self.mac, self.ip, self.hostname)
+        }
+    }
+
+    /// Parse a line of the static leases file
+    fn parse(line: &str) -> Option<StaticLease> {
+        let mut fields = line.trim().split(',');
+
+        Some(StaticLease {
+            mac: fields.next().filter(|mac| !mac.is_empty())?.to_lowercase(),
+            ip: fields.next()?.to_owned(),
+            hostname: fields.next().unwrap_or_default().to_owned()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhcpLease, StaticLease};
+
+    /// Leases are parsed, with unknown values left empty
+    #[test]
+    fn parse_lease() {
+        assert_eq!(
+            DhcpLease::parse("1570000000 00:11:22:AA:BB:CC 192.168.1.50 laptop *"),
+            Some(DhcpLease {
+                expires: 1_570_000_000,
+                mac: "00:11:22:aa:bb:cc".to_owned(),
+                ip: "192.168.1.50".to_owned(),
+                hostname: "laptop".to_owned(),
+                client_id: "".to_owned()
+            })
+        );
+    }
+
+    /// The DHCPv6 server DUID is not a lease
+    #[test]
+    fn parse_duid() {
+        assert_eq!(DhcpLease::parse("duid 00:01:00:01:25:d3:a2:f1"), None);
+    }
+
+    /// Static leases without a hostname do not include it in the option
+    #[test]
+    fn static_lease_option() {
+        let lease = StaticLease::parse("00:11:22:33:44:55,192.168.1.10,").unwrap();
+
+        assert_eq!(
+            lease.dnsmasq_option(),
+            "dhcp-host=00:11:22:33:44:55,192.168.1.10"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Custom DHCP Options
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    settings::ValueType,
+    util::{Error, ErrorKind}
+};
+use failure::ResultExt;
+use regex::Regex;
+use std::io::Write;
+
+/// Options which are part of the DHCP protocol itself, and are set by
+/// dnsmasq. Sending them from the config would confuse clients.
+const PROTOCOL_OPTIONS: &[u8] = &[50, 51, 52, 53, 54, 55, 57];
+
+/// A DHCP option sent to clients, for options which the DHCP settings do not
+/// cover, such as PXE boot or NTP servers. An option can be limited to the
+/// clients with a dnsmasq tag, or to one client by its MAC address.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct DhcpOption {
+    /// The option number, from 1 to 254
+    pub number: u8,
+    pub value: String,
+    #[serde(default)]
+    pub tag: String,
+    #[serde(default)]
+    pub mac: String
+}
+
+impl DhcpOption {
+    /// Read the options. Each line has the format
+    /// `<number>,<tag>,<MAC>,<value>`, and the tag and MAC may be empty.
+    pub fn read_all(env: &Env) -> Result<Vec<DhcpOption>, Error> {
+        if !env.file_exists(PiholeFile::DhcpOptions) {
+            return Ok(Vec::new());
+        }
+
+        Ok(env
+            .read_file_lines(PiholeFile::DhcpOptions)?
+            .iter()
+            .filter_map(|line| DhcpOption::parse(line))
+            .collect())
+    }
+
+    /// Save the options, replacing the previously saved options
+    pub fn write_all(env: &Env, options: &[DhcpOption]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::DhcpOptions).to_owned();
+        let mut file = env.write_file(PiholeFile::DhcpOptions, false)?;
+
+        for option in options {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                option.number, option.tag, option.mac, option.value
+            )
+            .context(ErrorKind::FileWrite(file_location.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if another option has the same number and applies to the same
+    /// clients
+    pub fn same_scope(&self, other: &DhcpOption) -> bool {
+        self.number == other.number && self.tag == other.tag && self.mac == other.mac
+    }
+
+    /// Check if the option number, scope, and value are valid. Values of
+    /// well known options are checked for their type. Other values may be
+    /// anything dnsmasq accepts, but must be on one line.
+    pub fn is_valid(&self) -> bool {
+        let valid_number =
+            self.number != 0 && self.number != 255 && !PROTOCOL_OPTIONS.contains(&self.number);
+        let valid_tag =
+            self.tag.is_empty() || Regex::new("^[a-zA-Z0-9_-]+$").unwrap().is_match(&self.tag);
+        let valid_mac = self.mac.is_empty() || ValueType::MacAddress.is_valid(&self.mac);
+        let one_scope = self.tag.is_empty() || self.mac.is_empty();
+
+        valid_number
+            && valid_tag
+            && valid_mac
+            && one_scope
+            && !self.value.is_empty()
+            && !self.value.chars().any(char::is_control)
+            && self.is_valid_value()
+    }
+
+    /// Get the dnsmasq options which send the option. Options for one client
+    /// first tag the client by its MAC address.
+    pub fn dnsmasq_option(&self) -> String {
+        if !self.mac.is_empty() {
+            let tag = format!("mac-{}", self.mac.replace(":", ""));
+
+            format!(
+                "dhcp-mac=set:{},{}\ndhcp-option=tag:{},{},{}",
+                tag, self.mac, tag, self.number, self.value
+            )
+        } else if !self.tag.is_empty() {
+            format!(
+                "dhcp-option=tag:{},{},{}",
+                self.tag, self.number, self.value
+            )
+        } else {
+            format!("dhcp-option={},{}", self.number, self.value)
+        }
+    }
+
+    /// Check the value against the type of the option, if it is well known
+    fn is_valid_value(&self) -> bool {
+        let value = self.value.as_str();
+
+        match self.number {
+            // Router, DNS server, NTP server, and NetBIOS name server
+            3 | 6 | 42 | 44 => ValueType::Array(&[ValueType::Ipv4]).is_valid(value),
+            // Domain name
+            15 => ValueType::Hostname.is_valid(value),
+            // Interface MTU, which must be at least 68
+            26 => value.parse::<u16>().ok().map_or(false, |mtu| mtu >= 68),
+            // TFTP server name
+            66 => ValueType::Hostname.is_valid(value) || ValueType::Ipv4.is_valid(value),
+            // Boot file name
+            67 => !value.contains(|c: char| c.is_whitespace() || c == ','),
+            // Domain search list
+            119 => ValueType::Array(&[ValueType::Hostname]).is_valid(value),
+            // Web proxy auto-discovery
+            252 => ValueType::Url.is_valid(value),
+            _ => true
+        }
+    }
+
+    /// Parse a line of the options file. The value is last because it may
+    /// contain commas.
+    fn parse(line: &str) -> Option<DhcpOption> {
+        let mut fields = line.trim().splitn(4, ',');
+
+        Some(DhcpOption {
+            number: fields.next()?.parse().ok()?,
+            tag: fields.next()?.to_owned(),
+            mac: fields.next()?.to_lowercase(),
+            value: fields.next().filter(|value| !value.is_empty())?.to_owned()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhcpOption;
+
+    /// Create an option for all clients
+    fn option(number: u8, value: &str) -> DhcpOption {
+        DhcpOption {
+            number,
+            value: value.to_owned(),
+            tag: "".to_owned(),
+            mac: "".to_owned()
+        }
+    }
+
+    /// Values of well known options must have the type of the option
+    #[test]
+    fn value_validation() {
+        assert!(option(42, "192.168.1.1,192.168.1.2").is_valid());
+        assert!(!option(42, "ntp.lan").is_valid());
+        assert!(option(67, "pxelinux.0").is_valid());
+        assert!(!option(26, "60").is_valid());
+        assert!(option(160, "\"any value\"").is_valid());
+        assert!(!option(160, "one\ntwo").is_valid());
+        assert!(!option(53, "1").is_valid());
+    }
+
+    /// An option can be limited to a tag or a client, but not both
+    #[test]
+    fn scope_validation() {
+        let mut option = option(66, "192.168.1.5");
+        option.tag = "pxe".to_owned();
+        assert!(option.is_valid());
+
+        option.mac = "00:11:22:33:44:55".to_owned();
+        assert!(!option.is_valid());
+    }
+
+    /// The value keeps its commas when parsed
+    #[test]
+    fn parse_option() {
+        assert_eq!(
+            DhcpOption::parse("42,,00:11:22:33:44:55,192.168.1.1,192.168.1.2"),
+            Some(DhcpOption {
+                number: 42,
+                value: "192.168.1.1,192.168.1.2".to_owned(),
+                tag: "".to_owned(),
+                mac: "00:11:22:33:44:55".to_owned()
+            })
+        );
+    }
+
+    /// Options for one client tag the client by its MAC address
+    #[test]
+    fn client_option() {
+        let mut option = option(42, "192.168.1.1");
+        option.mac = "00:11:22:33:44:55".to_owned();
+
+        assert_eq!(
+            option.dnsmasq_option(),
+            "dhcp-mac=set:mac-001122334455,00:11:22:33:44:55\n\
+             dhcp-option=tag:mac-001122334455,42,192.168.1.1"
+        );
+    }
+}
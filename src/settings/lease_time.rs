@@ -0,0 +1,139 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// DHCP Lease Time Type
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::util::{Error, ErrorKind};
+use std::{fmt, str::FromStr};
+
+/// dnsmasq does not allow shorter leases
+const MIN_MINUTES: u64 = 2;
+
+/// How long a DHCP lease lasts. It is parsed from a duration such as `45m`,
+/// `12h`, or `2d`, or from `infinite`. A number without a unit is in hours,
+/// and zero is infinite, which is how setupVars.conf stores it.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum LeaseTime {
+    Infinite,
+    Minutes(u64)
+}
+
+impl LeaseTime {
+    /// Get the value to save in setupVars.conf. Whole hours are saved as a
+    /// number so that older Pi-hole scripts can still read them.
+    pub fn setup_vars_value(self) -> String {
+        match self {
+            LeaseTime::Infinite => "0".to_owned(),
+            LeaseTime::Minutes(minutes) if minutes % 60 == 0 => (minutes / 60).to_string(),
+            LeaseTime::Minutes(minutes) => format!("{}m", minutes)
+        }
+    }
+
+    /// Get the lease time in the format of a dnsmasq `dhcp-range`
+    pub fn dnsmasq_value(self) -> String {
+        match self {
+            LeaseTime::Infinite => "infinite".to_owned(),
+            LeaseTime::Minutes(minutes) if minutes % 60 == 0 => format!("{}h", minutes / 60),
+            LeaseTime::Minutes(minutes) => format!("{}m", minutes)
+        }
+    }
+}
+
+impl FromStr for LeaseTime {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+
+        if s == "infinite" {
+            return Ok(LeaseTime::Infinite);
+        }
+
+        let (digits, minutes_per_unit) = match s.chars().last() {
+            Some('m') => (&s[..s.len() - 1], 1),
+            Some('h') => (&s[..s.len() - 1], 60),
+            Some('d') => (&s[..s.len() - 1], 24 * 60),
+            _ => (s, 60)
+        };
+
+        let number: u64 = digits
+            .parse()
+            .map_err(|_| Error::from(ErrorKind::InvalidSettingValue))?;
+
+        // setupVars.conf uses zero hours for infinite leases
+        if number == 0 && digits.len() == s.len() {
+            return Ok(LeaseTime::Infinite);
+        }
+
+        let minutes = number
+            .checked_mul(minutes_per_unit)
+            .filter(|&minutes| minutes >= MIN_MINUTES)
+            .ok_or(ErrorKind::InvalidSettingValue)?;
+
+        Ok(LeaseTime::Minutes(minutes))
+    }
+}
+
+impl fmt::Display for LeaseTime {
+    /// Show the lease time in the largest unit which fits it
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LeaseTime::Infinite => write!(f, "infinite"),
+            LeaseTime::Minutes(minutes) if minutes % (24 * 60) == 0 => {
+                write!(f, "{}d", minutes / (24 * 60))
+            }
+            LeaseTime::Minutes(minutes) if minutes % 60 == 0 => write!(f, "{}h", minutes / 60),
+            LeaseTime::Minutes(minutes) => write!(f, "{}m", minutes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LeaseTime;
+
+    /// Durations are parsed with their unit, and numbers are in hours
+    #[test]
+    fn parse() {
+        assert_eq!("45m".parse::<LeaseTime>().unwrap(), LeaseTime::Minutes(45));
+        assert_eq!("12h".parse::<LeaseTime>().unwrap(), LeaseTime::Minutes(720));
+        assert_eq!("2d".parse::<LeaseTime>().unwrap(), LeaseTime::Minutes(2880));
+        assert_eq!("24".parse::<LeaseTime>().unwrap(), LeaseTime::Minutes(1440));
+        assert_eq!("0".parse::<LeaseTime>().unwrap(), LeaseTime::Infinite);
+        assert_eq!(
+            "infinite".parse::<LeaseTime>().unwrap(),
+            LeaseTime::Infinite
+        );
+    }
+
+    /// Lease times must be a duration of at least two minutes
+    #[test]
+    fn parse_invalid() {
+        assert!("1m".parse::<LeaseTime>().is_err());
+        assert!("0h".parse::<LeaseTime>().is_err());
+        assert!("12x".parse::<LeaseTime>().is_err());
+        assert!("h".parse::<LeaseTime>().is_err());
+        assert!("-1".parse::<LeaseTime>().is_err());
+    }
+
+    /// Lease times are shown in the largest unit, and saved in hours when
+    /// possible
+    #[test]
+    fn format() {
+        let two_days = LeaseTime::Minutes(2880);
+        let minutes = LeaseTime::Minutes(90);
+
+        assert_eq!(two_days.to_string(), "2d");
+        assert_eq!(two_days.setup_vars_value(), "48");
+        assert_eq!(two_days.dnsmasq_value(), "48h");
+        assert_eq!(minutes.to_string(), "90m");
+        assert_eq!(minutes.setup_vars_value(), "90m");
+        assert_eq!(LeaseTime::Infinite.setup_vars_value(), "0");
+    }
+}
@@ -0,0 +1,235 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Settings Conflict Detection
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::Error
+};
+use std::net::Ipv4Addr;
+
+/// The rules which settings are checked against. Each rule looks for a
+/// combination of settings which are valid on their own, but do not work
+/// together.
+const RULES: &[LintRule] = &[
+    LintRule {
+        name: "dhcp_interface",
+        check: check_dhcp_interface
+    },
+    LintRule {
+        name: "dhcp_range",
+        check: check_dhcp_range
+    },
+    LintRule {
+        name: "conditional_forwarding_ip",
+        check: check_conditional_forwarding_ip
+    },
+    LintRule {
+        name: "dnssec_upstream",
+        check: check_dnssec_upstream
+    }
+];
+
+/// A rule for conflicting settings. The check returns a message describing
+/// the conflict, if there is one.
+struct LintRule {
+    name: &'static str,
+    check: fn(&Env) -> Result<Option<String>, Error>
+}
+
+/// A conflict found in the settings
+#[derive(Serialize)]
+pub struct LintWarning {
+    pub rule: &'static str,
+    pub message: String
+}
+
+/// Check the settings against every rule
+pub fn lint_settings(env: &Env) -> Result<Vec<LintWarning>, Error> {
+    let mut warnings = Vec::new();
+
+    for rule in RULES {
+        if let Some(message) = (rule.check)(env)? {
+            warnings.push(LintWarning {
+                rule: rule.name,
+                message
+            });
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// The DHCP server needs an interface to listen on
+fn check_dhcp_interface(env: &Env) -> Result<Option<String>, Error> {
+    if SetupVarsEntry::DhcpActive.is_true(env)?
+        && SetupVarsEntry::PiholeInterface.read(env)?.is_empty()
+    {
+        return Ok(Some(
+            "DHCP is enabled, but no interface is set for it to listen on".to_owned()
+        ));
+    }
+
+    Ok(None)
+}
+
+/// The DHCP range must not be empty, unless the network is IPv6-only
+fn check_dhcp_range(env: &Env) -> Result<Option<String>, Error> {
+    if !SetupVarsEntry::DhcpActive.is_true(env)? || !SetupVarsEntry::DhcpIpv4.is_true(env)? {
+        return Ok(None);
+    }
+
+    let start = SetupVarsEntry::DhcpStart.read(env)?.parse::<Ipv4Addr>();
+    let end = SetupVarsEntry::DhcpEnd.read(env)?.parse::<Ipv4Addr>();
+
+    match (start, end) {
+        (Ok(start), Ok(end)) if start > end => Ok(Some(format!(
+            "The DHCP range starts at {}, which is after its end at {}",
+            start, end
+        ))),
+        (Ok(_), Ok(_)) => Ok(None),
+        _ => Ok(Some(
+            "DHCP is enabled, but the start or end of its range is not set".to_owned()
+        ))
+    }
+}
+
+/// Conditional forwarding needs the router to forward to
+fn check_conditional_forwarding_ip(env: &Env) -> Result<Option<String>, Error> {
+    if SetupVarsEntry::ConditionalForwarding.is_true(env)?
+        && SetupVarsEntry::ConditionalForwardingIp
+            .read(env)?
+            .is_empty()
+    {
+        return Ok(Some(
+            "Conditional forwarding is enabled, but the router IP is not set".to_owned()
+        ));
+    }
+
+    Ok(None)
+}
+
+/// DNSSEC needs upstream servers which pass on DNSSEC records. Servers on the
+/// local network, such as routers, usually do not.
+fn check_dnssec_upstream(env: &Env) -> Result<Option<String>, Error> {
+    if !SetupVarsEntry::Dnssec.is_true(env)? {
+        return Ok(None);
+    }
+
+    let mut local_servers = Vec::new();
+
+    for num in 1.. {
+        let server = SetupVarsEntry::PiholeDns(num).read(env)?;
+
+        if server.is_empty() {
+            break;
+        }
+
+        if is_local_server(&server) {
+            local_servers.push(server);
+        }
+    }
+
+    if local_servers.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "DNSSEC is enabled, but these upstream servers are on the local network and may \
+         not support DNSSEC: {}",
+        local_servers.join(", ")
+    )))
+}
+
+/// Check if an upstream server, which may include a port, is on the local
+/// network
+fn is_local_server(server: &str) -> bool {
+    let address = server.split(|c| c == ':' || c == '#').next().unwrap_or("");
+
+    address
+        .parse::<Ipv4Addr>()
+        .map(|ip| ip.is_private() || ip.is_loopback() || ip.is_link_local())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::lint_settings;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// Get the names of the rules which the settings break
+    fn broken_rules(setup_vars: &str) -> Vec<&'static str> {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, setup_vars)
+                .build()
+        );
+
+        lint_settings(&env)
+            .unwrap()
+            .into_iter()
+            .map(|warning| warning.rule)
+            .collect()
+    }
+
+    /// Settings which work together have no warnings
+    #[test]
+    fn no_conflicts() {
+        assert!(broken_rules(
+            "DHCP_ACTIVE=true\n\
+             DHCP_START=192.168.1.50\n\
+             DHCP_END=192.168.1.150\n\
+             PIHOLE_INTERFACE=eth0\n\
+             DNSSEC=true\n\
+             PIHOLE_DNS_1=8.8.8.8\n"
+        )
+        .is_empty());
+    }
+
+    /// DHCP needs an interface and a range
+    #[test]
+    fn dhcp_conflicts() {
+        assert_eq!(
+            broken_rules(
+                "DHCP_ACTIVE=true\n\
+                 DHCP_START=192.168.1.150\n\
+                 DHCP_END=192.168.1.50\n"
+            ),
+            vec!["dhcp_interface", "dhcp_range"]
+        );
+    }
+
+    /// Conditional forwarding needs a router IP
+    #[test]
+    fn conditional_forwarding_conflict() {
+        assert_eq!(
+            broken_rules("CONDITIONAL_FORWARDING=true\n"),
+            vec!["conditional_forwarding_ip"]
+        );
+    }
+
+    /// DNSSEC is not trusted to work with upstream servers on the local
+    /// network
+    #[test]
+    fn dnssec_local_upstream() {
+        assert_eq!(
+            broken_rules(
+                "DNSSEC=true\n\
+                 PIHOLE_DNS_1=8.8.8.8\n\
+                 PIHOLE_DNS_2=192.168.1.1#5353\n"
+            ),
+            vec!["dnssec_upstream"]
+        );
+    }
+}
@@ -8,14 +8,32 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+mod batch;
+mod client_retention;
+mod custom_dns;
+mod dhcp_leases;
+mod dhcp_options;
 mod dnsmasq;
 mod entries;
+mod lease_time;
+mod lint;
+mod noise_domains;
 mod privacy_level;
+mod subnet;
 mod value_type;
 
 pub use self::{
+    batch::SettingsBatch,
+    client_retention::ClientRetention,
+    custom_dns::{CnameRecord, DnsRecord},
+    dhcp_leases::{DhcpLease, StaticLease},
+    dhcp_options::DhcpOption,
     dnsmasq::generate_dnsmasq_config,
     entries::{ConfigEntry, FtlConfEntry, SetupVarsEntry},
+    lease_time::LeaseTime,
+    lint::{lint_settings, LintWarning},
+    noise_domains::{NoiseDomains, DEFAULT_NOISE_DOMAINS},
     privacy_level::FtlPrivacyLevel,
+    subnet::Subnet,
     value_type::ValueType
 };
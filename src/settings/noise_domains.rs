@@ -0,0 +1,127 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Noise Domains Hidden From Top Domains
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::Env,
+    settings::{ConfigEntry, SetupVarsEntry},
+    util::Error
+};
+
+/// Domains which are queried often but are not interesting, such as time
+/// servers, connectivity checks, CDNs, and reverse lookups. Users can add more
+/// domains with [`SetupVarsEntry::ApiNoiseDomains`].
+///
+/// [`SetupVarsEntry::ApiNoiseDomains`]:
+/// ../entries/enum.SetupVarsEntry.html#variant.ApiNoiseDomains
+pub const DEFAULT_NOISE_DOMAINS: [&str; 20] = [
+    "pool.ntp.org",
+    "time.apple.com",
+    "time.windows.com",
+    "time.google.com",
+    "connectivitycheck.gstatic.com",
+    "connectivitycheck.android.com",
+    "clients3.google.com",
+    "captive.apple.com",
+    "msftconnecttest.com",
+    "msftncsi.com",
+    "detectportal.firefox.com",
+    "nmcheck.gnome.org",
+    "connectivity-check.ubuntu.com",
+    "akamaiedge.net",
+    "akamaitechnologies.com",
+    "cloudfront.net",
+    "edgekey.net",
+    "fastly.net",
+    "in-addr.arpa",
+    "ip6.arpa"
+];
+
+/// The noise domains, including the user's noise domains. A noise domain also
+/// matches all of its subdomains.
+pub struct NoiseDomains {
+    domains: Vec<String>
+}
+
+impl NoiseDomains {
+    /// Read the noise domains, adding the user's noise domains to the
+    /// defaults. The domains are lowercase.
+    pub fn read(env: &Env) -> Result<NoiseDomains, Error> {
+        let mut domains: Vec<String> = DEFAULT_NOISE_DOMAINS
+            .iter()
+            .map(|&domain| domain.to_owned())
+            .collect();
+
+        domains.extend(
+            SetupVarsEntry::ApiNoiseDomains
+                .read_list(env)?
+                .into_iter()
+                .map(|domain| domain.to_lowercase())
+        );
+
+        Ok(NoiseDomains { domains })
+    }
+
+    /// Get the noise domains
+    pub fn domains(&self) -> &[String] {
+        &self.domains
+    }
+
+    /// Check if the domain is a noise domain or a subdomain of one
+    pub fn matches(&self, domain: &str) -> bool {
+        let domain = domain.to_lowercase();
+
+        self.domains.iter().any(|noise| {
+            domain == *noise
+                || (domain.ends_with(noise.as_str())
+                    && domain[..domain.len() - noise.len()].ends_with('.'))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NoiseDomains;
+    use crate::{
+        env::{Config, Env, PiholeFile},
+        testing::TestEnvBuilder
+    };
+
+    /// Create the noise domains with the user's noise domains
+    fn noise_domains(setup_vars: &str) -> NoiseDomains {
+        let env = Env::Test(
+            Config::default(),
+            TestEnvBuilder::new()
+                .file(PiholeFile::SetupVars, setup_vars)
+                .build()
+        );
+
+        NoiseDomains::read(&env).unwrap()
+    }
+
+    /// Noise domains match themselves and their subdomains
+    #[test]
+    fn matches_subdomains() {
+        let noise = noise_domains("");
+
+        assert!(noise.matches("pool.ntp.org"));
+        assert!(noise.matches("0.debian.POOL.ntp.org"));
+        assert!(!noise.matches("notpool.ntp.org"));
+        assert!(!noise.matches("example.com"));
+    }
+
+    /// The user's noise domains are added to the defaults
+    #[test]
+    fn user_domains() {
+        let noise = noise_domains("API_NOISE_DOMAINS=Telemetry.example.com\n");
+
+        assert!(noise.matches("eu.telemetry.example.com"));
+        assert!(noise.matches("time.apple.com"));
+    }
+}
@@ -0,0 +1,179 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Subnet (CIDR) Type
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::util::{Error, ErrorKind};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// An IPv4 or IPv6 subnet in CIDR notation, such as `192.168.1.0/24`
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Subnet {
+    address: IpAddr,
+    prefix: u8
+}
+
+impl Subnet {
+    /// Get the length of the prefix
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Check if this is an IPv6 subnet
+    pub fn is_ipv6(&self) -> bool {
+        self.address.is_ipv6()
+    }
+
+    /// Combine the prefix bits of the subnet with the other bits of the
+    /// address, such as `fd00:1::/64` and `::100` into `fd00:1::100`. The
+    /// address must be the same version as the subnet.
+    pub fn with_host(&self, host: &IpAddr) -> Option<IpAddr> {
+        match (self.address, host) {
+            (IpAddr::V4(subnet), IpAddr::V4(host)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+
+                Some(IpAddr::V4(
+                    ((u32::from(subnet) & mask) | (u32::from(*host) & !mask)).into()
+                ))
+            }
+            (IpAddr::V6(subnet), IpAddr::V6(host)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+
+                Some(IpAddr::V6(
+                    ((u128::from(subnet) & mask) | (u128::from(*host) & !mask)).into()
+                ))
+            }
+            _ => None
+        }
+    }
+
+    /// Check if the IP address is in this subnet. IPv4 addresses are never in
+    /// an IPv6 subnet, and vice versa.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.address, ip) {
+            (IpAddr::V4(subnet), IpAddr::V4(ip)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+
+                u32::from(subnet) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(subnet), IpAddr::V6(ip)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+
+                u128::from(subnet) & mask == u128::from(*ip) & mask
+            }
+            _ => false
+        }
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut split = s.splitn(2, '/');
+
+        let address: IpAddr = split
+            .next()
+            .and_then(|address| address.parse().ok())
+            .ok_or(ErrorKind::InvalidSettingValue)?;
+        let prefix: u8 = split
+            .next()
+            .and_then(|prefix| prefix.parse().ok())
+            .ok_or(ErrorKind::InvalidSettingValue)?;
+
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+
+        if prefix > max_prefix {
+            return Err(Error::from(ErrorKind::InvalidSettingValue));
+        }
+
+        Ok(Subnet { address, prefix })
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Subnet;
+    use std::net::IpAddr;
+
+    /// Parse an IP address for the tests
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    /// IPv4 addresses are matched by the prefix bits
+    #[test]
+    fn contains_ipv4() {
+        let subnet: Subnet = "192.168.1.0/24".parse().unwrap();
+
+        assert!(subnet.contains(&ip("192.168.1.1")));
+        assert!(subnet.contains(&ip("192.168.1.255")));
+        assert!(!subnet.contains(&ip("192.168.2.1")));
+        assert!(!subnet.contains(&ip("::1")));
+    }
+
+    /// IPv6 addresses are matched by the prefix bits
+    #[test]
+    fn contains_ipv6() {
+        let subnet: Subnet = "fd00:1::/64".parse().unwrap();
+
+        assert!(subnet.contains(&ip("fd00:1::1")));
+        assert!(!subnet.contains(&ip("fd00:2::1")));
+        assert!(!subnet.contains(&ip("10.0.0.1")));
+    }
+
+    /// A prefix of zero matches every address of the same version
+    #[test]
+    fn contains_all() {
+        let subnet: Subnet = "0.0.0.0/0".parse().unwrap();
+
+        assert!(subnet.contains(&ip("10.0.0.1")));
+        assert!(subnet.contains(&ip("255.255.255.255")));
+    }
+
+    /// The host bits of the address are added to the prefix
+    #[test]
+    fn with_host() {
+        let subnet: Subnet = "fd00:1::/64".parse().unwrap();
+
+        assert_eq!(subnet.with_host(&ip("::100")), Some(ip("fd00:1::100")));
+        assert_eq!(subnet.with_host(&ip("10.0.0.1")), None);
+    }
+
+    /// Subnets must have an address and a prefix in range
+    #[test]
+    fn parse_invalid() {
+        assert!("192.168.1.0".parse::<Subnet>().is_err());
+        assert!("192.168.1.0/33".parse::<Subnet>().is_err());
+        assert!("fd00::/129".parse::<Subnet>().is_err());
+        assert!("not a subnet/24".parse::<Subnet>().is_err());
+    }
+
+    /// Subnets are displayed in CIDR notation
+    #[test]
+    fn display() {
+        let subnet: Subnet = "10.0.0.0/8".parse().unwrap();
+
+        assert_eq!(subnet.to_string(), "10.0.0.0/8");
+    }
+}
@@ -8,6 +8,7 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
+use crate::settings::{ClientRetention, LeaseTime, Subnet};
 use get_if_addrs::get_if_addrs;
 use regex::Regex;
 use std::{
@@ -20,12 +21,22 @@ use std::{
 #[cfg_attr(test, derive(Debug))]
 pub enum ValueType {
     Boolean,
+    /// A client IP address or subnet with a retention in hours, such as
+    /// `10.0.1.0/24@24`
+    ClientRetention,
     /// A comma separated array of strings which match at least one of the
     /// specified value types
     Array(&'static [ValueType]),
     ConditionalForwardingReverse,
     Decimal,
+    /// An additional IPv4 DHCP range for another subnet, which is the start,
+    /// the end, and the router of the subnet, such as
+    /// `192.168.2.50,192.168.2.150,192.168.2.1`
+    DhcpRange,
     Domain,
+    /// A conditional forwarding rule, which is a domain and the IPv4 server
+    /// to forward it to, such as `lan,192.168.1.1`
+    ForwardingRule,
     #[allow(dead_code)]
     Filename,
     Hostname,
@@ -35,12 +46,23 @@ pub enum ValueType {
     IPv4OptionalPort,
     Ipv4Mask,
     Ipv6,
+    /// An IPv6 prefix in CIDR notation, such as `fd00:1::/64`
+    IPv6CIDR,
     Path,
     PortNumber,
     YesNo,
     WebPassword,
     String(&'static [&'static str]),
-    LanguageCode
+    LanguageCode,
+    /// A DHCP lease time, such as `12h`, `2d`, or `infinite`
+    LeaseTime,
+    /// A MAC address with colon separated hex octets, such as
+    /// `00:11:22:33:44:55`
+    MacAddress,
+    /// An IPv4 or IPv6 subnet in CIDR notation
+    Subnet,
+    /// An HTTP or HTTPS URL
+    Url
 }
 
 impl ValueType {
@@ -55,6 +77,7 @@ impl ValueType {
                     .iter()
                     .any(|value_type| value_type.is_valid(value))
             }),
+            ValueType::ClientRetention => value.parse::<ClientRetention>().is_ok(),
             ValueType::Boolean => match value {
                 "true" | "false" => true,
                 _ => false
@@ -73,6 +96,19 @@ impl ValueType {
                 let decimal_re = Regex::new(r"^(\d)+(\.)?(\d)*$").unwrap();
                 decimal_re.is_match(value)
             }
+            ValueType::DhcpRange => {
+                let split: Vec<&str> = value.split(',').collect();
+
+                match split.as_slice() {
+                    [start, end, router] => {
+                        is_ipv4_valid(start)
+                            && is_ipv4_valid(end)
+                            && is_ipv4_valid(router)
+                            && start.parse::<Ipv4Addr>().ok() <= end.parse::<Ipv4Addr>().ok()
+                    }
+                    _ => false
+                }
+            }
             ValueType::Domain => {
                 // Like a hostname, but must be fully qualified
                 let split: Vec<&str> = value.split('.').collect();
@@ -84,6 +120,18 @@ impl ValueType {
 
                 ValueType::Hostname.is_valid(value)
             }
+            ValueType::ForwardingRule => {
+                let mut split = value.splitn(2, ',');
+
+                match (split.next(), split.next()) {
+                    (Some(domain), Some(server)) => {
+                        (ValueType::Hostname.is_valid(domain)
+                            || ValueType::ConditionalForwardingReverse.is_valid(domain))
+                            && is_ipv4_valid(server)
+                    }
+                    _ => false
+                }
+            }
             ValueType::Filename => {
                 Path::new(value).file_name().is_some()
                     && !value.ends_with('/')
@@ -167,6 +215,10 @@ impl ValueType {
                     false
                 }
             }
+            ValueType::IPv6CIDR => value
+                .parse::<Subnet>()
+                .ok()
+                .map_or(false, |subnet| subnet.is_ipv6()),
             ValueType::Path => {
                 // Test if a path and filename have been specified
                 let path = Path::new(value);
@@ -190,6 +242,14 @@ impl ValueType {
             }
             ValueType::String(strings) => strings.contains(&value),
             ValueType::LanguageCode => Regex::new("^[a-zA-Z]+(-[a-zA-Z]+)*$")
+                .unwrap()
+                .is_match(value),
+            ValueType::LeaseTime => value.parse::<LeaseTime>().is_ok(),
+            ValueType::MacAddress => Regex::new("^([0-9a-fA-F]{2}:){5}[0-9a-fA-F]{2}$")
+                .unwrap()
+                .is_match(value),
+            ValueType::Subnet => value.parse::<Subnet>().is_ok(),
+            ValueType::Url => Regex::new(r"^https?://[^\s/?#]+[^\s]*$")
                 .unwrap()
                 .is_match(value)
         }
@@ -238,7 +298,18 @@ mod tests {
                 true
             ),
             (ValueType::Decimal, "3.14", true),
+            (
+                ValueType::DhcpRange,
+                "192.168.2.50,192.168.2.150,192.168.2.1",
+                true
+            ),
             (ValueType::Domain, "domain.com", true),
+            (ValueType::ForwardingRule, "lan,192.168.1.1", true),
+            (
+                ValueType::ForwardingRule,
+                "0.10.in-addr.arpa,10.0.0.1",
+                true
+            ),
             (ValueType::Filename, "c3po", true),
             (ValueType::Hostname, "localhost", true),
             (ValueType::Integer, "8675309", true),
@@ -252,10 +323,17 @@ mod tests {
                 "f7c4:12f8:4f5a:8454:5241:cf80:d61c:3e2c",
                 true
             ),
+            (ValueType::IPv6CIDR, "fd00:1::/64", true),
             (ValueType::Path, "/tmp/directory/file.ext", true),
             (ValueType::PortNumber, "9000", true),
             (ValueType::YesNo, "yes", true),
             (ValueType::String(&["boxed", ""]), "boxed", true),
+            (ValueType::LeaseTime, "12h", true),
+            (ValueType::MacAddress, "00:1a:2B:3c:4d:5e", true),
+            (ValueType::Subnet, "192.168.1.0/24", true),
+            (ValueType::Subnet, "fd00::/64", true),
+            (ValueType::ClientRetention, "10.0.1.0/24@24", true),
+            (ValueType::Url, "https://example.com/hook?id=1", true),
         ];
 
         for (setting, value, result) in tests {
@@ -291,7 +369,15 @@ mod tests {
             ),
             (ValueType::Decimal, "3/4", false),
             (ValueType::Decimal, "3.14.15.26", false),
+            (ValueType::DhcpRange, "192.168.2.50,192.168.2.150", false),
+            (
+                ValueType::DhcpRange,
+                "192.168.2.150,192.168.2.50,192.168.2.1",
+                false
+            ),
             (ValueType::Domain, "D0#A!N", false),
+            (ValueType::ForwardingRule, "lan", false),
+            (ValueType::ForwardingRule, "lan,192.168.1.1:53", false),
             (ValueType::Filename, "c3p0/", false),
             (ValueType::Hostname, ".localhost", false),
             (ValueType::Hostname, "localhost.", false),
@@ -307,10 +393,20 @@ mod tests {
             (ValueType::Ipv4Mask, "192.168.2.9", false),
             (ValueType::Ipv4Mask, "192.168.1.1/qwfp", false),
             (ValueType::Ipv6, "192.168.0.3", false),
+            (ValueType::IPv6CIDR, "192.168.1.0/24", false),
+            (ValueType::IPv6CIDR, "fd00:1::", false),
             (ValueType::Path, "~/tmp/directory/file.ext", false),
             (ValueType::PortNumber, "65536", false),
             (ValueType::YesNo, "true", false),
             (ValueType::String(&["boxed", ""]), "lan", false),
+            (ValueType::LeaseTime, "12 hours", false),
+            (ValueType::MacAddress, "00-1a-2b-3c-4d-5e", false),
+            (ValueType::MacAddress, "00:1a:2b:3c:4d", false),
+            (ValueType::Subnet, "192.168.1.0", false),
+            (ValueType::Subnet, "192.168.1.0/33", false),
+            (ValueType::ClientRetention, "10.0.1.0/24@0", false),
+            (ValueType::Url, "ftp://example.com", false),
+            (ValueType::Url, "http://example.com/a b", false),
         ];
 
         for (setting, value, result) in tests {
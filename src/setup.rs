@@ -9,18 +9,42 @@
 // Please see LICENSE file for your rights under this license.
 
 use crate::{
-    databases::{ftl::FtlDatabase, load_databases},
+    allowed_methods::AllowedMethods,
+    api_state::ApiState,
+    client_nicknames::ClientNicknames,
+    cors::cors_fairing,
+    databases::{ftl::FtlDatabase, load_databases, long_term::StoreBackend},
     env::{Config, Env},
+    fault_injection::FaultInjection,
     ftl::{FtlConnectionType, FtlMemory},
+    gravity_schedule::GravitySchedule,
+    log_rotation::start_log_rotation,
+    metrics::RequestStats,
+    notifications::{watch_clients, watch_threats, watch_thresholds, Notifier},
+    plugins::{Plugin, Plugins},
+    process_info::ProcessInfo,
+    query_purge::QueryPurge,
+    rate_limit::RateLimiter,
     routes::{
+        api_keys,
         auth::{self, AuthData},
-        dns, settings, stats, version, web
+        dns::{self, resume_blocking_pause, GravityReloader, ListChanges},
+        groups, search, settings,
+        stats::{self, CursorSigner, SummaryWaiters},
+        users, version, web
+    },
+    security_headers::SecurityHeaders,
+    services::{
+        start_query_rollup, ClientExportJob, GravityIndex, GravityJob, RequestLogger,
+        ThreatCategories
     },
     settings::{ConfigEntry, SetupVarsEntry},
     util::{Error, ErrorKind}
 };
+use failure::ResultExt;
 use rocket::config::{ConfigBuilder, Environment};
-use rocket_cors::Cors;
+use std::{sync::Arc, thread};
+use task_scheduler::Scheduler;
 
 #[cfg(test)]
 use crate::{databases::load_test_databases, env::PiholeFile};
@@ -43,29 +67,264 @@ fn unauthorized() -> Error {
     Error::from(ErrorKind::Unauthorized)
 }
 
+#[catch(403)]
+fn forbidden() -> Error {
+    Error::from(ErrorKind::Forbidden)
+}
+
 /// Run the API normally (connect to FTL over the socket)
 pub fn start() -> Result<(), Error> {
-    let config = Config::parse(CONFIG_LOCATION)?;
+    start_with_config(CONFIG_LOCATION)
+}
+
+/// Run the API normally, using the config file at `config_location`
+pub fn start_with_config(config_location: &str) -> Result<(), Error> {
+    start_with_plugins(config_location, Vec::new())
+}
+
+/// Add the plugins loaded from the plugin directory. Libraries are loaded
+/// with the `dylib-plugins` feature, and WebAssembly modules with the
+/// `wasm-plugins` feature.
+#[cfg(any(feature = "dylib-plugins", feature = "wasm-plugins"))]
+fn with_loaded_plugins(
+    mut plugins: Vec<Box<dyn Plugin>>,
+    config: &Config
+) -> Result<Vec<Box<dyn Plugin>>, Error> {
+    if let Some(plugin_dir) = config.plugin_dir() {
+        #[cfg(feature = "dylib-plugins")]
+        plugins.extend(crate::dylib_plugins::load_plugins(plugin_dir)?);
+        #[cfg(feature = "wasm-plugins")]
+        plugins.extend(crate::wasm_plugins::load_plugins(plugin_dir)?);
+    }
+
+    Ok(plugins)
+}
+
+/// Plugins can not be loaded without the `dylib-plugins` or `wasm-plugins`
+/// feature. A configured plugin directory is an error, instead of starting
+/// without the plugins which were expected.
+#[cfg(not(any(feature = "dylib-plugins", feature = "wasm-plugins")))]
+fn with_loaded_plugins(
+    plugins: Vec<Box<dyn Plugin>>,
+    config: &Config
+) -> Result<Vec<Box<dyn Plugin>>, Error> {
+    if let Some(plugin_dir) = config.plugin_dir() {
+        return Err(Error::from(ErrorKind::PluginLoad(plugin_dir.to_owned())));
+    }
+
+    Ok(plugins)
+}
+
+/// The state which is shared by every listener. It is created once and
+/// cloned into each listener's server, and the clones share their data, so
+/// sessions, rate limits, logs, and background work are the same whichever
+/// address a request arrives on.
+#[derive(Clone)]
+struct SharedState {
+    auth_data: AuthData,
+    rate_limiter: RateLimiter,
+    scheduler: Arc<Scheduler>,
+    request_stats: RequestStats,
+    request_logger: RequestLogger,
+    cursor_signer: CursorSigner,
+    notifier: Notifier,
+    gravity_schedule: GravitySchedule,
+    query_purge: QueryPurge,
+    threat_categories: ThreatCategories,
+    gravity_job: GravityJob,
+    client_export_job: ClientExportJob,
+    gravity_index: GravityIndex,
+    gravity_reloader: GravityReloader,
+    list_changes: ListChanges,
+    client_nicknames: ClientNicknames,
+    summary_waiters: SummaryWaiters,
+    plugins: Plugins
+}
+
+impl SharedState {
+    /// Create the shared state for the API key and config. The background
+    /// services are not started.
+    fn new(
+        env: &Env,
+        api_key: String,
+        cursor_signer: CursorSigner,
+        plugins: Plugins
+    ) -> SharedState {
+        // Scheduled and requested Gravity updates share one job slot
+        let gravity_job = GravityJob::default();
+
+        SharedState {
+            auth_data: AuthData::new(api_key),
+            rate_limiter: RateLimiter::new(env.config()),
+            scheduler: Arc::new(Scheduler::new()),
+            request_stats: RequestStats::new(
+                env.config().slow_request_threshold(),
+                env.config().lock_hold_threshold(),
+                env.config().request_log().map(ToOwned::to_owned)
+            ),
+            request_logger: RequestLogger::new(env.config()),
+            cursor_signer,
+            notifier: Notifier::default(),
+            gravity_schedule: GravitySchedule::new(gravity_job.clone()),
+            query_purge: QueryPurge::default(),
+            threat_categories: ThreatCategories::default(),
+            gravity_job,
+            client_export_job: ClientExportJob::default(),
+            gravity_index: GravityIndex::default(),
+            gravity_reloader: GravityReloader::default(),
+            list_changes: ListChanges::default(),
+            client_nicknames: ClientNicknames::default(),
+            summary_waiters: SummaryWaiters::default(),
+            plugins
+        }
+    }
+}
+
+/// Run the API using the config file at `config_location`, extended by the
+/// plugins. This lets a program which uses this crate as a library add its
+/// own routes and watch the queries, without changing the API itself. With
+/// the `dylib-plugins` or `wasm-plugins` feature, the plugins in the
+/// configured plugin directory are loaded too.
+pub fn start_with_plugins(
+    config_location: &str,
+    plugins: Vec<Box<dyn Plugin>>
+) -> Result<(), Error> {
+    launch(config_location, plugins, &|config: &Config| {
+        FtlMemory::production_with_snapshots(config.shm_snapshot_ttl())
+    })
+}
+
+/// Run the API using the config file at `config_location`, with `ftl_memory`
+/// in place of FTL's shared memory for the listeners. This is used by the
+/// integration tests to serve stats from the test `FtlMemory`. Each listener
+/// gets its own `FtlMemory` from `ftl_memory`. The background services still
+/// use FTL's shared memory.
+#[cfg(feature = "test-harness")]
+pub fn start_with_ftl_memory(
+    config_location: &str,
+    ftl_memory: fn() -> FtlMemory
+) -> Result<(), Error> {
+    launch(config_location, Vec::new(), &|_: &Config| ftl_memory())
+}
+
+/// Start the background services and run a server for each listener. The
+/// listeners read shared memory through the `FtlMemory` from `ftl_memory`.
+fn launch(
+    config_location: &str,
+    plugins: Vec<Box<dyn Plugin>>,
+    ftl_memory: &dyn Fn(&Config) -> FtlMemory
+) -> Result<(), Error> {
+    let config = Config::parse(config_location)?;
+    let plugins = Plugins::new(with_loaded_plugins(plugins, &config)?)?;
     let env = Env::Production(config);
     let key = SetupVarsEntry::WebPassword.read(&env)?;
 
-    setup(
-        rocket::custom(
-            ConfigBuilder::new(Environment::Production)
-                .address(env.config().address())
-                .port(env.config().port() as u16)
-                .log_level(env.config().log_level()?)
-                .extra("databases", load_databases(&env)?)
-                .finalize()
-                .unwrap()
-        ),
-        FtlConnectionType::Socket,
+    // The state is created once, so every listener shares it
+    let state = SharedState::new(&env, key, CursorSigner::random(&env)?, plugins);
+
+    // Check for new clients in the background, using separate handles to
+    // shared memory and the environment
+    watch_clients(
+        state.notifier.clone(),
         FtlMemory::production(),
-        env,
-        key,
-        true
-    )
-    .launch();
+        Env::Production(env.config().clone())
+    );
+
+    // Download the threat feed in the background, and check for blocked
+    // threats to notify about
+    state
+        .threat_categories
+        .start(Env::Production(env.config().clone()));
+    watch_threats(
+        state.notifier.clone(),
+        state.threat_categories.clone(),
+        FtlMemory::production(),
+        Env::Production(env.config().clone())
+    );
+
+    // Check the threshold rules' metrics in the background
+    watch_thresholds(
+        state.notifier.clone(),
+        Env::Production(env.config().clone())
+    );
+
+    // Send the new queries to the plugins in the background
+    state.plugins.watch_queries(FtlMemory::production());
+
+    // Run scheduled Gravity updates in the background
+    state
+        .gravity_schedule
+        .start(Env::Production(env.config().clone()));
+
+    // Rotate the API logs in the background
+    start_log_rotation(Env::Production(env.config().clone()));
+
+    // Purge the queries of clients with a shorter retention every night
+    state
+        .query_purge
+        .start(Env::Production(env.config().clone()));
+
+    // Roll up the queries into hourly and daily counts in the background
+    start_query_rollup(Env::Production(env.config().clone()));
+
+    // Resume a timed disable of blocking from before the API restarted
+    if let Err(e) = resume_blocking_pause(&env, &state.scheduler) {
+        e.print_stacktrace();
+    }
+
+    // Rocket only listens on one address, so each listener gets its own
+    // server. The servers share the state and the saved session key, so
+    // sessions stay valid on every listener and after a restart.
+    let session_key = ApiState::session_key(&env)?;
+    let mut servers = Vec::new();
+
+    for listener in env.config().listeners() {
+        let mut config_builder = ConfigBuilder::new(Environment::Production)
+            .address(listener.address())
+            .port(listener.port() as u16)
+            .log_level(env.config().log_level()?)
+            .keep_alive(env.config().keep_alive())
+            .limits(env.config().limits())
+            .secret_key(session_key.clone())
+            .extra("databases", load_databases(&env)?);
+
+        // Only override the number of workers if it is configured, otherwise
+        // use Rocket's default which is based on the number of CPUs
+        if let Some(workers) = env.config().workers() {
+            config_builder = config_builder.workers(workers);
+        }
+
+        if let Some(tls) = listener.tls() {
+            config_builder = config_builder.tls(tls.certs(), tls.key());
+        }
+
+        servers.push(setup(
+            rocket::custom(
+                config_builder
+                    .finalize()
+                    .context(ErrorKind::ConfigParsingError)?
+            ),
+            FtlConnectionType::socket(),
+            ftl_memory(env.config()),
+            Env::Production(env.config().clone()),
+            ProcessInfo::production(),
+            state.clone(),
+            true
+        ));
+    }
+
+    // Run the last server on this thread, and the others in the background.
+    // There is always at least one listener.
+    let last_server = servers.pop().unwrap();
+
+    for server in servers {
+        thread::spawn(move || {
+            let error = server.launch();
+            eprintln!("Failed to launch a listener: {}", error);
+        });
+    }
+
+    last_server.launch();
 
     Ok(())
 }
@@ -80,18 +339,30 @@ pub fn test(
 ) -> Client {
     use toml;
 
+    let env = Env::Test(toml::from_str("").unwrap(), env_data);
+    let state = SharedState::new(
+        &env,
+        "test_key".to_owned(),
+        CursorSigner::test(),
+        Plugins::default()
+    );
+
     Client::new(setup(
         rocket::custom(
             ConfigBuilder::new(Environment::Development)
                 .log_level(LoggingLevel::Debug)
-                .extra("databases", load_test_databases())
+                .extra("databases", load_test_databases(None))
                 .finalize()
                 .unwrap()
         ),
         FtlConnectionType::Test(ftl_data),
         ftl_memory,
-        Env::Test(toml::from_str("").unwrap(), env_data),
-        "test_key".to_owned(),
+        env,
+        ProcessInfo::Test {
+            api_uptime: 3600,
+            ftl_uptime: Some(7200)
+        },
+        state,
         needs_database
     ))
     .unwrap()
@@ -103,41 +374,93 @@ fn setup(
     ftl_socket: FtlConnectionType,
     ftl_memory: FtlMemory,
     env: Env,
-    api_key: String,
+    process_info: ProcessInfo,
+    state: SharedState,
     needs_database: bool
 ) -> rocket::Rocket {
     // Set up CORS
-    let cors = Cors {
-        allow_credentials: true,
-        ..Cors::default()
-    };
+    let cors = cors_fairing(env.config());
 
-    // Attach the databases if required
+    // Attach the databases if required. The long-term statistics database
+    // is FTL's database unless another one is configured.
+    let store_backend = StoreBackend::from_config(env.config());
     let server = if needs_database {
-        server.attach(FtlDatabase::fairing())
+        store_backend.attach(server.attach(FtlDatabase::fairing()))
     } else {
         server
     };
 
-    // Create a scheduler for scheduling work (ex. disable for 10 minutes)
-    let scheduler = task_scheduler::Scheduler::new();
+    // Set up the security headers
+    let security_headers = SecurityHeaders::new(env.config());
+
+    // Set up fault injection, which is only enabled for testing error handling
+    let fault_injection = FaultInjection::new(env.config().fault_injection());
+
+    // Mount the plugins' routes
+    let server = state.plugins.mount(server);
 
     // Set up the server
     server
         // Attach CORS handler
         .attach(cors)
+        // Attach the security headers
+        .attach(security_headers)
+        // Attach the rate limiter
+        .attach(state.rate_limiter)
+        // Attach the request statistics collector
+        .attach(state.request_stats.clone())
+        // Attach the request logger
+        .attach(state.request_logger.clone())
+        // Attach the fault injector
+        .attach(fault_injection)
         // Add custom error handlers
-        .register(catchers![not_found, unauthorized])
+        .register(catchers![not_found, unauthorized, forbidden])
         // Manage the FTL socket configuration
         .manage(ftl_socket)
         // Manage the FTL shared memory configuration
         .manage(ftl_memory)
         // Manage the environment
         .manage(env)
+        // Manage the long-term statistics database backend
+        .manage(store_backend)
         // Manage the API key
-        .manage(AuthData::new(api_key))
+        .manage(state.auth_data)
         // Manage the scheduler
-        .manage(scheduler)
+        .manage(state.scheduler)
+        // Manage the request statistics
+        .manage(state.request_stats)
+        // Manage the request log
+        .manage(state.request_logger)
+        // Manage the history cursor signer
+        .manage(state.cursor_signer)
+        // Manage the login and new client notifier
+        .manage(state.notifier)
+        // Manage the Gravity builder, to report the progress of builds run by
+        // the schedule
+        .manage(state.gravity_schedule.builder())
+        // Manage the Gravity update schedule
+        .manage(state.gravity_schedule)
+        // Manage the nightly query purge
+        .manage(state.query_purge)
+        // Manage the threat categories
+        .manage(state.threat_categories)
+        // Manage the API and FTL process information
+        .manage(process_info)
+        // Manage the Gravity updates started through the API
+        .manage(state.gravity_job)
+        // Manage the client exports started through the API
+        .manage(state.client_export_job)
+        // Manage the Gravity lookup index
+        .manage(state.gravity_index)
+        // Manage the debounced Gravity reloads
+        .manage(state.gravity_reloader)
+        // Manage the recent list changes
+        .manage(state.list_changes)
+        // Manage the client nicknames, and load them from the database
+        .manage(state.client_nicknames)
+        .attach(ClientNicknames::fairing())
+        // Manage the summary requests waiting for changes
+        .manage(state.summary_waiters)
         // Mount the web interface
         .mount("/", routes![
             web::web_interface_redirect,
@@ -147,44 +470,255 @@ fn setup(
         // Mount the API
         .mount("/admin/api", routes![
             version::version,
+            search::search,
             auth::check,
             auth::logout,
+            auth::get_permissions,
+            users::get_users,
+            users::add_user,
+            users::update_user,
+            users::delete_user,
+            users::get_preferences,
+            users::put_preferences,
+            api_keys::get_api_keys,
+            api_keys::add_api_key,
+            api_keys::delete_api_key,
             stats::get_summary,
+            stats::get_compact_summary,
+            stats::get_summary_compare,
+            stats::get_forecast,
             stats::top_domains,
             stats::top_clients,
             stats::upstreams,
+            stats::response_times,
             stats::query_types,
+            stats::query_statuses,
             stats::history,
+            stats::history_head,
+            stats::query_transitions,
+            stats::get_views,
+            stats::get_view,
+            stats::add_view,
+            stats::update_view,
+            stats::delete_view,
             stats::recent_blocked,
+            stats::get_annotations,
+            stats::add_annotation,
+            stats::delete_annotation,
+            stats::get_audit,
+            stats::add_audit,
+            stats::delete_audit,
+            stats::subnets,
             stats::clients,
+            stats::export_client_data,
+            stats::get_client_export,
+            stats::delete_client_export,
+            stats::get_client_export_status,
+            stats::get_client_export_archive,
+            stats::get_client_export_archive_head,
+            stats::delete_client_data,
+            stats::client_query_types,
+            stats::domain_cooccurrence,
             stats::over_time_history,
+            stats::cache_hit_ratio,
             stats::over_time_clients,
             stats::database::get_summary_db,
+            stats::database::client_query_types_db,
+            stats::database::clients_db,
+            stats::database::heatmap_db,
+            stats::history_db,
+            stats::history_db_head,
             stats::database::over_time_clients_db,
             stats::database::over_time_history_db,
             stats::database::query_types_db,
+            stats::database::query_statuses_db,
+            stats::database::rollup_db,
             stats::database::top_clients_db,
             stats::database::top_domains_db,
             stats::database::upstreams_db,
             dns::get_whitelist,
             dns::get_blacklist,
             dns::get_regexlist,
+            dns::get_regex_whitelist,
+            dns::get_gravity_reload,
+            dns::get_cache,
+            dns::flush_cache,
+            dns::get_gravity_build,
+            dns::get_gravity_contains,
+            dns::check_domains,
+            dns::update_gravity,
+            dns::get_gravity_status,
+            dns::get_adlists,
+            dns::add_adlist,
+            dns::update_adlist,
+            dns::delete_adlist,
+            dns::put_adlist_checksum,
+            dns::get_threat_feed,
+            dns::put_threat_feed,
+            dns::get_list_hash,
+            dns::get_lists_hash,
+            dns::get_list_changes,
             dns::status,
             dns::change_status,
             dns::add_whitelist,
             dns::add_blacklist,
             dns::add_regexlist,
+            dns::add_regex_whitelist,
+            dns::add_whitelist_batch,
+            dns::add_blacklist_batch,
+            dns::add_regexlist_batch,
+            dns::add_regex_whitelist_batch,
             dns::delete_whitelist,
             dns::delete_blacklist,
             dns::delete_regexlist,
+            dns::delete_regex_whitelist,
+            dns::update_list_entry,
+            dns::delete_whitelist_batch,
+            dns::delete_blacklist_batch,
+            dns::delete_regexlist_batch,
+            dns::delete_regex_whitelist_batch,
+            groups::get_groups,
+            groups::get_group,
+            groups::add_group,
+            groups::update_group,
+            groups::delete_group,
+            groups::add_group_client,
+            groups::delete_group_client,
+            groups::add_group_domain,
+            groups::delete_group_domain,
             settings::get_dhcp,
             settings::put_dhcp,
+            settings::get_dhcpv6,
+            settings::put_dhcpv6,
+            settings::get_dhcp_leases,
+            settings::delete_dhcp_lease,
+            settings::get_static_leases,
+            settings::add_static_lease,
+            settings::delete_static_lease,
+            settings::get_dhcp_options,
+            settings::add_dhcp_option,
+            settings::delete_dhcp_option,
             settings::get_dns,
             settings::put_dns,
+            settings::get_dns_records,
+            settings::add_dns_record,
+            settings::delete_dns_record,
+            settings::get_cname_records,
+            settings::add_cname_record,
+            settings::delete_cname_record,
             settings::get_ftldb,
             settings::get_ftl,
+            settings::get_ftl_counters,
+            settings::get_ftl_memory,
+            settings::get_all_settings,
+            settings::get_teleporter,
+            settings::post_teleporter,
+            settings::get_settings_diff,
+            settings::get_settings_lint,
             settings::get_network,
             settings::get_web,
-            settings::put_web
+            settings::put_web,
+            settings::get_api_stats,
+            settings::get_api_metrics,
+            settings::get_api_logs,
+            settings::get_notifications,
+            settings::put_notifications,
+            settings::put_settings_batch,
+            settings::get_thresholds,
+            settings::put_thresholds,
+            settings::get_alerts,
+            settings::get_gravity_schedule,
+            settings::put_gravity_schedule,
+            settings::get_privacy,
+            settings::put_privacy,
+            settings::get_logs,
+            settings::put_logs,
+            settings::get_time,
+            settings::put_device_name,
+            settings::get_clients,
+            settings::get_client,
+            settings::put_client,
+            settings::delete_client,
+            settings::get_subnets,
+            settings::put_subnets,
+            settings::get_noise_domains,
+            settings::put_noise_domains
         ])
+        // Answer OPTIONS requests using the mounted routes
+        .attach(AllowedMethods::default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{setup, SharedState};
+    use crate::{
+        env::Env,
+        ftl::FtlConnectionType,
+        plugins::Plugins,
+        process_info::ProcessInfo,
+        routes::stats::{testing::test_memory, CursorSigner}
+    };
+    use rocket::{
+        config::{Config, Environment},
+        local::Client
+    };
+    use std::collections::HashMap;
+
+    /// Create a listener's server from the shared state
+    fn listener(state: SharedState) -> Client {
+        Client::new(setup(
+            rocket::custom(Config::new(Environment::Development)),
+            FtlConnectionType::Test(HashMap::new()),
+            test_memory(),
+            Env::Test(toml::from_str("").unwrap(), HashMap::new()),
+            ProcessInfo::Test {
+                api_uptime: 3600,
+                ftl_uptime: Some(7200)
+            },
+            state,
+            false
+        ))
+        .unwrap()
+    }
+
+    /// Listeners created from the same state share it, so the requests of
+    /// every listener are counted together
+    #[test]
+    fn listeners_share_state() {
+        let env = Env::Test(toml::from_str("").unwrap(), HashMap::new());
+        let state = SharedState::new(
+            &env,
+            "test_key".to_owned(),
+            CursorSigner::test(),
+            Plugins::default()
+        );
+        let first = listener(state.clone());
+        let second = listener(state.clone());
+
+        first.get("/admin/api/dns/status").dispatch();
+        second.get("/admin/api/dns/status").dispatch();
+
+        let histograms = state.request_stats.route_histograms();
+        assert_eq!(histograms.len(), 1);
+        assert_eq!(histograms[0].1.count(), 2);
+    }
+
+    /// Without a plugin feature, a plugin directory stops the API from
+    /// starting
+    #[cfg(not(any(feature = "dylib-plugins", feature = "wasm-plugins")))]
+    #[test]
+    fn plugin_dir_without_feature() {
+        use super::with_loaded_plugins;
+        use crate::util::ErrorKind;
+
+        let config = toml::from_str("[general]\nplugin_dir = \"/etc/pihole/plugins\"").unwrap();
+
+        assert_eq!(
+            with_loaded_plugins(Vec::new(), &config)
+                .map(|_| ())
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::PluginLoad("/etc/pihole/plugins".to_owned()))
+        );
+        assert!(with_loaded_plugins(Vec::new(), &toml::from_str("").unwrap()).is_ok());
+    }
 }
@@ -0,0 +1,482 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Synthetic Test Data Generator
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    databases::ftl::{network, queries},
+    ftl::FtlQueryStatus,
+    util::{current_time, Error, ErrorKind}
+};
+use diesel::{prelude::*, sql_query, sql_types::Integer, SqliteConnection};
+use failure::ResultExt;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path
+};
+
+/// The name of the generated FTL database
+const FTL_DATABASE_NAME: &str = "pihole-FTL.db";
+
+/// The name of the generated Gravity list
+const GRAVITY_LIST_NAME: &str = "gravity.list";
+
+/// The version of the FTL database schema which is generated
+const FTL_DATABASE_VERSION: i32 = 3;
+
+/// The query types which are generated, by their FTL number, and how likely
+/// each one is. The weights add up to 100.
+const QUERY_TYPE_WEIGHTS: &[(i32, u64)] = &[(1, 50), (2, 35), (6, 10), (4, 3), (7, 2)];
+
+/// The upstream servers forwarded queries are sent to
+const UPSTREAMS: &[&str] = &["8.8.8.8", "8.8.4.4", "1.1.1.1"];
+
+/// The percentage of allowed queries which are answered from the cache
+const CACHE_PERCENT: u64 = 30;
+
+/// The options of the generated data, given on the command line
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct TestDataOptions {
+    /// The directory the files are written to
+    pub output: String,
+    pub days: u64,
+    pub clients: usize,
+    pub domains: usize,
+    /// The fraction of queries which are blocked, from 0 to 1
+    pub block_rate: f64,
+    pub queries_per_day: usize,
+    /// The seed of the random data. The same seed generates the same data.
+    pub seed: u64
+}
+
+impl Default for TestDataOptions {
+    fn default() -> Self {
+        TestDataOptions {
+            output: String::new(),
+            days: 7,
+            clients: 10,
+            domains: 1000,
+            block_rate: 0.1,
+            queries_per_day: 10_000,
+            seed: 1
+        }
+    }
+}
+
+impl TestDataOptions {
+    /// Parse the options from the arguments of the `generate-test-data`
+    /// command. The output directory is the only argument without a flag.
+    pub fn parse(args: &[String]) -> Result<TestDataOptions, Error> {
+        let mut options = TestDataOptions::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if !options.output.is_empty() {
+                    return Err(invalid_argument(arg));
+                }
+
+                options.output = arg.to_owned();
+                continue;
+            }
+
+            let value = args.next().ok_or_else(|| invalid_argument(arg))?;
+
+            match arg.as_str() {
+                "--days" => options.days = parse_value(arg, value)?,
+                "--clients" => options.clients = parse_value(arg, value)?,
+                "--domains" => options.domains = parse_value(arg, value)?,
+                "--block-rate" => options.block_rate = parse_value(arg, value)?,
+                "--queries-per-day" => options.queries_per_day = parse_value(arg, value)?,
+                "--seed" => options.seed = parse_value(arg, value)?,
+                _ => return Err(invalid_argument(arg))
+            }
+        }
+
+        if options.output.is_empty() {
+            return Err(Error::from(ErrorKind::InvalidArgument(
+                "missing the output directory".to_owned()
+            )));
+        }
+
+        if options.clients == 0
+            || options.domains == 0
+            || !(options.block_rate >= 0.0 && options.block_rate <= 1.0)
+        {
+            return Err(Error::from(ErrorKind::InvalidArgument(
+                "there must be a client and a domain, and the block rate must be from 0 to 1"
+                    .to_owned()
+            )));
+        }
+
+        Ok(options)
+    }
+
+    /// Get the number of domains which are blocked. There is at least one if
+    /// any queries are blocked.
+    fn blocked_domains(&self) -> usize {
+        if self.block_rate == 0.0 {
+            return 0;
+        }
+
+        ((self.domains as f64 * self.block_rate).round() as usize)
+            .max(1)
+            .min(self.domains)
+    }
+}
+
+/// Generate a synthetic FTL database and Gravity list in the output
+/// directory, for benchmarks and for developing against realistic amounts of
+/// data. The queries end at the current time.
+pub fn generate_test_data(args: &[String]) -> Result<(), Error> {
+    let options = TestDataOptions::parse(args)?;
+    let database_path = Path::new(&options.output).join(FTL_DATABASE_NAME);
+    let gravity_path = Path::new(&options.output).join(GRAVITY_LIST_NAME);
+    let database_location = database_path.to_string_lossy().into_owned();
+
+    // Do not add the queries to an existing database
+    if database_path.exists() {
+        return Err(Error::from(ErrorKind::InvalidArgument(format!(
+            "{} already exists",
+            database_location
+        ))));
+    }
+
+    write_gravity_list(&gravity_path, &options)?;
+
+    let db = SqliteConnection::establish(&database_location).context(ErrorKind::FtlDatabase)?;
+    let now = current_time();
+    let (total, blocked) = write_database(&db, &options, now)?;
+
+    println!(
+        "Generated {} queries ({} blocked) in {}, and {} blocked domains in {}",
+        total,
+        blocked,
+        database_location,
+        options.blocked_domains(),
+        gravity_path.display()
+    );
+
+    Ok(())
+}
+
+/// Get the name of a generated domain. The blocked domains come first.
+fn domain_name(index: usize, options: &TestDataOptions) -> String {
+    if index < options.blocked_domains() {
+        format!("ads{}.example.com", index)
+    } else {
+        format!("domain{}.example.com", index - options.blocked_domains())
+    }
+}
+
+/// Get the IP address of a generated client
+fn client_ip(index: usize) -> String {
+    format!("10.0.{}.{}", index / 250, index % 250 + 1)
+}
+
+/// Write the blocked domains to the Gravity list
+fn write_gravity_list(path: &Path, options: &TestDataOptions) -> Result<(), Error> {
+    let location = path.to_string_lossy().into_owned();
+    let mut writer =
+        BufWriter::new(File::create(path).context(ErrorKind::FileWrite(location.clone()))?);
+
+    for index in 0..options.blocked_domains() {
+        writeln!(writer, "{}", domain_name(index, options))
+            .context(ErrorKind::FileWrite(location.clone()))?;
+    }
+
+    writer.flush().context(ErrorKind::FileWrite(location))?;
+
+    Ok(())
+}
+
+/// Create the FTL tables and fill them with queries over the configured
+/// number of days before `now`. The number of queries and blocked queries
+/// is returned.
+fn write_database(
+    db: &SqliteConnection,
+    options: &TestDataOptions,
+    now: u64
+) -> Result<(usize, usize), Error> {
+    create_ftl_tables(db)?;
+
+    let mut rng = Rng::new(options.seed);
+    let start = now.saturating_sub(options.days * 24 * 60 * 60);
+    let total = options.days as usize * options.queries_per_day;
+    let allowed_domains = options.domains - options.blocked_domains();
+    let mut blocked = 0;
+    let mut client_counts = vec![0i32; options.clients];
+
+    db.transaction::<_, diesel::result::Error, _>(|| {
+        // Insert a day of queries at a time, to keep the memory use low
+        for day in 0..options.days as usize {
+            let mut rows = Vec::with_capacity(options.queries_per_day);
+
+            for i in 0..options.queries_per_day {
+                let number = day * options.queries_per_day + i;
+                // The queries are spread evenly, so they are in order of ID
+                // and timestamp like the queries FTL saves
+                let timestamp = start + (now - start) * number as u64 / total.max(1) as u64;
+                let client = rng.skewed(options.clients);
+                let is_blocked = allowed_domains == 0
+                    || (options.block_rate > 0.0 && rng.chance(options.block_rate));
+
+                let (domain, status, upstream) = if is_blocked {
+                    blocked += 1;
+
+                    (
+                        domain_name(rng.skewed(options.blocked_domains()), options),
+                        FtlQueryStatus::Gravity,
+                        None
+                    )
+                } else {
+                    let domain = domain_name(
+                        options.blocked_domains() + rng.skewed(allowed_domains),
+                        options
+                    );
+
+                    if rng.below(100) < CACHE_PERCENT {
+                        (domain, FtlQueryStatus::Cache, None)
+                    } else {
+                        let upstream = UPSTREAMS[rng.below(UPSTREAMS.len() as u64) as usize];
+                        (domain, FtlQueryStatus::Forward, Some(upstream.to_owned()))
+                    }
+                };
+
+                client_counts[client] += 1;
+                rows.push((
+                    queries::timestamp.eq(timestamp as i32),
+                    queries::query_type.eq(rng.query_type()),
+                    queries::status.eq(status as i32),
+                    queries::domain.eq(domain),
+                    queries::client.eq(client_ip(client)),
+                    queries::upstream.eq(upstream)
+                ));
+            }
+
+            diesel::insert_into(queries::table)
+                .values(&rows)
+                .execute(db)?;
+        }
+
+        let network_rows: Vec<_> = client_counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| {
+                (
+                    network::ip.eq(client_ip(index)),
+                    network::hwaddr.eq(format!(
+                        "02:00:00:00:{:02x}:{:02x}",
+                        index / 256,
+                        index % 256
+                    )),
+                    network::interface.eq("eth0"),
+                    network::name.eq(Some(format!("client{}", index))),
+                    network::firstSeen.eq(start as i32),
+                    network::lastQuery.eq(now as i32),
+                    network::numQueries.eq(count)
+                )
+            })
+            .collect();
+
+        diesel::insert_into(network::table)
+            .values(&network_rows)
+            .execute(db)?;
+
+        // The FTL properties are the schema version and the last saved
+        // timestamp, and the counters are the total and blocked queries
+        for (table, id, value) in &[
+            ("ftl", 0, FTL_DATABASE_VERSION),
+            ("ftl", 1, now as i32),
+            ("counters", 0, total as i32),
+            ("counters", 1, blocked as i32)
+        ] {
+            sql_query(format!("INSERT INTO {} (id, value) VALUES (?, ?)", table))
+                .bind::<Integer, _>(*id)
+                .bind::<Integer, _>(*value)
+                .execute(db)?;
+        }
+
+        Ok(())
+    })
+    .context(ErrorKind::FtlDatabase)?;
+
+    Ok((total, blocked))
+}
+
+/// Create the tables of the FTL database, as FTL creates them
+fn create_ftl_tables(db: &SqliteConnection) -> Result<(), Error> {
+    for statement in &[
+        "CREATE TABLE queries ( id INTEGER PRIMARY KEY AUTOINCREMENT, \
+         timestamp INTEGER NOT NULL, type INTEGER NOT NULL, status INTEGER NOT NULL, \
+         domain TEXT NOT NULL, client TEXT NOT NULL, forward TEXT )",
+        "CREATE TABLE ftl ( id INTEGER PRIMARY KEY NOT NULL, value BLOB NOT NULL )",
+        "CREATE TABLE counters ( id INTEGER PRIMARY KEY NOT NULL, value INTEGER NOT NULL )",
+        "CREATE TABLE network ( id INTEGER PRIMARY KEY NOT NULL, ip TEXT NOT NULL, \
+         hwaddr TEXT NOT NULL, interface TEXT NOT NULL, name TEXT, \
+         firstSeen INTEGER NOT NULL, lastQuery INTEGER NOT NULL, \
+         numQueries INTEGER NOT NULL, macVendor TEXT)",
+        "CREATE INDEX idx_queries_timestamps ON queries (timestamp)"
+    ] {
+        sql_query(*statement)
+            .execute(db)
+            .context(ErrorKind::FtlDatabase)?;
+    }
+
+    Ok(())
+}
+
+/// Create the error for an invalid command line argument
+fn invalid_argument(arg: &str) -> Error {
+    Error::from(ErrorKind::InvalidArgument(arg.to_owned()))
+}
+
+/// Parse the value of a command line flag
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| invalid_argument(&format!("{} {}", flag, value)))
+}
+
+/// A small xorshift random number generator. The data only needs to look
+/// realistic, and a fixed seed makes it the same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Xorshift gets stuck at zero
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Get a number from 0 to 1
+    fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Get a number below `max`
+    fn below(&mut self, max: u64) -> u64 {
+        self.next() % max.max(1)
+    }
+
+    /// Check if something with the probability happens
+    fn chance(&mut self, probability: f64) -> bool {
+        self.fraction() < probability
+    }
+
+    /// Get an index below `count`, where lower indexes are more likely. Real
+    /// traffic goes mostly to a few popular domains and busy clients.
+    fn skewed(&mut self, count: usize) -> usize {
+        let fraction = self.fraction();
+
+        ((fraction * fraction * count as f64) as usize).min(count.saturating_sub(1))
+    }
+
+    /// Get a query type, using the weights of the generated types
+    fn query_type(&mut self) -> i32 {
+        let mut roll = self.below(100);
+
+        for &(query_type, weight) in QUERY_TYPE_WEIGHTS {
+            if roll < weight {
+                return query_type;
+            }
+
+            roll -= weight;
+        }
+
+        QUERY_TYPE_WEIGHTS[0].0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_database, TestDataOptions};
+    use crate::databases::ftl::{counters, queries};
+    use diesel::{prelude::*, SqliteConnection};
+
+    /// Convert the arguments to owned strings
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    /// The flags override the defaults
+    #[test]
+    fn parse_options() {
+        let options = TestDataOptions::parse(&args(&[
+            "--days",
+            "30",
+            "/tmp/data",
+            "--block-rate",
+            "0.25",
+            "--clients",
+            "50"
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            options,
+            TestDataOptions {
+                output: "/tmp/data".to_owned(),
+                days: 30,
+                clients: 50,
+                block_rate: 0.25,
+                ..TestDataOptions::default()
+            }
+        );
+    }
+
+    /// Unknown flags, flags without values, and invalid values are errors
+    #[test]
+    fn invalid_options() {
+        assert!(TestDataOptions::parse(&args(&["/tmp/data", "--weeks", "2"])).is_err());
+        assert!(TestDataOptions::parse(&args(&["/tmp/data", "--days"])).is_err());
+        assert!(TestDataOptions::parse(&args(&["/tmp/data", "--block-rate", "2"])).is_err());
+        assert!(TestDataOptions::parse(&args(&["--days", "2"])).is_err());
+    }
+
+    /// The database has the requested number of queries, and the blocked
+    /// queries are counted
+    #[test]
+    fn generate_database() {
+        let db = SqliteConnection::establish(":memory:").unwrap();
+        let options = TestDataOptions {
+            days: 2,
+            clients: 3,
+            domains: 20,
+            block_rate: 0.5,
+            queries_per_day: 100,
+            ..TestDataOptions::default()
+        };
+
+        let (total, blocked) = write_database(&db, &options, 1_000_000).unwrap();
+        let query_count: i64 = queries::table.count().get_result(&db).unwrap();
+        let blocked_count: i64 = queries::table
+            .filter(queries::status.eq(1))
+            .count()
+            .get_result(&db)
+            .unwrap();
+        let saved_blocked: i32 = counters::table
+            .filter(counters::id.eq(1))
+            .select(counters::value)
+            .first(&db)
+            .unwrap();
+
+        assert_eq!(total, 200);
+        assert_eq!(query_count, 200);
+        assert_eq!(blocked_count as usize, blocked);
+        assert_eq!(saved_blocked as usize, blocked);
+        assert!(blocked > 0 && blocked < 200);
+    }
+}
@@ -26,6 +26,20 @@ pub fn write_eom(data: &mut Vec<u8>) {
     data.push(0xc1);
 }
 
+/// Create a test `FtlMemory` without any data
+pub fn empty_ftl_memory() -> FtlMemory {
+    FtlMemory::Test {
+        clients: Vec::new(),
+        domains: Vec::new(),
+        over_time: Vec::new(),
+        queries: Vec::new(),
+        upstreams: Vec::new(),
+        strings: HashMap::new(),
+        counters: FtlCounters::default(),
+        settings: FtlSettings::default()
+    }
+}
+
 /// Builds the data needed to create a `Env::Test`
 pub struct TestEnvBuilder {
     test_files: Vec<TestFile<NamedTempFile>>
@@ -144,7 +158,10 @@ pub struct TestBuilder {
     ftl_memory: FtlMemory,
     test_config_builder: TestEnvBuilder,
     expected_json: serde_json::Value,
+    expect_empty_body: bool,
+    expected_body: Option<String>,
     expected_status: Status,
+    expected_headers: Vec<(String, String)>,
     needs_database: bool
 }
 
@@ -157,23 +174,17 @@ impl TestBuilder {
             should_auth: true,
             body_data: None,
             ftl_data: HashMap::new(),
-            ftl_memory: FtlMemory::Test {
-                clients: Vec::new(),
-                domains: Vec::new(),
-                over_time: Vec::new(),
-                queries: Vec::new(),
-                upstreams: Vec::new(),
-                strings: HashMap::new(),
-                counters: FtlCounters::default(),
-                settings: FtlSettings::default()
-            },
+            ftl_memory: empty_ftl_memory(),
             test_config_builder: TestEnvBuilder::new(),
             expected_json: json!({
                 "data": [],
                 "errors": []
             })
             .into(),
+            expect_empty_body: false,
+            expected_body: None,
             expected_status: Status::Ok,
+            expected_headers: Vec::new(),
             needs_database: false
         }
     }
@@ -235,11 +246,27 @@ impl TestBuilder {
         self
     }
 
+    pub fn expect_empty_body(mut self) -> Self {
+        self.expect_empty_body = true;
+        self
+    }
+
+    pub fn expect_body(mut self, expected_body: &str) -> Self {
+        self.expected_body = Some(expected_body.to_owned());
+        self
+    }
+
     pub fn expect_status(mut self, status: Status) -> Self {
         self.expected_status = status;
         self
     }
 
+    pub fn expect_header(mut self, name: &str, value: &str) -> Self {
+        self.expected_headers
+            .push((name.to_owned(), value.to_owned()));
+        self
+    }
+
     pub fn need_database(mut self, need_database: bool) -> Self {
         self.needs_database = need_database;
         self
@@ -285,18 +312,32 @@ impl TestBuilder {
         // Check the status
         assert_eq!(self.expected_status, response.status());
 
-        // Check that something was returned
+        // Check the headers
+        for (name, value) in &self.expected_headers {
+            assert_eq!(Some(value.as_str()), response.headers().get_one(name));
+        }
+
         let body = response.body_string();
-        assert!(body.is_some());
 
-        let body_str = body.unwrap();
-        println!("Body:\n{}", body_str);
+        if self.expect_empty_body {
+            // Check that nothing was returned
+            assert_eq!(body.unwrap_or_default(), "");
+        } else if let Some(expected_body) = self.expected_body {
+            // Check that the body is the same as the expected text
+            assert_eq!(Some(expected_body), body);
+        } else {
+            // Check that something was returned
+            assert!(body.is_some());
 
-        // Check that it is correct JSON
-        let parsed: serde_json::Value = serde_json::from_str(&body_str).unwrap();
+            let body_str = body.unwrap();
+            println!("Body:\n{}", body_str);
 
-        // Check that is is the same as the expected JSON
-        assert_eq!(self.expected_json, parsed);
+            // Check that it is correct JSON
+            let parsed: serde_json::Value = serde_json::from_str(&body_str).unwrap();
+
+            // Check that is is the same as the expected JSON
+            assert_eq!(self.expected_json, parsed);
+        }
 
         // Check the files against the expected data
         let mut buffer = String::new();
@@ -0,0 +1,284 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// User Accounts
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    env::{Env, PiholeFile},
+    util::{random_base64, Error, ErrorKind}
+};
+use failure::ResultExt;
+use hmac::{
+    crypto_mac::MacResult,
+    digest::generic_array::{typenum::U32, GenericArray},
+    Hmac, Mac
+};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use std::io::{Read, Write};
+
+/// The maximum length of an account name
+const MAX_NAME_LENGTH: usize = 32;
+
+/// The minimum length of a password
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The maximum size of an account's preferences, in bytes of JSON
+const MAX_PREFERENCES_SIZE: usize = 16384;
+
+/// The number of PBKDF2 iterations used for new password hashes. It is
+/// stored with each hash, so it can be raised without breaking existing
+/// accounts.
+#[cfg(not(test))]
+const PASSWORD_ITERATIONS: u32 = 100_000;
+
+/// The number of PBKDF2 iterations used for new password hashes, which is
+/// lowered during tests to keep them fast
+#[cfg(test)]
+const PASSWORD_ITERATIONS: u32 = 1000;
+
+/// What an account is allowed to do
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can read and change everything, including the other accounts
+    Admin,
+    /// Can only read, and change their own preferences
+    Viewer
+}
+
+/// A named user account with its own password and preferences. Logging in
+/// with the API key instead of an account gives the admin role.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Account {
+    pub name: String,
+    pub role: Role,
+    /// The salt of the password hash, in base64
+    salt: String,
+    /// The number of PBKDF2 iterations used to hash the password
+    iterations: u32,
+    /// The password hashed with PBKDF2-HMAC-SHA256 using the salt, in base64
+    password_hash: String,
+    /// The UI preferences, such as saved filters and the dashboard layout
+    #[serde(default)]
+    pub preferences: Map<String, Value>
+}
+
+/// The account information which is shown by the API
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct AccountInfo {
+    pub name: String,
+    pub role: Role
+}
+
+impl Account {
+    /// Create a new account with no preferences
+    pub fn new(name: String, password: &str, role: Role) -> Result<Account, Error> {
+        if !Account::is_valid_name(&name) {
+            return Err(Error::from(ErrorKind::InvalidAccount));
+        }
+
+        let mut account = Account {
+            name,
+            role,
+            salt: String::new(),
+            iterations: PASSWORD_ITERATIONS,
+            password_hash: String::new(),
+            preferences: Map::new()
+        };
+        account.set_password(password)?;
+
+        Ok(account)
+    }
+
+    /// Replace the password, using a new salt
+    pub fn set_password(&mut self, password: &str) -> Result<(), Error> {
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(Error::from(ErrorKind::InvalidAccount));
+        }
+
+        self.salt = random_base64(16)?;
+        self.iterations = PASSWORD_ITERATIONS;
+        self.password_hash = base64::encode(&hash_password(&self.salt, self.iterations, password));
+
+        Ok(())
+    }
+
+    /// Check if the password is the account's password. The hashes are
+    /// compared in constant time.
+    pub fn password_matches(&self, password: &str) -> bool {
+        match base64::decode(&self.password_hash) {
+            Ok(ref expected) if expected.len() == 32 => {
+                let expected = MacResult::new(GenericArray::clone_from_slice(expected));
+                let actual = MacResult::new(hash_password(&self.salt, self.iterations, password));
+
+                expected == actual
+            }
+            _ => false
+        }
+    }
+
+    /// Replace the preferences, if they are not too large
+    pub fn set_preferences(&mut self, preferences: Map<String, Value>) -> Result<(), Error> {
+        let size = serde_json::to_vec(&preferences)
+            .context(ErrorKind::InvalidAccount)?
+            .len();
+
+        if size > MAX_PREFERENCES_SIZE {
+            return Err(Error::from(ErrorKind::InvalidAccount));
+        }
+
+        self.preferences = preferences;
+        Ok(())
+    }
+
+    /// Get the account information without the password
+    pub fn info(&self) -> AccountInfo {
+        AccountInfo {
+            name: self.name.clone(),
+            role: self.role
+        }
+    }
+
+    /// Names are stored in the session cookie, so they are limited to
+    /// letters, numbers, and `.`, `_`, and `-`
+    fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= MAX_NAME_LENGTH
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+    }
+
+    /// Read the accounts. If the file does not exist, there are no accounts.
+    pub fn read_all(env: &Env) -> Result<Vec<Account>, Error> {
+        if !env.file_exists(PiholeFile::ApiUsers) {
+            return Ok(Vec::new());
+        }
+
+        let file_location = env.file_location(PiholeFile::ApiUsers).to_owned();
+        let mut json = String::new();
+        env.read_file(PiholeFile::ApiUsers)?
+            .read_to_string(&mut json)
+            .context(ErrorKind::FileRead(file_location.clone()))?;
+
+        if json.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(serde_json::from_str(&json).context(ErrorKind::FileRead(file_location))?)
+    }
+
+    /// Save the accounts, replacing the previously saved accounts
+    pub fn write_all(env: &Env, accounts: &[Account]) -> Result<(), Error> {
+        let file_location = env.file_location(PiholeFile::ApiUsers).to_owned();
+        let mut file = env.write_file(PiholeFile::ApiUsers, false)?;
+
+        serde_json::to_writer(&mut file, accounts)
+            .context(ErrorKind::FileWrite(file_location.clone()))?;
+        writeln!(file).context(ErrorKind::FileWrite(file_location))?;
+
+        Ok(())
+    }
+
+    /// Find the account with the name
+    pub fn find(env: &Env, name: &str) -> Result<Option<Account>, Error> {
+        Ok(Account::read_all(env)?
+            .into_iter()
+            .find(|account| account.name == name))
+    }
+}
+
+/// Hash the password with PBKDF2-HMAC-SHA256. Only the first block of the
+/// derived key is needed, because it is as long as the hash.
+fn hash_password(salt: &str, iterations: u32, password: &str) -> GenericArray<u8, U32> {
+    // HMAC accepts keys of any size, so this will not fail
+    let prf = Hmac::<Sha256>::new_varkey(password.as_bytes()).unwrap();
+
+    let mut mac = prf.clone();
+    mac.input(salt.as_bytes());
+    mac.input(&1u32.to_be_bytes());
+    let mut block = mac.result().code();
+    let mut hash = block;
+
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.input(&block);
+        block = mac.result().code();
+
+        for (hash_byte, block_byte) in hash.iter_mut().zip(block.iter()) {
+            *hash_byte ^= block_byte;
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_password, Account, Role};
+    use serde_json::{Map, Value};
+
+    /// Only the account's password matches
+    #[test]
+    fn password() {
+        let account = Account::new("alice".to_owned(), "correct horse", Role::Viewer).unwrap();
+
+        assert!(account.password_matches("correct horse"));
+        assert!(!account.password_matches("battery staple"));
+    }
+
+    /// The hash matches the common PBKDF2-HMAC-SHA256 test vectors
+    #[test]
+    fn pbkdf2_vectors() {
+        assert_eq!(
+            base64::encode(&hash_password("salt", 1, "password")),
+            "Eg+2z/z4syxD5yJSVsT4N6hlSMkszDVICAWYfLcL4Xs="
+        );
+        assert_eq!(
+            base64::encode(&hash_password("salt", 4096, "password")),
+            "xeR41ZKIyEGqUw22hFxMjZYok6ABzk4RpJY4c6qYE0o="
+        );
+    }
+
+    /// The hash is checked with the iterations stored in the account, so
+    /// changing the default does not break existing accounts
+    #[test]
+    fn stored_iterations() {
+        let mut account = Account::new("alice".to_owned(), "correct horse", Role::Viewer).unwrap();
+        account.iterations = 2;
+        account.password_hash = base64::encode(&hash_password(&account.salt, 2, "correct horse"));
+
+        assert!(account.password_matches("correct horse"));
+    }
+
+    /// Names which can not be stored in the session cookie are not allowed
+    #[test]
+    fn invalid_name() {
+        assert!(Account::new("".to_owned(), "password", Role::Admin).is_err());
+        assert!(Account::new("al:ice".to_owned(), "password", Role::Admin).is_err());
+    }
+
+    /// Short passwords are not allowed
+    #[test]
+    fn short_password() {
+        assert!(Account::new("alice".to_owned(), "short", Role::Admin).is_err());
+    }
+
+    /// Preferences are limited in size
+    #[test]
+    fn large_preferences() {
+        let mut account = Account::new("alice".to_owned(), "password", Role::Admin).unwrap();
+        let mut preferences = Map::new();
+        preferences.insert("layout".to_owned(), Value::String("x".repeat(20000)));
+
+        assert!(account.set_preferences(preferences).is_err());
+    }
+}
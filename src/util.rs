@@ -8,9 +8,9 @@
 // This file is copyright under the latest version of the EUPL.
 // Please see LICENSE file for your rights under this license.
 
-use failure::{Backtrace, Context, Fail};
+use failure::{Backtrace, Context, Fail, ResultExt};
 use rocket::{
-    http::Status,
+    http::{ContentType, Status},
     request,
     response::{self, Responder, Response},
     Outcome, Request
@@ -20,9 +20,15 @@ use serde::Serialize;
 use shmem;
 use std::{
     env,
-    fmt::{self, Display}
+    fmt::{self, Display},
+    fs::File,
+    io::{Cursor, Read},
+    time::{SystemTime, UNIX_EPOCH}
 };
 
+/// The source of random bytes for keys and salts
+const RANDOM_SOURCE: &str = "/dev/urandom";
+
 /// Type alias for the most common return type of the API methods
 pub type Reply = Result<SetStatus<JsonValue>, Error>;
 
@@ -34,7 +40,10 @@ pub fn reply<D: Serialize>(data: Result<D, Error>, status: Status) -> Reply {
         Err(e) => {
             // Only print out the error if it's not a common error
             match e.kind() {
-                ErrorKind::Unauthorized | ErrorKind::NotFound => (),
+                ErrorKind::Unauthorized
+                | ErrorKind::Forbidden
+                | ErrorKind::NotFound
+                | ErrorKind::RateLimited => (),
                 _ => e.print_stacktrace()
             }
 
@@ -86,6 +95,41 @@ pub fn reply_success() -> Reply {
     reply(Ok(json!({ "status": "success" })), Status::Ok)
 }
 
+/// Generate `len` random bytes, encoded in base64
+pub fn random_base64(len: usize) -> Result<String, Error> {
+    let mut random = vec![0u8; len];
+    File::open(RANDOM_SOURCE)
+        .and_then(|mut file| file.read_exact(&mut random))
+        .context(ErrorKind::FileRead(RANDOM_SOURCE.to_owned()))?;
+
+    Ok(base64::encode(&random))
+}
+
+/// Get the current Unix timestamp. A clock set before the epoch gives 0.
+pub fn current_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Get the path of the request the way Rocket routes it. Rocket skips empty
+/// path segments, so `//admin/api//auth` is routed like `/admin/api/auth`, and
+/// checks of the path must see it the same way.
+pub fn routed_path(request: &Request) -> String {
+    let path: String = request
+        .uri()
+        .segments()
+        .map(|segment| format!("/{}", segment))
+        .collect();
+
+    if path.is_empty() {
+        "/".to_owned()
+    } else {
+        path
+    }
+}
+
 /// Wraps `ErrorKind` to provide context via `Context`.
 ///
 /// See https://boats.gitlab.io/failure/error-errorkind.html
@@ -102,6 +146,10 @@ pub enum ErrorKind {
     Unknown,
     #[fail(display = "Failed to create the blocklist")]
     GravityError,
+    #[fail(display = "Gravity is already updating")]
+    GravityRunning,
+    #[fail(display = "A client is already being exported")]
+    ClientExportRunning,
     #[fail(display = "Failed to connect to FTL")]
     FtlConnectionFail,
     #[fail(display = "Error reading from FTL")]
@@ -118,6 +166,8 @@ pub enum ErrorKind {
     BadRequest,
     #[fail(display = "Unauthorized")]
     Unauthorized,
+    #[fail(display = "Forbidden")]
+    Forbidden,
     #[fail(display = "Error reading from {}", _0)]
     FileRead(String),
     #[fail(display = "Error writing to {}", _0)]
@@ -148,7 +198,32 @@ pub enum ErrorKind {
     )]
     SharedMemoryVersion(usize, usize),
     #[fail(display = "Error while interacting with the FTL database")]
-    FtlDatabase
+    FtlDatabase,
+    #[fail(display = "History cursor has expired")]
+    CursorExpired,
+    #[fail(display = "Invalid saved view")]
+    InvalidSavedView,
+    #[fail(display = "Invalid group")]
+    InvalidGroup,
+    #[fail(display = "Invalid account")]
+    InvalidAccount,
+    #[fail(display = "Too many requests")]
+    RateLimited,
+    #[fail(display = "Invalid alert rule: {}", _0)]
+    InvalidAlertRule(String),
+    #[fail(display = "Invalid command line argument: {}", _0)]
+    InvalidArgument(String),
+    #[fail(display = "Invalid plugin name: {}", _0)]
+    InvalidPlugin(String),
+    #[fail(display = "Failed to load plugin: {}", _0)]
+    PluginLoad(String),
+    #[fail(
+        display = "Plugin was built with a different version of the API or compiler: {}",
+        _0
+    )]
+    PluginVersion(String),
+    #[fail(display = "Plugin failed: {}", _0)]
+    PluginFailed(String)
 }
 
 impl Error {
@@ -216,6 +291,8 @@ impl ErrorKind {
         match self {
             ErrorKind::Unknown => "unknown",
             ErrorKind::GravityError => "gravity_error",
+            ErrorKind::GravityRunning => "gravity_running",
+            ErrorKind::ClientExportRunning => "client_export_running",
             ErrorKind::FtlConnectionFail => "ftl_connection_fail",
             ErrorKind::FtlReadError => "ftl_read_error",
             ErrorKind::FtlEomError => "ftl_eom_error",
@@ -224,6 +301,7 @@ impl ErrorKind {
             ErrorKind::InvalidDomain => "invalid_domain",
             ErrorKind::BadRequest => "bad_request",
             ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::Forbidden => "forbidden",
             ErrorKind::FileRead(_) => "file_read",
             ErrorKind::FileWrite(_) => "file_write",
             ErrorKind::ConfigParsingError => "config_parsing_error",
@@ -235,7 +313,18 @@ impl ErrorKind {
             ErrorKind::SharedMemoryRead => "shared_memory_read",
             ErrorKind::SharedMemoryLock => "shared_memory_lock",
             ErrorKind::SharedMemoryVersion(_, _) => "shared_memory_version",
-            ErrorKind::FtlDatabase => "ftl_database"
+            ErrorKind::FtlDatabase => "ftl_database",
+            ErrorKind::CursorExpired => "cursor_expired",
+            ErrorKind::InvalidSavedView => "invalid_saved_view",
+            ErrorKind::InvalidGroup => "invalid_group",
+            ErrorKind::InvalidAccount => "invalid_account",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::InvalidAlertRule(_) => "invalid_alert_rule",
+            ErrorKind::InvalidArgument(_) => "invalid_argument",
+            ErrorKind::InvalidPlugin(_) => "invalid_plugin",
+            ErrorKind::PluginLoad(_) => "plugin_load",
+            ErrorKind::PluginVersion(_) => "plugin_version",
+            ErrorKind::PluginFailed(_) => "plugin_failed"
         }
     }
 
@@ -243,11 +332,21 @@ impl ErrorKind {
     pub fn status(&self) -> Status {
         match self {
             ErrorKind::NotFound => Status::NotFound,
-            ErrorKind::AlreadyExists => Status::Conflict,
-            ErrorKind::InvalidDomain | ErrorKind::BadRequest | ErrorKind::InvalidSettingValue => {
-                Status::BadRequest
-            }
+            ErrorKind::AlreadyExists
+            | ErrorKind::GravityRunning
+            | ErrorKind::ClientExportRunning => Status::Conflict,
+            ErrorKind::InvalidDomain
+            | ErrorKind::BadRequest
+            | ErrorKind::InvalidSettingValue
+            | ErrorKind::CursorExpired
+            | ErrorKind::InvalidSavedView
+            | ErrorKind::InvalidGroup
+            | ErrorKind::InvalidAccount
+            | ErrorKind::InvalidAlertRule(_)
+            | ErrorKind::InvalidArgument(_) => Status::BadRequest,
             ErrorKind::Unauthorized => Status::Unauthorized,
+            ErrorKind::Forbidden => Status::Forbidden,
+            ErrorKind::RateLimited => Status::TooManyRequests,
             ErrorKind::Unknown
             | ErrorKind::GravityError
             | ErrorKind::FtlConnectionFail
@@ -256,6 +355,10 @@ impl ErrorKind {
             | ErrorKind::FileRead(_)
             | ErrorKind::FileWrite(_)
             | ErrorKind::ConfigParsingError
+            | ErrorKind::InvalidPlugin(_)
+            | ErrorKind::PluginLoad(_)
+            | ErrorKind::PluginVersion(_)
+            | ErrorKind::PluginFailed(_)
             | ErrorKind::RestartDnsError
             | ErrorKind::ReloadDnsError
             | ErrorKind::DnsmasqConfigWrite
@@ -272,6 +375,7 @@ impl ErrorKind {
         match self {
             ErrorKind::FileRead(file) => Some(json!({ "file": file })),
             ErrorKind::FileWrite(file) => Some(json!({ "file": file })),
+            ErrorKind::InvalidAlertRule(reason) => Some(json!({ "reason": reason })),
             _ => None
         }
     }
@@ -327,6 +431,91 @@ impl<'r> Responder<'r> for Error {
     }
 }
 
+/// This wraps another Responder and allows clients to cache successful
+/// responses for the number of seconds. Errors are never cached.
+#[derive(Debug)]
+pub struct Cached<R>(pub R, pub u32);
+
+impl<'r, R: Responder<'r>> Responder<'r> for Cached<R> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let mut response = self.0.respond_to(request)?;
+
+        if response.status().class().is_success() {
+            response.set_raw_header("Cache-Control", format!("public, max-age={}", self.1));
+        }
+
+        Ok(response)
+    }
+}
+
+/// A CSV file which is downloaded by the browser instead of being shown
+#[derive(Debug)]
+pub struct CsvFile {
+    /// The suggested file name
+    pub name: String,
+    pub data: String
+}
+
+impl<'r> Responder<'r> for CsvFile {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::CSV)
+            .raw_header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.name)
+            )
+            .sized_body(Cursor::new(self.data))
+            .ok()
+    }
+}
+
+/// A gzipped tar archive which is downloaded by the browser
+#[derive(Debug)]
+pub struct TarGzFile {
+    /// The suggested file name
+    pub name: String,
+    pub data: Vec<u8>
+}
+
+impl<'r> Responder<'r> for TarGzFile {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::new("application", "gzip"))
+            .raw_header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.name)
+            )
+            .sized_body(Cursor::new(self.data))
+            .ok()
+    }
+}
+
+/// The headers of a large reply, for answering a HEAD request without
+/// building the body. Rocket would otherwise answer HEAD with the GET route
+/// and drop the body after building it.
+#[derive(Debug)]
+pub struct HeadReply {
+    pub content_type: ContentType,
+    /// The suggested file name, if the reply is downloaded
+    pub file_name: Option<String>
+}
+
+impl<'r> Responder<'r> for HeadReply {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        let mut response = Response::build();
+        response.header(self.content_type);
+
+        if let Some(name) = self.file_name {
+            response.raw_header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", name)
+            );
+        }
+
+        response.ok()
+    }
+}
+
 /// This wraps another Responder and sets the HTTP status
 #[derive(Debug)]
 pub struct SetStatus<R>(R, Status);
@@ -0,0 +1,630 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// WebAssembly Plugins
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+use crate::{
+    plugins::{Plugin, QueryEvent},
+    routes::auth::User,
+    util::{reply, Error, ErrorKind, Reply}
+};
+use failure::ResultExt;
+use rocket::{
+    handler::{self, Handler},
+    http::{uri::Segments, Method, Status},
+    Data, Outcome, Request, Route
+};
+use serde_json::Value;
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc, Mutex
+    },
+    thread,
+    time::Duration
+};
+use wasmi::{
+    memory_units::Pages, ExternVal, ImportsBuilder, MemoryRef, Module, ModuleInstance, ModuleRef,
+    NopExternals, RuntimeValue
+};
+
+/// The version of the WebAssembly plugin interface, which a module's
+/// `pihole_plugin_version` export must return
+const WASM_PLUGIN_VERSION: i32 = 1;
+
+/// The extension of the files in the plugin directory which are loaded
+const MODULE_EXTENSION: &str = "wasm";
+
+/// The most memory a module may declare, in 64 KiB pages
+const MAX_MEMORY_PAGES: usize = 256;
+
+/// How long a module may take to start or to answer a request
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many queries may wait for a module before new ones are dropped
+const MAX_WAITING_QUERIES: usize = 1024;
+
+/// The largest request body which is sent to a module, in bytes
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+/// The methods of the routes which are mounted for a module
+const METHODS: &[Method] = &[
+    Method::Get,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete
+];
+
+/// A plugin which runs a WebAssembly module. Unlike a plugin library, the
+/// module is sandboxed: it can not import anything, so it can only use its
+/// own memory and the JSON it is sent. The module must export:
+///
+/// - `memory`, with a maximum size of at most 16 MiB
+/// - `pihole_plugin_version() -> i32`, which returns the interface version
+/// - `alloc(len: i32) -> i32`, which reserves memory for JSON sent to the
+///   module and returns its address
+///
+/// It can also export:
+///
+/// - `on_query(ptr: i32, len: i32)`, which is sent each query as JSON
+/// - `handle_request(ptr: i32, len: i32) -> i64`, which is sent each request to
+///   the plugin's routes as JSON with the `method`, `path`, `query`, and
+///   `body`. It returns the address of the response in the upper 32 bits and
+///   its length in the lower 32 bits. The response is JSON with the `data` to
+///   reply with, and optionally the HTTP `status`.
+struct WasmPlugin {
+    calls: Arc<PluginCalls>,
+    handles_requests: bool
+}
+
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.calls.name
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        if !self.handles_requests {
+            return Vec::new();
+        }
+
+        let handler = RequestHandler {
+            calls: self.calls.clone()
+        };
+
+        METHODS
+            .iter()
+            .flat_map(|&method| {
+                vec![
+                    Route::new(method, "/", handler.clone()),
+                    Route::new(method, "/<path..>", handler.clone()),
+                ]
+            })
+            .collect()
+    }
+
+    fn on_query(&self, query: &QueryEvent) {
+        let query = json!({
+            "timestamp": query.timestamp,
+            "domain": query.domain,
+            "client": query.client,
+            "status": query.status,
+            "blocked": query.blocked
+        });
+
+        self.calls.on_query(query.to_string());
+    }
+}
+
+/// Sends calls to a module's instance. Instances can not be shared between
+/// threads, so each one runs in its own thread which takes calls from a
+/// channel.
+struct PluginCalls {
+    name: String,
+    sender: Mutex<SyncSender<Call>>,
+    timeout: Duration,
+    /// Set when a call times out. The interpreter can not stop a running
+    /// call, so the module's thread stays busy and no more calls are sent.
+    timed_out: AtomicBool
+}
+
+/// A call to a module's instance, with its JSON input
+enum Call {
+    Query(String),
+    Request(String, Sender<Result<String, Error>>)
+}
+
+impl PluginCalls {
+    /// Send the query to the module. If the module is not keeping up, the
+    /// query is dropped instead of waiting for it.
+    fn on_query(&self, query: String) {
+        if !self.timed_out.load(Ordering::Relaxed) {
+            let _ = self.sender.lock().unwrap().try_send(Call::Query(query));
+        }
+    }
+
+    /// Send the request to the module and wait for its response
+    fn handle_request(&self, request: String) -> Result<String, Error> {
+        let error = || Error::from(ErrorKind::PluginFailed(self.name.clone()));
+
+        if self.timed_out.load(Ordering::Relaxed) {
+            return Err(error());
+        }
+
+        let (response_sender, response) = mpsc::channel();
+        self.sender
+            .lock()
+            .unwrap()
+            .try_send(Call::Request(request, response_sender))
+            .map_err(|_| error())?;
+
+        match response.recv_timeout(self.timeout) {
+            Ok(response) => response,
+            Err(_) => {
+                self.timed_out.store(true, Ordering::Relaxed);
+                Err(error())
+            }
+        }
+    }
+}
+
+/// Answers the requests to a module's routes. The routes need the same
+/// authentication as the rest of the API.
+#[derive(Clone)]
+struct RequestHandler {
+    calls: Arc<PluginCalls>
+}
+
+impl Handler for RequestHandler {
+    fn handle<'r>(&self, request: &'r Request, data: Data) -> handler::Outcome<'r> {
+        match request.guard::<User>() {
+            Outcome::Success(_) => (),
+            Outcome::Failure((status, _)) => return Outcome::Failure(status),
+            Outcome::Forward(()) => return Outcome::Forward(data)
+        }
+
+        handler::Outcome::from(request, self.reply(request, data))
+    }
+}
+
+impl RequestHandler {
+    /// Send the request to the module and convert its response to a reply
+    fn reply(&self, request: &Request, data: Data) -> Reply {
+        let path = match request.get_segments::<Segments>(0) {
+            Some(Ok(segments)) => format!("/{}", segments.collect::<Vec<_>>().join("/")),
+            _ => "/".to_owned()
+        };
+
+        let mut body = String::new();
+        data.open()
+            .take(MAX_BODY_SIZE + 1)
+            .read_to_string(&mut body)
+            .context(ErrorKind::BadRequest)?;
+
+        if body.len() as u64 > MAX_BODY_SIZE {
+            return Err(Error::from(ErrorKind::BadRequest));
+        }
+
+        let request_json = json!({
+            "method": request.method().as_str(),
+            "path": path,
+            "query": request.uri().query(),
+            "body": body
+        });
+
+        let response = self.calls.handle_request(request_json.to_string())?;
+        let (data, status) = parse_response(&response)
+            .ok_or_else(|| Error::from(ErrorKind::PluginFailed(self.calls.name.clone())))?;
+
+        reply(Ok(data), status)
+    }
+}
+
+/// Get the data and status of a module's response. The status is 200 OK if
+/// it is not set.
+fn parse_response(response: &str) -> Option<(Value, Status)> {
+    let mut response: Value = serde_json::from_str(response).ok()?;
+    let status = match response.get("status") {
+        Some(status) => Status::from_code(status.as_u64()? as u16)?,
+        None => Status::Ok
+    };
+
+    Some((response.get_mut("data")?.take(), status))
+}
+
+/// Load the module of each WebAssembly file in the directory, in order of
+/// file name. Each plugin is named after its file.
+pub fn load_plugins(plugin_dir: &str) -> Result<Vec<Box<dyn Plugin>>, Error> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(plugin_dir)
+        .context(ErrorKind::PluginLoad(plugin_dir.to_owned()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == MODULE_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| load_plugin(path, CALL_TIMEOUT))
+        .collect()
+}
+
+/// Start the module in its own thread, and wait for it to be ready
+fn load_plugin(path: &Path, timeout: Duration) -> Result<Box<dyn Plugin>, Error> {
+    let path_name = path.display().to_string();
+    let error = || ErrorKind::PluginLoad(path_name.clone());
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .ok_or_else(error)?;
+    let wasm = fs::read(path).context(error())?;
+
+    let (sender, calls) = mpsc::sync_channel(MAX_WAITING_QUERIES);
+    let (ready_sender, ready) = mpsc::channel();
+    let thread_name = name.clone();
+    let thread_path = path_name.clone();
+
+    thread::Builder::new()
+        .name(format!("Plugin {}", name))
+        .spawn(
+            move || match Instance::new(thread_name, &thread_path, &wasm) {
+                Ok(instance) => {
+                    let _ = ready_sender.send(Ok(instance.handles_requests()));
+                    instance.run(&calls);
+                }
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                }
+            }
+        )
+        .context(error())?;
+
+    let handles_requests = ready.recv_timeout(timeout).context(error())??;
+
+    Ok(Box::new(WasmPlugin {
+        calls: Arc::new(PluginCalls {
+            name,
+            sender: Mutex::new(sender),
+            timeout,
+            timed_out: AtomicBool::new(false)
+        }),
+        handles_requests
+    }))
+}
+
+/// An instance of a module, which only lives in its plugin's thread
+struct Instance {
+    name: String,
+    module: ModuleRef,
+    memory: MemoryRef
+}
+
+impl Instance {
+    /// Instantiate the module and check that it follows the plugin
+    /// interface. Its memory is checked before its start function runs.
+    fn new(name: String, path: &str, wasm: &[u8]) -> Result<Instance, Error> {
+        let error = || ErrorKind::PluginLoad(path.to_owned());
+        let module = Module::from_buffer(wasm).context(error())?;
+
+        // Nothing is provided to import, so modules with imports fail here
+        let not_started =
+            ModuleInstance::new(&module, &ImportsBuilder::default()).context(error())?;
+
+        let memory = match not_started.not_started_instance().export_by_name("memory") {
+            Some(ExternVal::Memory(memory)) => memory,
+            _ => return Err(Error::from(error()))
+        };
+
+        if memory
+            .maximum()
+            .map_or(true, |maximum| maximum > Pages(MAX_MEMORY_PAGES))
+        {
+            return Err(Error::from(error()));
+        }
+
+        let module = not_started.run_start(&mut NopExternals).context(error())?;
+        let instance = Instance {
+            name,
+            module,
+            memory
+        };
+
+        let version = instance
+            .module
+            .invoke_export("pihole_plugin_version", &[], &mut NopExternals)
+            .context(error())?;
+
+        match version {
+            Some(RuntimeValue::I32(WASM_PLUGIN_VERSION)) => Ok(instance),
+            _ => Err(Error::from(ErrorKind::PluginVersion(path.to_owned())))
+        }
+    }
+
+    /// Check if the module answers requests
+    fn handles_requests(&self) -> bool {
+        self.module
+            .export_by_name("handle_request")
+            .map_or(false, |export| export.as_func().is_some())
+    }
+
+    /// Answer calls until the plugin is dropped
+    fn run(&self, calls: &Receiver<Call>) {
+        for call in calls {
+            match call {
+                Call::Query(query) => {
+                    if self.module.export_by_name("on_query").is_some() {
+                        if let Err(e) = self.on_query(&query) {
+                            e.print_stacktrace();
+                        }
+                    }
+                }
+                Call::Request(request, response) => {
+                    let _ = response.send(self.handle_request(&request));
+                }
+            }
+        }
+    }
+
+    /// Send the query to the module
+    fn on_query(&self, query: &str) -> Result<(), Error> {
+        let (ptr, len) = self.write_input(query)?;
+
+        self.invoke("on_query", &[ptr, len])?;
+        Ok(())
+    }
+
+    /// Send the request to the module and read its response
+    fn handle_request(&self, request: &str) -> Result<String, Error> {
+        let (ptr, len) = self.write_input(request)?;
+
+        match self.invoke("handle_request", &[ptr, len])? {
+            Some(RuntimeValue::I64(response)) => {
+                let response = response as u64;
+                let bytes = self
+                    .memory
+                    .get((response >> 32) as u32, response as u32 as usize)
+                    .context(self.error())?;
+
+                Ok(String::from_utf8(bytes).context(self.error())?)
+            }
+            _ => Err(Error::from(self.error()))
+        }
+    }
+
+    /// Copy the input into memory reserved by the module, and get its
+    /// address and length as arguments for the module
+    fn write_input(&self, input: &str) -> Result<(RuntimeValue, RuntimeValue), Error> {
+        let len = RuntimeValue::I32(input.len() as i32);
+
+        match self.invoke("alloc", &[len])? {
+            Some(RuntimeValue::I32(ptr)) => {
+                self.memory
+                    .set(ptr as u32, input.as_bytes())
+                    .context(self.error())?;
+
+                Ok((RuntimeValue::I32(ptr), len))
+            }
+            _ => Err(Error::from(self.error()))
+        }
+    }
+
+    /// Call a function which the module exports
+    fn invoke(&self, name: &str, args: &[RuntimeValue]) -> Result<Option<RuntimeValue>, Error> {
+        Ok(self
+            .module
+            .invoke_export(name, args, &mut NopExternals)
+            .context(self.error())?)
+    }
+
+    /// The error of a failed call to the module
+    fn error(&self) -> ErrorKind {
+        ErrorKind::PluginFailed(self.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_plugin, load_plugins, parse_response};
+    use crate::{env::Env, plugins::QueryEvent, routes::auth::AuthData, util::ErrorKind};
+    use rocket::{
+        config::{Config, Environment},
+        http::{Header, Status},
+        local::Client
+    };
+    use serde_json::Value;
+    use std::{collections::HashMap, fs, path::Path, time::Duration};
+    use tempfile::{tempdir, TempDir};
+
+    /// The directory of the test modules
+    const MODULE_DIR: &str = "test/wasm_plugins";
+
+    /// Create a plugin directory with the test modules
+    fn plugin_dir(modules: &[&str]) -> TempDir {
+        let dir = tempdir().unwrap();
+
+        for module in modules {
+            let file_name = format!("{}.wasm", module);
+            fs::copy(
+                Path::new(MODULE_DIR).join(&file_name),
+                dir.path().join(&file_name)
+            )
+            .unwrap();
+        }
+
+        dir
+    }
+
+    /// Load the plugins of the test modules, and get the error kind if they
+    /// fail to load
+    fn load(modules: &[&str]) -> Result<Vec<String>, ErrorKind> {
+        let dir = plugin_dir(modules);
+
+        load_plugins(dir.path().to_str().unwrap())
+            .map(|plugins| {
+                plugins
+                    .iter()
+                    .map(|plugin| plugin.name().to_owned())
+                    .collect()
+            })
+            .map_err(|e| e.kind())
+    }
+
+    /// Create a client for a server with the plugin's routes mounted
+    fn client(plugin: &dyn super::Plugin) -> Client {
+        let env = Env::Test(toml::from_str("").unwrap(), HashMap::new());
+        let server = rocket::custom(Config::new(Environment::Development))
+            .manage(AuthData::new("test_key".to_owned()))
+            .manage(env)
+            .mount(&format!("/plugins/{}", plugin.name()), plugin.routes());
+
+        Client::new(server).unwrap()
+    }
+
+    /// Send a request to the plugin's routes, and get the status and JSON
+    fn request(client: &Client, path: &str, body: &str) -> (Status, Value) {
+        let mut response = client
+            .post(path)
+            .header(Header::new("X-Pi-hole-Authenticate", "test_key"))
+            .body(body)
+            .dispatch();
+        let json = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        (response.status(), json)
+    }
+
+    /// Only WebAssembly modules are loaded, and each plugin is named after
+    /// its file
+    #[test]
+    fn only_modules() {
+        let dir = plugin_dir(&["echo"]);
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let plugins = load_plugins(dir.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].name(), "echo");
+        assert_eq!(plugins[0].routes().len(), 10);
+    }
+
+    /// Modules which could use anything outside of the sandbox, or which do
+    /// not follow the plugin interface, are not loaded
+    #[test]
+    fn invalid_modules() {
+        for module in &["imports", "unlimited"] {
+            match load(&[module]) {
+                Err(ErrorKind::PluginLoad(path)) => {
+                    assert!(path.ends_with(&format!("{}.wasm", module)))
+                }
+                result => panic!("{}: {:?}", module, result)
+            }
+        }
+
+        match load(&["old_version"]) {
+            Err(ErrorKind::PluginVersion(path)) => assert!(path.ends_with("old_version.wasm")),
+            result => panic!("{:?}", result)
+        }
+    }
+
+    /// Requests to the plugin's routes are sent to the module, and its
+    /// response is the reply. The module is also sent the queries.
+    #[test]
+    fn requests_and_queries() {
+        let dir = plugin_dir(&["echo"]);
+        let plugin = load_plugin(&dir.path().join("echo.wasm"), Duration::from_secs(5)).unwrap();
+        let client = client(plugin.as_ref());
+
+        assert_eq!(
+            request(&client, "/plugins/echo/top/domains?limit=5", "hello"),
+            (
+                Status::Ok,
+                json!({
+                    "request": {
+                        "method": "POST",
+                        "path": "/top/domains",
+                        "query": "limit=5",
+                        "body": "hello"
+                    },
+                    "query": null
+                })
+                .into()
+            )
+        );
+
+        plugin.on_query(&QueryEvent {
+            timestamp: 263_581,
+            domain: "google.com".to_owned(),
+            client: "192.168.1.10".to_owned(),
+            status: "forward",
+            blocked: false
+        });
+
+        let (status, json) = request(&client, "/plugins/echo", "");
+        assert_eq!(status, Status::Ok);
+        assert_eq!(json["request"]["path"], "/");
+        assert_eq!(
+            json["query"],
+            json!({
+                "timestamp": 263_581,
+                "domain": "google.com",
+                "client": "192.168.1.10",
+                "status": "forward",
+                "blocked": false
+            })
+            .0
+        );
+    }
+
+    /// The plugin's routes need authentication
+    #[test]
+    fn requests_need_auth() {
+        let dir = plugin_dir(&["echo"]);
+        let plugin = load_plugin(&dir.path().join("echo.wasm"), Duration::from_secs(5)).unwrap();
+        let client = client(plugin.as_ref());
+
+        let response = client.get("/plugins/echo").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    /// A module which takes too long to answer fails the request, and is not
+    /// sent any more calls
+    #[test]
+    fn request_timeout() {
+        let dir = plugin_dir(&["slow"]);
+        let plugin = load_plugin(&dir.path().join("slow.wasm"), Duration::from_millis(10)).unwrap();
+        let client = client(plugin.as_ref());
+
+        let (status, json) = request(&client, "/plugins/slow", "");
+        assert_eq!(status, Status::InternalServerError);
+        assert_eq!(json["error"]["key"], "plugin_failed");
+
+        let (status, _) = request(&client, "/plugins/slow", "");
+        assert_eq!(status, Status::InternalServerError);
+    }
+
+    /// The response's status is optional, and the data is required
+    #[test]
+    fn responses() {
+        assert_eq!(
+            parse_response("{\"data\":[1]}"),
+            Some((json!([1]).into(), Status::Ok))
+        );
+        assert_eq!(
+            parse_response("{\"status\":404,\"data\":null}"),
+            Some((Value::Null, Status::NotFound))
+        );
+        assert_eq!(parse_response("{\"status\":404}"), None);
+        assert_eq!(parse_response("{\"status\":\"a\",\"data\":1}"), None);
+        assert_eq!(parse_response("[1]"), None);
+    }
+}
@@ -0,0 +1,51 @@
+;; A WebAssembly plugin for the tests. It keeps the last query it was sent,
+;; and answers requests with the request and that query.
+;;
+;; Build it with `wat2wasm echo.wat`
+(module
+  (memory (export "memory") 1 1)
+
+  ;; The last query, which is "null" until a query is sent
+  (data (i32.const 16384) "null")
+  (global $query_len (mut i32) (i32.const 4))
+
+  (data (i32.const 0) "{\"data\":{\"request\":")
+  (data (i32.const 64) ",\"query\":")
+  (data (i32.const 128) "}}")
+
+  (func (export "pihole_plugin_version") (result i32)
+    (i32.const 1))
+
+  ;; The input is always written to the same place, which fits 8 KiB
+  (func (export "alloc") (param $len i32) (result i32)
+    (if (i32.gt_u (local.get $len) (i32.const 8192))
+      (then (unreachable)))
+    (i32.const 8192))
+
+  ;; Copy the bytes and return the end of the destination
+  (func $copy (param $dst i32) (param $src i32) (param $len i32) (result i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $len)))
+        (i32.store8 (local.get $dst) (i32.load8_u (local.get $src)))
+        (local.set $dst (i32.add (local.get $dst) (i32.const 1)))
+        (local.set $src (i32.add (local.get $src) (i32.const 1)))
+        (local.set $len (i32.sub (local.get $len) (i32.const 1)))
+        (br $next)))
+    (local.get $dst))
+
+  (func (export "on_query") (param $ptr i32) (param $len i32)
+    (drop (call $copy (i32.const 16384) (local.get $ptr) (local.get $len)))
+    (global.set $query_len (local.get $len)))
+
+  (func (export "handle_request") (param $ptr i32) (param $len i32) (result i64)
+    (local $end i32)
+    (local.set $end (call $copy (i32.const 32768) (i32.const 0) (i32.const 19)))
+    (local.set $end (call $copy (local.get $end) (local.get $ptr) (local.get $len)))
+    (local.set $end (call $copy (local.get $end) (i32.const 64) (i32.const 9)))
+    (local.set $end
+      (call $copy (local.get $end) (i32.const 16384) (global.get $query_len)))
+    (local.set $end (call $copy (local.get $end) (i32.const 128) (i32.const 2)))
+    (i64.or
+      (i64.shl (i64.const 32768) (i64.const 32))
+      (i64.extend_i32_u (i32.sub (local.get $end) (i32.const 32768))))))
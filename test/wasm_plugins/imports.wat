@@ -0,0 +1,12 @@
+;; A WebAssembly plugin for the tests, which imports a function.
+;;
+;; Build it with `wat2wasm imports.wat`
+(module
+  (import "env" "print" (func $print (param i32 i32)))
+  (memory (export "memory") 1 1)
+
+  (func (export "pihole_plugin_version") (result i32)
+    (i32.const 1))
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 0)))
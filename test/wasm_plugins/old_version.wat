@@ -0,0 +1,12 @@
+;; A WebAssembly plugin for the tests, which was built for an older version
+;; of the plugin interface.
+;;
+;; Build it with `wat2wasm old_version.wat`
+(module
+  (memory (export "memory") 1 1)
+
+  (func (export "pihole_plugin_version") (result i32)
+    (i32.const 0))
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 0)))
@@ -0,0 +1,21 @@
+;; A WebAssembly plugin for the tests, which takes a long time to answer
+;; requests.
+;;
+;; Build it with `wat2wasm slow.wat`
+(module
+  (memory (export "memory") 1 1)
+  (data (i32.const 0) "{\"data\":null}")
+
+  (func (export "pihole_plugin_version") (result i32)
+    (i32.const 1))
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 1024))
+
+  (func (export "handle_request") (param $ptr i32) (param $len i32) (result i64)
+    (local $i i32)
+    (local.set $i (i32.const 20000000))
+    (loop $next
+      (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+      (br_if $next (local.get $i)))
+    (i64.const 13)))
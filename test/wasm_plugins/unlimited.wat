@@ -0,0 +1,11 @@
+;; A WebAssembly plugin for the tests, whose memory has no maximum size.
+;;
+;; Build it with `wat2wasm unlimited.wat`
+(module
+  (memory (export "memory") 1)
+
+  (func (export "pihole_plugin_version") (result i32)
+    (i32.const 1))
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 0)))
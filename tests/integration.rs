@@ -0,0 +1,385 @@
+// Pi-hole: A black hole for Internet advertisements
+// (c) 2019 Pi-hole, LLC (https://pi-hole.net)
+// Network-wide ad blocking via your own hardware.
+//
+// API
+// Integration Tests
+//
+// This file is copyright under the latest version of the EUPL.
+// Please see LICENSE file for your rights under this license.
+
+//! These tests run the full API over HTTP, using temporary Pi-hole files, a
+//! copy of the test FTL database, and test shared memory. FTL is not running,
+//! so the FTL socket is unavailable. They need the `test-harness` feature.
+
+use pihole_api::{
+    FtlClient, FtlCounters, FtlDnssecType, FtlDomain, FtlMemory, FtlQuery, FtlQueryReplyType,
+    FtlQueryStatus, FtlQueryType, FtlRegexMatch, FtlSettings, FtlUpstream, PiholeFile, MAGIC_BYTE
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Once
+    },
+    thread,
+    time::Duration
+};
+use tempfile::tempdir;
+
+/// The API key set in the test setupVars.conf
+const API_KEY: &str = "integration_test_key";
+
+static START: Once = Once::new();
+static PORT: AtomicUsize = AtomicUsize::new(0);
+
+/// Start the API if it has not been started yet, and get its port
+fn server_port() -> u16 {
+    START.call_once(|| {
+        // Keep the temporary files until the tests exit
+        let dir = tempdir().unwrap().into_path();
+        let port = free_port();
+
+        write_files(&dir, port);
+
+        let config_location = dir.join("API.toml").to_str().unwrap().to_owned();
+        thread::spawn(move || {
+            if let Err(e) = pihole_api::start_with_ftl_memory(&config_location, test_memory) {
+                e.print_stacktrace();
+            }
+        });
+
+        wait_for_server(port);
+        PORT.store(port as usize, Ordering::SeqCst);
+    });
+
+    PORT.load(Ordering::SeqCst) as u16
+}
+
+/// Find a port which is not in use
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Write the API config and the Pi-hole files into the directory. Every
+/// Pi-hole file is located in the directory, under its default file name, so
+/// the tests never touch the real Pi-hole files.
+fn write_files(dir: &Path, port: u16) {
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+    let file_path = |file: PiholeFile| {
+        path(
+            Path::new(file.default_location())
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+        )
+    };
+
+    fs::copy("test/FTL.db", path("FTL.db")).unwrap();
+    fs::write(
+        file_path(PiholeFile::SetupVars),
+        format!(
+            "WEBPASSWORD={}\n\
+             GRAVITY_UPDATE_FREQUENCY=0\n",
+            API_KEY
+        )
+    )
+    .unwrap();
+    fs::write(
+        file_path(PiholeFile::FtlConfig),
+        format!("DBFILE={}\n", path("FTL.db"))
+    )
+    .unwrap();
+    fs::write(file_path(PiholeFile::Whitelist), "example.com\n").unwrap();
+    fs::write(file_path(PiholeFile::Blacklist), "").unwrap();
+    fs::write(file_path(PiholeFile::Regexlist), "").unwrap();
+    fs::write(file_path(PiholeFile::RegexWhitelist), "").unwrap();
+    fs::write(
+        file_path(PiholeFile::Gravity),
+        "ads.example.net\ntracker.example.org\n"
+    )
+    .unwrap();
+
+    let file_locations: String = PiholeFile::ALL
+        .iter()
+        .map(|&file| format!("{} = \"{}\"\n", file.config_key(), file_path(file)))
+        .collect();
+
+    fs::write(
+        path("API.toml"),
+        format!(
+            "[general]\n\
+             address = \"127.0.0.1\"\n\
+             port = {}\n\
+             \n\
+             [file_locations]\n\
+             {}",
+            port, file_locations
+        )
+    )
+    .unwrap();
+}
+
+/// Create the shared memory of the tests. `ads.example.net` was blocked once
+/// by Gravity for the laptop, and the other queries were forwarded.
+///
+/// | ID | Type | Status  | Domain          | Client   | Upstream |
+/// | -- | ---- | ------- | --------------- | -------- | -------- |
+/// | 1  | A    | Forward | example.com     | 10.0.0.2 | 8.8.8.8  |
+/// | 2  | AAAA | Forward | example.com     | 10.0.0.3 | 8.8.8.8  |
+/// | 3  | A    | Gravity | ads.example.net | 10.0.0.2 |          |
+fn test_memory() -> FtlMemory {
+    let query = |id, query_type, status, domain_id, client_id| FtlQuery {
+        magic: MAGIC_BYTE,
+        id,
+        database_id: 0,
+        timestamp: 1_000_000,
+        time_index: 1,
+        response_time: 1,
+        domain_id,
+        client_id,
+        upstream_id: 0,
+        query_type,
+        status,
+        reply_type: FtlQueryReplyType::IP,
+        dnssec_type: FtlDnssecType::Unspecified,
+        is_complete: true,
+        is_private: false,
+        ad_bit: false
+    };
+
+    let mut strings = HashMap::new();
+    strings.insert(1, "example.com".to_owned());
+    strings.insert(2, "ads.example.net".to_owned());
+    strings.insert(3, "10.0.0.2".to_owned());
+    strings.insert(4, "laptop".to_owned());
+    strings.insert(5, "10.0.0.3".to_owned());
+    strings.insert(6, "8.8.8.8".to_owned());
+
+    FtlMemory::Test {
+        clients: vec![
+            FtlClient::new(2, 1, 3, Some(4)),
+            FtlClient::new(1, 0, 5, None),
+        ],
+        domains: vec![
+            FtlDomain::new(2, 0, 1, FtlRegexMatch::NotBlocked),
+            FtlDomain::new(1, 1, 2, FtlRegexMatch::NotBlocked),
+        ],
+        over_time: Vec::new(),
+        upstreams: vec![FtlUpstream::new(2, 0, 6, None)],
+        queries: vec![
+            query(1, FtlQueryType::A, FtlQueryStatus::Forward, 0, 0),
+            query(2, FtlQueryType::AAAA, FtlQueryStatus::Forward, 0, 1),
+            query(3, FtlQueryType::A, FtlQueryStatus::Gravity, 1, 0),
+        ],
+        strings,
+        counters: FtlCounters {
+            total_queries: 3,
+            blocked_queries: 1,
+            forwarded_queries: 2,
+            total_upstreams: 1,
+            total_clients: 2,
+            total_domains: 2,
+            gravity_size: 2,
+            query_type_counters: [2, 1, 0, 0, 0, 0, 0],
+            ..FtlCounters::default()
+        },
+        settings: FtlSettings::default()
+    }
+}
+
+/// Wait until the server accepts connections
+fn wait_for_server(port: u16) {
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    panic!("The API did not start");
+}
+
+/// Send a request to the API and get the status code and JSON body
+fn request(method: &str, path: &str, body: Option<Value>, authenticate: bool) -> (u16, Value) {
+    let mut stream = TcpStream::connect(("127.0.0.1", server_port())).unwrap();
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+
+    let mut request = format!(
+        "{} /admin/api{} HTTP/1.1\r\n\
+         Host: localhost\r\n\
+         Connection: close\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n",
+        method,
+        path,
+        body.len()
+    );
+
+    if authenticate {
+        request.push_str(&format!("X-Pi-hole-Authenticate: {}\r\n", API_KEY));
+    }
+
+    request.push_str("\r\n");
+    request.push_str(&body);
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let status = response
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .expect("Invalid status line");
+    let body = response
+        .splitn(2, "\r\n\r\n")
+        .nth(1)
+        .expect("Missing response body");
+
+    (status, serde_json::from_str(body).unwrap())
+}
+
+/// Requests without the API key are unauthorized
+#[test]
+fn auth_required() {
+    let (status, body) = request("GET", "/auth", None, false);
+
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["key"], "unauthorized");
+}
+
+/// Requests with the API key are authorized
+#[test]
+fn auth_with_key() {
+    let (status, body) = request("GET", "/auth", None, true);
+
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "success");
+}
+
+/// The lists are read from the configured files
+#[test]
+fn get_whitelist() {
+    let (status, body) = request("GET", "/dns/whitelist", None, false);
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        serde_json::json!([{
+            "domain": "example.com",
+            "comment": null,
+            "enabled": true,
+            "date_added": null,
+            "date_modified": null
+        }])
+    );
+}
+
+/// Invalid domains are rejected before the lists are changed
+#[test]
+fn add_invalid_domain() {
+    let (status, body) = request(
+        "POST",
+        "/dns/blacklist",
+        Some(serde_json::json!({ "domain": "not a domain" })),
+        true
+    );
+
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["key"], "invalid_domain");
+}
+
+/// Settings are written to setupVars.conf and read back
+#[test]
+fn subnet_settings() {
+    let (status, _) = request(
+        "PUT",
+        "/settings/subnets",
+        Some(serde_json::json!({ "subnets": ["192.168.1.0/24"] })),
+        true
+    );
+    assert_eq!(status, 200);
+
+    let (status, body) = request("GET", "/settings/subnets", None, true);
+    assert_eq!(status, 200);
+    assert_eq!(body["subnets"], serde_json::json!(["192.168.1.0/24"]));
+}
+
+/// Database stats are read from the FTL database
+#[test]
+fn database_summary() {
+    let (status, body) = request(
+        "GET",
+        "/stats/database/summary?from=0&until=177180",
+        None,
+        true
+    );
+
+    assert_eq!(status, 200);
+    assert!(body["total_queries"]["A"].is_number());
+}
+
+/// The summary is read from shared memory
+#[test]
+fn summary() {
+    let (status, body) = request("GET", "/stats/summary", None, true);
+
+    assert_eq!(status, 200);
+    assert_eq!(body["total_queries"]["A"], 2);
+    assert_eq!(body["total_queries"]["AAAA"], 1);
+    assert_eq!(body["blocked_queries"], 1);
+    assert_eq!(body["forwarded_queries"], 2);
+    assert_eq!(body["unique_domains"], 2);
+    assert_eq!(body["total_clients"], 2);
+    assert_eq!(body["active_clients"], 2);
+}
+
+/// The top clients are read from shared memory and sorted by their queries
+#[test]
+fn top_clients() {
+    let (status, body) = request("GET", "/stats/top_clients", None, true);
+
+    assert_eq!(status, 200);
+    assert_eq!(body["total_queries"], 3);
+
+    let clients: Vec<(&str, u64)> = body["top_clients"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|client| {
+            (
+                client["ip"].as_str().unwrap(),
+                client["count"].as_u64().unwrap()
+            )
+        })
+        .collect();
+    assert_eq!(clients, vec![("10.0.0.2", 2), ("10.0.0.3", 1)]);
+}
+
+/// Domains are found in the Gravity fixture through their parent domains
+#[test]
+fn gravity_contains() {
+    let (status, body) = request(
+        "GET",
+        "/dns/gravity/contains/cdn.ads.example.net",
+        None,
+        true
+    );
+
+    assert_eq!(status, 200);
+    assert_eq!(body["contains"], true);
+    assert_eq!(body["gravity"], "ads.example.net");
+    assert_eq!(body["exact"], false);
+}